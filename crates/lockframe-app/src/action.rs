@@ -2,11 +2,21 @@
 //!
 //! This module defines the [`AppAction`] enum, which represents instructions
 //! produced by the [`crate::App`] state machine for the runtime to execute.
+//!
+//! Protocol actions apply to the account the App was acting for when it
+//! produced them: the active account, or the one passed to
+//! [`crate::App::handle_for`].
+
+use std::time::Duration;
 
 use lockframe_core::mls::RoomId;
+use lockframe_proto::payloads::app::ReplyTo;
+
+use crate::{AccountId, Notification, RoomOrder};
 
 /// Actions produced by the App state machine.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub enum AppAction {
     /// Render the UI.
     Render,
@@ -14,8 +24,64 @@ pub enum AppAction {
     /// Quit the application.
     Quit,
 
+    /// Surface a notification to the user.
+    Notify(Notification),
+
+    /// Run an action later, after the runtime's clock has advanced.
+    Schedule {
+        /// Delay from now.
+        after: Duration,
+        /// Action to run.
+        action: Box<AppAction>,
+    },
+
+    /// Pin a room to the top of the room list, or unpin it.
+    PinRoom {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Whether the room is pinned.
+        pinned: bool,
+    },
+
+    /// Change the order of the room list.
+    SetRoomOrder {
+        /// New order.
+        order: RoomOrder,
+    },
+
+    /// Tell a room's other members whether we are composing a message.
+    ///
+    /// Produced on every edit of a draft. The bridge throttles them, sending
+    /// an active notification only every few seconds, and a stop only after
+    /// an active one.
+    SendTyping {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// True while the draft has text.
+        active: bool,
+    },
+
+    /// Fetch a room's messages from before `until_log_index`.
+    LoadHistory {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Log index of the oldest message held. Only earlier ones are
+        /// fetched.
+        until_log_index: u64,
+    },
+
+    /// Save a room's draft so it survives restarts.
+    SaveDraft {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Draft text. Empty removes the saved draft.
+        text: String,
+    },
+
     /// Connect to server.
     Connect {
+        /// Account to connect.
+        account: AccountId,
         /// Server address (host:port).
         server_addr: String,
     },
@@ -44,6 +110,26 @@ pub enum AppAction {
         room_id: RoomId,
         /// Message content bytes.
         content: Vec<u8>,
+        /// Message being replied to, if any.
+        reply_to: Option<ReplyTo>,
+    },
+
+    /// Edit one of our messages.
+    EditMessage {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Log index of the message to edit.
+        target_log_index: u64,
+        /// Replacement content bytes.
+        content: Vec<u8>,
+    },
+
+    /// Delete one of our messages.
+    DeleteMessage {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Log index of the message to delete.
+        target_log_index: u64,
     },
 
     /// Publish `KeyPackage` to server.
@@ -56,4 +142,36 @@ pub enum AppAction {
         /// User ID to add.
         user_id: u64,
     },
+
+    /// Remove a member from a room.
+    RemoveMember {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// User ID to remove.
+        user_id: u64,
+    },
+
+    /// Invite a user to a room. They join themselves if they accept.
+    InviteUser {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// User ID to invite.
+        user_id: u64,
+    },
+
+    /// Search the public room directory.
+    SearchDirectory {
+        /// Text room names must contain. Empty matches every listed room.
+        query: String,
+        /// Continue after this room. `None` starts from the first match.
+        after: Option<RoomId>,
+    },
+
+    /// Publish or withdraw a room's directory listing.
+    SetRoomListing {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Listed name. `None` removes the room from the directory.
+        name: Option<String>,
+    },
 }
@@ -9,22 +9,83 @@
 //!
 //! # Responsibilities
 //!
-//! - Tracks the list of rooms, unread badges, and the currently active room.
+//! - Holds one or more accounts, each with its own connection and rooms, and
+//!   which of them is active.
+//! - Tracks the list of rooms, unread and mention counts, and the currently
+//!   active room.
+//! - Carries out frontends' [`crate::Intent`]s, rejecting those the current
+//!   state doesn't allow.
+//! - Decides which incoming messages deserve a notification.
+//! - Queues failures and notices for frontends to show until dismissed.
+//! - Orders and filters the room list frontends render.
 //! - Stores terminal dimensions to handle resize events.
 //! - Tracks high-level connection state for UI feedback.
+//! - With the `devtools` feature, records its inputs in an [`crate::EventLog`]
+//!   that can be replayed and stepped back through.
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+};
 
 use lockframe_core::mls::RoomId;
+use lockframe_proto::payloads::{
+    app::ReplyTo,
+    session::{DirectoryEntry, NoticeKind},
+};
 
-use crate::{AppAction, AppEvent, ConnectionState, RoomState};
+use crate::{
+    AccountId, AccountSummary, AppAction, AppEvent, ConnectionQuality, ConnectionState, Directory,
+    History, Intent, Mentions, Notice, Notification, PendingInvite, RestoreStep, RoomOrder,
+    RoomState, Severity,
+    snapshot::{AccountSnapshot, AppSnapshot},
+};
+#[cfg(feature = "devtools")]
+use crate::{EventLog, Input};
+
+/// Characters of message content kept in a notification preview.
+const PREVIEW_CHARS: usize = 80;
+
+/// State of an account that is not active.
+#[derive(Debug, Clone)]
+struct Parked {
+    id: AccountId,
+    name: String,
+    state: ConnectionState,
+    server_addr: String,
+    rooms: HashMap<RoomId, RoomState>,
+    active_room: Option<RoomId>,
+    directory: Option<Directory>,
+    activity: u64,
+    reconnects: u32,
+    invites: Vec<PendingInvite>,
+}
+
+/// Notices kept before the oldest is dropped.
+pub const MAX_NOTICES: usize = 8;
 
 /// Application state machine.
 ///
 /// Pure state machine that processes events and produces actions.
 /// No I/O dependencies - fully testable in simulation.
+///
+/// An App holds one or more accounts. The per-account fields below belong to
+/// the active account; the others are parked until switched to, so each
+/// account has its own room namespace. Terminal size, status, mentions and
+/// room list settings are shared.
 #[derive(Debug, Clone)]
 pub struct App {
+    /// Active account.
+    account: AccountId,
+    /// Display name of the active account.
+    account_name: String,
+    /// Accounts that are not active, in the order they were added.
+    parked: Vec<Parked>,
+    /// ID of the next account added.
+    next_account: u32,
+    /// Whether the account being handled is parked, so none of its rooms
+    /// is on screen.
+    background: bool,
     /// Connection state.
     state: ConnectionState,
     /// Server address for connection.
@@ -37,23 +98,70 @@ pub struct App {
     terminal_size: (u16, u16),
     /// Transient status message. `None` if no message.
     status_message: Option<String>,
+    /// Notices not yet dismissed, oldest first, across accounts.
+    notices: VecDeque<Notice>,
+    /// ID of the next notice raised.
+    next_notice: u64,
+    /// Room directory being browsed. `None` if the directory is closed.
+    directory: Option<Directory>,
+    /// What counts as a mention of the user.
+    mentions: Mentions,
+    /// Order of the room list.
+    room_order: RoomOrder,
+    /// Text room list entries must contain. Empty shows every room.
+    room_filter: String,
+    /// Activity counter rooms are stamped with, for [`RoomOrder::Recent`].
+    activity: u64,
+    /// Times the connection was re-established, for [`ConnectionQuality`].
+    reconnects: u32,
+    /// Invites waiting for an answer, oldest first.
+    invites: Vec<PendingInvite>,
+    /// Inputs handled so far.
+    #[cfg(feature = "devtools")]
+    log: EventLog,
 }
 
 impl App {
     /// Create a new App with the given server address.
     pub fn new(server_addr: String) -> Self {
         Self {
+            account: AccountId::default(),
+            account_name: server_addr.clone(),
+            parked: Vec::new(),
+            next_account: 1,
+            background: false,
             state: ConnectionState::Disconnected,
-            server_addr,
             rooms: HashMap::new(),
             active_room: None,
             terminal_size: (80, 24),
             status_message: None,
+            notices: VecDeque::new(),
+            next_notice: 0,
+            directory: None,
+            mentions: Mentions::default(),
+            room_order: RoomOrder::default(),
+            room_filter: String::new(),
+            activity: 0,
+            reconnects: 0,
+            invites: Vec::new(),
+            #[cfg(feature = "devtools")]
+            log: EventLog::new(server_addr.clone()),
+            server_addr,
         }
     }
 
     /// Process an event and return actions.
     pub fn handle(&mut self, event: AppEvent) -> Vec<AppAction> {
+        #[cfg(feature = "devtools")]
+        let input = Input::Event { account: self.account, event: event.clone() };
+        let actions = self.apply(event);
+        #[cfg(feature = "devtools")]
+        self.record(input, &actions);
+        actions
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn apply(&mut self, event: AppEvent) -> Vec<AppAction> {
         match event {
             AppEvent::Tick => vec![],
             AppEvent::Resize(cols, rows) => {
@@ -65,36 +173,121 @@ impl App {
                 vec![AppAction::Render]
             },
             AppEvent::Connected { session_id, sender_id } => {
-                self.state = ConnectionState::Connected { session_id, sender_id };
+                let quality =
+                    ConnectionQuality { reconnects: self.reconnects, ..Default::default() };
+                self.state = ConnectionState::Connected { session_id, sender_id, quality };
                 vec![AppAction::Render]
             },
-            AppEvent::RoomJoined { room_id } => {
-                let is_new = !self.rooms.contains_key(&room_id);
-                self.rooms.entry(room_id).or_insert_with(|| RoomState::new(room_id));
-                if self.active_room.is_none() {
-                    self.active_room = Some(room_id);
-                }
-                if is_new {
-                    self.status_message = Some(format!("Joined room {room_id}"));
-                }
-                vec![AppAction::Render]
+            AppEvent::Disconnected => self.session_restore(None),
+            AppEvent::HeartbeatAnswered { rtt } => self.update_quality(|quality| {
+                quality.rtt = Some(rtt);
+                quality.missed_heartbeats = 0;
+            }),
+            AppEvent::HeartbeatMissed { missed } => {
+                self.update_quality(|quality| quality.missed_heartbeats = missed)
             },
+            AppEvent::SessionRestore { step } => self.session_restore(Some(step)),
+            AppEvent::RoomJoined { room_id } => self.room_joined(room_id),
             AppEvent::RoomLeft { room_id } => {
                 self.rooms.remove(&room_id);
                 if self.active_room == Some(room_id) {
-                    self.active_room = self.rooms.keys().next().copied();
+                    self.active_room = self.room_list().first().copied();
                 }
                 vec![AppAction::Render]
             },
-            AppEvent::MessageReceived { room_id, sender_id, content } => {
+            AppEvent::RoomLeaving { room_id } => {
                 if let Some(room) = self.rooms.get_mut(&room_id) {
-                    room.add_message(sender_id, content);
-                    if self.active_room != Some(room_id) {
-                        room.unread = true;
-                    }
+                    room.leaving = true;
                 }
                 vec![AppAction::Render]
             },
+            AppEvent::InviteReceived { room_id, inviter, expires_at } => {
+                self.invite_received(PendingInvite { room_id, inviter, expires_at })
+            },
+            AppEvent::Invited { room_id } => {
+                vec![AppAction::Notify(Notification::Invite { room_id }), AppAction::Render]
+            },
+            AppEvent::RoomError { room_id, message } => {
+                let notification = Notification::RoomError { room_id, message: message.clone() };
+                let mut actions = vec![AppAction::Notify(notification)];
+                actions.extend(self.push_notice(Severity::Error, Some(room_id), message));
+                actions
+            },
+            AppEvent::DraftRestored { room_id, text } => {
+                self.update_room(room_id, |room| room.restore_draft(text))
+            },
+            AppEvent::MessageReceived {
+                room_id,
+                sender_id,
+                log_index,
+                content,
+                reply_to,
+                timestamp,
+            } => self.message_received(room_id, sender_id, log_index, content, reply_to, timestamp),
+            AppEvent::AttachmentReceived {
+                room_id,
+                sender_id,
+                log_index,
+                attachment,
+                timestamp,
+            } => {
+                let content = attachment.filename.clone().into_bytes();
+                let actions = self.message_received(
+                    room_id,
+                    sender_id,
+                    Some(log_index),
+                    content,
+                    None,
+                    timestamp,
+                );
+                if let Some(room) = self.rooms.get_mut(&room_id) {
+                    room.set_attachment(log_index, attachment);
+                }
+                actions
+            },
+            AppEvent::MessageSending {
+                room_id,
+                sender_id,
+                local_id,
+                content,
+                reply_to,
+                delivery,
+                timestamp,
+            } => {
+                let activity = self.next_activity();
+                self.rooms.get_mut(&room_id).map_or_else(Vec::new, |room| {
+                    room.last_activity = activity;
+                    room.add_local_message(
+                        sender_id, local_id, content, reply_to, delivery, timestamp,
+                    );
+                    vec![AppAction::Render]
+                })
+            },
+            AppEvent::DeliveryChanged { room_id, local_id, delivery, log_index } => {
+                self.update_room(room_id, |room| room.set_delivery(local_id, delivery, log_index))
+            },
+            AppEvent::MessageEdited { room_id, sender_id, target_log_index, content } => self
+                .update_room(room_id, |room| {
+                    room.edit_message(sender_id, target_log_index, content)
+                }),
+            AppEvent::MessageDeleted { room_id, sender_id, target_log_index } => {
+                self.update_room(room_id, |room| room.delete_message(sender_id, target_log_index))
+            },
+            AppEvent::MessageExpired { room_id, log_index } => {
+                self.update_room(room_id, |room| room.expire_message(log_index))
+            },
+            AppEvent::ReactionChanged { room_id, target_log_index, change } => {
+                self.update_room(room_id, |room| room.react(target_log_index, change))
+            },
+            AppEvent::HistoryChanged { room_id, history } => {
+                self.update_room(room_id, |room| room.set_history(history))
+            },
+            AppEvent::EpochChanged { room_id, epoch } => {
+                self.update_room(room_id, |room| room.set_epoch(epoch))
+            },
+            AppEvent::TypingChanged { room_id, sender_id, active } => {
+                self.update_room(room_id, |room| room.set_typing(sender_id, active))
+            },
             AppEvent::MemberAdded { room_id, member_id } => {
                 if let Some(room) = self.rooms.get_mut(&room_id) {
                     room.members.insert(member_id);
@@ -103,27 +296,401 @@ impl App {
                 vec![AppAction::Render]
             },
             AppEvent::MemberRemoved { room_id, member_id } => {
-                if let Some(room) = self.rooms.get_mut(&room_id) {
-                    room.members.remove(&member_id);
-                }
-                vec![AppAction::Render]
+                self.update_room(room_id, |room| room.remove_member(member_id))
             },
-            AppEvent::Error { message } => {
-                self.status_message = Some(format!("Error: {message}"));
-                vec![AppAction::Render]
+            AppEvent::MembersChanged { room_id, members } => {
+                self.update_room(room_id, |room| room.set_members(members))
+            },
+            AppEvent::DirectoryResults { rooms, next } => self.directory_results(rooms, next),
+            AppEvent::Error { message, error_id: None } => {
+                self.push_notice(Severity::Error, None, message)
             },
+            AppEvent::Error { message, error_id: Some(id) } => {
+                self.push_notice(Severity::Error, None, format!("{message} (err {id})"))
+            },
+            AppEvent::Notice { severity, room_id, message } => {
+                self.push_notice(severity, room_id, message)
+            },
+            AppEvent::ServerNotice { room_id, kind, text } => {
+                self.server_notice(room_id, kind, text)
+            },
+        }
+    }
+
+    /// Queue a notice for the account being handled.
+    fn push_notice(
+        &mut self,
+        severity: Severity,
+        room_id: Option<RoomId>,
+        message: String,
+    ) -> Vec<AppAction> {
+        self.queue_notice(severity, room_id, message, None)
+    }
+
+    /// Queue a notice from the server's operator. Maintenance is a warning,
+    /// since the connection is about to degrade.
+    fn server_notice(
+        &mut self,
+        room_id: Option<RoomId>,
+        kind: NoticeKind,
+        text: String,
+    ) -> Vec<AppAction> {
+        let severity = match kind {
+            NoticeKind::Maintenance => Severity::Warning,
+            NoticeKind::Info | NoticeKind::Policy => Severity::Info,
+        };
+        self.queue_notice(severity, room_id, text, Some(kind))
+    }
+
+    /// Queue a notice, from the server's operator if `server` is set. A
+    /// notice repeating the newest one is counted rather than queued again.
+    fn queue_notice(
+        &mut self,
+        severity: Severity,
+        room_id: Option<RoomId>,
+        message: String,
+        server: Option<NoticeKind>,
+    ) -> Vec<AppAction> {
+        let account = self.account;
+        let repeated = self.notices.back_mut().filter(|last| {
+            (last.severity, last.account, last.room_id, last.server)
+                == (severity, account, room_id, server)
+                && last.message == message
+        });
+        if let Some(last) = repeated {
+            last.count += 1;
+        } else {
+            if self.notices.len() == MAX_NOTICES {
+                self.notices.pop_front();
+            }
+            let id = self.next_notice;
+            self.next_notice += 1;
+            self.notices.push_back(Notice {
+                id,
+                severity,
+                account,
+                room_id,
+                message,
+                count: 1,
+                server,
+            });
+        }
+        vec![AppAction::Render]
+    }
+
+    /// Add a page of search results to the open directory.
+    fn directory_results(
+        &mut self,
+        rooms: Vec<DirectoryEntry>,
+        next: Option<RoomId>,
+    ) -> Vec<AppAction> {
+        // Results for a directory closed since the search was sent
+        let Some(directory) = self.directory.as_mut() else {
+            return vec![];
+        };
+        directory.rooms.extend(rooms);
+        directory.next = next;
+        self.status_message = Some(match (directory.rooms.len(), next) {
+            (0, _) => "No listed rooms found".to_string(),
+            (found, Some(_)) => format!("Found {found} rooms, /next for more"),
+            (found, None) => format!("Found {found} rooms"),
+        });
+        vec![AppAction::Render]
+    }
+
+    /// Add a joined room, taking its name from the open directory and
+    /// making it active if no room is.
+    fn room_joined(&mut self, room_id: RoomId) -> Vec<AppAction> {
+        let is_new = !self.rooms.contains_key(&room_id);
+        let activity = self.next_activity();
+        let listed = self
+            .directory
+            .as_ref()
+            .and_then(|directory| directory.rooms.iter().find(|entry| entry.room_id == room_id));
+        let name = listed.map(|entry| entry.name.clone());
+        let room = self.rooms.entry(room_id).or_insert_with(|| RoomState::new(room_id));
+        room.last_activity = activity;
+        if name.is_some() {
+            room.name = name;
+        }
+        if self.active_room.is_none() {
+            self.active_room = Some(room_id);
+        }
+        self.invites.retain(|invite| invite.room_id != room_id);
+        if is_new {
+            self.status_message = Some(format!("Joined room {room_id}"));
+            self.directory = None;
         }
+        vec![AppAction::Render]
+    }
+
+    /// Hold an invite for the user to answer, replacing any earlier one to
+    /// the same room.
+    fn invite_received(&mut self, invite: PendingInvite) -> Vec<AppAction> {
+        if self.rooms.contains_key(&invite.room_id) {
+            return vec![];
+        }
+        self.invites.retain(|pending| pending.room_id != invite.room_id);
+        self.invites.push(invite);
+        self.status_message =
+            Some(format!("User {} invited you to room {}", invite.inviter, invite.room_id));
+        vec![AppAction::Notify(Notification::Invite { room_id: invite.room_id }), AppAction::Render]
+    }
+
+    /// Store a message, counting it as unread and notifying about it when it
+    /// comes from another member and the room is not active or it mentions
+    /// the user.
+    /// Show one of our own messages as soon as it is handed to the client.
+    fn message_received(
+        &mut self,
+        room_id: RoomId,
+        sender_id: u64,
+        log_index: Option<u64>,
+        content: Vec<u8>,
+        reply_to: Option<ReplyTo>,
+        timestamp: u64,
+    ) -> Vec<AppAction> {
+        let own_id = match self.state {
+            ConnectionState::Connected { sender_id, .. } => Some(sender_id),
+            _ => None,
+        };
+        let inactive = self.background || self.active_room != Some(room_id);
+        let activity = self.next_activity();
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return vec![AppAction::Render];
+        };
+
+        // Loaded history is neither activity nor unread
+        let earlier = log_index
+            .zip(room.oldest_log_index())
+            .is_some_and(|(log_index, oldest)| log_index < oldest);
+        if !earlier {
+            room.last_activity = activity;
+        }
+
+        let text = String::from_utf8_lossy(&content);
+        let from_other = !earlier && log_index.is_some() && own_id != Some(sender_id);
+        let mention = from_other && self.mentions.matches(own_id, &text);
+        let notification = (from_other && (inactive || mention)).then(|| Notification::Message {
+            room_id,
+            sender_id,
+            log_index,
+            preview: text.chars().take(PREVIEW_CHARS).collect(),
+            mention,
+        });
+
+        room.add_message(sender_id, log_index, content, reply_to, mention, timestamp);
+        if from_other && inactive {
+            room.unread += 1;
+            room.mentions += usize::from(mention);
+        }
+
+        let mut actions = vec![AppAction::Render];
+        actions.extend(notification.map(AppAction::Notify));
+        actions
+    }
+
+    /// Apply `change` to the connection's quality, rendering if it changed
+    /// anything.
+    fn update_quality(&mut self, change: impl FnOnce(&mut ConnectionQuality)) -> Vec<AppAction> {
+        let ConnectionState::Connected { quality, .. } = &mut self.state else {
+            return vec![];
+        };
+        let before = *quality;
+        change(quality);
+        if *quality == before { vec![] } else { vec![AppAction::Render] }
+    }
+
+    /// Report the connection dropping (`None`) or a restore step.
+    fn session_restore(&mut self, step: Option<RestoreStep>) -> Vec<AppAction> {
+        match step {
+            None => self.state = ConnectionState::Disconnected,
+            Some(RestoreStep::Authenticating) => self.reconnects += 1,
+            Some(_) => {},
+        }
+        self.status_message = Some(match step {
+            None => "Connection lost, reconnecting...".to_string(),
+            Some(RestoreStep::Authenticating) => "Reconnected, restoring session...".to_string(),
+            Some(RestoreStep::Syncing { remaining }) => format!("Syncing {remaining} rooms..."),
+            Some(RestoreStep::Replaying { messages }) => {
+                format!("Sending {messages} queued messages...")
+            },
+            Some(RestoreStep::Restored) => "Session restored".to_string(),
+        });
+        let _ = self.update_quality(|quality| {
+            quality.restoring = step.filter(|&step| step != RestoreStep::Restored);
+        });
+        vec![AppAction::Render]
+    }
+
+    /// Apply `change` to a room, rendering if it changed anything.
+    fn update_room(
+        &mut self,
+        room_id: RoomId,
+        change: impl FnOnce(&mut RoomState) -> bool,
+    ) -> Vec<AppAction> {
+        let changed = self.rooms.get_mut(&room_id).is_some_and(change);
+        if changed { vec![AppAction::Render] } else { vec![] }
+    }
+
+    fn next_activity(&mut self) -> u64 {
+        self.activity += 1;
+        self.activity
+    }
+
+    /// Add an account, connecting to `server_addr`. It starts parked; see
+    /// [`App::switch_account`].
+    pub fn add_account(&mut self, name: impl Into<String>, server_addr: String) -> AccountId {
+        let name = name.into();
+        #[cfg(feature = "devtools")]
+        self.record(Input::AddAccount { name: name.clone(), server_addr: server_addr.clone() }, &[
+        ]);
+        let id = AccountId(self.next_account);
+        self.next_account += 1;
+        self.parked.push(Parked {
+            id,
+            name,
+            state: ConnectionState::Disconnected,
+            server_addr,
+            rooms: HashMap::new(),
+            active_room: None,
+            directory: None,
+            activity: 0,
+            reconnects: 0,
+            invites: Vec::new(),
+        });
+        id
+    }
+
+    /// Make `account` the active account.
+    pub fn switch_account(&mut self, account: AccountId) -> Vec<AppAction> {
+        if account == self.account || !self.swap_in(account) {
+            return vec![];
+        }
+        if let Some(room) = self.active_room.and_then(|id| self.rooms.get_mut(&id)) {
+            room.mark_read();
+        }
+        self.status_message = Some(format!("Switched to {}", self.account_name));
+        vec![AppAction::Render]
+    }
+
+    /// Process an event for `account`, which need not be active.
+    ///
+    /// Actions returned apply to `account`.
+    pub fn handle_for(&mut self, account: AccountId, event: AppEvent) -> Vec<AppAction> {
+        #[cfg(feature = "devtools")]
+        let input = Input::Event { account, event: event.clone() };
+        let active = self.account;
+        let actions = if account == active {
+            self.apply(event)
+        } else if self.swap_in(account) {
+            self.background = true;
+            let actions = self.apply(event);
+            self.background = false;
+            self.swap_in(active);
+            actions
+        } else {
+            tracing::warn!(?account, "event for unknown account");
+            vec![]
+        };
+        #[cfg(feature = "devtools")]
+        self.record(input, &actions);
+        actions
+    }
+
+    /// Swap the active account's state with that of a parked account.
+    /// Returns `false` if no such account is parked.
+    fn swap_in(&mut self, account: AccountId) -> bool {
+        let Some(parked) = self.parked.iter_mut().find(|parked| parked.id == account) else {
+            return false;
+        };
+        mem::swap(&mut self.account, &mut parked.id);
+        mem::swap(&mut self.account_name, &mut parked.name);
+        mem::swap(&mut self.state, &mut parked.state);
+        mem::swap(&mut self.server_addr, &mut parked.server_addr);
+        mem::swap(&mut self.rooms, &mut parked.rooms);
+        mem::swap(&mut self.active_room, &mut parked.active_room);
+        mem::swap(&mut self.directory, &mut parked.directory);
+        mem::swap(&mut self.activity, &mut parked.activity);
+        mem::swap(&mut self.reconnects, &mut parked.reconnects);
+        mem::swap(&mut self.invites, &mut parked.invites);
+        true
     }
 
     /// Set a status message to display to the user.
     pub fn set_status(&mut self, message: impl Into<String>) {
-        self.status_message = Some(message.into());
+        let message = message.into();
+        #[cfg(feature = "devtools")]
+        self.record(Input::SetStatus(message.clone()), &[]);
+        self.status_message = Some(message);
+    }
+
+    /// Carry out an intent from a frontend.
+    ///
+    /// Intents [`Intent::check`] rejects are reported as an
+    /// [`AppEvent::Error`] instead.
+    pub fn dispatch(&mut self, intent: Intent) -> Vec<AppAction> {
+        #[cfg(feature = "devtools")]
+        let input = Input::Intent(intent.clone());
+        let actions = self.carry_out(intent);
+        #[cfg(feature = "devtools")]
+        self.record(input, &actions);
+        actions
+    }
+
+    fn carry_out(&mut self, intent: Intent) -> Vec<AppAction> {
+        if let Err(e) = intent.check(self) {
+            self.status_message = Some(format!("Error: {e}"));
+            return vec![AppAction::Render];
+        }
+        // `check` rejects intents on the active room when there is none
+        let room_id = self.active_room.unwrap_or_default();
+
+        match intent {
+            Intent::Connect => self.connect(),
+            Intent::SwitchAccount { account } => self.switch_account(account),
+            Intent::CreateRoom { room_id } => self.create_room(room_id),
+            Intent::JoinRoom { room_id } => self.join_room(room_id),
+            Intent::SelectRoom { room_id } => {
+                self.select_room(room_id);
+                vec![AppAction::Render]
+            },
+            Intent::LeaveRoom => self.leave_room(room_id),
+            Intent::PublishKeyPackage => self.publish_key_package(),
+            Intent::AddMember { user_id } => self.add_member(room_id, user_id),
+            Intent::RemoveMember { user_id } => self.remove_member(room_id, user_id),
+            Intent::InviteUser { user_id } => self.invite_user(room_id, user_id),
+            Intent::AcceptInvite { room_id } => self.answer_invite(room_id, true),
+            Intent::DeclineInvite { room_id } => self.answer_invite(room_id, false),
+            Intent::EditDraft { text, cursor } => self.edit_draft(room_id, text, cursor),
+            Intent::ReplyTo { log_index } => {
+                self.update_room(room_id, |room| room.set_reply_target(log_index))
+            },
+            Intent::Scroll { offset } => self.update_room(room_id, |room| room.set_scroll(offset)),
+            Intent::LoadHistory => self.load_history(room_id),
+            Intent::SendMessage { content } => self.send_message(room_id, content.into_bytes()),
+            Intent::EditMessage { log_index, content } => {
+                self.edit_message(room_id, log_index, content.into_bytes())
+            },
+            Intent::DeleteMessage { log_index } => self.delete_message(room_id, log_index),
+            Intent::SearchRooms { query } => self.search_directory(query),
+            Intent::NextRooms => self.next_directory_page(),
+            Intent::ListRoom { name } => self.set_room_listing(room_id, name),
+            Intent::PinRoom { pinned } => self.pin_room(room_id, pinned),
+            Intent::SortRooms { order } => self.set_room_order(order),
+            Intent::FilterRooms { filter } => self.set_room_filter(filter),
+            Intent::DismissNotice { id } => self.dismiss_notice(id),
+            Intent::Quit => self.quit(),
+        }
     }
 
     /// Initiate connection to the server.
     pub fn connect(&mut self) -> Vec<AppAction> {
         self.state = ConnectionState::Connecting;
-        vec![AppAction::Connect { server_addr: self.server_addr.clone() }, AppAction::Render]
+        vec![
+            AppAction::Connect { account: self.account, server_addr: self.server_addr.clone() },
+            AppAction::Render,
+        ]
     }
 
     /// Create a new room with the given ID.
@@ -153,9 +720,180 @@ impl App {
         vec![AppAction::AddMember { room_id, user_id }, AppAction::Render]
     }
 
+    /// Remove a member from the specified room.
+    pub fn remove_member(&mut self, room_id: RoomId, user_id: u64) -> Vec<AppAction> {
+        self.status_message = Some(format!("Removing user {user_id}..."));
+        vec![AppAction::RemoveMember { room_id, user_id }, AppAction::Render]
+    }
+
+    /// Invite a user to the specified room.
+    pub fn invite_user(&mut self, room_id: RoomId, user_id: u64) -> Vec<AppAction> {
+        self.status_message = Some(format!("Inviting user {user_id}..."));
+        vec![AppAction::InviteUser { room_id, user_id }, AppAction::Render]
+    }
+
+    /// Accept or decline the invite to a room, or the newest invite if
+    /// `room_id` is `None`. Accepting joins the room.
+    pub fn answer_invite(&mut self, room_id: Option<RoomId>, accept: bool) -> Vec<AppAction> {
+        let Some(invite) = self.invite(room_id).copied() else {
+            return vec![];
+        };
+        self.invites.retain(|pending| pending.room_id != invite.room_id);
+        if accept {
+            self.status_message = Some(format!("Joining room {}...", invite.room_id));
+            self.join_room(invite.room_id)
+        } else {
+            self.status_message = Some(format!("Declined invite to room {}", invite.room_id));
+            vec![AppAction::Render]
+        }
+    }
+
+    /// Replace the message being composed in the specified room, saving it
+    /// and telling the room's members we are typing if the text changed.
+    pub fn edit_draft(&mut self, room_id: RoomId, text: String, cursor: usize) -> Vec<AppAction> {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return vec![];
+        };
+        let save = room.draft.text != text;
+        let was_composing = !room.draft.text.is_empty();
+        if !room.set_draft(text, cursor) {
+            return vec![];
+        }
+        let mut actions = vec![AppAction::Render];
+        if save {
+            let active = !room.draft.text.is_empty();
+            actions.push(AppAction::SaveDraft { room_id, text: room.draft.text.clone() });
+            if active || was_composing {
+                actions.push(AppAction::SendTyping { room_id, active });
+            }
+        }
+        actions
+    }
+
+    /// Fetch the specified room's messages from before its oldest one,
+    /// unless they are being fetched already or there are none.
+    pub fn load_history(&mut self, room_id: RoomId) -> Vec<AppAction> {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return vec![];
+        };
+        if room.history != History::Partial {
+            return vec![];
+        }
+        match room.oldest_log_index() {
+            Some(until_log_index) if until_log_index > 0 => {
+                room.history = History::Loading { fetched: 0, total: until_log_index };
+                vec![AppAction::LoadHistory { room_id, until_log_index }, AppAction::Render]
+            },
+            _ => {
+                room.history = History::Complete;
+                vec![AppAction::Render]
+            },
+        }
+    }
+
     /// Send a message to the specified room.
-    pub fn send_message(&self, room_id: RoomId, content: Vec<u8>) -> Vec<AppAction> {
-        vec![AppAction::SendMessage { room_id, content }, AppAction::Render]
+    ///
+    /// The message replies to the draft's reply target, if set. The room's
+    /// draft and reply target are cleared and its messages scroll back to
+    /// the newest.
+    pub fn send_message(&mut self, room_id: RoomId, content: Vec<u8>) -> Vec<AppAction> {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return vec![
+                AppAction::SendMessage { room_id, content, reply_to: None },
+                AppAction::Render,
+            ];
+        };
+        room.scroll = 0;
+        let draft = mem::take(&mut room.draft);
+        // A target not held is hashed as empty, so receivers holding it see
+        // a mismatch rather than a false match
+        let reply_to = draft.reply_to.map(|log_index| {
+            ReplyTo::new(log_index, room.message(log_index).map_or(&[], |m| &m.content))
+        });
+
+        let mut actions =
+            vec![AppAction::SendMessage { room_id, content, reply_to }, AppAction::Render];
+        if !draft.text.is_empty() {
+            actions.push(AppAction::SaveDraft { room_id, text: String::new() });
+        }
+        actions
+    }
+
+    /// Edit one of our messages in the specified room.
+    pub fn edit_message(
+        &self,
+        room_id: RoomId,
+        target_log_index: u64,
+        content: Vec<u8>,
+    ) -> Vec<AppAction> {
+        vec![AppAction::EditMessage { room_id, target_log_index, content }, AppAction::Render]
+    }
+
+    /// Delete one of our messages in the specified room.
+    pub fn delete_message(&self, room_id: RoomId, target_log_index: u64) -> Vec<AppAction> {
+        vec![AppAction::DeleteMessage { room_id, target_log_index }, AppAction::Render]
+    }
+
+    /// Open the room directory with rooms whose names contain `query`.
+    pub fn search_directory(&mut self, query: String) -> Vec<AppAction> {
+        self.directory = Some(Directory { query: query.clone(), rooms: Vec::new(), next: None });
+        self.status_message = Some("Searching rooms...".to_string());
+        vec![AppAction::SearchDirectory { query, after: None }, AppAction::Render]
+    }
+
+    /// Fetch the next page of the open directory search.
+    pub fn next_directory_page(&mut self) -> Vec<AppAction> {
+        let Some(directory) = &self.directory else {
+            self.status_message = Some("No room search open".to_string());
+            return vec![AppAction::Render];
+        };
+        let Some(after) = directory.next else {
+            self.status_message = Some("No more rooms".to_string());
+            return vec![AppAction::Render];
+        };
+        vec![AppAction::SearchDirectory { query: directory.query.clone(), after: Some(after) }]
+    }
+
+    /// Close the room directory.
+    pub fn close_directory(&mut self) {
+        #[cfg(feature = "devtools")]
+        self.record(Input::CloseDirectory, &[]);
+        self.directory = None;
+    }
+
+    /// List the specified room in the directory under `name`, or remove it
+    /// with `None`.
+    pub fn set_room_listing(&mut self, room_id: RoomId, name: Option<String>) -> Vec<AppAction> {
+        self.status_message = Some(match &name {
+            Some(name) => format!("Listing room as {name}"),
+            None => "Removing room from directory".to_string(),
+        });
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.name.clone_from(&name);
+        }
+        vec![AppAction::SetRoomListing { room_id, name }, AppAction::Render]
+    }
+
+    /// Pin a room to the top of the room list, or unpin it.
+    pub fn pin_room(&mut self, room_id: RoomId, pinned: bool) -> Vec<AppAction> {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return vec![];
+        };
+        room.pinned = pinned;
+        vec![AppAction::Render]
+    }
+
+    /// Change the order of the room list.
+    pub fn set_room_order(&mut self, order: RoomOrder) -> Vec<AppAction> {
+        self.room_order = order;
+        vec![AppAction::Render]
+    }
+
+    /// Show only rooms whose names contain `filter`, ignoring case. An empty
+    /// filter shows every room.
+    pub fn set_room_filter(&mut self, filter: impl Into<String>) -> Vec<AppAction> {
+        self.room_filter = filter.into();
+        vec![AppAction::Render]
     }
 
     /// Quit the application.
@@ -165,15 +903,99 @@ impl App {
 
     /// Set the active room.
     pub fn set_active_room(&mut self, room_id: RoomId) {
+        #[cfg(feature = "devtools")]
+        self.record(Input::SelectRoom(room_id), &[]);
+        self.select_room(room_id);
+    }
+
+    fn select_room(&mut self, room_id: RoomId) {
         if self.rooms.contains_key(&room_id) {
             self.active_room = Some(room_id);
+            self.directory = None;
             if let Some(room) = self.rooms.get_mut(&room_id) {
-                room.unread = false;
+                room.mark_read();
             }
         }
     }
 
-    /// Current connection state.
+    /// Change what counts as a mention of the user.
+    pub fn set_mentions(&mut self, mentions: Mentions) {
+        #[cfg(feature = "devtools")]
+        self.record(Input::SetMentions(mentions.clone()), &[]);
+        self.mentions = mentions;
+    }
+
+    /// What counts as a mention of the user.
+    pub fn mentions(&self) -> &Mentions {
+        &self.mentions
+    }
+
+    /// Joined rooms matching the room filter, pinned rooms first, then in
+    /// the room order.
+    pub fn room_list(&self) -> Vec<RoomId> {
+        let filter = self.room_filter.to_lowercase();
+        let mut rooms: Vec<&RoomState> = self
+            .rooms
+            .values()
+            .filter(|room| room.display_name().to_lowercase().contains(&filter))
+            .collect();
+        rooms.sort_by(|a, b| {
+            b.pinned.cmp(&a.pinned).then_with(|| match self.room_order {
+                RoomOrder::Recent => b.last_activity.cmp(&a.last_activity),
+                RoomOrder::Alphabetical => {
+                    let name = |room: &RoomState| room.name.as_ref().map(|n| n.to_lowercase());
+                    // Named rooms before unnamed ones
+                    (name(a).is_none(), name(a))
+                        .cmp(&(name(b).is_none(), name(b)))
+                        .then(a.room_id.cmp(&b.room_id))
+                },
+            })
+        });
+        rooms.into_iter().map(|room| room.room_id).collect()
+    }
+
+    /// Order of the room list.
+    pub fn room_order(&self) -> RoomOrder {
+        self.room_order
+    }
+
+    /// Text room list entries must contain. Empty shows every room.
+    pub fn room_filter(&self) -> &str {
+        &self.room_filter
+    }
+
+    /// Unread messages across all rooms.
+    pub fn total_unread(&self) -> usize {
+        self.rooms.values().map(|room| room.unread).sum()
+    }
+
+    /// Active account.
+    pub fn active_account(&self) -> AccountId {
+        self.account
+    }
+
+    /// All accounts, in the order they were added.
+    pub fn accounts(&self) -> Vec<AccountSummary> {
+        let active = AccountSummary {
+            id: self.account,
+            name: self.account_name.clone(),
+            server_addr: self.server_addr.clone(),
+            state: self.state.clone(),
+            unread: self.total_unread(),
+        };
+        let parked = self.parked.iter().map(|parked| AccountSummary {
+            id: parked.id,
+            name: parked.name.clone(),
+            server_addr: parked.server_addr.clone(),
+            state: parked.state.clone(),
+            unread: parked.rooms.values().map(|room| room.unread).sum(),
+        });
+        let mut accounts: Vec<AccountSummary> = parked.chain([active]).collect();
+        accounts.sort_by_key(|account| account.id);
+        accounts
+    }
+
+    /// Current connection state of the active account.
     pub fn connection_state(&self) -> &ConnectionState {
         &self.state
     }
@@ -207,15 +1029,248 @@ impl App {
     pub fn status_message(&self) -> Option<&str> {
         self.status_message.as_deref()
     }
+
+    /// Invites waiting for an answer, oldest first.
+    pub fn invites(&self) -> &[PendingInvite] {
+        &self.invites
+    }
+
+    /// The invite to a room, or the newest invite if `room_id` is `None`.
+    pub fn invite(&self, room_id: Option<RoomId>) -> Option<&PendingInvite> {
+        match room_id {
+            Some(room_id) => self.invites.iter().find(|invite| invite.room_id == room_id),
+            None => self.invites.last(),
+        }
+    }
+
+    /// Notices not yet dismissed, oldest first. Only the last
+    /// [`MAX_NOTICES`] are kept.
+    pub fn notices(&self) -> &VecDeque<Notice> {
+        &self.notices
+    }
+
+    /// Dismiss a notice, or every notice if `id` is `None`.
+    pub fn dismiss_notice(&mut self, id: Option<u64>) -> Vec<AppAction> {
+        let before = self.notices.len();
+        self.notices.retain(|notice| id.is_some_and(|id| notice.id != id));
+        if self.notices.len() == before { vec![] } else { vec![AppAction::Render] }
+    }
+
+    /// Room directory being browsed. `None` if the directory is closed.
+    pub fn directory(&self) -> Option<&Directory> {
+        self.directory.as_ref()
+    }
+
+    /// Capture the App's state for a crash report, with message content and
+    /// drafts redacted.
+    pub fn snapshot(&self) -> AppSnapshot {
+        let mut snapshot = self.snapshot_with_content();
+        snapshot.redact();
+        snapshot
+    }
+
+    /// Capture the App's state including message content, for checkpoints
+    /// that stay with the user.
+    pub fn snapshot_with_content(&self) -> AppSnapshot {
+        let active = Parked {
+            id: self.account,
+            name: self.account_name.clone(),
+            state: self.state.clone(),
+            server_addr: self.server_addr.clone(),
+            rooms: self.rooms.clone(),
+            active_room: self.active_room,
+            directory: self.directory.clone(),
+            activity: self.activity,
+            reconnects: self.reconnects,
+            invites: self.invites.clone(),
+        };
+        AppSnapshot {
+            active: active.into(),
+            parked: self.parked.iter().cloned().map(AccountSnapshot::from).collect(),
+            next_account: self.next_account,
+            terminal_size: self.terminal_size,
+            status_message: self.status_message.clone(),
+            notices: self.notices.iter().cloned().collect(),
+            next_notice: self.next_notice,
+            mentions: self.mentions.clone(),
+            room_order: self.room_order,
+            room_filter: self.room_filter.clone(),
+            redacted: false,
+        }
+    }
+
+    /// Rebuild an App from a snapshot.
+    ///
+    /// With the `devtools` feature the event log starts empty, so it only
+    /// reproduces the App when replayed on top of the same snapshot.
+    pub fn restore(snapshot: AppSnapshot) -> Self {
+        let AppSnapshot {
+            active,
+            parked,
+            next_account,
+            terminal_size,
+            status_message,
+            notices,
+            next_notice,
+            mentions,
+            room_order,
+            room_filter,
+            redacted: _,
+        } = snapshot;
+        let active = Parked::from(active);
+
+        Self {
+            account: active.id,
+            account_name: active.name,
+            parked: parked.into_iter().map(Parked::from).collect(),
+            next_account,
+            background: false,
+            state: active.state,
+            rooms: active.rooms,
+            active_room: active.active_room,
+            terminal_size,
+            status_message,
+            notices: notices.into(),
+            next_notice,
+            directory: active.directory,
+            mentions,
+            room_order,
+            room_filter,
+            activity: active.activity,
+            reconnects: active.reconnects,
+            invites: active.invites,
+            #[cfg(feature = "devtools")]
+            log: EventLog::new(active.server_addr.clone()),
+            server_addr: active.server_addr,
+        }
+    }
+}
+
+impl From<Parked> for AccountSnapshot {
+    fn from(parked: Parked) -> Self {
+        let mut rooms: Vec<RoomState> = parked.rooms.into_values().collect();
+        rooms.sort_by_key(|room| room.room_id);
+        Self {
+            id: parked.id,
+            name: parked.name,
+            server_addr: parked.server_addr,
+            state: parked.state,
+            rooms,
+            active_room: parked.active_room,
+            directory: parked.directory,
+            activity: parked.activity,
+            reconnects: parked.reconnects,
+            invites: parked.invites,
+        }
+    }
+}
+
+impl From<AccountSnapshot> for Parked {
+    fn from(account: AccountSnapshot) -> Self {
+        Self {
+            id: account.id,
+            name: account.name,
+            state: account.state,
+            server_addr: account.server_addr,
+            rooms: account.rooms.into_iter().map(|room| (room.room_id, room)).collect(),
+            active_room: account.active_room,
+            directory: account.directory,
+            activity: account.activity,
+            reconnects: account.reconnects,
+            invites: account.invites,
+        }
+    }
+}
+
+#[cfg(feature = "devtools")]
+impl App {
+    /// Inputs handled so far.
+    pub fn event_log(&self) -> &EventLog {
+        &self.log
+    }
+
+    /// Undo the last input. Returns `false` if there is none.
+    ///
+    /// The App is rebuilt by replaying its log without the last entry. The
+    /// entry is kept, so [`App::step_forward`] can redo it until a new input
+    /// is handled.
+    pub fn step_back(&mut self) -> bool {
+        let Some(applied) = self.log.applied().checked_sub(1) else {
+            return false;
+        };
+        let log = self.log.clone();
+        *self = log.replay_to(applied);
+        self.log = log;
+        self.log.set_applied(applied);
+        true
+    }
+
+    /// Redo an input undone by [`App::step_back`]. Returns `false` if there
+    /// is none.
+    pub fn step_forward(&mut self) -> bool {
+        let Some(entry) = self.log.entries().get(self.log.applied()).cloned() else {
+            return false;
+        };
+        let log = self.log.clone();
+        self.apply_input(entry.input);
+        let applied = self.log.applied();
+        self.log = log;
+        self.log.set_applied(applied);
+        true
+    }
+
+    /// Handle a recorded input the way it was first handled.
+    pub(crate) fn apply_input(&mut self, input: Input) -> Vec<AppAction> {
+        match input {
+            Input::Event { account, event } => self.handle_for(account, event),
+            Input::Intent(intent) => self.dispatch(intent),
+            Input::SelectRoom(room_id) => {
+                self.set_active_room(room_id);
+                vec![]
+            },
+            Input::CloseDirectory => {
+                self.close_directory();
+                vec![]
+            },
+            Input::SetStatus(message) => {
+                self.set_status(message);
+                vec![]
+            },
+            Input::SetMentions(mentions) => {
+                self.set_mentions(mentions);
+                vec![]
+            },
+            Input::AddAccount { name, server_addr } => {
+                self.add_account(name, server_addr);
+                vec![]
+            },
+        }
+    }
+
+    fn record(&mut self, input: Input, actions: &[AppAction]) {
+        // Ticks change nothing and would swamp the log
+        if !matches!(input, Input::Event { event: AppEvent::Tick, .. }) {
+            self.log.push(input, actions.to_vec());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use lockframe_proto::payloads::{app::Attachment, session::DirectoryEntry};
+
     use super::*;
+    use crate::{AccountId, CredentialKind, Delivery, Draft, IntentError, Member, ReactionChange};
 
     fn connected_app() -> App {
         let mut app = App::new("localhost:8080".into());
-        app.state = ConnectionState::Connected { session_id: 1, sender_id: 42 };
+        app.state = ConnectionState::Connected {
+            session_id: 1,
+            sender_id: 42,
+            quality: ConnectionQuality::default(),
+        };
         app
     }
 
@@ -226,7 +1281,10 @@ mod tests {
         let _ = app.handle(AppEvent::MessageReceived {
             room_id: 1,
             sender_id: 42,
+            log_index: Some(0),
             content: b"hello".to_vec(),
+            reply_to: None,
+            timestamp: 0,
         });
 
         assert_eq!(app.rooms.get(&1).map(|r| r.messages.len()), Some(1));
@@ -236,6 +1294,527 @@ mod tests {
         assert_eq!(app.rooms.get(&1).map(|r| r.messages.len()), Some(1));
     }
 
+    #[test]
+    fn attachments_arrive_as_messages_named_after_the_file() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 2 });
+        let attachment = Attachment {
+            filename: "cat.png".to_string(),
+            size: 4,
+            hash: [1; 32],
+            key: [2; 32],
+            nonce: [3; 24],
+            ciphertext: vec![4; 20],
+        };
+
+        let actions = app.handle(AppEvent::AttachmentReceived {
+            room_id: 2,
+            sender_id: 7,
+            log_index: 3,
+            attachment: attachment.clone(),
+            timestamp: 0,
+        });
+        assert!(actions.iter().any(|action| matches!(
+            action,
+            AppAction::Notify(Notification::Message { preview, .. }) if preview == "cat.png"
+        )));
+        let message = app.rooms[&2].message(3).unwrap();
+        assert_eq!(message.content_str(), "cat.png");
+        assert_eq!(message.attachment.as_ref(), Some(&attachment));
+
+        // Deleting it takes the key with it
+        let _ =
+            app.handle(AppEvent::MessageDeleted { room_id: 2, sender_id: 7, target_log_index: 3 });
+        assert_eq!(app.rooms[&2].message(3).unwrap().attachment, None);
+    }
+
+    #[test]
+    fn intents_are_checked_against_state() {
+        let mut app = connected_app();
+        let actions = app.dispatch(Intent::AddMember { user_id: 7 });
+        assert_eq!(actions, vec![AppAction::Render]);
+        assert_eq!(app.status_message(), Some("Error: no active room, join or select one first"));
+
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        assert_eq!(app.dispatch(Intent::AddMember { user_id: 7 })[0], AppAction::AddMember {
+            room_id: 1,
+            user_id: 7
+        });
+        let _ = app.handle(AppEvent::MemberAdded { room_id: 1, member_id: 7 });
+        assert_eq!(
+            Intent::AddMember { user_id: 7 }.check(&app),
+            Err(IntentError::AlreadyMember { user_id: 7 })
+        );
+
+        assert_eq!(
+            Intent::CreateRoom { room_id: 1 }.check(&app),
+            Err(IntentError::AlreadyInRoom { room_id: 1 })
+        );
+        assert_eq!(Intent::Connect.check(&app), Err(IntentError::AlreadyConnected));
+        assert_eq!(
+            Intent::SendMessage { content: "  ".into() }.check(&app),
+            Err(IntentError::EmptyMessage)
+        );
+        assert_eq!(Intent::NextRooms.check(&app), Err(IntentError::NoDirectory));
+
+        // Only our own messages can be edited
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(0),
+            content: b"hi".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+        let edit = Intent::EditMessage { log_index: 0, content: "hello".into() };
+        assert_eq!(edit.check(&app), Err(IntentError::NoSuchMessage { log_index: 0 }));
+    }
+
+    #[test]
+    fn accounts_keep_separate_rooms_and_connections() {
+        let mut app = connected_app();
+        let personal = app.active_account();
+        let work = app.add_account("work", "work.example:4433".into());
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+
+        // Events for a parked account land in its own namespace
+        let _ = app.handle_for(work, AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle_for(work, AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            content: b"standup?".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+            log_index: Some(0),
+        });
+        assert!(app.rooms[&1].messages.is_empty());
+        let accounts = app.accounts();
+        assert_eq!(accounts.iter().map(|a| (a.id, a.unread)).collect::<Vec<_>>(), vec![
+            (personal, 0),
+            (work, 1)
+        ]);
+        assert_eq!(accounts[1].state, ConnectionState::Disconnected);
+
+        assert_eq!(app.dispatch(Intent::SwitchAccount { account: work }), vec![AppAction::Render]);
+        assert_eq!(app.active_account(), work);
+        assert_eq!(app.server_addr(), "work.example:4433");
+        assert_eq!(app.rooms[&1].messages.len(), 1);
+        assert_eq!(app.rooms[&1].unread, 0, "read once on screen");
+        assert!(matches!(
+            app.connect().as_slice(),
+            [AppAction::Connect { account, .. }, AppAction::Render] if *account == work
+        ));
+
+        let _ = app.dispatch(Intent::SwitchAccount { account: AccountId(9) });
+        assert_eq!(app.status_message(), Some("Error: no account 9"));
+        assert_eq!(app.active_account(), work);
+    }
+
+    #[test]
+    fn own_messages_move_through_delivery_states() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::MessageSending {
+            room_id: 1,
+            sender_id: 42,
+            local_id: 7,
+            content: b"hi".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+            delivery: Delivery::Pending,
+        });
+        let delivery = |app: &App| app.rooms[&1].messages[0].delivery;
+        assert_eq!(delivery(&app), Delivery::Pending);
+
+        let changed = |app: &mut App, delivery, log_index| {
+            app.handle(AppEvent::DeliveryChanged { room_id: 1, local_id: 7, delivery, log_index })
+        };
+        assert!(changed(&mut app, Delivery::Sent, None).contains(&AppAction::Render));
+        assert!(changed(&mut app, Delivery::Sent, None).is_empty(), "no change");
+        let _ = changed(&mut app, Delivery::Delivered, Some(3));
+        assert_eq!(delivery(&app), Delivery::Delivered);
+
+        // Delivered messages can be edited by log index
+        let _ = app.handle(AppEvent::MessageEdited {
+            room_id: 1,
+            sender_id: 42,
+            target_log_index: 3,
+            content: b"hello".to_vec(),
+        });
+        assert_eq!(app.rooms[&1].messages[0].content, b"hello");
+    }
+
+    #[test]
+    fn unread_messages_and_mentions_notify_until_read() {
+        let mut app = connected_app();
+        app.set_mentions(Mentions { keywords: vec!["Alice".into()], user_id: true });
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 2 });
+        let mut receive = |room_id, sender_id, text: &str| {
+            app.handle(AppEvent::MessageReceived {
+                room_id,
+                sender_id,
+                log_index: Some(0),
+                content: text.as_bytes().to_vec(),
+                reply_to: None,
+                timestamp: 0,
+            })
+        };
+        let notified = |actions: Vec<AppAction>| {
+            actions.into_iter().find_map(|action| match action {
+                AppAction::Notify(notification) => Some(notification),
+                _ => None,
+            })
+        };
+
+        // Active room, no mention: nothing to surface
+        assert!(notified(receive(1, 7, "hello")).is_none());
+        // Our own messages never notify
+        assert!(notified(receive(2, 42, "alice?")).is_none());
+        // Mentions notify even in the active room
+        assert!(notified(receive(1, 7, "ping @42")).is_some_and(|n| n.is_urgent()));
+
+        let notification = notified(receive(2, 7, "hey ALICE, look")).unwrap();
+        assert!(matches!(
+            notification,
+            Notification::Message { mention: true, ref preview, .. } if preview == "hey ALICE, look"
+        ));
+        assert!(notified(receive(2, 7, "malice")).is_some_and(|n| !n.is_urgent()));
+
+        let room = &app.rooms()[&2];
+        assert_eq!((room.unread, room.mentions), (2, 1));
+        assert_eq!(app.rooms()[&1].unread, 0);
+        assert_eq!(app.total_unread(), 2);
+
+        app.set_active_room(2);
+        assert_eq!(app.total_unread(), 0);
+        assert_eq!(app.rooms()[&2].mentions, 0);
+    }
+
+    #[test]
+    fn invites_and_room_errors_notify_urgently() {
+        let mut app = connected_app();
+        let notified = |actions: Vec<AppAction>| {
+            actions.into_iter().find_map(|action| match action {
+                AppAction::Notify(notification) => Some(notification),
+                _ => None,
+            })
+        };
+
+        let invite = notified(app.handle(AppEvent::Invited { room_id: 5 })).unwrap();
+        assert!(invite.is_urgent());
+        assert_eq!(invite.room_id(), 5);
+
+        let error =
+            notified(app.handle(AppEvent::RoomError { room_id: 5, message: "removed".into() }))
+                .unwrap();
+        assert!(
+            matches!(error, Notification::RoomError { ref message, .. } if message == "removed")
+        );
+        assert_eq!(app.notices()[0].room_id, Some(5));
+    }
+
+    #[test]
+    fn invites_wait_for_an_answer() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        assert_eq!(app.dispatch(Intent::InviteUser { user_id: 7 })[0], AppAction::InviteUser {
+            room_id: 1,
+            user_id: 7
+        });
+        assert_eq!(Intent::AcceptInvite { room_id: None }.check(&app), Err(IntentError::NoInvite));
+
+        let invite = |room_id| AppEvent::InviteReceived { room_id, inviter: 7, expires_at: 0 };
+        // Rooms we are in already need no invite
+        assert!(app.handle(invite(1)).is_empty());
+        let actions = app.handle(invite(2));
+        assert!(actions.contains(&AppAction::Notify(Notification::Invite { room_id: 2 })));
+        let _ = app.handle(invite(3));
+        assert_eq!(app.invites().len(), 2);
+
+        assert_eq!(app.dispatch(Intent::DeclineInvite { room_id: Some(2) }), vec![
+            AppAction::Render
+        ]);
+        assert_eq!(app.dispatch(Intent::AcceptInvite { room_id: None }), vec![
+            AppAction::JoinRoom { room_id: 3 },
+            AppAction::Render
+        ]);
+        assert!(app.invites().is_empty());
+    }
+
+    #[test]
+    fn notices_queue_until_dismissed() {
+        let mut app = connected_app();
+        let failed = || AppEvent::Error { message: "send failed".into(), error_id: None };
+        let _ = app.handle(failed());
+        let _ = app.handle(failed());
+        assert_eq!(app.notices().len(), 1);
+        assert_eq!(app.notices()[0].count, 2);
+
+        for n in 0..MAX_NOTICES {
+            let _ = app.handle(AppEvent::Notice {
+                severity: Severity::Warning,
+                room_id: None,
+                message: format!("notice {n}"),
+            });
+        }
+        assert_eq!(app.notices().len(), MAX_NOTICES);
+        assert!(app.notices().iter().all(|notice| notice.severity == Severity::Warning));
+
+        let newest = app.notices().back().map(|notice| notice.id);
+        assert_eq!(app.dispatch(Intent::DismissNotice { id: newest }), vec![AppAction::Render]);
+        assert_eq!(app.notices().len(), MAX_NOTICES - 1);
+        assert_eq!(app.dispatch(Intent::DismissNotice { id: newest }), vec![]);
+
+        let _ = app.dispatch(Intent::DismissNotice { id: None });
+        assert!(app.notices().is_empty());
+    }
+
+    #[test]
+    fn error_notices_show_the_error_id() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::Error {
+            message: "send failed".into(),
+            error_id: Some(lockframe_proto::ErrorId::new(0x7f3a)),
+        });
+        assert_eq!(app.notices()[0].message, "send failed (err 7f3a)");
+    }
+
+    #[test]
+    fn server_notices_are_kept_apart_from_client_notices() {
+        let mut app = connected_app();
+        let text = "Restarting at 02:00 UTC";
+        let _ = app.handle(AppEvent::Notice {
+            severity: Severity::Warning,
+            room_id: None,
+            message: text.into(),
+        });
+        let _ = app.handle(AppEvent::ServerNotice {
+            room_id: None,
+            kind: NoticeKind::Maintenance,
+            text: text.into(),
+        });
+
+        let notices = app.notices();
+        assert_eq!(notices.len(), 2);
+        assert_eq!(notices[0].server, None);
+        assert_eq!(notices[1].server, Some(NoticeKind::Maintenance));
+        assert_eq!(notices[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn snapshots_restore_every_account_and_redact_content() {
+        let mut app = connected_app();
+        let work = app.add_account("work", "work.example:4433".into());
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(0),
+            content: b"secret plans".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+        let _ = app.edit_draft(1, "half a thought".into(), 4);
+        let _ = app.handle_for(work, AppEvent::RoomJoined { room_id: 2 });
+        let _ = app.handle(AppEvent::Error { message: "send failed".into(), error_id: None });
+
+        let snapshot = app.snapshot();
+        assert!(snapshot.is_redacted());
+        assert_eq!(snapshot.accounts().collect::<Vec<_>>(), vec![app.active_account(), work]);
+
+        let restored = App::restore(snapshot);
+        let room = &restored.rooms()[&1];
+        assert_eq!(room.messages.len(), 1);
+        assert!(room.messages[0].content.is_empty());
+        assert_eq!(room.messages[0].sender_id, 7);
+        assert_eq!(room.draft, Draft::default());
+        assert_eq!(restored.active_room(), app.active_room());
+        assert_eq!(restored.notices(), app.notices());
+        assert_eq!(restored.accounts(), app.accounts());
+
+        // Checkpoints keep content, and the parked account comes back too
+        let mut restored = App::restore(app.snapshot_with_content());
+        assert_eq!(restored.rooms()[&1].messages[0].content, b"secret plans");
+        assert_eq!(restored.rooms()[&1].draft.text, "half a thought");
+        let _ = restored.switch_account(work);
+        assert!(restored.rooms().contains_key(&2));
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn snapshots_serialize() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: u128::MAX });
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: u128::MAX,
+            sender_id: 7,
+            log_index: Some(0),
+            content: b"secret plans".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+
+        let json = serde_json::to_string(&app.snapshot()).unwrap();
+        let content = serde_json::to_string(b"secret plans").unwrap();
+        assert!(!json.contains(content.trim_matches(['[', ']'])));
+        let restored = App::restore(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.rooms()[&u128::MAX].messages.len(), 1);
+        assert_eq!(restored.accounts(), app.accounts());
+    }
+
+    #[test]
+    fn room_list_puts_pinned_rooms_first_then_orders_and_filters() {
+        let mut app = connected_app();
+        for room_id in [1, 2, 3] {
+            let _ = app.handle(AppEvent::RoomJoined { room_id });
+        }
+        let _ = app.set_room_listing(3, Some("alpha".into()));
+        let _ = app.set_room_listing(1, Some("Beta".into()));
+        assert_eq!(app.room_list(), vec![3, 1, 2]);
+
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(0),
+            content: b"hi".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+        let _ = app.set_room_order(RoomOrder::Recent);
+        assert_eq!(app.room_list(), vec![1, 3, 2]);
+
+        let _ = app.pin_room(2, true);
+        assert_eq!(app.room_list(), vec![2, 1, 3]);
+
+        let _ = app.set_room_filter("ALP");
+        assert_eq!(app.room_list(), vec![3]);
+    }
+
+    #[test]
+    fn edit_and_delete_only_apply_to_own_messages() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(3),
+            content: b"helo".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+
+        let actions = app.handle(AppEvent::MessageEdited {
+            room_id: 1,
+            sender_id: 8,
+            target_log_index: 3,
+            content: b"hijacked".to_vec(),
+        });
+        assert!(actions.is_empty());
+
+        let _ = app.handle(AppEvent::MessageEdited {
+            room_id: 1,
+            sender_id: 7,
+            target_log_index: 3,
+            content: b"hello".to_vec(),
+        });
+        let message = &app.rooms[&1].messages[0];
+        assert_eq!(message.content, b"hello");
+        assert!(message.edited);
+
+        let _ =
+            app.handle(AppEvent::MessageDeleted { room_id: 1, sender_id: 7, target_log_index: 3 });
+        let message = &app.rooms[&1].messages[0];
+        assert!(message.deleted);
+        assert!(message.content.is_empty());
+    }
+
+    #[test]
+    fn reactions_are_counted_even_before_their_message_arrives() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let react = |sender_id, content: &str, add| AppEvent::ReactionChanged {
+            room_id: 1,
+            target_log_index: 3,
+            change: ReactionChange { sender_id, content: content.to_string(), add },
+        };
+
+        // Nothing to render until the message is there
+        assert!(app.handle(react(7, "+1", true)).is_empty());
+        let _ = app.handle(react(8, "+1", true));
+        let _ = app.handle(react(8, "+1", false));
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 9,
+            log_index: Some(3),
+            content: b"lunch?".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+        let counts = |app: &App| -> Vec<(String, usize)> {
+            app.rooms[&1].messages[0]
+                .reaction_counts()
+                .map(|(content, count)| (content.to_string(), count))
+                .collect()
+        };
+        assert_eq!(counts(&app), vec![("+1".to_string(), 1)]);
+        assert!(app.rooms[&1].pending_reactions.is_empty());
+
+        // Reacting twice counts once
+        assert!(app.handle(react(7, "+1", true)).is_empty());
+        assert_eq!(app.handle(react(8, "+1", true)), vec![AppAction::Render]);
+        let _ = app.handle(react(8, "no", true));
+        assert_eq!(counts(&app), vec![("+1".to_string(), 2), ("no".to_string(), 1)]);
+
+        let _ = app.handle(react(8, "no", false));
+        assert_eq!(counts(&app), vec![("+1".to_string(), 2)]);
+    }
+
+    #[test]
+    fn expired_message_is_removed() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(3),
+            content: b"gone soon".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+
+        let actions = app.handle(AppEvent::MessageExpired { room_id: 1, log_index: 3 });
+        assert_eq!(actions, vec![AppAction::Render]);
+        assert!(app.rooms[&1].messages.is_empty());
+    }
+
+    #[test]
+    fn directory_pages_accumulate_until_closed() {
+        let mut app = connected_app();
+        let entry = |room_id| DirectoryEntry { room_id, name: "lobby".into(), member_count: 3 };
+
+        let actions = app.search_directory("lob".into());
+        assert_eq!(actions[0], AppAction::SearchDirectory { query: "lob".into(), after: None });
+
+        let _ = app.handle(AppEvent::DirectoryResults { rooms: vec![entry(1)], next: Some(1) });
+        assert_eq!(app.next_directory_page(), vec![AppAction::SearchDirectory {
+            query: "lob".into(),
+            after: Some(1)
+        }]);
+
+        let _ = app.handle(AppEvent::DirectoryResults { rooms: vec![entry(2)], next: None });
+        let directory = app.directory().unwrap();
+        assert_eq!(directory.rooms.len(), 2);
+        assert_eq!(app.next_directory_page(), vec![AppAction::Render]);
+
+        // Joining a room closes the directory, and late results are ignored
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 2 });
+        assert!(app.directory().is_none());
+        let actions = app.handle(AppEvent::DirectoryResults { rooms: vec![entry(3)], next: None });
+        assert!(actions.is_empty());
+    }
+
     #[test]
     fn api_create_room() {
         let mut app = connected_app();
@@ -282,7 +1861,7 @@ mod tests {
 
     #[test]
     fn api_send_message() {
-        let app = connected_app();
+        let mut app = connected_app();
         let actions = app.send_message(100, b"hello".to_vec());
 
         assert!(matches!(actions.as_slice(), [
@@ -291,6 +1870,203 @@ mod tests {
         ]));
     }
 
+    #[test]
+    fn heartbeats_and_reconnects_update_connection_quality() {
+        let mut app = connected_app();
+        let quality = |app: &App| match app.connection_state() {
+            ConnectionState::Connected { quality, .. } => *quality,
+            _ => panic!("not connected"),
+        };
+
+        let _ = app.handle(AppEvent::HeartbeatMissed { missed: 2 });
+        assert!(quality(&app).is_degraded());
+        let _ = app.handle(AppEvent::HeartbeatAnswered { rtt: Duration::from_millis(30) });
+        assert!(!quality(&app).is_degraded());
+        assert_eq!(quality(&app).rtt, Some(Duration::from_millis(30)));
+
+        let _ = app.handle(AppEvent::Disconnected);
+        let _ = app.handle(AppEvent::SessionRestore { step: RestoreStep::Authenticating });
+        let _ = app.handle(AppEvent::Connected { session_id: 2, sender_id: 42 });
+        assert_eq!(quality(&app), ConnectionQuality { reconnects: 1, ..Default::default() });
+
+        let syncing = RestoreStep::Syncing { remaining: 2 };
+        let _ = app.handle(AppEvent::SessionRestore { step: syncing });
+        assert_eq!(quality(&app).restoring, Some(syncing));
+        let _ = app.handle(AppEvent::SessionRestore { step: RestoreStep::Restored });
+        assert_eq!(quality(&app).restoring, None);
+    }
+
+    #[test]
+    fn epoch_changes_update_the_room() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+
+        let actions = app.handle(AppEvent::EpochChanged { room_id: 1, epoch: 3 });
+        assert_eq!(actions, vec![AppAction::Render]);
+        assert_eq!(app.rooms()[&1].epoch, 3);
+        assert_eq!(app.handle(AppEvent::EpochChanged { room_id: 1, epoch: 3 }), vec![]);
+    }
+
+    #[test]
+    fn members_follow_the_group() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let member = |user_id, signature_key| Member {
+            user_id,
+            credential: CredentialKind::Basic,
+            signature_key,
+        };
+
+        let members = vec![member(42, Some([0; 32])), member(7, None)];
+        let actions = app.handle(AppEvent::MembersChanged { room_id: 1, members });
+        assert_eq!(actions, vec![AppAction::Render]);
+        assert_eq!(app.rooms()[&1].sorted_members(), vec![7, 42]);
+        assert_eq!(app.dispatch(Intent::RemoveMember { user_id: 7 })[0], AppAction::RemoveMember {
+            room_id: 1,
+            user_id: 7
+        });
+
+        let members = vec![member(42, Some([0xff; 32]))];
+        let _ = app.handle(AppEvent::MembersChanged { room_id: 1, members });
+        assert_eq!(
+            Intent::RemoveMember { user_id: 7 }.check(&app),
+            Err(IntentError::NotMember { user_id: 7 })
+        );
+        assert_eq!(
+            app.rooms()[&1].roster[&42].safety_code().as_deref(),
+            Some("27775 27775 27775 27775 27775 27775")
+        );
+        assert_eq!(app.rooms()[&1].roster.get(&7), None);
+    }
+
+    #[test]
+    fn drafts_stay_with_their_room() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 2 });
+
+        let actions = app.dispatch(Intent::EditDraft { text: "half typed".into(), cursor: 4 });
+        assert!(actions.contains(&AppAction::SaveDraft { room_id: 1, text: "half typed".into() }));
+        let _ = app.dispatch(Intent::SelectRoom { room_id: 2 });
+        let _ = app.handle(AppEvent::Disconnected);
+        let _ = app.dispatch(Intent::SelectRoom { room_id: 1 });
+        assert_eq!(app.rooms()[&1].draft.text, "half typed");
+        assert_eq!(app.rooms()[&1].draft.cursor, 4);
+
+        // A saved draft doesn't replace one started since
+        let _ = app.handle(AppEvent::DraftRestored { room_id: 1, text: "older".into() });
+        assert_eq!(app.rooms()[&1].draft.text, "half typed");
+        let _ = app.handle(AppEvent::DraftRestored { room_id: 2, text: "older".into() });
+        assert_eq!(app.rooms()[&2].draft.text, "older");
+
+        let actions = app.dispatch(Intent::SendMessage { content: "half typed".into() });
+        assert!(actions.contains(&AppAction::SaveDraft { room_id: 1, text: String::new() }));
+        assert_eq!(app.rooms()[&1].draft, Draft::default());
+    }
+
+    #[test]
+    fn history_loads_before_the_oldest_message() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 2 });
+        let message = |room_id, log_index: u64| AppEvent::MessageReceived {
+            room_id,
+            sender_id: 7,
+            log_index: Some(log_index),
+            content: format!("#{log_index}").into_bytes(),
+            reply_to: None,
+            timestamp: 0,
+        };
+        let _ = app.handle(message(1, 5));
+        let _ = app.handle(message(1, 6));
+        let _ = app.dispatch(Intent::Scroll { offset: 1 });
+
+        let actions = app.dispatch(Intent::LoadHistory);
+        assert!(actions.contains(&AppAction::LoadHistory { room_id: 1, until_log_index: 5 }));
+        assert!(app.dispatch(Intent::LoadHistory).is_empty());
+
+        // Earlier messages go first without moving the view or counting
+        let _ = app.dispatch(Intent::SelectRoom { room_id: 2 });
+        let _ = app.handle(message(1, 2));
+        let room = &app.rooms()[&1];
+        let order: Vec<_> = room.messages.iter().filter_map(|m| m.log_index).collect();
+        assert_eq!(order, vec![2, 5, 6]);
+        assert_eq!((room.scroll, room.unread), (1, 0));
+
+        let history = History::Complete;
+        let _ = app.handle(AppEvent::HistoryChanged { room_id: 1, history });
+        let _ = app.dispatch(Intent::SelectRoom { room_id: 1 });
+        assert!(app.dispatch(Intent::LoadHistory).is_empty());
+    }
+
+    #[test]
+    fn typing_follows_drafts_and_members() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+
+        let actions = app.dispatch(Intent::EditDraft { text: "h".into(), cursor: 1 });
+        assert!(actions.contains(&AppAction::SendTyping { room_id: 1, active: true }));
+        let actions = app.dispatch(Intent::EditDraft { text: "h".into(), cursor: 0 });
+        assert!(!actions.iter().any(|a| matches!(a, AppAction::SendTyping { .. })));
+        let actions = app.dispatch(Intent::EditDraft { text: String::new(), cursor: 0 });
+        assert!(actions.contains(&AppAction::SendTyping { room_id: 1, active: false }));
+
+        let _ = app.handle(AppEvent::TypingChanged { room_id: 1, sender_id: 7, active: true });
+        assert!(app.rooms()[&1].typing.contains(&7));
+
+        // The message ends the typing
+        let message = AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(0),
+            content: b"hi".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        };
+        let _ = app.handle(message);
+        assert!(app.rooms()[&1].typing.is_empty());
+    }
+
+    #[test]
+    fn replies_name_their_target_and_join_its_thread() {
+        let mut app = connected_app();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(4),
+            content: b"lunch?".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+
+        let _ = app.dispatch(Intent::ReplyTo { log_index: Some(4) });
+        let actions = app.dispatch(Intent::SendMessage { content: "yes".into() });
+        let reply_to = Some(ReplyTo::new(4, b"lunch?"));
+        assert!(actions.contains(&AppAction::SendMessage {
+            room_id: 1,
+            content: b"yes".to_vec(),
+            reply_to
+        }));
+        assert_eq!(app.rooms()[&1].draft.reply_to, None);
+
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 42,
+            log_index: Some(5),
+            content: b"yes".to_vec(),
+            reply_to,
+            timestamp: 0,
+        });
+        let room = &app.rooms()[&1];
+        assert_eq!(room.replies(4).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(room.message(5).and_then(|m| m.reply_to), reply_to);
+
+        // Expiring the reply takes it out of the thread
+        let _ = app.handle(AppEvent::MessageExpired { room_id: 1, log_index: 5 });
+        assert_eq!(app.rooms()[&1].replies(4).count(), 0);
+    }
+
     #[test]
     fn api_connect() {
         let mut app = App::new("localhost:8080".into());
@@ -13,12 +13,81 @@
 //!   [`crate::AppEvent`]s to update the UI.
 //! - Manages time ticks generically to support both real-time execution and
 //!   deterministic simulation.
+//! - Follows our own messages from the pacer through to the server's
+//!   acknowledgement, reporting each step as [`AppEvent::DeliveryChanged`].
+//! - Opens sessions, and restores them after a lost connection by re-syncing
+//!   joined rooms and then releasing the outbox (see [`crate::session`]).
+//! - Sends heartbeats while a session is open, reporting their round trips and
+//!   misses so the App can tell how well the connection is doing.
+//! - Throttles our typing notifications, and reports members whose
+//!   notifications stop arriving as no longer typing.
 
-use lockframe_client::{Client, ClientAction, ClientError, ClientEvent, ClientIdentity};
-use lockframe_core::env::Environment;
-use lockframe_proto::{Frame, FrameHeader, Opcode, Payload, payloads::session::SyncRequest};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
 
-use crate::{AppAction, AppEvent};
+use lockframe_client::{
+    Client, ClientAction, ClientConfig, ClientError, ClientEvent, ClientIdentity, ClientStorage,
+    MemberInfo, PacerConfig,
+};
+use lockframe_core::{env::Environment, mls::RoomId};
+use lockframe_proto::{
+    ErrorId, Frame, FrameFlags, FrameHeader, Opcode, Payload,
+    payloads::{
+        app::{AppMessageBody, ReplyTo},
+        session::{Hello, SyncRequest},
+    },
+};
+
+use crate::{
+    AppAction, AppEvent, CredentialKind, Delivery, History, Member, ReactionChange, RestoreStep,
+    Severity, session::Session,
+};
+
+/// Most frames fetched by the first sync request for a room.
+const SYNC_LIMIT: u64 = 1000;
+
+/// How long a sent message may go unacknowledged before it is marked failed.
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time between heartbeats. A heartbeat not answered by the next counts as
+/// missed.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long an invite we send stays valid.
+const INVITE_TTL: Duration = Duration::from_hours(24);
+
+/// Least time between two of our notifications that we are typing in a room.
+const TYPING_REFRESH: Duration = Duration::from_secs(3);
+
+/// How long a member counts as typing after their last notification. Long
+/// enough to span a refresh that arrives late.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Heartbeats of an open session.
+#[derive(Debug)]
+struct Heartbeat<I> {
+    /// When the last heartbeat was sent. `None` before the first.
+    sent: Option<I>,
+    /// Whether the last heartbeat was answered.
+    answered: bool,
+    /// Heartbeats in a row that went unanswered.
+    missed: u32,
+}
+
+/// One of our own messages the server has not acknowledged yet.
+#[derive(Debug)]
+struct Unacked<I> {
+    room_id: RoomId,
+    /// `None` for edits and deletes, which are only tracked while the pacer
+    /// holds them so released frames match up with the right message.
+    local_id: Option<u64>,
+    /// Request ID stamped on the sent frame. `None` while the pacer holds it.
+    request_id: Option<u32>,
+    /// First tick seen since the frame was queued or sent.
+    since: Option<I>,
+}
 
 /// Bridge between App and Client protocol logic.
 ///
@@ -27,14 +96,62 @@ use crate::{AppAction, AppEvent};
 pub struct Bridge<E: Environment> {
     client: Client<E>,
     outgoing: Vec<Frame>,
+    next_local_id: u64,
+    unacked: Vec<Unacked<E::Instant>>,
+    session: Session<E::Instant>,
+    /// `None` while no session is open.
+    heartbeat: Option<Heartbeat<E::Instant>>,
+    /// Rooms we asked to leave, whose removal is not an error.
+    leaving: HashSet<RoomId>,
+    /// When we last told each room we are typing.
+    typing_sent: HashMap<RoomId, E::Instant>,
+    /// Members typing, by room and sender ID, with when they last said so.
+    typing: HashMap<(RoomId, u64), E::Instant>,
+    /// Epoch last reported for each joined room.
+    epochs: BTreeMap<RoomId, u64>,
+    env: E,
 }
 
 impl<E: Environment> Bridge<E> {
     /// Create a new Bridge with the given environment and sender ID.
+    ///
+    /// Outgoing messages are paced with the default [`PacerConfig`].
     pub fn new(env: E, sender_id: u64) -> Self {
         let identity = ClientIdentity::new(sender_id);
-        let client = Client::new(env, identity);
-        Self { client, outgoing: Vec::new() }
+        let client = Client::with_config(env.clone(), identity, Self::config());
+        Self::with_client(env, client)
+    }
+
+    /// Create a Bridge whose client keeps rooms, the block list and drafts
+    /// in `storage`.
+    pub fn with_storage(
+        env: E,
+        sender_id: u64,
+        storage: Box<dyn ClientStorage>,
+    ) -> Result<Self, ClientError> {
+        let identity = ClientIdentity::new(sender_id);
+        let client = Client::with_storage(env.clone(), identity, Self::config(), storage)?;
+        Ok(Self::with_client(env, client))
+    }
+
+    fn config() -> ClientConfig {
+        ClientConfig { pacer: Some(PacerConfig::default()), ..ClientConfig::default() }
+    }
+
+    fn with_client(env: E, client: Client<E>) -> Self {
+        Self {
+            client,
+            outgoing: Vec::new(),
+            next_local_id: 0,
+            unacked: Vec::new(),
+            session: Session::default(),
+            heartbeat: None,
+            leaving: HashSet::new(),
+            typing_sent: HashMap::new(),
+            typing: HashMap::new(),
+            epochs: BTreeMap::new(),
+            env,
+        }
     }
 
     /// Client's stable sender ID.
@@ -45,63 +162,563 @@ impl<E: Environment> Bridge<E> {
     /// Process an App action and return resulting App events.
     pub fn process_app_action(&mut self, action: AppAction) -> Vec<AppEvent> {
         match action {
-            AppAction::CreateRoom { room_id } => {
-                let result = self.client.handle(ClientEvent::CreateRoom { room_id });
-                self.handle_client_result(result)
+            AppAction::CreateRoom { room_id } => self.forward(ClientEvent::CreateRoom { room_id }),
+            AppAction::SendMessage { room_id, content, reply_to } => {
+                self.send_message(room_id, content, reply_to)
             },
-            AppAction::SendMessage { room_id, content } => {
-                let result = self
-                    .client
-                    .handle(ClientEvent::SendMessage { room_id, plaintext: content.clone() });
+            AppAction::EditMessage { room_id, target_log_index, content } => {
+                let result = self.client.handle(ClientEvent::EditMessage {
+                    room_id,
+                    target_log_index,
+                    new_plaintext: content.clone(),
+                });
+                if let Ok(actions) = &result {
+                    self.track(room_id, None, actions);
+                }
                 let mut events = self.handle_client_result(result);
 
                 if !events.iter().any(|e| matches!(e, AppEvent::Error { .. })) {
-                    // Optimistically show own message as server won't echo it back and we can't
-                    // decrypt own messages due to ratchet
-                    events.push(AppEvent::MessageReceived {
+                    events.push(AppEvent::MessageEdited {
                         room_id,
                         sender_id: self.client.sender_id(),
+                        target_log_index,
                         content,
                     });
                 }
                 events
             },
+            AppAction::DeleteMessage { room_id, target_log_index } => {
+                let result =
+                    self.client.handle(ClientEvent::DeleteMessage { room_id, target_log_index });
+                if let Ok(actions) = &result {
+                    self.track(room_id, None, actions);
+                }
+                let mut events = self.handle_client_result(result);
+
+                if !events.iter().any(|e| matches!(e, AppEvent::Error { .. })) {
+                    events.push(AppEvent::MessageDeleted {
+                        room_id,
+                        sender_id: self.client.sender_id(),
+                        target_log_index,
+                    });
+                }
+                events
+            },
             AppAction::LeaveRoom { room_id } => {
+                self.leaving.insert(room_id);
                 let result = self.client.handle(ClientEvent::LeaveRoom { room_id });
-                self.handle_client_result(result)
+                let mut events = self.handle_client_result(result);
+                if self.client.is_leaving(room_id) {
+                    events.push(AppEvent::RoomLeaving { room_id });
+                }
+                events
             },
-            AppAction::JoinRoom { room_id } => {
-                let result = self.client.handle(ClientEvent::ExternalJoin { room_id });
-                self.handle_client_result(result)
+            AppAction::JoinRoom { room_id } => self.forward(ClientEvent::ExternalJoin { room_id }),
+            AppAction::PublishKeyPackage => self.forward(ClientEvent::PublishKeyPackage),
+            AppAction::AddMember { room_id, user_id } => {
+                self.forward(ClientEvent::FetchAndAddMember { room_id, user_id })
+            },
+            AppAction::RemoveMember { room_id, user_id } => {
+                self.forward(ClientEvent::RemoveMembers { room_id, member_ids: vec![user_id] })
             },
-            AppAction::PublishKeyPackage => {
-                let result = self.client.handle(ClientEvent::PublishKeyPackage);
+            AppAction::InviteUser { room_id, user_id } => {
+                let ttl = INVITE_TTL;
+                let result = self.client.handle(ClientEvent::InviteUser { room_id, user_id, ttl });
                 self.handle_client_result(result)
             },
-            AppAction::AddMember { room_id, user_id } => {
+            AppAction::SearchDirectory { query, after } => {
+                self.forward(ClientEvent::SearchDirectory { query, after })
+            },
+            AppAction::SetRoomListing { room_id, name } => {
+                self.forward(ClientEvent::SetRoomListing { room_id, name })
+            },
+            AppAction::LoadHistory { room_id, until_log_index } => {
                 let result =
-                    self.client.handle(ClientEvent::FetchAndAddMember { room_id, user_id });
-                self.handle_client_result(result)
+                    self.client.handle(ClientEvent::BackfillRoom { room_id, until_log_index });
+                let mut events = self.handle_client_result(result);
+                // Let the user try again
+                if events.iter().any(|e| matches!(e, AppEvent::Error { .. })) {
+                    events.push(AppEvent::HistoryChanged { room_id, history: History::Partial });
+                }
+                events
+            },
+            AppAction::SendTyping { room_id, active } => self.send_typing(room_id, active),
+            AppAction::SaveDraft { room_id, text } => {
+                match self.client.save_draft(room_id, &text) {
+                    Ok(()) => vec![],
+                    Err(e) => {
+                        vec![AppEvent::Error {
+                            message: format!("Failed to save draft: {e}"),
+                            error_id: None,
+                        }]
+                    },
+                }
+            },
+            AppAction::Render
+            | AppAction::Quit
+            | AppAction::Notify(_)
+            | AppAction::Schedule { .. }
+            | AppAction::PinRoom { .. }
+            | AppAction::SetRoomOrder { .. }
+            | AppAction::Connect { .. } => vec![],
+        }
+    }
+
+    /// Queue the Hello that opens a session, presenting `auth_token` if the
+    /// server requires one.
+    ///
+    /// After [`Bridge::connection_lost`] this starts restoring the session.
+    pub fn begin_session(&mut self, auth_token: Option<Vec<u8>>) -> Vec<AppEvent> {
+        let hello = Hello {
+            version: 1,
+            capabilities: Vec::new(),
+            sender_id: Some(self.client.sender_id()),
+            auth_token,
+        };
+        match Payload::Hello(hello).into_frame(FrameHeader::new(Opcode::Hello)) {
+            Ok(frame) => self.outgoing.insert(0, frame),
+            Err(e) => {
+                return vec![AppEvent::Error { message: format!("Hello: {e}"), error_id: None }];
             },
-            AppAction::Render | AppAction::Quit | AppAction::Connect { .. } => vec![],
         }
+        self.session
+            .hello_sent()
+            .map(|step| AppEvent::SessionRestore { step })
+            .into_iter()
+            .collect()
+    }
+
+    /// The connection dropped. Frames wait in the outbox until a new
+    /// session, opened with [`Bridge::begin_session`], is restored.
+    pub fn connection_lost(&mut self) -> Vec<AppEvent> {
+        self.session.lost();
+        self.heartbeat = None;
+        self.outgoing.retain(|frame| frame.header.opcode_enum() != Some(Opcode::Hello));
+        vec![AppEvent::Disconnected]
     }
 
     /// Handle a frame from the server.
     pub fn handle_frame(&mut self, frame: Frame) -> Vec<AppEvent> {
+        let room_id = frame.header.room_id();
+        let welcome = frame.header.opcode_enum() == Some(Opcode::Welcome);
+        let synced = match frame.header.opcode_enum() {
+            Some(Opcode::HelloReply) => return self.handle_hello_reply(&frame),
+            Some(Opcode::Ping) => {
+                self.outgoing.push(Frame::new(FrameHeader::new(Opcode::Pong), Vec::new()));
+                return vec![];
+            },
+            Some(Opcode::Pong) => return self.handle_pong(),
+            Some(Opcode::SyncResponse) => self.session.synced(room_id),
+            // Ephemeral frames are never sequenced, so their log index means nothing
+            Some(Opcode::AppMessage | Opcode::Commit)
+                if !frame.header.flags().contains(FrameFlags::EPHEMERAL) =>
+            {
+                self.session.saw(room_id, frame.header.log_index());
+                None
+            },
+            _ => None,
+        };
+
         let result = self.client.handle(ClientEvent::FrameReceived(frame));
-        self.handle_client_result(result)
+
+        // Messages a restored room held until its rekeying commit landed
+        let mut events = Vec::new();
+        if let Ok(actions) = &result {
+            for frame in actions.iter().filter_map(sent_app_message) {
+                events.extend(self.released(frame.header.room_id(), frame.header.request_id()));
+            }
+        }
+        events.extend(self.handle_client_result(result));
+        if welcome {
+            // Another member added us
+            let invited: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    AppEvent::RoomJoined { room_id } => {
+                        Some(AppEvent::Invited { room_id: *room_id })
+                    },
+                    _ => None,
+                })
+                .collect();
+            events.extend(invited);
+        }
+        if let Some(remaining) = synced {
+            events.push(AppEvent::SessionRestore { step: RestoreStep::Syncing { remaining } });
+        }
+        if self.session.sync_finished(None) {
+            events.extend(self.finish_restore());
+        }
+        events
     }
 
     /// Process a time tick.
     pub fn handle_tick(&mut self, now: E::Instant) -> Vec<AppEvent> {
         let result = self.client.handle(ClientEvent::Tick { now });
-        self.handle_client_result(result)
+
+        let mut events = Vec::new();
+        if let Ok(actions) = &result {
+            for frame in actions.iter().filter_map(sent_app_message) {
+                events.extend(self.released(frame.header.room_id(), frame.header.request_id()));
+            }
+        }
+        events.extend(self.handle_client_result(result));
+        events.extend(self.expire_unacked(now));
+        events.extend(self.expire_typing(now));
+        events.extend(self.send_heartbeat(now));
+        if self.session.sync_finished(Some(now)) {
+            events.extend(self.finish_restore());
+        }
+        events
     }
 
     /// Take pending outgoing frames.
+    ///
+    /// While a session is being restored only the handshake and re-syncs
+    /// are taken; everything else stays in the outbox.
     pub fn take_outgoing(&mut self) -> Vec<Frame> {
-        std::mem::take(&mut self.outgoing)
+        if !self.session.is_restoring() {
+            return std::mem::take(&mut self.outgoing);
+        }
+        let (ready, held) =
+            std::mem::take(&mut self.outgoing).into_iter().partition(|f| self.session.passes(f));
+        self.outgoing = held;
+        ready
+    }
+
+    /// The server accepted our Hello.
+    fn handle_hello_reply(&mut self, frame: &Frame) -> Vec<AppEvent> {
+        let session_id = match Payload::from_frame(frame) {
+            Ok(Payload::HelloReply(reply)) => reply.session_id,
+            Ok(other) => {
+                tracing::warn!("Unexpected payload type for HelloReply: {:?}", other);
+                return vec![];
+            },
+            Err(e) => {
+                tracing::warn!("Failed to parse HelloReply: {:?}", e);
+                return vec![];
+            },
+        };
+
+        let mut events = self.process_app_action(AppAction::PublishKeyPackage);
+        events.push(AppEvent::Connected { session_id, sender_id: self.client.sender_id() });
+        self.heartbeat = Some(Heartbeat { sent: None, answered: true, missed: 0 });
+
+        if let Some(resync) = self.session.authenticated() {
+            let remaining = resync.len();
+            for (room_id, from) in resync {
+                self.request_sync(Some(room_id), from, SYNC_LIMIT);
+            }
+            events.push(AppEvent::SessionRestore { step: RestoreStep::Syncing { remaining } });
+            if self.session.sync_finished(None) {
+                events.extend(self.finish_restore());
+            }
+        }
+        events
+    }
+
+    /// Send a heartbeat if one is due, reporting the last as missed if the
+    /// server has not answered it.
+    fn send_heartbeat(&mut self, now: E::Instant) -> Option<AppEvent> {
+        let heartbeat = self.heartbeat.as_mut()?;
+        if heartbeat.sent.is_some_and(|sent| now - sent < HEARTBEAT_INTERVAL) {
+            return None;
+        }
+        let missed = !heartbeat.answered;
+        if missed {
+            heartbeat.missed += 1;
+        }
+        heartbeat.sent = Some(now);
+        heartbeat.answered = false;
+        let event = missed.then_some(AppEvent::HeartbeatMissed { missed: heartbeat.missed });
+        self.outgoing.push(Frame::new(FrameHeader::new(Opcode::Ping), Vec::new()));
+        event
+    }
+
+    /// The server answered a heartbeat.
+    fn handle_pong(&mut self) -> Vec<AppEvent> {
+        let now = self.env.now();
+        let Some(heartbeat) = self.heartbeat.as_mut().filter(|heartbeat| !heartbeat.answered)
+        else {
+            return vec![];
+        };
+        let Some(sent) = heartbeat.sent else {
+            return vec![];
+        };
+        heartbeat.answered = true;
+        heartbeat.missed = 0;
+        vec![AppEvent::HeartbeatAnswered { rtt: now - sent }]
+    }
+
+    /// Re-syncing is over: release the outbox, and propose leaving again
+    /// the rooms we are leaving, since the connection may have lost the
+    /// proposals.
+    fn finish_restore(&mut self) -> Vec<AppEvent> {
+        let unsynced = self.session.unsynced();
+        self.session.restored();
+        let mut events = Vec::new();
+        let leaving: Vec<RoomId> = self
+            .leaving
+            .iter()
+            .copied()
+            .filter(|&room_id| self.client.is_leaving(room_id))
+            .collect();
+        for room_id in leaving {
+            let result = self.client.handle(ClientEvent::LeaveRoom { room_id });
+            events.extend(self.handle_client_result(result));
+        }
+        let messages = self
+            .outgoing
+            .iter()
+            .filter(|frame| frame.header.opcode_enum() == Some(Opcode::AppMessage))
+            .count();
+
+        if unsynced > 0 {
+            events.push(AppEvent::Notice {
+                severity: Severity::Warning,
+                room_id: None,
+                message: format!("{unsynced} rooms did not re-sync, recent history may be missing"),
+            });
+        }
+        if messages > 0 {
+            events.push(AppEvent::SessionRestore { step: RestoreStep::Replaying { messages } });
+        }
+        events.push(AppEvent::SessionRestore { step: RestoreStep::Restored });
+        events
+    }
+
+    /// Note which rooms we are in and how far we have seen each, for
+    /// re-syncing after a lost connection.
+    fn observe(&mut self, action: &ClientAction) {
+        match *action {
+            ClientAction::RoomJoined { room_id, .. } => self.session.joined(room_id),
+            ClientAction::PersistRoom(ref snapshot) => self.session.joined(snapshot.room_id),
+            ClientAction::RoomRemoved { room_id, .. } => self.session.left(room_id),
+            ClientAction::DeliverMessage { room_id, log_index, .. }
+            | ClientAction::DeliverAttachment { room_id, log_index, .. }
+            | ClientAction::MessageEdited { room_id, log_index, .. }
+            | ClientAction::MessageDeleted { room_id, log_index, .. }
+            | ClientAction::MessageSequenced { room_id, log_index, .. } => {
+                self.session.saw(room_id, log_index);
+            },
+            _ => {},
+        }
+    }
+
+    /// Start tracking a message, edit or delete the client accepted,
+    /// returning the state it starts in.
+    fn track(
+        &mut self,
+        room_id: RoomId,
+        local_id: Option<u64>,
+        actions: &[ClientAction],
+    ) -> Delivery {
+        let request_id = actions.iter().find_map(sent_app_message).map(|f| f.header.request_id());
+        if local_id.is_some() || request_id.is_none() {
+            self.unacked.push(Unacked { room_id, local_id, request_id, since: None });
+        }
+        if request_id.is_some() { Delivery::Sent } else { Delivery::Pending }
+    }
+
+    /// The client released the oldest frame it held for `room_id`, from its
+    /// pacer or behind a restored room's rekeying commit.
+    fn released(&mut self, room_id: RoomId, request_id: u32) -> Option<AppEvent> {
+        let index =
+            self.unacked.iter().position(|u| u.room_id == room_id && u.request_id.is_none())?;
+        let Some(local_id) = self.unacked[index].local_id else {
+            self.unacked.remove(index);
+            return None;
+        };
+        let unacked = &mut self.unacked[index];
+        unacked.request_id = Some(request_id);
+        unacked.since = None;
+        Some(AppEvent::DeliveryChanged {
+            room_id,
+            local_id,
+            delivery: Delivery::Sent,
+            log_index: None,
+        })
+    }
+
+    /// Send a message, tracking it until the server acknowledges it.
+    fn send_message(
+        &mut self,
+        room_id: RoomId,
+        content: Vec<u8>,
+        reply_to: Option<ReplyTo>,
+    ) -> Vec<AppEvent> {
+        // Members stop showing us as typing once the message arrives
+        self.typing_sent.remove(&room_id);
+        let event = match reply_to {
+            Some(reply_to) => ClientEvent::SendAppMessage {
+                room_id,
+                body: AppMessageBody::Reply { reply_to, text: content.clone() },
+            },
+            None => ClientEvent::SendMessage { room_id, plaintext: content.clone() },
+        };
+        let result = self.client.handle(event);
+        let local_id = self.next_local_id;
+        self.next_local_id += 1;
+        let delivery =
+            result.as_ref().ok().map(|actions| self.track(room_id, Some(local_id), actions));
+        let mut events = self.handle_client_result(result);
+
+        // Show the message right away. The server's echo of it only
+        // acknowledges it, as we can't decrypt our own messages
+        if let Some(delivery) = delivery {
+            events.push(AppEvent::MessageSending {
+                room_id,
+                sender_id: self.client.sender_id(),
+                local_id,
+                content,
+                reply_to,
+                delivery,
+                timestamp: self.env.wall_clock_secs().saturating_mul(1000),
+            });
+        }
+        events
+    }
+
+    /// Tell a room whether we are typing, at most every [`TYPING_REFRESH`]
+    /// while we are, and that we stopped only if we said we were.
+    fn send_typing(&mut self, room_id: RoomId, active: bool) -> Vec<AppEvent> {
+        let now = self.env.now();
+        if active {
+            if self.typing_sent.get(&room_id).is_some_and(|&sent| now - sent < TYPING_REFRESH) {
+                return vec![];
+            }
+            self.typing_sent.insert(room_id, now);
+        } else if self.typing_sent.remove(&room_id).is_none() {
+            return vec![];
+        }
+        let body = AppMessageBody::Typing { active };
+        let result = self.client.handle(ClientEvent::SendAppMessage { room_id, body });
+        self.handle_client_result(result)
+    }
+
+    /// A member said whether they are typing. Reported only if that changed.
+    fn typing_received(
+        &mut self,
+        room_id: RoomId,
+        sender_id: u64,
+        active: bool,
+    ) -> Option<AppEvent> {
+        let changed = if active {
+            self.typing.insert((room_id, sender_id), self.env.now()).is_none()
+        } else {
+            self.typing.remove(&(room_id, sender_id)).is_some()
+        };
+        changed.then_some(AppEvent::TypingChanged { room_id, sender_id, active })
+    }
+
+    /// Report members not heard typing for [`TYPING_TIMEOUT`] as stopped.
+    fn expire_typing(&mut self, now: E::Instant) -> Vec<AppEvent> {
+        let mut events = Vec::new();
+        self.typing.retain(|&(room_id, sender_id), &mut since| {
+            let typing = now - since < TYPING_TIMEOUT;
+            if !typing {
+                events.push(AppEvent::TypingChanged { room_id, sender_id, active: false });
+            }
+            typing
+        });
+        events
+    }
+
+    /// Events for a room the client dropped. Unless we asked to leave it,
+    /// the room was closed or we were removed, which the user should hear
+    /// about.
+    fn room_removed(&mut self, room_id: RoomId, reason: String) -> Vec<AppEvent> {
+        self.unacked.retain(|u| u.room_id != room_id);
+        self.epochs.remove(&room_id);
+        let mut events = vec![AppEvent::RoomLeft { room_id }];
+        if !self.leaving.remove(&room_id) {
+            events.push(AppEvent::RoomError { room_id, message: reason });
+        }
+        events
+    }
+
+    /// Events for joining a room: the join, its epoch and members, then any
+    /// draft saved for it.
+    fn room_joined(&mut self, room_id: RoomId) -> Vec<AppEvent> {
+        let mut events = vec![AppEvent::RoomJoined { room_id }];
+        if let Some(epoch) = self.client.epoch(room_id) {
+            self.epochs.insert(room_id, epoch);
+            events.push(AppEvent::EpochChanged { room_id, epoch });
+            events.push(members_changed(room_id, &self.client.members(room_id)));
+        }
+        match self.client.load_draft(room_id) {
+            Ok(Some(text)) => events.push(AppEvent::DraftRestored { room_id, text }),
+            Ok(None) => {},
+            Err(e) => tracing::warn!(room_id, "failed to load draft: {e}"),
+        }
+        events
+    }
+
+    /// Queue a [`SyncRequest`] for up to `limit` frames from `from`.
+    fn request_sync(&mut self, room_id: Option<RoomId>, from: u64, limit: u64) {
+        let payload = SyncRequest::new(from, limit);
+        if let Ok(mut frame) =
+            Payload::SyncRequest(payload).into_frame(FrameHeader::new(Opcode::SyncRequest))
+        {
+            if let Some(room_id) = room_id {
+                frame.header.set_room_id(room_id);
+            }
+            self.outgoing.push(frame);
+        }
+    }
+
+    /// The server acknowledged the frame sent with `request_id`.
+    fn sequenced(&mut self, room_id: RoomId, request_id: u32, log_index: u64) -> Option<AppEvent> {
+        let index = self
+            .unacked
+            .iter()
+            .position(|u| u.room_id == room_id && u.request_id == Some(request_id))?;
+        let local_id = self.unacked.remove(index).local_id?;
+        Some(AppEvent::DeliveryChanged {
+            room_id,
+            local_id,
+            delivery: Delivery::Delivered,
+            log_index: Some(log_index),
+        })
+    }
+
+    /// Give up on messages left unacknowledged for [`ACK_TIMEOUT`].
+    fn expire_unacked(&mut self, now: E::Instant) -> Vec<AppEvent> {
+        // Frames in the outbox haven't been sent; start counting once they are
+        if self.session.is_restoring() {
+            for unacked in &mut self.unacked {
+                unacked.since = None;
+            }
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        self.unacked.retain_mut(|unacked| {
+            let since = *unacked.since.get_or_insert(now);
+            if now - since < ACK_TIMEOUT {
+                return true;
+            }
+            if let Some(local_id) = unacked.local_id {
+                events.push(AppEvent::DeliveryChanged {
+                    room_id: unacked.room_id,
+                    local_id,
+                    delivery: Delivery::Failed,
+                    log_index: None,
+                });
+                events.push(AppEvent::Notice {
+                    severity: Severity::Error,
+                    room_id: Some(unacked.room_id),
+                    message: "Message could not be sent".to_string(),
+                });
+            }
+            false
+        });
+        events
+    }
+
+    /// Hand `event` to the client and translate what it does.
+    fn forward(&mut self, event: ClientEvent<E::Instant>) -> Vec<AppEvent> {
+        let result = self.client.handle(event);
+        self.handle_client_result(result)
     }
 
     fn handle_client_result(
@@ -110,41 +727,77 @@ impl<E: Environment> Bridge<E> {
     ) -> Vec<AppEvent> {
         match result {
             Ok(actions) => self.process_client_actions(actions),
-            Err(e) => vec![AppEvent::Error { message: e.to_string() }],
+            Err(e) => {
+                let error_id = e.error_id().unwrap_or_else(|| self.new_error_id());
+                tracing::warn!(%error_id, "client error: {e}");
+                vec![AppEvent::Error { message: e.to_string(), error_id: Some(error_id) }]
+            },
         }
     }
 
+    /// Fresh ID for a failure first seen on this side.
+    fn new_error_id(&self) -> ErrorId {
+        let mut id = [0u8; 2];
+        self.env.random_bytes(&mut id);
+        ErrorId::from_random(id)
+    }
+
     fn process_client_actions(&mut self, actions: Vec<ClientAction>) -> Vec<AppEvent> {
         let mut events = Vec::new();
 
         for action in actions {
+            self.observe(&action);
             match action {
                 ClientAction::Send(frame) => {
                     self.outgoing.push(frame);
                 },
-                ClientAction::DeliverMessage { room_id, sender_id, plaintext, .. } => {
+                ClientAction::DeliverMessage {
+                    room_id,
+                    sender_id,
+                    plaintext,
+                    log_index,
+                    display_timestamp,
+                    reply_to,
+                    ..
+                } => {
+                    self.typing.remove(&(room_id, sender_id));
                     events.push(AppEvent::MessageReceived {
                         room_id,
                         sender_id,
+                        log_index: Some(log_index),
                         content: plaintext,
+                        reply_to,
+                        timestamp: display_timestamp,
+                    });
+                },
+                ClientAction::DeliverAttachment {
+                    room_id,
+                    sender_id,
+                    attachment,
+                    log_index,
+                    display_timestamp,
+                    ..
+                } => {
+                    self.typing.remove(&(room_id, sender_id));
+                    events.push(AppEvent::AttachmentReceived {
+                        room_id,
+                        sender_id,
+                        log_index,
+                        attachment,
+                        timestamp: display_timestamp,
                     });
                 },
-                ClientAction::RoomRemoved { room_id, .. } => {
-                    events.push(AppEvent::RoomLeft { room_id });
+                ClientAction::MessageSequenced { room_id, request_id, log_index } => {
+                    events.extend(self.sequenced(room_id, request_id, log_index));
+                },
+                ClientAction::RoomRemoved { room_id, reason } => {
+                    events.extend(self.room_removed(room_id, reason));
                 },
                 ClientAction::PersistRoom(snapshot) => {
-                    events.push(AppEvent::RoomJoined { room_id: snapshot.room_id });
+                    events.extend(self.room_joined(snapshot.room_id));
                 },
                 ClientAction::RequestSync { from_epoch, .. } => {
-                    let payload = SyncRequest { from_log_index: from_epoch, limit: 100 };
-                    if let Ok(frame) = Payload::SyncRequest(payload)
-                        .into_frame(FrameHeader::new(Opcode::SyncRequest))
-                    {
-                        self.outgoing.push(frame);
-                    }
-                },
-                ClientAction::MemberAdded { room_id, user_id } => {
-                    events.push(AppEvent::MemberAdded { room_id, member_id: user_id });
+                    self.request_sync(None, from_epoch, 100);
                 },
                 ClientAction::KeyPackageNeeded { reason } => {
                     tracing::warn!(%reason, "KeyPackage needed, auto-republishing");
@@ -153,35 +806,187 @@ impl<E: Environment> Bridge<E> {
                     }
                 },
                 ClientAction::RoomJoined { room_id, .. } => {
-                    events.push(AppEvent::RoomJoined { room_id });
-                    let payload = SyncRequest { from_log_index: 0, limit: 1000 };
-
-                    if let Ok(mut frame) = Payload::SyncRequest(payload)
-                        .into_frame(FrameHeader::new(Opcode::SyncRequest))
-                    {
-                        frame.header.set_room_id(room_id);
-                        self.outgoing.push(frame);
-                    }
+                    events.extend(self.room_joined(room_id));
+                    self.request_sync(Some(room_id), 0, SYNC_LIMIT);
                 },
-                ClientAction::Log { .. } | ClientAction::KeyPackagePublished => {},
+                ClientAction::DeliverTyping { room_id, sender_id, active } => {
+                    events.extend(self.typing_received(room_id, sender_id, active));
+                },
+                other => events.extend(direct_event(other)),
             }
         }
 
+        events.extend(self.epoch_changes());
+        events
+    }
+
+    /// Events for joined rooms whose epoch moved since it was last
+    /// reported.
+    fn epoch_changes(&mut self) -> Vec<AppEvent> {
+        let mut events = Vec::new();
+        for (&room_id, reported) in &mut self.epochs {
+            if let Some(epoch) = self.client.epoch(room_id)
+                && epoch != *reported
+            {
+                *reported = epoch;
+                events.push(AppEvent::EpochChanged { room_id, epoch });
+            }
+        }
         events
     }
 }
 
+/// A joined room's members as the client has them.
+fn members_changed(room_id: RoomId, members: &[MemberInfo]) -> AppEvent {
+    let members = members
+        .iter()
+        .map(|member| Member {
+            user_id: member.user_id,
+            credential: CredentialKind::from(&member.credential),
+            signature_key: member.signature_key,
+        })
+        .collect();
+    AppEvent::MembersChanged { room_id, members }
+}
+
+/// The App event a client action translates to on its own, without bridge
+/// state.
+fn direct_event(action: ClientAction) -> Option<AppEvent> {
+    match action {
+        ClientAction::MessageEdited {
+            room_id,
+            sender_id,
+            target_log_index,
+            new_plaintext,
+            ..
+        } => {
+            Some(AppEvent::MessageEdited {
+                room_id,
+                sender_id,
+                target_log_index,
+                content: new_plaintext,
+            })
+        },
+        ClientAction::MessageDeleted { room_id, sender_id, target_log_index, .. } => {
+            Some(AppEvent::MessageDeleted { room_id, sender_id, target_log_index })
+        },
+        ClientAction::MessageExpired { room_id, log_index } => {
+            Some(AppEvent::MessageExpired { room_id, log_index })
+        },
+        ClientAction::DeliverReaction { room_id, sender_id, reaction, .. } => {
+            Some(AppEvent::ReactionChanged {
+                room_id,
+                target_log_index: reaction.message_log_index,
+                change: ReactionChange {
+                    sender_id,
+                    content: reaction.content,
+                    add: reaction.add,
+                },
+            })
+        },
+        ClientAction::MembersChanged { room_id, members } => {
+            Some(members_changed(room_id, &members))
+        },
+        ClientAction::MemberAdded { room_id, user_id } => {
+            Some(AppEvent::MemberAdded { room_id, member_id: user_id })
+        },
+        ClientAction::ReplayDetected { room_id, sender_id, replayed_log_index, .. } => {
+            tracing::warn!(room_id, sender_id, replayed_log_index, "replayed message");
+            Some(AppEvent::RoomError {
+                room_id,
+                message: format!("Replayed message from {sender_id} was dropped"),
+            })
+        },
+        ClientAction::DirectoryResults { rooms, next } => {
+            Some(AppEvent::DirectoryResults { rooms, next })
+        },
+        ClientAction::InviteReceived { room_id, inviter, expires_at } => {
+            Some(AppEvent::InviteReceived { room_id, inviter, expires_at })
+        },
+        ClientAction::ServerNotice { room_id, kind, text } => {
+            Some(AppEvent::ServerNotice { room_id, kind, text })
+        },
+        ClientAction::Backpressure { room_id, queued, retry_after } => {
+            tracing::debug!(room_id, queued, ?retry_after, "send queued by pacer");
+            Some(AppEvent::Notice {
+                severity: Severity::Warning,
+                room_id: Some(room_id),
+                message: "Rate limited, messages are queued".to_string(),
+            })
+        },
+        ClientAction::BackfillProgress { room_id, fetched, total } => {
+            let history = if fetched >= total {
+                History::Complete
+            } else {
+                History::Loading { fetched, total }
+            };
+            Some(AppEvent::HistoryChanged { room_id, history })
+        },
+        ClientAction::HistoryTruncated { room_id, .. } => {
+            Some(AppEvent::HistoryChanged { room_id, history: History::Complete })
+        },
+        // Handled by the bridge itself
+        ClientAction::Send(_)
+        | ClientAction::DeliverMessage { .. }
+        | ClientAction::DeliverAttachment { .. }
+        | ClientAction::MessageSequenced { .. }
+        | ClientAction::RoomRemoved { .. }
+        | ClientAction::PersistRoom(_)
+        | ClientAction::RequestSync { .. }
+        | ClientAction::KeyPackageNeeded { .. }
+        | ClientAction::RoomJoined { .. }
+        | ClientAction::DeliverTyping { .. }
+        // Not shown
+        | ClientAction::GapDetected { .. }
+        | ClientAction::DeliverReceipt { .. }
+        | ClientAction::DeliverCustom { .. }
+        | ClientAction::DeliverUnsupported { .. }
+        | ClientAction::PresenceChanged { .. }
+        | ClientAction::UnreadCountChanged { .. }
+        | ClientAction::MessageHidden { .. }
+        | ClientAction::Log { .. }
+        | ClientAction::KeyPackagePublished => None,
+
+    }
+}
+
+/// The frame of a sent application message, if `action` sends one.
+fn sent_app_message(action: &ClientAction) -> Option<&Frame> {
+    match action {
+        ClientAction::Send(frame) if frame.header.opcode_enum() == Some(Opcode::AppMessage) => {
+            Some(frame)
+        },
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use lockframe_core::env::test_utils::MockEnv;
+    use lockframe_proto::payloads::session::HelloReply;
 
     use super::*;
+    use crate::App;
 
     #[test]
     fn create_room_produces_room_joined() {
         let mut bridge: Bridge<MockEnv> = Bridge::new(MockEnv::new(), 42);
         let events = bridge.process_app_action(AppAction::CreateRoom { room_id: 1 });
         assert!(events.iter().any(|e| matches!(e, AppEvent::RoomJoined { room_id: 1 })));
+        assert!(
+            events.iter().any(|e| matches!(e, AppEvent::EpochChanged { room_id: 1, epoch: 0 }))
+        );
+        let members = events.iter().find_map(|e| match e {
+            AppEvent::MembersChanged { room_id: 1, members } => Some(members),
+            _ => None,
+        });
+        assert!(members.is_some_and(|members| {
+            matches!(members.as_slice(), [Member {
+                user_id: 42,
+                credential: CredentialKind::Basic,
+                signature_key: Some(_),
+            }])
+        }));
     }
 
     #[test]
@@ -190,19 +995,117 @@ mod tests {
         let _ = bridge.process_app_action(AppAction::CreateRoom { room_id: 1 });
         let _ = bridge.take_outgoing();
 
-        let _ = bridge
-            .process_app_action(AppAction::SendMessage { room_id: 1, content: b"hello".to_vec() });
+        let _ = bridge.process_app_action(AppAction::SendMessage {
+            room_id: 1,
+            content: b"hello".to_vec(),
+            reply_to: None,
+        });
 
         assert!(!bridge.take_outgoing().is_empty());
     }
 
+    #[test]
+    fn own_messages_can_be_edited_and_deleted_once_sequenced() {
+        let mut bridge: Bridge<MockEnv> = Bridge::new(MockEnv::new(), 42);
+        let mut app = App::new("localhost:8080".into());
+        let apply = |app: &mut App, events: Vec<AppEvent>| {
+            for event in events {
+                let _ = app.handle(event);
+            }
+        };
+        apply(&mut app, bridge.process_app_action(AppAction::CreateRoom { room_id: 1 }));
+        let _ = bridge.take_outgoing();
+
+        apply(
+            &mut app,
+            bridge.process_app_action(AppAction::SendMessage {
+                room_id: 1,
+                content: b"helo".to_vec(),
+                reply_to: None,
+            }),
+        );
+        let mut echo = bridge.take_outgoing().pop().unwrap();
+        echo.header.set_log_index(5);
+        apply(&mut app, bridge.handle_frame(echo));
+        let message = |app: &App| app.rooms()[&1].message(5).cloned().unwrap();
+        assert_eq!(message(&app).local_id, Some(0));
+
+        apply(
+            &mut app,
+            bridge.process_app_action(AppAction::EditMessage {
+                room_id: 1,
+                target_log_index: 5,
+                content: b"hello".to_vec(),
+            }),
+        );
+        assert_eq!(message(&app).content, b"hello");
+        assert!(message(&app).edited);
+
+        apply(
+            &mut app,
+            bridge.process_app_action(AppAction::DeleteMessage { room_id: 1, target_log_index: 5 }),
+        );
+        assert!(message(&app).deleted);
+    }
+
     #[test]
     fn send_to_unknown_room_produces_error() {
         let mut bridge: Bridge<MockEnv> = Bridge::new(MockEnv::new(), 42);
         let events = bridge.process_app_action(AppAction::SendMessage {
             room_id: 999,
             content: b"hello".to_vec(),
+            reply_to: None,
         });
         assert!(events.iter().any(|e| matches!(e, AppEvent::Error { .. })));
     }
+
+    #[test]
+    fn heartbeats_report_round_trips_and_misses() {
+        let env = MockEnv::new();
+        let mut bridge: Bridge<MockEnv> = Bridge::new(env.clone(), 42);
+        let reply = HelloReply { session_id: 1, capabilities: Vec::new(), challenge: None };
+        let reply =
+            Payload::HelloReply(reply).into_frame(FrameHeader::new(Opcode::HelloReply)).unwrap();
+        let _ = bridge.handle_frame(reply);
+        let _ = bridge.take_outgoing();
+
+        let _ = bridge.handle_tick(env.now());
+        let pings = bridge.take_outgoing();
+        assert!(pings.iter().any(|f| f.header.opcode_enum() == Some(Opcode::Ping)));
+
+        env.advance_time(Duration::from_millis(40));
+        let events = bridge.handle_frame(Frame::new(FrameHeader::new(Opcode::Pong), Vec::new()));
+        assert!(
+            matches!(events[..], [AppEvent::HeartbeatAnswered { rtt }] if rtt == Duration::from_millis(40))
+        );
+
+        // Next heartbeat goes unanswered until the one after is due
+        env.advance_time(HEARTBEAT_INTERVAL);
+        let _ = bridge.handle_tick(env.now());
+        env.advance_time(HEARTBEAT_INTERVAL);
+        let events = bridge.handle_tick(env.now());
+        assert!(events.iter().any(|e| matches!(e, AppEvent::HeartbeatMissed { missed: 1 })));
+    }
+
+    #[test]
+    fn typing_stops_when_notifications_stop() {
+        let env = MockEnv::new();
+        let mut bridge: Bridge<MockEnv> = Bridge::new(env.clone(), 42);
+        let typing = |active| ClientAction::DeliverTyping { room_id: 1, sender_id: 7, active };
+
+        let events = bridge.process_client_actions(vec![typing(true)]);
+        assert!(matches!(events[..], [AppEvent::TypingChanged { sender_id: 7, active: true, .. }]));
+
+        // A refresh keeps the member typing without being reported again
+        env.advance_time(TYPING_TIMEOUT / 2);
+        assert!(bridge.process_client_actions(vec![typing(true)]).is_empty());
+        env.advance_time(TYPING_TIMEOUT / 2);
+        let events = bridge.handle_tick(env.now());
+        assert!(!events.iter().any(|e| matches!(e, AppEvent::TypingChanged { .. })));
+
+        env.advance_time(TYPING_TIMEOUT);
+        let events = bridge.handle_tick(env.now());
+        assert!(events.iter().any(|e| matches!(e, AppEvent::TypingChanged { active: false, .. })));
+        assert!(bridge.process_client_actions(vec![typing(false)]).is_empty());
+    }
 }
@@ -0,0 +1,173 @@
+//! Event log for reproducing and debugging App state.
+//!
+//! With the `devtools` feature, [`crate::App`] records each input it handles
+//! together with the actions it returned. The App is a pure state machine,
+//! so replaying the log into a fresh App rebuilds the same state: a log
+//! dumped from a frontend reproduces a bug report exactly, and stepping back
+//! is a replay that stops one input short.
+//!
+//! Inputs are what frontends and the runtime feed the App: events, intents,
+//! room selection and a few settings. Ticks are left out. Calling the
+//! per-operation methods such as [`crate::App::create_room`] directly is not
+//! recorded; frontends go through [`crate::App::dispatch`].
+
+use lockframe_core::mls::RoomId;
+use serde::{Deserialize, Serialize};
+
+use crate::{AccountId, App, AppAction, AppEvent, Intent, Mentions};
+
+/// One input to an App.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Input {
+    /// Event handled for an account.
+    Event {
+        /// Account the event was for.
+        account: AccountId,
+        /// The event.
+        event: AppEvent,
+    },
+    /// Intent dispatched by a frontend.
+    Intent(Intent),
+    /// Room made active directly.
+    SelectRoom(RoomId),
+    /// Room directory closed.
+    CloseDirectory,
+    /// Status message set by a frontend.
+    SetStatus(String),
+    /// Mention settings changed.
+    SetMentions(Mentions),
+    /// Account added.
+    AddAccount {
+        /// Display name.
+        name: String,
+        /// Server address (host:port).
+        server_addr: String,
+    },
+}
+
+/// An input and the actions the App returned for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// The input.
+    pub input: Input,
+    /// Actions returned.
+    pub actions: Vec<AppAction>,
+}
+
+/// Append-only log of an App's inputs.
+///
+/// Serializable, so frontends can dump it and tests can load it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventLog {
+    /// Server address the App was created with
+    server_addr: String,
+    entries: Vec<LogEntry>,
+    /// Entries reflected in the App's state. Those after it were stepped
+    /// back over.
+    applied: usize,
+}
+
+impl EventLog {
+    pub(crate) fn new(server_addr: String) -> Self {
+        Self { server_addr, entries: Vec::new(), applied: 0 }
+    }
+
+    /// Recorded entries, including any stepped back over.
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    /// Number of entries reflected in the App's state.
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    /// Rebuild the App this log was recorded from.
+    ///
+    /// Entries whose actions differ on replay are logged as warnings: the
+    /// App was fed input the log does not capture.
+    pub fn replay(&self) -> App {
+        self.replay_to(self.applied)
+    }
+
+    /// Rebuild the App as it was after the first `count` entries.
+    pub fn replay_to(&self, count: usize) -> App {
+        let mut app = App::new(self.server_addr.clone());
+        for (index, entry) in self.entries.iter().take(count).enumerate() {
+            if app.apply_input(entry.input.clone()) != entry.actions {
+                tracing::warn!(index, "replay diverged from the recorded actions");
+            }
+        }
+        app
+    }
+
+    /// Record an input, dropping entries stepped back over.
+    pub(crate) fn push(&mut self, input: Input, actions: Vec<AppAction>) {
+        self.entries.truncate(self.applied);
+        self.entries.push(LogEntry { input, actions });
+        self.applied = self.entries.len();
+    }
+
+    pub(crate) fn set_applied(&mut self, applied: usize) {
+        self.applied = applied.min(self.entries.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RoomOrder;
+
+    fn recorded_app() -> App {
+        let mut app = App::new("localhost:4433".into());
+        let _ = app.handle(AppEvent::Connected { session_id: 1, sender_id: 42 });
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 1 });
+        let _ = app.handle(AppEvent::Tick);
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 1,
+            sender_id: 7,
+            log_index: Some(0),
+            content: b"hi".to_vec(),
+            reply_to: None,
+            timestamp: 0,
+        });
+        let _ = app.dispatch(Intent::SortRooms { order: RoomOrder::Recent });
+        app
+    }
+
+    #[test]
+    fn dumped_log_replays_to_the_same_state() {
+        let app = recorded_app();
+        assert_eq!(app.event_log().entries().len(), 4, "ticks are not recorded");
+
+        let dump = serde_json::to_string(app.event_log()).unwrap();
+        let log: EventLog = serde_json::from_str(&dump).unwrap();
+        let replayed = log.replay();
+        assert_eq!(replayed.rooms()[&1].messages.len(), 1);
+        assert_eq!(replayed.room_order(), RoomOrder::Recent);
+        assert_eq!(replayed.connection_state(), app.connection_state());
+        assert_eq!(replayed.event_log().entries().len(), 4);
+    }
+
+    #[test]
+    fn stepping_back_undoes_inputs_until_new_input() {
+        let mut app = recorded_app();
+        assert!(app.step_back());
+        assert_eq!(app.room_order(), RoomOrder::Alphabetical);
+        assert!(app.step_back());
+        assert!(app.rooms()[&1].messages.is_empty());
+
+        assert!(app.step_forward());
+        assert_eq!(app.rooms()[&1].messages.len(), 1);
+        assert_eq!(app.event_log().applied(), 3);
+
+        // New input drops the undone sort
+        let _ = app.handle(AppEvent::Resize(100, 40));
+        assert!(!app.step_forward());
+        assert_eq!(app.event_log().entries().len(), 4);
+        assert_eq!(app.room_order(), RoomOrder::Alphabetical);
+
+        while app.step_back() {}
+        assert!(app.rooms().is_empty());
+    }
+}
@@ -4,12 +4,14 @@
 //! implementations. Each frontend implements the trait to provide
 //! platform-specific I/O, while the generic [`crate::Runtime`] handles all
 //! orchestration.
+//!
+//! A driver keeps one connection per account, identified by [`AccountId`].
 
 use std::{future::Future, ops::Sub, time::Duration};
 
 use lockframe_proto::Frame;
 
-use crate::{App, AppAction};
+use crate::{AccountId, App, AppAction, Notifier};
 
 /// Abstracts I/O operations for the application runtime.
 ///
@@ -23,6 +25,9 @@ pub trait Driver: Send {
     /// Time instant type. Enables virtual time in simulation.
     type Instant: Copy + Ord + Send + Sync + Sub<Output = Duration>;
 
+    /// Where notifications go. Simulations use [`crate::NoopNotifier`].
+    type Notifier: Notifier;
+
     /// Poll for input and return actions to process.
     ///
     /// Returns empty vector if no input is ready.
@@ -31,31 +36,48 @@ pub trait Driver: Send {
         app: &mut App,
     ) -> impl Future<Output = Result<Vec<AppAction>, Self::Error>> + Send;
 
-    /// Send a frame to the server.
+    /// Send a frame to an account's server.
     ///
     /// # Errors
     ///
     /// Returns an error if the connection is closed or send fails.
-    fn send_frame(&mut self, frame: Frame) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn send_frame(
+        &mut self,
+        account: AccountId,
+        frame: Frame,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
-    /// Receive a frame from the server.
+    /// Receive a frame from any account's server.
     ///
-    /// Returns frame or `None` if the connection is closed.
-    fn recv_frame(&mut self) -> impl Future<Output = Option<Frame>> + Send;
+    /// Returns the account and frame, or `None` if no frame is ready.
+    fn recv_frame(&mut self) -> impl Future<Output = Option<(AccountId, Frame)>> + Send;
 
-    /// Establish connection to the server.
+    /// Establish an account's connection to its server.
     ///
     /// # Errors
     ///
     /// Returns an error if connection cannot be established.
-    fn connect(&mut self, addr: &str) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn connect(
+        &mut self,
+        account: AccountId,
+        addr: &str,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
-    /// Check if connected to server.
-    fn is_connected(&self) -> bool;
+    /// Check if an account is connected to its server.
+    fn is_connected(&self, account: AccountId) -> bool;
 
     /// Current time instant.
+    ///
+    /// Timers scheduled on the [`crate::Runtime`] fire by this clock.
     fn now(&self) -> Self::Instant;
 
+    /// The next scheduled timer is due in `after`, or no timer is pending.
+    ///
+    /// Called at the end of each event loop cycle. Drivers whose
+    /// [`Driver::poll_event`] waits for input should return by then so the
+    /// timer fires on time. Drivers that poll often enough ignore it.
+    fn set_wakeup(&mut self, _after: Option<Duration>) {}
+
     /// Render the application state.
     ///
     /// # Errors
@@ -63,6 +85,9 @@ pub trait Driver: Send {
     /// Returns an error if rendering fails.
     fn render(&mut self, app: &App) -> Result<(), Self::Error>;
 
+    /// The notifier the runtime hands notifications to.
+    fn notifier(&mut self) -> &mut Self::Notifier;
+
     /// Stop the connection and clean up resources.
     fn stop(&mut self);
 }
@@ -7,10 +7,22 @@
 //! - System events (Resize, Tick)
 //! - Protocol notifications
 
+use std::time::Duration;
+
 use lockframe_core::mls::RoomId;
+use lockframe_proto::{
+    ErrorId,
+    payloads::{
+        app::{Attachment, ReplyTo},
+        session::{DirectoryEntry, NoticeKind},
+    },
+};
+
+use crate::{Delivery, History, Member, ReactionChange, RestoreStep, Severity};
 
 /// Events processed by the App state machine.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub enum AppEvent {
     /// Periodic tick.
     Tick,
@@ -29,6 +41,27 @@ pub enum AppEvent {
         sender_id: u64,
     },
 
+    /// Connection to the server lost. The runtime reconnects.
+    Disconnected,
+
+    /// The server answered a heartbeat.
+    HeartbeatAnswered {
+        /// Time from sending the heartbeat to the answer.
+        rtt: Duration,
+    },
+
+    /// A heartbeat went unanswered until the next was due.
+    HeartbeatMissed {
+        /// Heartbeats in a row that went unanswered.
+        missed: u32,
+    },
+
+    /// Restoring the session after reconnecting made progress.
+    SessionRestore {
+        /// Step reached.
+        step: RestoreStep,
+    },
+
     /// Joined a room.
     RoomJoined {
         /// 128-bit room UUID.
@@ -41,14 +74,176 @@ pub enum AppEvent {
         room_id: RoomId,
     },
 
+    /// Asked to leave a room, which stays until another member commits our
+    /// removal.
+    RoomLeaving {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Another member added us to a room, just joined.
+    Invited {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Another member invited us to a room we are not in.
+    InviteReceived {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// User who sent the invite.
+        inviter: u64,
+        /// Unix time in seconds the invite expires.
+        expires_at: u64,
+    },
+
+    /// Something went wrong in a room, such as it being closed or a message
+    /// being dropped.
+    RoomError {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// What went wrong.
+        message: String,
+    },
+
+    /// Draft saved in an earlier run found for a joined room.
+    DraftRestored {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Draft text.
+        text: String,
+    },
+
     /// Message received.
     MessageReceived {
         /// 128-bit room UUID.
         room_id: RoomId,
         /// ID of the sender.
         sender_id: u64,
+        /// Server-assigned log index. `None` for local echoes.
+        log_index: Option<u64>,
         /// Message content bytes.
         content: Vec<u8>,
+        /// Message this one replies to, if it is a reply.
+        reply_to: Option<ReplyTo>,
+        /// When the sender sent it, corrected for their clock skew (Unix
+        /// milliseconds). 0 if unknown.
+        timestamp: u64,
+    },
+
+    /// A member shared a file. Shown like a message whose content is the
+    /// file name.
+    AttachmentReceived {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// ID of the sender.
+        sender_id: u64,
+        /// Server-assigned log index.
+        log_index: u64,
+        /// File metadata and sealed content.
+        attachment: Attachment,
+        /// When the sender sent it, corrected for their clock skew (Unix
+        /// milliseconds). 0 if unknown.
+        timestamp: u64,
+    },
+
+    /// One of our own messages was handed to the client. Shown right away;
+    /// [`AppEvent::DeliveryChanged`] follows it through to the server.
+    MessageSending {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Our sender ID.
+        sender_id: u64,
+        /// Bridge-assigned ID for matching delivery updates.
+        local_id: u64,
+        /// Message content bytes.
+        content: Vec<u8>,
+        /// Message this one replies to, if it is a reply.
+        reply_to: Option<ReplyTo>,
+        /// State the message starts in.
+        delivery: Delivery,
+        /// When it was sent (Unix milliseconds).
+        timestamp: u64,
+    },
+
+    /// One of our own messages moved to a new delivery state.
+    DeliveryChanged {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// ID from the message's [`AppEvent::MessageSending`].
+        local_id: u64,
+        /// New delivery state.
+        delivery: Delivery,
+        /// Server-assigned log index, once delivered.
+        log_index: Option<u64>,
+    },
+
+    /// A sender edited one of their messages.
+    MessageEdited {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// ID of the sender.
+        sender_id: u64,
+        /// Log index of the edited message.
+        target_log_index: u64,
+        /// Replacement content bytes.
+        content: Vec<u8>,
+    },
+
+    /// A sender deleted one of their messages.
+    MessageDeleted {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// ID of the sender.
+        sender_id: u64,
+        /// Log index of the deleted message.
+        target_log_index: u64,
+    },
+
+    /// A member added or removed a reaction to a message. The message may
+    /// not have arrived yet.
+    ReactionChanged {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Log index of the message reacted to.
+        target_log_index: u64,
+        /// The reaction and who made it.
+        change: ReactionChange,
+    },
+
+    /// A member started or stopped composing a message. Members not heard
+    /// from for a while are reported as stopped.
+    TypingChanged {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Member's sender ID.
+        sender_id: u64,
+        /// True while composing.
+        active: bool,
+    },
+
+    /// Loading a room's earlier history progressed, finished or failed.
+    HistoryChanged {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// How much is loaded now.
+        history: History,
+    },
+
+    /// A room's group moved to a new MLS epoch, or joined at one.
+    EpochChanged {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Epoch the group is at now.
+        epoch: u64,
+    },
+
+    /// A disappearing message expired.
+    MessageExpired {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Log index of the expired message.
+        log_index: u64,
     },
 
     /// Member added to room.
@@ -67,9 +262,49 @@ pub enum AppEvent {
         member_id: u64,
     },
 
+    /// A room's group has these members now, after joining or moving to a
+    /// new epoch.
+    MembersChanged {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// Every current member.
+        members: Vec<Member>,
+    },
+
     /// Error occurred.
     Error {
         /// Error description.
         message: String,
+        /// ID the failure was logged under, here or on the server.
+        error_id: Option<ErrorId>,
+    },
+
+    /// Something the user should know about, such as a failed send or a
+    /// rate limit.
+    Notice {
+        /// How serious it is.
+        severity: Severity,
+        /// Room it is about, if any.
+        room_id: Option<RoomId>,
+        /// What happened.
+        message: String,
+    },
+
+    /// The server's operator sent a notice, such as a maintenance window.
+    ServerNotice {
+        /// Room it is for. `None` if it is for everyone on the server.
+        room_id: Option<RoomId>,
+        /// What it is about.
+        kind: NoticeKind,
+        /// Text to show.
+        text: String,
+    },
+
+    /// A page of room directory results arrived.
+    DirectoryResults {
+        /// Listed rooms on this page.
+        rooms: Vec<DirectoryEntry>,
+        /// Cursor for the next page. `None` if this was the last.
+        next: Option<RoomId>,
     },
 }
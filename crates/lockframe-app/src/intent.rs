@@ -0,0 +1,344 @@
+//! Frontend-independent user intents.
+//!
+//! Frontends translate user input into an [`Intent`]: the TUI parses slash
+//! commands, a desktop shell maps menu items and shortcuts. [`App::dispatch`]
+//! checks the intent against the current state and turns it into actions, or
+//! rejects it with an [`IntentError`] shown as the status message.
+//!
+//! Intents that act on a room act on the active room, so frontends select a
+//! room first with [`Intent::SelectRoom`].
+
+use lockframe_core::mls::RoomId;
+
+use crate::{AccountId, App, ConnectionState, RoomOrder};
+
+/// Something the user asked for, independent of how they asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum Intent {
+    /// Connect to the server.
+    Connect,
+
+    /// Make another account the active account.
+    SwitchAccount {
+        /// Account to switch to.
+        account: AccountId,
+    },
+
+    /// Create a new room.
+    CreateRoom {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Join an existing room via external commit.
+    JoinRoom {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Make a joined room the active room.
+    SelectRoom {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Leave the active room.
+    LeaveRoom,
+
+    /// Publish a key package to the server.
+    PublishKeyPackage,
+
+    /// Add a member to the active room.
+    AddMember {
+        /// User ID to add.
+        user_id: u64,
+    },
+
+    /// Remove a member from the active room.
+    RemoveMember {
+        /// User ID to remove.
+        user_id: u64,
+    },
+
+    /// Invite a user to the active room, leaving it to them to join.
+    InviteUser {
+        /// User ID to invite.
+        user_id: u64,
+    },
+
+    /// Accept an invite, joining its room.
+    AcceptInvite {
+        /// Room of the invite. `None` accepts the newest.
+        room_id: Option<RoomId>,
+    },
+
+    /// Decline an invite.
+    DeclineInvite {
+        /// Room of the invite. `None` declines the newest.
+        room_id: Option<RoomId>,
+    },
+
+    /// Replace the message being composed in the active room.
+    EditDraft {
+        /// Text typed so far.
+        text: String,
+        /// Cursor position within the text.
+        cursor: usize,
+    },
+
+    /// Reply to a message in the active room with the next message sent,
+    /// or stop replying.
+    ReplyTo {
+        /// Log index of the message. `None` stops replying.
+        log_index: Option<u64>,
+    },
+
+    /// Scroll the active room's messages.
+    Scroll {
+        /// Messages up from the newest. 0 follows new messages.
+        offset: usize,
+    },
+
+    /// Load the active room's messages from before the oldest one held.
+    LoadHistory,
+
+    /// Send a message to the active room.
+    SendMessage {
+        /// Message text.
+        content: String,
+    },
+
+    /// Replace the content of one of our messages in the active room.
+    EditMessage {
+        /// Log index of the message.
+        log_index: u64,
+        /// New message text.
+        content: String,
+    },
+
+    /// Delete one of our messages in the active room.
+    DeleteMessage {
+        /// Log index of the message.
+        log_index: u64,
+    },
+
+    /// Search the room directory.
+    SearchRooms {
+        /// Text room names must contain. Empty lists every room.
+        query: String,
+    },
+
+    /// Show the next page of room directory results.
+    NextRooms,
+
+    /// List the active room in the directory, or remove it.
+    ListRoom {
+        /// Listed name. `None` removes the listing.
+        name: Option<String>,
+    },
+
+    /// Pin the active room to the top of the room list, or unpin it.
+    PinRoom {
+        /// Whether the room is pinned.
+        pinned: bool,
+    },
+
+    /// Change the order of the room list.
+    SortRooms {
+        /// New order.
+        order: RoomOrder,
+    },
+
+    /// Show only rooms whose names contain text. Empty shows every room.
+    FilterRooms {
+        /// Text room names must contain.
+        filter: String,
+    },
+
+    /// Dismiss a notice.
+    DismissNotice {
+        /// Notice to dismiss. `None` dismisses every notice.
+        id: Option<u64>,
+    },
+
+    /// Quit the application.
+    Quit,
+}
+
+/// Why an [`Intent`] can't be carried out in the current state.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IntentError {
+    /// Connect while connected or connecting.
+    #[error("already connected")]
+    AlreadyConnected,
+
+    /// Intent needs the user's sender ID, which only a session provides.
+    #[error("not connected")]
+    NotConnected,
+
+    /// Intent acts on the active room and there is none.
+    #[error("no active room, join or select one first")]
+    NoActiveRoom,
+
+    /// Create or join a room we are already in.
+    #[error("already in room {room_id:x}")]
+    AlreadyInRoom {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Switch to an account the App does not hold.
+    #[error("no account {}", account.0)]
+    NoSuchAccount {
+        /// Account ID.
+        account: AccountId,
+    },
+
+    /// Select a room we are not in.
+    #[error("not in room {room_id:x}")]
+    NotInRoom {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+
+    /// Add a user who is already a member of the active room.
+    #[error("user {user_id} is already a member")]
+    AlreadyMember {
+        /// User ID.
+        user_id: u64,
+    },
+
+    /// Remove a user who is not a member of the active room.
+    #[error("user {user_id} is not a member")]
+    NotMember {
+        /// User ID.
+        user_id: u64,
+    },
+
+    /// Send or edit to empty text.
+    #[error("message is empty")]
+    EmptyMessage,
+
+    /// Reply to a message that does not exist or was deleted.
+    #[error("no message at log index {log_index}")]
+    NoMessage {
+        /// Log index of the message.
+        log_index: u64,
+    },
+
+    /// Edit or delete a message that is not ours, or does not exist.
+    #[error("no message of yours at log index {log_index}")]
+    NoSuchMessage {
+        /// Log index of the message.
+        log_index: u64,
+    },
+
+    /// Next page without an open room search.
+    #[error("no room search open")]
+    NoDirectory,
+
+    /// Next page when the search has no more results.
+    #[error("no more rooms")]
+    NoMoreRooms,
+
+    /// Accept or decline without a matching invite.
+    #[error("no pending invite")]
+    NoInvite,
+}
+
+impl Intent {
+    /// Check that `app` can carry out this intent.
+    ///
+    /// Frontends can use this to disable menu entries and palette items
+    /// that [`App::dispatch`] would reject.
+    pub fn check(&self, app: &App) -> Result<(), IntentError> {
+        let active = app.active_room_state();
+        let in_room = |room_id: &RoomId| app.rooms().contains_key(room_id);
+
+        match self {
+            Self::Connect => match app.connection_state() {
+                ConnectionState::Disconnected => Ok(()),
+                ConnectionState::Connecting | ConnectionState::Connected { .. } => {
+                    Err(IntentError::AlreadyConnected)
+                },
+            },
+            Self::SwitchAccount { account }
+                if !app.accounts().iter().any(|summary| summary.id == *account) =>
+            {
+                Err(IntentError::NoSuchAccount { account: *account })
+            },
+            Self::CreateRoom { room_id } | Self::JoinRoom { room_id } if in_room(room_id) => {
+                Err(IntentError::AlreadyInRoom { room_id: *room_id })
+            },
+            Self::SelectRoom { room_id } if !in_room(room_id) => {
+                Err(IntentError::NotInRoom { room_id: *room_id })
+            },
+            Self::LeaveRoom
+            | Self::AddMember { .. }
+            | Self::RemoveMember { .. }
+            | Self::InviteUser { .. }
+            | Self::EditDraft { .. }
+            | Self::ReplyTo { .. }
+            | Self::Scroll { .. }
+            | Self::LoadHistory
+            | Self::SendMessage { .. }
+            | Self::EditMessage { .. }
+            | Self::DeleteMessage { .. }
+            | Self::ListRoom { .. }
+            | Self::PinRoom { .. }
+                if active.is_none() =>
+            {
+                Err(IntentError::NoActiveRoom)
+            },
+            Self::AddMember { user_id } | Self::InviteUser { user_id }
+                if active.is_some_and(|room| room.members.contains(user_id)) =>
+            {
+                Err(IntentError::AlreadyMember { user_id: *user_id })
+            },
+            Self::RemoveMember { user_id }
+                if !active.is_some_and(|room| room.members.contains(user_id)) =>
+            {
+                Err(IntentError::NotMember { user_id: *user_id })
+            },
+            Self::SendMessage { content } | Self::EditMessage { content, .. }
+                if content.trim().is_empty() =>
+            {
+                Err(IntentError::EmptyMessage)
+            },
+            Self::EditMessage { log_index, .. } | Self::DeleteMessage { log_index } => {
+                let ConnectionState::Connected { sender_id, .. } = *app.connection_state() else {
+                    return Err(IntentError::NotConnected);
+                };
+                let ours = active.is_some_and(|room| {
+                    room.messages.iter().any(|m| {
+                        m.log_index == Some(*log_index) && m.sender_id == sender_id && !m.deleted
+                    })
+                });
+                if ours {
+                    Ok(())
+                } else {
+                    Err(IntentError::NoSuchMessage { log_index: *log_index })
+                }
+            },
+            Self::ReplyTo { log_index: Some(log_index) }
+                if !active.is_some_and(|room| {
+                    room.messages.iter().any(|m| m.log_index == Some(*log_index) && !m.deleted)
+                }) =>
+            {
+                Err(IntentError::NoMessage { log_index: *log_index })
+            },
+            Self::AcceptInvite { room_id } | Self::DeclineInvite { room_id }
+                if app.invite(*room_id).is_none() =>
+            {
+                Err(IntentError::NoInvite)
+            },
+            Self::NextRooms => match app.directory() {
+                None => Err(IntentError::NoDirectory),
+                Some(directory) if directory.next.is_none() => Err(IntentError::NoMoreRooms),
+                Some(_) => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
@@ -6,23 +6,52 @@
 //!
 //! # Components
 //!
-//! - [`App`]: Application state (rooms, connection, status)
+//! - [`App`]: Application state (accounts, rooms, connection, status)
 //! - [`Bridge`]: Protocol bridge (translates App actions to Client events)
 //! - [`Driver`]: Trait for platform-specific I/O abstraction
+//! - [`Intent`]: What the user asked for, for frontends to translate input into
 //! - [`Runtime`]: Generic orchestration loop using Driver
+//! - [`ScriptDriver`]: Headless Driver that plays a [`Script`], for tests and
+//!   bots
+//!
+//! # Features
+//!
+//! - `snapshot`: make [`AppSnapshot`] and the state and event types
+//!   serde-serializable.
+//! - `devtools`: record every input the [`App`] handles in an `EventLog` that
+//!   can be dumped, replayed and stepped back through.
 
 mod action;
 mod app;
 mod bridge;
+#[cfg(feature = "devtools")]
+mod devtools;
 mod driver;
 mod event;
+mod intent;
+mod notifier;
 mod runtime;
+mod script;
+mod session;
+mod snapshot;
 mod state;
+mod timer;
 
 pub use action::AppAction;
-pub use app::App;
+pub use app::{App, MAX_NOTICES};
 pub use bridge::Bridge;
+#[cfg(feature = "devtools")]
+pub use devtools::{EventLog, Input, LogEntry};
 pub use driver::Driver;
 pub use event::AppEvent;
+pub use intent::{Intent, IntentError};
+pub use notifier::{NoopNotifier, Notifier};
 pub use runtime::Runtime;
-pub use state::{ConnectionState, Message, RoomState};
+pub use script::{Recording, Script, ScriptDriver, Snapshot, Step};
+pub use snapshot::AppSnapshot;
+pub use state::{
+    AccountId, AccountSummary, ConnectionQuality, ConnectionState, CredentialKind, Delivery,
+    Directory, Draft, History, Member, Mentions, Message, Notice, Notification, PendingInvite,
+    ReactionChange, RestoreStep, RoomOrder, RoomState, Severity,
+};
+pub use timer::TimerId;
@@ -0,0 +1,23 @@
+//! Where notifications go.
+//!
+//! The App decides what deserves the user's attention and returns it as
+//! [`crate::AppAction::Notify`]. The [`crate::Runtime`] hands each
+//! [`Notification`] to the [`Notifier`] its [`crate::Driver`] provides: a
+//! terminal frontend rings the bell or raises a desktop notification, a
+//! simulation drops it with [`NoopNotifier`].
+
+use crate::Notification;
+
+/// Surfaces notifications to the user.
+pub trait Notifier: Send {
+    /// Surface `notification`.
+    fn notify(&mut self, notification: &Notification);
+}
+
+/// Notifier that drops every notification.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopNotifier;
+
+impl Notifier for NoopNotifier {
+    fn notify(&mut self, _notification: &Notification) {}
+}
@@ -4,16 +4,48 @@
 //! - [`App`]: UI state machine
 //! - [`Bridge`]: Protocol bridge to Client
 //! - [`Driver`]: Platform-specific I/O
+//!
+//! It also keeps timers: actions to run at a later point on the driver's
+//! clock, scheduled by the embedder or by the App with
+//! [`AppAction::Schedule`].
+//!
+//! Each account has its own [`Bridge`] and connection. When an account's
+//! connection drops the runtime reconnects it with exponential backoff, and
+//! its Bridge restores the session.
 
 use std::{ops::Sub, time::Duration};
 
 use lockframe_core::env::Environment;
-use lockframe_proto::{Frame, FrameHeader, Opcode, Payload, payloads::session::Hello};
 
-use crate::{App, AppAction, AppEvent, Bridge, Driver};
+use crate::{
+    AccountId, App, AppAction, AppEvent, Bridge, Driver, Notifier, Severity,
+    timer::{TimerId, Timers},
+};
+
+/// Wait before the first reconnect attempt.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between reconnect attempts.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_mins(1);
+
+/// An account's bridge and connection.
+struct Link<E: Environment> {
+    account: AccountId,
+    bridge: Bridge<E>,
+    server_addr: String,
+    auth_token: Option<Vec<u8>>,
+    /// Whether the driver was connected at the end of the last cycle
+    up: bool,
+    /// Reconnects attempted since the connection was lost
+    reconnect_attempts: u32,
+}
 
 /// Generic runtime that orchestrates App, Bridge, and Driver.
 ///
+/// Each account the App holds has its own Bridge and connection. Actions
+/// from frontend input and timers apply to the active account, and events
+/// from an account's Bridge are handled for that account.
+///
 /// # Type Parameters
 ///
 /// - `D`: Platform-specific I/O driver
@@ -25,8 +57,8 @@ where
 {
     driver: D,
     app: App,
-    bridge: Bridge<E>,
-    server_addr: String,
+    links: Vec<Link<E>>,
+    timers: Timers<D::Instant>,
 }
 
 impl<D, E> Runtime<D, E>
@@ -38,8 +70,70 @@ where
     /// Create a new runtime with the given driver and environment.
     pub fn new(driver: D, env: E, sender_id: u64, server_addr: String) -> Self {
         let app = App::new(server_addr.clone());
-        let bridge = Bridge::new(env, sender_id);
-        Self { driver, app, bridge, server_addr }
+        let link = Link {
+            account: app.active_account(),
+            bridge: Bridge::new(env, sender_id),
+            server_addr,
+            auth_token: None,
+            up: false,
+            reconnect_attempts: 0,
+        };
+        Self { driver, app, links: vec![link], timers: Timers::default() }
+    }
+
+    /// Present `token` in Hello. Servers with an authenticator configured
+    /// require one, and refuse it unless it was issued to `sender_id`.
+    #[must_use]
+    pub fn with_auth_token(mut self, token: impl Into<Vec<u8>>) -> Self {
+        if let Some(link) = self.links.first_mut() {
+            link.auth_token = Some(token.into());
+        }
+        self
+    }
+
+    /// Add an account connecting to `server_addr` as `sender_id`,
+    /// presenting `auth_token` in Hello if given.
+    ///
+    /// Accounts added before [`Runtime::run`] connect when it starts.
+    pub fn add_account(
+        &mut self,
+        name: impl Into<String>,
+        env: E,
+        sender_id: u64,
+        server_addr: String,
+        auth_token: Option<Vec<u8>>,
+    ) -> AccountId {
+        let account = self.app.add_account(name, server_addr.clone());
+        self.links.push(Link {
+            account,
+            bridge: Bridge::new(env, sender_id),
+            server_addr,
+            auth_token,
+            up: false,
+            reconnect_attempts: 0,
+        });
+        account
+    }
+
+    /// Run `action` once the driver's clock reaches `at`.
+    pub fn schedule(&mut self, at: D::Instant, action: AppAction) -> TimerId {
+        self.timers.schedule(at, Duration::ZERO, None, action)
+    }
+
+    /// Run `action` once `after` has passed.
+    pub fn schedule_in(&mut self, after: Duration, action: AppAction) -> TimerId {
+        self.timers.schedule(self.driver.now(), after, None, action)
+    }
+
+    /// Run `action` every `period`, starting one period from now. A cycle
+    /// that falls behind by several periods runs it once.
+    pub fn schedule_every(&mut self, period: Duration, action: AppAction) -> TimerId {
+        self.timers.schedule(self.driver.now(), period, Some(period), action)
+    }
+
+    /// Cancel a timer. Returns `false` if it already fired or was cancelled.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        self.timers.cancel(id)
     }
 
     /// Run the main event loop.
@@ -50,12 +144,17 @@ where
     /// 3. Processes actions and events between App and Bridge
     /// 4. Sends outgoing frames through the driver
     ///
+    /// Returns the App as it was when the application quit.
+    ///
     /// # Errors
     ///
     /// Returns an error if the driver encounters an I/O error.
-    pub async fn run(mut self) -> Result<(), D::Error> {
+    pub async fn run(mut self) -> Result<App, D::Error> {
         self.driver.render(&self.app)?;
-        self.connect().await?;
+        let accounts: Vec<AccountId> = self.links.iter().map(|link| link.account).collect();
+        for account in accounts {
+            self.connect(account).await?;
+        }
 
         loop {
             let should_quit = self.process_cycle().await?;
@@ -65,7 +164,7 @@ where
         }
 
         self.driver.stop();
-        Ok(())
+        Ok(self.app)
     }
 
     /// Process one cycle of the event loop.
@@ -73,37 +172,59 @@ where
     /// Returns `true` if the application should quit.
     async fn process_cycle(&mut self) -> Result<bool, D::Error> {
         let actions = self.driver.poll_event(&mut self.app).await?;
-        if !actions.is_empty() && self.process_actions(actions).await? {
+        let active = self.app.active_account();
+        if !actions.is_empty() && self.process_actions(active, actions).await? {
             return Ok(true);
         }
 
-        if self.driver.is_connected()
-            && let Some(frame) = self.driver.recv_frame().await
+        if let Some((account, frame)) = self.driver.recv_frame().await
+            && let Some(link) = self.link_mut(account)
         {
-            if let Some(Opcode::HelloReply) = frame.header.opcode_enum() {
-                self.handle_hello_reply(frame).await?;
-            } else {
-                let events = self.bridge.handle_frame(frame);
-                self.send_outgoing_frames().await?;
-                if self.process_bridge_events(events).await? {
+            let events = link.bridge.handle_frame(frame);
+            self.send_outgoing_frames(account).await?;
+            if self.process_bridge_events(account, events).await? {
+                return Ok(true);
+            }
+        }
+
+        let now = self.driver.now();
+        for index in 0..self.links.len() {
+            let link = &mut self.links[index];
+            let account = link.account;
+            if link.up && !self.driver.is_connected(account) {
+                link.up = false;
+                let events = link.bridge.connection_lost();
+                if self.process_bridge_events(account, events).await? {
                     return Ok(true);
                 }
+                self.schedule_reconnect(account);
+            }
+
+            let events = self.links[index].bridge.handle_tick(now);
+            self.send_outgoing_frames(account).await?;
+            if self.process_bridge_events(account, events).await? {
+                return Ok(true);
             }
         }
 
-        let now = self.driver.now();
-        let events = self.bridge.handle_tick(now);
-        if self.process_bridge_events(events).await? {
+        let due = self.timers.take_due(now);
+        let active = self.app.active_account();
+        if !due.is_empty() && self.process_actions(active, due).await? {
             return Ok(true);
         }
+        self.driver.set_wakeup(self.timers.next_due_in(self.driver.now()));
 
         Ok(false)
     }
 
-    /// Process actions returned by the App.
+    /// Process actions the App produced for `account`.
     ///
     /// Returns `true` if should quit.
-    async fn process_actions(&mut self, initial_actions: Vec<AppAction>) -> Result<bool, D::Error> {
+    async fn process_actions(
+        &mut self,
+        account: AccountId,
+        initial_actions: Vec<AppAction>,
+    ) -> Result<bool, D::Error> {
         let mut pending_actions = initial_actions;
 
         while !pending_actions.is_empty() {
@@ -113,23 +234,64 @@ where
                 match action {
                     AppAction::Render => self.driver.render(&self.app)?,
                     AppAction::Quit => return Ok(true),
-                    AppAction::Connect { server_addr: _ } => {
-                        self.connect().await?;
+                    AppAction::Notify(notification) => {
+                        self.driver.notifier().notify(&notification);
+                    },
+                    AppAction::Schedule { after, action } => {
+                        self.schedule_in(after, *action);
+                    },
+                    AppAction::PinRoom { room_id, pinned } => {
+                        pending_actions.extend(self.app.pin_room(room_id, pinned));
+                    },
+                    AppAction::SetRoomOrder { order } => {
+                        pending_actions.extend(self.app.set_room_order(order));
+                    },
+                    AppAction::Connect { account, server_addr: _ } => {
+                        let reconnecting =
+                            self.link_mut(account).is_some_and(|link| link.reconnect_attempts > 0);
+                        match self.connect(account).await {
+                            Err(e) if reconnecting => {
+                                tracing::warn!("Reconnect failed: {:?}", e);
+                                pending_actions.extend(self.app.handle_for(
+                                    account,
+                                    AppEvent::Notice {
+                                        severity: Severity::Warning,
+                                        room_id: None,
+                                        message: format!("Reconnect failed: {e}"),
+                                    },
+                                ));
+                                self.schedule_reconnect(account);
+                            },
+                            result => result?,
+                        }
                     },
 
-                    // Protocol operations go through the bridge
+                    // Protocol operations go through the account's bridge
                     AppAction::CreateRoom { .. }
                     | AppAction::JoinRoom { .. }
                     | AppAction::LeaveRoom { .. }
                     | AppAction::SendMessage { .. }
+                    | AppAction::EditMessage { .. }
+                    | AppAction::DeleteMessage { .. }
                     | AppAction::PublishKeyPackage
-                    | AppAction::AddMember { .. } => {
-                        let events = self.bridge.process_app_action(action);
+                    | AppAction::AddMember { .. }
+                    | AppAction::RemoveMember { .. }
+                    | AppAction::InviteUser { .. }
+                    | AppAction::SearchDirectory { .. }
+                    | AppAction::SetRoomListing { .. }
+                    | AppAction::LoadHistory { .. }
+                    | AppAction::SendTyping { .. }
+                    | AppAction::SaveDraft { .. } => {
+                        let Some(link) = self.link_mut(account) else {
+                            tracing::warn!(?account, "action for unknown account");
+                            continue;
+                        };
+                        let events = link.bridge.process_app_action(action);
                         for event in events {
-                            let new_actions = self.app.handle(event);
+                            let new_actions = self.app.handle_for(account, event);
                             pending_actions.extend(new_actions);
                         }
-                        self.send_outgoing_frames().await?;
+                        self.send_outgoing_frames(account).await?;
                     },
                 }
             }
@@ -137,39 +299,6 @@ where
         Ok(false)
     }
 
-    /// Handle `HelloReply` frame to complete connection handshake.
-    async fn handle_hello_reply(&mut self, frame: Frame) -> Result<(), D::Error> {
-        let payload = match Payload::from_frame(&frame) {
-            Ok(p) => p,
-            Err(e) => {
-                tracing::warn!("Failed to parse HelloReply: {:?}", e);
-                return Ok(());
-            },
-        };
-
-        let hello_reply = match payload {
-            Payload::HelloReply(reply) => reply,
-            other => {
-                tracing::warn!("Unexpected payload type for HelloReply: {:?}", other);
-                return Ok(());
-            },
-        };
-
-        let events = self.bridge.process_app_action(AppAction::PublishKeyPackage);
-        for event in events {
-            let actions = self.app.handle(event);
-            self.process_actions_sync(actions);
-        }
-
-        let session_id = hello_reply.session_id;
-        let sender_id = self.bridge.sender_id();
-        let actions = self.app.handle(AppEvent::Connected { session_id, sender_id });
-        self.process_actions_sync(actions);
-
-        self.send_outgoing_frames().await?;
-        Ok(())
-    }
-
     /// Process actions synchronously (for use in sync contexts).
     fn process_actions_sync(&mut self, actions: Vec<AppAction>) {
         for action in actions {
@@ -180,6 +309,18 @@ where
                     }
                 },
                 AppAction::Quit => {},
+                AppAction::Notify(notification) => self.driver.notifier().notify(&notification),
+                AppAction::Schedule { after, action } => {
+                    self.schedule_in(after, *action);
+                },
+                AppAction::PinRoom { room_id, pinned } => {
+                    let actions = self.app.pin_room(room_id, pinned);
+                    self.process_actions_sync(actions);
+                },
+                AppAction::SetRoomOrder { order } => {
+                    let actions = self.app.set_room_order(order);
+                    self.process_actions_sync(actions);
+                },
 
                 // Protocol actions shouldn't happen in sync contexts
                 AppAction::Connect { .. }
@@ -187,66 +328,96 @@ where
                 | AppAction::JoinRoom { .. }
                 | AppAction::LeaveRoom { .. }
                 | AppAction::SendMessage { .. }
+                | AppAction::EditMessage { .. }
+                | AppAction::DeleteMessage { .. }
                 | AppAction::PublishKeyPackage
-                | AppAction::AddMember { .. } => {
+                | AppAction::AddMember { .. }
+                | AppAction::RemoveMember { .. }
+                | AppAction::InviteUser { .. }
+                | AppAction::SearchDirectory { .. }
+                | AppAction::SetRoomListing { .. }
+                | AppAction::LoadHistory { .. }
+                | AppAction::SendTyping { .. }
+                | AppAction::SaveDraft { .. } => {
                     tracing::warn!("Unexpected protocol action in sync context: {:?}", action);
                 },
             }
         }
     }
 
-    /// Process events from Bridge back to App.
-    async fn process_bridge_events(&mut self, events: Vec<AppEvent>) -> Result<bool, D::Error> {
+    /// Process events from an account's Bridge back to App.
+    async fn process_bridge_events(
+        &mut self,
+        account: AccountId,
+        events: Vec<AppEvent>,
+    ) -> Result<bool, D::Error> {
         for event in events {
-            let actions = self.app.handle(event);
-            if self.process_actions(actions).await? {
+            let actions = self.app.handle_for(account, event);
+            if self.process_actions(account, actions).await? {
                 return Ok(true);
             }
         }
         Ok(false)
     }
 
-    /// Connect to the server and send Hello.
-    async fn connect(&mut self) -> Result<(), D::Error> {
-        self.driver.connect(&self.server_addr).await?;
+    /// Connect an account to its server and open a session.
+    async fn connect(&mut self, account: AccountId) -> Result<(), D::Error> {
+        let Some(link) = self.link_mut(account) else {
+            tracing::warn!(?account, "connect for unknown account");
+            return Ok(());
+        };
+        let server_addr = link.server_addr.clone();
+        self.driver.connect(account, &server_addr).await?;
 
-        let actions = self.app.handle(AppEvent::Connecting);
+        let Some(link) = self.link_mut(account) else {
+            return Ok(());
+        };
+        link.up = true;
+        link.reconnect_attempts = 0;
+        let auth_token = link.auth_token.clone();
+        let events = link.bridge.begin_session(auth_token);
+
+        let actions = self.app.handle_for(account, AppEvent::Connecting);
         self.process_actions_sync(actions);
-        self.send_hello().await?;
+        for event in events {
+            let actions = self.app.handle_for(account, event);
+            self.process_actions_sync(actions);
+        }
 
-        Ok(())
+        self.send_outgoing_frames(account).await
     }
 
-    /// Send Hello frame to authenticate with the server.
-    async fn send_hello(&mut self) -> Result<(), D::Error> {
-        let sender_id = self.bridge.sender_id();
-        let hello = Hello {
-            version: 1,
-            capabilities: Vec::new(),
-            sender_id: Some(sender_id),
-            auth_token: None,
-        };
-
-        let frame = match Payload::Hello(hello).into_frame(FrameHeader::new(Opcode::Hello)) {
-            Ok(frame) => frame,
-            Err(e) => {
-                tracing::error!("Failed to create Hello frame: {:?}", e);
-                return Ok(());
-            },
+    /// Try to connect an account again after a backoff that doubles with
+    /// each failed attempt, up to [`MAX_RECONNECT_DELAY`].
+    fn schedule_reconnect(&mut self, account: AccountId) {
+        let Some(link) = self.link_mut(account) else {
+            return;
         };
-
-        self.driver.send_frame(frame).await
+        let delay = RECONNECT_DELAY
+            .saturating_mul(1 << link.reconnect_attempts.min(16))
+            .min(MAX_RECONNECT_DELAY);
+        link.reconnect_attempts = link.reconnect_attempts.saturating_add(1);
+        tracing::info!(?account, attempt = link.reconnect_attempts, ?delay, "scheduling reconnect");
+        let server_addr = link.server_addr.clone();
+        self.schedule_in(delay, AppAction::Connect { account, server_addr });
     }
 
-    /// Send all pending outgoing frames to the server.
-    async fn send_outgoing_frames(&mut self) -> Result<(), D::Error> {
-        let frames = self.bridge.take_outgoing();
+    /// Send an account's pending outgoing frames to its server.
+    async fn send_outgoing_frames(&mut self, account: AccountId) -> Result<(), D::Error> {
+        let Some(link) = self.link_mut(account) else {
+            return Ok(());
+        };
+        let frames = link.bridge.take_outgoing();
         for frame in frames {
-            self.driver.send_frame(frame).await?;
+            self.driver.send_frame(account, frame).await?;
         }
         Ok(())
     }
 
+    fn link_mut(&mut self, account: AccountId) -> Option<&mut Link<E>> {
+        self.links.iter_mut().find(|link| link.account == account)
+    }
+
     /// Get a reference to the App
     pub fn app(&self) -> &App {
         &self.app
@@ -0,0 +1,257 @@
+//! Headless driver that plays a script.
+//!
+//! [`ScriptDriver`] implements [`Driver`] without a terminal or network: it
+//! feeds the App a [`Script`] of timed steps and records what the
+//! [`crate::Runtime`] does in response, the frames it sends and the App as it
+//! was at each render. Integration tests and bots run the full Runtime with
+//! it.
+//!
+//! Step times count from the driver's creation on the environment's clock,
+//! so under virtual time a script of minutes plays instantly.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use lockframe_core::env::Environment;
+use lockframe_proto::Frame;
+
+use crate::{AccountId, App, AppAction, AppEvent, Driver, Intent, Notification, Notifier};
+
+/// One scripted input.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Dispatch an intent, as a frontend would.
+    Intent(Intent),
+    /// Feed an event to the App.
+    Event(AppEvent),
+    /// Deliver a frame as if an account's server sent it.
+    Frame {
+        /// Account whose server sent the frame.
+        account: AccountId,
+        /// The frame.
+        frame: Frame,
+    },
+    /// Do nothing. A last step keeps the runtime running until it is due.
+    Wait,
+}
+
+/// Steps to play, each at a time after the driver starts.
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    steps: VecDeque<(Duration, Step)>,
+}
+
+impl Script {
+    /// Create an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step to play `at` after the driver starts. Steps due at the
+    /// same time play in the order they were added.
+    #[must_use]
+    pub fn at(mut self, at: Duration, step: Step) -> Self {
+        let index = self.steps.partition_point(|(due, _)| *due <= at);
+        self.steps.insert(index, (at, step));
+        self
+    }
+
+    /// Steps left to play.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether every step was played.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// The App as it was rendered.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Time since the driver started.
+    pub at: Duration,
+    /// App state.
+    pub app: App,
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    snapshots: Vec<Snapshot>,
+    sent: Vec<(AccountId, Frame)>,
+    notifications: Vec<Notification>,
+}
+
+/// What a [`ScriptDriver`] recorded. Stays readable after the driver is
+/// moved into a [`crate::Runtime`].
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    inner: Arc<Mutex<Recorded>>,
+}
+
+impl Recording {
+    /// The App at each render, oldest first.
+    pub fn snapshots(&self) -> Vec<Snapshot> {
+        self.with(|recorded| recorded.snapshots.clone())
+    }
+
+    /// The App at the last render.
+    pub fn last(&self) -> Option<Snapshot> {
+        self.with(|recorded| recorded.snapshots.last().cloned())
+    }
+
+    /// Take the frames sent so far, with the account that sent each.
+    pub fn take_sent(&self) -> Vec<(AccountId, Frame)> {
+        self.with(|recorded| std::mem::take(&mut recorded.sent))
+    }
+
+    /// Notifications surfaced so far.
+    pub fn notifications(&self) -> Vec<Notification> {
+        self.with(|recorded| recorded.notifications.clone())
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Recorded) -> T) -> T {
+        f(&mut self.inner.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Notifier for Recording {
+    fn notify(&mut self, notification: &Notification) {
+        self.with(|recorded| recorded.notifications.push(notification.clone()));
+    }
+}
+
+/// Driver that plays a [`Script`] and records the results.
+///
+/// Every account connects at once and stays connected. The App quits once
+/// the last step has played.
+pub struct ScriptDriver<E: Environment> {
+    env: E,
+    start: E::Instant,
+    script: Script,
+    incoming: VecDeque<(AccountId, Frame)>,
+    connected: BTreeSet<AccountId>,
+    wakeup: Option<Duration>,
+    recording: Recording,
+}
+
+impl<E: Environment> ScriptDriver<E> {
+    /// Create a driver that plays `script` on `env`'s clock, starting now.
+    pub fn new(env: E, script: Script) -> Self {
+        let start = env.now();
+        Self {
+            env,
+            start,
+            script,
+            incoming: VecDeque::new(),
+            connected: BTreeSet::new(),
+            wakeup: None,
+            recording: Recording::default(),
+        }
+    }
+
+    /// Handle to what the driver records.
+    pub fn recording(&self) -> Recording {
+        self.recording.clone()
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.env.now() - self.start
+    }
+}
+
+impl<E: Environment> Driver for ScriptDriver<E> {
+    type Error = Infallible;
+    type Instant = E::Instant;
+    type Notifier = Recording;
+
+    async fn poll_event(&mut self, app: &mut App) -> Result<Vec<AppAction>, Self::Error> {
+        let Some(&(at, _)) = self.script.steps.front() else {
+            return Ok(vec![AppAction::Quit]);
+        };
+
+        if let Some(until_step) = at.checked_sub(self.elapsed()).filter(|wait| !wait.is_zero()) {
+            let wait = self.wakeup.map_or(until_step, |wakeup| wakeup.min(until_step));
+            self.env.sleep(wait).await;
+            return Ok(vec![]);
+        }
+
+        let Some((_, step)) = self.script.steps.pop_front() else {
+            return Ok(vec![]);
+        };
+        Ok(match step {
+            Step::Intent(intent) => app.dispatch(intent),
+            Step::Event(event) => app.handle(event),
+            Step::Frame { account, frame } => {
+                self.incoming.push_back((account, frame));
+                vec![]
+            },
+            Step::Wait => vec![],
+        })
+    }
+
+    async fn send_frame(&mut self, account: AccountId, frame: Frame) -> Result<(), Self::Error> {
+        self.recording.with(|recorded| recorded.sent.push((account, frame)));
+        Ok(())
+    }
+
+    async fn recv_frame(&mut self) -> Option<(AccountId, Frame)> {
+        self.incoming.pop_front()
+    }
+
+    async fn connect(&mut self, account: AccountId, _addr: &str) -> Result<(), Self::Error> {
+        self.connected.insert(account);
+        Ok(())
+    }
+
+    fn is_connected(&self, account: AccountId) -> bool {
+        self.connected.contains(&account)
+    }
+
+    fn now(&self) -> Self::Instant {
+        self.env.now()
+    }
+
+    fn set_wakeup(&mut self, after: Option<Duration>) {
+        self.wakeup = after;
+    }
+
+    fn render(&mut self, app: &App) -> Result<(), Self::Error> {
+        let snapshot = Snapshot { at: self.elapsed(), app: app.clone() };
+        self.recording.with(|recorded| recorded.snapshots.push(snapshot));
+        Ok(())
+    }
+
+    fn notifier(&mut self) -> &mut Self::Notifier {
+        &mut self.recording
+    }
+
+    fn stop(&mut self) {
+        self.connected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_kept_in_time_order() {
+        let script = Script::new()
+            .at(Duration::from_secs(2), Step::Wait)
+            .at(Duration::from_secs(1), Step::Intent(Intent::Connect))
+            .at(Duration::from_secs(1), Step::Intent(Intent::Quit));
+
+        let steps: Vec<_> = script.steps.iter().map(|(at, step)| (at.as_secs(), step)).collect();
+        assert!(matches!(steps[..], [
+            (1, Step::Intent(Intent::Connect)),
+            (1, Step::Intent(Intent::Quit)),
+            (2, Step::Wait)
+        ]));
+    }
+}
@@ -0,0 +1,235 @@
+//! Session restore after a lost connection.
+//!
+//! When the connection drops, the [`crate::Bridge`] keeps working offline:
+//! frames it produces wait in the outbox. Reconnecting restores the session
+//! in steps, each reported as an [`crate::AppEvent::SessionRestore`]:
+//!
+//! 1. Authenticating: a new Hello goes out, and nothing else until the server
+//!    replies.
+//! 2. Syncing: every joined room is re-synced from the last log index seen in
+//!    it, so messages sent while offline are fetched.
+//! 3. Replaying: once every room answered, or after [`RESYNC_TIMEOUT`], the
+//!    outbox is released.
+//!
+//! A bridge that never lost its connection passes frames straight through,
+//! so the first session needs no restore.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Sub,
+    time::Duration,
+};
+
+use lockframe_core::mls::RoomId;
+use lockframe_proto::{Frame, Opcode};
+
+use crate::RestoreStep;
+
+/// Longest wait for rooms to answer their re-sync before the outbox is
+/// released anyway.
+pub(crate) const RESYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+enum Phase<I> {
+    /// Connected, or never disconnected
+    Live,
+    /// Connection lost, no new session yet
+    Lost,
+    /// Hello sent, waiting for the reply
+    Authenticating,
+    /// Waiting for re-synced rooms to answer
+    Syncing {
+        rooms: HashSet<RoomId>,
+        /// First tick seen while syncing
+        since: Option<I>,
+    },
+}
+
+/// Connection phase and what restoring a session needs to know.
+#[derive(Debug)]
+pub(crate) struct Session<I> {
+    phase: Phase<I>,
+    /// Joined rooms and the highest log index seen in each
+    watermarks: HashMap<RoomId, Option<u64>>,
+}
+
+impl<I> Default for Session<I> {
+    fn default() -> Self {
+        Self { phase: Phase::Live, watermarks: HashMap::new() }
+    }
+}
+
+impl<I: Copy + Ord + Sub<Output = Duration>> Session<I> {
+    /// Whether `frame` may be sent now. Before a restored session is
+    /// established only the handshake, heartbeats and re-syncs go out.
+    pub(crate) fn passes(&self, frame: &Frame) -> bool {
+        match self.phase {
+            Phase::Live => true,
+            Phase::Lost => false,
+            Phase::Authenticating | Phase::Syncing { .. } => {
+                matches!(
+                    frame.header.opcode_enum(),
+                    Some(Opcode::Hello | Opcode::Ping | Opcode::Pong | Opcode::SyncRequest)
+                )
+            },
+        }
+    }
+
+    /// Whether frames are held back for a restore.
+    pub(crate) fn is_restoring(&self) -> bool {
+        !matches!(self.phase, Phase::Live)
+    }
+
+    pub(crate) fn joined(&mut self, room_id: RoomId) {
+        self.watermarks.entry(room_id).or_insert(None);
+    }
+
+    pub(crate) fn left(&mut self, room_id: RoomId) {
+        self.watermarks.remove(&room_id);
+        if let Phase::Syncing { rooms, .. } = &mut self.phase {
+            rooms.remove(&room_id);
+        }
+    }
+
+    /// A frame at `log_index` was seen in a joined room.
+    pub(crate) fn saw(&mut self, room_id: RoomId, log_index: u64) {
+        if let Some(seen) = self.watermarks.get_mut(&room_id) {
+            *seen = Some(seen.map_or(log_index, |seen| seen.max(log_index)));
+        }
+    }
+
+    pub(crate) fn lost(&mut self) {
+        self.phase = Phase::Lost;
+    }
+
+    /// A Hello was sent. Returns the first restore step if this restores a
+    /// lost session.
+    pub(crate) fn hello_sent(&mut self) -> Option<RestoreStep> {
+        if matches!(self.phase, Phase::Live) {
+            return None;
+        }
+        self.phase = Phase::Authenticating;
+        Some(RestoreStep::Authenticating)
+    }
+
+    /// The server accepted the session. Returns the rooms to re-sync and the
+    /// log index to sync each from, if restoring.
+    pub(crate) fn authenticated(&mut self) -> Option<Vec<(RoomId, u64)>> {
+        if !matches!(self.phase, Phase::Authenticating) {
+            return None;
+        }
+        let resync: Vec<(RoomId, u64)> = self
+            .watermarks
+            .iter()
+            .map(|(&room_id, seen)| (room_id, seen.map_or(0, |seen| seen.saturating_add(1))))
+            .collect();
+        let rooms = resync.iter().map(|&(room_id, _)| room_id).collect();
+        self.phase = Phase::Syncing { rooms, since: None };
+        Some(resync)
+    }
+
+    /// A room answered its re-sync. Returns the rooms still syncing.
+    pub(crate) fn synced(&mut self, room_id: RoomId) -> Option<usize> {
+        let Phase::Syncing { rooms, .. } = &mut self.phase else {
+            return None;
+        };
+        rooms.remove(&room_id).then_some(rooms.len())
+    }
+
+    /// Whether re-syncing is over, because every room answered or it timed
+    /// out at `now`.
+    pub(crate) fn sync_finished(&mut self, now: Option<I>) -> bool {
+        let Phase::Syncing { rooms, since } = &mut self.phase else {
+            return false;
+        };
+        if rooms.is_empty() {
+            return true;
+        }
+        let Some(now) = now else {
+            return false;
+        };
+        let since = *since.get_or_insert(now);
+        if now - since < RESYNC_TIMEOUT {
+            return false;
+        }
+        tracing::warn!(rooms = rooms.len(), "re-sync timed out, releasing outbox");
+        true
+    }
+
+    /// Rooms that have not answered the re-sync.
+    pub(crate) fn unsynced(&self) -> usize {
+        match &self.phase {
+            Phase::Syncing { rooms, .. } => rooms.len(),
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn restored(&mut self) {
+        self.phase = Phase::Live;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::disallowed_methods)]
+
+    use std::time::Instant;
+
+    use lockframe_proto::FrameHeader;
+
+    use super::*;
+
+    fn frame(opcode: Opcode) -> Frame {
+        Frame::new(FrameHeader::new(opcode), Vec::new())
+    }
+
+    #[test]
+    fn first_session_needs_no_restore() {
+        let mut session = Session::<Instant>::default();
+        assert!(session.passes(&frame(Opcode::AppMessage)));
+        assert_eq!(session.hello_sent(), None);
+        assert_eq!(session.authenticated(), None);
+    }
+
+    #[test]
+    fn restore_resyncs_rooms_from_their_watermarks() {
+        let mut session = Session::<Instant>::default();
+        session.joined(1);
+        session.joined(2);
+        session.saw(1, 7);
+        session.saw(1, 4);
+        session.saw(3, 9);
+
+        session.lost();
+        assert!(!session.passes(&frame(Opcode::Hello)));
+        assert_eq!(session.hello_sent(), Some(RestoreStep::Authenticating));
+        assert!(session.passes(&frame(Opcode::Hello)));
+        assert!(!session.passes(&frame(Opcode::AppMessage)));
+
+        let mut resync = session.authenticated().unwrap();
+        resync.sort_unstable();
+        assert_eq!(resync, vec![(1, 8), (2, 0)]);
+        assert!(!session.sync_finished(None));
+
+        assert_eq!(session.synced(2), Some(1));
+        assert_eq!(session.synced(2), None, "already answered");
+        assert_eq!(session.synced(1), Some(0));
+        assert!(session.sync_finished(None));
+        session.restored();
+        assert!(session.passes(&frame(Opcode::AppMessage)));
+    }
+
+    #[test]
+    fn resync_gives_up_on_silent_rooms() {
+        let start = Instant::now();
+        let mut session = Session::default();
+        session.joined(1);
+        session.lost();
+        session.hello_sent();
+        session.authenticated();
+
+        assert!(!session.sync_finished(Some(start)));
+        assert!(!session.sync_finished(Some(start + Duration::from_secs(5))));
+        assert!(session.sync_finished(Some(start + RESYNC_TIMEOUT)));
+    }
+}
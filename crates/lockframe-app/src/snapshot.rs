@@ -0,0 +1,84 @@
+//! Point-in-time copies of App state.
+//!
+//! [`crate::App::snapshot`] captures everything the App holds for each of
+//! its accounts, and [`crate::App::restore`] rebuilds an App from it. With
+//! the `snapshot` feature an [`AppSnapshot`] is serde-serializable, so the
+//! frontend picks the format: the TUI writes one as JSON when it panics, and
+//! simulations checkpoint long runs and resume from them.
+//!
+//! Snapshots go into crash reports, so message content, reactions and drafts
+//! are redacted unless asked for with [`crate::App::snapshot_with_content`].
+//! Everything else, such as room names and member IDs, is kept.
+
+use lockframe_core::mls::RoomId;
+
+use crate::{
+    AccountId, ConnectionState, Directory, Draft, Mentions, Notice, PendingInvite, RoomOrder,
+    RoomState,
+};
+
+/// State of an App at one point in time.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct AppSnapshot {
+    /// Active account.
+    pub(crate) active: AccountSnapshot,
+    /// Accounts that are not active, in the order they were added.
+    pub(crate) parked: Vec<AccountSnapshot>,
+    pub(crate) next_account: u32,
+    pub(crate) terminal_size: (u16, u16),
+    pub(crate) status_message: Option<String>,
+    pub(crate) notices: Vec<Notice>,
+    pub(crate) next_notice: u64,
+    pub(crate) mentions: Mentions,
+    pub(crate) room_order: RoomOrder,
+    pub(crate) room_filter: String,
+    /// Message content and drafts were left out.
+    pub(crate) redacted: bool,
+}
+
+impl AppSnapshot {
+    /// Whether message content and drafts were left out. Restoring a
+    /// redacted snapshot gives rooms whose messages are empty.
+    pub fn is_redacted(&self) -> bool {
+        self.redacted
+    }
+
+    /// Accounts captured, the active one first.
+    pub fn accounts(&self) -> impl Iterator<Item = AccountId> + '_ {
+        std::iter::once(&self.active).chain(&self.parked).map(|account| account.id)
+    }
+
+    /// Blank out message content, reactions and drafts.
+    pub(crate) fn redact(&mut self) {
+        let rooms = std::iter::once(&mut self.active)
+            .chain(&mut self.parked)
+            .flat_map(|account| &mut account.rooms);
+        for room in rooms {
+            for message in &mut room.messages {
+                message.content.clear();
+                message.reactions.clear();
+            }
+            room.pending_reactions.clear();
+            room.draft = Draft::default();
+        }
+        self.redacted = true;
+    }
+}
+
+/// State of one account.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct AccountSnapshot {
+    pub(crate) id: AccountId,
+    pub(crate) name: String,
+    pub(crate) server_addr: String,
+    pub(crate) state: ConnectionState,
+    /// Rooms in room ID order.
+    pub(crate) rooms: Vec<RoomState>,
+    pub(crate) active_room: Option<RoomId>,
+    pub(crate) directory: Option<Directory>,
+    pub(crate) activity: u64,
+    pub(crate) reconnects: u32,
+    pub(crate) invites: Vec<PendingInvite>,
+}
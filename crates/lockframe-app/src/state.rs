@@ -7,12 +7,48 @@
 //! the subset of protocol state necessary for rendering the UI without exposing
 //! the cryptographic complexities of the underlying client.
 
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    time::Duration,
+};
 
-use lockframe_core::mls::RoomId;
+use lockframe_core::mls::{Credential, RoomId};
+use lockframe_proto::payloads::{
+    app::{Attachment, ReplyTo},
+    session::{DirectoryEntry, NoticeKind},
+};
+
+/// Identifies one of the accounts an [`crate::App`] holds.
+///
+/// The account an App is created with is `AccountId::default()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccountId(pub u32);
+
+/// One account as the account list shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountSummary {
+    /// Account ID.
+    pub id: AccountId,
+    /// Display name.
+    pub name: String,
+    /// Server address (host:port).
+    pub server_addr: String,
+    /// Connection state.
+    pub state: ConnectionState,
+    /// Unread messages across the account's rooms.
+    pub unread: usize,
+}
+
+/// Round trip above which the connection counts as degraded.
+const SLOW_RTT: Duration = Duration::from_secs(1);
+
+/// Most messages a room holds reactions for before the messages arrive.
+const MAX_PENDING_REACTION_TARGETS: usize = 256;
 
 /// Connection state.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConnectionState {
     /// Not connected to server.
     Disconnected,
@@ -24,11 +60,70 @@ pub enum ConnectionState {
         session_id: u64,
         /// Client's sender ID.
         sender_id: u64,
+        /// How well the connection is doing.
+        quality: ConnectionQuality,
     },
 }
 
+/// How well a connection is doing, from the heartbeats the bridge sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionQuality {
+    /// Round trip of the last answered heartbeat. `None` until one is
+    /// answered.
+    pub rtt: Option<Duration>,
+    /// Heartbeats in a row the server has not answered.
+    pub missed_heartbeats: u32,
+    /// Times the connection dropped and was re-established since the
+    /// account was added.
+    pub reconnects: u32,
+    /// How far restoring the session after the last reconnect got. `None`
+    /// once it is restored.
+    pub restoring: Option<RestoreStep>,
+}
+
+impl ConnectionQuality {
+    /// Heartbeats go unanswered or take over a second to come back, so
+    /// sends may be slow or fail.
+    pub fn is_degraded(&self) -> bool {
+        self.missed_heartbeats > 0 || self.rtt.is_some_and(|rtt| rtt > SLOW_RTT)
+    }
+}
+
+/// How much of a room's history from before its oldest message is loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum History {
+    /// Earlier messages may exist and can be loaded.
+    #[default]
+    Partial,
+    /// Earlier messages are being fetched.
+    Loading {
+        /// Frames fetched so far.
+        fetched: u64,
+        /// Frames expected in total.
+        total: u64,
+    },
+    /// Nothing earlier is left: the room's log starts at the oldest
+    /// message, or the server discarded the rest.
+    Complete,
+}
+
+/// Message being composed in a room.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct Draft {
+    /// Text typed so far.
+    pub text: String,
+    /// Cursor position within the text.
+    pub cursor: usize,
+    /// Log index of the message being replied to.
+    pub reply_to: Option<u64>,
+}
+
 /// Per-room state.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub struct RoomState {
     /// 128-bit room UUID.
     pub room_id: RoomId,
@@ -36,29 +131,682 @@ pub struct RoomState {
     pub messages: Vec<Message>,
     /// Member IDs in this room.
     pub members: HashSet<u64>,
-    /// Room has unread messages.
-    pub unread: bool,
+    /// What the group's ratchet tree says about each member, by user ID.
+    /// Filled in when the bridge reports the group's members.
+    pub roster: BTreeMap<u64, Member>,
+    /// Messages from other members received while the room was not active.
+    pub unread: usize,
+    /// Unread messages that mention the user.
+    pub mentions: usize,
+    /// Listed name from the room directory, if known.
+    pub name: Option<String>,
+    /// Room is kept at the top of the room list.
+    pub pinned: bool,
+    /// Position of the room's latest activity among all rooms. Higher is
+    /// more recent.
+    pub last_activity: u64,
+    /// Message being composed, kept while other rooms are active.
+    pub draft: Draft,
+    /// Messages scrolled up from the newest. 0 follows new messages.
+    pub scroll: usize,
+    /// Other members composing a message, by sender ID.
+    pub typing: BTreeSet<u64>,
+    /// How much history from before the oldest message is loaded.
+    pub history: History,
+    /// MLS epoch of the room's group, 0 until the bridge reports one.
+    pub epoch: u64,
+    /// Reactions to messages not held yet, by the target's log index, in
+    /// the order they arrived. Applied when the target is added.
+    pub pending_reactions: BTreeMap<u64, Vec<ReactionChange>>,
+    /// Log indexes of the replies to each message, by the log index of the
+    /// message replied to. Replies join once sequenced, whether or not
+    /// the message they reply to is held.
+    pub threads: BTreeMap<u64, BTreeSet<u64>>,
+    /// We asked to leave and wait for another member to commit our removal.
+    pub leaving: bool,
 }
 
 impl RoomState {
     /// Create empty room state.
     pub fn new(room_id: RoomId) -> Self {
-        Self { room_id, messages: Vec::new(), members: HashSet::new(), unread: false }
+        Self {
+            room_id,
+            messages: Vec::new(),
+            members: HashSet::new(),
+            roster: BTreeMap::new(),
+            unread: 0,
+            mentions: 0,
+            name: None,
+            pinned: false,
+            last_activity: 0,
+            draft: Draft::default(),
+            scroll: 0,
+            typing: BTreeSet::new(),
+            history: History::default(),
+            epoch: 0,
+            pending_reactions: BTreeMap::new(),
+            threads: BTreeMap::new(),
+            leaving: false,
+        }
+    }
+
+    /// Name to show for the room: its listed name, or its ID in hex.
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("{:x}", self.room_id))
+    }
+
+    /// Message with log index `log_index`, if held.
+    pub fn message(&self, log_index: u64) -> Option<&Message> {
+        self.messages.iter().find(|m| m.log_index == Some(log_index))
+    }
+
+    /// Log indexes of the replies to the message at `log_index`, in log
+    /// order.
+    pub fn replies(&self, log_index: u64) -> impl Iterator<Item = u64> + '_ {
+        self.threads.get(&log_index).into_iter().flatten().copied()
+    }
+
+    /// Log index of the oldest message held, if any was sequenced.
+    pub fn oldest_log_index(&self) -> Option<u64> {
+        self.messages.iter().filter_map(|m| m.log_index).min()
+    }
+
+    /// Add a message to this room, in log order. Loaded history goes before
+    /// the messages already held.
+    pub fn add_message(
+        &mut self,
+        sender_id: u64,
+        log_index: Option<u64>,
+        content: Vec<u8>,
+        reply_to: Option<ReplyTo>,
+        mentions_me: bool,
+        timestamp: u64,
+    ) {
+        // Sending the message ends composing it
+        self.typing.remove(&sender_id);
+
+        let index = log_index.map_or(self.messages.len(), |log_index| {
+            self.messages
+                .iter()
+                .position(|m| m.log_index.is_some_and(|other| other > log_index))
+                .unwrap_or(self.messages.len())
+        });
+
+        // Keep a scrolled-up view on the same messages. Those below the view
+        // push it up; those above it are counted from the newest anyway
+        if self.scroll > 0 && index >= self.messages.len() - self.scroll {
+            self.scroll += 1;
+        }
+        let mut message = Message {
+            sender_id,
+            log_index,
+            content,
+            edited: false,
+            deleted: false,
+            mentions_me,
+            delivery: if log_index.is_some() { Delivery::Delivered } else { Delivery::Sent },
+            local_id: None,
+            timestamp,
+            reactions: BTreeMap::new(),
+            reply_to,
+            attachment: None,
+        };
+        if let Some(log_index) = log_index {
+            for change in self.pending_reactions.remove(&log_index).unwrap_or_default() {
+                message.react(change);
+            }
+            if let Some(reply_to) = reply_to {
+                self.threads.entry(reply_to.target_log_index).or_default().insert(log_index);
+            }
+        }
+        self.messages.insert(index, message);
+    }
+
+    /// Replace the draft's text and cursor, keeping its reply target.
+    ///
+    /// Returns `true` if the draft changed.
+    pub fn set_draft(&mut self, text: String, cursor: usize) -> bool {
+        let cursor = cursor.min(text.len());
+        if self.draft.text == text && self.draft.cursor == cursor {
+            return false;
+        }
+        self.draft.text = text;
+        self.draft.cursor = cursor;
+        true
+    }
+
+    /// Put back a draft saved in an earlier run, unless one was started
+    /// since.
+    ///
+    /// Returns `true` if the draft changed.
+    pub fn restore_draft(&mut self, text: String) -> bool {
+        if !self.draft.text.is_empty() || text.is_empty() {
+            return false;
+        }
+        self.draft.cursor = text.len();
+        self.draft.text = text;
+        true
+    }
+
+    /// Record how much earlier history is loaded.
+    ///
+    /// Returns `true` if that changed.
+    pub fn set_history(&mut self, history: History) -> bool {
+        let changed = self.history != history;
+        self.history = history;
+        changed
+    }
+
+    /// Note whether a member is composing a message.
+    ///
+    /// Returns `true` if that changed.
+    pub fn set_typing(&mut self, sender_id: u64, active: bool) -> bool {
+        if active { self.typing.insert(sender_id) } else { self.typing.remove(&sender_id) }
+    }
+
+    /// Set the message being replied to, or clear it with `None`.
+    ///
+    /// Returns `true` if the reply target changed.
+    pub fn set_reply_target(&mut self, log_index: Option<u64>) -> bool {
+        let changed = self.draft.reply_to != log_index;
+        self.draft.reply_to = log_index;
+        changed
+    }
+
+    /// Member IDs in ascending order, as member lists show them.
+    pub fn sorted_members(&self) -> Vec<u64> {
+        let mut members: Vec<u64> = self.members.iter().copied().collect();
+        members.sort_unstable();
+        members
+    }
+
+    /// Replace the members with the group's current ones.
+    ///
+    /// Returns `true` if anything about them changed.
+    pub fn set_members(&mut self, members: Vec<Member>) -> bool {
+        let roster: BTreeMap<u64, Member> =
+            members.into_iter().map(|member| (member.user_id, member)).collect();
+        let ids: HashSet<u64> = roster.keys().copied().collect();
+        let changed = self.roster != roster || self.members != ids;
+        self.roster = roster;
+        self.members = ids;
+        changed
+    }
+
+    /// Remove a member.
+    ///
+    /// Returns `true` if they were a member.
+    pub fn remove_member(&mut self, user_id: u64) -> bool {
+        self.roster.remove(&user_id);
+        self.members.remove(&user_id)
+    }
+
+    /// Record the group moving to `epoch`.
+    ///
+    /// Returns `true` if the epoch changed.
+    pub fn set_epoch(&mut self, epoch: u64) -> bool {
+        let changed = self.epoch != epoch;
+        self.epoch = epoch;
+        changed
+    }
+
+    /// Scroll to `offset` messages up from the newest, stopping at the
+    /// oldest.
+    ///
+    /// Returns `true` if the position changed.
+    pub fn set_scroll(&mut self, offset: usize) -> bool {
+        let offset = offset.min(self.messages.len().saturating_sub(1));
+        let changed = self.scroll != offset;
+        self.scroll = offset;
+        changed
+    }
+
+    /// Add one of our own messages before the server has acknowledged it.
+    pub fn add_local_message(
+        &mut self,
+        sender_id: u64,
+        local_id: u64,
+        content: Vec<u8>,
+        reply_to: Option<ReplyTo>,
+        delivery: Delivery,
+        timestamp: u64,
+    ) {
+        self.messages.push(Message {
+            sender_id,
+            log_index: None,
+            content,
+            edited: false,
+            deleted: false,
+            mentions_me: false,
+            delivery,
+            local_id: Some(local_id),
+            timestamp,
+            reactions: BTreeMap::new(),
+            reply_to,
+            attachment: None,
+        });
+    }
+
+    /// Move one of our own messages to a new delivery state, recording the
+    /// log index the server gave it once delivered.
+    ///
+    /// Returns `true` if the message exists and its state changed.
+    pub fn set_delivery(
+        &mut self,
+        local_id: u64,
+        delivery: Delivery,
+        log_index: Option<u64>,
+    ) -> bool {
+        let Some(message) = self.messages.iter_mut().find(|m| m.local_id == Some(local_id)) else {
+            return false;
+        };
+        if message.delivery == delivery && (log_index.is_none() || message.log_index == log_index) {
+            return false;
+        }
+        message.delivery = delivery;
+        if let Some(log_index) = log_index {
+            message.log_index = Some(log_index);
+            for change in self.pending_reactions.remove(&log_index).unwrap_or_default() {
+                message.react(change);
+            }
+            if let Some(reply_to) = message.reply_to {
+                self.threads.entry(reply_to.target_log_index).or_default().insert(log_index);
+            }
+        }
+        true
+    }
+
+    /// Clear the unread and mention counts.
+    pub fn mark_read(&mut self) {
+        self.unread = 0;
+        self.mentions = 0;
+    }
+
+    /// Replace the content of a message.
+    ///
+    /// Only applies if the message exists and was sent by `sender_id`.
+    /// Returns `true` if the message was edited.
+    pub fn edit_message(&mut self, sender_id: u64, log_index: u64, content: Vec<u8>) -> bool {
+        let Some(message) = self.authored_message_mut(sender_id, log_index) else {
+            return false;
+        };
+        message.content = content;
+        message.edited = true;
+        true
+    }
+
+    /// Retract a message, keeping a placeholder in the history.
+    ///
+    /// Only applies if the message exists and was sent by `sender_id`.
+    /// Returns `true` if the message was deleted.
+    pub fn delete_message(&mut self, sender_id: u64, log_index: u64) -> bool {
+        let Some(message) = self.authored_message_mut(sender_id, log_index) else {
+            return false;
+        };
+        message.content.clear();
+        message.reactions.clear();
+        message.attachment = None;
+        message.deleted = true;
+        true
+    }
+
+    /// Add or remove a member's reaction to a message.
+    ///
+    /// A reaction to a message not held yet is kept until the message
+    /// arrives, for up to 256 messages at a time. Returns `true` if a held
+    /// message's reactions changed.
+    pub fn react(&mut self, target_log_index: u64, change: ReactionChange) -> bool {
+        if let Some(message) =
+            self.messages.iter_mut().find(|m| m.log_index == Some(target_log_index))
+        {
+            return message.react(change);
+        }
+        if self.pending_reactions.len() < MAX_PENDING_REACTION_TARGETS
+            || self.pending_reactions.contains_key(&target_log_index)
+        {
+            self.pending_reactions.entry(target_log_index).or_default().push(change);
+        }
+        false
+    }
+
+    /// Drop a disappearing message entirely, content included.
+    ///
+    /// Returns `true` if the message was held.
+    pub fn expire_message(&mut self, log_index: u64) -> bool {
+        self.pending_reactions.remove(&log_index);
+        self.threads.remove(&log_index);
+        if let Some(reply_to) = self.message(log_index).and_then(|m| m.reply_to)
+            && let Some(replies) = self.threads.get_mut(&reply_to.target_log_index)
+        {
+            replies.remove(&log_index);
+            if replies.is_empty() {
+                self.threads.remove(&reply_to.target_log_index);
+            }
+        }
+        let before = self.messages.len();
+        self.messages.retain(|m| m.log_index != Some(log_index));
+        self.messages.len() != before
+    }
+
+    /// Share a file through the held message at `log_index`.
+    ///
+    /// Returns `true` if the message is held.
+    pub fn set_attachment(&mut self, log_index: u64, attachment: Attachment) -> bool {
+        let Some(message) = self.messages.iter_mut().find(|m| m.log_index == Some(log_index))
+        else {
+            return false;
+        };
+        message.attachment = Some(attachment);
+        true
     }
 
-    /// Add a message to this room.
-    pub fn add_message(&mut self, sender_id: u64, content: Vec<u8>) {
-        self.messages.push(Message { sender_id, content });
+    fn authored_message_mut(&mut self, sender_id: u64, log_index: u64) -> Option<&mut Message> {
+        self.messages
+            .iter_mut()
+            .find(|m| m.log_index == Some(log_index) && m.sender_id == sender_id && !m.deleted)
     }
 }
 
+/// Order of the room list. Pinned rooms always come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoomOrder {
+    /// Named rooms by name ignoring case, then unnamed rooms by ID.
+    #[default]
+    Alphabetical,
+    /// Most recent message or join first.
+    Recent,
+}
+
+/// Room directory search being browsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct Directory {
+    /// Text room names must contain.
+    pub query: String,
+    /// Listed rooms found so far, in room ID order.
+    pub rooms: Vec<DirectoryEntry>,
+    /// Cursor for the next page. `None` once every match is shown.
+    pub next: Option<RoomId>,
+}
+
+/// What counts as a mention of the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mentions {
+    /// Words that mention the user, matched as whole words ignoring case.
+    pub keywords: Vec<String>,
+    /// Whether `@<sender id>` mentions the user.
+    pub user_id: bool,
+}
+
+impl Default for Mentions {
+    fn default() -> Self {
+        Self { keywords: Vec::new(), user_id: true }
+    }
+}
+
+impl Mentions {
+    /// Whether `content` mentions the user with sender ID `sender_id`.
+    pub fn matches(&self, sender_id: Option<u64>, content: &str) -> bool {
+        let handle = sender_id.filter(|_| self.user_id).map(|id| format!("@{id}"));
+        content
+            .split(|c: char| !(c.is_alphanumeric() || c == '@' || c == '_' || c == '-'))
+            .filter(|word| !word.is_empty())
+            .any(|word| {
+                handle.as_deref() == Some(word)
+                    || self
+                        .keywords
+                        .iter()
+                        .any(|keyword| keyword.eq_ignore_ascii_case(word.trim_start_matches('@')))
+            })
+    }
+}
+
+/// Something that deserves the user's attention, for the driver's
+/// [`crate::Notifier`] to surface as an OS notification, a bell, or not at
+/// all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum Notification {
+    /// Message from another member in a room not on screen, or one that
+    /// mentions the user.
+    Message {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// ID of the sender.
+        sender_id: u64,
+        /// Server-assigned log index of the message.
+        log_index: Option<u64>,
+        /// Start of the message content.
+        preview: String,
+        /// Message mentions the user.
+        mention: bool,
+    },
+    /// Another member added the user to a room.
+    Invite {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+    },
+    /// Something went wrong in a room.
+    RoomError {
+        /// 128-bit room UUID.
+        room_id: RoomId,
+        /// What went wrong.
+        message: String,
+    },
+}
+
+impl Notification {
+    /// Room the notification is about.
+    pub fn room_id(&self) -> RoomId {
+        match self {
+            Self::Message { room_id, .. }
+            | Self::Invite { room_id }
+            | Self::RoomError { room_id, .. } => *room_id,
+        }
+    }
+
+    /// Whether the user should look now: a mention, invite or error rather
+    /// than an ordinary unread message.
+    pub fn is_urgent(&self) -> bool {
+        !matches!(self, Self::Message { mention: false, .. })
+    }
+}
+
+/// An invite to a room waiting for the user to accept or decline it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingInvite {
+    /// 128-bit room UUID.
+    pub room_id: RoomId,
+    /// User who sent the invite.
+    pub inviter: u64,
+    /// Unix time in seconds the invite expires.
+    pub expires_at: u64,
+}
+
+/// How serious a [`Notice`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    /// Worth knowing, nothing failed.
+    Info,
+    /// Something is degraded but will be retried.
+    Warning,
+    /// Something failed and will not be retried.
+    Error,
+}
+
+/// A failure or notice kept for the user until dismissed, for frontends to
+/// show as a toast rather than in the message pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct Notice {
+    /// Identifies the notice for dismissal.
+    pub id: u64,
+    /// How serious it is.
+    pub severity: Severity,
+    /// Account it happened on.
+    pub account: AccountId,
+    /// Room it is about, if any.
+    pub room_id: Option<RoomId>,
+    /// What happened.
+    pub message: String,
+    /// Times it was raised in a row.
+    pub count: u32,
+    /// What the server's operator sent it about. `None` for notices the
+    /// client raised itself.
+    pub server: Option<NoticeKind>,
+}
+
 /// A message in a room.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
     /// ID of the sender.
     pub sender_id: u64,
+    /// Server-assigned log index. `None` for our own messages until the
+    /// server acknowledges them.
+    pub log_index: Option<u64>,
     /// Message content bytes.
     pub content: Vec<u8>,
+    /// Content was replaced by the sender.
+    pub edited: bool,
+    /// Message was retracted by the sender. Content is empty.
+    pub deleted: bool,
+    /// Message mentions the user.
+    pub mentions_me: bool,
+    /// How far the message has got. Always [`Delivery::Delivered`] for
+    /// messages from others.
+    pub delivery: Delivery,
+    /// Bridge-assigned ID of our own messages sent from this session, used
+    /// to match delivery updates.
+    pub local_id: Option<u64>,
+    /// When the message was sent (Unix milliseconds). 0 if unknown.
+    pub timestamp: u64,
+    /// Members who reacted, by reaction content (e.g. an emoji). Empty
+    /// sets are removed, so each entry counts at least one member.
+    pub reactions: BTreeMap<String, BTreeSet<u64>>,
+    /// Message this one replies to, if it is a reply.
+    pub reply_to: Option<ReplyTo>,
+    /// File the message shares, if it is an attachment. Its content is then
+    /// the file name.
+    pub attachment: Option<Attachment>,
+}
+
+/// A member adding or removing a reaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReactionChange {
+    /// ID of the member reacting.
+    pub sender_id: u64,
+    /// Reaction content (e.g. an emoji).
+    pub content: String,
+    /// True to add the reaction, false to remove it.
+    pub add: bool,
+}
+
+/// A room member as the group's ratchet tree records them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct Member {
+    /// User ID.
+    pub user_id: u64,
+    /// Kind of credential the member joined with.
+    pub credential: CredentialKind,
+    /// Ed25519 signature key of the member's client, if known.
+    pub signature_key: Option<[u8; 32]>,
+}
+
+impl Member {
+    /// Code for checking the member's signature key out of band: 30 digits
+    /// in groups of five, the same on every client that sees the same key.
+    pub fn safety_code(&self) -> Option<String> {
+        let key = self.signature_key?;
+        let groups: Vec<String> = key
+            .chunks_exact(5)
+            .map(|chunk| {
+                let n = chunk.iter().fold(0u64, |n, &b| n << 8 | u64::from(b));
+                format!("{:05}", n % 100_000)
+            })
+            .collect();
+        Some(groups.join(" "))
+    }
+}
+
+/// Kind of credential a member joined with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum CredentialKind {
+    /// Bare user ID, trusted as asserted.
+    Basic,
+    /// X.509 certificate chain.
+    X509 {
+        /// Certificates in the chain.
+        certificates: usize,
+    },
+    /// Application-defined credential.
+    Custom {
+        /// Identifies the credential format.
+        credential_type: String,
+    },
+}
+
+impl From<&Credential> for CredentialKind {
+    fn from(credential: &Credential) -> Self {
+        match credential {
+            Credential::BasicId(_) => Self::Basic,
+            Credential::X509 { chain, .. } => Self::X509 { certificates: chain.len() },
+            Credential::Custom { credential_type, .. } => {
+                Self::Custom { credential_type: credential_type.clone() }
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for CredentialKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Basic => write!(f, "basic"),
+            Self::X509 { certificates } => write!(f, "X.509, {certificates} certificates"),
+            Self::Custom { credential_type } => write!(f, "{credential_type}"),
+        }
+    }
+}
+
+/// Progress restoring a session after the connection dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum RestoreStep {
+    /// Reconnected, waiting for the server to accept the new session.
+    Authenticating,
+    /// Fetching what joined rooms missed while offline.
+    Syncing {
+        /// Rooms that have not answered yet.
+        remaining: usize,
+    },
+    /// Sending messages queued while offline.
+    Replaying {
+        /// Messages queued.
+        messages: usize,
+    },
+    /// Session restored.
+    Restored,
+}
+
+/// Delivery state of one of our own messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum Delivery {
+    /// Held back by the client's send pacer.
+    Pending,
+    /// Sent to the server, not yet acknowledged.
+    Sent,
+    /// Sequenced by the server and relayed to the room.
+    Delivered,
+    /// Not acknowledged in time. Other members may not have it.
+    Failed,
 }
 
 impl Message {
@@ -66,4 +814,30 @@ impl Message {
     pub fn content_str(&self) -> std::borrow::Cow<'_, str> {
         String::from_utf8_lossy(&self.content)
     }
+
+    /// Reactions with the number of members who reacted with each, in
+    /// content order.
+    pub fn reaction_counts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.reactions.iter().map(|(content, senders)| (content.as_str(), senders.len()))
+    }
+
+    /// Apply a reaction change. Deleted messages take no reactions.
+    ///
+    /// Returns `true` if the reactions changed.
+    fn react(&mut self, change: ReactionChange) -> bool {
+        if self.deleted {
+            return false;
+        }
+        if change.add {
+            return self.reactions.entry(change.content).or_default().insert(change.sender_id);
+        }
+        let Some(senders) = self.reactions.get_mut(&change.content) else {
+            return false;
+        };
+        let removed = senders.remove(&change.sender_id);
+        if senders.is_empty() {
+            self.reactions.remove(&change.content);
+        }
+        removed
+    }
 }
@@ -0,0 +1,174 @@
+//! Timers for the runtime.
+//!
+//! The [`crate::Runtime`] keeps actions to run later: once at a point on the
+//! driver's clock, once after a delay, or every period. Timers are checked
+//! against [`crate::Driver::now`] once per event loop cycle, so they never fire
+//! early, and in simulation they follow virtual time.
+//!
+//! Instants only support subtraction, so a timer is kept as the instant it
+//! counts from plus the delay after it, and compared by elapsed time.
+
+use std::{ops::Sub, time::Duration};
+
+use crate::AppAction;
+
+/// Shortest period of a repeating timer.
+const MIN_PERIOD: Duration = Duration::from_millis(1);
+
+/// Handle for cancelling a scheduled timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+#[derive(Debug)]
+struct Timer<I> {
+    id: TimerId,
+    /// Instant the delay counts from
+    from: I,
+    /// Delay after `from` until the timer is due. Grows by the period each
+    /// time a repeating timer fires.
+    after: Duration,
+    /// Period of a repeating timer
+    every: Option<Duration>,
+    action: AppAction,
+}
+
+impl<I: Copy + Ord + Sub<Output = Duration>> Timer<I> {
+    /// Time left until due at `now`, or how late the timer is.
+    fn remaining(&self, now: I) -> Result<Duration, Duration> {
+        if now < self.from {
+            return Ok(self.after.saturating_add(self.from - now));
+        }
+        let elapsed = now - self.from;
+        match self.after.checked_sub(elapsed) {
+            Some(left) if !left.is_zero() => Ok(left),
+            _ => Err(elapsed.saturating_sub(self.after)),
+        }
+    }
+}
+
+/// Pending timers, in scheduling order.
+#[derive(Debug)]
+pub(crate) struct Timers<I> {
+    timers: Vec<Timer<I>>,
+    next_id: u64,
+}
+
+impl<I> Default for Timers<I> {
+    fn default() -> Self {
+        Self { timers: Vec::new(), next_id: 0 }
+    }
+}
+
+impl<I: Copy + Ord + Sub<Output = Duration>> Timers<I> {
+    /// Run `action` once `after` has passed since `from`, and then every
+    /// `every` if given.
+    pub(crate) fn schedule(
+        &mut self,
+        from: I,
+        after: Duration,
+        every: Option<Duration>,
+        action: AppAction,
+    ) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        let every = every.map(|every| every.max(MIN_PERIOD));
+        self.timers.push(Timer { id, from, after, every, action });
+        id
+    }
+
+    /// Cancel a timer. Returns `false` if it already fired or was cancelled.
+    pub(crate) fn cancel(&mut self, id: TimerId) -> bool {
+        let before = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        self.timers.len() != before
+    }
+
+    /// Actions of the timers due at `now`, in the order they fell due.
+    ///
+    /// One-shot timers are removed. Repeating timers fire once however many
+    /// periods were missed, and are re-armed for their next period.
+    pub(crate) fn take_due(&mut self, now: I) -> Vec<AppAction> {
+        let mut due = Vec::new();
+        let mut index = 0;
+        while index < self.timers.len() {
+            let Err(late) = self.timers[index].remaining(now) else {
+                index += 1;
+                continue;
+            };
+            let timer = &mut self.timers[index];
+            if let Some(every) = timer.every {
+                let skipped = late.as_nanos() / every.as_nanos();
+                let periods = u32::try_from(skipped.saturating_add(1)).unwrap_or(u32::MAX);
+                timer.after = timer.after.saturating_add(every.saturating_mul(periods));
+                due.push((late, timer.id, timer.action.clone()));
+                index += 1;
+            } else {
+                let timer = self.timers.remove(index);
+                due.push((late, timer.id, timer.action));
+            }
+        }
+
+        due.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.0.cmp(&b.1.0)));
+        due.into_iter().map(|(_, _, action)| action).collect()
+    }
+
+    /// Time from `now` until the next timer is due.
+    pub(crate) fn next_due_in(&self, now: I) -> Option<Duration> {
+        self.timers.iter().map(|timer| timer.remaining(now).unwrap_or(Duration::ZERO)).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::disallowed_methods)]
+
+    use std::time::Instant;
+
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn one_shot_timers_fire_once_in_due_order() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        timers.schedule(start, secs(5), None, AppAction::Quit);
+        timers.schedule(start, secs(2), None, AppAction::Render);
+        let cancelled = timers.schedule(start, secs(1), None, AppAction::PublishKeyPackage);
+        assert!(timers.cancel(cancelled));
+        assert!(!timers.cancel(cancelled));
+
+        assert!(timers.take_due(start + secs(1)).is_empty());
+        assert_eq!(timers.next_due_in(start + secs(1)), Some(secs(1)));
+        assert_eq!(timers.take_due(start + secs(6)), vec![AppAction::Render, AppAction::Quit]);
+        assert!(timers.take_due(start + secs(10)).is_empty());
+        assert_eq!(timers.next_due_in(start + secs(10)), None);
+    }
+
+    #[test]
+    fn future_start_counts_from_that_instant() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        timers.schedule(start + secs(3), Duration::ZERO, None, AppAction::Render);
+
+        assert_eq!(timers.next_due_in(start), Some(secs(3)));
+        assert!(timers.take_due(start + secs(2)).is_empty());
+        assert_eq!(timers.take_due(start + secs(3)), vec![AppAction::Render]);
+    }
+
+    #[test]
+    fn repeating_timers_rearm_and_skip_missed_periods() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        timers.schedule(start, secs(2), Some(secs(2)), AppAction::Render);
+
+        assert_eq!(timers.take_due(start + secs(2)), vec![AppAction::Render]);
+        assert!(timers.take_due(start + secs(3)).is_empty());
+
+        // Stalled through three periods: fires once, next due at 10s
+        assert_eq!(timers.take_due(start + secs(9)), vec![AppAction::Render]);
+        assert_eq!(timers.next_due_in(start + secs(9)), Some(secs(1)));
+    }
+}
@@ -7,10 +7,16 @@
 //! - Messages are delivered to the correct rooms
 //! - Member lists are consistent
 
-use lockframe_app::{App, AppAction, AppEvent, Bridge};
+use lockframe_app::{App, AppAction, AppEvent, Bridge, RestoreStep};
 use lockframe_core::env::Environment;
 use lockframe_harness::SimEnv;
-use lockframe_proto::{Frame, FrameHeader, Opcode, Payload, payloads::mls::GroupInfoPayload};
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode, Payload,
+    payloads::{
+        mls::GroupInfoPayload,
+        session::{HelloReply, SyncResponse},
+    },
+};
 
 /// Create a connected App ready for testing.
 fn connected_app(sender_id: u64) -> App {
@@ -31,14 +37,33 @@ fn process_actions<E: Environment>(
             | AppAction::JoinRoom { .. }
             | AppAction::LeaveRoom { .. }
             | AppAction::SendMessage { .. }
+            | AppAction::EditMessage { .. }
+            | AppAction::DeleteMessage { .. }
             | AppAction::PublishKeyPackage
-            | AppAction::AddMember { .. } => {
+            | AppAction::AddMember { .. }
+            | AppAction::RemoveMember { .. }
+            | AppAction::InviteUser { .. }
+            | AppAction::SearchDirectory { .. }
+            | AppAction::SetRoomListing { .. }
+            | AppAction::LoadHistory { .. }
+            | AppAction::SendTyping { .. }
+            | AppAction::SaveDraft { .. } => {
                 let events = bridge.process_app_action(action);
                 for event in events {
                     app.handle(event);
                 }
             },
-            AppAction::Render | AppAction::Quit | AppAction::Connect { .. } => {},
+            AppAction::Render
+            | AppAction::Quit
+            | AppAction::Notify(_)
+            | AppAction::Schedule { .. }
+            | AppAction::Connect { .. } => {},
+            AppAction::PinRoom { room_id, pinned } => {
+                app.pin_room(room_id, pinned);
+            },
+            AppAction::SetRoomOrder { order } => {
+                app.set_room_order(order);
+            },
         }
     }
 
@@ -224,6 +249,62 @@ fn leave_removes_room() {
     assert_eq!(app.active_room(), None, "No active room");
 }
 
+#[test]
+fn leaving_room_waits_for_the_commit_removing_us() {
+    let env = SimEnv::with_seed(42);
+    let alice_id = 1;
+    let mut alice_app = connected_app(alice_id);
+    let mut alice_bridge: Bridge<SimEnv> = Bridge::new(env.clone(), alice_id);
+    let alice_frames = create_room(&mut alice_app, &mut alice_bridge, 100);
+    let group_info = extract_group_info(&frames_by_opcode(&alice_frames, Opcode::GroupInfo)[0])
+        .expect("Extract GroupInfo");
+
+    let bob_id = 2;
+    let mut bob_app = connected_app(bob_id);
+    let mut bob_bridge: Bridge<SimEnv> = Bridge::new(env, bob_id);
+    join_room(&mut bob_app, &mut bob_bridge, 100);
+    let group_info_frame =
+        Payload::GroupInfo(group_info).into_frame(FrameHeader::new(Opcode::GroupInfo)).unwrap();
+    receive_frame(&mut bob_app, &mut bob_bridge, group_info_frame);
+    let ext_commit = frames_by_opcode(&bob_bridge.take_outgoing(), Opcode::ExternalCommit);
+    receive_frame(&mut alice_app, &mut alice_bridge, ext_commit[0].clone());
+    receive_frame(&mut bob_app, &mut bob_bridge, ext_commit[0].clone());
+
+    // Bob stays in the room, marked as leaving, until Alice commits
+    let frames = leave_room(&mut bob_app, &mut bob_bridge, 100);
+    assert_eq!(frames_by_opcode(&frames, Opcode::Proposal).len(), 1);
+    assert!(bob_app.rooms().get(&100).is_some_and(|room| room.leaving));
+
+    // The connection drops before the proposal is known to arrive, so it
+    // is proposed again once the session is restored
+    for event in bob_bridge.connection_lost() {
+        bob_app.handle(event);
+    }
+    bob_bridge.begin_session(None);
+    let reply = HelloReply { session_id: 2, capabilities: Vec::new(), challenge: None };
+    let reply =
+        Payload::HelloReply(reply).into_frame(FrameHeader::new(Opcode::HelloReply)).unwrap();
+    receive_frame(&mut bob_app, &mut bob_bridge, reply);
+    let response =
+        SyncResponse { frames: Vec::new(), has_more: false, server_epoch: 0, next_log_index: None };
+    let mut response =
+        Payload::SyncResponse(response).into_frame(FrameHeader::new(Opcode::SyncResponse)).unwrap();
+    response.header.set_room_id(100);
+    receive_frame(&mut bob_app, &mut bob_bridge, response);
+    let proposals = frames_by_opcode(&bob_bridge.take_outgoing(), Opcode::Proposal);
+    assert_eq!(proposals.len(), 1, "Bob should propose leaving again");
+    assert!(bob_app.rooms().contains_key(&100));
+
+    receive_frame(&mut alice_app, &mut alice_bridge, proposals[0].clone());
+    let commit = frames_by_opcode(&alice_bridge.take_outgoing(), Opcode::Commit);
+    assert_eq!(commit.len(), 1, "Alice should commit Bob's removal");
+    receive_frame(&mut bob_app, &mut bob_bridge, commit[0].clone());
+
+    // Oracle: the room is gone once the commit removing Bob arrives
+    assert!(!bob_app.rooms().contains_key(&100));
+    assert_eq!(bob_app.active_room(), None);
+}
+
 #[test]
 fn external_commit_broadcast_back_to_joiner() {
     let env = SimEnv::with_seed(42);
@@ -558,3 +639,54 @@ fn add_member_command_generates_frames() {
     let kp_fetch_frames = frames_by_opcode(&frames, Opcode::KeyPackageFetch);
     assert_eq!(kp_fetch_frames.len(), 1, "Should fetch key package for user 2");
 }
+
+#[test]
+fn session_restore_resyncs_before_replaying_outbox() {
+    let env = SimEnv::with_seed(42);
+    let alice_id = 1;
+    let mut alice_app = connected_app(alice_id);
+    let mut alice_bridge: Bridge<SimEnv> = Bridge::new(env, alice_id);
+    create_room(&mut alice_app, &mut alice_bridge, 100);
+
+    // Offline: messages wait in the outbox
+    for event in alice_bridge.connection_lost() {
+        alice_app.handle(event);
+    }
+    assert!(send_message(&mut alice_app, &mut alice_bridge, 100, "while away").is_empty());
+
+    // Reconnect: only the Hello goes out
+    let events = alice_bridge.begin_session(None);
+    assert!(matches!(events[..], [AppEvent::SessionRestore { step: RestoreStep::Authenticating }]));
+    let frames = alice_bridge.take_outgoing();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].header.opcode_enum(), Some(Opcode::Hello));
+
+    // Accepted: the joined room is re-synced, the message still held
+    let reply = HelloReply { session_id: 2, capabilities: Vec::new(), challenge: None };
+    let reply =
+        Payload::HelloReply(reply).into_frame(FrameHeader::new(Opcode::HelloReply)).unwrap();
+    let events = alice_bridge.handle_frame(reply);
+    assert!(events.iter().any(|e| matches!(e, AppEvent::SessionRestore { step } if *step == RestoreStep::Syncing { remaining: 1 })));
+    let frames = alice_bridge.take_outgoing();
+    let sync_requests = frames_by_opcode(&frames, Opcode::SyncRequest);
+    assert_eq!(sync_requests.len(), 1);
+    assert_eq!(sync_requests[0].header.room_id(), 100);
+    assert!(frames_by_opcode(&frames, Opcode::AppMessage).is_empty());
+
+    // Room answered: the outbox is released
+    let response =
+        SyncResponse { frames: Vec::new(), has_more: false, server_epoch: 0, next_log_index: None };
+    let mut response =
+        Payload::SyncResponse(response).into_frame(FrameHeader::new(Opcode::SyncResponse)).unwrap();
+    response.header.set_room_id(100);
+    let events = alice_bridge.handle_frame(response);
+    assert!(
+        events
+            .iter()
+            .any(|e| matches!(e, AppEvent::SessionRestore { step: RestoreStep::Restored }))
+    );
+    for event in events {
+        alice_app.handle(event);
+    }
+    assert_eq!(alice_app.status_message(), Some("Session restored"));
+}
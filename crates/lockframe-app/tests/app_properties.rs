@@ -49,14 +49,33 @@ fn process_actions(app: &mut App, bridge: &mut Bridge<SimEnv>, actions: Vec<AppA
             | AppAction::JoinRoom { .. }
             | AppAction::LeaveRoom { .. }
             | AppAction::SendMessage { .. }
+            | AppAction::EditMessage { .. }
+            | AppAction::DeleteMessage { .. }
             | AppAction::PublishKeyPackage
-            | AppAction::AddMember { .. } => {
+            | AppAction::AddMember { .. }
+            | AppAction::RemoveMember { .. }
+            | AppAction::InviteUser { .. }
+            | AppAction::SearchDirectory { .. }
+            | AppAction::SetRoomListing { .. }
+            | AppAction::LoadHistory { .. }
+            | AppAction::SendTyping { .. }
+            | AppAction::SaveDraft { .. } => {
                 let events = bridge.process_app_action(action);
                 for event in events {
                     app.handle(event);
                 }
             },
-            AppAction::Render | AppAction::Quit | AppAction::Connect { .. } => {},
+            AppAction::Render
+            | AppAction::Quit
+            | AppAction::Notify(_)
+            | AppAction::Schedule { .. }
+            | AppAction::Connect { .. } => {},
+            AppAction::PinRoom { room_id, pinned } => {
+                app.pin_room(room_id, pinned);
+            },
+            AppAction::SetRoomOrder { order } => {
+                app.set_room_order(order);
+            },
         }
     }
 }
@@ -0,0 +1,41 @@
+//! Runtime driven end to end by a [`ScriptDriver`], without a terminal or
+//! network.
+
+use std::time::Duration;
+
+use lockframe_app::{AccountId, Intent, Runtime, Script, ScriptDriver, Step};
+use lockframe_harness::SimEnv;
+use lockframe_proto::{
+    FrameHeader, Opcode, Payload,
+    payloads::session::{DirectoryEntry, DirectoryResults},
+};
+
+#[tokio::test(start_paused = true)]
+async fn script_plays_steps_on_schedule() {
+    let env = SimEnv::with_seed(7);
+    let entry = DirectoryEntry { room_id: 5, name: "rust".into(), member_count: 3 };
+    let results = Payload::DirectoryResults(DirectoryResults { rooms: vec![entry], next: None })
+        .into_frame(FrameHeader::new(Opcode::DirectoryResults))
+        .unwrap();
+    let script = Script::new()
+        .at(Duration::from_secs(1), Step::Intent(Intent::SearchRooms { query: "ru".into() }))
+        .at(Duration::from_secs(2), Step::Frame { account: AccountId::default(), frame: results })
+        .at(Duration::from_secs(3), Step::Wait);
+
+    let driver = ScriptDriver::new(env.clone(), script);
+    let recording = driver.recording();
+    let app = Runtime::new(driver, env, 1, "localhost:4433".into()).run().await.unwrap();
+
+    let sent: Vec<_> =
+        recording.take_sent().iter().filter_map(|(_, frame)| frame.header.opcode_enum()).collect();
+    assert_eq!(sent, [Opcode::Hello, Opcode::DirectorySearch]);
+
+    // Results show up once their frame is due, not before
+    let snapshots = recording.snapshots();
+    let first_found = snapshots
+        .iter()
+        .find(|snapshot| snapshot.app.directory().is_some_and(|d| !d.rooms.is_empty()))
+        .unwrap();
+    assert!(first_found.at >= Duration::from_secs(2));
+    assert_eq!(app.directory().unwrap().rooms[0].name, "rust");
+}
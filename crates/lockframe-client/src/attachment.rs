@@ -0,0 +1,51 @@
+//! Encrypted attachments.
+//!
+//! An attachment is announced in an ordinary application message whose body
+//! holds the file's metadata and the key it is sealed under (see
+//! [`Attachment`]). Sealing happens in [`crate::Client::seal_attachment`],
+//! which draws the key from the client's environment; opening needs nothing
+//! but the announcement, so frontends can do it on demand.
+
+use lockframe_crypto::{
+    AttachmentError, attachment_hash, open_attachment as open_sealed, seal_attachment,
+};
+use lockframe_proto::payloads::app::Attachment;
+
+/// Seal `data` as an attachment named `filename` under `key` and `nonce`.
+pub(crate) fn seal(filename: String, data: &[u8], key: [u8; 32], nonce: [u8; 24]) -> Attachment {
+    let ciphertext = seal_attachment(data, &key, &nonce);
+    Attachment {
+        filename,
+        size: data.len() as u64,
+        hash: attachment_hash(&ciphertext),
+        key,
+        nonce,
+        ciphertext,
+    }
+}
+
+/// Decrypt an attachment's content, checking it is the file announced.
+///
+/// # Errors
+///
+/// - `HashMismatch` if the ciphertext is not the announced one
+/// - `DecryptionFailed` if it does not decrypt under the announced key
+pub fn open_attachment(attachment: &Attachment) -> Result<Vec<u8>, AttachmentError> {
+    open_sealed(&attachment.ciphertext, &attachment.key, &attachment.nonce, &attachment.hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_attachments_open() {
+        let attachment = seal("cat.png".to_string(), b"meow", [1; 32], [2; 24]);
+        assert_eq!(attachment.size, 4);
+        assert_eq!(open_attachment(&attachment).unwrap(), b"meow");
+
+        let mut swapped = attachment;
+        swapped.ciphertext[0] ^= 1;
+        assert_eq!(open_attachment(&swapped), Err(AttachmentError::HashMismatch));
+    }
+}
@@ -0,0 +1,117 @@
+//! Paginated history backfill.
+//!
+//! Backfill fetches a room's log from the start up to a target index in
+//! batches of [`BACKFILL_BATCH`] frames, one `SyncRequest` at a time. Each
+//! response is decrypted as it arrives, so memory stays bounded by the batch
+//! size and progress can be reported between batches.
+
+use lockframe_proto::payloads::session::SyncRequest;
+
+/// Frames requested per batch.
+pub const BACKFILL_BATCH: u64 = 100;
+
+/// Progress of one room's backfill.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backfill {
+    /// First log index not yet fetched.
+    next_log_index: u64,
+    /// Backfill stops before this log index.
+    until_log_index: u64,
+    /// Frames fetched so far.
+    fetched: u64,
+    /// Server ran out of history or the target was reached.
+    complete: bool,
+}
+
+impl Backfill {
+    /// Start a backfill of log indices `0..until_log_index`.
+    pub fn new(until_log_index: u64) -> Self {
+        Self { next_log_index: 0, until_log_index, fetched: 0, complete: until_log_index == 0 }
+    }
+
+    /// Request for the next batch, `None` once complete.
+    pub fn next_request(&self) -> Option<SyncRequest> {
+        if self.complete {
+            return None;
+        }
+
+        let remaining = self.until_log_index.saturating_sub(self.next_log_index);
+        Some(SyncRequest::new(self.next_log_index, remaining.min(BACKFILL_BATCH)))
+    }
+
+    /// Account for a received batch, given the log indices of its frames.
+    pub fn record_batch(&mut self, log_indices: impl IntoIterator<Item = u64>, has_more: bool) {
+        let before = self.next_log_index;
+
+        for log_index in log_indices {
+            if log_index >= self.next_log_index && log_index < self.until_log_index {
+                self.fetched = self.fetched.saturating_add(1);
+                self.next_log_index = log_index.saturating_add(1);
+            }
+        }
+
+        // An empty batch means the server has nothing more in range, whatever
+        // `has_more` claims.
+        self.complete = !has_more
+            || self.next_log_index == before
+            || self.next_log_index >= self.until_log_index;
+    }
+
+    /// Frames fetched so far.
+    pub fn fetched(&self) -> u64 {
+        self.fetched
+    }
+
+    /// Frames expected in total. Once complete this is the number actually
+    /// fetched, which is lower than requested if the room's history is
+    /// shorter.
+    pub fn total(&self) -> u64 {
+        if self.complete { self.fetched } else { self.until_log_index }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_paginated_up_to_target() {
+        let mut backfill = Backfill::new(250);
+        assert_eq!(backfill.next_request(), Some(SyncRequest::new(0, 100)));
+
+        backfill.record_batch(0..100, true);
+        backfill.record_batch(100..200, true);
+        assert_eq!(backfill.next_request(), Some(SyncRequest::new(200, 50)));
+        assert_eq!((backfill.fetched(), backfill.total()), (200, 250));
+
+        backfill.record_batch(200..250, true);
+        assert_eq!(backfill.next_request(), None);
+        assert_eq!((backfill.fetched(), backfill.total()), (250, 250));
+    }
+
+    #[test]
+    fn short_history_completes_with_actual_total() {
+        let mut backfill = Backfill::new(500);
+        backfill.record_batch(0..30, false);
+
+        assert_eq!(backfill.next_request(), None);
+        assert_eq!((backfill.fetched(), backfill.total()), (30, 30));
+    }
+
+    #[test]
+    fn empty_batch_completes() {
+        let mut backfill = Backfill::new(500);
+        backfill.record_batch(std::iter::empty(), true);
+
+        assert_eq!(backfill.next_request(), None);
+    }
+
+    #[test]
+    fn frames_outside_range_are_not_counted() {
+        let mut backfill = Backfill::new(10);
+        backfill.record_batch(5..20, true);
+
+        assert_eq!(backfill.fetched(), 5);
+        assert_eq!(backfill.next_request(), None);
+    }
+}
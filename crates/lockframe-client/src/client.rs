@@ -4,65 +4,133 @@
 //! memberships and orchestrates MLS operations with sender key encryption.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    mem,
+    ops::Range,
     slice,
+    sync::Arc,
     time::Duration,
 };
 
+use ed25519_dalek::{Signature, VerifyingKey};
 use lockframe_core::{
     env::Environment,
-    mls::{MlsAction, MlsGroup, PendingJoinState, RoomId},
+    mls::{
+        AcceptAllCredentials, Credential, CredentialVerifier, MlsAction, MlsGroup,
+        PendingJoinState, RoomId,
+    },
+};
+use lockframe_crypto::{
+    ATTACHMENT_KEY_SIZE, ATTACHMENT_NONCE_SIZE, BACKUP_NONCE_SIZE, BACKUP_SALT_SIZE, BackupParams,
+    EncryptedMessage as CryptoEncryptedMessage, NONCE_RANDOM_SIZE, labels, open_backup,
+    seal_backup,
 };
-use lockframe_crypto::{EncryptedMessage as CryptoEncryptedMessage, NONCE_RANDOM_SIZE};
 use lockframe_proto::{
-    Frame, FrameHeader, Opcode, Payload,
+    Frame, FrameFlags, FrameHeader, Opcode, Payload, ProtocolError,
     payloads::{
-        app::EncryptedMessage,
-        mls::{GroupInfoPayload, KeyPackageFetchPayload, KeyPackagePublishRequest},
-        session::SyncResponse,
+        app::{AppMessageBody, Attachment, EncryptedMessage, Receipt, ReceiptType},
+        mls::{
+            GroupInfoPayload, KeyPackageFetchPayload, KeyPackageLowStockPayload,
+            KeyPackagePublishRequest,
+        },
+        moderation::{CloseRoom, ReportMessage},
+        session::{
+            DirectoryPublish, DirectoryResults, DirectorySearch, HistoryTruncated, Invite,
+            LaggedBehind, PresenceStatus, ServerNotice, SyncRequest, SyncResponse,
+        },
     },
 };
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
 
 use crate::{
+    attachment,
+    backfill::{BACKFILL_BATCH, Backfill},
+    clock_skew::SkewEstimator,
+    disappearing::Disappearing,
     error::ClientError,
     event::{ClientAction, ClientEvent, RoomStateSnapshot},
-    sender_key_store::SenderKeyStore,
+    gaps::{self, GapDetector},
+    pacer::{Admission, Pacer, PacerConfig, QueueFull},
+    read_markers::ReadMarkers,
+    replay_window::{ReplayCheck, ReplayWindow},
+    roster::{MemberInfo, Roster},
+    sender_key_store::{SenderKeySnapshot, SenderKeyStore},
+    storage::{ClientStorage, ClientStorageError},
+    transcript::{Transcript, TranscriptDocument},
 };
 
-/// Label for MLS secret export (domain separation).
-const SENDER_KEY_LABEL: &str = "lockframe sender keys v1";
-
-/// Context for MLS secret export.
-const SENDER_KEY_CONTEXT: &[u8] = b"";
-
 /// Size of the sender key secret in bytes.
 const SENDER_KEY_SECRET_SIZE: usize = 32;
 
 /// Timeout for pending commits before requesting sync (30 seconds).
 const COMMIT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// How long a leave proposal waits for another member to commit it before it
+/// is proposed again (1 minute).
+const LEAVE_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// Most messages held in a restored room until its self-update commit lands.
+const MAX_HELD_FOR_REKEY: usize = 64;
+
 /// Timeout for pending `KeyPackage` fetch operations (1 minute).
 const KEY_PACKAGE_FETCH_TIMEOUT: Duration = Duration::from_mins(1);
 
+/// Most `KeyPackages` published in response to one low-stock notice.
+const MAX_KEY_PACKAGE_REPLENISH: u32 = 32;
+
+/// Rooms asked for per room directory page.
+const DIRECTORY_PAGE: u32 = 20;
+
+/// Lifetime of a typing notification. Receivers drop older ones, so a stale
+/// one is never shown after a delayed delivery.
+const TYPING_TTL_SECS: u64 = 10;
+
 /// Client identity.
 ///
 /// Owns the persistent cryptographic material that identifies this client
 /// across all room memberships.
 ///
-/// Note: MLS credential and signer are owned by `MlsGroup` per-room.
-/// This may be refactored when we implement proper identity management.
+/// Note: MLS signers are owned by `MlsGroup` per-room. The credential is
+/// presented in every group the client creates or joins.
 pub struct ClientIdentity {
     /// Stable sender ID used in frame headers.
     pub sender_id: u64,
+
+    /// Credential presented to other members.
+    pub credential: Credential,
 }
 
 impl ClientIdentity {
     /// Create a new client identity with the given sender ID.
     pub fn new(sender_id: u64) -> Self {
-        Self { sender_id }
+        Self { sender_id, credential: Credential::BasicId(sender_id) }
+    }
+
+    /// Create an identity presenting `credential`. The sender ID is the
+    /// credential's member ID.
+    pub fn with_credential(credential: Credential) -> Self {
+        Self { sender_id: credential.member_id(), credential }
     }
 }
 
+/// Client configuration.
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    /// Per-room send pacing. `None` sends every message immediately.
+    pub pacer: Option<PacerConfig>,
+
+    /// Keep decrypted history for [`Client::export_transcript`].
+    pub record_transcript: bool,
+
+    /// Rooms kept hydrated in memory when storage is attached. Least recently
+    /// used rooms beyond this are moved to storage. `None` keeps all rooms.
+    pub max_hydrated_rooms: Option<usize>,
+
+    /// Argon2 cost of deriving the key for [`Client::export_backup`].
+    pub backup_params: BackupParams,
+}
+
 /// Per-room state combining MLS group and sender keys.
 struct RoomState<E: Environment> {
     /// MLS group state machine.
@@ -73,6 +141,261 @@ struct RoomState<E: Environment> {
 
     /// Our leaf index in the MLS tree.
     my_leaf_index: u32,
+
+    /// Already-delivered application messages, for duplicate and replay
+    /// detection.
+    replay_window: ReplayWindow,
+
+    /// Outgoing message pacing, if enabled.
+    pacer: Option<Pacer<E::Instant>>,
+
+    /// Decrypted history, if recording is enabled.
+    transcript: Option<Transcript>,
+
+    /// Read position and unread messages.
+    read_markers: ReadMarkers,
+
+    /// Lifetime of sent messages and expiry of delivered ones.
+    disappearing: Disappearing,
+
+    /// Next log index expected, for spotting lost broadcasts.
+    gaps: GapDetector,
+
+    /// `request_id` for the next message we send. Zero until the first send
+    /// picks a random start, so echoes from before a restart rarely collide.
+    next_request_id: u32,
+
+    /// When we last proposed to leave, while waiting for the commit removing
+    /// us.
+    leaving_since: Option<E::Instant>,
+
+    /// Whether our sender keys came from a backup, and may repeat
+    /// generations the exporting device already used, until the epoch moves
+    /// on.
+    rekey_before_send: bool,
+
+    /// Messages sent while [`Self::rekey_before_send`] holds, sent once the
+    /// epoch moves on.
+    held_for_rekey: Vec<AppMessageBody>,
+
+    /// Members and the epochs they joined at.
+    roster: Roster,
+}
+
+impl<E: Environment> RoomState<E> {
+    fn new(
+        mls_group: MlsGroup<E>,
+        sender_keys: SenderKeyStore,
+        my_leaf_index: u32,
+        config: &ClientConfig,
+        now: E::Instant,
+    ) -> Self {
+        Self {
+            mls_group,
+            sender_keys,
+            my_leaf_index,
+            replay_window: ReplayWindow::new(),
+            pacer: config.pacer.map(|pacer| Pacer::new(pacer, now)),
+            transcript: config.record_transcript.then(Transcript::new),
+            read_markers: ReadMarkers::new(),
+            disappearing: Disappearing::new(),
+            gaps: GapDetector::new(),
+            next_request_id: 0,
+            leaving_since: None,
+            rekey_before_send: false,
+            held_for_rekey: Vec::new(),
+            roster: Roster::new(),
+        }
+    }
+
+    /// Whether the room can be moved to storage without losing in-flight
+    /// work.
+    fn is_idle(&self) -> bool {
+        !self.mls_group.has_pending_commit()
+            && self.leaving_since.is_none()
+            && self.held_for_rekey.is_empty()
+            && self.pacer.as_ref().is_none_or(Pacer::is_idle)
+    }
+
+    /// Propose removing ourselves, noting when so the proposal can be made
+    /// again if no commit covers it.
+    fn propose_leave(
+        &mut self,
+        room_id: RoomId,
+        now: E::Instant,
+    ) -> Result<Vec<MlsAction>, ClientError> {
+        let mls_actions = self.mls_group.leave_group().map_err(ClientError::mls(room_id))?;
+        self.leaving_since = Some(now);
+        Ok(mls_actions)
+    }
+
+    /// Serialize for storage.
+    fn dehydrate(&self) -> Result<Vec<u8>, ClientError> {
+        let mls_group = self.mls_group.export_snapshot()?;
+
+        let room = DehydratedRoom {
+            mls_group,
+            sender_keys: self.sender_keys.snapshot(),
+            my_leaf_index: self.my_leaf_index,
+            replay_window: &self.replay_window,
+            transcript: self.transcript.as_ref(),
+            read_markers: &self.read_markers,
+            disappearing: &self.disappearing,
+            roster: &self.roster,
+            rekey_before_send: self.rekey_before_send,
+        };
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&room, &mut buf)
+            .map_err(|e| ClientStorageError::Serialization(e.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Rebuild from [`Self::dehydrate`] output. Send pacing and gap detection
+    /// start fresh.
+    fn hydrate(
+        env: E,
+        bytes: &[u8],
+        config: &ClientConfig,
+        now: E::Instant,
+    ) -> Result<Self, ClientError> {
+        let room: DehydratedRoom<
+            ReplayWindow,
+            Option<Transcript>,
+            ReadMarkers,
+            Disappearing,
+            Roster,
+        > = ciborium::de::from_reader(bytes)
+            .map_err(|e| ClientStorageError::Serialization(e.to_string()))?;
+
+        let mls_group = MlsGroup::restore(env, &room.mls_group)?;
+
+        // Rooms stored before the roster count their members from here
+        let mut roster = room.roster;
+        roster.update(mls_group.member_leaves(), mls_group.epoch());
+
+        Ok(Self {
+            mls_group,
+            sender_keys: SenderKeyStore::from_snapshot(room.sender_keys),
+            my_leaf_index: room.my_leaf_index,
+            replay_window: room.replay_window,
+            pacer: config.pacer.map(|pacer| Pacer::new(pacer, now)),
+            transcript: room.transcript,
+            read_markers: room.read_markers,
+            disappearing: room.disappearing,
+            gaps: GapDetector::new(),
+            next_request_id: 0,
+            leaving_since: None,
+            rekey_before_send: room.rekey_before_send,
+            held_for_rekey: Vec::new(),
+            roster,
+        })
+    }
+
+    /// Bring the roster up to date with the tree, and the action reporting
+    /// it if membership changed.
+    fn refresh_members(&mut self, room_id: RoomId) -> Option<ClientAction> {
+        let changed = self.roster.update(self.mls_group.member_leaves(), self.mls_group.epoch());
+        changed.then(|| ClientAction::MembersChanged { room_id, members: self.roster.members() })
+    }
+
+    /// Record a decrypted message from another member and build the actions
+    /// delivering it. Content from a blocked sender is still recorded but
+    /// delivered as hidden.
+    fn deliver(
+        &mut self,
+        room_id: RoomId,
+        sequenced: Sequenced,
+        body: AppMessageBody,
+        blocked: bool,
+    ) -> Vec<ClientAction> {
+        let Sequenced { sender_id, log_index, timestamp, expires_at, .. } = sequenced;
+
+        if let Some(expires_at) = expires_at {
+            self.disappearing.track(expires_at, log_index);
+        }
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.record(log_index, sender_id, timestamp, &body);
+        }
+
+        // Only content the user reads counts; edits, reactions, receipts and
+        // typing refer to messages already counted.
+        let counts_as_unread = matches!(
+            body,
+            AppMessageBody::Text(_)
+                | AppMessageBody::Reply { .. }
+                | AppMessageBody::Attachment(_)
+                | AppMessageBody::Custom { .. }
+                | AppMessageBody::Unsupported
+        );
+
+        if blocked {
+            return if counts_as_unread {
+                vec![ClientAction::MessageHidden { room_id, sender_id, log_index, timestamp }]
+            } else {
+                vec![]
+            };
+        }
+
+        let mut actions = vec![body_to_action(room_id, sequenced, body)];
+        if counts_as_unread && self.read_markers.record_message(log_index) {
+            actions.push(self.unread_count_changed(room_id));
+        }
+        actions
+    }
+
+    fn unread_count_changed(&self, room_id: RoomId) -> ClientAction {
+        ClientAction::UnreadCountChanged {
+            room_id,
+            unread: self.read_markers.unread_count(),
+            read_up_to: self.read_markers.read_up_to(),
+        }
+    }
+}
+
+/// Sender and ordering metadata of a delivered message.
+#[derive(Debug, Clone, Copy)]
+struct Sequenced {
+    sender_id: u64,
+    log_index: u64,
+    /// When we received the message (Unix milliseconds).
+    timestamp: u64,
+    /// Skew-corrected send time, see [`SkewEstimator`].
+    display_timestamp: u64,
+    /// When the message disappears (Unix seconds), if it does.
+    expires_at: Option<u64>,
+}
+
+/// Stored form of a [`RoomState`]. Generic so the same layout serializes
+/// from borrowed state and deserializes into owned state.
+#[derive(Serialize, Deserialize)]
+struct DehydratedRoom<W, T, R, D, M> {
+    mls_group: Vec<u8>,
+    sender_keys: SenderKeySnapshot,
+    my_leaf_index: u32,
+    replay_window: W,
+    transcript: T,
+    read_markers: R,
+    /// Rooms stored before disappearing messages keep messages forever
+    #[serde(default)]
+    disappearing: D,
+    /// Rooms stored before the roster rebuild it on restore
+    #[serde(default)]
+    roster: M,
+    /// Rooms stored before backups rekeyed on restore never need to
+    #[serde(default)]
+    rekey_before_send: bool,
+}
+
+/// Plaintext of a [`Client::export_backup`] backup.
+#[derive(Serialize, Deserialize)]
+struct ClientBackup {
+    sender_id: u64,
+    /// Credential in its MLS identity encoding
+    credential: Vec<u8>,
+    /// Room ID and [`RoomState::dehydrate`] output, in room ID order
+    rooms: Vec<(RoomId, Vec<u8>)>,
+    blocked_users: Vec<u64>,
 }
 
 /// State stored between `KeyPackage` generation and Welcome receipt.
@@ -86,6 +409,9 @@ pub struct Client<E: Environment> {
     /// Client identity.
     identity: ClientIdentity,
 
+    /// Client configuration.
+    config: ClientConfig,
+
     /// Active room memberships.
     rooms: HashMap<RoomId, RoomState<E>>,
 
@@ -99,19 +425,85 @@ pub struct Client<E: Environment> {
 
     /// Pending external joins awaiting `GroupInfo` responses.
     pending_external_joins: HashSet<RoomId>,
+
+    /// Where idle rooms are dehydrated to, if attached.
+    storage: Option<Box<dyn ClientStorage>>,
+
+    /// Rooms held in storage rather than memory, with their stored epoch.
+    dormant: HashMap<RoomId, u64>,
+
+    /// Hydrated rooms, least recently used first.
+    lru: VecDeque<RoomId>,
+
+    /// Users whose content is hidden in every room.
+    blocked_users: BTreeSet<u64>,
+
+    /// Sender clock skew, for display timestamps.
+    skew: SkewEstimator,
+
+    /// History backfills in progress.
+    backfills: HashMap<RoomId, Backfill>,
+
+    /// Decides whether other members' credentials are acceptable.
+    credential_verifier: Arc<dyn CredentialVerifier>,
 }
 
 impl<E: Environment> Client<E> {
     /// Create a new client with the given identity.
     pub fn new(env: E, identity: ClientIdentity) -> Self {
+        Self::with_config(env, identity, ClientConfig::default())
+    }
+
+    /// Create a new client with the given identity and configuration.
+    pub fn with_config(env: E, identity: ClientIdentity, config: ClientConfig) -> Self {
         Self {
             env,
             identity,
+            config,
             rooms: HashMap::new(),
             pending_joins: HashMap::new(),
             pending_adds: HashMap::new(),
             pending_external_joins: HashSet::new(),
+            storage: None,
+            dormant: HashMap::new(),
+            lru: VecDeque::new(),
+            blocked_users: BTreeSet::new(),
+            skew: SkewEstimator::new(),
+            backfills: HashMap::new(),
+            credential_verifier: Arc::new(AcceptAllCredentials),
+        }
+    }
+
+    /// Create a client that moves idle rooms to `storage`.
+    ///
+    /// Rooms already in storage count as memberships but are not loaded until
+    /// their first event, so startup cost does not grow with the number of
+    /// rooms. See [`ClientConfig::max_hydrated_rooms`]. The block list is
+    /// loaded from and saved to the same storage.
+    pub fn with_storage(
+        env: E,
+        identity: ClientIdentity,
+        config: ClientConfig,
+        storage: Box<dyn ClientStorage>,
+    ) -> Result<Self, ClientError> {
+        let dormant = storage.list_rooms()?.into_iter().collect();
+        let blocked_users = storage.load_blocked_users()?.into_iter().collect();
+
+        let mut client = Self::with_config(env, identity, config);
+        client.storage = Some(storage);
+        client.dormant = dormant;
+        client.blocked_users = blocked_users;
+        Ok(client)
+    }
+
+    /// Check members' credentials with `verifier` when joining via Welcome
+    /// and when Commits add or update members. Applies to every room,
+    /// including ones already joined. Accepts all credentials by default.
+    pub fn set_credential_verifier(&mut self, verifier: Arc<dyn CredentialVerifier>) {
+        for room in self.rooms.values_mut() {
+            room.mls_group.set_credential_verifier(Arc::clone(&verifier));
         }
+        self.credential_verifier = verifier;
     }
 
     /// Client's stable sender ID used in frame headers.
@@ -119,22 +511,207 @@ impl<E: Environment> Client<E> {
         self.identity.sender_id
     }
 
-    /// Number of active room memberships.
+    /// Whether content from `user_id` is hidden.
+    pub fn is_blocked(&self, user_id: u64) -> bool {
+        self.blocked_users.contains(&user_id)
+    }
+
+    /// Blocked users, in ascending order.
+    pub fn blocked_users(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocked_users.iter().copied()
+    }
+
+    /// Lifetime in seconds of messages we send to a room. `None` if they
+    /// never disappear or the room is not hydrated.
+    pub fn message_ttl(&self, room_id: RoomId) -> Option<u64> {
+        self.rooms.get(&room_id).and_then(|room| room.disappearing.ttl_secs())
+    }
+
+    /// Number of active room memberships, hydrated or not.
     pub fn room_count(&self) -> usize {
+        self.rooms.len() + self.dormant.len()
+    }
+
+    /// Number of rooms currently hydrated in memory.
+    pub fn hydrated_room_count(&self) -> usize {
         self.rooms.len()
     }
 
+    /// Save the message being composed in a room, so it survives restarts.
+    /// An empty draft removes it. Does nothing without storage.
+    pub fn save_draft(&self, room_id: RoomId, text: &str) -> Result<(), ClientError> {
+        if let Some(storage) = self.storage.as_deref() {
+            storage.store_draft(room_id, text)?;
+        }
+        Ok(())
+    }
+
+    /// Message being composed in a room, as last saved. `None` if there is
+    /// none or no storage.
+    pub fn load_draft(&self, room_id: RoomId) -> Result<Option<String>, ClientError> {
+        match self.storage.as_deref() {
+            Some(storage) => Ok(storage.load_draft(room_id)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Move every idle room to storage, e.g. before shutdown.
+    ///
+    /// Rooms with a pending commit or queued sends stay hydrated. Returns the
+    /// number of rooms moved. Does nothing without storage.
+    pub fn dehydrate_idle_rooms(&mut self) -> Result<usize, ClientError> {
+        let idle: Vec<RoomId> = self
+            .rooms
+            .iter()
+            .filter(|(room_id, room)| room.is_idle() && !self.has_pending_add(**room_id))
+            .map(|(&room_id, _)| room_id)
+            .collect();
+
+        let mut moved = 0;
+        for room_id in idle {
+            if self.dehydrate(room_id)? {
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Export recorded history for `range` of log indices as a signed
+    /// document.
+    ///
+    /// Requires [`ClientConfig::record_transcript`]. Signed with our MLS
+    /// signature key for the room.
+    pub fn export_transcript(
+        &mut self,
+        room_id: RoomId,
+        range: Range<u64>,
+    ) -> Result<TranscriptDocument, ClientError> {
+        self.hydrate(room_id)?;
+        let room = self.rooms.get(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+        let transcript = room.transcript.as_ref().ok_or_else(|| ClientError::InvalidState {
+            reason: "transcript recording is disabled".to_string(),
+        })?;
+
+        let signer_key =
+            room.mls_group.signature_public_key().map_err(ClientError::mls(room_id))?;
+        let entries = transcript.entries(range.clone());
+
+        TranscriptDocument::new_signed(
+            room_id,
+            range,
+            self.identity.sender_id,
+            entries,
+            signer_key,
+            |data| room.mls_group.sign(data).map_err(ClientError::mls(room_id)),
+        )
+    }
+
+    /// Export our identity and every room, hydrated or not, encrypted under
+    /// a key derived from `passphrase`.
+    ///
+    /// The backup holds MLS group state and sender key ratchets, and nothing
+    /// in it is readable without the passphrase, so it can be kept anywhere,
+    /// including the server. Joins still waiting for a Welcome are left out.
+    /// The cost of key derivation is [`ClientConfig::backup_params`].
+    pub fn export_backup(&self, passphrase: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let mut rooms = Vec::with_capacity(self.room_count());
+        for (&room_id, room) in &self.rooms {
+            rooms.push((room_id, room.dehydrate()?));
+        }
+        if let Some(storage) = self.storage.as_deref() {
+            for &room_id in self.dormant.keys() {
+                let bytes =
+                    storage.load_room(room_id)?.ok_or(ClientStorageError::NotFound { room_id })?;
+                rooms.push((room_id, bytes));
+            }
+        }
+        rooms.sort_unstable_by_key(|(room_id, _)| *room_id);
+
+        let backup = ClientBackup {
+            sender_id: self.identity.sender_id,
+            credential: self.identity.credential.to_identity_bytes()?,
+            rooms,
+            blocked_users: self.blocked_users.iter().copied().collect(),
+        };
+        // Wiped on drop, so room secrets don't linger in freed memory
+        let mut plaintext = Zeroizing::new(Vec::new());
+        ciborium::ser::into_writer(&backup, &mut *plaintext)
+            .map_err(|e| ClientStorageError::Serialization(e.to_string()))?;
+
+        let mut salt = [0u8; BACKUP_SALT_SIZE];
+        let mut nonce = [0u8; BACKUP_NONCE_SIZE];
+        self.env.random_bytes(&mut salt);
+        self.env.random_bytes(&mut nonce);
+        Ok(seal_backup(&plaintext, passphrase, self.config.backup_params, salt, nonce)?)
+    }
+
+    /// Seal `data` as an attachment named `filename`, under a key drawn for
+    /// it alone.
+    ///
+    /// Send the result as [`AppMessageBody::Attachment`]; members open it
+    /// with [`crate::open_attachment`].
+    pub fn seal_attachment(&self, filename: String, data: &[u8]) -> Attachment {
+        let mut key = [0u8; ATTACHMENT_KEY_SIZE];
+        let mut nonce = [0u8; ATTACHMENT_NONCE_SIZE];
+        self.env.random_bytes(&mut key);
+        self.env.random_bytes(&mut nonce);
+        attachment::seal(filename, data, key, nonce)
+    }
+
+    /// Rebuild a client from [`Self::export_backup`] output, e.g. on a new
+    /// device.
+    ///
+    /// Every room comes back hydrated at the epoch it was exported at; rooms
+    /// that moved on since catch up through sync. The exporting device may
+    /// have sent with the same sender keys since, so the first message sent
+    /// to a room that has not moved on is held behind a self-update commit
+    /// and goes out in the fresh epoch. The block list is restored as well.
+    pub fn import_backup(
+        env: E,
+        backup: &[u8],
+        passphrase: &[u8],
+        config: ClientConfig,
+    ) -> Result<Self, ClientError> {
+        let plaintext = Zeroizing::new(open_backup(backup, passphrase)?);
+        let backup: ClientBackup = ciborium::de::from_reader(&plaintext[..])
+            .map_err(|e| ClientStorageError::Serialization(e.to_string()))?;
+
+        let credential = Credential::from_identity_bytes(&backup.credential)?;
+        let identity = ClientIdentity { sender_id: backup.sender_id, credential };
+        let mut client = Self::with_config(env, identity, config);
+
+        for (room_id, bytes) in backup.rooms {
+            let now = client.env.now();
+            let mut room = RoomState::hydrate(client.env.clone(), &bytes, &client.config, now)?;
+            room.rekey_before_send = true;
+            client.rooms.insert(room_id, room);
+            client.lru.push_back(room_id);
+        }
+        client.blocked_users = backup.blocked_users.into_iter().collect();
+        Ok(client)
+    }
+
     /// Check if the client is a member of a room.
     pub fn is_member(&self, room_id: RoomId) -> bool {
-        self.rooms.contains_key(&room_id)
+        self.rooms.contains_key(&room_id) || self.dormant.contains_key(&room_id)
+    }
+
+    /// Whether we proposed to leave a room and are waiting for the commit
+    /// removing us. The room stays a member until then.
+    pub fn is_leaving(&self, room_id: RoomId) -> bool {
+        self.rooms.get(&room_id).is_some_and(|room| room.leaving_since.is_some())
     }
 
     /// Current MLS epoch for a room. `None` if not a member.
     pub fn epoch(&self, room_id: RoomId) -> Option<u64> {
-        self.rooms.get(&room_id).map(|r| r.mls_group.epoch())
+        self.rooms
+            .get(&room_id)
+            .map(|r| r.mls_group.epoch())
+            .or_else(|| self.dormant.get(&room_id).copied())
     }
 
-    /// MLS tree hash for a room. `None` if not a member or export fails.
+    /// MLS tree hash for a room. `None` if not a hydrated member or export
+    /// fails.
     ///
     /// Tree hash is a cryptographic commitment to the group's ratchet tree.
     /// All members at the same epoch must have identical tree hashes.
@@ -145,7 +722,7 @@ impl<E: Environment> Client<E> {
             .map(|state| state.tree_hash)
     }
 
-    /// Member IDs in a room. `None` if not a member or export fails.
+    /// Member IDs in a room. `None` if not a hydrated member or export fails.
     ///
     /// Returns all member IDs (`sender_ids`) currently in the MLS group.
     pub fn member_ids(&self, room_id: RoomId) -> Option<Vec<u64>> {
@@ -155,6 +732,28 @@ impl<E: Environment> Client<E> {
             .map(|state| state.members)
     }
 
+    /// Members of a room as its ratchet tree has them, one per client, in
+    /// leaf order. Empty if not a hydrated member.
+    ///
+    /// [`ClientAction::MembersChanged`] reports every change to this list.
+    pub fn members(&self, room_id: RoomId) -> Vec<MemberInfo> {
+        self.rooms.get(&room_id).map(|r| r.roster.members()).unwrap_or_default()
+    }
+
+    /// Credentials of a room's members. `None` if not a hydrated member.
+    pub fn member_credentials(&self, room_id: RoomId) -> Option<Vec<Credential>> {
+        self.rooms.get(&room_id).map(|r| r.mls_group.member_credentials())
+    }
+
+    /// Signature public keys of a room's members, by member ID. `None` if
+    /// not a hydrated member or export fails.
+    pub fn member_keys(&self, room_id: RoomId) -> Option<HashMap<u64, [u8; 32]>> {
+        self.rooms
+            .get(&room_id)
+            .and_then(|r| r.mls_group.export_group_state().ok())
+            .map(|state| state.member_keys)
+    }
+
     /// Generate a `KeyPackage` for this client to join a room.
     ///
     /// The returned `KeyPackage` should be sent to the room creator who will
@@ -163,9 +762,10 @@ impl<E: Environment> Client<E> {
     ///
     /// Returns (serialized `KeyPackage` bytes, `KeyPackage` hash ref).
     pub fn generate_key_package(&mut self) -> Result<(Vec<u8>, Vec<u8>), ClientError> {
-        let (kp_bytes, hash_ref, pending_state) =
-            MlsGroup::generate_key_package(self.env.clone(), self.identity.sender_id)
-                .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let (kp_bytes, hash_ref, pending_state) = MlsGroup::generate_key_package_with_credential(
+            self.env.clone(),
+            &self.identity.credential,
+        )?;
 
         self.pending_joins.insert(hash_ref.clone(), pending_state);
 
@@ -173,18 +773,57 @@ impl<E: Environment> Client<E> {
     }
 
     /// Process an event and return resulting actions.
+    ///
+    /// With storage attached, the room the event targets is hydrated first,
+    /// and least recently used rooms are dehydrated afterwards.
     pub fn handle(
         &mut self,
         event: ClientEvent<E::Instant>,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let room_id = event_room_id(&event);
+        if let Some(room_id) = room_id {
+            self.hydrate(room_id)?;
+        }
+
+        let mut actions = self.dispatch(event)?;
+
+        if let Some(room_id) = room_id {
+            self.touch(room_id);
+        }
+        actions.extend(self.evict_cold_rooms());
+
+        Ok(actions)
+    }
+
+    fn dispatch(
+        &mut self,
+        event: ClientEvent<E::Instant>,
     ) -> Result<Vec<ClientAction>, ClientError> {
         match event {
+            ClientEvent::MarkRead { room_id, log_index } => {
+                self.handle_mark_read(room_id, log_index)
+            },
+            ClientEvent::BlockUser { user_id } => self.handle_set_blocked(user_id, true),
+            ClientEvent::UnblockUser { user_id } => self.handle_set_blocked(user_id, false),
             ClientEvent::CreateRoom { room_id } => self.handle_create_room(room_id),
             ClientEvent::SendMessage { room_id, plaintext } => {
-                self.handle_send_message(room_id, &plaintext)
+                self.handle_send_message(room_id, AppMessageBody::Text(plaintext))
+            },
+            ClientEvent::SendAppMessage { room_id, body } => {
+                self.handle_send_message(room_id, body)
+            },
+            ClientEvent::EditMessage { room_id, target_log_index, new_plaintext } => self
+                .handle_send_message(room_id, AppMessageBody::Edit {
+                    target_log_index,
+                    new_text: new_plaintext,
+                }),
+            ClientEvent::DeleteMessage { room_id, target_log_index } => {
+                self.handle_send_message(room_id, AppMessageBody::Delete { target_log_index })
             },
             ClientEvent::FrameReceived(frame) => self.handle_frame(&frame),
             ClientEvent::Tick { now } => self.handle_tick(now),
             ClientEvent::LeaveRoom { room_id } => self.handle_leave_room(room_id),
+            ClientEvent::ForgetRoom { room_id } => self.handle_forget_room(room_id),
             ClientEvent::JoinRoom { room_id, welcome } => self.handle_join_room(room_id, &welcome),
             ClientEvent::AddMembers { room_id, key_packages } => {
                 self.handle_add_members(room_id, &key_packages)
@@ -197,7 +836,127 @@ impl<E: Environment> Client<E> {
                 self.handle_fetch_and_add_member(room_id, user_id)
             },
             ClientEvent::ExternalJoin { room_id } => self.handle_external_join(room_id),
+            ClientEvent::BackfillRoom { room_id, until_log_index } => {
+                self.handle_backfill_room(room_id, until_log_index)
+            },
+            ClientEvent::SetMessageTtl { room_id, ttl_secs } => {
+                let room =
+                    self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+                room.disappearing.set_ttl_secs(ttl_secs);
+                Ok(vec![])
+            },
+            ClientEvent::SetRoomListing { room_id, name } => {
+                self.handle_set_room_listing(room_id, name)
+            },
+            ClientEvent::ReportMessage { room_id, log_index, reason } => {
+                self.handle_report_message(room_id, log_index, reason)
+            },
+            ClientEvent::SearchDirectory { query, after } => {
+                let search = DirectorySearch { query, after, limit: DIRECTORY_PAGE };
+                let frame = Payload::DirectorySearch(search)
+                    .into_frame(FrameHeader::new(Opcode::DirectorySearch))
+                    .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+                Ok(vec![ClientAction::Send(frame)])
+            },
+            ClientEvent::InviteUser { room_id, user_id, ttl } => {
+                self.handle_invite_user(room_id, user_id, ttl)
+            },
+        }
+    }
+
+    /// Load a dormant room back into memory. No-op if it is already
+    /// hydrated or not a member.
+    fn hydrate(&mut self, room_id: RoomId) -> Result<(), ClientError> {
+        let Some(storage) = self.storage.as_deref() else {
+            return Ok(());
+        };
+        if self.dormant.remove(&room_id).is_none() {
+            return Ok(());
+        }
+
+        let bytes = storage.load_room(room_id)?.ok_or(ClientStorageError::NotFound { room_id })?;
+        let mut room = RoomState::hydrate(self.env.clone(), &bytes, &self.config, self.env.now())?;
+        room.mls_group.set_credential_verifier(Arc::clone(&self.credential_verifier));
+
+        self.rooms.insert(room_id, room);
+        Ok(())
+    }
+
+    /// Move a hydrated room to storage. Returns false without storage.
+    fn dehydrate(&mut self, room_id: RoomId) -> Result<bool, ClientError> {
+        let (Some(storage), Some(room)) = (self.storage.as_deref(), self.rooms.get(&room_id))
+        else {
+            return Ok(false);
+        };
+
+        let epoch = room.mls_group.epoch();
+        storage.store_room(room_id, epoch, &room.dehydrate()?)?;
+
+        self.rooms.remove(&room_id);
+        self.lru.retain(|&id| id != room_id);
+        self.dormant.insert(room_id, epoch);
+        Ok(true)
+    }
+
+    /// Drop a room, hydrated or not, along with its stored entry. Returns
+    /// whether it was known.
+    fn remove_room(&mut self, room_id: RoomId) -> Result<bool, ClientError> {
+        let known = self.rooms.remove(&room_id).is_some() | self.dormant.remove(&room_id).is_some();
+        self.lru.retain(|&id| id != room_id);
+        self.backfills.remove(&room_id);
+        if known && let Some(storage) = self.storage.as_deref() {
+            storage.remove_room(room_id)?;
         }
+        Ok(known)
+    }
+
+    /// Mark a room as most recently used.
+    fn touch(&mut self, room_id: RoomId) {
+        self.lru.retain(|&id| id != room_id);
+        if self.rooms.contains_key(&room_id) {
+            self.lru.push_back(room_id);
+        }
+    }
+
+    /// Dehydrate least recently used idle rooms until within
+    /// [`ClientConfig::max_hydrated_rooms`].
+    ///
+    /// The most recently used room is never evicted. Storage failures leave
+    /// the room hydrated and are reported as log actions.
+    fn evict_cold_rooms(&mut self) -> Vec<ClientAction> {
+        let Some(max) = self.config.max_hydrated_rooms else {
+            return Vec::new();
+        };
+
+        let mut actions = Vec::new();
+        let mut candidate = 0;
+        while self.rooms.len() > max && candidate + 1 < self.lru.len() {
+            let room_id = self.lru[candidate];
+            let idle = self.rooms.get(&room_id).is_some_and(RoomState::is_idle)
+                && !self.has_pending_add(room_id);
+
+            if !idle {
+                candidate += 1;
+                continue;
+            }
+
+            match self.dehydrate(room_id) {
+                Ok(true) => {},
+                Ok(false) => break,
+                Err(e) => {
+                    actions.push(ClientAction::Log {
+                        message: format!("Failed to dehydrate room {room_id:x}: {e}"),
+                    });
+                    break;
+                },
+            }
+        }
+
+        actions
+    }
+
+    fn has_pending_add(&self, room_id: RoomId) -> bool {
+        self.pending_adds.keys().any(|&(pending_room, _)| pending_room == room_id)
     }
 
     fn handle_create_room(&mut self, room_id: RoomId) -> Result<Vec<ClientAction>, ClientError> {
@@ -205,18 +964,19 @@ impl<E: Environment> Client<E> {
             return Err(ClientError::RoomAlreadyExists { room_id });
         }
 
-        let member_id = self.identity.sender_id;
-
-        let (mls_group, mls_actions) = MlsGroup::new(self.env.clone(), room_id, member_id)
-            .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let (mut mls_group, mls_actions) =
+            MlsGroup::new_with_credential(self.env.clone(), room_id, &self.identity.credential)
+                .map_err(ClientError::mls(room_id))?;
+        mls_group.set_credential_verifier(Arc::clone(&self.credential_verifier));
 
         let sender_keys = self.initialize_sender_keys(&mls_group)?;
         let my_leaf_index = mls_group.own_leaf_index();
 
-        let initial_state =
-            mls_group.export_state().map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let initial_state = mls_group.export_state().map_err(ClientError::mls(room_id))?;
 
-        let room_state = RoomState { mls_group, sender_keys, my_leaf_index };
+        let mut room_state =
+            RoomState::new(mls_group, sender_keys, my_leaf_index, &self.config, self.env.now());
+        let members_changed = room_state.refresh_members(room_id);
         self.rooms.insert(room_id, room_state);
 
         let mut actions = self.convert_mls_actions(room_id, mls_actions);
@@ -227,6 +987,7 @@ impl<E: Environment> Client<E> {
             mls_state: initial_state,
             my_leaf_index,
         }));
+        actions.extend(members_changed);
 
         actions.push(ClientAction::Log { message: format!("Created room {room_id:x} at epoch 0") });
 
@@ -238,27 +999,137 @@ impl<E: Environment> Client<E> {
         &self,
         mls_group: &MlsGroup<E>,
     ) -> Result<SenderKeyStore, ClientError> {
-        let epoch_secret = mls_group
-            .export_secret(SENDER_KEY_LABEL, SENDER_KEY_CONTEXT, SENDER_KEY_SECRET_SIZE)
-            .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let epoch_secret = mls_group.export_secret(
+            labels::SENDER_KEY_EXPORTER,
+            labels::SENDER_KEY_EXPORTER_CONTEXT,
+            SENDER_KEY_SECRET_SIZE,
+        )?;
 
         let member_indices = mls_group.member_leaf_indices();
 
         Ok(SenderKeyStore::initialize_epoch(&epoch_secret, mls_group.epoch(), &member_indices))
     }
 
+    /// Add or remove a user from the block list and persist it.
+    fn handle_set_blocked(
+        &mut self,
+        user_id: u64,
+        blocked: bool,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let changed = if blocked {
+            self.blocked_users.insert(user_id)
+        } else {
+            self.blocked_users.remove(&user_id)
+        };
+
+        if changed && let Some(storage) = self.storage.as_ref() {
+            let user_ids: Vec<u64> = self.blocked_users.iter().copied().collect();
+            storage.store_blocked_users(&user_ids)?;
+        }
+
+        Ok(vec![])
+    }
+
+    /// Advance the read position and broadcast a read receipt.
+    fn handle_mark_read(
+        &mut self,
+        room_id: RoomId,
+        log_index: u64,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+
+        if !room.read_markers.mark_read(log_index) {
+            return Ok(vec![]);
+        }
+
+        let mut actions = vec![room.unread_count_changed(room_id)];
+        let receipt = AppMessageBody::Receipt(Receipt {
+            message_log_index: log_index,
+            kind: ReceiptType::Read,
+            timestamp: self.env.wall_clock_secs().saturating_mul(1000),
+        });
+        actions.extend(self.handle_send_message(room_id, receipt)?);
+        Ok(actions)
+    }
+
+    /// Encrypt an application message body and frame it for the room.
     fn handle_send_message(
         &mut self,
         room_id: RoomId,
-        plaintext: &[u8],
+        body: AppMessageBody,
     ) -> Result<Vec<ClientAction>, ClientError> {
+        let now = self.env.now();
         let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+        if room.leaving_since.is_some() {
+            return Err(ClientError::InvalidState {
+                reason: format!("Leaving room {room_id:032x}"),
+            });
+        }
+
+        // Nothing is encrypted with sender keys restored from a backup.
+        // Messages wait for a self-update commit that moves to a fresh
+        // epoch; typing notifications are worthless by then
+        if room.rekey_before_send {
+            if !body.is_ephemeral() {
+                let queued = room.held_for_rekey.len();
+                if queued >= MAX_HELD_FOR_REKEY {
+                    return Err(ClientError::SendQueueFull { room_id, queued });
+                }
+                room.held_for_rekey.push(body);
+            }
+            if room.mls_group.has_pending_commit() {
+                return Ok(Vec::new());
+            }
+            let mls_actions = room.mls_group.self_update().map_err(ClientError::mls(room_id))?;
+            return Ok(self.convert_mls_actions(room_id, mls_actions));
+        }
+
+        // Typing notifications are throttled by the caller and worthless once
+        // late, so they skip the pacer rather than queue behind messages
+        let pacer = room.pacer.as_mut().filter(|_| !body.is_ephemeral());
+        let body = match pacer {
+            None => body,
+            Some(pacer) => match pacer.admit(body, now) {
+                Ok(Admission::Send(body)) => body,
+                Ok(Admission::Deferred { queued, retry_after }) => {
+                    return Ok(vec![ClientAction::Backpressure { room_id, queued, retry_after }]);
+                },
+                Err(QueueFull { queued }) => {
+                    return Err(ClientError::SendQueueFull { room_id, queued });
+                },
+            },
+        };
+
+        let frame =
+            Self::encrypt_message(&self.env, self.identity.sender_id, room_id, room, &body)?;
+        Ok(vec![ClientAction::Send(frame)])
+    }
+
+    /// Encrypt an application message body into a signed frame.
+    ///
+    /// Ephemeral bodies are flagged so the server relays them without storing
+    /// them, expire after [`TYPING_TTL_SECS`] and carry no request ID, since
+    /// they are never acknowledged.
+    fn encrypt_message(
+        env: &E,
+        sender_id: u64,
+        room_id: RoomId,
+        room: &mut RoomState<E>,
+        body: &AppMessageBody,
+    ) -> Result<Frame, ClientError> {
+        let plaintext = body
+            .encode_at(env.wall_clock_secs().saturating_mul(1000))
+            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
 
         let mut random_bytes = [0u8; NONCE_RANDOM_SIZE];
-        self.env.random_bytes(&mut random_bytes);
+        env.random_bytes(&mut random_bytes);
 
         let crypto_encrypted =
-            room.sender_keys.encrypt(room.my_leaf_index, plaintext, random_bytes)?;
+            room.sender_keys.encrypt(room.my_leaf_index, &plaintext, random_bytes)?;
+
+        if let Some(transcript) = room.transcript.as_mut() {
+            transcript.record_sent(crypto_encrypted.epoch, crypto_encrypted.generation, body);
+        }
 
         let encrypted = crypto_to_proto_encrypted(&crypto_encrypted);
         let payload = serialize_encrypted_message(&encrypted);
@@ -270,18 +1141,77 @@ impl<E: Environment> Client<E> {
 
         let mut header = FrameHeader::new(Opcode::AppMessage);
         header.set_room_id(room_id);
-        header.set_sender_id(self.identity.sender_id);
+        header.set_sender_id(sender_id);
         header.set_epoch(room.mls_group.epoch());
         header.set_payload_size(payload_len);
+        if body.is_ephemeral() {
+            header.set_flags(header.flags() | FrameFlags::EPHEMERAL);
+            header.set_expires_at(Some(env.wall_clock_secs().saturating_add(TYPING_TTL_SECS)));
+        } else {
+            header.set_expires_at(room.disappearing.expires_at(env.wall_clock_secs()));
+            header.set_request_id(Self::next_request_id(env, room));
+        }
 
         room.mls_group.sign_frame_header(&mut header);
 
-        let frame = Frame::new(header, payload);
+        Ok(Frame::new(header, payload))
+    }
 
-        Ok(vec![ClientAction::Send(frame)])
+    /// Take the `request_id` for a room's next message, never zero.
+    fn next_request_id(env: &E, room: &mut RoomState<E>) -> u32 {
+        if room.next_request_id == 0 {
+            let mut bytes = [0u8; 4];
+            env.random_bytes(&mut bytes);
+            room.next_request_id = u32::from_be_bytes(bytes).max(1);
+        }
+        let request_id = room.next_request_id;
+        room.next_request_id = request_id.wrapping_add(1).max(1);
+        request_id
     }
 
+    /// Process a frame, then request any frames its log index shows were
+    /// lost before it. A frame that fails to process is not counted as
+    /// received, so the next gap refetches it.
     fn handle_frame(&mut self, frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let mut actions = self.dispatch_frame(frame)?;
+        actions.extend(self.repair_gap(frame)?);
+        Ok(actions)
+    }
+
+    /// Request the frames skipped before `frame`, if it reveals a gap in its
+    /// room's log. Whatever the repair fetches twice is dropped by the replay
+    /// window.
+    fn repair_gap(&mut self, frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let room_id = frame.header.room_id();
+        if !gaps::is_sequenced(&frame.header) {
+            return Ok(vec![]);
+        }
+        let Some(gap) = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.gaps.observe(frame.header.log_index()))
+        else {
+            return Ok(vec![]);
+        };
+
+        let request = SyncRequest::new(gap.start, gap.end - gap.start);
+        let mut repair = Payload::SyncRequest(request)
+            .into_frame(FrameHeader::new(Opcode::SyncRequest))
+            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+        repair.header.set_room_id(room_id);
+        repair.header.set_sender_id(self.identity.sender_id);
+
+        Ok(vec![
+            ClientAction::GapDetected {
+                room_id,
+                from_log_index: gap.start,
+                until_log_index: gap.end,
+            },
+            ClientAction::Send(repair),
+        ])
+    }
+
+    fn dispatch_frame(&mut self, frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
         let room_id = frame.header.room_id();
 
         let opcode = frame.header.opcode_enum().ok_or_else(|| ClientError::InvalidFrame {
@@ -293,29 +1223,76 @@ impl<E: Environment> Client<E> {
                 // Ignore session-level responses (handled at transport layer)
                 Ok(vec![])
             },
-            Opcode::Error => Ok(vec![ClientAction::Log {
-                message: format!("Server error: room_id={room_id:x}"),
-            }]),
+            Opcode::Error => Err(Self::handle_error(room_id, frame)),
             Opcode::AppMessage => self.handle_app_message(room_id, frame),
             Opcode::Commit | Opcode::ExternalCommit => self.handle_commit(room_id, frame),
+            Opcode::Proposal => self.handle_proposal(room_id, frame),
             Opcode::Welcome => self.handle_welcome(room_id, frame),
             Opcode::SyncResponse => self.handle_sync_response(room_id, frame),
+            Opcode::HistoryTruncated => Self::handle_history_truncated(room_id, frame),
+            Opcode::Presence | Opcode::PresenceReply => Self::handle_presence(frame),
+            Opcode::LaggedBehind => self.handle_lagged_behind(frame),
+            Opcode::DirectoryResults => Self::handle_directory_results(frame),
+            Opcode::ServerNotice => Self::handle_server_notice(frame),
+            Opcode::Invite => self.handle_invite(frame),
+            Opcode::CloseRoom => self.handle_room_closed(room_id, frame),
             Opcode::KeyPackageFetch => self.handle_key_package_fetch_response(frame),
+            Opcode::KeyPackageLowStock => self.handle_key_package_low_stock(frame),
             Opcode::GroupInfo => self.handle_group_info_response(frame),
             _ => {
                 let room =
                     self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
 
-                let mls_actions = room
-                    .mls_group
-                    .process_message(frame)
-                    .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+                let mls_actions =
+                    room.mls_group.process_message(frame).map_err(ClientError::mls(room_id))?;
 
                 Ok(self.convert_mls_actions(room_id, mls_actions))
             },
         }
     }
 
+    /// Note the server's echo of one of our own messages, which acknowledges
+    /// it. Reports the log index the message got if it carries a request ID.
+    /// Ephemeral frames are never sequenced, so their echo means nothing.
+    fn handle_own_echo(&mut self, room_id: RoomId, frame: &Frame) -> Vec<ClientAction> {
+        if frame.header.flags().contains(FrameFlags::EPHEMERAL) {
+            return Vec::new();
+        }
+        if let (Some(expires_at), Some(room)) =
+            (frame.header.expires_at(), self.rooms.get_mut(&room_id))
+        {
+            room.disappearing.track(expires_at, frame.header.log_index());
+        }
+        self.record_own_echo(room_id, frame);
+
+        match frame.header.request_id() {
+            0 => Vec::new(),
+            request_id => vec![ClientAction::MessageSequenced {
+                room_id,
+                request_id,
+                log_index: frame.header.log_index(),
+            }],
+        }
+    }
+
+    /// Record our own sequenced message in the transcript, if recording.
+    fn record_own_echo(&mut self, room_id: RoomId, frame: &Frame) {
+        let Some(transcript) = self.rooms.get_mut(&room_id).and_then(|r| r.transcript.as_mut())
+        else {
+            return;
+        };
+
+        if let Ok(encrypted) = deserialize_encrypted_message(&frame.payload) {
+            transcript.record_echo(
+                encrypted.epoch,
+                encrypted.generation,
+                frame.header.log_index(),
+                self.identity.sender_id,
+                self.env.wall_clock_secs().saturating_mul(1000),
+            );
+        }
+    }
+
     /// Handle application message (encrypted content).
     fn handle_app_message(
         &mut self,
@@ -325,7 +1302,7 @@ impl<E: Environment> Client<E> {
         if frame.header.sender_id() == self.identity.sender_id {
             // Skip our own messages - we already have the plaintext locally
             // and our sender ratchet has already advanced past this generation
-            return Ok(vec![]);
+            return Ok(self.handle_own_echo(room_id, frame));
         }
 
         let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
@@ -379,16 +1356,71 @@ impl<E: Environment> Client<E> {
             });
         }
 
+        let log_index = frame.header.log_index();
+        let epoch = proto_encrypted.epoch;
+        let generation = proto_encrypted.generation;
+
+        match room.replay_window.check(verified_sender_id, epoch, generation, log_index) {
+            ReplayCheck::Fresh => {},
+            ReplayCheck::Duplicate => {
+                return Ok(vec![ClientAction::Log {
+                    message: format!(
+                        "Dropping redelivered message in room {room_id:x} at log index {log_index}"
+                    ),
+                }]);
+            },
+            ReplayCheck::Replay { original_log_index } => {
+                return Ok(vec![
+                    ClientAction::Log {
+                        message: format!(
+                            "Replay in room {room_id:x}: message from {verified_sender_id} (epoch {epoch}, generation {generation}) first seen at log index {original_log_index}, replayed at {log_index}"
+                        ),
+                    },
+                    ClientAction::ReplayDetected {
+                        room_id,
+                        sender_id: verified_sender_id,
+                        epoch,
+                        generation,
+                        original_log_index,
+                        replayed_log_index: log_index,
+                    },
+                ]);
+            },
+        }
+
         let encrypted = proto_to_crypto_encrypted(&proto_encrypted);
         let plaintext = room.sender_keys.decrypt(&encrypted)?;
 
-        Ok(vec![ClientAction::DeliverMessage {
-            room_id,
+        // Record only after decryption succeeded, so a forged frame cannot
+        // shadow the genuine message.
+        room.replay_window.record(verified_sender_id, epoch, generation, log_index);
+
+        let (body, sent_at) = match decode_body(room_id, log_index, plaintext) {
+            Ok(decoded) => decoded,
+            Err(message) => return Ok(vec![ClientAction::Log { message }]),
+        };
+
+        // Decrypted to keep the ratchet in step, but never shown
+        if frame.header.is_expired(self.env.wall_clock_secs()) {
+            return Ok(vec![]);
+        }
+
+        let received_at = self.env.wall_clock_secs().saturating_mul(1000);
+        let sequenced = Sequenced {
             sender_id: verified_sender_id,
-            plaintext,
-            log_index: frame.header.log_index(),
-            timestamp: frame.header.hlc_timestamp(),
-        }])
+            log_index,
+            timestamp: received_at,
+            display_timestamp: self.skew.observe(room_id, verified_sender_id, sent_at, received_at),
+            // An ephemeral frame's expiry is its own TTL, not a disappearing
+            // message timer, and it has no log index to expire
+            expires_at: frame
+                .header
+                .expires_at()
+                .filter(|_| !frame.header.flags().contains(FrameFlags::EPHEMERAL)),
+        };
+
+        let blocked = self.blocked_users.contains(&verified_sender_id);
+        Ok(room.deliver(room_id, sequenced, body, blocked))
     }
 
     /// Handle MLS commit (epoch transition).
@@ -405,10 +1437,8 @@ impl<E: Environment> Client<E> {
 
         let mut actions = {
             if is_own_commit && room.mls_group.has_pending_commit() {
-                let mls_actions = room
-                    .mls_group
-                    .merge_pending_commit()
-                    .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+                let mls_actions =
+                    room.mls_group.merge_pending_commit().map_err(ClientError::mls(room_id))?;
                 self.convert_mls_actions(room_id, mls_actions)
             } else if is_own_commit && !room.mls_group.has_mls_pending_commit() {
                 // The MLS group is already at the committed epoch, so we should
@@ -422,15 +1452,20 @@ impl<E: Environment> Client<E> {
                 // Process the Commit even if we don't have a pending commit.
                 // This handles the race condition where we receive our own Commit back
                 // before the original send operation consumed the pending commit.
-                let mls_actions = room
-                    .mls_group
-                    .process_message(frame)
-                    .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+                let mls_actions =
+                    room.mls_group.process_message(frame).map_err(ClientError::mls(room_id))?;
 
                 self.convert_mls_actions(room_id, mls_actions)
             }
         };
 
+        // A commit removing us, such as one finalizing our leave, leaves no
+        // group to derive sender keys from
+        if actions.iter().any(|action| matches!(action, ClientAction::RoomRemoved { .. })) {
+            self.remove_room(room_id)?;
+            return Ok(actions);
+        }
+
         let (new_sender_keys, new_leaf_index, epoch, my_leaf_index) = {
             let room = self.rooms.get(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
             let sender_keys = self.initialize_sender_keys(&room.mls_group)?;
@@ -442,16 +1477,34 @@ impl<E: Environment> Client<E> {
         let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
         room.sender_keys = new_sender_keys;
         room.my_leaf_index = new_leaf_index;
+        room.replay_window.retain_from_epoch(epoch);
+
+        // Sender keys are fresh in the new epoch, so held messages can go.
+        // Leaving drops them, as it refuses new sends
+        room.rekey_before_send = false;
+        let mut held = mem::take(&mut room.held_for_rekey);
+        if room.leaving_since.is_some() {
+            held.clear();
+        }
 
         actions.push(ClientAction::PersistRoom(RoomStateSnapshot {
             room_id,
             epoch,
-            mls_state: room
-                .mls_group
-                .export_state()
-                .map_err(|e| ClientError::Mls { reason: e.to_string() })?,
+            mls_state: room.mls_group.export_state().map_err(ClientError::mls(room_id))?,
             my_leaf_index,
         }));
+        actions.extend(room.refresh_members(room_id));
+
+        // Our leave proposal lapsed with the old epoch unless this commit
+        // removed us, so propose it again
+        if room.leaving_since.is_some() {
+            let mls_actions = room.propose_leave(room_id, self.env.now())?;
+            actions.extend(self.convert_mls_actions(room_id, mls_actions));
+        }
+
+        for body in held {
+            actions.extend(self.handle_send_message(room_id, body)?);
+        }
 
         Ok(actions)
     }
@@ -469,7 +1522,7 @@ impl<E: Environment> Client<E> {
         let pending_hashes: Vec<Vec<u8>> = self.pending_joins.keys().cloned().collect();
 
         if pending_hashes.is_empty() {
-            return Err(ClientError::Mls {
+            return Err(ClientError::InvalidState {
                 reason: "No pending KeyPackage state available for Welcome".to_string(),
             });
         }
@@ -484,8 +1537,10 @@ impl<E: Environment> Client<E> {
                     welcome_bytes,
                     pending_state,
                 ) {
-                    Ok(result) => {
-                        return Ok(result);
+                    Ok((mut group, actions)) => {
+                        group.set_credential_verifier(Arc::clone(&self.credential_verifier));
+                        group.verify_member_credentials().map_err(ClientError::mls(room_id))?;
+                        return Ok((group, actions));
                     },
                     Err(e) => {
                         // KeyPackage didn't match this Welcome. State is consumed by
@@ -496,12 +1551,13 @@ impl<E: Environment> Client<E> {
             }
         }
 
-        Err(ClientError::Mls {
-            reason: format!(
-                "No pending KeyPackage matched this Welcome: {}",
-                last_error.map_or_else(|| "unknown".to_string(), |e| e.to_string())
-            ),
-        })
+        // Only the last mismatch is reported; earlier ones failed the same way
+        Err(last_error.map_or_else(
+            || ClientError::InvalidState {
+                reason: "No pending KeyPackage matched this Welcome".to_string(),
+            },
+            ClientError::mls(room_id),
+        ))
     }
 
     /// Handle incoming Welcome frame.
@@ -535,13 +1591,12 @@ impl<E: Environment> Client<E> {
         let sender_keys = self.initialize_sender_keys(&mls_group)?;
         let my_leaf_index = mls_group.own_leaf_index();
 
-        let room_state = RoomState { mls_group, sender_keys, my_leaf_index };
+        let mut room_state =
+            RoomState::new(mls_group, sender_keys, my_leaf_index, &self.config, self.env.now());
         let current_epoch = room_state.mls_group.epoch();
+        let members_changed = room_state.refresh_members(room_id);
 
-        let mls_state = room_state
-            .mls_group
-            .export_state()
-            .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let mls_state = room_state.mls_group.export_state().map_err(ClientError::mls(room_id))?;
 
         let snapshot = crate::event::RoomStateSnapshot {
             room_id,
@@ -555,6 +1610,7 @@ impl<E: Environment> Client<E> {
         let mut actions = self.convert_mls_actions(room_id, mls_actions);
         actions.push(ClientAction::Log { message: format!("Joined room {room_id:x} via Welcome") });
         actions.push(ClientAction::PersistRoom(snapshot));
+        actions.extend(members_changed);
         actions.push(ClientAction::RequestSync {
             room_id,
             from_epoch: current_epoch,
@@ -582,13 +1638,16 @@ impl<E: Environment> Client<E> {
         let sender_keys = self.initialize_sender_keys(&mls_group)?;
         let my_leaf_index = mls_group.own_leaf_index();
 
-        let room_state = RoomState { mls_group, sender_keys, my_leaf_index };
+        let mut room_state =
+            RoomState::new(mls_group, sender_keys, my_leaf_index, &self.config, self.env.now());
+        let members_changed = room_state.refresh_members(room_id);
         self.rooms.insert(room_id, room_state);
 
         let mut actions = self.convert_mls_actions(room_id, mls_actions);
         actions.push(ClientAction::Log {
             message: format!("Joined room {room_id:x} via JoinRoom event"),
         });
+        actions.extend(members_changed);
 
         Ok(actions)
     }
@@ -619,10 +1678,12 @@ impl<E: Environment> Client<E> {
             ),
         });
 
+        let mut log_indices = Vec::with_capacity(sync_response.frames.len());
         for (i, frame_bytes) in sync_response.frames.iter().enumerate() {
             let sync_frame = Frame::decode(frame_bytes).map_err(|e| ClientError::InvalidFrame {
                 reason: format!("Failed to decode sync frame {i}: {e}"),
             })?;
+            log_indices.push(sync_frame.header.log_index());
 
             match self.handle_frame(&sync_frame) {
                 Ok(actions) => all_actions.extend(actions),
@@ -636,7 +1697,13 @@ impl<E: Environment> Client<E> {
             }
         }
 
-        if sync_response.has_more {
+        if self.backfills.contains_key(&room_id) {
+            all_actions.extend(self.continue_backfill(
+                room_id,
+                log_indices,
+                sync_response.has_more,
+            )?);
+        } else if sync_response.has_more {
             // More frames avaliable
             let current_epoch = self.rooms.get(&room_id).map_or(0, |r| r.mls_group.epoch());
 
@@ -664,6 +1731,295 @@ impl<E: Environment> Client<E> {
         Ok(all_actions)
     }
 
+    /// Start backfilling a room's history.
+    fn handle_backfill_room(
+        &mut self,
+        room_id: RoomId,
+        until_log_index: u64,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        if !self.rooms.contains_key(&room_id) {
+            return Err(ClientError::RoomNotFound { room_id });
+        }
+
+        if self.backfills.contains_key(&room_id) {
+            return Err(ClientError::InvalidState {
+                reason: format!("backfill already running for room {room_id:x}"),
+            });
+        }
+
+        self.backfills.insert(room_id, Backfill::new(until_log_index));
+        self.backfill_step(room_id)
+    }
+
+    /// Account for a received backfill batch, then continue.
+    /// Handle a retention tombstone found at the start of synced history.
+    fn handle_history_truncated(
+        room_id: RoomId,
+        frame: &Frame,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let tombstone: HistoryTruncated =
+            ciborium::de::from_reader(&frame.payload[..]).map_err(|e| {
+                ClientError::InvalidFrame {
+                    reason: format!("Failed to decode HistoryTruncated: {e}"),
+                }
+            })?;
+
+        Ok(vec![ClientAction::HistoryTruncated {
+            room_id,
+            first_log_index: tombstone.first_log_index,
+        }])
+    }
+
+    fn handle_presence(frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let statuses = match Payload::from_frame(frame) {
+            Ok(Payload::Presence(status)) => vec![status],
+            Ok(Payload::PresenceReply(reply)) => reply.users,
+            Ok(_) => Vec::new(),
+            Err(e) => {
+                return Err(ClientError::InvalidFrame {
+                    reason: format!("Failed to decode presence: {e}"),
+                });
+            },
+        };
+
+        Ok(statuses
+            .into_iter()
+            .map(|PresenceStatus { user_id, online, last_seen_secs }| {
+                ClientAction::PresenceChanged { user_id, online, last_seen_secs }
+            })
+            .collect())
+    }
+
+    fn handle_directory_results(frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let Ok(Payload::DirectoryResults(DirectoryResults { rooms, next })) =
+            Payload::from_frame(frame)
+        else {
+            return Err(ClientError::InvalidFrame {
+                reason: "Failed to decode DirectoryResults".to_string(),
+            });
+        };
+        Ok(vec![ClientAction::DirectoryResults { rooms, next }])
+    }
+
+    /// The server's rejection of something we sent. Error frames only carry
+    /// a room ID when the failure was in a room.
+    fn handle_error(room_id: RoomId, frame: &Frame) -> ClientError {
+        match Payload::from_frame(frame) {
+            Ok(Payload::Error(error)) => ClientError::Rejected {
+                room_id: (room_id != 0).then_some(room_id),
+                source: error.into_error(),
+            },
+            _ => ClientError::InvalidFrame { reason: "Failed to decode Error".to_string() },
+        }
+    }
+
+    fn handle_server_notice(frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let Ok(Payload::ServerNotice(ServerNotice { kind, text, room_id })) =
+            Payload::from_frame(frame)
+        else {
+            return Err(ClientError::InvalidFrame {
+                reason: "Failed to decode ServerNotice".to_string(),
+            });
+        };
+        Ok(vec![ClientAction::ServerNotice { room_id, kind, text }])
+    }
+
+    /// Sign an invite to a room we are a member of.
+    fn handle_invite_user(
+        &self,
+        room_id: RoomId,
+        user_id: u64,
+        ttl: Duration,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let room = self.rooms.get(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+        let signer_key =
+            room.mls_group.signature_public_key().map_err(ClientError::mls(room_id))?;
+        let mut invite = Invite {
+            room_id,
+            inviter: self.identity.sender_id,
+            invitee: user_id,
+            expires_at: self.env.wall_clock_secs().saturating_add(ttl.as_secs()),
+            signer_key: signer_key.to_vec(),
+            signature: Vec::new(),
+        };
+        invite.signature = room
+            .mls_group
+            .sign(&invite.signed_content())
+            .map_err(ClientError::mls(room_id))?
+            .to_vec();
+
+        let mut header = FrameHeader::new(Opcode::Invite);
+        header.set_room_id(room_id);
+        header.set_sender_id(self.identity.sender_id);
+        let frame = Payload::Invite(invite)
+            .into_frame(header)
+            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+        Ok(vec![ClientAction::Send(frame)])
+    }
+
+    /// Check an invite the server relayed to us.
+    fn handle_invite(&self, frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let Ok(Payload::Invite(invite)) = Payload::from_frame(frame) else {
+            return Err(ClientError::InvalidFrame {
+                reason: "Failed to decode Invite".to_string(),
+            });
+        };
+        let invalid = |reason: &str| ClientError::InvalidFrame {
+            reason: format!("invite to room {:x} {reason}", invite.room_id),
+        };
+
+        if invite.invitee != self.identity.sender_id {
+            return Err(invalid("is for another user"));
+        }
+        if invite.expires_at <= self.env.wall_clock_secs() {
+            return Err(invalid("has expired"));
+        }
+        let key = <[u8; 32]>::try_from(invite.signer_key.as_slice())
+            .ok()
+            .and_then(|key| VerifyingKey::from_bytes(&key).ok());
+        let signature = Signature::from_slice(&invite.signature).ok();
+        let signed = key.zip(signature).is_some_and(|(key, signature)| {
+            key.verify_strict(&invite.signed_content(), &signature).is_ok()
+        });
+        if !signed {
+            return Err(invalid("has an invalid signature"));
+        }
+
+        if self.is_member(invite.room_id) {
+            return Ok(vec![]);
+        }
+        Ok(vec![ClientAction::InviteReceived {
+            room_id: invite.room_id,
+            inviter: invite.inviter,
+            expires_at: invite.expires_at,
+        }])
+    }
+
+    /// List or unlist a room we are a member of.
+    fn handle_set_room_listing(
+        &self,
+        room_id: RoomId,
+        name: Option<String>,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        if !self.rooms.contains_key(&room_id) {
+            return Err(ClientError::RoomNotFound { room_id });
+        }
+
+        let mut header = FrameHeader::new(Opcode::DirectoryPublish);
+        header.set_room_id(room_id);
+        header.set_sender_id(self.identity.sender_id);
+        let frame = Payload::DirectoryPublish(DirectoryPublish { name })
+            .into_frame(header)
+            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+        Ok(vec![ClientAction::Send(frame)])
+    }
+
+    /// Report a message in a room we are in.
+    fn handle_report_message(
+        &self,
+        room_id: RoomId,
+        log_index: u64,
+        reason: String,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        if !self.rooms.contains_key(&room_id) {
+            return Err(ClientError::RoomNotFound { room_id });
+        }
+
+        let mut header = FrameHeader::new(Opcode::Report);
+        header.set_room_id(room_id);
+        header.set_sender_id(self.identity.sender_id);
+        let frame = Payload::ReportMessage(ReportMessage { message_log_index: log_index, reason })
+            .into_frame(header)
+            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+        Ok(vec![ClientAction::Send(frame)])
+    }
+
+    /// Forget a room its owner has closed. The server refuses any further
+    /// frames for it.
+    fn handle_room_closed(
+        &mut self,
+        room_id: RoomId,
+        frame: &Frame,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let close: CloseRoom = ciborium::de::from_reader(&frame.payload[..]).map_err(|e| {
+            ClientError::InvalidFrame { reason: format!("Failed to decode CloseRoom: {e}") }
+        })?;
+
+        if !self.remove_room(room_id)? {
+            return Ok(vec![]);
+        }
+
+        let reason = if close.reason.is_empty() {
+            "Room closed".to_string()
+        } else {
+            format!("Room closed: {}", close.reason)
+        };
+        Ok(vec![ClientAction::RoomRemoved { room_id, reason }])
+    }
+
+    /// Refetch frames the server dropped while we were reading too slowly.
+    /// Anything fetched twice is dropped by the replay window.
+    fn handle_lagged_behind(&self, frame: &Frame) -> Result<Vec<ClientAction>, ClientError> {
+        let notice: LaggedBehind = ciborium::de::from_reader(&frame.payload[..]).map_err(|e| {
+            ClientError::InvalidFrame { reason: format!("Failed to decode LaggedBehind: {e}") }
+        })?;
+
+        let mut actions = vec![ClientAction::Log {
+            message: format!("Server dropped {} frames, resyncing", notice.dropped),
+        }];
+        for gap in notice.rooms.iter().filter(|gap| self.rooms.contains_key(&gap.room_id)) {
+            let mut frame =
+                Payload::SyncRequest(SyncRequest::new(gap.from_log_index, BACKFILL_BATCH))
+                    .into_frame(FrameHeader::new(Opcode::SyncRequest))
+                    .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+            frame.header.set_room_id(gap.room_id);
+            frame.header.set_sender_id(self.identity.sender_id);
+            actions.push(ClientAction::Send(frame));
+        }
+        Ok(actions)
+    }
+
+    fn continue_backfill(
+        &mut self,
+        room_id: RoomId,
+        log_indices: Vec<u64>,
+        has_more: bool,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        if let Some(backfill) = self.backfills.get_mut(&room_id) {
+            backfill.record_batch(log_indices, has_more);
+        }
+        self.backfill_step(room_id)
+    }
+
+    /// Report progress and request the next batch, or finish.
+    fn backfill_step(&mut self, room_id: RoomId) -> Result<Vec<ClientAction>, ClientError> {
+        let Some(backfill) = self.backfills.get(&room_id) else {
+            return Ok(vec![]);
+        };
+
+        let mut actions = vec![ClientAction::BackfillProgress {
+            room_id,
+            fetched: backfill.fetched(),
+            total: backfill.total(),
+        }];
+
+        match backfill.next_request() {
+            Some(request) => {
+                let mut frame = Payload::SyncRequest(request)
+                    .into_frame(FrameHeader::new(Opcode::SyncRequest))
+                    .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+                frame.header.set_room_id(room_id);
+                frame.header.set_sender_id(self.identity.sender_id);
+                actions.push(ClientAction::Send(frame));
+            },
+            None => {
+                self.backfills.remove(&room_id);
+            },
+        }
+
+        Ok(actions)
+    }
+
     /// Handle add members request.
     ///
     /// Adds members to a room using their serialized `KeyPackages`.
@@ -677,7 +2033,7 @@ impl<E: Environment> Client<E> {
         let mls_actions = room
             .mls_group
             .add_members_from_bytes(key_packages_bytes)
-            .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+            .map_err(ClientError::mls(room_id))?;
 
         Ok(self.convert_mls_actions(room_id, mls_actions))
     }
@@ -688,10 +2044,8 @@ impl<E: Environment> Client<E> {
         member_ids: &[u64],
     ) -> Result<Vec<ClientAction>, ClientError> {
         let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
-        let mls_actions = room
-            .mls_group
-            .remove_members(member_ids)
-            .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let mls_actions =
+            room.mls_group.remove_members(member_ids).map_err(ClientError::mls(room_id))?;
 
         Ok(self.convert_mls_actions(room_id, mls_actions))
     }
@@ -700,13 +2054,7 @@ impl<E: Environment> Client<E> {
     ///
     /// Generates a `KeyPackage` and sends it to the server registry.
     fn handle_publish_key_package(&mut self) -> Result<Vec<ClientAction>, ClientError> {
-        let (kp_bytes, hash_ref) = self.generate_key_package()?;
-
-        let payload = KeyPackagePublishRequest { key_package_bytes: kp_bytes, hash_ref };
-
-        let frame = Payload::KeyPackagePublish(payload)
-            .into_frame(FrameHeader::new(Opcode::KeyPackagePublish))
-            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })?;
+        let frame = self.key_package_publish_frame()?;
 
         Ok(vec![
             ClientAction::Send(frame),
@@ -715,6 +2063,46 @@ impl<E: Environment> Client<E> {
         ])
     }
 
+    /// Handle a low-stock notice for our `KeyPackage` pool.
+    ///
+    /// Publishes enough fresh `KeyPackages` to refill the pool to the
+    /// server's target.
+    fn handle_key_package_low_stock(
+        &mut self,
+        frame: &Frame,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let notice: KeyPackageLowStockPayload = ciborium::de::from_reader(&frame.payload[..])
+            .map_err(|e| ClientError::InvalidFrame {
+                reason: format!("Failed to decode KeyPackageLowStock: {e}"),
+            })?;
+
+        let wanted = notice.target.saturating_sub(notice.remaining).min(MAX_KEY_PACKAGE_REPLENISH);
+        let mut actions = Vec::with_capacity(wanted as usize + 2);
+        for _ in 0..wanted {
+            actions.push(ClientAction::Send(self.key_package_publish_frame()?));
+        }
+
+        actions.push(ClientAction::Log {
+            message: format!(
+                "KeyPackage pool low ({} left), published {wanted} more",
+                notice.remaining
+            ),
+        });
+        if wanted > 0 {
+            actions.push(ClientAction::KeyPackagePublished);
+        }
+        Ok(actions)
+    }
+
+    /// Generate a `KeyPackage` and wrap it in a publish frame.
+    fn key_package_publish_frame(&mut self) -> Result<Frame, ClientError> {
+        let (key_package_bytes, hash_ref) = self.generate_key_package()?;
+
+        Payload::KeyPackagePublish(KeyPackagePublishRequest { key_package_bytes, hash_ref })
+            .into_frame(FrameHeader::new(Opcode::KeyPackagePublish))
+            .map_err(|e| ClientError::InvalidFrame { reason: e.to_string() })
+    }
+
     /// Handle fetch and add member request.
     ///
     /// Sends a `KeyPackage` fetch request for the specified user.
@@ -863,24 +2251,24 @@ impl<E: Environment> Client<E> {
             });
         }
 
-        let member_id = self.identity.sender_id;
-
-        let (mls_group, mls_actions) = MlsGroup::join_from_external(
+        let (mut mls_group, mls_actions) = MlsGroup::join_from_external_with_credential(
             self.env.clone(),
             room_id,
-            member_id,
+            &self.identity.credential,
             &payload.group_info_bytes,
         )
-        .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        .map_err(ClientError::mls(room_id))?;
+        mls_group.set_credential_verifier(Arc::clone(&self.credential_verifier));
 
         let sender_keys = self.initialize_sender_keys(&mls_group)?;
         let my_leaf_index = mls_group.own_leaf_index();
         let epoch = mls_group.epoch();
 
-        let initial_state =
-            mls_group.export_state().map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+        let initial_state = mls_group.export_state().map_err(ClientError::mls(room_id))?;
 
-        let room_state = RoomState { mls_group, sender_keys, my_leaf_index };
+        let mut room_state =
+            RoomState::new(mls_group, sender_keys, my_leaf_index, &self.config, self.env.now());
+        let members_changed = room_state.refresh_members(room_id);
         self.rooms.insert(room_id, room_state);
 
         let mut actions = self.convert_mls_actions(room_id, mls_actions);
@@ -893,6 +2281,7 @@ impl<E: Environment> Client<E> {
         }));
 
         actions.push(ClientAction::RoomJoined { room_id, epoch });
+        actions.extend(members_changed);
 
         Ok(actions)
     }
@@ -905,6 +2294,7 @@ impl<E: Environment> Client<E> {
     /// `RequestSync` actions.
     fn handle_tick(&mut self, now: E::Instant) -> Result<Vec<ClientAction>, ClientError> {
         let mut actions = Vec::new();
+        let now_secs = self.env.wall_clock_secs();
 
         let stale_adds: Vec<(RoomId, u64)> = self
             .pending_adds
@@ -924,12 +2314,28 @@ impl<E: Environment> Client<E> {
             }
         }
 
+        let uncommitted: Vec<RoomId> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.leaving_since.is_some_and(|since| now - since > LEAVE_TIMEOUT))
+            .map(|(&room_id, _)| room_id)
+            .collect();
+        for room_id in uncommitted {
+            // Nobody committed the leave, so the proposal may have been lost.
+            // We stay in the group until someone removes us
+            let Some(room) = self.rooms.get_mut(&room_id) else { continue };
+            match room.propose_leave(room_id, now) {
+                Ok(mls_actions) => actions.extend(self.convert_mls_actions(room_id, mls_actions)),
+                Err(e) => actions.push(ClientAction::Log {
+                    message: format!("Failed to propose leaving room {room_id:x} again: {e}"),
+                }),
+            }
+        }
+
         for (&room_id, room) in &mut self.rooms {
             if room.mls_group.is_commit_timeout(now, COMMIT_TIMEOUT) {
                 let current_epoch = room.mls_group.epoch();
-                room.mls_group
-                    .clear_pending_commit()
-                    .map_err(|e| ClientError::Mls { reason: e.to_string() })?;
+                room.mls_group.clear_pending_commit().map_err(ClientError::mls(room_id))?;
 
                 // Sync MLS group to prevent hanging states
                 actions.push(ClientAction::RequestSync {
@@ -943,19 +2349,102 @@ impl<E: Environment> Client<E> {
                     ),
                 });
             }
-        }
 
-        Ok(actions)
-    }
+            for log_index in room.disappearing.take_expired(now_secs) {
+                if let Some(transcript) = room.transcript.as_mut() {
+                    transcript.forget(log_index);
+                }
+                actions.push(ClientAction::MessageExpired { room_id, log_index });
+            }
 
-    fn handle_leave_room(&mut self, room_id: RoomId) -> Result<Vec<ClientAction>, ClientError> {
-        if self.rooms.remove(&room_id).is_none() {
+            while let Some(body) = room.pacer.as_mut().and_then(|pacer| pacer.release(now)) {
+                let sender_id = self.identity.sender_id;
+                match Self::encrypt_message(&self.env, sender_id, room_id, room, &body) {
+                    Ok(frame) => actions.push(ClientAction::Send(frame)),
+                    Err(e) => {
+                        // Keep the message queued for the next tick
+                        if let Some(pacer) = room.pacer.as_mut() {
+                            pacer.unrelease(body);
+                        }
+                        return Err(e);
+                    },
+                }
+            }
+        }
+
+        Ok(actions)
+    }
+
+    /// Propose removing ourselves from a room.
+    ///
+    /// MLS does not let a member commit its own removal, so the room stays
+    /// until another member commits the proposal and the commit reaches us.
+    /// Until then [`Self::is_leaving`] holds and sending is refused. The
+    /// proposal is made again after every epoch change, after
+    /// [`LEAVE_TIMEOUT`], and when asked to leave again, such as after a
+    /// reconnect that may have lost it. The sole member of a room has nobody
+    /// to commit, so it leaves at once.
+    fn handle_leave_room(&mut self, room_id: RoomId) -> Result<Vec<ClientAction>, ClientError> {
+        let now = self.env.now();
+        let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+        if room.mls_group.member_leaf_indices().len() <= 1 {
+            return self.handle_forget_room(room_id);
+        }
+
+        let mls_actions = room.propose_leave(room_id, now)?;
+
+        Ok(self.convert_mls_actions(room_id, mls_actions))
+    }
+
+    /// Drop a room's state without telling the group.
+    fn handle_forget_room(&mut self, room_id: RoomId) -> Result<Vec<ClientAction>, ClientError> {
+        if !self.rooms.contains_key(&room_id) {
             return Err(ClientError::RoomNotFound { room_id });
         }
+        self.remove_room(room_id)?;
 
         Ok(vec![ClientAction::RoomRemoved { room_id, reason: "Left room".to_string() }])
     }
 
+    /// Keep another member's proposal for the commit covering it, and
+    /// commit it if we are the designated committer.
+    ///
+    /// The designated committer is the member at the lowest leaf index other
+    /// than the proposer, so a leaving member is never asked to commit its
+    /// own removal. Any other member's next commit covers the proposal too.
+    fn handle_proposal(
+        &mut self,
+        room_id: RoomId,
+        frame: &Frame,
+    ) -> Result<Vec<ClientAction>, ClientError> {
+        let proposer_id = frame.header.sender_id();
+        if proposer_id == self.identity.sender_id {
+            return Ok(vec![]);
+        }
+
+        let room = self.rooms.get_mut(&room_id).ok_or(ClientError::RoomNotFound { room_id })?;
+        let mut mls_actions =
+            room.mls_group.process_message(frame).map_err(ClientError::mls(room_id))?;
+
+        let group = &room.mls_group;
+        let committer = group
+            .member_leaf_indices()
+            .into_iter()
+            .filter(|&leaf_index| group.member_id_by_leaf_index(leaf_index) != Some(proposer_id))
+            .min();
+        if committer == Some(group.own_leaf_index())
+            && room.leaving_since.is_none()
+            && !group.has_pending_commit()
+            && group.has_pending_proposals()
+        {
+            let commit =
+                room.mls_group.commit_pending_proposals().map_err(ClientError::mls(room_id))?;
+            mls_actions.extend(commit);
+        }
+
+        Ok(self.convert_mls_actions(room_id, mls_actions))
+    }
+
     /// Convert MLS actions to client actions.
     fn convert_mls_actions(
         &self,
@@ -977,6 +2466,8 @@ impl<E: Environment> Client<E> {
                         plaintext,
                         log_index: 0,
                         timestamp: 0,
+                        display_timestamp: 0,
+                        reply_to: None,
                     }
                 },
                 MlsAction::RemoveGroup { reason } => ClientAction::RoomRemoved { room_id, reason },
@@ -995,217 +2486,1293 @@ impl<E: Environment> Client<E> {
                 },
                 MlsAction::Log { message } => ClientAction::Log { message },
             })
-            .collect()
+            .collect()
+    }
+}
+
+/// Room an event targets, for hydration. `None` for client-wide events.
+fn event_room_id<I>(event: &ClientEvent<I>) -> Option<RoomId> {
+    match event {
+        ClientEvent::FrameReceived(frame) => Some(frame.header.room_id()),
+        ClientEvent::Tick { .. }
+        | ClientEvent::PublishKeyPackage
+        | ClientEvent::BlockUser { .. }
+        | ClientEvent::UnblockUser { .. }
+        | ClientEvent::SearchDirectory { .. } => None,
+        ClientEvent::SendMessage { room_id, .. }
+        | ClientEvent::SendAppMessage { room_id, .. }
+        | ClientEvent::EditMessage { room_id, .. }
+        | ClientEvent::DeleteMessage { room_id, .. }
+        | ClientEvent::MarkRead { room_id, .. }
+        | ClientEvent::CreateRoom { room_id }
+        | ClientEvent::JoinRoom { room_id, .. }
+        | ClientEvent::LeaveRoom { room_id }
+        | ClientEvent::ForgetRoom { room_id }
+        | ClientEvent::AddMembers { room_id, .. }
+        | ClientEvent::RemoveMembers { room_id, .. }
+        | ClientEvent::FetchAndAddMember { room_id, .. }
+        | ClientEvent::ExternalJoin { room_id }
+        | ClientEvent::BackfillRoom { room_id, .. }
+        | ClientEvent::SetMessageTtl { room_id, .. }
+        | ClientEvent::SetRoomListing { room_id, .. }
+        | ClientEvent::ReportMessage { room_id, .. }
+        | ClientEvent::InviteUser { room_id, .. } => Some(*room_id),
+    }
+}
+
+/// Map a decrypted application message body to the action delivering it.
+fn body_to_action(room_id: RoomId, sequenced: Sequenced, body: AppMessageBody) -> ClientAction {
+    let Sequenced { sender_id, log_index, timestamp, display_timestamp, .. } = sequenced;
+
+    match body {
+        AppMessageBody::Text(plaintext) => ClientAction::DeliverMessage {
+            room_id,
+            sender_id,
+            plaintext,
+            log_index,
+            timestamp,
+            display_timestamp,
+            reply_to: None,
+        },
+        AppMessageBody::Reply { reply_to, text } => ClientAction::DeliverMessage {
+            room_id,
+            sender_id,
+            plaintext: text,
+            log_index,
+            timestamp,
+            display_timestamp,
+            reply_to: Some(reply_to),
+        },
+        AppMessageBody::Edit { target_log_index, new_text } => ClientAction::MessageEdited {
+            room_id,
+            sender_id,
+            target_log_index,
+            new_plaintext: new_text,
+            log_index,
+            timestamp,
+        },
+        AppMessageBody::Delete { target_log_index } => ClientAction::MessageDeleted {
+            room_id,
+            sender_id,
+            target_log_index,
+            log_index,
+            timestamp,
+        },
+        AppMessageBody::Reaction(reaction) => {
+            ClientAction::DeliverReaction { room_id, sender_id, reaction, log_index, timestamp }
+        },
+        AppMessageBody::Receipt(receipt) => {
+            ClientAction::DeliverReceipt { room_id, sender_id, receipt, log_index }
+        },
+        AppMessageBody::Typing { active } => {
+            ClientAction::DeliverTyping { room_id, sender_id, active }
+        },
+        AppMessageBody::Attachment(attachment) => ClientAction::DeliverAttachment {
+            room_id,
+            sender_id,
+            attachment,
+            log_index,
+            timestamp,
+            display_timestamp,
+        },
+        AppMessageBody::Custom { type_url, bytes } => ClientAction::DeliverCustom {
+            room_id,
+            sender_id,
+            type_url,
+            bytes,
+            log_index,
+            timestamp,
+            display_timestamp,
+        },
+        AppMessageBody::Unsupported => ClientAction::DeliverUnsupported {
+            room_id,
+            sender_id,
+            log_index,
+            timestamp,
+            display_timestamp,
+        },
+    }
+}
+
+fn crypto_to_proto_encrypted(crypto: &CryptoEncryptedMessage) -> EncryptedMessage {
+    EncryptedMessage {
+        epoch: crypto.epoch,
+        sender_index: crypto.sender_index,
+        generation: crypto.generation,
+        nonce: crypto.nonce,
+        ciphertext: crypto.ciphertext.clone(),
+        push_keys: None, // Not implemented yet
+    }
+}
+
+fn proto_to_crypto_encrypted(proto: &EncryptedMessage) -> CryptoEncryptedMessage {
+    CryptoEncryptedMessage {
+        epoch: proto.epoch,
+        sender_index: proto.sender_index,
+        generation: proto.generation,
+        nonce: proto.nonce,
+        ciphertext: proto.ciphertext.clone(),
+    }
+}
+
+fn serialize_encrypted_message(encrypted: &EncryptedMessage) -> Vec<u8> {
+    let mut data = Vec::new();
+    #[allow(clippy::expect_used)]
+    ciborium::ser::into_writer(encrypted, &mut data)
+        .expect("invariant: CBOR serialization to Vec cannot fail (no I/O errors)");
+    data
+}
+
+/// Decode a decrypted message body and its claimed send time. Peers
+/// predating the typed body send raw text, so only bytes that are not an
+/// envelope at all are taken as text; an unreadable body in a current
+/// envelope decodes as [`AppMessageBody::Unsupported`]. A body from a newer
+/// envelope version could be anything, so it is dropped and the reason
+/// returned for logging.
+fn decode_body(
+    room_id: RoomId,
+    log_index: u64,
+    plaintext: Vec<u8>,
+) -> Result<(AppMessageBody, Option<u64>), String> {
+    match AppMessageBody::decode_with_sent_at(&plaintext) {
+        Ok(decoded) => Ok(decoded),
+        Err(ProtocolError::UnsupportedVersion(version)) => Err(format!(
+            "Dropping message in room {room_id:x} at log index {log_index}: envelope version {version} is newer than supported"
+        )),
+        Err(_) => Ok((AppMessageBody::Text(plaintext), None)),
+    }
+}
+
+fn deserialize_encrypted_message(data: &[u8]) -> Result<EncryptedMessage, String> {
+    ciborium::de::from_reader(data).map_err(|e| format!("CBOR decode failed: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use lockframe_core::env::test_utils::MockEnv;
+    use lockframe_crypto::BackupError;
+    use lockframe_proto::{
+        ErrorId,
+        payloads::{
+            ErrorPayload,
+            app::{APP_MESSAGE_VERSION, Reaction, ReplyTo},
+            session::{DirectoryEntry, NoticeKind, RoomGap},
+        },
+    };
+
+    use super::*;
+    use crate::storage::MemoryClientStorage;
+
+    #[test]
+    fn create_client() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let client: Client<MockEnv> = Client::new(env, identity);
+
+        assert_eq!(client.sender_id(), 42);
+        assert_eq!(client.room_count(), 0);
+    }
+
+    #[test]
+    fn directory_search_round_trip() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(42));
+
+        let actions = client
+            .handle(ClientEvent::SearchDirectory { query: "rust".into(), after: Some(7) })
+            .unwrap();
+        let [ClientAction::Send(frame)] = actions.as_slice() else {
+            panic!("expected one frame, got {actions:?}");
+        };
+        let Ok(Payload::DirectorySearch(search)) = Payload::from_frame(frame) else {
+            panic!("expected DirectorySearch");
+        };
+        assert_eq!((search.query.as_str(), search.after), ("rust", Some(7)));
+
+        let entry = DirectoryEntry { room_id: 9, name: "rust".into(), member_count: 4 };
+        let frame =
+            Payload::DirectoryResults(DirectoryResults { rooms: vec![entry.clone()], next: None })
+                .into_frame(FrameHeader::new(Opcode::DirectoryResults))
+                .unwrap();
+        let actions = client.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(matches!(
+            actions.as_slice(),
+            [ClientAction::DirectoryResults { rooms, next: None }] if rooms == &[entry]
+        ));
+
+        // Only rooms we are in can be listed
+        let result = client.handle(ClientEvent::SetRoomListing { room_id: 9, name: None });
+        assert!(matches!(result, Err(ClientError::RoomNotFound { room_id: 9 })));
+    }
+
+    #[test]
+    fn server_notices_are_delivered_as_typed_actions() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(42));
+
+        let notice = ServerNotice {
+            kind: NoticeKind::Maintenance,
+            text: "Restarting at 02:00 UTC".into(),
+            room_id: None,
+        };
+        let frame = Payload::ServerNotice(notice)
+            .into_frame(FrameHeader::new(Opcode::ServerNotice))
+            .unwrap();
+        let actions = client.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(matches!(
+            actions.as_slice(),
+            [ClientAction::ServerNotice { room_id: None, kind: NoticeKind::Maintenance, text }]
+                if text == "Restarting at 02:00 UTC"
+        ));
+    }
+
+    #[test]
+    fn server_errors_surface_with_their_error_id() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(42));
+
+        let error = ErrorPayload::room_not_found(9).with_error_id(ErrorId::new(0x7f3a));
+        let mut frame = Payload::Error(error).into_frame(FrameHeader::new(Opcode::Error)).unwrap();
+        frame.header.set_room_id(9);
+        let err = client.handle(ClientEvent::FrameReceived(frame)).unwrap_err();
+        assert_eq!(err.error_id(), Some(ErrorId::new(0x7f3a)));
+        assert_eq!(err.room_id(), Some(9));
+    }
+
+    #[test]
+    fn signed_invites_reach_the_invitee() {
+        let env = MockEnv::with_crypto_rng();
+        let mut alice = Client::new(env.clone(), ClientIdentity::new(1));
+        let mut bob = Client::new(env, ClientIdentity::new(2));
+        alice.handle(ClientEvent::CreateRoom { room_id: 9 }).unwrap();
+
+        let ttl = Duration::from_mins(5);
+        let actions =
+            alice.handle(ClientEvent::InviteUser { room_id: 9, user_id: 2, ttl }).unwrap();
+        let [ClientAction::Send(frame)] = actions.as_slice() else {
+            panic!("expected one frame, got {actions:?}");
+        };
+
+        let actions = bob.handle(ClientEvent::FrameReceived(frame.clone())).unwrap();
+        assert!(matches!(actions.as_slice(), [ClientAction::InviteReceived {
+            room_id: 9,
+            inviter: 1,
+            ..
+        }]));
+
+        // Altering the invite breaks its signature
+        let Ok(Payload::Invite(mut invite)) = Payload::from_frame(frame) else {
+            panic!("expected Invite");
+        };
+        assert_eq!(invite.invitee, 2);
+        invite.expires_at += 3600;
+        let forged = Payload::Invite(invite).into_frame(frame.header).unwrap();
+        let result = bob.handle(ClientEvent::FrameReceived(forged));
+        assert!(matches!(result, Err(ClientError::InvalidFrame { .. })));
+    }
+
+    #[test]
+    fn create_room() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let room_id = 0x1234_5678_9abc_def0_u128;
+        let actions = client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        assert!(client.is_member(room_id));
+        assert_eq!(client.epoch(room_id), Some(0));
+        assert!(!actions.is_empty());
+    }
+
+    #[test]
+    fn create_duplicate_room_fails() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        let result = client.handle(ClientEvent::CreateRoom { room_id });
+        assert!(matches!(result, Err(ClientError::RoomAlreadyExists { .. })));
+    }
+
+    #[test]
+    fn send_message_to_unknown_room_fails() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let result = client.handle(ClientEvent::SendMessage {
+            room_id: 0x9999_u128,
+            plaintext: b"hello".to_vec(),
+        });
+
+        assert!(matches!(result, Err(ClientError::RoomNotFound { .. })));
+    }
+
+    #[test]
+    fn leave_room() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+        assert!(client.is_member(room_id));
+
+        let actions = client.handle(ClientEvent::LeaveRoom { room_id }).unwrap();
+        assert!(!client.is_member(room_id));
+        assert!(matches!(actions[0], ClientAction::RoomRemoved { .. }));
+    }
+
+    #[test]
+    fn leave_unknown_room_fails() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let result = client.handle(ClientEvent::LeaveRoom { room_id: 0x9999_u128 });
+        assert!(matches!(result, Err(ClientError::RoomNotFound { .. })));
+    }
+
+    #[test]
+    fn send_message_produces_encrypted_frame() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        let actions = client
+            .handle(ClientEvent::SendMessage { room_id, plaintext: b"Hello, World!".to_vec() })
+            .unwrap();
+
+        // Should produce a Send action with encrypted frame
+        assert_eq!(actions.len(), 1);
+        match &actions[0] {
+            ClientAction::Send(frame) => {
+                assert_eq!(frame.header.opcode_enum(), Some(Opcode::AppMessage));
+                assert_eq!(frame.header.room_id(), room_id);
+                // Payload should be encrypted (not plaintext)
+                assert!(!frame.payload.is_empty());
+                assert_ne!(frame.payload.as_ref(), b"Hello, World!");
+            },
+            _ => {
+                panic!("Expected Send action")
+            },
+        }
+    }
+
+    #[test]
+    fn app_message_with_invalid_signature_is_rejected() {
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(99); // Different sender
+        header.set_epoch(0);
+        let frame = Frame::new(header, Vec::<u8>::new());
+
+        let result = client.handle(ClientEvent::FrameReceived(frame));
+        assert!(matches!(result, Err(ClientError::InvalidFrame { .. })));
+    }
+
+    #[test]
+    fn encrypt_decrypt_roundtrip_same_client() {
+        // NOTE: This test demonstrates a known limitation.
+        // In the current sender key design, sender and receiver share the same ratchet.
+        // When you send (encrypt), your ratchet advances. When you receive your own
+        // message back, decryption fails because the ratchet already moved forward.
+        //
+        // In production:
+        // 1. Servers shouldn't echo your own messages back to you
+        // 2. Or: We need separate send/receive ratchets per member
+        //
+        // For now, we test that encryption works and produces valid output,
+        // and that the serialization roundtrips correctly.
+
+        let env = MockEnv::new();
+        let identity = ClientIdentity::new(42);
+        let mut client = Client::new(env, identity);
+
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        // Send a message
+        let plaintext = b"Secret message";
+        let actions = client
+            .handle(ClientEvent::SendMessage { room_id, plaintext: plaintext.to_vec() })
+            .unwrap();
+
+        let ClientAction::Send(frame) = &actions[0] else {
+            panic!("Expected Send action");
+        };
+        let frame = frame.clone();
+
+        // Verify the encrypted payload can be deserialized
+        let encrypted = deserialize_encrypted_message(&frame.payload).unwrap();
+        assert_eq!(encrypted.epoch, 0);
+        assert_eq!(encrypted.sender_index, 0); // Creator is leaf 0
+        assert_eq!(encrypted.generation, 0); // First message
+        assert!(!encrypted.ciphertext.is_empty());
+
+        // Verify sender's ratchet advanced
+        let room = client.rooms.get(&room_id).unwrap();
+        assert_eq!(room.sender_keys.generation(0), Some(1)); // Now at gen 1
+    }
+
+    /// Alice creates a room and adds Bob, both at epoch 1.
+    fn two_member_room(room_id: RoomId) -> (Client<MockEnv>, Client<MockEnv>) {
+        let mut alice = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(1));
+        let mut bob = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(2));
+
+        alice.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+        let (key_package, _) = bob.generate_key_package().unwrap();
+
+        let actions = alice
+            .handle(ClientEvent::AddMembers { room_id, key_packages: vec![key_package] })
+            .unwrap();
+
+        let mut welcome = None;
+        for action in actions {
+            let ClientAction::Send(frame) = action else { continue };
+            match frame.header.opcode_enum() {
+                Some(Opcode::Commit) => {
+                    alice.handle(ClientEvent::FrameReceived(frame)).unwrap();
+                },
+                Some(Opcode::Welcome) => welcome = Some(frame.payload.to_vec()),
+                _ => {},
+            }
+        }
+
+        bob.handle(ClientEvent::JoinRoom { room_id, welcome: welcome.unwrap() }).unwrap();
+        assert_eq!(alice.epoch(room_id), bob.epoch(room_id));
+
+        (alice, bob)
+    }
+
+    fn sequenced_message(alice: &mut Client<MockEnv>, room_id: RoomId, log_index: u64) -> Frame {
+        let actions = alice
+            .handle(ClientEvent::SendMessage { room_id, plaintext: b"hello".to_vec() })
+            .unwrap();
+        let ClientAction::Send(mut frame) = actions.into_iter().next().unwrap() else {
+            panic!("Expected Send action");
+        };
+        frame.header.set_log_index(log_index);
+        frame
+    }
+
+    /// An application message from `sender` carrying `envelope` as its
+    /// plaintext, sequenced at log index 7.
+    fn envelope_frame(
+        sender: &mut Client<MockEnv>,
+        room_id: RoomId,
+        envelope: &ciborium::Value,
+    ) -> Frame {
+        let mut plaintext = Vec::new();
+        ciborium::ser::into_writer(envelope, &mut plaintext).unwrap();
+
+        let sender_id = sender.sender_id();
+        let room = sender.rooms.get_mut(&room_id).unwrap();
+        let encrypted = room
+            .sender_keys
+            .encrypt(room.my_leaf_index, &plaintext, [0; NONCE_RANDOM_SIZE])
+            .unwrap();
+        let payload = serialize_encrypted_message(&crypto_to_proto_encrypted(&encrypted));
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(sender_id);
+        header.set_epoch(room.mls_group.epoch());
+        header.set_payload_size(payload.len() as u32);
+        header.set_log_index(7);
+        room.mls_group.sign_frame_header(&mut header);
+        Frame::new(header, payload)
+    }
+
+    /// The frame with `opcode` among `actions`.
+    fn sent(actions: &[ClientAction], opcode: Opcode) -> Frame {
+        actions
+            .iter()
+            .find_map(|action| match action {
+                ClientAction::Send(frame) if frame.header.opcode_enum() == Some(opcode) => {
+                    Some(frame.clone())
+                },
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn leaving_waits_for_another_member_to_commit() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        let actions = bob.handle(ClientEvent::LeaveRoom { room_id }).unwrap();
+        let proposal = sent(&actions, Opcode::Proposal);
+        assert!(bob.is_member(room_id));
+        assert!(bob.is_leaving(room_id));
+        let send = ClientEvent::SendMessage { room_id, plaintext: b"bye".to_vec() };
+        assert!(bob.handle(send).is_err());
+
+        // Alice is the only member left to commit it
+        let actions = alice.handle(ClientEvent::FrameReceived(proposal)).unwrap();
+        let commit = sent(&actions, Opcode::Commit);
+        alice.handle(ClientEvent::FrameReceived(commit.clone())).unwrap();
+        assert_eq!(alice.member_ids(room_id), Some(vec![1]));
+
+        let actions = bob.handle(ClientEvent::FrameReceived(commit)).unwrap();
+        assert!(actions.iter().any(|action| matches!(action, ClientAction::RoomRemoved { .. })));
+        assert!(!bob.is_member(room_id));
+    }
+
+    #[test]
+    fn members_follow_the_tree_with_their_joined_epoch() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+        let joined = |client: &Client<MockEnv>| -> Vec<_> {
+            let members = client.members(room_id);
+            members.iter().map(|m| (m.user_id, m.leaf_index, m.joined_epoch)).collect()
+        };
+        assert_eq!(joined(&alice), vec![(1, 0, 0), (2, 1, 1)]);
+        assert_eq!(joined(&bob), vec![(1, 0, 1), (2, 1, 1)]);
+
+        let actions = bob.handle(ClientEvent::LeaveRoom { room_id }).unwrap();
+        let proposal = sent(&actions, Opcode::Proposal);
+        let actions = alice.handle(ClientEvent::FrameReceived(proposal)).unwrap();
+        let commit = sent(&actions, Opcode::Commit);
+        let actions = alice.handle(ClientEvent::FrameReceived(commit)).unwrap();
+
+        let changed = actions.iter().find_map(|action| match action {
+            ClientAction::MembersChanged { members, .. } => Some(members.clone()),
+            _ => None,
+        });
+        assert_eq!(changed, Some(alice.members(room_id)));
+        assert_eq!(joined(&alice), vec![(1, 0, 0)]);
+    }
+
+    #[test]
+    fn uncommitted_leave_is_proposed_again() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+        let start = bob.env.now();
+
+        // The first proposal never reaches Alice
+        bob.handle(ClientEvent::LeaveRoom { room_id }).unwrap();
+        let now = start + LEAVE_TIMEOUT;
+        let actions = bob.handle(ClientEvent::Tick { now }).unwrap();
+        assert!(!actions.iter().any(|action| matches!(action, ClientAction::Send(_))));
+
+        let now = start + LEAVE_TIMEOUT + Duration::from_secs(1);
+        let actions = bob.handle(ClientEvent::Tick { now }).unwrap();
+        assert!(!actions.iter().any(|action| matches!(action, ClientAction::RoomRemoved { .. })));
+        assert!(bob.is_leaving(room_id));
+
+        let actions = alice.handle(ClientEvent::FrameReceived(sent(&actions, Opcode::Proposal)));
+        let commit = sent(&actions.unwrap(), Opcode::Commit);
+        let actions = bob.handle(ClientEvent::FrameReceived(commit)).unwrap();
+        assert!(actions.iter().any(|action| matches!(action, ClientAction::RoomRemoved { .. })));
+        assert!(!bob.is_member(room_id));
+    }
+
+    #[test]
+    fn leave_is_proposed_again_after_an_epoch_change() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+        let mut carol = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(3));
+
+        // Alice commits an add before the proposal reaches her, which drops it
+        let actions = bob.handle(ClientEvent::LeaveRoom { room_id }).unwrap();
+        let stale = sent(&actions, Opcode::Proposal);
+        let (key_package, _) = carol.generate_key_package().unwrap();
+        let actions = alice
+            .handle(ClientEvent::AddMembers { room_id, key_packages: vec![key_package] })
+            .unwrap();
+        let commit = sent(&actions, Opcode::Commit);
+        alice.handle(ClientEvent::FrameReceived(commit.clone())).unwrap();
+        assert!(alice.handle(ClientEvent::FrameReceived(stale)).is_err());
+
+        let actions = bob.handle(ClientEvent::FrameReceived(commit)).unwrap();
+        assert!(bob.is_leaving(room_id));
+        let actions = alice.handle(ClientEvent::FrameReceived(sent(&actions, Opcode::Proposal)));
+        let commit = sent(&actions.unwrap(), Opcode::Commit);
+        let actions = bob.handle(ClientEvent::FrameReceived(commit)).unwrap();
+        assert!(actions.iter().any(|action| matches!(action, ClientAction::RoomRemoved { .. })));
+        assert!(!bob.is_member(room_id));
+    }
+
+    #[test]
+    fn redelivered_message_is_dropped() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+        let frame = sequenced_message(&mut alice, room_id, 7);
+
+        let first = bob.handle(ClientEvent::FrameReceived(frame.clone())).unwrap();
+        assert!(first.iter().any(|a| matches!(a, ClientAction::DeliverMessage { .. })));
+
+        let second = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(!second.iter().any(|a| matches!(
+            a,
+            ClientAction::DeliverMessage { .. } | ClientAction::ReplayDetected { .. }
+        )));
+    }
+
+    #[test]
+    fn messages_from_a_newer_envelope_version_are_dropped() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        // Same envelope as today's, but claiming a version from the future
+        let current = AppMessageBody::Text(b"hello".to_vec()).encode().unwrap();
+        let mut envelope: ciborium::Value = ciborium::de::from_reader(current.as_slice()).unwrap();
+        for (key, value) in envelope.as_map_mut().unwrap() {
+            if key.as_text() == Some("version") {
+                *value = ciborium::Value::from(APP_MESSAGE_VERSION + 1);
+            }
+        }
+        let frame = envelope_frame(&mut alice, room_id, &envelope);
+
+        let actions = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(!actions.iter().any(|a| matches!(a, ClientAction::DeliverMessage { .. })));
+        assert!(
+            actions
+                .iter()
+                .any(|a| matches!(a, ClientAction::Log { message } if message.contains("newer")))
+        );
+    }
+
+    #[test]
+    fn unknown_kinds_of_message_are_delivered_as_unsupported() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        // Today's envelope around a kind of body this build does not know
+        let current = AppMessageBody::Text(b"hello".to_vec()).encode().unwrap();
+        let mut envelope: ciborium::Value = ciborium::de::from_reader(current.as_slice()).unwrap();
+        for (key, value) in envelope.as_map_mut().unwrap() {
+            if key.as_text() == Some("body") {
+                *value = ciborium::Value::Map(vec![("Poll".into(), "lunch?".into())]);
+            }
+        }
+        let frame = envelope_frame(&mut alice, room_id, &envelope);
+
+        let actions = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(!actions.iter().any(|a| matches!(a, ClientAction::DeliverMessage { .. })));
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::DeliverUnsupported {
+            sender_id: 1,
+            log_index: 7,
+            ..
+        })));
+    }
+
+    #[test]
+    fn attachments_are_delivered_and_open() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        let attachment = alice.seal_attachment("cat.png".to_string(), b"meow");
+        let body = AppMessageBody::Attachment(attachment.clone());
+        let actions = alice.handle(ClientEvent::SendAppMessage { room_id, body }).unwrap();
+        let mut frame = sent(&actions, Opcode::AppMessage);
+        frame.header.set_log_index(7);
+
+        let actions = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        let delivered = actions
+            .into_iter()
+            .find_map(|action| match action {
+                ClientAction::DeliverAttachment { attachment, log_index: 7, .. } => {
+                    Some(attachment)
+                },
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(delivered, attachment);
+        assert_eq!(crate::open_attachment(&delivered).unwrap(), b"meow");
+    }
+
+    #[test]
+    fn resequenced_message_is_flagged_as_replay() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+        let frame = sequenced_message(&mut alice, room_id, 7);
+
+        bob.handle(ClientEvent::FrameReceived(frame.clone())).unwrap();
+
+        let mut replayed = frame;
+        replayed.header.set_log_index(12);
+        let actions = bob.handle(ClientEvent::FrameReceived(replayed)).unwrap();
+
+        assert!(!actions.iter().any(|a| matches!(a, ClientAction::DeliverMessage { .. })));
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::ReplayDetected {
+            sender_id: 1,
+            generation: 0,
+            original_log_index: 7,
+            replayed_log_index: 12,
+            ..
+        })));
+    }
+
+    #[test]
+    fn edit_and_delete_are_delivered_as_typed_actions() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        let actions = alice
+            .handle(ClientEvent::EditMessage {
+                room_id,
+                target_log_index: 3,
+                new_plaintext: b"fixed".to_vec(),
+            })
+            .unwrap();
+        let ClientAction::Send(edit) = &actions[0] else { panic!("Expected Send action") };
+
+        let actions =
+            alice.handle(ClientEvent::DeleteMessage { room_id, target_log_index: 3 }).unwrap();
+        let ClientAction::Send(delete) = &actions[0] else { panic!("Expected Send action") };
+
+        let actions = bob.handle(ClientEvent::FrameReceived(edit.clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(
+            a,
+            ClientAction::MessageEdited { sender_id: 1, target_log_index: 3, new_plaintext, .. }
+                if new_plaintext == b"fixed"
+        )));
+
+        let actions = bob.handle(ClientEvent::FrameReceived(delete.clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::MessageDeleted {
+            sender_id: 1,
+            target_log_index: 3,
+            ..
+        })));
     }
-}
 
-fn crypto_to_proto_encrypted(crypto: &CryptoEncryptedMessage) -> EncryptedMessage {
-    EncryptedMessage {
-        epoch: crypto.epoch,
-        sender_index: crypto.sender_index,
-        generation: crypto.generation,
-        nonce: crypto.nonce,
-        ciphertext: crypto.ciphertext.clone(),
-        push_keys: None, // Not implemented yet
+    #[test]
+    fn reactions_and_typing_are_delivered_as_typed_actions() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        let reaction = Reaction { message_log_index: 3, content: "+1".to_string(), add: true };
+        let actions = alice
+            .handle(ClientEvent::SendAppMessage {
+                room_id,
+                body: AppMessageBody::Reaction(reaction.clone()),
+            })
+            .unwrap();
+        let ClientAction::Send(reaction_frame) = &actions[0] else {
+            panic!("Expected Send action")
+        };
+
+        let actions = alice
+            .handle(ClientEvent::SendAppMessage {
+                room_id,
+                body: AppMessageBody::Typing { active: true },
+            })
+            .unwrap();
+        let ClientAction::Send(typing_frame) = &actions[0] else { panic!("Expected Send action") };
+
+        let actions = bob.handle(ClientEvent::FrameReceived(reaction_frame.clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(
+            a,
+            ClientAction::DeliverReaction { sender_id: 1, reaction: r, .. } if *r == reaction
+        )));
+
+        let actions = bob.handle(ClientEvent::FrameReceived(typing_frame.clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::DeliverTyping {
+            sender_id: 1,
+            active: true,
+            ..
+        })));
     }
-}
 
-fn proto_to_crypto_encrypted(proto: &EncryptedMessage) -> CryptoEncryptedMessage {
-    CryptoEncryptedMessage {
-        epoch: proto.epoch,
-        sender_index: proto.sender_index,
-        generation: proto.generation,
-        nonce: proto.nonce,
-        ciphertext: proto.ciphertext.clone(),
+    #[test]
+    fn replies_are_delivered_as_messages_with_their_target() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        let reply_to = ReplyTo::new(3, b"lunch?");
+        let body = AppMessageBody::Reply { reply_to, text: b"sure".to_vec() };
+        let actions = alice.handle(ClientEvent::SendAppMessage { room_id, body }).unwrap();
+        let ClientAction::Send(frame) = &actions[0] else { panic!("Expected Send action") };
+
+        let actions = bob.handle(ClientEvent::FrameReceived(frame.clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(
+            a,
+            ClientAction::DeliverMessage { plaintext, reply_to: Some(r), .. }
+                if plaintext == b"sure" && *r == reply_to
+        )));
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::UnreadCountChanged { .. })));
     }
-}
 
-fn serialize_encrypted_message(encrypted: &EncryptedMessage) -> Vec<u8> {
-    let mut data = Vec::new();
-    #[allow(clippy::expect_used)]
-    ciborium::ser::into_writer(encrypted, &mut data)
-        .expect("invariant: CBOR serialization to Vec cannot fail (no I/O errors)");
-    data
-}
+    #[test]
+    fn typing_is_sent_as_an_unacknowledged_ephemeral_frame() {
+        let room_id = 0x1234_u128;
+        let (mut alice, _bob) = two_member_room(room_id);
 
-fn deserialize_encrypted_message(data: &[u8]) -> Result<EncryptedMessage, String> {
-    ciborium::de::from_reader(data).map_err(|e| format!("CBOR decode failed: {e}"))
-}
+        let actions = alice
+            .handle(ClientEvent::SendAppMessage {
+                room_id,
+                body: AppMessageBody::Typing { active: true },
+            })
+            .unwrap();
+        let ClientAction::Send(typing_frame) = &actions[0] else { panic!("Expected Send action") };
+        assert!(typing_frame.header.flags().contains(FrameFlags::EPHEMERAL));
+        assert_eq!(typing_frame.header.request_id(), 0);
+        assert!(typing_frame.header.expires_at().is_some());
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+        // The server's echo acknowledges nothing and expires nothing
+        assert!(alice.handle(ClientEvent::FrameReceived(typing_frame.clone())).unwrap().is_empty());
+    }
 
-    use lockframe_core::env::test_utils::MockEnv;
+    #[test]
+    fn lost_broadcasts_are_refetched_by_range() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+        let frames: Vec<Frame> =
+            (4..8).map(|log_index| sequenced_message(&mut alice, room_id, log_index)).collect();
 
-    use super::*;
+        assert!(
+            bob.handle(ClientEvent::FrameReceived(frames[0].clone()))
+                .unwrap()
+                .iter()
+                .all(|a| !matches!(a, ClientAction::GapDetected { .. } | ClientAction::Send(_)))
+        );
 
-    #[test]
-    fn create_client() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let client: Client<MockEnv> = Client::new(env, identity);
+        // 5 and 6 are lost on the way
+        let actions = bob.handle(ClientEvent::FrameReceived(frames[3].clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::GapDetected {
+            from_log_index: 5,
+            until_log_index: 7,
+            ..
+        })));
+        let repair = actions
+            .iter()
+            .find_map(|a| match a {
+                ClientAction::Send(frame) => Some(Payload::from_frame(frame).unwrap()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(repair, Payload::SyncRequest(SyncRequest::new(5, 2)));
 
-        assert_eq!(client.sender_id(), 42);
-        assert_eq!(client.room_count(), 0);
+        for frame in &frames[1..3] {
+            let actions = bob.handle(ClientEvent::FrameReceived(frame.clone())).unwrap();
+            assert!(actions.iter().any(|a| matches!(a, ClientAction::DeliverMessage { .. })));
+            assert!(!actions.iter().any(|a| matches!(a, ClientAction::GapDetected { .. })));
+        }
     }
 
     #[test]
-    fn create_room() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+    fn mark_read_clears_unread_and_sends_receipt() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        for log_index in [4, 5] {
+            let frame = sequenced_message(&mut alice, room_id, log_index);
+            let actions = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+            assert!(
+                actions.iter().any(|a| matches!(a, ClientAction::UnreadCountChanged {
+                    read_up_to: None,
+                    ..
+                }))
+            );
+        }
 
-        let room_id = 0x1234_5678_9abc_def0_u128;
-        let actions = client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+        let actions = bob.handle(ClientEvent::MarkRead { room_id, log_index: 4 }).unwrap();
+        assert!(matches!(actions[0], ClientAction::UnreadCountChanged {
+            unread: 1,
+            read_up_to: Some(4),
+            ..
+        }));
+        let ClientAction::Send(receipt_frame) = &actions[1] else { panic!("Expected Send action") };
 
-        assert!(client.is_member(room_id));
-        assert_eq!(client.epoch(room_id), Some(0));
-        assert!(!actions.is_empty());
+        let actions = alice.handle(ClientEvent::FrameReceived(receipt_frame.clone())).unwrap();
+        assert!(actions.iter().any(|a| matches!(
+            a,
+            ClientAction::DeliverReceipt { sender_id: 2, receipt: r, .. }
+                if r.kind == ReceiptType::Read && r.message_log_index == 4
+        )));
+
+        let stale = bob.handle(ClientEvent::MarkRead { room_id, log_index: 3 }).unwrap();
+        assert!(stale.is_empty());
     }
 
     #[test]
-    fn create_duplicate_room_fails() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+    fn messages_from_blocked_users_are_hidden() {
+        let room_id = 0x1234_u128;
+        let (mut alice, mut bob) = two_member_room(room_id);
+
+        bob.handle(ClientEvent::BlockUser { user_id: 1 }).unwrap();
+        let frame = sequenced_message(&mut alice, room_id, 4);
+        let actions = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(matches!(actions.as_slice(), [ClientAction::MessageHidden {
+            sender_id: 1,
+            log_index: 4,
+            ..
+        }]));
+
+        bob.handle(ClientEvent::UnblockUser { user_id: 1 }).unwrap();
+        let frame = sequenced_message(&mut alice, room_id, 5);
+        let actions = bob.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::DeliverMessage { .. })));
+    }
 
+    #[test]
+    fn block_list_is_persisted() {
+        let storage = MemoryClientStorage::new();
+        let mut client = Client::with_storage(
+            MockEnv::new(),
+            ClientIdentity::new(1),
+            ClientConfig::default(),
+            Box::new(storage.clone()),
+        )
+        .unwrap();
+        client.handle(ClientEvent::BlockUser { user_id: 7 }).unwrap();
+
+        let restored = Client::with_storage(
+            MockEnv::new(),
+            ClientIdentity::new(1),
+            ClientConfig::default(),
+            Box::new(storage),
+        )
+        .unwrap();
+        assert!(restored.is_blocked(7));
+        assert_eq!(restored.blocked_users().collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn backfill_paginates_and_reports_progress() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(1));
         let room_id = 0x1234_u128;
         client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
 
-        let result = client.handle(ClientEvent::CreateRoom { room_id });
-        assert!(matches!(result, Err(ClientError::RoomAlreadyExists { .. })));
+        let actions =
+            client.handle(ClientEvent::BackfillRoom { room_id, until_log_index: 150 }).unwrap();
+        assert!(matches!(actions[0], ClientAction::BackfillProgress {
+            fetched: 0,
+            total: 150,
+            ..
+        }));
+        let ClientAction::Send(request) = &actions[1] else { panic!("Expected Send action") };
+        assert_eq!(request.header.opcode_enum(), Some(Opcode::SyncRequest));
+        assert_eq!(request.header.room_id(), room_id);
+
+        let response = SyncResponse {
+            frames: Vec::new(),
+            has_more: false,
+            server_epoch: 0,
+            next_log_index: None,
+        };
+        let mut frame = Payload::SyncResponse(response)
+            .into_frame(FrameHeader::new(Opcode::SyncResponse))
+            .unwrap();
+        frame.header.set_room_id(room_id);
+
+        let actions = client.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::BackfillProgress {
+            fetched: 0,
+            total: 0,
+            ..
+        })));
+        assert!(!actions.iter().any(|a| matches!(a, ClientAction::Send(_))));
     }
 
     #[test]
-    fn send_message_to_unknown_room_fails() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+    fn low_stock_notice_replenishes_key_packages() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(1));
 
-        let result = client.handle(ClientEvent::SendMessage {
-            room_id: 0x9999_u128,
-            plaintext: b"hello".to_vec(),
-        });
+        let frame =
+            Payload::KeyPackageLowStock(KeyPackageLowStockPayload { remaining: 1, target: 4 })
+                .into_frame(FrameHeader::new(Opcode::KeyPackageLowStock))
+                .unwrap();
+        let actions = client.handle(ClientEvent::FrameReceived(frame)).unwrap();
 
-        assert!(matches!(result, Err(ClientError::RoomNotFound { .. })));
+        let published = actions
+            .iter()
+            .filter(|a| {
+                matches!(a, ClientAction::Send(f) if f.header.opcode_enum() == Some(Opcode::KeyPackagePublish))
+            })
+            .count();
+        assert_eq!(published, 3);
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::KeyPackagePublished)));
     }
 
     #[test]
-    fn leave_room() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+    fn synced_tombstone_reports_truncated_history() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(1));
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        let mut header = FrameHeader::new(Opcode::HistoryTruncated);
+        header.set_room_id(room_id);
+        header.set_log_index(499);
+        let tombstone = Payload::HistoryTruncated(HistoryTruncated { first_log_index: 500 })
+            .into_frame(header)
+            .unwrap();
+        let mut tombstone_bytes = Vec::new();
+        tombstone.encode(&mut tombstone_bytes).unwrap();
+
+        let response = SyncResponse {
+            frames: vec![tombstone_bytes],
+            has_more: false,
+            server_epoch: 0,
+            next_log_index: None,
+        };
+        let mut frame = Payload::SyncResponse(response)
+            .into_frame(FrameHeader::new(Opcode::SyncResponse))
+            .unwrap();
+        frame.header.set_room_id(room_id);
+
+        let actions = client.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        assert!(
+            actions
+                .iter()
+                .any(|a| matches!(a, ClientAction::HistoryTruncated { first_log_index: 500, .. }))
+        );
+    }
 
+    #[test]
+    fn closed_room_is_removed() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(1));
         let room_id = 0x1234_u128;
         client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
-        assert!(client.is_member(room_id));
 
-        let actions = client.handle(ClientEvent::LeaveRoom { room_id }).unwrap();
+        let mut header = FrameHeader::new(Opcode::CloseRoom);
+        header.set_room_id(room_id);
+        let close = Payload::CloseRoom(CloseRoom { reason: "archived".into(), moderator_id: 2 })
+            .into_frame(header)
+            .unwrap();
+
+        let actions = client.handle(ClientEvent::FrameReceived(close.clone())).unwrap();
+        assert!(matches!(
+            &actions[..],
+            [ClientAction::RoomRemoved { reason, .. }] if reason == "Room closed: archived"
+        ));
         assert!(!client.is_member(room_id));
-        assert!(matches!(actions[0], ClientAction::RoomRemoved { .. }));
+
+        // A repeated tombstone, e.g. from sync, is ignored
+        assert!(client.handle(ClientEvent::FrameReceived(close)).unwrap().is_empty());
     }
 
     #[test]
-    fn leave_unknown_room_fails() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+    fn lagged_behind_resyncs_known_rooms() {
+        let mut client = Client::new(MockEnv::new(), ClientIdentity::new(1));
+        let room_id = 0x1234_u128;
+        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
 
-        let result = client.handle(ClientEvent::LeaveRoom { room_id: 0x9999_u128 });
-        assert!(matches!(result, Err(ClientError::RoomNotFound { .. })));
+        let notice = LaggedBehind {
+            dropped: 5,
+            rooms: vec![RoomGap { room_id, from_log_index: 40 }, RoomGap {
+                room_id: 0x9999,
+                from_log_index: 7,
+            }],
+        };
+        let frame = Payload::LaggedBehind(notice)
+            .into_frame(FrameHeader::new(Opcode::LaggedBehind))
+            .unwrap();
+
+        let actions = client.handle(ClientEvent::FrameReceived(frame)).unwrap();
+        let requests: Vec<_> = actions
+            .iter()
+            .filter_map(|a| match a {
+                ClientAction::Send(f) => {
+                    Some((f.header.room_id(), Payload::from_frame(f).unwrap()))
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(requests, vec![(
+            room_id,
+            Payload::SyncRequest(SyncRequest::new(40, BACKFILL_BATCH))
+        )]);
     }
 
     #[test]
-    fn send_message_produces_encrypted_frame() {
+    fn paced_sends_are_queued_and_released_on_tick() {
         let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+        let config = ClientConfig {
+            pacer: Some(PacerConfig { messages_per_second: 1, burst: 1, max_queued: 1 }),
+            ..ClientConfig::default()
+        };
+        let mut client = Client::with_config(env.clone(), ClientIdentity::new(42), config);
 
         let room_id = 0x1234_u128;
         client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
 
         let actions = client
-            .handle(ClientEvent::SendMessage { room_id, plaintext: b"Hello, World!".to_vec() })
+            .handle(ClientEvent::SendMessage { room_id, plaintext: b"first".to_vec() })
             .unwrap();
+        assert!(matches!(actions.as_slice(), [ClientAction::Send(_)]));
 
-        // Should produce a Send action with encrypted frame
-        assert_eq!(actions.len(), 1);
-        match &actions[0] {
-            ClientAction::Send(frame) => {
-                assert_eq!(frame.header.opcode_enum(), Some(Opcode::AppMessage));
-                assert_eq!(frame.header.room_id(), room_id);
-                // Payload should be encrypted (not plaintext)
-                assert!(!frame.payload.is_empty());
-                assert_ne!(frame.payload.as_ref(), b"Hello, World!");
-            },
-            _ => {
-                panic!("Expected Send action")
-            },
-        }
+        let actions = client
+            .handle(ClientEvent::SendMessage { room_id, plaintext: b"second".to_vec() })
+            .unwrap();
+        assert!(matches!(actions.as_slice(), [ClientAction::Backpressure { queued: 1, .. }]));
+
+        let refused =
+            client.handle(ClientEvent::SendMessage { room_id, plaintext: b"third".to_vec() });
+        assert!(matches!(refused, Err(ClientError::SendQueueFull { queued: 1, .. })));
+
+        let actions =
+            client.handle(ClientEvent::Tick { now: env.now() + Duration::from_secs(1) }).unwrap();
+        assert_eq!(actions.iter().filter(|a| matches!(a, ClientAction::Send(_))).count(), 1);
     }
 
     #[test]
-    fn app_message_with_invalid_signature_is_rejected() {
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+    fn own_echo_reports_sequenced_request_id() {
+        let mut alice = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(1));
+        let room_id = 0x1234_u128;
+        alice.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+
+        let first = sequenced_message(&mut alice, room_id, 4);
+        let second = sequenced_message(&mut alice, room_id, 5);
+        assert_ne!(first.header.request_id(), 0);
+        assert_eq!(second.header.request_id(), first.header.request_id().wrapping_add(1).max(1));
+
+        let actions = alice.handle(ClientEvent::FrameReceived(second.clone())).unwrap();
+        assert!(matches!(
+            actions.as_slice(),
+            [ClientAction::MessageSequenced { request_id, log_index: 5, .. }]
+                if *request_id == second.header.request_id()
+        ));
+    }
+
+    #[test]
+    fn own_messages_are_exported_in_signed_transcript() {
+        let config = ClientConfig { record_transcript: true, ..ClientConfig::default() };
+        let mut alice =
+            Client::with_config(MockEnv::with_crypto_rng(), ClientIdentity::new(1), config);
 
         let room_id = 0x1234_u128;
-        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+        alice.handle(ClientEvent::CreateRoom { room_id }).unwrap();
 
-        let mut header = FrameHeader::new(Opcode::AppMessage);
-        header.set_room_id(room_id);
-        header.set_sender_id(99); // Different sender
-        header.set_epoch(0);
-        let frame = Frame::new(header, Vec::<u8>::new());
+        let echo = sequenced_message(&mut alice, room_id, 4);
+        alice.handle(ClientEvent::FrameReceived(echo)).unwrap();
 
-        let result = client.handle(ClientEvent::FrameReceived(frame));
-        assert!(matches!(result, Err(ClientError::InvalidFrame { .. })));
+        let document = alice.export_transcript(room_id, 0..10).unwrap();
+        assert_eq!(document.entries.len(), 1);
+        assert_eq!(document.entries[0].log_index, 4);
+        assert_eq!(document.entries[0].body, AppMessageBody::Text(b"hello".to_vec()));
+
+        let imported = TranscriptDocument::from_json(&document.to_json().unwrap()).unwrap();
+        imported.verify().unwrap();
     }
 
     #[test]
-    fn encrypt_decrypt_roundtrip_same_client() {
-        // NOTE: This test demonstrates a known limitation.
-        // In the current sender key design, sender and receiver share the same ratchet.
-        // When you send (encrypt), your ratchet advances. When you receive your own
-        // message back, decryption fails because the ratchet already moved forward.
-        //
-        // In production:
-        // 1. Servers shouldn't echo your own messages back to you
-        // 2. Or: We need separate send/receive ratchets per member
-        //
-        // For now, we test that encryption works and produces valid output,
-        // and that the serialization roundtrips correctly.
+    fn least_recently_used_rooms_are_dehydrated_and_restored() {
+        let storage = MemoryClientStorage::new();
+        let config = ClientConfig { max_hydrated_rooms: Some(1), ..ClientConfig::default() };
+        let mut client = Client::with_storage(
+            MockEnv::with_crypto_rng(),
+            ClientIdentity::new(42),
+            config,
+            Box::new(storage.clone()),
+        )
+        .unwrap();
 
-        let env = MockEnv::new();
-        let identity = ClientIdentity::new(42);
-        let mut client = Client::new(env, identity);
+        client.handle(ClientEvent::CreateRoom { room_id: 1 }).unwrap();
+        client.handle(ClientEvent::CreateRoom { room_id: 2 }).unwrap();
 
-        let room_id = 0x1234_u128;
-        client.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+        assert_eq!(client.room_count(), 2);
+        assert_eq!(client.hydrated_room_count(), 1);
+        assert_eq!(storage.list_rooms().unwrap(), vec![(1, 0)]);
+        assert!(client.is_member(1));
 
-        // Send a message
-        let plaintext = b"Secret message";
         let actions = client
-            .handle(ClientEvent::SendMessage { room_id, plaintext: plaintext.to_vec() })
+            .handle(ClientEvent::SendMessage { room_id: 1, plaintext: b"hi".to_vec() })
             .unwrap();
+        assert!(actions.iter().any(|a| matches!(a, ClientAction::Send(_))));
+        assert_eq!(client.hydrated_room_count(), 1);
+        let mut stored = storage.list_rooms().unwrap();
+        stored.sort_unstable();
+        assert_eq!(stored, vec![(1, 0), (2, 0)]);
+    }
 
-        let ClientAction::Send(frame) = &actions[0] else {
-            panic!("Expected Send action");
-        };
-        let frame = frame.clone();
+    #[test]
+    fn hydrated_rooms_survive_a_crash() {
+        let storage = MemoryClientStorage::new();
+        let config = ClientConfig { max_hydrated_rooms: Some(1), ..ClientConfig::default() };
+        let mut client = Client::with_storage(
+            MockEnv::with_crypto_rng(),
+            ClientIdentity::new(42),
+            config.clone(),
+            Box::new(storage.clone()),
+        )
+        .unwrap();
+        client.handle(ClientEvent::CreateRoom { room_id: 1 }).unwrap();
+        client.handle(ClientEvent::CreateRoom { room_id: 2 }).unwrap();
+        client.handle(ClientEvent::SendMessage { room_id: 1, plaintext: b"hi".to_vec() }).unwrap();
+        drop(client);
+
+        let mut restarted = Client::with_storage(
+            MockEnv::with_crypto_rng(),
+            ClientIdentity::new(42),
+            config,
+            Box::new(storage.clone()),
+        )
+        .unwrap();
+        assert_eq!(restarted.room_count(), 2);
+        assert!(restarted.is_member(1));
 
-        // Verify the encrypted payload can be deserialized
-        let encrypted = deserialize_encrypted_message(&frame.payload).unwrap();
-        assert_eq!(encrypted.epoch, 0);
-        assert_eq!(encrypted.sender_index, 0); // Creator is leaf 0
-        assert_eq!(encrypted.generation, 0); // First message
-        assert!(!encrypted.ciphertext.is_empty());
+        // Forgetting the room drops its entry
+        restarted.handle(ClientEvent::ForgetRoom { room_id: 1 }).unwrap();
+        assert_eq!(storage.list_rooms().unwrap(), vec![(2, 0)]);
+    }
 
-        // Verify sender's ratchet advanced
-        let room = client.rooms.get(&room_id).unwrap();
-        assert_eq!(room.sender_keys.generation(0), Some(1)); // Now at gen 1
+    #[test]
+    fn backups_restore_identity_and_rooms() {
+        let room_id = 1;
+        let config = ClientConfig {
+            backup_params: BackupParams { memory_kib: 64, iterations: 1, lanes: 1 },
+            ..ClientConfig::default()
+        };
+        let (mut alice, mut bob) = two_member_room(room_id);
+        alice.config = config.clone();
+        alice.handle(ClientEvent::BlockUser { user_id: 7 }).unwrap();
+
+        let backup = alice.export_backup(b"passphrase").unwrap();
+
+        let wrong =
+            Client::import_backup(MockEnv::with_crypto_rng(), &backup, b"guess", config.clone());
+        assert!(matches!(wrong, Err(ClientError::Backup(BackupError::DecryptionFailed))));
+
+        let mut restored =
+            Client::import_backup(MockEnv::with_crypto_rng(), &backup, b"passphrase", config)
+                .unwrap();
+        assert_eq!(restored.sender_id(), 1);
+        assert_eq!(restored.epoch(room_id), alice.epoch(room_id));
+        assert_eq!(restored.tree_hash(room_id), alice.tree_hash(room_id));
+        assert_eq!(restored.blocked_users().collect::<Vec<_>>(), vec![7]);
+
+        // The original device keeps using the exported sender keys
+        let before = sequenced_message(&mut alice, room_id, 1);
+        bob.handle(ClientEvent::FrameReceived(before)).unwrap();
+
+        // Encrypting now would repeat the generation Alice just used, so the
+        // message waits for a commit moving to a fresh epoch
+        let epoch = restored.epoch(room_id).unwrap();
+        let actions = restored
+            .handle(ClientEvent::SendMessage { room_id, plaintext: b"restored".to_vec() })
+            .unwrap();
+        let commit = sent(&actions, Opcode::Commit);
+        assert!(!actions.iter().any(|a| matches!(
+            a,
+            ClientAction::Send(frame) if frame.header.opcode_enum() == Some(Opcode::AppMessage)
+        )));
+
+        let actions = restored.handle(ClientEvent::FrameReceived(commit.clone())).unwrap();
+        let message = sent(&actions, Opcode::AppMessage);
+        assert_eq!(message.header.epoch(), epoch + 1);
+
+        bob.handle(ClientEvent::FrameReceived(commit)).unwrap();
+        let actions = bob.handle(ClientEvent::FrameReceived(message)).unwrap();
+        assert!(actions.iter().any(|a| matches!(
+            a,
+            ClientAction::DeliverMessage { sender_id: 1, plaintext, .. } if plaintext == b"restored"
+        )));
     }
 
     #[test]
@@ -1366,4 +3933,64 @@ mod tests {
         // This should be a hard error (protocol violation)
         assert!(matches!(result, Err(ClientError::RoomAlreadyExists { .. })));
     }
+
+    struct RejectX509;
+
+    impl CredentialVerifier for RejectX509 {
+        fn verify(&self, credential: &Credential) -> Result<(), lockframe_core::mls::MlsError> {
+            match credential {
+                Credential::X509 { member_id, .. } => {
+                    Err(lockframe_core::mls::MlsError::InvalidCredential {
+                        member_id: *member_id,
+                        reason: "untrusted issuer".to_string(),
+                    })
+                },
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn identity_sender_id_follows_credential() {
+        let identity =
+            ClientIdentity::with_credential(Credential::X509 { member_id: 9, chain: vec![] });
+        assert_eq!(identity.sender_id, 9);
+    }
+
+    #[test]
+    fn welcome_with_rejected_credential_fails_join() {
+        let room_id = 0x1234_u128;
+        let credential = Credential::X509 { member_id: 1, chain: vec![vec![0x30]] };
+        let mut alice =
+            Client::new(MockEnv::with_crypto_rng(), ClientIdentity::with_credential(credential));
+        let mut bob = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(2));
+        bob.set_credential_verifier(Arc::new(RejectX509));
+
+        alice.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+        let (key_package, _) = bob.generate_key_package().unwrap();
+        let actions = alice
+            .handle(ClientEvent::AddMembers { room_id, key_packages: vec![key_package] })
+            .unwrap();
+        let welcome = actions
+            .into_iter()
+            .find_map(|action| match action {
+                ClientAction::Send(frame)
+                    if frame.header.opcode_enum() == Some(Opcode::Welcome) =>
+                {
+                    Some(frame.payload.to_vec())
+                },
+                _ => None,
+            })
+            .unwrap();
+
+        let result = bob.handle(ClientEvent::JoinRoom { room_id, welcome });
+        assert!(matches!(
+            result,
+            Err(ClientError::Mls {
+                source: lockframe_core::mls::MlsError::InvalidCredential { member_id: 1, .. },
+                ..
+            })
+        ));
+        assert!(!bob.rooms.contains_key(&room_id));
+    }
 }
@@ -0,0 +1,147 @@
+//! Sender clock skew estimation.
+//!
+//! Senders stamp messages with their own wall clock, which may be wrong. The
+//! server's log order is authoritative, so display timestamps are derived from
+//! the claimed time, corrected by the sender's estimated skew, and then
+//! clamped so they never run backwards in log order and never lie in the
+//! future relative to when we received the message.
+//!
+//! Skew is estimated per sender from `claimed - received` over recent live
+//! messages. Transit delay makes every sample an underestimate, so the
+//! largest recent sample is the best estimate. Samples far in the past are
+//! indistinguishable from delayed delivery (sync, backfill) and are not used,
+//! so clocks running behind by more than [`MAX_TRANSIT_MS`] go uncorrected
+//! apart from the ordering clamp.
+
+use std::collections::{HashMap, VecDeque};
+
+use lockframe_core::mls::RoomId;
+
+/// Samples kept per sender.
+const WINDOW: usize = 16;
+
+/// Skew below this is treated as noise and not corrected.
+const TOLERANCE_MS: i64 = 2_000;
+
+/// Samples older than this are treated as delayed delivery, not skew.
+const MAX_TRANSIT_MS: i64 = 60_000;
+
+/// Estimates sender clock skew and produces display timestamps.
+#[derive(Debug, Default)]
+pub struct SkewEstimator {
+    /// Recent `claimed - received` samples per sender, in milliseconds.
+    samples: HashMap<u64, VecDeque<i64>>,
+
+    /// Last display timestamp handed out per room, for monotonicity.
+    last_display: HashMap<RoomId, u64>,
+}
+
+impl SkewEstimator {
+    /// Create an estimator with no samples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current skew estimate for `sender_id` in milliseconds. Positive means
+    /// the sender's clock runs ahead of ours.
+    pub fn skew(&self, sender_id: u64) -> i64 {
+        let estimate =
+            self.samples.get(&sender_id).and_then(|s| s.iter().max().copied()).unwrap_or(0);
+
+        if estimate.abs() < TOLERANCE_MS { 0 } else { estimate }
+    }
+
+    /// Record a message in log order and return its display timestamp
+    /// (Unix milliseconds).
+    ///
+    /// `sent_at` is the sender's claimed time, `None` for senders that do not
+    /// stamp messages. `received_at` is our wall clock at receipt.
+    pub fn observe(
+        &mut self,
+        room_id: RoomId,
+        sender_id: u64,
+        sent_at: Option<u64>,
+        received_at: u64,
+    ) -> u64 {
+        let corrected = match sent_at {
+            Some(sent_at) => {
+                self.record_sample(sender_id, sent_at, received_at);
+                let skew = self.skew(sender_id);
+                sent_at.saturating_add_signed(skew.saturating_neg())
+            },
+            None => received_at,
+        };
+
+        let floor = self.last_display.get(&room_id).copied().unwrap_or(0);
+        let display = corrected.min(received_at).max(floor);
+        self.last_display.insert(room_id, display);
+        display
+    }
+
+    fn record_sample(&mut self, sender_id: u64, sent_at: u64, received_at: u64) {
+        let sample = i64::try_from(i128::from(sent_at) - i128::from(received_at)).unwrap_or(0);
+        if sample < -MAX_TRANSIT_MS {
+            return;
+        }
+
+        let samples = self.samples.entry(sender_id).or_default();
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    #[test]
+    fn accurate_clock_is_not_corrected() {
+        let mut skew = SkewEstimator::new();
+        let display = skew.observe(1, 7, Some(NOW - 300), NOW);
+
+        assert_eq!(skew.skew(7), 0);
+        assert_eq!(display, NOW - 300);
+    }
+
+    #[test]
+    fn fast_clock_is_corrected() {
+        let mut skew = SkewEstimator::new();
+        // Sender runs 10 minutes ahead
+        let ahead = 600_000;
+        skew.observe(1, 7, Some(NOW + ahead), NOW);
+        let display = skew.observe(1, 7, Some(NOW + ahead + 1_000), NOW + 1_500);
+
+        assert_eq!(skew.skew(7), i64::try_from(ahead).unwrap());
+        assert_eq!(display, NOW + 1_000);
+    }
+
+    #[test]
+    fn display_never_runs_backwards_in_log_order() {
+        let mut skew = SkewEstimator::new();
+        let first = skew.observe(1, 7, Some(NOW), NOW);
+        // Sequenced later, but the sender claims an earlier time
+        let second = skew.observe(1, 8, Some(NOW - 30_000), NOW + 10);
+
+        assert!(second >= first);
+    }
+
+    #[test]
+    fn delayed_delivery_is_not_mistaken_for_skew() {
+        let mut skew = SkewEstimator::new();
+        let sent_at = NOW - 3_600_000;
+        let display = skew.observe(1, 7, Some(sent_at), NOW);
+
+        assert_eq!(skew.skew(7), 0);
+        assert_eq!(display, sent_at);
+    }
+
+    #[test]
+    fn unstamped_messages_use_receipt_time() {
+        let mut skew = SkewEstimator::new();
+        assert_eq!(skew.observe(1, 7, None, NOW), NOW);
+    }
+}
@@ -0,0 +1,86 @@
+//! Per-room disappearing message policy.
+//!
+//! With a time-to-live set, every message we send carries an `expires_at`
+//! header field that the server enforces. Independently of the server, every
+//! delivered message carrying an expiry is tracked here and reported as
+//! expired once its time passes, so the application can drop the plaintext
+//! it holds.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Disappearing message policy and pending expiries for one room.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Disappearing {
+    /// Lifetime of messages we send, in seconds. `None` keeps them.
+    ttl_secs: Option<u64>,
+
+    /// `(expires_at, log_index)` of delivered messages not yet expired.
+    pending: BTreeSet<(u64, u64)>,
+}
+
+impl Disappearing {
+    /// Create a policy that keeps messages forever.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lifetime of messages we send, in seconds.
+    pub fn ttl_secs(&self) -> Option<u64> {
+        self.ttl_secs
+    }
+
+    /// Change the lifetime of messages we send. Already sent messages keep
+    /// theirs.
+    pub fn set_ttl_secs(&mut self, ttl_secs: Option<u64>) {
+        self.ttl_secs = ttl_secs.filter(|&secs| secs > 0);
+    }
+
+    /// Expiry for a message sent at `now_secs`, if the policy sets one.
+    pub fn expires_at(&self, now_secs: u64) -> Option<u64> {
+        self.ttl_secs.map(|ttl| now_secs.saturating_add(ttl))
+    }
+
+    /// Track a delivered message that expires at `expires_at`.
+    pub fn track(&mut self, expires_at: u64, log_index: u64) {
+        self.pending.insert((expires_at, log_index));
+    }
+
+    /// Remove and return the log indices of messages expired at `now_secs`,
+    /// in expiry order.
+    pub fn take_expired(&mut self, now_secs: u64) -> Vec<u64> {
+        let still_pending = self.pending.split_off(&(now_secs.saturating_add(1), 0));
+        let expired = std::mem::replace(&mut self.pending, still_pending);
+        expired.into_iter().map(|(_, log_index)| log_index).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_expire_in_order() {
+        let mut disappearing = Disappearing::new();
+        disappearing.track(200, 5);
+        disappearing.track(100, 9);
+        disappearing.track(300, 1);
+
+        assert!(disappearing.take_expired(99).is_empty());
+        assert_eq!(disappearing.take_expired(200), vec![9, 5]);
+        assert_eq!(disappearing.take_expired(u64::MAX), vec![1]);
+    }
+
+    #[test]
+    fn ttl_sets_expiry_of_sent_messages() {
+        let mut disappearing = Disappearing::new();
+        assert_eq!(disappearing.expires_at(1_000), None);
+
+        disappearing.set_ttl_secs(Some(60));
+        assert_eq!(disappearing.expires_at(1_000), Some(1_060));
+
+        disappearing.set_ttl_secs(Some(0));
+        assert_eq!(disappearing.ttl_secs(), None);
+    }
+}
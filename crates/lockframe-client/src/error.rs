@@ -1,9 +1,12 @@
 //! Client error types.
 
-use lockframe_core::mls::RoomId;
-use lockframe_crypto::SenderKeyError;
+use lockframe_core::mls::{MlsError, RoomId};
+use lockframe_crypto::{BackupError, SenderKeyError};
+use lockframe_proto::{ErrorId, ProtocolError};
 use thiserror::Error;
 
+use crate::{storage::ClientStorageError, transcript::TranscriptError};
+
 /// Errors from client operations.
 #[derive(Debug, Error)]
 pub enum ClientError {
@@ -15,8 +18,10 @@ pub enum ClientError {
     },
 
     /// Frame epoch doesn't match room's current epoch.
-    #[error("epoch mismatch: expected {expected}, got {actual}")]
+    #[error("epoch mismatch in room {room_id:x}: expected {expected}, got {actual}")]
     EpochMismatch {
+        /// Room the frame was for.
+        room_id: RoomId,
         /// Expected epoch (room's current epoch).
         expected: u64,
         /// Actual epoch in the frame.
@@ -31,10 +36,12 @@ pub enum ClientError {
     },
 
     /// MLS operation failed.
-    #[error("MLS error: {reason}")]
+    #[error("MLS error: {source}")]
     Mls {
-        /// Description of the MLS failure.
-        reason: String,
+        /// Room the operation was for, if any.
+        room_id: Option<RoomId>,
+        /// Underlying MLS failure.
+        source: MlsError,
     },
 
     /// Sender key operation failed.
@@ -55,6 +62,36 @@ pub enum ClientError {
         reason: String,
     },
 
+    /// Too many messages are already waiting to be sent to the room.
+    #[error("send queue for room {room_id:x} is full ({queued} waiting)")]
+    SendQueueFull {
+        /// Room the message was for.
+        room_id: RoomId,
+        /// Messages waiting in the room's queue.
+        queued: usize,
+    },
+
+    /// Transcript encoding or verification failed.
+    #[error("transcript error: {0}")]
+    Transcript(#[from] TranscriptError),
+
+    /// Backup could not be sealed or opened.
+    #[error("backup error: {0}")]
+    Backup(#[from] BackupError),
+
+    /// Room storage operation failed.
+    #[error("storage error: {0}")]
+    Storage(#[from] ClientStorageError),
+
+    /// Server answered with an Error frame.
+    #[error("rejected by server: {source}")]
+    Rejected {
+        /// Room the rejected frame was for, if any.
+        room_id: Option<RoomId>,
+        /// The server's error, with the ID it logged it under.
+        source: ProtocolError,
+    },
+
     /// Sync required to process frame.
     #[error("sync required: room {room_id:x} needs epoch {target_epoch}")]
     SyncRequired {
@@ -65,7 +102,18 @@ pub enum ClientError {
     },
 }
 
+impl From<MlsError> for ClientError {
+    fn from(source: MlsError) -> Self {
+        Self::Mls { room_id: None, source }
+    }
+}
+
 impl ClientError {
+    /// MLS failure scoped to a room, for use with `map_err`.
+    pub(crate) fn mls(room_id: RoomId) -> impl FnOnce(MlsError) -> Self {
+        move |source| Self::Mls { room_id: Some(room_id), source }
+    }
+
     /// Returns true if this error is fatal (unrecoverable).
     ///
     /// Fatal errors indicate protocol violations or bugs.
@@ -73,16 +121,86 @@ impl ClientError {
     pub fn is_fatal(&self) -> bool {
         match self {
             // Fatal: protocol violations, crypto failures
-            Self::InvalidFrame { .. } | Self::InvalidState { .. } | Self::Mls { .. } => true,
+            Self::InvalidFrame { .. } | Self::InvalidState { .. } => true,
 
-            // Fatal sender key errors
+            // Fatal MLS and sender key errors
+            Self::Mls { source, .. } => !source.is_transient(),
             Self::SenderKey(e) => e.is_fatal(),
 
             // Transient: can be recovered
             Self::RoomNotFound { .. }
             | Self::RoomAlreadyExists { .. }
             | Self::EpochMismatch { .. }
-            | Self::SyncRequired { .. } => false,
+            | Self::SyncRequired { .. }
+            | Self::SendQueueFull { .. }
+            | Self::Rejected { .. }
+            | Self::Transcript(_)
+            | Self::Backup(_)
+            | Self::Storage(_) => false,
+        }
+    }
+
+    /// Returns true if repeating the operation may succeed.
+    ///
+    /// Retryable errors clear once the client catches up (sync), a competing
+    /// commit settles, the send queue drains, or storage becomes available
+    /// again. Matches the
+    /// harness model's `ErrorProperties::is_retryable` classification.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::EpochMismatch { .. }
+            | Self::SyncRequired { .. }
+            | Self::SendQueueFull { .. }
+            | Self::Storage(ClientStorageError::Io(_))
+            | Self::SenderKey(
+                SenderKeyError::EpochMismatch { .. } | SenderKeyError::UnknownSender { .. },
+            ) => true,
+
+            Self::Mls { source, .. } => source.is_transient(),
+
+            Self::RoomNotFound { .. }
+            | Self::RoomAlreadyExists { .. }
+            | Self::InvalidFrame { .. }
+            | Self::InvalidState { .. }
+            | Self::SenderKey(_)
+            | Self::Rejected { .. }
+            | Self::Transcript(_)
+            | Self::Backup(_)
+            | Self::Storage(_) => false,
+        }
+    }
+
+    /// Room the failed operation was for, when known.
+    pub fn room_id(&self) -> Option<RoomId> {
+        match self {
+            Self::RoomNotFound { room_id }
+            | Self::RoomAlreadyExists { room_id }
+            | Self::EpochMismatch { room_id, .. }
+            | Self::SyncRequired { room_id, .. }
+            | Self::SendQueueFull { room_id, .. }
+            | Self::Storage(ClientStorageError::NotFound { room_id }) => Some(*room_id),
+            Self::Mls { room_id, .. } | Self::Rejected { room_id, .. } => *room_id,
+            _ => None,
+        }
+    }
+
+    /// Epoch the offending frame or message carried, when known.
+    pub fn epoch(&self) -> Option<u64> {
+        match self {
+            Self::EpochMismatch { actual, .. }
+            | Self::SenderKey(SenderKeyError::EpochMismatch { actual, .. }) => Some(*actual),
+            Self::SyncRequired { target_epoch, .. } => Some(*target_epoch),
+            Self::Mls { source: MlsError::EpochMismatch { received, .. }, .. } => Some(*received),
+            Self::Mls { source: MlsError::InvalidState { epoch, .. }, .. } => Some(*epoch),
+            _ => None,
+        }
+    }
+
+    /// ID the server logged the failure under, if it sent one.
+    pub fn error_id(&self) -> Option<ErrorId> {
+        match self {
+            Self::Rejected { source, .. } => source.error_id(),
+            _ => None,
         }
     }
 }
@@ -111,7 +229,52 @@ mod tests {
 
     #[test]
     fn error_display() {
-        let err = ClientError::EpochMismatch { expected: 5, actual: 3 };
-        assert_eq!(err.to_string(), "epoch mismatch: expected 5, got 3");
+        let err = ClientError::EpochMismatch { room_id: 0xab, expected: 5, actual: 3 };
+        assert_eq!(err.to_string(), "epoch mismatch in room ab: expected 5, got 3");
+    }
+
+    #[test]
+    fn epoch_mismatch_is_retryable_with_context() {
+        let err = ClientError::EpochMismatch { room_id: 123, expected: 5, actual: 3 };
+        assert!(err.is_retryable());
+        assert!(!err.is_fatal());
+        assert_eq!(err.room_id(), Some(123));
+        assert_eq!(err.epoch(), Some(3));
+    }
+
+    #[test]
+    fn mls_errors_follow_source_classification() {
+        let transient = ClientError::mls(123)(MlsError::EpochMismatch { expected: 5, received: 4 });
+        assert!(transient.is_retryable());
+        assert!(!transient.is_fatal());
+        assert_eq!(transient.room_id(), Some(123));
+        assert_eq!(transient.epoch(), Some(4));
+
+        let fatal = ClientError::from(MlsError::Crypto("bad signature".to_string()));
+        assert!(fatal.is_fatal());
+        assert!(!fatal.is_retryable());
+        assert_eq!(fatal.room_id(), None);
+    }
+
+    #[test]
+    fn rejections_keep_the_server_error_id() {
+        let err = ClientError::Rejected {
+            room_id: Some(123),
+            source: ProtocolError::Rejected {
+                code: 0x0005,
+                message: "room not found".to_string(),
+                error_id: Some(ErrorId::new(0x7f3a)),
+            },
+        };
+        assert_eq!(err.error_id(), Some(ErrorId::new(0x7f3a)));
+        assert_eq!(err.room_id(), Some(123));
+        assert!(!err.is_fatal());
+    }
+
+    #[test]
+    fn room_not_found_is_neither_fatal_nor_retryable() {
+        let err = ClientError::RoomNotFound { room_id: 123 };
+        assert!(!err.is_retryable());
+        assert_eq!(err.room_id(), Some(123));
     }
 }
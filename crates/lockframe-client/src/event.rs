@@ -1,7 +1,17 @@
 //! Client events and actions.
 
+use std::time::Duration;
+
 use lockframe_core::mls::RoomId;
-use lockframe_proto::Frame;
+use lockframe_proto::{
+    Frame,
+    payloads::{
+        app::{AppMessageBody, Attachment, Reaction, Receipt, ReplyTo},
+        session::{DirectoryEntry, NoticeKind},
+    },
+};
+
+use crate::roster::MemberInfo;
 
 /// Events the caller feeds into the client.
 ///
@@ -34,6 +44,65 @@ pub enum ClientEvent<I = std::time::Instant> {
         plaintext: Vec<u8>,
     },
 
+    /// Application wants to send a typed message body.
+    ///
+    /// General form of `SendMessage`, `EditMessage`, and `DeleteMessage`,
+    /// used for reactions, receipts, typing notifications, and custom
+    /// content.
+    SendAppMessage {
+        /// Target room.
+        room_id: RoomId,
+        /// Message body to encrypt.
+        body: AppMessageBody,
+    },
+
+    /// Application wants to edit one of its earlier messages.
+    EditMessage {
+        /// Room containing the message.
+        room_id: RoomId,
+        /// Log index of the message to edit.
+        target_log_index: u64,
+        /// Replacement plaintext.
+        new_plaintext: Vec<u8>,
+    },
+
+    /// Application wants to delete one of its earlier messages.
+    DeleteMessage {
+        /// Room containing the message.
+        room_id: RoomId,
+        /// Log index of the message to delete.
+        target_log_index: u64,
+    },
+
+    /// User has read a room up to and including `log_index`.
+    ///
+    /// Clears the unread count below that point and broadcasts an encrypted
+    /// read receipt to the room. Positions at or below the current one are
+    /// ignored.
+    MarkRead {
+        /// Room that was read.
+        room_id: RoomId,
+        /// Highest log index the user has seen.
+        log_index: u64,
+    },
+
+    /// Hide all content from a user, in every room.
+    ///
+    /// Blocking is local: the user stays a member and their messages are
+    /// still decrypted to keep the ratchets in step, but they are delivered
+    /// as [`ClientAction::MessageHidden`] instead. The block list survives
+    /// restarts when the client has storage attached.
+    BlockUser {
+        /// User to block.
+        user_id: u64,
+    },
+
+    /// Stop hiding content from a previously blocked user.
+    UnblockUser {
+        /// User to unblock.
+        user_id: u64,
+    },
+
     /// Application wants to create a new room.
     CreateRoom {
         /// Room ID to create.
@@ -49,11 +118,22 @@ pub enum ClientEvent<I = std::time::Instant> {
     },
 
     /// Application wants to leave a room.
+    ///
+    /// Sends a proposal removing us from the group. The room is removed
+    /// once another member commits it; see [`crate::Client::is_leaving`].
+    /// Sent again while leaving, it proposes again.
     LeaveRoom {
         /// Room to leave.
         room_id: RoomId,
     },
 
+    /// Drop a room's local state without telling the group, for example
+    /// after learning out of band that we were removed.
+    ForgetRoom {
+        /// Room to forget.
+        room_id: RoomId,
+    },
+
     /// Application wants to add members to a room.
     AddMembers {
         /// Target room.
@@ -98,6 +178,79 @@ pub enum ClientEvent<I = std::time::Instant> {
         /// Room to join.
         room_id: RoomId,
     },
+
+    /// Fetch and decrypt a room's history up to `until_log_index`.
+    ///
+    /// The client sends paginated `SyncRequest`s itself, one batch at a
+    /// time, and reports [`ClientAction::BackfillProgress`] after each.
+    /// While a backfill runs, every sync response for the room is counted
+    /// towards it.
+    BackfillRoom {
+        /// Room to backfill.
+        room_id: RoomId,
+        /// Backfill stops before this log index.
+        until_log_index: u64,
+    },
+
+    /// Make messages we send to a room disappear `ttl_secs` after sending,
+    /// or keep them with `None`.
+    ///
+    /// The expiry is stamped in each frame header, so the server deletes
+    /// the frames and every receiver drops the content on its own.
+    SetMessageTtl {
+        /// Room the policy applies to.
+        room_id: RoomId,
+        /// Lifetime of sent messages in seconds.
+        ttl_secs: Option<u64>,
+    },
+
+    /// List a room in the server's room directory under `name`, or remove
+    /// its listing with `None`.
+    ///
+    /// The name is public. The server only accepts listings from the room's
+    /// owner and admins.
+    SetRoomListing {
+        /// Room to list.
+        room_id: RoomId,
+        /// Name to list the room under.
+        name: Option<String>,
+    },
+
+    /// Report a message in a room to the server's operators.
+    ///
+    /// The report names the message by log index and never includes its
+    /// content. Other members are not told about it.
+    ReportMessage {
+        /// Room the message is in.
+        room_id: RoomId,
+        /// Log index of the reported message.
+        log_index: u64,
+        /// Why the message is being reported.
+        reason: String,
+    },
+
+    /// Search the server's room directory. Results arrive as
+    /// [`ClientAction::DirectoryResults`].
+    SearchDirectory {
+        /// Text listed room names must contain.
+        query: String,
+        /// Continue after this room, from a previous page's `next`.
+        after: Option<RoomId>,
+    },
+
+    /// Invite a user to a room we are a member of.
+    ///
+    /// The invite is signed with our MLS signature key for the room and
+    /// relayed by the server to the user, who joins by external commit if
+    /// they accept. It does not add them.
+    InviteUser {
+        /// Room to invite the user to.
+        room_id: RoomId,
+        /// User to invite.
+        user_id: u64,
+        /// How long the invite stays valid.
+        ttl: Duration,
+    },
 }
 
 /// Serializable snapshot of room state for persistence.
@@ -129,10 +282,273 @@ pub enum ClientAction {
         plaintext: Vec<u8>,
         /// Log index in the room.
         log_index: u64,
-        /// Message timestamp (HLC).
+        /// When the message was received (Unix milliseconds).
+        timestamp: u64,
+        /// Sender's send time corrected for clock skew (Unix milliseconds).
+        /// Non-decreasing in log order within a room.
+        display_timestamp: u64,
+        /// Message this one replies to, if it is a reply.
+        reply_to: Option<ReplyTo>,
+    },
+
+    /// The server sequenced one of our own messages.
+    ///
+    /// Every message we send carries a `request_id` in its header, also
+    /// found on the [`ClientAction::Send`] frame, and the server's echo of
+    /// it confirms delivery. Echoes of messages sent before a restart may
+    /// carry IDs the application never saw.
+    MessageSequenced {
+        /// Room the message was sent to.
+        room_id: RoomId,
+        /// `request_id` of the sent frame.
+        request_id: u32,
+        /// Log index the server assigned.
+        log_index: u64,
+    },
+
+    /// A member edited one of their earlier messages.
+    ///
+    /// The client does not keep message history, so it cannot check that
+    /// `sender_id` authored the target. The application must only apply the
+    /// edit if it did.
+    MessageEdited {
+        /// Room the edit is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// Log index of the edited message.
+        target_log_index: u64,
+        /// Replacement plaintext.
+        new_plaintext: Vec<u8>,
+        /// Log index of the edit itself.
+        log_index: u64,
+        /// When the edit was received (Unix milliseconds).
+        timestamp: u64,
+    },
+
+    /// A member deleted one of their earlier messages.
+    ///
+    /// Same authorship caveat as [`ClientAction::MessageEdited`].
+    MessageDeleted {
+        /// Room the delete is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// Log index of the deleted message.
+        target_log_index: u64,
+        /// Log index of the delete itself.
+        log_index: u64,
+        /// When the deletion was received (Unix milliseconds).
+        timestamp: u64,
+    },
+
+    /// A disappearing message expired. The application must drop any
+    /// content it holds for `log_index`.
+    MessageExpired {
+        /// Room the message was in.
+        room_id: RoomId,
+        /// Log index of the expired message.
+        log_index: u64,
+    },
+
+    /// A message from a blocked user was received and hidden.
+    ///
+    /// Only emitted for content the user would otherwise see. Reactions,
+    /// receipts, typing, edits, and deletes from blocked users are dropped
+    /// silently.
+    MessageHidden {
+        /// Room the message is from.
+        room_id: RoomId,
+        /// Blocked sender's stable ID.
+        sender_id: u64,
+        /// Log index in the room.
+        log_index: u64,
+        /// When the message was received (Unix milliseconds).
+        timestamp: u64,
+    },
+
+    /// A member reacted to a message.
+    DeliverReaction {
+        /// Room the reaction is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// The reaction, including its target message.
+        reaction: Reaction,
+        /// Log index of the reaction itself.
+        log_index: u64,
+        /// When the reaction was received (Unix milliseconds).
         timestamp: u64,
     },
 
+    /// A member acknowledged a message.
+    DeliverReceipt {
+        /// Room the receipt is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// The receipt, including its target message.
+        receipt: Receipt,
+        /// Log index of the receipt itself.
+        log_index: u64,
+    },
+
+    /// A member started or stopped composing.
+    DeliverTyping {
+        /// Room the notification is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// True while composing.
+        active: bool,
+    },
+
+    /// A member shared an encrypted file.
+    ///
+    /// The attachment carries the key to its content; open it with
+    /// [`crate::open_attachment`] when the user asks for it.
+    DeliverAttachment {
+        /// Room the attachment is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// File metadata and sealed content.
+        attachment: Attachment,
+        /// Log index in the room.
+        log_index: u64,
+        /// When the message was received (Unix milliseconds).
+        timestamp: u64,
+        /// Sender's send time corrected for clock skew (Unix milliseconds).
+        /// Non-decreasing in log order within a room.
+        display_timestamp: u64,
+    },
+
+    /// A member sent application-defined content.
+    DeliverCustom {
+        /// Room the content is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// Content type identifier.
+        type_url: String,
+        /// Opaque content bytes.
+        bytes: Vec<u8>,
+        /// Log index in the room.
+        log_index: u64,
+        /// When the message was received (Unix milliseconds).
+        timestamp: u64,
+        /// Sender's send time corrected for clock skew (Unix milliseconds).
+        /// Non-decreasing in log order within a room.
+        display_timestamp: u64,
+    },
+
+    /// A member sent content this client cannot read, such as a kind of
+    /// message from a newer release. Nothing of it is known beyond who sent
+    /// it and when.
+    DeliverUnsupported {
+        /// Room the content is from.
+        room_id: RoomId,
+        /// Sender's stable ID.
+        sender_id: u64,
+        /// Log index in the room.
+        log_index: u64,
+        /// When the message was received (Unix milliseconds).
+        timestamp: u64,
+        /// Sender's send time corrected for clock skew (Unix milliseconds).
+        /// Non-decreasing in log order within a room.
+        display_timestamp: u64,
+    },
+
+    /// An outgoing message exceeded the room's send budget and was queued.
+    ///
+    /// Queued messages are sent on later ticks, in order. The caller may use
+    /// this to slow down or show a pending indicator.
+    Backpressure {
+        /// Room the message was queued for.
+        room_id: RoomId,
+        /// Messages waiting in the room's queue.
+        queued: usize,
+        /// Time until the next queued message can be sent.
+        retry_after: Duration,
+    },
+
+    /// A room's unread count or read position changed.
+    UnreadCountChanged {
+        /// Room whose count changed.
+        room_id: RoomId,
+        /// Messages from other members above the read position.
+        unread: usize,
+        /// Highest log index marked read, if any.
+        read_up_to: Option<u64>,
+    },
+
+    /// A backfill made progress. Complete once `fetched == total`.
+    BackfillProgress {
+        /// Room being backfilled.
+        room_id: RoomId,
+        /// Frames fetched so far.
+        fetched: u64,
+        /// Frames expected in total. Lowered to the actual count on
+        /// completion if the history was shorter than requested.
+        total: u64,
+    },
+
+    /// The server has discarded the room's history before `first_log_index`
+    /// under its retention policy. Earlier messages cannot be fetched.
+    HistoryTruncated {
+        /// Room whose history was truncated.
+        room_id: RoomId,
+        /// Lowest log index the server still holds.
+        first_log_index: u64,
+    },
+
+    /// A user's online status changed or was reported in reply to a
+    /// `PresenceQuery`. Only sent by servers that share presence.
+    PresenceChanged {
+        /// User the status describes.
+        user_id: u64,
+        /// Whether the user is connected.
+        online: bool,
+        /// Unix time the user was last connected, if known.
+        last_seen_secs: Option<u64>,
+    },
+
+    /// Another member invited us to a room. Join it with
+    /// [`ClientEvent::ExternalJoin`] to accept.
+    ///
+    /// The invite's signature was checked, but not that the key belongs to
+    /// `inviter`: that is only known once we are in the room.
+    InviteReceived {
+        /// Room we are invited to.
+        room_id: RoomId,
+        /// User who invited us.
+        inviter: u64,
+        /// Unix time in seconds the invite expires.
+        expires_at: u64,
+    },
+
+    /// A page of room directory search results.
+    DirectoryResults {
+        /// Matching listed rooms, in room ID order.
+        rooms: Vec<DirectoryEntry>,
+        /// Pass as `after` to fetch the next page. `None` on the last page.
+        next: Option<RoomId>,
+    },
+
+    /// A notice from the server's operator, such as a maintenance window.
+    ///
+    /// Comes from the server, not a room member: show it apart from room
+    /// messages.
+    ServerNotice {
+        /// Room the notice is for. `None` if it is for everyone on the
+        /// server.
+        room_id: Option<RoomId>,
+        /// What the notice is about.
+        kind: NoticeKind,
+        /// Text to show.
+        text: String,
+    },
+
     /// Request missing commits for epoch sync.
     ///
     /// The caller should fetch commits from the server and feed
@@ -146,6 +562,20 @@ pub enum ClientAction {
         to_epoch: u64,
     },
 
+    /// Sequenced frames of a room were lost on the way.
+    ///
+    /// Log indices `from_log_index..until_log_index` never arrived. A
+    /// `SyncRequest` for just that range is sent alongside, and the frames
+    /// are processed as they come back.
+    GapDetected {
+        /// Room with the gap.
+        room_id: RoomId,
+        /// First missing log index.
+        from_log_index: u64,
+        /// Log index of the frame after the gap.
+        until_log_index: u64,
+    },
+
     /// Persist room state.
     ///
     /// The caller decides the storage backend.
@@ -176,6 +606,15 @@ pub enum ClientAction {
         user_id: u64,
     },
 
+    /// A room's members changed: someone joined or left, or a member's
+    /// credential or key changed. Also sent when we create or join a room.
+    MembersChanged {
+        /// Room whose members changed.
+        room_id: RoomId,
+        /// Every current member, as [`crate::Client::members`] returns them.
+        members: Vec<MemberInfo>,
+    },
+
     /// `KeyPackage` was published successfully.
     KeyPackagePublished,
 
@@ -198,4 +637,24 @@ pub enum ClientAction {
         /// Epoch we joined at.
         epoch: u64,
     },
+
+    /// An already-delivered message was sequenced again by the server.
+    ///
+    /// Security event: the same `(sender, epoch, generation)` appeared at a
+    /// different log index, meaning captured ciphertext was re-submitted.
+    /// The message is not delivered a second time.
+    ReplayDetected {
+        /// Room the replay occurred in.
+        room_id: RoomId,
+        /// Sender the replayed message claims to be from.
+        sender_id: u64,
+        /// Epoch of the replayed message.
+        epoch: u64,
+        /// Ratchet generation of the replayed message.
+        generation: u32,
+        /// Log index the message was first delivered at.
+        original_log_index: u64,
+        /// Log index the replay was sequenced at.
+        replayed_log_index: u64,
+    },
 }
@@ -0,0 +1,109 @@
+//! Log sequence gap detection.
+//!
+//! The server gives every sequenced frame of a room the next log index and
+//! broadcasts it to each member, so a member sees consecutive indices. A
+//! skipped index means a broadcast was lost on the way. [`GapDetector`]
+//! notices the skip and names the missing range, which the client refetches
+//! from the server's storage with a `SyncRequest` for just that range instead
+//! of a full re-sync.
+
+use std::ops::Range;
+
+use lockframe_proto::{FrameFlags, FrameHeader, Opcode};
+
+/// Tracks the next log index expected in one room.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GapDetector {
+    /// One past the highest log index seen, `None` until the first frame.
+    next_log_index: Option<u64>,
+}
+
+impl GapDetector {
+    /// Create a detector expecting nothing yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note a sequenced frame at `log_index`, returning the indices skipped
+    /// since the previous one.
+    ///
+    /// The first frame seen sets the baseline: the room's history before it
+    /// is backfill's business, not a gap. Frames below the highest seen are
+    /// redeliveries or repairs and change nothing.
+    pub fn observe(&mut self, log_index: u64) -> Option<Range<u64>> {
+        let next = self.next_log_index.unwrap_or(log_index);
+        if log_index < next {
+            return None;
+        }
+
+        self.next_log_index = Some(log_index.saturating_add(1));
+        (log_index > next).then_some(next..log_index)
+    }
+}
+
+/// Whether the server assigns `header`'s frame a log index.
+///
+/// Room frames are sequenced except ephemeral ones, MLS plumbing that is
+/// answered or routed directly, and reports, which go to moderators only.
+pub fn is_sequenced(header: &FrameHeader) -> bool {
+    if header.flags().contains(FrameFlags::EPHEMERAL) {
+        return false;
+    }
+    match header.opcode_enum() {
+        Some(
+            Opcode::Proposal
+            | Opcode::Commit
+            | Opcode::PSKProposal
+            | Opcode::ReInit
+            | Opcode::ExternalCommit,
+        ) => true,
+        Some(Opcode::Typing | Opcode::Presence | Opcode::Report) | None => false,
+        Some(opcode) => (0x2000..0x4000).contains(&opcode.to_u16()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_indices_have_no_gap() {
+        let mut gaps = GapDetector::new();
+
+        assert_eq!(gaps.observe(40), None);
+        assert_eq!(gaps.observe(41), None);
+        assert_eq!(gaps.observe(42), None);
+    }
+
+    #[test]
+    fn skipped_indices_are_reported_once() {
+        let mut gaps = GapDetector::new();
+        gaps.observe(3);
+
+        assert_eq!(gaps.observe(7), Some(4..7));
+        assert_eq!(gaps.observe(8), None);
+
+        // The repair fills the gap without reopening it
+        for log_index in 4..7 {
+            assert_eq!(gaps.observe(log_index), None);
+        }
+        assert_eq!(gaps.observe(7), None);
+        assert_eq!(gaps.observe(10), Some(9..10));
+    }
+
+    #[test]
+    fn only_room_log_frames_are_sequenced() {
+        assert!(is_sequenced(&FrameHeader::new(Opcode::AppMessage)));
+        assert!(is_sequenced(&FrameHeader::new(Opcode::Commit)));
+        assert!(is_sequenced(&FrameHeader::new(Opcode::Kick)));
+
+        assert!(!is_sequenced(&FrameHeader::new(Opcode::Welcome)));
+        assert!(!is_sequenced(&FrameHeader::new(Opcode::KeyPackageFetch)));
+        assert!(!is_sequenced(&FrameHeader::new(Opcode::SyncResponse)));
+        assert!(!is_sequenced(&FrameHeader::new(Opcode::Report)));
+
+        let mut typing = FrameHeader::new(Opcode::AppMessage);
+        typing.set_flags(FrameFlags::EPHEMERAL);
+        assert!(!is_sequenced(&typing));
+    }
+}
@@ -14,6 +14,9 @@
 //!
 //! - [`Client`]: Top-level state machine managing multiple rooms
 //! - [`SenderKeyStore`]: Per-room sender key ratchet management
+//! - [`ClientStorage`]: Where idle rooms are kept when not hydrated
+//! - [`PacerConfig`]: Per-room outgoing message rate limits
+//! - [`TranscriptDocument`]: Signed export of a room's decrypted history
 //! - [`ClientEvent`]: Events fed into the client
 //! - [`ClientAction`]: Actions produced by the client
 //!
@@ -26,19 +29,36 @@
 //! - [`transport::TlsMode`]: Secure or insecure TLS verification
 //! - [`transport::TransportConfig`]: Transport configuration options
 
+mod attachment;
+mod backfill;
 mod client;
+mod clock_skew;
+mod disappearing;
 mod error;
 mod event;
+mod gaps;
+mod pacer;
+mod read_markers;
+mod replay_window;
+mod roster;
 mod sender_key_store;
+mod storage;
+mod transcript;
 
 #[cfg(feature = "transport")]
 pub mod transport;
 
-pub use client::{Client, ClientIdentity};
+pub use attachment::open_attachment;
+pub use client::{Client, ClientConfig, ClientIdentity};
 pub use error::ClientError;
 pub use event::{ClientAction, ClientEvent, RoomStateSnapshot};
 pub use lockframe_core::{
     env::Environment,
-    mls::{MemberId, RoomId},
+    mls::{AcceptAllCredentials, Credential, CredentialVerifier, MemberId, RoomId},
 };
-pub use sender_key_store::SenderKeyStore;
+pub use lockframe_crypto::AttachmentError;
+pub use pacer::PacerConfig;
+pub use roster::MemberInfo;
+pub use sender_key_store::{SenderKeySnapshot, SenderKeyStore};
+pub use storage::{ClientStorage, ClientStorageError, MemoryClientStorage};
+pub use transcript::{TRANSCRIPT_VERSION, TranscriptDocument, TranscriptEntry, TranscriptError};
@@ -0,0 +1,261 @@
+//! Per-room send pacing.
+//!
+//! The server sequences application messages in order and rejects senders
+//! that flood a room. Rather than letting bursty UI activity (pasting several
+//! lines, rapid reactions) trip that limit, the client paces its own sends
+//! with a token bucket per room. Messages over the budget are queued and
+//! released on later ticks, in the order they were submitted. The queue is
+//! bounded; once it is full, further messages are refused rather than held
+//! without limit.
+//!
+//! Queued messages are kept as plaintext bodies, not frames. They are
+//! encrypted on release so they always use the room's current epoch and the
+//! next ratchet generation, even if a commit landed while they waited.
+
+use std::{collections::VecDeque, ops::Sub, time::Duration};
+
+use lockframe_proto::payloads::app::AppMessageBody;
+
+/// One token, in credit units. Credit accrues at `messages_per_second` units
+/// per nanosecond, so integer arithmetic stays exact.
+const TOKEN: u128 = 1_000_000_000;
+
+/// Token bucket configuration for outgoing application messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacerConfig {
+    /// Sustained send rate per room.
+    pub messages_per_second: u32,
+
+    /// Messages that may be sent back-to-back before pacing kicks in.
+    pub burst: u32,
+
+    /// Most messages waiting in a room's queue. Further sends are refused.
+    pub max_queued: usize,
+}
+
+impl Default for PacerConfig {
+    fn default() -> Self {
+        Self { messages_per_second: 10, burst: 20, max_queued: 256 }
+    }
+}
+
+/// Outcome of submitting a message to the pacer.
+#[derive(Debug)]
+pub enum Admission {
+    /// Within budget, send now.
+    Send(AppMessageBody),
+
+    /// Over budget, message was queued.
+    Deferred {
+        /// Messages now waiting in this room's queue.
+        queued: usize,
+        /// Time until the next queued message can be released.
+        retry_after: Duration,
+    },
+}
+
+/// A message refused because the room's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull {
+    /// Messages waiting in the room's queue.
+    pub queued: usize,
+}
+
+/// Token bucket and send queue for one room.
+///
+/// # Invariants
+///
+/// - `credit <= burst * TOKEN`
+/// - `queue.len() <= max_queued`
+/// - If `queue` is non-empty, new messages are queued behind it regardless of
+///   available credit, so delivery order matches submission order
+#[derive(Debug)]
+pub struct Pacer<I> {
+    config: PacerConfig,
+    credit: u128,
+    last_refill: I,
+    queue: VecDeque<AppMessageBody>,
+}
+
+impl<I> Pacer<I>
+where
+    I: Copy + Ord + Sub<Output = Duration>,
+{
+    /// Create a pacer with a full bucket.
+    pub fn new(config: PacerConfig, now: I) -> Self {
+        Self { config, credit: Self::capacity(config), last_refill: now, queue: VecDeque::new() }
+    }
+
+    /// Submit a message for sending.
+    ///
+    /// # Errors
+    ///
+    /// [`QueueFull`] if the message would have to wait and
+    /// [`PacerConfig::max_queued`] messages already are.
+    pub fn admit(&mut self, body: AppMessageBody, now: I) -> Result<Admission, QueueFull> {
+        self.refill(now);
+
+        if self.queue.is_empty() && self.credit >= TOKEN {
+            self.credit -= TOKEN;
+            return Ok(Admission::Send(body));
+        }
+        if self.queue.len() >= self.config.max_queued {
+            return Err(QueueFull { queued: self.queue.len() });
+        }
+
+        self.queue.push_back(body);
+        Ok(Admission::Deferred { queued: self.queue.len(), retry_after: self.retry_after() })
+    }
+
+    /// Take the oldest queued message if the budget allows sending it now.
+    ///
+    /// If it cannot be sent after all, hand it back with [`Self::unrelease`].
+    pub fn release(&mut self, now: I) -> Option<AppMessageBody> {
+        self.refill(now);
+        if self.credit < TOKEN {
+            return None;
+        }
+
+        let body = self.queue.pop_front()?;
+        self.credit -= TOKEN;
+        Some(body)
+    }
+
+    /// Put back a message taken by [`Self::release`] that was not sent, at
+    /// the front of the queue, refunding its token.
+    pub fn unrelease(&mut self, body: AppMessageBody) {
+        self.queue.push_front(body);
+        self.credit = self.credit.saturating_add(TOKEN).min(Self::capacity(self.config));
+    }
+
+    /// True if no messages are waiting.
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn refill(&mut self, now: I) {
+        if now <= self.last_refill {
+            return;
+        }
+
+        let elapsed = (now - self.last_refill).as_nanos();
+        let earned = elapsed.saturating_mul(u128::from(self.config.messages_per_second));
+        self.credit = self.credit.saturating_add(earned).min(Self::capacity(self.config));
+        self.last_refill = now;
+    }
+
+    fn retry_after(&self) -> Duration {
+        let rate = u128::from(self.config.messages_per_second.max(1));
+        let missing = TOKEN.saturating_sub(self.credit);
+        let nanos = missing.div_ceil(rate);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    fn capacity(config: PacerConfig) -> u128 {
+        u128::from(config.burst.max(1)) * TOKEN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(n: u8) -> AppMessageBody {
+        AppMessageBody::Text(vec![n])
+    }
+
+    fn pacer(messages_per_second: u32, burst: u32) -> Pacer<Duration> {
+        Pacer::new(PacerConfig { messages_per_second, burst, max_queued: 8 }, Duration::ZERO)
+    }
+
+    /// Every message the budget allows at `now`.
+    fn release(pacer: &mut Pacer<Duration>, now: Duration) -> Vec<AppMessageBody> {
+        std::iter::from_fn(|| pacer.release(now)).collect()
+    }
+
+    #[test]
+    fn burst_is_sent_immediately() {
+        let mut pacer = pacer(1, 3);
+
+        for n in 0..3 {
+            assert!(matches!(pacer.admit(body(n), Duration::ZERO), Ok(Admission::Send(_))));
+        }
+    }
+
+    #[test]
+    fn over_budget_is_deferred() {
+        let mut pacer = pacer(2, 1);
+        assert!(matches!(pacer.admit(body(0), Duration::ZERO), Ok(Admission::Send(_))));
+
+        let admission = pacer.admit(body(1), Duration::ZERO);
+        assert!(matches!(admission, Ok(Admission::Deferred {
+            queued: 1,
+            retry_after
+        }) if retry_after == Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn release_preserves_order() {
+        let mut pacer = pacer(1, 1);
+        let _ = pacer.admit(body(0), Duration::ZERO);
+        let _ = pacer.admit(body(1), Duration::ZERO);
+        let _ = pacer.admit(body(2), Duration::ZERO);
+
+        assert!(release(&mut pacer, Duration::from_millis(500)).is_empty());
+        assert_eq!(release(&mut pacer, Duration::from_secs(1)), vec![body(1)]);
+        assert_eq!(release(&mut pacer, Duration::from_secs(2)), vec![body(2)]);
+    }
+
+    #[test]
+    fn queued_messages_block_new_sends() {
+        let mut pacer = pacer(1, 2);
+        let _ = pacer.admit(body(0), Duration::ZERO);
+        let _ = pacer.admit(body(1), Duration::ZERO);
+        let _ = pacer.admit(body(2), Duration::ZERO);
+
+        // One token has accrued, but body(2) is still ahead of body(3)
+        let admission = pacer.admit(body(3), Duration::from_secs(1));
+        assert!(matches!(admission, Ok(Admission::Deferred { queued: 2, .. })));
+        assert_eq!(release(&mut pacer, Duration::from_secs(1)), vec![body(2)]);
+    }
+
+    #[test]
+    fn credit_is_capped_at_burst() {
+        let mut pacer = pacer(10, 2);
+        let released = release(&mut pacer, Duration::from_mins(1));
+        assert!(released.is_empty());
+
+        let sent = (0..5)
+            .filter(|&n| {
+                matches!(pacer.admit(body(n), Duration::from_mins(1)), Ok(Admission::Send(_)))
+            })
+            .count();
+        assert_eq!(sent, 2);
+    }
+
+    #[test]
+    fn full_queue_refuses_messages() {
+        let mut pacer = pacer(1, 1);
+        let _ = pacer.admit(body(0), Duration::ZERO);
+        for n in 1..=8 {
+            assert!(pacer.admit(body(n), Duration::ZERO).is_ok());
+        }
+
+        assert_eq!(pacer.admit(body(9), Duration::ZERO).unwrap_err(), QueueFull { queued: 8 });
+        assert_eq!(release(&mut pacer, Duration::from_secs(1)), vec![body(1)]);
+        assert!(pacer.admit(body(9), Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn unreleased_messages_keep_their_place_and_token() {
+        let mut pacer = pacer(1, 2);
+        let _ = pacer.admit(body(0), Duration::ZERO);
+        let _ = pacer.admit(body(1), Duration::ZERO);
+        let _ = pacer.admit(body(2), Duration::ZERO);
+        let _ = pacer.admit(body(3), Duration::ZERO);
+
+        let taken = pacer.release(Duration::from_secs(2)).unwrap();
+        pacer.unrelease(taken);
+        assert_eq!(release(&mut pacer, Duration::from_secs(2)), vec![body(2), body(3)]);
+    }
+}
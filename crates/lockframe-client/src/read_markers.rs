@@ -0,0 +1,100 @@
+//! Per-room read position and unread count.
+//!
+//! The read position is the highest log index the user has seen. Messages from
+//! other members above it are unread. The position only moves forward, so a
+//! stale marker from another device or a reordered receipt never resurrects
+//! messages as unread.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Read position and unread messages for one room.
+///
+/// # Invariants
+///
+/// - Every entry in `unread` is greater than `read_up_to`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadMarkers {
+    /// Highest log index marked read.
+    read_up_to: Option<u64>,
+
+    /// Log indices of unread messages.
+    unread: BTreeSet<u64>,
+}
+
+impl ReadMarkers {
+    /// Create markers with nothing read and nothing unread.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highest log index marked read.
+    pub fn read_up_to(&self) -> Option<u64> {
+        self.read_up_to
+    }
+
+    /// Number of unread messages.
+    pub fn unread_count(&self) -> usize {
+        self.unread.len()
+    }
+
+    /// Count a newly delivered message as unread. Returns true if the unread
+    /// count changed.
+    pub fn record_message(&mut self, log_index: u64) -> bool {
+        if self.read_up_to.is_some_and(|read| log_index <= read) {
+            return false;
+        }
+        self.unread.insert(log_index)
+    }
+
+    /// Mark everything up to and including `log_index` as read. Returns true
+    /// if the read position advanced.
+    pub fn mark_read(&mut self, log_index: u64) -> bool {
+        if self.read_up_to.is_some_and(|read| log_index <= read) {
+            return false;
+        }
+
+        self.read_up_to = Some(log_index);
+        self.unread = self.unread.split_off(&log_index.saturating_add(1));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_above_position_are_unread() {
+        let mut markers = ReadMarkers::new();
+        assert!(markers.record_message(1));
+        assert!(markers.record_message(2));
+        assert!(!markers.record_message(2));
+
+        assert_eq!(markers.unread_count(), 2);
+    }
+
+    #[test]
+    fn mark_read_clears_up_to_position() {
+        let mut markers = ReadMarkers::new();
+        for i in 1..=3 {
+            markers.record_message(i);
+        }
+
+        assert!(markers.mark_read(2));
+        assert_eq!(markers.read_up_to(), Some(2));
+        assert_eq!(markers.unread_count(), 1);
+    }
+
+    #[test]
+    fn position_never_moves_backwards() {
+        let mut markers = ReadMarkers::new();
+        markers.mark_read(5);
+
+        assert!(!markers.mark_read(3));
+        assert_eq!(markers.read_up_to(), Some(5));
+        assert!(!markers.record_message(4));
+        assert_eq!(markers.unread_count(), 0);
+    }
+}
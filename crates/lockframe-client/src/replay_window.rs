@@ -0,0 +1,161 @@
+//! Replay window for application messages.
+//!
+//! Every sender key message is uniquely identified by the sender, the epoch it
+//! was encrypted in, and the ratchet generation of its message key. The window
+//! remembers which of these triples have already been delivered in a room, and
+//! at which log index, so that a message is never handed to the application
+//! twice.
+//!
+//! Seeing the same triple at the same log index again is benign: the server
+//! redelivers frames after a reconnect or an overlapping sync. Seeing it at a
+//! different log index means the ciphertext was submitted for sequencing a
+//! second time, which only happens if someone is replaying captured traffic.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+/// Number of generations remembered per sender, behind the highest seen.
+///
+/// Matches the sender ratchet's skip limit: anything older than this is
+/// rejected by the ratchet itself, so tracking it here buys nothing.
+const WINDOW_SIZE: u32 = 1000;
+
+/// Outcome of checking a message against the replay window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayCheck {
+    /// Message has not been delivered before.
+    Fresh,
+
+    /// Same message at the same log index. Redelivery, safe to drop.
+    Duplicate,
+
+    /// Same message at a different log index. Someone re-submitted it.
+    Replay {
+        /// Log index the message was first delivered at.
+        original_log_index: u64,
+    },
+}
+
+/// Tracks delivered `(sender_id, epoch, generation)` triples for one room.
+///
+/// # Invariants
+///
+/// - Per sender and epoch, at most `WINDOW_SIZE` generations are retained
+/// - Only epochs at or after the last `retain_from_epoch` call are retained
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReplayWindow {
+    /// Delivered generations (`generation` -> `log_index`) per sender and
+    /// epoch.
+    seen: HashMap<(u64, u64), BTreeMap<u32, u64>>,
+}
+
+impl ReplayWindow {
+    /// Create an empty replay window.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check whether a message has already been delivered.
+    ///
+    /// Does not modify the window. Call [`Self::record`] once the message has
+    /// been authenticated, so forged frames cannot poison the window.
+    pub fn check(
+        &self,
+        sender_id: u64,
+        epoch: u64,
+        generation: u32,
+        log_index: u64,
+    ) -> ReplayCheck {
+        let Some(generations) = self.seen.get(&(sender_id, epoch)) else {
+            return ReplayCheck::Fresh;
+        };
+
+        match generations.get(&generation) {
+            None => ReplayCheck::Fresh,
+            Some(&original) if original == log_index => ReplayCheck::Duplicate,
+            Some(&original) => ReplayCheck::Replay { original_log_index: original },
+        }
+    }
+
+    /// Record a delivered message.
+    ///
+    /// Generations more than `WINDOW_SIZE` behind the highest recorded one for
+    /// this sender are forgotten.
+    pub fn record(&mut self, sender_id: u64, epoch: u64, generation: u32, log_index: u64) {
+        let generations = self.seen.entry((sender_id, epoch)).or_default();
+        generations.insert(generation, log_index);
+
+        if let Some((&highest, _)) = generations.last_key_value() {
+            let floor = highest.saturating_sub(WINDOW_SIZE);
+            *generations = generations.split_off(&floor);
+        }
+    }
+
+    /// Forget all entries for epochs before `epoch`.
+    ///
+    /// Called on epoch transitions. Messages from earlier epochs can no longer
+    /// be decrypted, so there is nothing left to protect.
+    pub fn retain_from_epoch(&mut self, epoch: u64) {
+        self.seen.retain(|&(_, entry_epoch), _| entry_epoch >= epoch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unseen_message_is_fresh() {
+        let window = ReplayWindow::new();
+        assert_eq!(window.check(1, 0, 0, 10), ReplayCheck::Fresh);
+    }
+
+    #[test]
+    fn same_log_index_is_duplicate() {
+        let mut window = ReplayWindow::new();
+        window.record(1, 0, 0, 10);
+
+        assert_eq!(window.check(1, 0, 0, 10), ReplayCheck::Duplicate);
+    }
+
+    #[test]
+    fn different_log_index_is_replay() {
+        let mut window = ReplayWindow::new();
+        window.record(1, 0, 0, 10);
+
+        assert_eq!(window.check(1, 0, 0, 42), ReplayCheck::Replay { original_log_index: 10 });
+    }
+
+    #[test]
+    fn key_includes_sender_and_epoch() {
+        let mut window = ReplayWindow::new();
+        window.record(1, 0, 0, 10);
+
+        assert_eq!(window.check(2, 0, 0, 10), ReplayCheck::Fresh);
+        assert_eq!(window.check(1, 1, 0, 10), ReplayCheck::Fresh);
+        assert_eq!(window.check(1, 0, 1, 11), ReplayCheck::Fresh);
+    }
+
+    #[test]
+    fn old_generations_fall_out_of_window() {
+        let mut window = ReplayWindow::new();
+        window.record(1, 0, 0, 0);
+        window.record(1, 0, WINDOW_SIZE + 1, 1);
+
+        assert_eq!(window.check(1, 0, 0, 0), ReplayCheck::Fresh);
+        assert_eq!(window.check(1, 0, WINDOW_SIZE + 1, 1), ReplayCheck::Duplicate);
+    }
+
+    #[test]
+    fn retain_from_epoch_drops_older_epochs() {
+        let mut window = ReplayWindow::new();
+        window.record(1, 0, 0, 0);
+        window.record(1, 1, 0, 1);
+
+        window.retain_from_epoch(1);
+
+        assert_eq!(window.check(1, 0, 0, 0), ReplayCheck::Fresh);
+        assert_eq!(window.check(1, 1, 0, 1), ReplayCheck::Duplicate);
+    }
+}
@@ -0,0 +1,112 @@
+//! Per-room membership as the ratchet tree has it.
+//!
+//! The tree is the authority on who is in a room, but it does not record when
+//! a member joined. The roster remembers the epoch each leaf's occupant was
+//! first seen at, so it survives commits and restarts, and tells the client
+//! when membership changed.
+
+use std::collections::BTreeMap;
+
+use lockframe_core::mls::{Credential, MemberLeaf};
+use serde::{Deserialize, Serialize};
+
+/// A room member, one per client they joined with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberInfo {
+    /// User ID the credential identifies.
+    pub user_id: u64,
+    /// Leaf the member's client holds in the ratchet tree. Leaves are
+    /// reused, so it names a client only for as long as it is a member.
+    /// Rooms stored before the rename call it `device_id`
+    #[serde(alias = "device_id")]
+    pub leaf_index: u32,
+    /// Identity the member presents.
+    pub credential: Credential,
+    /// Ed25519 signature key of the member's client. `None` if malformed.
+    pub signature_key: Option<[u8; 32]>,
+    /// Epoch the member was first seen at. Members already in the group
+    /// when we joined count from the epoch we joined at.
+    pub joined_epoch: u64,
+}
+
+/// Members of one room, by leaf.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Roster {
+    members: BTreeMap<u32, MemberInfo>,
+}
+
+impl Roster {
+    /// Create an empty roster.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current members, in leaf order.
+    pub fn members(&self) -> Vec<MemberInfo> {
+        self.members.values().cloned().collect()
+    }
+
+    /// Replace the members with the tree's `leaves` at `epoch`. A leaf keeps
+    /// its joined epoch while the same user holds it. Returns true if any
+    /// member joined, left or changed credential or key.
+    pub fn update(&mut self, leaves: Vec<MemberLeaf>, epoch: u64) -> bool {
+        let members: BTreeMap<u32, MemberInfo> = leaves
+            .into_iter()
+            .map(|leaf| {
+                let user_id = leaf.credential.member_id();
+                let joined_epoch = self
+                    .members
+                    .get(&leaf.leaf_index)
+                    .filter(|member| member.user_id == user_id)
+                    .map_or(epoch, |member| member.joined_epoch);
+                let member = MemberInfo {
+                    user_id,
+                    leaf_index: leaf.leaf_index,
+                    credential: leaf.credential,
+                    signature_key: leaf.signature_key,
+                    joined_epoch,
+                };
+                (leaf.leaf_index, member)
+            })
+            .collect();
+
+        let changed = members != self.members;
+        self.members = members;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(leaf_index: u32, user_id: u64) -> MemberLeaf {
+        MemberLeaf {
+            leaf_index,
+            credential: Credential::BasicId(user_id),
+            signature_key: Some([leaf_index as u8; 32]),
+        }
+    }
+
+    #[test]
+    fn members_keep_the_epoch_they_joined_at() {
+        let mut roster = Roster::new();
+        assert!(roster.update(vec![leaf(0, 1)], 0));
+        assert!(roster.update(vec![leaf(0, 1), leaf(1, 2)], 1));
+        assert!(!roster.update(vec![leaf(0, 1), leaf(1, 2)], 2), "unchanged membership");
+
+        let joined: Vec<_> = roster.members().iter().map(|m| (m.user_id, m.joined_epoch)).collect();
+        assert_eq!(joined, vec![(1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn a_reused_leaf_counts_as_a_new_member() {
+        let mut roster = Roster::new();
+        roster.update(vec![leaf(0, 1), leaf(1, 2)], 1);
+        assert!(roster.update(vec![leaf(0, 1)], 2));
+        assert!(roster.update(vec![leaf(0, 1), leaf(1, 3)], 3));
+
+        let newcomer = &roster.members()[1];
+        assert_eq!((newcomer.user_id, newcomer.leaf_index, newcomer.joined_epoch), (3, 1, 3));
+    }
+}
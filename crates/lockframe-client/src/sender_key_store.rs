@@ -7,9 +7,22 @@
 use std::collections::HashMap;
 
 use lockframe_crypto::{
-    EncryptedMessage, NONCE_RANDOM_SIZE, SenderKeyError, SymmetricRatchet, decrypt_message,
-    derive_sender_key_seed, encrypt_message,
+    EncryptedMessage, MAX_SKIP, MessageKey, NONCE_RANDOM_SIZE, SenderKeyError, SymmetricRatchet,
+    decrypt_message, derive_sender_key_seed, encrypt_message,
 };
+use serde::{Deserialize, Serialize};
+
+/// Persisted ratchet positions of a [`SenderKeyStore`].
+///
+/// Contains chain keys: anyone holding it can decrypt the room's future
+/// messages for this epoch. Keys of skipped messages are not kept, so a
+/// restored store cannot decrypt messages that were still missing.
+#[derive(Serialize, Deserialize)]
+pub struct SenderKeySnapshot {
+    epoch: u64,
+    /// `(sender_index, chain_key, generation)` per member.
+    ratchets: Vec<(u32, [u8; 32], u32)>,
+}
 
 /// Manages sender key ratchets for all members in a room.
 ///
@@ -20,6 +33,8 @@ use lockframe_crypto::{
 ///
 /// - All ratchets are for the same epoch
 /// - Ratchet generations only increase (forward secrecy)
+/// - A skipped message key is used at most once, and at most `MAX_SKIP` are
+///   kept per sender
 /// - Store is immutable after creation (new epoch = new store)
 pub struct SenderKeyStore {
     /// Current epoch these keys are valid for.
@@ -27,6 +42,10 @@ pub struct SenderKeyStore {
 
     /// Ratchet state per member (`sender_index` -> ratchet).
     ratchets: HashMap<u32, SymmetricRatchet>,
+
+    /// Keys of generations the ratchet skipped past, so messages arriving
+    /// after later ones still decrypt (`(sender_index, generation)` -> key).
+    skipped: HashMap<(u32, u32), MessageKey>,
 }
 
 impl SenderKeyStore {
@@ -42,7 +61,33 @@ impl SenderKeyStore {
             ratchets.insert(sender_index, SymmetricRatchet::new(&seed));
         }
 
-        Self { epoch, ratchets }
+        Self { epoch, ratchets, skipped: HashMap::new() }
+    }
+
+    /// Capture the current ratchet positions for persistence.
+    pub fn snapshot(&self) -> SenderKeySnapshot {
+        let ratchets = self
+            .ratchets
+            .iter()
+            .map(|(&index, ratchet)| (index, *ratchet.chain_key(), ratchet.generation()))
+            .collect();
+
+        SenderKeySnapshot { epoch: self.epoch, ratchets }
+    }
+
+    /// Restore a store from its latest snapshot.
+    ///
+    /// Restoring anything but the latest snapshot reuses message keys.
+    pub fn from_snapshot(snapshot: SenderKeySnapshot) -> Self {
+        let ratchets = snapshot
+            .ratchets
+            .into_iter()
+            .map(|(index, chain_key, generation)| {
+                (index, SymmetricRatchet::from_state(chain_key, generation))
+            })
+            .collect();
+
+        Self { epoch: snapshot.epoch, ratchets, skipped: HashMap::new() }
     }
 
     /// Current MLS epoch for this room.
@@ -80,14 +125,16 @@ impl SenderKeyStore {
 
     /// Decrypt a message from any member.
     ///
-    /// Advances the sender's ratchet to match the message generation.
+    /// Advances the sender's ratchet to match the message generation,
+    /// keeping the keys of generations it skips. A message behind the
+    /// ratchet decrypts with its kept key, which is then discarded.
     ///
     /// # Errors
     ///
     /// - `SenderKeyError::EpochMismatch` if message is for a different epoch
     /// - `SenderKeyError::UnknownSender` if sender not in this store
     /// - `SenderKeyError::RatchetTooFarBehind` if message generation too far
-    ///   ahead
+    ///   ahead, or behind with no kept key (already decrypted or evicted)
     /// - `SenderKeyError::DecryptionFailed` if authentication failed (tampering
     ///   or wrong key)
     pub fn decrypt(&mut self, encrypted: &EncryptedMessage) -> Result<Vec<u8>, SenderKeyError> {
@@ -98,12 +145,36 @@ impl SenderKeyStore {
             });
         }
 
+        let sender_index = encrypted.sender_index;
+        let target = encrypted.generation;
         let ratchet = self
             .ratchets
-            .get_mut(&encrypted.sender_index)
-            .ok_or(SenderKeyError::UnknownSender { sender_index: encrypted.sender_index })?;
+            .get_mut(&sender_index)
+            .ok_or(SenderKeyError::UnknownSender { sender_index })?;
+
+        let current = ratchet.generation();
+        if target < current {
+            let too_far_behind = SenderKeyError::RatchetTooFarBehind { current, requested: target };
+            let message_key = self.skipped.get(&(sender_index, target)).ok_or(too_far_behind)?;
+            // Only a message that authenticates consumes the key, so a forgery
+            // cannot burn the genuine message's key
+            let plaintext = decrypt_message(encrypted, message_key)?;
+            self.skipped.remove(&(sender_index, target));
+            return Ok(plaintext);
+        }
+
+        if target - current > MAX_SKIP {
+            return Err(SenderKeyError::RatchetTooFarBehind { current, requested: target });
+        }
+        while ratchet.generation() < target {
+            let skipped = ratchet.advance()?;
+            self.skipped.insert((sender_index, skipped.generation()), skipped);
+        }
+        let message_key = ratchet.advance()?;
+        self.skipped.retain(|&(index, generation), _| {
+            index != sender_index || target - generation <= MAX_SKIP
+        });
 
-        let message_key = ratchet.advance_to(encrypted.generation)?;
         decrypt_message(encrypted, &message_key)
     }
 
@@ -165,6 +236,22 @@ mod tests {
         assert_eq!(decrypted, plaintext);
     }
 
+    #[test]
+    fn snapshot_restores_ratchet_positions() {
+        let members = vec![0, 1];
+        let mut store = SenderKeyStore::initialize_epoch(&test_epoch_secret(), 1, &members);
+        let _ = store.encrypt(0, b"msg1", [0; NONCE_RANDOM_SIZE]).unwrap();
+
+        let mut restored = SenderKeyStore::from_snapshot(store.snapshot());
+        assert_eq!(restored.epoch(), 1);
+        assert_eq!(restored.generation(0), Some(1));
+        assert_eq!(restored.generation(1), Some(0));
+
+        let encrypted = restored.encrypt(0, b"msg2", [0; NONCE_RANDOM_SIZE]).unwrap();
+        let mut receiver = SenderKeyStore::initialize_epoch(&test_epoch_secret(), 1, &members);
+        assert_eq!(receiver.decrypt(&encrypted).unwrap(), b"msg2");
+    }
+
     #[test]
     fn encrypt_advances_ratchet() {
         let members = vec![0];
@@ -221,7 +308,7 @@ mod tests {
         // Sender encrypts messages 0, 1, 2
         let mut sender_store = SenderKeyStore::initialize_epoch(&epoch_secret, 1, &members);
         let msg0 = sender_store.encrypt(0, b"msg0", [0; NONCE_RANDOM_SIZE]).unwrap();
-        let _msg1 = sender_store.encrypt(0, b"msg1", [1; NONCE_RANDOM_SIZE]).unwrap();
+        let msg1 = sender_store.encrypt(0, b"msg1", [1; NONCE_RANDOM_SIZE]).unwrap();
         let msg2 = sender_store.encrypt(0, b"msg2", [2; NONCE_RANDOM_SIZE]).unwrap();
 
         // Receiver gets them out of order: 2, 0, 1
@@ -231,7 +318,12 @@ mod tests {
         let decrypted = receiver_store.decrypt(&msg2).unwrap();
         assert_eq!(decrypted, b"msg2");
 
-        // msg0 and msg1 are now behind the ratchet - should fail
+        // msg0 and msg1 are behind the ratchet but their keys were kept
+        assert_eq!(receiver_store.decrypt(&msg0).unwrap(), b"msg0");
+        assert_eq!(receiver_store.decrypt(&msg1).unwrap(), b"msg1");
+        assert_eq!(receiver_store.generation(0), Some(3));
+
+        // Each kept key decrypts once
         let result = receiver_store.decrypt(&msg0);
         assert!(matches!(result, Err(SenderKeyError::RatchetTooFarBehind { .. })));
     }
@@ -0,0 +1,153 @@
+//! Client-side room storage.
+//!
+//! The client keeps a bounded number of rooms hydrated in memory. Rooms that
+//! fall out of that set are serialized into a [`ClientStorage`] and loaded
+//! back on their next event, so memory and startup cost scale with the number
+//! of active rooms rather than all rooms. The same storage keeps the block
+//! list and drafts.
+//!
+//! Stored snapshots contain the MLS group's private keys and the sender key
+//! chain keys. Implementations must protect them accordingly.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use lockframe_core::mls::RoomId;
+use thiserror::Error;
+
+/// Errors from client storage operations.
+#[derive(Debug, Error)]
+pub enum ClientStorageError {
+    /// Backend I/O failed.
+    #[error("storage I/O error: {0}")]
+    Io(String),
+
+    /// Room state could not be encoded or decoded.
+    #[error("room state serialization failed: {0}")]
+    Serialization(String),
+
+    /// Room is registered as stored but has no entry.
+    #[error("room {room_id:x} missing from storage")]
+    NotFound {
+        /// Room that was looked up.
+        room_id: RoomId,
+    },
+}
+
+/// Persistence for rooms, the block list and drafts.
+///
+/// A room's entry is written when it is dehydrated and kept while it is
+/// hydrated, so a crash loses at most what happened since. While a room is
+/// in memory its entry may be older than the live state and is never read;
+/// the next dehydrate overwrites it. Leaving a room removes its entry.
+pub trait ClientStorage: Send + Sync {
+    /// Store a dehydrated room, replacing any previous entry.
+    fn store_room(
+        &self,
+        room_id: RoomId,
+        epoch: u64,
+        state: &[u8],
+    ) -> Result<(), ClientStorageError>;
+
+    /// Load a dehydrated room. `None` if not stored.
+    fn load_room(&self, room_id: RoomId) -> Result<Option<Vec<u8>>, ClientStorageError>;
+
+    /// Remove a room's entry. Removing a missing room is not an error.
+    fn remove_room(&self, room_id: RoomId) -> Result<(), ClientStorageError>;
+
+    /// All stored rooms with the epoch they were stored at.
+    fn list_rooms(&self) -> Result<Vec<(RoomId, u64)>, ClientStorageError>;
+
+    /// Replace the stored list of blocked users.
+    fn store_blocked_users(&self, user_ids: &[u64]) -> Result<(), ClientStorageError>;
+
+    /// Load the stored list of blocked users. Empty if none was stored.
+    fn load_blocked_users(&self) -> Result<Vec<u64>, ClientStorageError>;
+
+    /// Store the message being composed in a room. An empty draft removes
+    /// the entry.
+    fn store_draft(&self, room_id: RoomId, text: &str) -> Result<(), ClientStorageError>;
+
+    /// Load the message being composed in a room. `None` if not stored.
+    fn load_draft(&self, room_id: RoomId) -> Result<Option<String>, ClientStorageError>;
+}
+
+/// Stored rooms: `room_id` -> (`epoch`, serialized state).
+type RoomMap = HashMap<RoomId, (u64, Vec<u8>)>;
+
+/// In-memory [`ClientStorage`].
+///
+/// Cloning shares the underlying map, so a test can keep a handle while the
+/// client owns another.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClientStorage {
+    rooms: Arc<RwLock<RoomMap>>,
+    blocked_users: Arc<RwLock<Vec<u64>>>,
+    drafts: Arc<RwLock<HashMap<RoomId, String>>>,
+}
+
+impl MemoryClientStorage {
+    /// Create empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ClientStorage for MemoryClientStorage {
+    fn store_room(
+        &self,
+        room_id: RoomId,
+        epoch: u64,
+        state: &[u8],
+    ) -> Result<(), ClientStorageError> {
+        let mut rooms = self.rooms.write().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        rooms.insert(room_id, (epoch, state.to_vec()));
+        Ok(())
+    }
+
+    fn load_room(&self, room_id: RoomId) -> Result<Option<Vec<u8>>, ClientStorageError> {
+        let rooms = self.rooms.read().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        Ok(rooms.get(&room_id).map(|(_, state)| state.clone()))
+    }
+
+    fn remove_room(&self, room_id: RoomId) -> Result<(), ClientStorageError> {
+        let mut rooms = self.rooms.write().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        rooms.remove(&room_id);
+        Ok(())
+    }
+
+    fn list_rooms(&self) -> Result<Vec<(RoomId, u64)>, ClientStorageError> {
+        let rooms = self.rooms.read().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        Ok(rooms.iter().map(|(&room_id, &(epoch, _))| (room_id, epoch)).collect())
+    }
+
+    fn store_blocked_users(&self, user_ids: &[u64]) -> Result<(), ClientStorageError> {
+        let mut blocked =
+            self.blocked_users.write().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        *blocked = user_ids.to_vec();
+        Ok(())
+    }
+
+    fn load_blocked_users(&self) -> Result<Vec<u64>, ClientStorageError> {
+        let blocked =
+            self.blocked_users.read().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        Ok(blocked.clone())
+    }
+
+    fn store_draft(&self, room_id: RoomId, text: &str) -> Result<(), ClientStorageError> {
+        let mut drafts = self.drafts.write().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        if text.is_empty() {
+            drafts.remove(&room_id);
+        } else {
+            drafts.insert(room_id, text.to_string());
+        }
+        Ok(())
+    }
+
+    fn load_draft(&self, room_id: RoomId) -> Result<Option<String>, ClientStorageError> {
+        let drafts = self.drafts.read().map_err(|e| ClientStorageError::Io(e.to_string()))?;
+        Ok(drafts.get(&room_id).cloned())
+    }
+}
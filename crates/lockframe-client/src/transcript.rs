@@ -0,0 +1,368 @@
+//! Transcript recording and export.
+//!
+//! With recording enabled, the client keeps the decrypted application
+//! messages of each room, keyed by log index. A range of that history can be
+//! exported as a [`TranscriptDocument`]: a self-contained record signed with
+//! the exporter's MLS signature key for the room, serializable as JSON or CBOR.
+//!
+//! Anyone holding a document can check that it was not altered after export
+//! with [`TranscriptDocument::verify`]. Whether the embedded signer key belongs
+//! to the claimed exporter is for the verifier to decide, e.g. by comparing it
+//! against the room's member keys.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use lockframe_core::mls::RoomId;
+use lockframe_proto::payloads::app::AppMessageBody;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Current transcript document format version.
+pub const TRANSCRIPT_VERSION: u8 = 1;
+
+/// Most of our own messages held awaiting their echo. Messages the server
+/// rejected or that were lost are never echoed, so the oldest are dropped
+/// past this.
+const MAX_SENT: usize = 256;
+
+/// Errors from transcript encoding and verification.
+#[derive(Debug, Error)]
+pub enum TranscriptError {
+    /// Serialization failed.
+    #[error("transcript encoding failed: {0}")]
+    Encoding(String),
+
+    /// Deserialization failed.
+    #[error("transcript decoding failed: {0}")]
+    Decoding(String),
+
+    /// Document was produced by a newer format version.
+    #[error("unsupported transcript version: {0}")]
+    UnsupportedVersion(u8),
+
+    /// Signature does not match the document contents.
+    #[error("transcript signature is invalid")]
+    InvalidSignature,
+}
+
+/// One delivered application message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// Log index in the room.
+    pub log_index: u64,
+    /// Sender's stable ID.
+    pub sender_id: u64,
+    /// When the message was received or, for our own, sequenced (Unix
+    /// milliseconds).
+    pub timestamp: u64,
+    /// Decrypted message body.
+    pub body: AppMessageBody,
+}
+
+/// Signed, portable export of a room's decrypted history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptDocument {
+    /// Format version.
+    pub version: u8,
+    /// Room the history belongs to.
+    pub room_id: RoomId,
+    /// Requested log index range, start inclusive.
+    pub start: u64,
+    /// Requested log index range, end exclusive.
+    pub end: u64,
+    /// Sender ID of the exporting client.
+    pub exported_by: u64,
+    /// Messages in the range, ordered by log index.
+    pub entries: Vec<TranscriptEntry>,
+    /// Exporter's Ed25519 signature public key for the room.
+    pub signer_key: [u8; 32],
+    /// Ed25519 signature over all other fields.
+    pub signature: Vec<u8>,
+}
+
+/// The signed portion of a document, borrowed so signing and verification
+/// encode exactly the same bytes.
+#[derive(Serialize)]
+struct SignedContent<'a> {
+    version: u8,
+    room_id: RoomId,
+    start: u64,
+    end: u64,
+    exported_by: u64,
+    entries: &'a [TranscriptEntry],
+    signer_key: [u8; 32],
+}
+
+impl TranscriptDocument {
+    /// Build and sign a document.
+    ///
+    /// `sign` produces an Ed25519 signature over the given bytes with the
+    /// private half of `signer_key`.
+    pub(crate) fn new_signed<E: From<TranscriptError>>(
+        room_id: RoomId,
+        range: Range<u64>,
+        exported_by: u64,
+        entries: Vec<TranscriptEntry>,
+        signer_key: [u8; 32],
+        sign: impl FnOnce(&[u8]) -> Result<[u8; 64], E>,
+    ) -> Result<Self, E> {
+        let mut document = Self {
+            version: TRANSCRIPT_VERSION,
+            room_id,
+            start: range.start,
+            end: range.end,
+            exported_by,
+            entries,
+            signer_key,
+            signature: Vec::new(),
+        };
+
+        let content = document.signed_content()?;
+        document.signature = sign(&content)?.to_vec();
+        Ok(document)
+    }
+
+    /// Check that the document was signed by `signer_key` and not altered.
+    pub fn verify(&self) -> Result<(), TranscriptError> {
+        if self.version > TRANSCRIPT_VERSION {
+            return Err(TranscriptError::UnsupportedVersion(self.version));
+        }
+
+        let key = VerifyingKey::from_bytes(&self.signer_key)
+            .map_err(|_| TranscriptError::InvalidSignature)?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|_| TranscriptError::InvalidSignature)?;
+
+        key.verify_strict(&self.signed_content()?, &signature)
+            .map_err(|_| TranscriptError::InvalidSignature)
+    }
+
+    /// Encode as JSON.
+    pub fn to_json(&self) -> Result<String, TranscriptError> {
+        serde_json::to_string(self).map_err(|e| TranscriptError::Encoding(e.to_string()))
+    }
+
+    /// Decode from JSON. Does not verify the signature.
+    pub fn from_json(json: &str) -> Result<Self, TranscriptError> {
+        serde_json::from_str(json).map_err(|e| TranscriptError::Decoding(e.to_string()))
+    }
+
+    /// Encode as CBOR.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, TranscriptError> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(self, &mut buf)
+            .map_err(|e| TranscriptError::Encoding(e.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Decode from CBOR. Does not verify the signature.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, TranscriptError> {
+        ciborium::de::from_reader(bytes).map_err(|e| TranscriptError::Decoding(e.to_string()))
+    }
+
+    fn signed_content(&self) -> Result<Vec<u8>, TranscriptError> {
+        let content = SignedContent {
+            version: self.version,
+            room_id: self.room_id,
+            start: self.start,
+            end: self.end,
+            exported_by: self.exported_by,
+            entries: &self.entries,
+            signer_key: self.signer_key,
+        };
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&content, &mut buf)
+            .map_err(|e| TranscriptError::Encoding(e.to_string()))?;
+        Ok(buf)
+    }
+}
+
+/// Recorded history for one room.
+///
+/// Our own messages are never delivered back to us, so their bodies are held
+/// by `(epoch, generation)` until the server echoes the sequenced frame and
+/// the log index is known, up to [`MAX_SENT`] of them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    entries: BTreeMap<u64, TranscriptEntry>,
+    sent: BTreeMap<(u64, u32), AppMessageBody>,
+}
+
+impl Transcript {
+    /// Create an empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a delivered message. Typing notifications are not history and
+    /// are skipped.
+    pub fn record(
+        &mut self,
+        log_index: u64,
+        sender_id: u64,
+        timestamp: u64,
+        body: &AppMessageBody,
+    ) {
+        if matches!(body, AppMessageBody::Typing { .. }) {
+            return;
+        }
+
+        self.entries.insert(log_index, TranscriptEntry {
+            log_index,
+            sender_id,
+            timestamp,
+            body: body.clone(),
+        });
+    }
+
+    /// Hold one of our own messages until it is sequenced, dropping the
+    /// oldest held message if [`MAX_SENT`] are waiting.
+    pub fn record_sent(&mut self, epoch: u64, generation: u32, body: &AppMessageBody) {
+        if matches!(body, AppMessageBody::Typing { .. }) {
+            return;
+        }
+        self.sent.insert((epoch, generation), body.clone());
+        if self.sent.len() > MAX_SENT {
+            self.sent.pop_first();
+        }
+    }
+
+    /// Move one of our own messages into history now that it is sequenced.
+    pub fn record_echo(
+        &mut self,
+        epoch: u64,
+        generation: u32,
+        log_index: u64,
+        sender_id: u64,
+        timestamp: u64,
+    ) {
+        if let Some(body) = self.sent.remove(&(epoch, generation)) {
+            self.record(log_index, sender_id, timestamp, &body);
+        }
+    }
+
+    /// Drop a message from history, e.g. once it has disappeared.
+    pub fn forget(&mut self, log_index: u64) {
+        self.entries.remove(&log_index);
+    }
+
+    /// Entries within `range`, ordered by log index.
+    pub fn entries(&self, range: Range<u64>) -> Vec<TranscriptEntry> {
+        self.entries.range(range).map(|(_, entry)| entry.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    fn text(s: &str) -> AppMessageBody {
+        AppMessageBody::Text(s.as_bytes().to_vec())
+    }
+
+    fn signed_document(entries: Vec<TranscriptEntry>) -> TranscriptDocument {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        TranscriptDocument::new_signed(
+            0x1234,
+            0..10,
+            1,
+            entries,
+            key.verifying_key().to_bytes(),
+            |data| Ok::<_, TranscriptError>(key.sign(data).to_bytes()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn typing_is_not_recorded() {
+        let mut transcript = Transcript::new();
+        transcript.record(0, 2, 100, &text("hi"));
+        transcript.record(1, 2, 101, &AppMessageBody::Typing { active: true });
+
+        let entries = transcript.entries(0..u64::MAX);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].body, text("hi"));
+    }
+
+    #[test]
+    fn own_messages_are_recorded_on_echo() {
+        let mut transcript = Transcript::new();
+        transcript.record_sent(0, 0, &text("mine"));
+        assert!(transcript.entries(0..u64::MAX).is_empty());
+
+        transcript.record_echo(0, 0, 5, 1, 100);
+        let entries = transcript.entries(0..u64::MAX);
+        assert_eq!(entries, vec![TranscriptEntry {
+            log_index: 5,
+            sender_id: 1,
+            timestamp: 100,
+            body: text("mine"),
+        }]);
+    }
+
+    #[test]
+    fn unechoed_messages_are_dropped_oldest_first() {
+        let mut transcript = Transcript::new();
+        for generation in 0..=u32::try_from(MAX_SENT).unwrap() {
+            transcript.record_sent(1, generation, &text("mine"));
+        }
+        transcript.record_sent(0, 9, &text("older epoch"));
+        assert_eq!(transcript.sent.len(), MAX_SENT);
+
+        // Generation 0 of epoch 1 went first, then the older epoch's message
+        transcript.record_echo(1, 0, 5, 1, 100);
+        transcript.record_echo(0, 9, 6, 1, 100);
+        transcript.record_echo(1, 1, 7, 1, 100);
+        let entries = transcript.entries(0..u64::MAX);
+        assert_eq!(entries.iter().map(|e| e.log_index).collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn entries_respect_range() {
+        let mut transcript = Transcript::new();
+        for i in 0..5 {
+            transcript.record(i, 2, 100 + i, &text("m"));
+        }
+
+        let indices: Vec<u64> = transcript.entries(1..3).iter().map(|e| e.log_index).collect();
+        assert_eq!(indices, vec![1, 2]);
+    }
+
+    #[test]
+    fn document_roundtrips_and_verifies() {
+        let entries =
+            vec![TranscriptEntry { log_index: 3, sender_id: 2, timestamp: 100, body: text("hi") }];
+        let document = signed_document(entries);
+        document.verify().unwrap();
+
+        let from_json = TranscriptDocument::from_json(&document.to_json().unwrap()).unwrap();
+        assert_eq!(from_json, document);
+        from_json.verify().unwrap();
+
+        let from_cbor = TranscriptDocument::from_cbor(&document.to_cbor().unwrap()).unwrap();
+        assert_eq!(from_cbor, document);
+        from_cbor.verify().unwrap();
+    }
+
+    #[test]
+    fn tampered_document_fails_verification() {
+        let entries =
+            vec![TranscriptEntry { log_index: 3, sender_id: 2, timestamp: 100, body: text("hi") }];
+        let mut document = signed_document(entries);
+        document.entries[0].body = text("bye");
+
+        assert!(matches!(document.verify(), Err(TranscriptError::InvalidSignature)));
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut document = signed_document(Vec::new());
+        document.version = TRANSCRIPT_VERSION + 1;
+
+        assert!(matches!(document.verify(), Err(TranscriptError::UnsupportedVersion(_))));
+    }
+}
@@ -1,16 +1,33 @@
 //! Model-based property tests.
 //!
 //! These tests generate random operation sequences and verify that the real
-//! implementation behaves identically to the reference model.
+//! implementation behaves identically to the reference model. Delivered
+//! plaintexts are compared in the order each client received them after
+//! every send and delivery, so a message dropped, duplicated, reordered or
+//! decrypted wrong shows up at the operation that caused it.
+//!
+//! Clients and the server can crash and restart. Restarted actors are rebuilt
+//! from their storage, so recovery must re-sync rooms without losing or
+//! duplicating messages.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use lockframe_client::{Client, ClientAction, ClientEvent, ClientIdentity};
+use lockframe_client::{
+    Client, ClientAction, ClientConfig, ClientEvent, ClientIdentity, MemoryClientStorage,
+};
+use lockframe_core::{
+    connection::{Connection, ConnectionAction, ConnectionConfig},
+    env::Environment,
+};
 use lockframe_harness::{
     ClientId, ModelMessage, ModelRoomId, ModelWorld, ObservableState, Operation, OperationError,
     OperationResult, SimEnv, SmallMessage,
 };
-use lockframe_proto::{Frame, FrameHeader, Opcode, Payload, payloads::mls::GroupInfoPayload};
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode, Payload,
+    payloads::{mls::GroupInfoPayload, session::Hello},
+};
+use lockframe_server::{DriverConfig, MemoryStorage, ServerAction, ServerDriver, ServerEvent};
 use proptest::prelude::*;
 
 /// Pending frame waiting for delivery.
@@ -23,67 +40,266 @@ struct PendingFrame {
 /// Delivered message for observable state.
 struct DeliveredMessage {
     room_id: ModelRoomId,
-    sender_id: u64,
+    sender_id: ClientId,
     content: Vec<u8>,
     log_index: u64,
     epoch: u64,
 }
 
 /// Real system wrapper that mirrors `ModelWorld`'s interface.
+///
+/// Membership and epochs are read from the clients' MLS groups. Members are
+/// added through the server's `KeyPackage` registry: the invitee publishes a
+/// `KeyPackage`, the inviter fetches it and commits the add, and the invitee
+/// joins from the Welcome.
+///
+/// Clients persist their rooms to storage after every operation. A crash
+/// drops the client and rebuilds it from storage. Frames sent to it while
+/// crashed are held, and on restart it replays them after the last few
+/// frames it had already processed, which its replay window must drop. A
+/// departure no running member can commit waits for the first to restart.
+/// A server crash drops the driver; the restart rebuilds it from storage and
+/// reconnects every running client.
+///
+/// Time is virtual. `AdvanceTime` ticks the server, the clients and their
+/// connections once per [`TICK_INTERVAL`], so heartbeats flow and timeouts
+/// are checked. The model expects no observable change from it: a
+/// connection closing on a timeout disconnects its client, which diverges.
+/// Partitions hold back room traffic only, so heartbeats keep partitioned
+/// clients' sessions open.
 struct RealWorld {
+    env: SimEnv,
     clients: Vec<Client<SimEnv>>,
-    room_membership: HashMap<(ClientId, ModelRoomId), bool>,
-    room_epochs: HashMap<(ClientId, ModelRoomId), u64>,
+    /// Stable sender ID of each client, indexed by model client ID.
+    sender_ids: Vec<u64>,
+    client_storage: Vec<MemoryClientStorage>,
+    connections: Vec<Connection<tokio::time::Instant>>,
+    server: Option<ServerDriver<SimEnv, MemoryStorage>>,
+    server_storage: MemoryStorage,
     pending_frames: Vec<PendingFrame>,
     delivered_messages: Vec<(ClientId, DeliveredMessage)>,
     next_log_index: HashMap<ModelRoomId, u64>,
-    key_packages: HashMap<ClientId, Vec<Vec<u8>>>,
     partitioned: HashMap<ClientId, bool>,
     disconnected: HashMap<ClientId, bool>,
     group_info: HashMap<ModelRoomId, Vec<u8>>,
     server_rooms: HashMap<ModelRoomId, bool>,
+    crashed: HashMap<ClientId, bool>,
+    recent_frames: HashMap<ClientId, Vec<(ModelRoomId, Frame)>>,
+    backlog: HashMap<ClientId, Vec<(ModelRoomId, Frame)>>,
+    deferred_departures: HashMap<ModelRoomId, Vec<ClientId>>,
+}
+
+/// How often a client runtime ticks.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Already-processed frames a restarted client replays before its backlog.
+const REPLAY_OVERLAP: usize = 4;
+
+/// Room ID the real system uses for a model room. Room ID 0 is reserved.
+fn real_room_id(room_id: ModelRoomId) -> u128 {
+    u128::from(room_id) + 1
+}
+
+/// Server session of a model client.
+fn session_id(client_id: ClientId) -> u64 {
+    u64::from(client_id) + 1
 }
 
-const KEY_PACKAGES_PER_CLIENT: usize = 10;
+/// Start a client from its storage, restoring the rooms persisted there.
+fn start_client(env: &SimEnv, sender_id: u64, storage: &MemoryClientStorage) -> Client<SimEnv> {
+    let identity = || ClientIdentity::new(sender_id);
+    match Client::with_storage(
+        env.clone(),
+        identity(),
+        ClientConfig::default(),
+        Box::new(storage.clone()),
+    ) {
+        Ok(client) => client,
+        // Unreadable storage starts empty, which shows up as a divergence
+        Err(_) => Client::new(env.clone(), identity()),
+    }
+}
+
+/// First frame sent with `opcode` in `actions`.
+fn sent_frame(actions: &[ClientAction], opcode: Opcode) -> Option<Frame> {
+    actions.iter().find_map(|action| match action {
+        ClientAction::Send(frame) if frame.header.opcode_enum() == Some(opcode) => {
+            Some(frame.clone())
+        },
+        _ => None,
+    })
+}
 
 impl RealWorld {
     fn new(num_clients: usize, seed: u64) -> Self {
-        let env = SimEnv::with_seed(seed);
-        let mut clients: Vec<Client<SimEnv>> = (0..num_clients)
-            .map(|i| {
-                let identity = ClientIdentity::new(i as u64 + 1);
-                Client::new(env.clone(), identity)
-            })
+        let env = SimEnv::with_manual_clock(seed);
+        let client_storage: Vec<MemoryClientStorage> =
+            (0..num_clients).map(|_| MemoryClientStorage::new()).collect();
+        // Offset from the model IDs, so nothing relies on the two matching
+        let sender_ids: Vec<u64> = (1..=num_clients as u64).collect();
+        let clients = sender_ids
+            .iter()
+            .zip(&client_storage)
+            .map(|(&sender_id, storage)| start_client(&env, sender_id, storage))
             .collect();
+        let connections = (0..num_clients)
+            .map(|_| Connection::new(env.now(), ConnectionConfig::default()))
+            .collect();
+        let server_storage = MemoryStorage::new();
+        let server =
+            ServerDriver::new(env.clone(), server_storage.clone(), DriverConfig::default());
 
-        let mut key_packages = HashMap::new();
-        for (i, client) in clients.iter_mut().enumerate() {
-            let client_id = i as ClientId;
-            let mut packages = Vec::with_capacity(KEY_PACKAGES_PER_CLIENT);
-            for _ in 0..KEY_PACKAGES_PER_CLIENT {
-                if let Ok((kp_bytes, _hash_ref)) = client.generate_key_package() {
-                    packages.push(kp_bytes);
-                }
-            }
-            key_packages.insert(client_id, packages);
-        }
-
-        Self {
+        let mut world = Self {
+            env,
             clients,
-            room_membership: HashMap::new(),
-            room_epochs: HashMap::new(),
+            sender_ids,
+            client_storage,
+            connections,
+            server: Some(server),
+            server_storage,
             pending_frames: Vec::new(),
             delivered_messages: Vec::new(),
             next_log_index: HashMap::new(),
-            key_packages,
             partitioned: HashMap::new(),
             disconnected: HashMap::new(),
             group_info: HashMap::new(),
             server_rooms: HashMap::new(),
+            crashed: HashMap::new(),
+            recent_frames: HashMap::new(),
+            backlog: HashMap::new(),
+            deferred_departures: HashMap::new(),
+        };
+        for client_id in 0..num_clients {
+            world.connect(client_id as ClientId);
+        }
+        world
+    }
+
+    /// Open a client's server session and handshake.
+    ///
+    /// A failed handshake leaves the client unable to publish or fetch
+    /// `KeyPackages`, which shows up as a divergence from the model.
+    fn connect(&mut self, client_id: ClientId) {
+        let now = self.env.now();
+        // The connection's own Hello carries no sender ID, which the server
+        // needs for the KeyPackage registry
+        let _ = self.connections[client_id as usize].send_hello(now);
+        let hello = Payload::Hello(Hello {
+            version: 1,
+            capabilities: vec![],
+            sender_id: Some(self.clients[client_id as usize].sender_id()),
+            auth_token: None,
+        })
+        .into_frame(FrameHeader::new(Opcode::Hello));
+
+        let session_id = session_id(client_id);
+        self.server_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None });
+        if let Ok(frame) = hello {
+            self.send_session_frame(client_id, frame);
         }
     }
 
+    /// Send a session-layer frame from a client's connection to the server.
+    fn send_session_frame(&mut self, client_id: ClientId, frame: Frame) {
+        let event = ServerEvent::FrameReceived { session_id: session_id(client_id), frame };
+        let actions = self.server_event(event);
+        self.handle_server_actions(actions);
+    }
+
+    /// Pass an event to the server. Nothing comes back while it is down.
+    fn server_event(&mut self, event: ServerEvent) -> Vec<ServerAction<tokio::time::Instant>> {
+        self.server.as_mut().and_then(|server| server.process_event(event).ok()).unwrap_or_default()
+    }
+
+    /// Pass the server's session-layer frames to the clients' connections,
+    /// and close the connections it closes.
+    fn handle_server_actions(&mut self, actions: Vec<ServerAction<tokio::time::Instant>>) {
+        let now = self.env.now();
+        for action in actions {
+            match action {
+                ServerAction::SendToSession { session_id, frame }
+                    if matches!(
+                        frame.header.opcode_enum(),
+                        Some(Opcode::HelloReply | Opcode::Ping | Opcode::Pong)
+                    ) =>
+                {
+                    let client_id = (session_id - 1) as ClientId;
+                    let Some(connection) = self.connections.get_mut(client_id as usize) else {
+                        continue;
+                    };
+                    match connection.handle_frame(&frame, now) {
+                        Ok(conn_actions) => self.handle_connection_actions(client_id, conn_actions),
+                        Err(_) => self.drop_connection(client_id),
+                    }
+                },
+                ServerAction::CloseConnection { session_id, .. } => {
+                    self.drop_connection((session_id - 1) as ClientId);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn handle_connection_actions(&mut self, client_id: ClientId, actions: Vec<ConnectionAction>) {
+        for action in actions {
+            match action {
+                ConnectionAction::SendFrame(frame) => self.send_session_frame(client_id, frame),
+                ConnectionAction::Close { .. } => self.drop_connection(client_id),
+            }
+        }
+    }
+
+    /// A client lost its connection, which its runtime handles as a
+    /// disconnect.
+    fn drop_connection(&mut self, client_id: ClientId) {
+        let _ = self.apply_disconnect(client_id);
+    }
+
+    /// Advance virtual time, ticking everything once per `TICK_INTERVAL`.
+    fn apply_advance_time(&mut self, millis: u16) {
+        let mut remaining = Duration::from_millis(u64::from(millis));
+        while !remaining.is_zero() {
+            let step = remaining.min(TICK_INTERVAL);
+            remaining -= step;
+            self.env.advance(step);
+            self.tick();
+        }
+    }
+
+    /// Connections are not ticked while the server is down, so they do not
+    /// time out waiting for it.
+    fn tick(&mut self) {
+        let now = self.env.now();
+        let server_up = self.server.is_some();
+        let actions = self.server_event(ServerEvent::Tick);
+        self.handle_server_actions(actions);
+
+        for client_id in 0..self.clients.len() as ClientId {
+            if self.is_crashed(client_id)
+                || self.disconnected.get(&client_id).copied().unwrap_or(false)
+            {
+                continue;
+            }
+            if server_up {
+                let actions = self.connections[client_id as usize].tick(now);
+                self.handle_connection_actions(client_id, actions);
+            }
+
+            // Timeouts resolve operations still in flight, and every
+            // operation completes within its step, so there is nothing to act on
+            let _ = self.clients[client_id as usize].handle(ClientEvent::Tick { now });
+        }
+    }
+
+    /// Apply an operation, then persist every running client's rooms so a
+    /// crash at any point restores the state after the last operation.
     fn apply(&mut self, op: &Operation) -> OperationResult {
+        let result = self.apply_operation(op);
+        self.checkpoint();
+        result
+    }
+
+    fn apply_operation(&mut self, op: &Operation) -> OperationResult {
         match op {
             Operation::CreateRoom { client_id, room_id } => {
                 self.apply_create_room(*client_id, *room_id)
@@ -103,7 +319,10 @@ impl RealWorld {
             Operation::RemoveMember { remover_id, target_id, room_id } => {
                 self.apply_remove_member(*remover_id, *target_id, *room_id)
             },
-            Operation::AdvanceTime { .. } => OperationResult::Ok,
+            Operation::AdvanceTime { millis } => {
+                self.apply_advance_time(*millis);
+                OperationResult::Ok
+            },
             Operation::DeliverPending => {
                 self.apply_deliver_pending();
                 OperationResult::Ok
@@ -111,10 +330,181 @@ impl RealWorld {
             Operation::Partition { client_id } => self.apply_partition(*client_id),
             Operation::HealPartition { client_id } => self.apply_heal_partition(*client_id),
             Operation::Disconnect { client_id } => self.apply_disconnect(*client_id),
+            Operation::CrashClient { client_id } => self.apply_crash_client(*client_id),
+            Operation::RestartClient { client_id } => self.apply_restart_client(*client_id),
+            Operation::CrashServer => {
+                self.server = None;
+                OperationResult::Ok
+            },
+            Operation::RestartServer => {
+                self.apply_restart_server();
+                OperationResult::Ok
+            },
+        }
+    }
+
+    fn checkpoint(&mut self) {
+        for client_id in 0..self.clients.len() as ClientId {
+            if !self.is_crashed(client_id) {
+                let _ = self.clients[client_id as usize].dehydrate_idle_rooms();
+            }
         }
     }
 
+    fn is_crashed(&self, client_id: ClientId) -> bool {
+        self.crashed.get(&client_id).copied().unwrap_or(false)
+    }
+
+    /// Error for an operation `actors` must take part in, if any of them has
+    /// crashed or the server is down.
+    fn unavailable(&self, actors: &[ClientId]) -> Option<OperationError> {
+        if actors.iter().any(|&cid| self.is_crashed(cid)) {
+            return Some(OperationError::Crashed);
+        }
+        self.server.is_none().then_some(OperationError::ServerUnavailable)
+    }
+
+    /// Whether a client is in a room. A client leaving it has left as far
+    /// as the model is concerned, with only the commit removing it to come.
+    fn is_member(&self, client_id: ClientId, room_id: ModelRoomId) -> bool {
+        let room_id = real_room_id(room_id);
+        self.clients
+            .get(client_id as usize)
+            .is_some_and(|client| client.is_member(room_id) && !client.is_leaving(room_id))
+    }
+
+    /// Current MLS epoch of a client's group, 0 if not a member.
+    fn epoch(&self, client_id: ClientId, room_id: ModelRoomId) -> u64 {
+        self.clients
+            .get(client_id as usize)
+            .and_then(|client| client.epoch(real_room_id(room_id)))
+            .unwrap_or(0)
+    }
+
+    /// Members of a room, in client order.
+    fn members(&self, room_id: ModelRoomId) -> Vec<ClientId> {
+        (0..self.clients.len() as ClientId).filter(|&cid| self.is_member(cid, room_id)).collect()
+    }
+
+    /// Send a client's outgoing frames to the server, returning the frames
+    /// the server sends back to that client.
+    fn send_to_server(&mut self, client_id: ClientId, actions: &[ClientAction]) -> Vec<Frame> {
+        let session_id = session_id(client_id);
+        let mut replies = Vec::new();
+        for action in actions {
+            let ClientAction::Send(frame) = action else { continue };
+            let event = ServerEvent::FrameReceived { session_id, frame: frame.clone() };
+            for server_action in self.server_event(event) {
+                if let ServerAction::SendToSession { session_id: s, frame } = server_action
+                    && s == session_id
+                {
+                    replies.push(frame);
+                }
+            }
+        }
+        replies
+    }
+
+    /// Drop the messages a client received in a room it no longer belongs
+    /// to, as the model does.
+    fn forget_room(&mut self, client_id: ClientId, room_id: ModelRoomId) {
+        self.delivered_messages.retain(|(cid, dm)| *cid != client_id || dm.room_id != room_id);
+    }
+
+    /// Keep the latest `GroupInfo` published for a room.
+    fn record_group_info(&mut self, room_id: ModelRoomId, actions: &[ClientAction]) {
+        if let Some(frame) = sent_frame(actions, Opcode::GroupInfo)
+            && let Ok(Payload::GroupInfo(gi)) = Payload::from_frame(&frame)
+        {
+            self.group_info.insert(room_id, gi.group_info_bytes);
+        }
+    }
+
+    /// Pass a room frame to a client, recording the messages it delivers,
+    /// and return the client's actions. A crashed client holds the frame
+    /// until it restarts.
+    fn receive_frame(
+        &mut self,
+        client_id: ClientId,
+        room_id: ModelRoomId,
+        frame: &Frame,
+    ) -> Vec<ClientAction> {
+        if self.is_crashed(client_id) {
+            self.backlog.entry(client_id).or_default().push((room_id, frame.clone()));
+            return Vec::new();
+        }
+
+        let Some(client) = self.clients.get_mut(client_id as usize) else { return Vec::new() };
+        let actions = client.handle(ClientEvent::FrameReceived(frame.clone())).unwrap_or_default();
+        self.record_group_info(room_id, &actions);
+        for action in &actions {
+            if let ClientAction::DeliverMessage { sender_id, plaintext, log_index, .. } = action {
+                // A sender outside the table goes unrecorded, which diverges
+                let Some(sender) = self.sender_ids.iter().position(|id| id == sender_id) else {
+                    continue;
+                };
+                self.delivered_messages.push((client_id, DeliveredMessage {
+                    room_id,
+                    sender_id: sender as ClientId,
+                    content: plaintext.clone(),
+                    log_index: *log_index,
+                    epoch: frame.header.epoch(),
+                }));
+            }
+        }
+
+        let recent = self.recent_frames.entry(client_id).or_default();
+        recent.push((room_id, frame.clone()));
+        if recent.len() > REPLAY_OVERLAP {
+            recent.remove(0);
+        }
+        actions
+    }
+
+    /// Deliver a commit to `recipients`, its sender included so it merges
+    /// its pending commit.
+    fn deliver_commit(&mut self, room_id: ModelRoomId, commit: &Frame, recipients: &[ClientId]) {
+        for &recipient_id in recipients {
+            self.receive_frame(recipient_id, room_id, commit);
+        }
+    }
+
+    /// Remove a departed client from the MLS group, committed by the
+    /// lowest running member so the others advance their epoch, and a
+    /// departed client still leaving drops the room. Deferred if every
+    /// remaining member has crashed.
+    fn commit_departure(&mut self, departed_id: ClientId, room_id: ModelRoomId) {
+        let remaining = self.members(room_id);
+        if remaining.is_empty() {
+            self.group_info.remove(&room_id);
+            return;
+        }
+        let Some(&committer_id) = remaining.iter().find(|&&cid| !self.is_crashed(cid)) else {
+            self.deferred_departures.entry(room_id).or_default().push(departed_id);
+            return;
+        };
+
+        let member_id = self.clients[departed_id as usize].sender_id();
+        let Ok(actions) = self.clients[committer_id as usize].handle(ClientEvent::RemoveMembers {
+            room_id: real_room_id(room_id),
+            member_ids: vec![member_id],
+        }) else {
+            return;
+        };
+
+        if let Some(commit) = sent_frame(&actions, Opcode::Commit) {
+            self.deliver_commit(room_id, &commit, &remaining);
+            if self.clients[departed_id as usize].is_leaving(real_room_id(room_id)) {
+                self.receive_frame(departed_id, room_id, &commit);
+            }
+        }
+    }
+
+    /// Waits for the server if it is down.
     fn apply_deliver_pending(&mut self) {
+        if self.server.is_none() {
+            return;
+        }
         let pending = std::mem::take(&mut self.pending_frames);
 
         for pf in pending {
@@ -122,39 +512,10 @@ impl RealWorld {
                 if self.partitioned.get(&recipient_id).copied().unwrap_or(false) {
                     continue;
                 }
-                if !self.room_membership.get(&(recipient_id, pf.room_id)).copied().unwrap_or(false)
-                {
+                if !self.is_member(recipient_id, pf.room_id) {
                     continue;
                 }
-
-                let Some(client) = self.clients.get_mut(recipient_id as usize) else { continue };
-
-                let result = client.handle(ClientEvent::FrameReceived(pf.frame.clone()));
-                if let Ok(actions) = result {
-                    for action in actions {
-                        if let ClientAction::DeliverMessage {
-                            sender_id,
-                            plaintext,
-                            log_index,
-                            ..
-                        } = action
-                        {
-                            // Use tracked epoch, not frame epoch (MLS epoch lags until commit)
-                            let recipient_epoch = self
-                                .room_epochs
-                                .get(&(recipient_id, pf.room_id))
-                                .copied()
-                                .unwrap_or(0);
-                            self.delivered_messages.push((recipient_id, DeliveredMessage {
-                                room_id: pf.room_id,
-                                sender_id,
-                                content: plaintext,
-                                log_index,
-                                epoch: recipient_epoch,
-                            }));
-                        }
-                    }
-                }
+                self.receive_frame(recipient_id, pf.room_id, &pf.frame);
             }
         }
     }
@@ -166,39 +527,27 @@ impl RealWorld {
         let mut client_messages: Vec<Vec<(ModelRoomId, Vec<ModelMessage>)>> =
             vec![Vec::new(); num_clients];
 
-        for (&(client_id, room_id), &is_member) in &self.room_membership {
-            if is_member {
+        let mut rooms: Vec<ModelRoomId> = self.server_rooms.keys().copied().collect();
+        rooms.sort_unstable();
+        for &room_id in &rooms {
+            for client_id in self.members(room_id) {
                 let idx = client_id as usize;
-                if idx < num_clients {
-                    client_rooms[idx].push(room_id);
-                    let epoch = self.room_epochs.get(&(client_id, room_id)).copied().unwrap_or(0);
-                    client_epochs[idx].push((room_id, epoch));
-                }
+                client_rooms[idx].push(room_id);
+                client_epochs[idx].push((room_id, self.epoch(client_id, room_id)));
             }
         }
 
-        for rooms in &mut client_rooms {
-            rooms.sort_unstable();
-        }
-        for epochs in &mut client_epochs {
-            epochs.sort_by_key(|(r, _)| *r);
-        }
-
         let mut msg_map: HashMap<(ClientId, ModelRoomId), Vec<ModelMessage>> = HashMap::new();
         for (client_id, dm) in &self.delivered_messages {
             let key = (*client_id, dm.room_id);
             msg_map.entry(key).or_default().push(ModelMessage {
-                sender_id: dm.sender_id as ClientId,
+                sender_id: dm.sender_id,
                 content: dm.content.clone(),
                 log_index: dm.log_index,
                 epoch: dm.epoch,
             });
         }
 
-        for msgs in msg_map.values_mut() {
-            msgs.sort_by_key(|m| m.log_index);
-        }
-
         for (idx, rooms) in client_rooms.iter().enumerate() {
             let client_id = idx as ClientId;
             let mut room_msgs = Vec::new();
@@ -229,88 +578,62 @@ impl RealWorld {
         if invitee_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
         }
+        if let Some(e) = self.unavailable(&[inviter_id, invitee_id]) {
+            return OperationResult::Error(e);
+        }
 
-        if !self.room_membership.get(&(inviter_id, room_id)).copied().unwrap_or(false) {
+        if !self.is_member(inviter_id, room_id) {
             return OperationResult::Error(OperationError::NotMember);
         }
 
-        if self.room_membership.get(&(invitee_id, room_id)).copied().unwrap_or(false) {
+        if self.is_member(invitee_id, room_id) {
             return OperationResult::Error(OperationError::AlreadyMember);
         }
 
-        let Some(key_package) = self.key_packages.get_mut(&invitee_id).and_then(Vec::pop) else {
-            return OperationResult::Error(OperationError::NotMember);
-        };
+        let real_room_id = real_room_id(room_id);
+        let invitee_user_id = self.clients[invitee_id as usize].sender_id();
 
-        let real_room_id = u128::from(room_id) + 1;
+        let Ok(publish) = self.clients[invitee_id as usize].handle(ClientEvent::PublishKeyPackage)
+        else {
+            return OperationResult::Error(OperationError::NoKeyPackage);
+        };
+        self.send_to_server(invitee_id, &publish);
 
-        let inviter = &mut self.clients[inviter_id as usize];
-        let add_result = inviter.handle(ClientEvent::AddMembers {
+        let Ok(fetch) = self.clients[inviter_id as usize].handle(ClientEvent::FetchAndAddMember {
             room_id: real_room_id,
-            key_packages: vec![key_package],
-        });
-
-        let Ok(actions) = add_result else {
+            user_id: invitee_user_id,
+        }) else {
             return OperationResult::Error(OperationError::NotMember);
         };
 
-        let welcome_frame = actions.iter().find_map(|action| {
-            if let ClientAction::Send(frame) = action
-                && frame.header.opcode_enum() == Some(Opcode::Welcome)
-            {
-                return Some(frame.clone());
-            }
-            None
-        });
-
-        // Deliver commits immediately so members can process the epoch transition
-        for action in &actions {
-            if let ClientAction::Send(frame) = action
-                && frame.header.opcode_enum() == Some(Opcode::Commit)
+        let mut actions = Vec::new();
+        for reply in self.send_to_server(inviter_id, &fetch) {
+            if let Ok(reply_actions) =
+                self.clients[inviter_id as usize].handle(ClientEvent::FrameReceived(reply))
             {
-                let recipients: Vec<ClientId> = self
-                    .room_membership
-                    .iter()
-                    .filter(|&(&(_, rid), &m)| m && rid == room_id)
-                    .map(|(&(cid, _), _)| cid)
-                    .collect();
-
-                for &recipient_id in &recipients {
-                    if let Some(client) = self.clients.get_mut(recipient_id as usize) {
-                        let _ = client.handle(ClientEvent::FrameReceived(frame.clone()));
-                    }
-                }
+                actions.extend(reply_actions);
             }
         }
 
-        if let Some(welcome) = welcome_frame {
-            let invitee = &mut self.clients[invitee_id as usize];
-            let join_result = invitee.handle(ClientEvent::JoinRoom {
-                room_id: real_room_id,
-                welcome: welcome.payload.to_vec(),
-            });
-
-            if join_result.is_err() {
-                return OperationResult::Error(OperationError::NotMember);
-            }
-        }
+        let (Some(commit), Some(welcome)) =
+            (sent_frame(&actions, Opcode::Commit), sent_frame(&actions, Opcode::Welcome))
+        else {
+            return OperationResult::Error(OperationError::NoKeyPackage);
+        };
 
-        let members: Vec<ClientId> = self
-            .room_membership
-            .iter()
-            .filter(|&(&(_, rid), &m)| m && rid == room_id)
-            .map(|(&(cid, _), _)| cid)
-            .collect();
+        // Deliver commits immediately so members can process the epoch transition
+        let members = self.members(room_id);
+        self.deliver_commit(room_id, &commit, &members);
 
-        for cid in &members {
-            let epoch = self.room_epochs.entry((*cid, room_id)).or_insert(0);
-            *epoch += 1;
+        let invitee = &mut self.clients[invitee_id as usize];
+        let join_result = invitee.handle(ClientEvent::JoinRoom {
+            room_id: real_room_id,
+            welcome: welcome.payload.to_vec(),
+        });
+        if join_result.is_err() {
+            return OperationResult::Error(OperationError::NotMember);
         }
 
-        let new_epoch = self.room_epochs.get(&(inviter_id, room_id)).copied().unwrap_or(0);
-        self.room_membership.insert((invitee_id, room_id), true);
-        self.room_epochs.insert((invitee_id, room_id), new_epoch);
-
         OperationResult::Ok
     }
 
@@ -323,11 +646,20 @@ impl RealWorld {
             return OperationResult::Error(OperationError::InvalidClient);
         }
 
-        if self.room_membership.get(&(joiner_id, room_id)).copied().unwrap_or(false) {
+        if let Some(e) = self.unavailable(&[joiner_id]) {
+            return OperationResult::Error(e);
+        }
+
+        if self.is_member(joiner_id, room_id) {
             return OperationResult::Error(OperationError::AlreadyMember);
         }
 
-        let real_room_id = u128::from(room_id) + 1;
+        // The GroupInfo is stale until a member commits the deferred departure
+        if self.deferred_departures.contains_key(&room_id) {
+            return OperationResult::Error(OperationError::Crashed);
+        }
+
+        let real_room_id = real_room_id(room_id);
 
         let joiner = &mut self.clients[joiner_id as usize];
         if joiner.handle(ClientEvent::ExternalJoin { room_id: real_room_id }).is_err() {
@@ -341,8 +673,9 @@ impl RealWorld {
             },
         };
 
-        let current_epoch =
-            self.room_epochs.iter().find(|&(&(_, rid), _)| rid == room_id).map_or(0, |(_, &e)| e);
+        // Crashed members lag behind by the commits they hold
+        let members = self.members(room_id);
+        let current_epoch = members.iter().map(|&cid| self.epoch(cid, room_id)).max().unwrap_or(0);
 
         let payload =
             GroupInfoPayload { room_id: real_room_id, epoch: current_epoch, group_info_bytes };
@@ -352,62 +685,21 @@ impl RealWorld {
             return OperationResult::Error(OperationError::NoGroupInfo);
         };
 
+        let joiner = &mut self.clients[joiner_id as usize];
         let Ok(join_actions) = joiner.handle(ClientEvent::FrameReceived(frame)) else {
             return OperationResult::Error(OperationError::NoGroupInfo);
         };
+        self.record_group_info(room_id, &join_actions);
 
-        let commit = join_actions.iter().find_map(|a| {
-            if let ClientAction::Send(frame) = a
-                && matches!(
-                    frame.header.opcode_enum(),
-                    Some(Opcode::Commit | Opcode::ExternalCommit)
-                )
-            {
-                return Some(frame.clone());
-            }
-            None
-        });
-
+        let commit = sent_frame(&join_actions, Opcode::ExternalCommit)
+            .or_else(|| sent_frame(&join_actions, Opcode::Commit));
         if let Some(commit) = commit {
-            let existing_members: Vec<ClientId> = self
-                .room_membership
-                .iter()
-                .filter(|&(&(_, rid), &m)| m && rid == room_id)
-                .map(|(&(cid, _), _)| cid)
-                .collect();
-
-            for member_id in existing_members {
-                if let Some(client) = self.clients.get_mut(member_id as usize) {
-                    let _ = client.handle(ClientEvent::FrameReceived(commit.clone()));
-                }
-            }
-        }
-
-        for action in &join_actions {
-            if let ClientAction::Send(frame) = action
-                && frame.header.opcode_enum() == Some(Opcode::GroupInfo)
-                && let Ok(Payload::GroupInfo(gi)) = Payload::from_frame(frame)
-            {
-                self.group_info.insert(room_id, gi.group_info_bytes);
-            }
-        }
-
-        let new_epoch = current_epoch + 1;
-        let existing_members: Vec<ClientId> = self
-            .room_membership
-            .iter()
-            .filter(|&(&(_, rid), &m)| m && rid == room_id)
-            .map(|(&(cid, _), _)| cid)
-            .collect();
-
-        for cid in &existing_members {
-            let epoch = self.room_epochs.entry((*cid, room_id)).or_insert(0);
-            *epoch += 1;
+            let mut recipients: Vec<ClientId> =
+                members.into_iter().filter(|&cid| cid != joiner_id).collect();
+            recipients.push(joiner_id);
+            self.deliver_commit(room_id, &commit, &recipients);
         }
 
-        self.room_membership.insert((joiner_id, room_id), true);
-        self.room_epochs.insert((joiner_id, room_id), new_epoch);
-
         OperationResult::Ok
     }
 
@@ -427,16 +719,19 @@ impl RealWorld {
         if remover_id == target_id {
             return OperationResult::Error(OperationError::CannotRemoveSelf);
         }
+        if let Some(e) = self.unavailable(&[remover_id, target_id]) {
+            return OperationResult::Error(e);
+        }
 
-        if !self.room_membership.get(&(remover_id, room_id)).copied().unwrap_or(false) {
+        if !self.is_member(remover_id, room_id) {
             return OperationResult::Error(OperationError::NotMember);
         }
 
-        if !self.room_membership.get(&(target_id, room_id)).copied().unwrap_or(false) {
+        if !self.is_member(target_id, room_id) {
             return OperationResult::Error(OperationError::NotMember);
         }
 
-        let real_room_id = u128::from(room_id) + 1;
+        let real_room_id = real_room_id(room_id);
         let target_member_id = self.clients[target_id as usize].sender_id();
 
         let remover = &mut self.clients[remover_id as usize];
@@ -448,41 +743,15 @@ impl RealWorld {
         let Ok(actions) = remove_result else {
             return OperationResult::Error(OperationError::NotMember);
         };
+        let Some(commit) = sent_frame(&actions, Opcode::Commit) else {
+            return OperationResult::Error(OperationError::NotMember);
+        };
 
-        // Deliver commits immediately so members can process the epoch transition
-        for action in &actions {
-            if let ClientAction::Send(frame) = action
-                && frame.header.opcode_enum() == Some(Opcode::Commit)
-            {
-                let recipients: Vec<ClientId> = self
-                    .room_membership
-                    .iter()
-                    .filter(|&(&(cid, rid), &m)| m && rid == room_id && cid != target_id)
-                    .map(|(&(cid, _), _)| cid)
-                    .collect();
-
-                for &recipient_id in &recipients {
-                    if let Some(client) = self.clients.get_mut(recipient_id as usize) {
-                        let _ = client.handle(ClientEvent::FrameReceived(frame.clone()));
-                    }
-                }
-            }
-        }
-
-        self.room_membership.insert((target_id, room_id), false);
-        self.room_epochs.remove(&(target_id, room_id));
-
-        let remaining: Vec<ClientId> = self
-            .room_membership
-            .iter()
-            .filter(|&(&(_, rid), &m)| m && rid == room_id)
-            .map(|(&(cid, _), _)| cid)
-            .collect();
-
-        for cid in &remaining {
-            let epoch = self.room_epochs.entry((*cid, room_id)).or_insert(0);
-            *epoch += 1;
-        }
+        // Deliver commits immediately so members can process the epoch
+        // transition, and the removed client drops the room
+        let members = self.members(room_id);
+        self.deliver_commit(room_id, &commit, &members);
+        self.forget_room(target_id, room_id);
 
         OperationResult::Ok
     }
@@ -491,12 +760,15 @@ impl RealWorld {
         if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
         }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
         if self.disconnected.get(&client_id).copied().unwrap_or(false) {
             return OperationResult::Error(OperationError::Disconnected);
         }
 
-        let real_room_id = u128::from(room_id) + 1;
+        let real_room_id = real_room_id(room_id);
 
         if self.server_rooms.contains_key(&room_id) {
             // Room persists even after all clients disconnect to prevent multiple clients
@@ -504,7 +776,7 @@ impl RealWorld {
             return OperationResult::Error(OperationError::RoomAlreadyExists);
         }
 
-        if self.room_membership.get(&(client_id, room_id)).copied().unwrap_or(false) {
+        if self.is_member(client_id, room_id) {
             return OperationResult::Error(OperationError::RoomAlreadyExists);
         }
 
@@ -514,21 +786,10 @@ impl RealWorld {
         match result {
             Ok(actions) => {
                 self.server_rooms.insert(room_id, true);
-                self.room_membership.insert((client_id, room_id), true);
-                self.room_epochs.insert((client_id, room_id), 0);
-
-                for action in &actions {
-                    if let ClientAction::Send(frame) = action
-                        && frame.header.opcode_enum() == Some(Opcode::GroupInfo)
-                        && let Ok(Payload::GroupInfo(gi)) = Payload::from_frame(frame)
-                    {
-                        self.group_info.entry(room_id).or_insert(gi.group_info_bytes);
-                    }
-                }
-
+                self.record_group_info(room_id, &actions);
                 OperationResult::Ok
             },
-            Err(_) => OperationResult::Error(OperationError::RoomAlreadyExists),
+            Err(e) => OperationResult::Error(OperationError::from(&e)),
         }
     }
 
@@ -538,37 +799,37 @@ impl RealWorld {
         room_id: ModelRoomId,
         content: &SmallMessage,
     ) -> OperationResult {
-        let Some(client) = self.clients.get_mut(client_id as usize) else {
+        if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
-        };
+        }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
         if self.partitioned.get(&client_id).copied().unwrap_or(false) {
             return OperationResult::Error(OperationError::Partitioned);
         }
 
-        if !self.room_membership.get(&(client_id, room_id)).copied().unwrap_or(false) {
+        if !self.is_member(client_id, room_id) {
             return OperationResult::Error(OperationError::NotMember);
         }
 
-        let real_room_id = u128::from(room_id) + 1;
+        let real_room_id = real_room_id(room_id);
         let plaintext = content.to_bytes();
 
-        let result = client.handle(ClientEvent::SendMessage {
+        let result = self.clients[client_id as usize].handle(ClientEvent::SendMessage {
             room_id: real_room_id,
             plaintext: plaintext.clone(),
         });
 
         match result {
             Ok(actions) => {
+                let other_recipients: Vec<ClientId> =
+                    self.members(room_id).into_iter().filter(|&cid| cid != client_id).collect();
+                let sender_epoch = self.epoch(client_id, room_id);
+
                 for action in actions {
                     if let ClientAction::Send(frame) = action {
-                        let other_recipients: Vec<ClientId> = self
-                            .room_membership
-                            .iter()
-                            .filter(|&(&(cid, rid), &m)| m && rid == room_id && cid != client_id)
-                            .map(|(&(cid, _), _)| cid)
-                            .collect();
-
                         let log_index_ref = self.next_log_index.entry(room_id).or_insert(0);
                         let log_index_val = *log_index_ref;
                         *log_index_ref += 1;
@@ -576,12 +837,9 @@ impl RealWorld {
                         let mut sequenced_frame = frame;
                         sequenced_frame.header.set_log_index(log_index_val);
 
-                        let sender_epoch =
-                            self.room_epochs.get(&(client_id, room_id)).copied().unwrap_or(0);
-
                         self.delivered_messages.push((client_id, DeliveredMessage {
                             room_id,
-                            sender_id: u64::from(client_id),
+                            sender_id: client_id,
                             content: plaintext.clone(),
                             log_index: log_index_val,
                             epoch: sender_epoch,
@@ -591,55 +849,60 @@ impl RealWorld {
                             self.pending_frames.push(PendingFrame {
                                 room_id,
                                 frame: sequenced_frame,
-                                recipients: other_recipients,
+                                recipients: other_recipients.clone(),
                             });
                         }
                     }
                 }
                 OperationResult::Ok
             },
-            Err(_) => OperationResult::Error(OperationError::NotMember),
+            Err(e) => OperationResult::Error(OperationError::from(&e)),
         }
     }
 
     fn apply_leave_room(&mut self, client_id: ClientId, room_id: ModelRoomId) -> OperationResult {
-        let Some(client) = self.clients.get_mut(client_id as usize) else {
+        if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
-        };
+        }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
-        if !self.room_membership.get(&(client_id, room_id)).copied().unwrap_or(false) {
+        if !self.is_member(client_id, room_id) {
             return OperationResult::Error(OperationError::NotMember);
         }
 
-        let real_room_id = u128::from(room_id) + 1;
-
-        let result = client.handle(ClientEvent::LeaveRoom { room_id: real_room_id });
-
-        match result {
-            Ok(_) => {
-                self.room_membership.insert((client_id, room_id), false);
-                self.room_epochs.remove(&(client_id, room_id));
-
-                let remaining: Vec<ClientId> = self
-                    .room_membership
-                    .iter()
-                    .filter(|&(&(_, rid), &m)| m && rid == room_id)
-                    .map(|(&(cid, _), _)| cid)
-                    .collect();
-
-                if remaining.is_empty() {
-                    self.group_info.remove(&room_id);
-                }
-
-                for cid in &remaining {
-                    let epoch = self.room_epochs.entry((*cid, room_id)).or_insert(0);
-                    *epoch += 1;
-                }
+        let others: Vec<ClientId> =
+            self.members(room_id).into_iter().filter(|&cid| cid != client_id).collect();
+        let client = &mut self.clients[client_id as usize];
+        let actions = match client.handle(ClientEvent::LeaveRoom { room_id: real_room_id(room_id) })
+        {
+            Ok(actions) => actions,
+            Err(e) => return OperationResult::Error(OperationError::from(&e)),
+        };
+        self.forget_room(client_id, room_id);
 
-                OperationResult::Ok
+        // The sole member leaves at once. Otherwise the model treats a leave
+        // as finished at once, so the proposal goes to every other member and
+        // the designated committer's commit to everyone straight away
+        let Some(proposal) = sent_frame(&actions, Opcode::Proposal) else {
+            return OperationResult::Ok;
+        };
+        let mut commit = None;
+        for &member_id in &others {
+            let actions = self.receive_frame(member_id, room_id, &proposal);
+            commit = commit.or_else(|| sent_frame(&actions, Opcode::Commit));
+        }
+        match commit {
+            Some(commit) => {
+                self.deliver_commit(room_id, &commit, &others);
+                self.receive_frame(client_id, room_id, &commit);
             },
-            Err(_) => OperationResult::Error(OperationError::NotMember),
+            // The designated committer has crashed, so the lowest running
+            // member removes us instead
+            None => self.commit_departure(client_id, room_id),
         }
+        OperationResult::Ok
     }
 
     fn apply_partition(&mut self, client_id: ClientId) -> OperationResult {
@@ -647,6 +910,10 @@ impl RealWorld {
             return OperationResult::Error(OperationError::InvalidClient);
         }
 
+        if self.is_crashed(client_id) {
+            return OperationResult::Error(OperationError::Crashed);
+        }
+
         if self.disconnected.get(&client_id).copied().unwrap_or(false) {
             return OperationResult::Error(OperationError::Disconnected);
         }
@@ -660,6 +927,10 @@ impl RealWorld {
             return OperationResult::Error(OperationError::InvalidClient);
         }
 
+        if self.is_crashed(client_id) {
+            return OperationResult::Error(OperationError::Crashed);
+        }
+
         if self.disconnected.get(&client_id).copied().unwrap_or(false) {
             return OperationResult::Error(OperationError::Disconnected);
         }
@@ -672,6 +943,9 @@ impl RealWorld {
         if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
         }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
         if self.disconnected.get(&client_id).copied().unwrap_or(false) {
             return OperationResult::Error(OperationError::Disconnected);
@@ -679,40 +953,129 @@ impl RealWorld {
 
         self.disconnected.insert(client_id, true);
         self.partitioned.insert(client_id, true);
+        self.connections[client_id as usize].close();
+        self.server_event(ServerEvent::ConnectionClosed {
+            session_id: session_id(client_id),
+            reason: "disconnected".to_string(),
+        });
 
-        let rooms: Vec<ModelRoomId> = self
-            .room_membership
-            .iter()
-            .filter(|&(&(cid, _), &m)| m && cid == client_id)
-            .map(|(&(_, rid), _)| rid)
+        let mut rooms: Vec<ModelRoomId> = self
+            .server_rooms
+            .keys()
+            .copied()
+            .filter(|&room_id| self.is_member(client_id, room_id))
             .collect();
+        rooms.sort_unstable();
 
         // Simulate a reconnect by clearing the client's local room state
-        let client = &mut self.clients[client_id as usize];
-        for room_id in &rooms {
-            let real_room_id = u128::from(*room_id) + 1;
-            let _ = client.handle(ClientEvent::LeaveRoom { room_id: real_room_id });
+        for room_id in rooms {
+            let client = &mut self.clients[client_id as usize];
+            let _ = client.handle(ClientEvent::ForgetRoom { room_id: real_room_id(room_id) });
+            self.forget_room(client_id, room_id);
+            self.commit_departure(client_id, room_id);
+        }
+
+        OperationResult::Ok
+    }
+
+    /// Drop a client's in-memory state and rebuild it from storage, so its
+    /// rooms and epochs read back as persisted. It stays offline until
+    /// restarted, replaying the frames it last processed first.
+    fn apply_crash_client(&mut self, client_id: ClientId) -> OperationResult {
+        if client_id as usize >= self.clients.len() {
+            return OperationResult::Error(OperationError::InvalidClient);
+        }
+
+        if self.is_crashed(client_id) {
+            return OperationResult::Ok;
+        }
+
+        self.crashed.insert(client_id, true);
+        if !self.disconnected.get(&client_id).copied().unwrap_or(false) {
+            self.connections[client_id as usize].close();
+            self.server_event(ServerEvent::ConnectionClosed {
+                session_id: session_id(client_id),
+                reason: "crashed".to_string(),
+            });
+        }
+
+        let overlap = self.recent_frames.remove(&client_id).unwrap_or_default();
+        self.backlog.insert(client_id, overlap);
+        let storage = &self.client_storage[client_id as usize];
+        let sender_id = self.sender_ids[client_id as usize];
+        self.clients[client_id as usize] = start_client(&self.env, sender_id, storage);
+
+        OperationResult::Ok
+    }
+
+    /// Reconnect a crashed client, replay what it missed, and commit the
+    /// departures that waited for it.
+    fn apply_restart_client(&mut self, client_id: ClientId) -> OperationResult {
+        if client_id as usize >= self.clients.len() {
+            return OperationResult::Error(OperationError::InvalidClient);
+        }
+
+        if !self.is_crashed(client_id) {
+            return OperationResult::Ok;
+        }
+        if self.server.is_none() {
+            return OperationResult::Error(OperationError::ServerUnavailable);
+        }
+
+        self.crashed.insert(client_id, false);
+        if !self.disconnected.get(&client_id).copied().unwrap_or(false) {
+            self.connections[client_id as usize] =
+                Connection::new(self.env.now(), ConnectionConfig::default());
+            self.connect(client_id);
+        }
+
+        for (room_id, frame) in self.backlog.remove(&client_id).unwrap_or_default() {
+            if self.is_member(client_id, room_id) {
+                self.receive_frame(client_id, room_id, &frame);
+            }
         }
 
+        let mut rooms: Vec<ModelRoomId> = self
+            .deferred_departures
+            .keys()
+            .copied()
+            .filter(|&room_id| self.is_member(client_id, room_id))
+            .collect();
+        rooms.sort_unstable();
         for room_id in rooms {
-            self.room_membership.insert((client_id, room_id), false);
-            self.room_epochs.remove(&(client_id, room_id));
-
-            let remaining: Vec<ClientId> = self
-                .room_membership
-                .iter()
-                .filter(|&(&(_, rid), &m)| m && rid == room_id)
-                .map(|(&(cid, _), _)| cid)
-                .collect();
-
-            for cid in &remaining {
-                let epoch = self.room_epochs.entry((*cid, room_id)).or_insert(0);
-                *epoch += 1;
+            for departed_id in self.deferred_departures.remove(&room_id).unwrap_or_default() {
+                self.commit_departure(departed_id, room_id);
             }
         }
 
         OperationResult::Ok
     }
+
+    /// Rebuild the server from its storage and reconnect running clients.
+    fn apply_restart_server(&mut self) {
+        if self.server.is_some() {
+            return;
+        }
+
+        let mut server = ServerDriver::new(
+            self.env.clone(),
+            self.server_storage.clone(),
+            DriverConfig::default(),
+        );
+        let _ = server.recover_from_storage();
+        self.server = Some(server);
+
+        for client_id in 0..self.clients.len() as ClientId {
+            if self.is_crashed(client_id)
+                || self.disconnected.get(&client_id).copied().unwrap_or(false)
+            {
+                continue;
+            }
+            self.connections[client_id as usize] =
+                Connection::new(self.env.now(), ConnectionConfig::default());
+            self.connect(client_id);
+        }
+    }
 }
 
 /// Strategy for generating `SmallMessage`.
@@ -753,7 +1116,11 @@ fn operation_strategy(num_clients: usize) -> impl Strategy<Value = Operation> {
         1 => Just(Operation::DeliverPending),
         1 => client_id.clone().prop_map(|c| Operation::Partition { client_id: c }),
         1 => client_id.clone().prop_map(|c| Operation::HealPartition { client_id: c }),
-        1 => client_id.prop_map(|c| Operation::Disconnect { client_id: c }),
+        1 => client_id.clone().prop_map(|c| Operation::Disconnect { client_id: c }),
+        1 => client_id.clone().prop_map(|c| Operation::CrashClient { client_id: c }),
+        1 => client_id.prop_map(|c| Operation::RestartClient { client_id: c }),
+        1 => Just(Operation::CrashServer),
+        1 => Just(Operation::RestartServer),
     ]
 }
 
@@ -783,10 +1150,22 @@ proptest! {
                 "Divergence at operation {}: {:?}\nModel: {:?}\nReal: {:?}",
                 i, clamped_op, model_result, real_result
             );
+
+            if matches!(clamped_op, Operation::SendMessage { .. } | Operation::DeliverPending) {
+                prop_assert_eq!(
+                    model.observable_state().client_messages,
+                    real.observable_state().client_messages,
+                    "Message divergence at operation {}: {:?}",
+                    i, clamped_op
+                );
+            }
         }
 
-        model.apply(&Operation::DeliverPending);
-        real.apply(&Operation::DeliverPending);
+        // Recover everything, so held frames are delivered before comparing
+        for op in recovery_operations(num_clients) {
+            model.apply(&op);
+            real.apply(&op);
+        }
 
         let model_state = model.observable_state();
         let real_state = real.observable_state();
@@ -822,6 +1201,9 @@ proptest! {
             let clamped_op = clamp_client_id(op, num_clients);
             let _ = model.apply(&clamped_op);
         }
+        for op in recovery_operations(num_clients) {
+            let _ = model.apply(&op);
+        }
 
         // Invariant: Observable state is consistent
         let state = model.observable_state();
@@ -837,6 +1219,22 @@ proptest! {
             }
         }
 
+        // Invariant: Members are at the server's epoch for the room
+        for (client_id, epochs) in state.client_epochs.iter().enumerate() {
+            for (room_id, epoch) in epochs {
+                prop_assert_eq!(
+                    Some(*epoch), model.server().epoch(*room_id),
+                    "Client {} is at the wrong epoch in room {}",
+                    client_id, room_id
+                );
+            }
+        }
+
+        // Invariant: Every published KeyPackage was consumed by its add
+        for client_id in 0..num_clients as ClientId {
+            prop_assert_eq!(model.server().key_package_count(client_id), 0);
+        }
+
         // Invariant: All messages have sequential log indices
         for (room_id, messages) in &state.server_messages {
             for (i, msg) in messages.iter().enumerate() {
@@ -1081,6 +1479,48 @@ proptest! {
         );
     }
 
+    /// Verify a crashed client receives what it missed, once and in order,
+    /// when it restarts.
+    #[test]
+    fn prop_restart_delivers_held_messages(
+        room_id in any::<ModelRoomId>(),
+        messages in prop::collection::vec(small_message_strategy(), 1..6)
+    ) {
+        let mut model = ModelWorld::new(2);
+
+        let _ = model.apply(&Operation::CreateRoom { client_id: 0, room_id });
+        let _ = model.apply(&Operation::AddMember { inviter_id: 0, invitee_id: 1, room_id });
+        let _ = model.apply(&Operation::CrashClient { client_id: 1 });
+
+        let result = model.apply(&Operation::SendMessage {
+            client_id: 1,
+            room_id,
+            content: SmallMessage { seed: 0, size_class: 0 },
+        });
+        prop_assert_eq!(result, OperationResult::Error(OperationError::Crashed));
+
+        for content in &messages {
+            let _ = model.apply(&Operation::SendMessage {
+                client_id: 0,
+                room_id,
+                content: content.clone(),
+            });
+        }
+        let _ = model.apply(&Operation::DeliverPending);
+        prop_assert!(
+            model.client_messages(1, room_id).is_some_and(<[ModelMessage]>::is_empty),
+            "Crashed client should not receive messages"
+        );
+
+        let _ = model.apply(&Operation::CrashServer);
+        let result = model.apply(&Operation::RestartClient { client_id: 1 });
+        prop_assert_eq!(result, OperationResult::Error(OperationError::ServerUnavailable));
+        let _ = model.apply(&Operation::RestartServer);
+        let _ = model.apply(&Operation::RestartClient { client_id: 1 });
+
+        prop_assert_eq!(model.client_messages(1, room_id), model.client_messages(0, room_id));
+    }
+
     /// Verify disconnect removes client from all rooms.
     #[test]
     fn prop_disconnect_clears_membership(
@@ -1302,6 +1742,15 @@ proptest! {
     }
 }
 
+/// Restart the server and every client, then deliver what is pending.
+fn recovery_operations(num_clients: usize) -> Vec<Operation> {
+    let restarts = (0..num_clients as ClientId).map(|c| Operation::RestartClient { client_id: c });
+    std::iter::once(Operation::RestartServer)
+        .chain(restarts)
+        .chain(std::iter::once(Operation::DeliverPending))
+        .collect()
+}
+
 /// Clamp `client_id` to valid range for the given number of clients.
 fn clamp_client_id(op: Operation, num_clients: usize) -> Operation {
     let clamp = |id: ClientId| id % num_clients as u8;
@@ -1325,6 +1774,12 @@ fn clamp_client_id(op: Operation, num_clients: usize) -> Operation {
             target_id: clamp(target_id),
             room_id,
         },
+        Operation::CrashClient { client_id } => {
+            Operation::CrashClient { client_id: clamp(client_id) }
+        },
+        Operation::RestartClient { client_id } => {
+            Operation::RestartClient { client_id: clamp(client_id) }
+        },
         other => other,
     }
 }
@@ -29,6 +29,7 @@ fn start_server() -> String {
         cert_path: None,
         key_path: None,
         driver: DriverConfig::default(),
+        ..Default::default()
     };
     let server = Server::bind(config).expect("valid server config");
     let addr = server.local_addr().expect("underlying socket").to_string();
@@ -28,6 +28,7 @@ use lockframe_proto::{
     Frame, FrameHeader, Opcode, Payload,
     payloads::session::{Goodbye, Hello, HelloReply},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::error::ConnectionError;
 
@@ -71,7 +72,7 @@ pub enum ConnectionState {
 }
 
 /// Connection configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConnectionConfig {
     /// Timeout for completing handshake
     pub handshake_timeout: Duration,
@@ -162,6 +163,12 @@ where
         self.config.handshake_timeout
     }
 
+    /// Replace the timeouts and heartbeat interval. Takes effect from the
+    /// next timeout check.
+    pub fn set_config(&mut self, config: ConnectionConfig) {
+        self.config = config;
+    }
+
     /// Assign session ID (server use only, before handling Hello).
     ///
     /// The server should generate a random session ID and set it before
@@ -414,7 +421,7 @@ where
 
                 self.state = ConnectionState::Closed;
 
-                let reply = Payload::Goodbye(Goodbye { reason: "ack".to_string() });
+                let reply = Payload::Goodbye(Goodbye::new("ack"));
                 let frame = reply.into_frame(FrameHeader::new(Opcode::Goodbye))?;
 
                 Ok(vec![ConnectionAction::SendFrame(frame), ConnectionAction::Close {
@@ -732,7 +739,7 @@ mod tests {
         conn.handle_frame(&reply_frame, t0).unwrap();
 
         // Send Goodbye
-        let goodbye = Payload::Goodbye(Goodbye { reason: "client shutdown".to_string() });
+        let goodbye = Payload::Goodbye(Goodbye::new("client shutdown"));
         let goodbye_frame = goodbye.into_frame(FrameHeader::new(Opcode::Goodbye)).unwrap();
 
         let actions = conn.handle_frame(&goodbye_frame, t0).unwrap();
@@ -754,7 +761,7 @@ mod tests {
         conn.send_hello(t0).unwrap();
 
         // Send Goodbye while still pending
-        let goodbye = Payload::Goodbye(Goodbye { reason: "timeout".to_string() });
+        let goodbye = Payload::Goodbye(Goodbye::new("timeout"));
         let goodbye_frame = goodbye.into_frame(FrameHeader::new(Opcode::Goodbye)).unwrap();
 
         let actions = conn.handle_frame(&goodbye_frame, t0).unwrap();
@@ -11,7 +11,10 @@
 ///
 /// At 1 epoch/hour, this allows ~114 years of operation.
 /// Real deployments will likely rotate groups much more frequently.
-pub const MAX_EPOCH: u64 = 1_000_000;
+///
+/// Frame headers carrying a higher epoch fail
+/// [`FrameHeader::validate`](lockframe_proto::FrameHeader::validate).
+pub const MAX_EPOCH: u64 = lockframe_proto::FrameHeader::MAX_EPOCH;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,162 @@
+//! Member credentials and their verification.
+//!
+//! Every kind of credential is carried in an MLS basic credential whose
+//! identity starts with the member ID as 8 little-endian bytes. A bare member
+//! ID is exactly those 8 bytes, so groups created before richer credentials
+//! existed are unchanged. Other kinds append their CBOR-encoded body.
+//!
+//! MLS itself only checks that members hold the signature key bound to their
+//! credential. Whether the credential is acceptable (a certificate chains to a
+//! trusted root, a verifiable credential is unrevoked) is decided by a
+//! [`CredentialVerifier`] when Welcomes and Commits are processed.
+
+use serde::{Deserialize, Serialize};
+
+use super::{MemberId, MlsError};
+
+/// Identity a member presents in MLS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Credential {
+    /// Bare member ID, trusted as asserted.
+    BasicId(MemberId),
+
+    /// X.509 certificate chain.
+    X509 {
+        /// Member ID the certificate is issued for.
+        member_id: MemberId,
+        /// DER-encoded certificates, leaf first.
+        chain: Vec<Vec<u8>>,
+    },
+
+    /// Application-defined credential, e.g. a verifiable credential.
+    Custom {
+        /// Member ID the credential is issued for.
+        member_id: MemberId,
+        /// Identifies the credential format.
+        credential_type: String,
+        /// Encoded credential.
+        bytes: Vec<u8>,
+    },
+}
+
+/// Encoded body following the member ID for non-basic credentials.
+#[derive(Serialize, Deserialize)]
+enum CredentialBody {
+    X509 { chain: Vec<Vec<u8>> },
+    Custom { credential_type: String, bytes: Vec<u8> },
+}
+
+impl Credential {
+    /// Member ID the credential identifies.
+    pub fn member_id(&self) -> MemberId {
+        match self {
+            Self::BasicId(member_id)
+            | Self::X509 { member_id, .. }
+            | Self::Custom { member_id, .. } => *member_id,
+        }
+    }
+
+    /// Encode as the identity of an MLS basic credential.
+    pub fn to_identity_bytes(&self) -> Result<Vec<u8>, MlsError> {
+        let mut bytes = self.member_id().to_le_bytes().to_vec();
+
+        let body = match self {
+            Self::BasicId(_) => return Ok(bytes),
+            Self::X509 { chain, .. } => CredentialBody::X509 { chain: chain.clone() },
+            Self::Custom { credential_type, bytes, .. } => CredentialBody::Custom {
+                credential_type: credential_type.clone(),
+                bytes: bytes.clone(),
+            },
+        };
+
+        ciborium::ser::into_writer(&body, &mut bytes)
+            .map_err(|e| MlsError::Serialization(format!("Failed to encode credential: {e}")))?;
+        Ok(bytes)
+    }
+
+    /// Decode from the identity of an MLS basic credential.
+    pub fn from_identity_bytes(identity: &[u8]) -> Result<Self, MlsError> {
+        let (id_bytes, body) = identity.split_at_checked(8).ok_or_else(|| {
+            MlsError::Serialization(format!(
+                "Invalid credential: expected at least 8 bytes, got {}",
+                identity.len()
+            ))
+        })?;
+
+        let member_id = id_bytes.try_into().map(u64::from_le_bytes).map_err(|_| {
+            MlsError::Serialization("Failed to extract member_id bytes".to_string())
+        })?;
+
+        if body.is_empty() {
+            return Ok(Self::BasicId(member_id));
+        }
+
+        let body: CredentialBody = ciborium::de::from_reader(body)
+            .map_err(|e| MlsError::Serialization(format!("Failed to decode credential: {e}")))?;
+
+        Ok(match body {
+            CredentialBody::X509 { chain } => Self::X509 { member_id, chain },
+            CredentialBody::Custom { credential_type, bytes } => {
+                Self::Custom { member_id, credential_type, bytes }
+            },
+        })
+    }
+}
+
+/// Decides whether a member's credential is acceptable.
+///
+/// Called for every member of a group joined via Welcome, and for every
+/// member a Commit adds or updates before it is merged. Rejecting fails the
+/// join or the Commit with the returned error, typically
+/// [`MlsError::InvalidCredential`].
+pub trait CredentialVerifier: Send + Sync {
+    /// Accept or reject a credential.
+    fn verify(&self, credential: &Credential) -> Result<(), MlsError>;
+}
+
+/// Verifier accepting every credential. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AcceptAllCredentials;
+
+impl CredentialVerifier for AcceptAllCredentials {
+    fn verify(&self, _credential: &Credential) -> Result<(), MlsError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_id_is_eight_bytes() {
+        let credential = Credential::BasicId(42);
+        let bytes = credential.to_identity_bytes().unwrap();
+
+        assert_eq!(bytes, 42u64.to_le_bytes());
+        assert_eq!(Credential::from_identity_bytes(&bytes).unwrap(), credential);
+    }
+
+    #[test]
+    fn rich_credentials_round_trip_behind_member_id() {
+        let credentials = [
+            Credential::X509 { member_id: 7, chain: vec![vec![0x30, 0x82], vec![0x30, 0x81]] },
+            Credential::Custom {
+                member_id: 9,
+                credential_type: "example.com/vc".to_string(),
+                bytes: vec![1, 2, 3],
+            },
+        ];
+
+        for credential in credentials {
+            let bytes = credential.to_identity_bytes().unwrap();
+            assert_eq!(bytes[..8], credential.member_id().to_le_bytes());
+            assert_eq!(Credential::from_identity_bytes(&bytes).unwrap(), credential);
+        }
+    }
+
+    #[test]
+    fn short_identity_is_rejected() {
+        assert!(Credential::from_identity_bytes(&[1, 2, 3]).is_err());
+    }
+}
@@ -91,6 +91,15 @@ pub enum MlsError {
     /// Frame validation failed
     #[error("validation failed: {0}")]
     ValidationFailed(String),
+
+    /// Member credential rejected by the credential verifier
+    #[error("credential of member {member_id} rejected: {reason}")]
+    InvalidCredential {
+        /// Member whose credential was rejected
+        member_id: u64,
+        /// Why it was rejected
+        reason: String,
+    },
 }
 
 impl MlsError {
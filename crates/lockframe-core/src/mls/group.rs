@@ -1,14 +1,15 @@
 //! Client-side MLS group state machine.
 
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use lockframe_proto::{Frame, FrameHeader, Opcode};
 use openmls::{
     key_packages::KeyPackageIn,
     prelude::{
         BasicCredential, Ciphersuite, Credential, CredentialWithKey, GroupId, KeyPackage,
-        LeafNodeIndex, MlsGroupCreateConfig, MlsGroupJoinConfig, MlsMessageBodyIn, MlsMessageIn,
-        OpenMlsProvider, ProcessedMessageContent, ProtocolMessage, ProtocolVersion, StagedWelcome,
+        LeafNodeIndex, LeafNodeParameters, MlsGroupCreateConfig, MlsGroupJoinConfig,
+        MlsMessageBodyIn, MlsMessageIn, OpenMlsProvider, ProcessedMessageContent, ProtocolMessage,
+        ProtocolVersion, StagedWelcome,
     },
 };
 use openmls_basic_credential::SignatureKeyPair;
@@ -17,6 +18,7 @@ use tls_codec::{Deserialize, Serialize};
 
 use super::{
     MlsGroupState,
+    credential::{AcceptAllCredentials, Credential as MemberCredential, CredentialVerifier},
     error::MlsError,
     provider::MlsProvider,
     validator::{MlsValidator, ValidationResult},
@@ -29,6 +31,17 @@ pub type RoomId = u128;
 /// Member identifier within a group.
 pub type MemberId = u64;
 
+/// A member's leaf in the ratchet tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberLeaf {
+    /// Position in the tree. Each of a user's clients holds its own leaf.
+    pub leaf_index: u32,
+    /// Identity the member presents.
+    pub credential: MemberCredential,
+    /// Ed25519 signature key bound to the leaf. `None` if malformed.
+    pub signature_key: Option<[u8; 32]>,
+}
+
 /// Opaque state needed to process a Welcome message.
 ///
 /// This is returned by [`MlsGroup::generate_key_package`] and must be passed
@@ -116,6 +129,23 @@ fn extract_member_id_from_credential(credential: &Credential) -> Result<MemberId
     Ok(u64::from_le_bytes(member_id_bytes))
 }
 
+/// Bind a member credential to our signature key.
+fn credential_with_key(
+    credential: &MemberCredential,
+    signer: &SignatureKeyPair,
+) -> Result<CredentialWithKey, MlsError> {
+    let credential = BasicCredential::new(credential.to_identity_bytes()?);
+    Ok(CredentialWithKey { credential: credential.into(), signature_key: signer.public().into() })
+}
+
+/// Run `verifier` over an MLS credential.
+fn verify_credential(
+    verifier: &dyn CredentialVerifier,
+    credential: &Credential,
+) -> Result<(), MlsError> {
+    verifier.verify(&MemberCredential::from_identity_bytes(credential.serialized_content())?)
+}
+
 /// Client-side MLS group state.
 ///
 /// Represents participation in a single MLS group (room). Clients can be
@@ -145,6 +175,9 @@ pub struct MlsGroup<E: Environment> {
 
     /// Pending commit that we sent (waiting for sequencer acceptance)
     pending_commit: Option<PendingCommit<E::Instant>>,
+
+    /// Decides whether members' credentials are acceptable
+    verifier: Arc<dyn CredentialVerifier>,
 }
 
 /// Tracks a commit we sent that's waiting for sequencer acceptance.
@@ -165,23 +198,29 @@ impl<E: Environment> MlsGroup<E> {
     ///
     /// Returns a tuple containing a new `MlsGroup` instance and any actions to
     /// execute.
-    #[allow(clippy::too_many_lines)]
     pub fn new(
         env: E,
         room_id: RoomId,
         member_id: MemberId,
     ) -> Result<(Self, Vec<MlsAction>), MlsError> {
+        Self::new_with_credential(env, room_id, &MemberCredential::BasicId(member_id))
+    }
+
+    /// Create a new MLS group, presenting `credential` as our identity.
+    #[allow(clippy::too_many_lines)]
+    pub fn new_with_credential(
+        env: E,
+        room_id: RoomId,
+        credential: &MemberCredential,
+    ) -> Result<(Self, Vec<MlsAction>), MlsError> {
+        let member_id = credential.member_id();
         let provider = MlsProvider::new(env);
         let ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
 
         let signer = SignatureKeyPair::new(ciphersuite.signature_algorithm())
             .map_err(|e| MlsError::Crypto(format!("Failed to generate keypair: {e}")))?;
 
-        let credential = BasicCredential::new(member_id.to_le_bytes().to_vec());
-        let credential_with_key = CredentialWithKey {
-            credential: credential.into(),
-            signature_key: signer.public().into(),
-        };
+        let credential_with_key = credential_with_key(credential, &signer)?;
 
         let group_config = MlsGroupCreateConfig::builder()
             .ciphersuite(ciphersuite)
@@ -192,8 +231,15 @@ impl<E: Environment> MlsGroup<E> {
             openmls::group::MlsGroup::new(&provider, &signer, &group_config, credential_with_key)
                 .map_err(|e| MlsError::Crypto(format!("Failed to create MLS group: {e}")))?;
 
-        let group =
-            Self { room_id, member_id, inner_group, signer, provider, pending_commit: None };
+        let group = Self {
+            room_id,
+            member_id,
+            inner_group,
+            signer,
+            provider,
+            pending_commit: None,
+            verifier: Arc::new(AcceptAllCredentials),
+        };
 
         // Export GroupInfo so external joiners can join immediately
         let group_info_bytes = group.export_group_info()?;
@@ -208,6 +254,21 @@ impl<E: Environment> MlsGroup<E> {
         Ok((group, actions))
     }
 
+    /// Use `verifier` to check members' credentials from now on.
+    pub fn set_credential_verifier(&mut self, verifier: Arc<dyn CredentialVerifier>) {
+        self.verifier = verifier;
+    }
+
+    /// Check every current member's credential with the verifier.
+    ///
+    /// Used after joining via Welcome, where the member list arrives all at
+    /// once rather than through Commits.
+    pub fn verify_member_credentials(&self) -> Result<(), MlsError> {
+        self.inner_group
+            .members()
+            .try_for_each(|member| verify_credential(&*self.verifier, &member.credential))
+    }
+
     /// MLS epoch number (increments on Commit).
     pub fn epoch(&self) -> u64 {
         self.inner_group.epoch().as_u64()
@@ -252,11 +313,64 @@ impl<E: Environment> MlsGroup<E> {
         }
     }
 
+    /// Sign arbitrary data with this group's MLS signature key.
+    ///
+    /// For artifacts that leave the frame protocol, such as transcript
+    /// exports. Verifiable against [`Self::signature_public_key`].
+    pub fn sign(&self, data: &[u8]) -> Result<[u8; 64], MlsError> {
+        let signature = self
+            .signer
+            .sign(data)
+            .map_err(|e| MlsError::Crypto(format!("signing failed: {e:?}")))?;
+
+        signature
+            .as_slice()
+            .try_into()
+            .map_err(|_| MlsError::Crypto("signature has unexpected length".to_string()))
+    }
+
+    /// Our Ed25519 signature public key in this group.
+    pub fn signature_public_key(&self) -> Result<[u8; 32], MlsError> {
+        self.signer
+            .public()
+            .try_into()
+            .map_err(|_| MlsError::Crypto("signature key has unexpected length".to_string()))
+    }
+
     /// All member positions in the ratchet tree (for sender key derivation).
     pub fn member_leaf_indices(&self) -> Vec<u32> {
         self.inner_group.members().map(|m| m.index.u32()).collect()
     }
 
+    /// Credentials of all current members, in leaf order. Members whose
+    /// credential doesn't decode are left out.
+    pub fn member_credentials(&self) -> Vec<MemberCredential> {
+        self.inner_group
+            .members()
+            .filter_map(|m| {
+                MemberCredential::from_identity_bytes(m.credential.serialized_content()).ok()
+            })
+            .collect()
+    }
+
+    /// Leaves of all current members, in leaf order. Members whose
+    /// credential doesn't decode are left out.
+    pub fn member_leaves(&self) -> Vec<MemberLeaf> {
+        self.inner_group
+            .members()
+            .filter_map(|m| {
+                let credential =
+                    MemberCredential::from_identity_bytes(m.credential.serialized_content())
+                        .ok()?;
+                Some(MemberLeaf {
+                    leaf_index: m.index.u32(),
+                    credential,
+                    signature_key: m.signature_key.as_slice().try_into().ok(),
+                })
+            })
+            .collect()
+    }
+
     /// Member ID at given leaf index. `None` if position is empty.
     ///
     /// Used to bind `sender_id` (frame header) to `sender_index` (encrypted
@@ -410,6 +524,12 @@ impl<E: Environment> MlsGroup<E> {
                         proposal.proposal()
                     ),
                 });
+
+                // A commit covering the proposal refers to it by reference,
+                // so every member has to keep it until the epoch ends
+                self.inner_group
+                    .store_pending_proposal(self.provider.storage(), *proposal)
+                    .map_err(|e| MlsError::Crypto(format!("Failed to store proposal: {e}")))?;
             },
             ProcessedMessageContent::ExternalJoinProposalMessage(_) => {
                 actions.push(MlsAction::Log {
@@ -419,6 +539,18 @@ impl<E: Environment> MlsGroup<E> {
             ProcessedMessageContent::StagedCommitMessage(staged_commit) => {
                 let old_epoch = self.epoch();
 
+                // Members added or updated by this commit must present
+                // acceptable credentials before it is merged
+                for add in staged_commit.add_proposals() {
+                    verify_credential(
+                        &*self.verifier,
+                        add.add_proposal().key_package().leaf_node().credential(),
+                    )?;
+                }
+                if let Some(leaf_node) = staged_commit.update_path_leaf_node() {
+                    verify_credential(&*self.verifier, leaf_node.credential())?;
+                }
+
                 self.inner_group
                     .merge_staged_commit(&self.provider, *staged_commit)
                     .map_err(|e| MlsError::Crypto(format!("Failed to merge commit: {e}")))?;
@@ -507,6 +639,79 @@ impl<E: Environment> MlsGroup<E> {
             .map_err(|e| MlsError::Crypto(format!("Failed to export group state: {e}")))
     }
 
+    /// Serialize the complete group for offloading to storage.
+    ///
+    /// Unlike [`Self::export_state`], the snapshot can be turned back into a
+    /// working group with [`Self::restore`]. It contains the signature private
+    /// key and epoch secrets. Fails while a commit is pending, since pending
+    /// commits are not carried over.
+    pub fn export_snapshot(&self) -> Result<Vec<u8>, MlsError> {
+        if self.pending_commit.is_some() {
+            return Err(MlsError::InvalidState {
+                epoch: self.epoch(),
+                operation: "snapshot with pending commit".to_string(),
+            });
+        }
+
+        let storage = self
+            .provider
+            .storage()
+            .values
+            .read()
+            .map_err(|_| MlsError::Crypto("MLS storage lock poisoned".to_string()))?
+            .clone();
+
+        let signer = self
+            .signer
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::Crypto(format!("Failed to serialize signer: {e:?}")))?;
+
+        let snapshot = GroupSnapshot {
+            room_id: self.room_id,
+            member_id: self.member_id,
+            group_id: self.inner_group.group_id().as_slice().to_vec(),
+            signer,
+            storage,
+        };
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&snapshot, &mut buf)
+            .map_err(|e| MlsError::Crypto(format!("Failed to encode group snapshot: {e}")))?;
+        Ok(buf)
+    }
+
+    /// Rebuild a group from [`Self::export_snapshot`] output.
+    pub fn restore(env: E, snapshot: &[u8]) -> Result<Self, MlsError> {
+        let snapshot: GroupSnapshot = ciborium::de::from_reader(snapshot)
+            .map_err(|e| MlsError::Crypto(format!("Failed to decode group snapshot: {e}")))?;
+
+        let provider = MlsProvider::new(env);
+        *provider
+            .storage()
+            .values
+            .write()
+            .map_err(|_| MlsError::Crypto("MLS storage lock poisoned".to_string()))? =
+            snapshot.storage;
+
+        let signer = SignatureKeyPair::tls_deserialize_exact(snapshot.signer.as_slice())
+            .map_err(|e| MlsError::Crypto(format!("Failed to deserialize signer: {e:?}")))?;
+
+        let group_id = GroupId::from_slice(&snapshot.group_id);
+        let inner_group = openmls::group::MlsGroup::load(provider.storage(), &group_id)
+            .map_err(|e| MlsError::Crypto(format!("Failed to load MLS group: {e:?}")))?
+            .ok_or_else(|| MlsError::Crypto("group missing from snapshot".to_string()))?;
+
+        Ok(Self {
+            room_id: snapshot.room_id,
+            member_id: snapshot.member_id,
+            inner_group,
+            signer,
+            provider,
+            pending_commit: None,
+            verifier: Arc::new(AcceptAllCredentials),
+        })
+    }
+
     /// Export the current group state needed for frame validation (infallible).
     ///
     /// Convenience wrapper around [`Self::export_group_state`] that returns
@@ -586,17 +791,21 @@ impl<E: Environment> MlsGroup<E> {
     /// `pending_state` must be kept and passed to
     /// [`Self::join_from_welcome`] when the Welcome message is received.
     pub fn generate_key_package(env: E, member_id: MemberId) -> KeyPackageResult<E> {
+        Self::generate_key_package_with_credential(env, &MemberCredential::BasicId(member_id))
+    }
+
+    /// Generate a `KeyPackage` presenting `credential` as our identity.
+    pub fn generate_key_package_with_credential(
+        env: E,
+        credential: &MemberCredential,
+    ) -> KeyPackageResult<E> {
         let provider = MlsProvider::new(env);
         let ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
 
         let signer = SignatureKeyPair::new(ciphersuite.signature_algorithm())
             .map_err(|e| MlsError::Crypto(format!("Failed to generate keypair: {e}")))?;
 
-        let credential = BasicCredential::new(member_id.to_le_bytes().to_vec());
-        let credential_with_key = CredentialWithKey {
-            credential: credential.into(),
-            signature_key: signer.public().into(),
-        };
+        let credential_with_key = credential_with_key(credential, &signer)?;
 
         let key_package_bundle = KeyPackage::builder()
             .build(ciphersuite, &provider, &signer, credential_with_key)
@@ -655,6 +864,7 @@ impl<E: Environment> MlsGroup<E> {
             signer,
             provider,
             pending_commit: None,
+            verifier: Arc::new(AcceptAllCredentials),
         };
 
         let actions = vec![MlsAction::Log {
@@ -682,19 +892,32 @@ impl<E: Environment> MlsGroup<E> {
         env: E,
         room_id: RoomId,
         member_id: MemberId,
+        group_info_bytes: &[u8],
+    ) -> Result<(Self, Vec<MlsAction>), MlsError> {
+        Self::join_from_external_with_credential(
+            env,
+            room_id,
+            &MemberCredential::BasicId(member_id),
+            group_info_bytes,
+        )
+    }
+
+    /// Join a group via external commit, presenting `credential` as our
+    /// identity.
+    pub fn join_from_external_with_credential(
+        env: E,
+        room_id: RoomId,
+        credential: &MemberCredential,
         mut group_info_bytes: &[u8],
     ) -> Result<(Self, Vec<MlsAction>), MlsError> {
+        let member_id = credential.member_id();
         let provider = MlsProvider::new(env);
         let ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
 
         let signer = SignatureKeyPair::new(ciphersuite.signature_algorithm())
             .map_err(|e| MlsError::Crypto(format!("Failed to generate keypair: {e}")))?;
 
-        let credential = BasicCredential::new(member_id.to_le_bytes().to_vec());
-        let credential_with_key = CredentialWithKey {
-            credential: credential.into(),
-            signature_key: signer.public().into(),
-        };
+        let credential_with_key = credential_with_key(credential, &signer)?;
 
         let mls_message_in = MlsMessageIn::tls_deserialize(&mut group_info_bytes).map_err(|e| {
             MlsError::Serialization(format!("Failed to deserialize GroupInfo message: {e}"))
@@ -733,6 +956,7 @@ impl<E: Environment> MlsGroup<E> {
             signer,
             provider,
             pending_commit: None,
+            verifier: Arc::new(AcceptAllCredentials),
         };
         let group_info_bytes = group.export_group_info()?;
 
@@ -873,6 +1097,116 @@ impl<E: Environment> MlsGroup<E> {
         Ok(actions)
     }
 
+    /// Whether proposals received this epoch are waiting for a commit.
+    pub fn has_pending_proposals(&self) -> bool {
+        self.inner_group.pending_proposals().next().is_some()
+    }
+
+    /// Commit the proposals received this epoch, such as another member's
+    /// leave proposal.
+    ///
+    /// Like [`Self::remove_members`], the commit must be sent to the
+    /// sequencer and advances the epoch when accepted.
+    pub fn commit_pending_proposals(&mut self) -> Result<Vec<MlsAction>, MlsError> {
+        if !self.has_pending_proposals() {
+            return Err(MlsError::Crypto("No pending proposals to commit".to_string()));
+        }
+
+        let target_epoch = self
+            .epoch()
+            .checked_add(1)
+            .ok_or_else(|| MlsError::Crypto("Epoch overflow".to_string()))?;
+        let now = self.provider.now();
+
+        let (mls_message_out, _welcome_option, group_info) = self
+            .inner_group
+            .commit_to_pending_proposals(&self.provider, &self.signer)
+            .map_err(|e| MlsError::Crypto(format!("Failed to commit proposals: {e}")))?;
+
+        self.pending_commit = Some(PendingCommit { target_epoch, sent_at: now });
+
+        let mut actions = Vec::new();
+
+        let group_info_bytes = group_info
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::Serialization(format!("Failed to serialize GroupInfo: {e}")))?;
+
+        actions.push(MlsAction::PublishGroupInfo {
+            room_id: self.room_id,
+            epoch: target_epoch,
+            group_info_bytes,
+        });
+
+        let commit_payload = mls_message_out
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::Serialization(format!("Failed to serialize commit: {e}")))?;
+
+        let mut commit_header = FrameHeader::new(Opcode::Commit);
+        commit_header.set_room_id(self.room_id);
+        commit_header.set_sender_id(self.member_id);
+        let commit_frame = Frame::new(commit_header, commit_payload);
+
+        actions.push(MlsAction::SendCommit(commit_frame));
+
+        actions.push(MlsAction::Log {
+            message: format!("Committing pending proposals for group {}", self.room_id),
+        });
+
+        Ok(actions)
+    }
+
+    /// Commit a fresh leaf for this member, moving the group to a new epoch.
+    ///
+    /// Every member's sender ratchets restart in the new epoch, so a member
+    /// whose group state was restored from a snapshot calls this before
+    /// sending to avoid reusing keys it may already have used. Like
+    /// [`Self::commit_pending_proposals`], the commit must be sent to the
+    /// sequencer and advances the epoch when accepted.
+    pub fn self_update(&mut self) -> Result<Vec<MlsAction>, MlsError> {
+        let target_epoch = self
+            .epoch()
+            .checked_add(1)
+            .ok_or_else(|| MlsError::Crypto("Epoch overflow".to_string()))?;
+        let now = self.provider.now();
+
+        let (mls_message_out, _welcome_option, group_info) = self
+            .inner_group
+            .self_update(&self.provider, &self.signer, LeafNodeParameters::default())
+            .map_err(|e| MlsError::Crypto(format!("Failed to update own leaf: {e}")))?
+            .into_contents();
+
+        self.pending_commit = Some(PendingCommit { target_epoch, sent_at: now });
+
+        let mut actions = Vec::new();
+
+        let group_info_bytes = group_info
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::Serialization(format!("Failed to serialize GroupInfo: {e}")))?;
+
+        actions.push(MlsAction::PublishGroupInfo {
+            room_id: self.room_id,
+            epoch: target_epoch,
+            group_info_bytes,
+        });
+
+        let commit_payload = mls_message_out
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::Serialization(format!("Failed to serialize commit: {e}")))?;
+
+        let mut commit_header = FrameHeader::new(Opcode::Commit);
+        commit_header.set_room_id(self.room_id);
+        commit_header.set_sender_id(self.member_id);
+        let commit_frame = Frame::new(commit_header, commit_payload);
+
+        actions.push(MlsAction::SendCommit(commit_frame));
+
+        actions.push(MlsAction::Log {
+            message: format!("Updating own leaf in group {}", self.room_id),
+        });
+
+        Ok(actions)
+    }
+
     /// Leave the group voluntarily.
     ///
     /// Creates a Remove proposal for this member. The proposal must be sent
@@ -943,6 +1277,19 @@ impl<E: Environment> MlsGroup<E> {
     }
 }
 
+/// Serialized form of an [`MlsGroup`] produced by
+/// [`MlsGroup::export_snapshot`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GroupSnapshot {
+    room_id: RoomId,
+    member_id: MemberId,
+    group_id: Vec<u8>,
+    /// TLS-serialized signature key pair.
+    signer: Vec<u8>,
+    /// Contents of the group's `OpenMLS` storage provider.
+    storage: HashMap<Vec<u8>, Vec<u8>>,
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -1158,6 +1505,25 @@ mod tests {
         assert!(has_log, "remove_members should log the removed member ID");
     }
 
+    #[test]
+    fn member_credentials_follow_membership() {
+        let env = MockEnv::with_crypto_rng();
+        let room_id = 0x1234_5678_9abc_def0_1234_5678_9abc_def0;
+
+        let alice_id = 42u64;
+        let (mut alice_group, _) =
+            MlsGroup::new(env.clone(), room_id, alice_id).expect("alice create group");
+        let (bob_kp_bytes, _, _) =
+            MlsGroup::generate_key_package(env, 100).expect("bob generate key package");
+        alice_group.add_members_from_bytes(&[bob_kp_bytes]).expect("alice add bob");
+        alice_group.merge_pending_commit().expect("merge add commit");
+
+        assert_eq!(alice_group.member_credentials(), vec![
+            MemberCredential::BasicId(alice_id),
+            MemberCredential::BasicId(100),
+        ]);
+    }
+
     /// Test that `remove_members` rejects removing self.
     #[test]
     fn remove_members_rejects_self_removal() {
@@ -7,6 +7,7 @@
 //! # Components
 //!
 //! - [`group`]: Client-side MLS group state machine
+//! - [`credential`]: Member credentials and their verification
 //! - [`state`]: MLS group state for storage and validation
 //! - [`provider`]: `OpenMLS` provider integration
 //! - [`validator`]: Frame validation for server sequencing
@@ -14,6 +15,7 @@
 //! - [`constants`]: Protocol constants and limits
 
 pub mod constants;
+pub mod credential;
 pub mod error;
 pub mod group;
 pub mod provider;
@@ -21,8 +23,9 @@ pub mod state;
 pub mod validator;
 
 pub use constants::MAX_EPOCH;
+pub use credential::{AcceptAllCredentials, Credential, CredentialVerifier};
 pub use error::MlsError;
-pub use group::{MemberId, MlsAction, MlsGroup, PendingJoinState, RoomId};
+pub use group::{MemberId, MemberLeaf, MlsAction, MlsGroup, PendingJoinState, RoomId};
 pub use provider::MlsProvider;
 pub use state::MlsGroupState;
 pub use validator::{MlsValidator, ValidationResult};
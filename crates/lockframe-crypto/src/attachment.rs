@@ -0,0 +1,120 @@
+//! Attachment encryption
+//!
+//! Each attachment is sealed with `XChaCha20-Poly1305` under a key of its
+//! own. The key, nonce and SHA-256 hash of the ciphertext travel in the
+//! message announcing the attachment, which the room's sender keys already
+//! protect, so the ciphertext itself can be stored or relayed by anyone.
+//!
+//! The hash names the ciphertext, not the file: it is checked before
+//! decrypting so a swapped blob is reported as such, and frontends show it
+//! so members can tell attachments apart.
+//!
+//! All functions are pure - the key and nonce must be provided by the
+//! caller.
+
+use chacha20poly1305::{
+    XChaCha20Poly1305, XNonce,
+    aead::{Aead, KeyInit},
+};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Size of the per-attachment key (32 bytes)
+pub const ATTACHMENT_KEY_SIZE: usize = 32;
+
+/// Size of the random `XChaCha20` nonce (24 bytes)
+pub const ATTACHMENT_NONCE_SIZE: usize = 24;
+
+/// Size of the ciphertext hash (32 bytes)
+pub const ATTACHMENT_HASH_SIZE: usize = 32;
+
+/// Errors from opening an attachment
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AttachmentError {
+    /// Ciphertext is not the one the message announced
+    #[error("attachment does not match its hash")]
+    HashMismatch,
+
+    /// Wrong key, or the ciphertext was modified
+    #[error("attachment could not be decrypted")]
+    DecryptionFailed,
+}
+
+/// Encrypt `plaintext` under a fresh attachment key.
+///
+/// # Security
+///
+/// - Caller MUST provide a fresh cryptographically secure `key` and `nonce` for
+///   every attachment
+pub fn seal_attachment(
+    plaintext: &[u8],
+    key: &[u8; ATTACHMENT_KEY_SIZE],
+    nonce: &[u8; ATTACHMENT_NONCE_SIZE],
+) -> Vec<u8> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let Ok(ciphertext) = cipher.encrypt(XNonce::from_slice(nonce), plaintext) else {
+        unreachable!("XChaCha20-Poly1305 encryption cannot fail with valid inputs");
+    };
+    ciphertext
+}
+
+/// SHA-256 of a sealed attachment.
+pub fn attachment_hash(ciphertext: &[u8]) -> [u8; ATTACHMENT_HASH_SIZE] {
+    Sha256::digest(ciphertext).into()
+}
+
+/// Decrypt an attachment sealed by [`seal_attachment`], checking it against
+/// the announced `hash` first.
+///
+/// # Errors
+///
+/// - `HashMismatch`: the ciphertext is not the announced one
+/// - `DecryptionFailed`: wrong key or nonce, or tampered ciphertext
+pub fn open_attachment(
+    ciphertext: &[u8],
+    key: &[u8; ATTACHMENT_KEY_SIZE],
+    nonce: &[u8; ATTACHMENT_NONCE_SIZE],
+    hash: &[u8; ATTACHMENT_HASH_SIZE],
+) -> Result<Vec<u8>, AttachmentError> {
+    if attachment_hash(ciphertext) != *hash {
+        return Err(AttachmentError::HashMismatch);
+    }
+    let cipher = XChaCha20Poly1305::new(key.into());
+    cipher
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| AttachmentError::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachment_round_trips() {
+        let ciphertext = seal_attachment(b"cat.png bytes", &[1; 32], &[2; 24]);
+        let hash = attachment_hash(&ciphertext);
+        assert_eq!(
+            open_attachment(&ciphertext, &[1; 32], &[2; 24], &hash).unwrap(),
+            b"cat.png bytes"
+        );
+    }
+
+    #[test]
+    fn swapped_ciphertext_fails_the_hash_check() {
+        let ciphertext = seal_attachment(b"cat.png bytes", &[1; 32], &[2; 24]);
+        let hash = attachment_hash(&ciphertext);
+
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            open_attachment(&tampered, &[1; 32], &[2; 24], &hash),
+            Err(AttachmentError::HashMismatch)
+        );
+
+        // A ciphertext that matches its hash still needs the right key
+        assert_eq!(
+            open_attachment(&ciphertext, &[3; 32], &[2; 24], &hash),
+            Err(AttachmentError::DecryptionFailed)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Domain separation labels
+//!
+//! Every label a Lockframe key is derived under, from the MLS exporter down
+//! to per-message keys. Keeping them in one place means a new derivation
+//! cannot reuse an existing context by accident: the labels fed to the same
+//! PRF must be prefix-free, which is checked at compile time.
+//!
+//! ```text
+//! MLS exporter(SENDER_KEY_EXPORTER, SENDER_KEY_EXPORTER_CONTEXT) → epoch secret
+//!     HKDF-Expand(SENDER_SEED || epoch || sender_index) → chain key 0
+//!         HMAC(chain key, CHAIN_KEY) → next chain key
+//!         HMAC(chain key, MESSAGE_KEY) → message key
+//! ```
+//!
+//! Nonces are not derived: they are built from epoch, sender index,
+//! generation and random bytes, see [`crate::encrypt_message`].
+//!
+//! Changing any label changes every key below it, so a change needs a new
+//! protocol version.
+
+/// MLS exporter label for the per-epoch sender key secret.
+pub const SENDER_KEY_EXPORTER: &str = "lockframe sender keys v1";
+
+/// MLS exporter context for the per-epoch sender key secret.
+pub const SENDER_KEY_EXPORTER_CONTEXT: &[u8] = b"";
+
+/// HKDF info prefix for each sender's ratchet seed, followed by the epoch
+/// and sender index.
+pub const SENDER_SEED: &[u8] = b"lockframeSenderV1";
+
+/// HMAC input for advancing a ratchet's chain key.
+pub const CHAIN_KEY: &[u8] = b"chain";
+
+/// HMAC input for a ratchet's message key.
+pub const MESSAGE_KEY: &[u8] = b"message";
+
+/// Labels used as HMAC input under a chain key.
+const RATCHET_LABELS: &[&[u8]] = &[CHAIN_KEY, MESSAGE_KEY];
+
+const _: () = assert!(is_prefix_free(RATCHET_LABELS), "ratchet labels must be prefix-free");
+
+/// Whether no label is a prefix of (or equal to) another, so that labels
+/// followed by any suffix still cannot collide.
+const fn is_prefix_free(labels: &[&[u8]]) -> bool {
+    let mut i = 0;
+    while i < labels.len() {
+        let mut j = 0;
+        while j < labels.len() {
+            if i != j && starts_with(labels[j], labels[i]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn starts_with(bytes: &[u8], prefix: &[u8]) -> bool {
+    if prefix.len() > bytes.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < prefix.len() {
+        if bytes[i] != prefix[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{SymmetricRatchet, derive_sender_key_seed};
+
+    #[test]
+    fn prefix_freedom_is_detected() {
+        assert!(is_prefix_free(&[b"chain", b"message"]));
+        assert!(!is_prefix_free(&[b"chain", b"chain"]));
+        assert!(!is_prefix_free(&[b"key", b"keys"]));
+    }
+
+    #[test]
+    fn derivation_tree_has_no_colliding_contexts() {
+        let epoch_secret = [7u8; 32];
+        let mut contexts = HashSet::new();
+        let mut keys = HashSet::new();
+
+        for epoch in 0..4u64 {
+            for sender_index in 0..4u32 {
+                let mut info = SENDER_SEED.to_vec();
+                info.extend_from_slice(&epoch.to_be_bytes());
+                info.extend_from_slice(&sender_index.to_be_bytes());
+                assert!(contexts.insert(info), "HKDF info reused");
+
+                let seed = derive_sender_key_seed(&epoch_secret, epoch, sender_index);
+                assert!(keys.insert(seed), "seed collides");
+
+                let mut ratchet = SymmetricRatchet::new(&seed);
+                for _ in 0..4 {
+                    let message_key = ratchet.advance().unwrap();
+                    assert!(keys.insert(*message_key.key()), "message key collides");
+                    assert!(keys.insert(*ratchet.chain_key()), "chain key collides");
+                }
+            }
+        }
+    }
+}
@@ -50,10 +50,32 @@
 //! - MLS commit advances epoch -> new epoch secret
 //! - New epoch secret -> all sender keys re-derived from scratch
 //! - Previous compromise doesn't affect new epoch's messages
+//!
+//! Every derivation label is defined in [`labels`].
+//!
+//! # Backups
+//!
+//! [`backup`] seals client state under a passphrase-derived key, so a backup
+//! can be kept by an untrusted party and restored on another device.
+//!
+//! # Attachments
+//!
+//! [`attachment`] seals files under a key of their own, announced in an
+//! encrypted message, so the ciphertext can live outside the room's log.
 
+pub mod attachment;
+pub mod backup;
+pub mod labels;
 pub mod sender_keys;
 
+pub use attachment::{
+    ATTACHMENT_HASH_SIZE, ATTACHMENT_KEY_SIZE, ATTACHMENT_NONCE_SIZE, AttachmentError,
+    attachment_hash, open_attachment, seal_attachment,
+};
+pub use backup::{
+    BACKUP_NONCE_SIZE, BACKUP_SALT_SIZE, BackupError, BackupParams, open_backup, seal_backup,
+};
 pub use sender_keys::{
-    EncryptedMessage, MessageKey, NONCE_RANDOM_SIZE, SenderKeyError, SymmetricRatchet,
+    EncryptedMessage, MAX_SKIP, MessageKey, NONCE_RANDOM_SIZE, SenderKeyError, SymmetricRatchet,
     decrypt_message, derive_sender_key_seed, encrypt_message,
 };
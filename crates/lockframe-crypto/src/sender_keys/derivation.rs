@@ -3,8 +3,7 @@
 use hkdf::Hkdf;
 use sha2::Sha256;
 
-/// Label used for sender key derivation
-const SENDER_KEY_LABEL: &[u8] = b"lockframeSenderV1";
+use crate::labels::SENDER_SEED;
 
 /// Derive a sender key seed from the MLS epoch secret.
 ///
@@ -24,9 +23,8 @@ pub fn derive_sender_key_seed(epoch_secret: &[u8], epoch: u64, sender_index: u32
     let hkdf = Hkdf::<Sha256>::new(None, epoch_secret);
 
     // Build the info parameter: label || epoch || sender_index
-    // Capacity: 16 (label) + 8 (epoch) + 4 (sender_index) = 28
-    let mut info = Vec::with_capacity(28);
-    info.extend_from_slice(SENDER_KEY_LABEL);
+    let mut info = Vec::with_capacity(SENDER_SEED.len() + 8 + 4);
+    info.extend_from_slice(SENDER_SEED);
     info.extend_from_slice(&epoch.to_be_bytes());
     info.extend_from_slice(&sender_index.to_be_bytes());
 
@@ -11,18 +11,13 @@ use sha2::Sha256;
 use zeroize::Zeroize;
 
 use super::error::SenderKeyError;
+use crate::labels::{CHAIN_KEY, MESSAGE_KEY};
 
 type HmacSha256 = Hmac<Sha256>;
 
-/// Label for deriving the next chain key
-const CHAIN_LABEL: &[u8] = b"chain";
-
-/// Label for deriving a message key
-const MESSAGE_LABEL: &[u8] = b"message";
-
 /// Maximum number of generations to skip when catching up.
 /// This limits the work done when receiving out-of-order messages.
-const MAX_SKIP: u32 = 1000;
+pub const MAX_SKIP: u32 = 1000;
 
 /// A message key derived from the ratchet.
 ///
@@ -83,6 +78,23 @@ impl SymmetricRatchet {
         Self { chain_key: *seed, generation: 0 }
     }
 
+    /// Restore a ratchet from persisted state.
+    ///
+    /// `chain_key` and `generation` must be the latest values read from the
+    /// same ratchet via [`Self::chain_key`] and [`Self::generation`]. Restoring
+    /// an older state would derive message keys that were already used.
+    pub fn from_state(chain_key: [u8; 32], generation: u32) -> Self {
+        Self { chain_key, generation }
+    }
+
+    /// Current chain key, for persistence.
+    ///
+    /// Anyone holding this can derive every future message key of the
+    /// ratchet. Store it with the same care as the MLS group state.
+    pub fn chain_key(&self) -> &[u8; 32] {
+        &self.chain_key
+    }
+
     /// Current generation number.
     ///
     /// This is the number of times `advance()` has been called.
@@ -158,7 +170,7 @@ impl SymmetricRatchet {
         let Ok(mut mac) = HmacSha256::new_from_slice(&self.chain_key) else {
             unreachable!("HMAC-SHA256 accepts any key size");
         };
-        mac.update(MESSAGE_LABEL);
+        mac.update(MESSAGE_KEY);
         let result = mac.finalize().into_bytes();
 
         let mut key = [0u8; 32];
@@ -171,7 +183,7 @@ impl SymmetricRatchet {
         let Ok(mut mac) = HmacSha256::new_from_slice(&self.chain_key) else {
             unreachable!("HMAC-SHA256 accepts any key size");
         };
-        mac.update(CHAIN_LABEL);
+        mac.update(CHAIN_KEY);
         let result = mac.finalize().into_bytes();
 
         let mut key = [0u8; 32];
@@ -217,6 +229,20 @@ mod tests {
         assert_eq!(ratchet.generation(), 2);
     }
 
+    #[test]
+    fn restored_ratchet_continues_sequence() {
+        let mut original = SymmetricRatchet::new(&test_seed());
+        let _ = original.advance().unwrap();
+
+        let mut restored =
+            SymmetricRatchet::from_state(*original.chain_key(), original.generation());
+
+        let expected = original.advance().unwrap();
+        let actual = restored.advance().unwrap();
+        assert_eq!(actual.generation(), expected.generation());
+        assert_eq!(actual.key(), expected.key());
+    }
+
     #[test]
     fn advance_produces_unique_keys() {
         let mut ratchet = SymmetricRatchet::new(&test_seed());
@@ -0,0 +1,241 @@
+//! Allocation tracking for performance oracles.
+//!
+//! With the `alloc-tracking` feature the harness installs
+//! [`TrackingAllocator`] as the global allocator. It forwards to the system
+//! allocator and counts, per thread, what the code under [`measure`]
+//! allocates. Counts are per thread so tests running in parallel do not
+//! see each other's allocations, and the simulation is single threaded, so
+//! the same run always counts the same.
+//!
+//! Budgets hold the zero-copy and batching work to account: "routing 10K
+//! frames allocates fewer than N bytes" is an assertion on
+//! [`AllocStats::within`], or on a scenario with
+//! [`Scenario::with_allocation_budget`](crate::scenario::Scenario::with_allocation_budget).
+
+#![allow(unsafe_code, reason = "A global allocator is an unsafe trait")]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fmt,
+};
+
+/// Allocations made while measuring.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Allocations, reallocations included.
+    pub allocations: u64,
+    /// Deallocations.
+    pub deallocations: u64,
+    /// Bytes allocated, counting the full new size of each reallocation.
+    pub bytes_allocated: u64,
+    /// Bytes freed.
+    pub bytes_freed: u64,
+    /// Most bytes held at once beyond what was held when measuring began.
+    pub peak_bytes: u64,
+}
+
+impl AllocStats {
+    /// Check the measured code stayed within `max_bytes` allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the overshoot if it allocated more.
+    pub fn within(&self, max_bytes: u64) -> Result<(), String> {
+        if self.bytes_allocated <= max_bytes {
+            return Ok(());
+        }
+        Err(format!(
+            "allocated {} bytes, over the budget of {max_bytes}: {self}",
+            self.bytes_allocated
+        ))
+    }
+}
+
+impl fmt::Display for AllocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} allocations ({} bytes), {} frees ({} bytes), peak {} bytes",
+            self.allocations,
+            self.bytes_allocated,
+            self.deallocations,
+            self.bytes_freed,
+            self.peak_bytes
+        )
+    }
+}
+
+/// Counters of the current thread.
+struct Counters {
+    /// Nested [`measure`] calls in progress; counting happens above zero.
+    depth: Cell<u32>,
+    allocations: Cell<u64>,
+    deallocations: Cell<u64>,
+    bytes_allocated: Cell<u64>,
+    bytes_freed: Cell<u64>,
+    /// Bytes held now, relative to when measuring began. May go negative
+    /// when freeing what was allocated before.
+    live: Cell<i64>,
+    peak: Cell<i64>,
+}
+
+thread_local! {
+    // Const-initialized without a destructor, so the allocator can touch it
+    // without allocating
+    static COUNTERS: Counters = const {
+        Counters {
+            depth: Cell::new(0),
+            allocations: Cell::new(0),
+            deallocations: Cell::new(0),
+            bytes_allocated: Cell::new(0),
+            bytes_freed: Cell::new(0),
+            live: Cell::new(0),
+            peak: Cell::new(0),
+        }
+    };
+}
+
+impl Counters {
+    fn snapshot(&self) -> AllocStats {
+        AllocStats {
+            allocations: self.allocations.get(),
+            deallocations: self.deallocations.get(),
+            bytes_allocated: self.bytes_allocated.get(),
+            bytes_freed: self.bytes_freed.get(),
+            peak_bytes: self.peak.get().max(0) as u64,
+        }
+    }
+
+    fn on_alloc(&self, size: usize) {
+        if self.depth.get() == 0 {
+            return;
+        }
+        self.allocations.set(self.allocations.get() + 1);
+        self.bytes_allocated.set(self.bytes_allocated.get() + size as u64);
+        self.live.set(self.live.get() + size as i64);
+        self.peak.set(self.peak.get().max(self.live.get()));
+    }
+
+    fn on_dealloc(&self, size: usize) {
+        if self.depth.get() == 0 {
+            return;
+        }
+        self.deallocations.set(self.deallocations.get() + 1);
+        self.bytes_freed.set(self.bytes_freed.get() + size as u64);
+        self.live.set(self.live.get() - size as i64);
+    }
+}
+
+/// Run `f`, returning what it allocated on this thread alongside its
+/// result.
+///
+/// Without the `alloc-tracking` feature nothing is counted and the stats are
+/// all zero.
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, AllocStats) {
+    let start = COUNTERS.with(|counters| {
+        if counters.depth.get() == 0 {
+            counters.live.set(0);
+            counters.peak.set(0);
+        }
+        counters.depth.set(counters.depth.get() + 1);
+        counters.snapshot()
+    });
+
+    let result = f();
+
+    let end = COUNTERS.with(|counters| {
+        counters.depth.set(counters.depth.get() - 1);
+        counters.snapshot()
+    });
+    let stats = AllocStats {
+        allocations: end.allocations - start.allocations,
+        deallocations: end.deallocations - start.deallocations,
+        bytes_allocated: end.bytes_allocated - start.bytes_allocated,
+        bytes_freed: end.bytes_freed - start.bytes_freed,
+        peak_bytes: end.peak_bytes.saturating_sub(start.peak_bytes),
+    };
+    (result, stats)
+}
+
+/// Whether allocations are counted: the `alloc-tracking` feature installs
+/// [`TrackingAllocator`].
+pub const fn is_enabled() -> bool {
+    cfg!(feature = "alloc-tracking")
+}
+
+/// Global allocator counting allocations made under [`measure`].
+pub struct TrackingAllocator;
+
+/// Run `f` on this thread's counters, unless the thread is being torn down.
+fn with_counters(f: impl FnOnce(&Counters)) {
+    let _ = COUNTERS.try_with(f);
+}
+
+// SAFETY: Every call is forwarded unchanged to the system allocator; the
+// counters are plain thread-local cells that never allocate.
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        with_counters(|counters| counters.on_alloc(layout.size()));
+        // SAFETY: The caller upholds `GlobalAlloc::alloc`'s contract.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        with_counters(|counters| counters.on_alloc(layout.size()));
+        // SAFETY: The caller upholds `GlobalAlloc::alloc_zeroed`'s contract.
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        with_counters(|counters| counters.on_dealloc(layout.size()));
+        // SAFETY: The caller upholds `GlobalAlloc::dealloc`'s contract.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        with_counters(|counters| {
+            counters.on_dealloc(layout.size());
+            counters.on_alloc(new_size);
+        });
+        // SAFETY: The caller upholds `GlobalAlloc::realloc`'s contract.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[cfg(all(test, feature = "alloc-tracking"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_counts_allocations_of_its_closure() {
+        let (bytes, stats) = measure(|| vec![0u8; 4096]);
+
+        assert_eq!(bytes.len(), 4096);
+        assert_eq!(stats.allocations, 1);
+        assert_eq!(stats.bytes_allocated, 4096);
+        assert_eq!(stats.peak_bytes, 4096);
+        assert!(stats.within(4096).is_ok());
+        assert!(stats.within(4095).is_err());
+    }
+
+    #[test]
+    fn peak_tracks_bytes_held_at_once() {
+        let ((), stats) = measure(|| {
+            for _ in 0..10 {
+                drop(vec![0u8; 1000]);
+            }
+        });
+
+        assert_eq!(stats.bytes_allocated, 10_000);
+        assert_eq!(stats.bytes_freed, 10_000);
+        assert_eq!(stats.peak_bytes, 1000);
+    }
+
+    #[test]
+    fn nothing_is_counted_outside_measure() {
+        let before = COUNTERS.with(Counters::snapshot);
+        drop(vec![0u8; 64]);
+        assert_eq!(COUNTERS.with(Counters::snapshot), before);
+    }
+}
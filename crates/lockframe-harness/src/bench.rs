@@ -0,0 +1,375 @@
+//! Deterministic performance benchmarks.
+//!
+//! A [`Benchmark`] measures the server's sequencer and broadcast path in
+//! virtual time. Members of each room of a fixed [`Topology`] send messages
+//! on a schedule jittered by the seed. Frames reach a `ServerDriver` after a
+//! fixed link latency and are processed one at a time. The server spends
+//! virtual time only on storage, at a fixed cost per read and write, so a
+//! frame that arrives while another is processed waits its turn.
+//!
+//! The [`BenchReport`] gives frames routed per virtual second and the
+//! latency from a message's send to its delivery at each recipient. With
+//! the same seed and topology every run reports the same numbers, so a test
+//! can hold them to a baseline: a regression that adds storage work or
+//! fan-out shows up as lower throughput or higher latency, not as noise.
+//!
+//! Reports are JSON. When [`BENCH_DIR_VAR`] is set, [`BenchReport::save`]
+//! writes them there for CI to keep.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use lockframe_core::env::Environment;
+use lockframe_proto::{Frame, FrameHeader, Opcode};
+use lockframe_server::{DriverConfig, ServerAction, ServerDriver, ServerEvent};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{ByzantineClient, SimEnv, SimStorage, SimStorageStats, scenario::LatencySummary};
+
+/// Environment variable naming the directory benchmark reports are written
+/// to.
+pub const BENCH_DIR_VAR: &str = "LOCKFRAME_BENCH_DIR";
+
+/// Shape of the load a [`Benchmark`] puts on the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Topology {
+    /// Rooms, each with its own members.
+    pub rooms: usize,
+    /// Members per room, each on its own session.
+    pub members_per_room: usize,
+    /// Messages each member sends.
+    pub messages_per_member: usize,
+    /// Time between a member's messages.
+    pub send_interval: Duration,
+    /// Most a send is delayed from its slot, drawn from the seed.
+    pub jitter: Duration,
+    /// One-way latency between a member and the server.
+    pub link_latency: Duration,
+    /// Payload size of each message.
+    pub payload_bytes: usize,
+    /// Server time spent per storage read.
+    pub read_cost: Duration,
+    /// Server time spent per storage write.
+    pub write_cost: Duration,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Self {
+            rooms: 4,
+            members_per_room: 8,
+            messages_per_member: 25,
+            send_interval: Duration::from_millis(100),
+            jitter: Duration::from_millis(50),
+            link_latency: Duration::from_millis(20),
+            payload_bytes: 256,
+            read_cost: Duration::from_micros(50),
+            write_cost: Duration::from_micros(200),
+        }
+    }
+}
+
+/// Storage work the server did, per message sent.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StorageCost {
+    /// Storage reads per message.
+    pub reads_per_message: f64,
+    /// Storage writes per message.
+    pub writes_per_message: f64,
+}
+
+/// Results of a [`Benchmark`] run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    /// Benchmark name.
+    pub name: String,
+    /// Seed the run used.
+    pub seed: u64,
+    /// Load the run put on the server.
+    pub topology: Topology,
+    /// Messages members sent.
+    pub messages_sent: usize,
+    /// Frames the server routed to room members, senders included.
+    pub frames_routed: usize,
+    /// Virtual time from the first send to the last delivery.
+    pub virtual_time: Duration,
+    /// Frames routed per virtual second.
+    pub frames_per_second: f64,
+    /// Latency from send to delivery, over every routed frame.
+    pub latency: Option<LatencySummary>,
+    /// Storage work per message.
+    pub storage: StorageCost,
+}
+
+impl BenchReport {
+    /// The report as pretty-printed JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report cannot be serialized.
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    /// Write the report as JSON into `dir`, named after the benchmark.
+    ///
+    /// Returns the file written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&self, dir: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let json = self.to_json()?;
+        let path = dir.as_ref().join(format!("bench-{}-{}.json", self.name, self.seed));
+
+        std::fs::create_dir_all(dir.as_ref()).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Write the report into [`BENCH_DIR_VAR`], if set.
+    ///
+    /// Returns the file written, or `None` if the variable is unset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self) -> Result<Option<PathBuf>, String> {
+        std::env::var_os(BENCH_DIR_VAR).map(|dir| self.write(dir)).transpose()
+    }
+}
+
+/// A message on its way to the server.
+struct Arrival {
+    session_id: u64,
+    sent_at: Duration,
+    frame: Frame,
+}
+
+/// Deterministic benchmark of the server's sequencer and broadcast path.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    name: String,
+    seed: u64,
+    topology: Topology,
+}
+
+impl Benchmark {
+    /// Benchmark called `name` with the default topology and seed 0.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), seed: 0, topology: Topology::default() }
+    }
+
+    /// Jitter sends with `seed`.
+    #[must_use]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Put `topology`'s load on the server.
+    #[must_use]
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Run the benchmark.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server fails to set up the rooms, or rejects
+    /// or drops a message.
+    pub fn run(&self) -> Result<BenchReport, String> {
+        let topology = &self.topology;
+        let env = SimEnv::with_manual_clock(self.seed);
+        let storage = SimStorage::new(self.seed).with_clock(env.clone());
+        let mut server = ServerDriver::new(env.clone(), storage.clone(), DriverConfig::default());
+
+        let mut arrivals = BTreeMap::new();
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        for room in 0..topology.rooms {
+            let room_id = room as u128 + 1;
+            let members = setup_room(&mut server, room_id, room, topology.members_per_room)?;
+            for (sender_id, session_id) in members {
+                for message in 0..topology.messages_per_member {
+                    let slot = topology.send_interval * u32::try_from(message).unwrap_or(u32::MAX);
+                    let jitter = topology.jitter.mul_f64(rng.r#gen::<f64>());
+                    let sent_at = slot + jitter;
+
+                    let mut header = FrameHeader::new(Opcode::AppMessage);
+                    header.set_room_id(room_id);
+                    header.set_sender_id(sender_id);
+                    let frame = Frame::new(header, vec![0u8; topology.payload_bytes]);
+                    // Ties break by insertion order, which is deterministic
+                    let key = (sent_at + topology.link_latency, arrivals.len());
+                    arrivals.insert(key, Arrival { session_id, sent_at, frame });
+                }
+            }
+        }
+        // Setup is free: time and storage work count from the first send
+        let setup = storage.stats();
+        storage.set_latency(topology.read_cost, topology.write_cost);
+        let start = env.now();
+
+        let messages_sent = arrivals.len();
+        let mut frames_routed = 0;
+        let mut latencies = Vec::new();
+        let mut last_delivery = Duration::ZERO;
+        for ((arrives_at, _), arrival) in arrivals {
+            // The server picks the frame up once it arrived and the previous
+            // one is done
+            env.advance(arrives_at.saturating_sub(env.now() - start));
+
+            let actions = server
+                .process_event(ServerEvent::FrameReceived {
+                    session_id: arrival.session_id,
+                    frame: arrival.frame,
+                })
+                .map_err(|e| format!("message from session {} failed: {e}", arrival.session_id))?;
+            let recipients = broadcast_recipients(&actions).ok_or_else(|| {
+                format!("message from session {} was not broadcast", arrival.session_id)
+            })?;
+
+            let delivered_at = env.now() - start + self.topology.link_latency;
+            frames_routed += recipients;
+            latencies.extend(std::iter::repeat_n(
+                delivered_at.saturating_sub(arrival.sent_at),
+                recipients,
+            ));
+            last_delivery = last_delivery.max(delivered_at);
+        }
+
+        latencies.sort_unstable();
+        let frames_per_second = if last_delivery.is_zero() {
+            0.0
+        } else {
+            frames_routed as f64 / last_delivery.as_secs_f64()
+        };
+        Ok(BenchReport {
+            name: self.name.clone(),
+            seed: self.seed,
+            topology: self.topology.clone(),
+            messages_sent,
+            frames_routed,
+            virtual_time: last_delivery,
+            frames_per_second,
+            latency: LatencySummary::from_sorted(&latencies),
+            storage: storage_cost(setup, storage.stats(), messages_sent),
+        })
+    }
+}
+
+/// Connect `members` sessions and put them all in `room_id`, created by the
+/// first. Returns each member's sender and session ID.
+///
+/// Sessions are numbered by room so rooms never share one.
+fn setup_room(
+    server: &mut ServerDriver<SimEnv, SimStorage>,
+    room_id: u128,
+    room: usize,
+    members: usize,
+) -> Result<Vec<(u64, u64)>, String> {
+    let members: Vec<(u64, u64)> = (0..members)
+        .map(|member| {
+            let id = (room * members + member) as u64 + 1;
+            (id, id)
+        })
+        .collect();
+
+    for &(sender_id, session_id) in &members {
+        let hello = ByzantineClient::new(sender_id).hello();
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+            .and_then(|_| {
+                server.process_event(ServerEvent::FrameReceived { session_id, frame: hello })
+            })
+            .map_err(|e| format!("session {session_id} failed to connect: {e}"))?;
+    }
+
+    let Some(&(creator, creator_session)) = members.first() else {
+        return Ok(members);
+    };
+    server.create_room(room_id, creator_session).map_err(|e| e.to_string())?;
+    for &(recipient, _) in &members[1..] {
+        let mut header = FrameHeader::new(Opcode::Welcome);
+        header.set_room_id(room_id);
+        header.set_sender_id(creator);
+        header.set_recipient_id(recipient);
+        server
+            .process_event(ServerEvent::FrameReceived {
+                session_id: creator_session,
+                frame: Frame::new(header, vec![0xBE, 0xEF]),
+            })
+            .map_err(|e| format!("welcome to {recipient} failed: {e}"))?;
+    }
+    Ok(members)
+}
+
+/// Number of sessions a broadcast in `actions` goes to. `None` without one.
+fn broadcast_recipients(actions: &[ServerAction<tokio::time::Instant>]) -> Option<usize> {
+    actions.iter().find_map(|action| match action {
+        ServerAction::Broadcast { session_ids, frame }
+            if frame.header.opcode_enum() == Some(Opcode::AppMessage) =>
+        {
+            Some(session_ids.len())
+        },
+        _ => None,
+    })
+}
+
+/// Storage work between `setup` and `end`, per message.
+fn storage_cost(setup: SimStorageStats, end: SimStorageStats, messages: usize) -> StorageCost {
+    let per_message = |count: usize| {
+        if messages == 0 { 0.0 } else { count as f64 / messages as f64 }
+    };
+    StorageCost {
+        reads_per_message: per_message(end.reads - setup.reads),
+        writes_per_message: per_message(end.writes - setup.writes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small() -> Topology {
+        Topology { rooms: 2, members_per_room: 3, messages_per_member: 4, ..Topology::default() }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_report() {
+        let run = |seed| Benchmark::new("small").with_seed(seed).with_topology(small()).run();
+
+        let report = run(3).unwrap();
+        assert_eq!(report, run(3).unwrap());
+        assert_ne!(report.latency, run(4).unwrap().latency);
+    }
+
+    #[test]
+    fn every_message_reaches_the_rest_of_its_room() {
+        let report = Benchmark::new("small").with_topology(small()).run().unwrap();
+
+        assert_eq!(report.messages_sent, 2 * 3 * 4);
+        // Senders get their own message back as confirmation
+        assert_eq!(report.frames_routed, report.messages_sent * 3);
+        let latency = report.latency.unwrap();
+        assert!(latency.min >= 2 * small().link_latency, "{latency}");
+    }
+
+    #[test]
+    fn report_round_trips_through_json() {
+        let report = Benchmark::new("small").with_topology(small()).run().unwrap();
+
+        let json = report.to_json().unwrap();
+        let parsed: BenchReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.latency, report.latency);
+        assert_eq!(parsed.virtual_time, report.virtual_time);
+        assert!((parsed.frames_per_second - report.frames_per_second).abs() < 1e-9);
+    }
+}
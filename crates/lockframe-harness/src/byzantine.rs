@@ -0,0 +1,325 @@
+//! Byzantine client for adversarial testing.
+//!
+//! `ByzantineClient` speaks the wire protocol without following it. Tests
+//! script it to send malformed frames, rewrite captured frames to stale
+//! epochs, replay captured ciphertexts and attempt operations its role does
+//! not allow. The server and honest clients must reject each of these with
+//! an error, never by crashing or diverging from the rest of the room.
+//!
+//! The client holds no MLS state. Valid ciphertexts come from honest clients,
+//! whose frames it [captures](ByzantineClient::capture) off the wire.
+
+use lockframe_core::mls::MAX_EPOCH;
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode, Payload,
+    payloads::{
+        moderation::{CloseRoom, Kick},
+        session::Hello,
+    },
+};
+
+/// Ways to break a frame's structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformation {
+    /// Header claims more payload than the frame carries.
+    PayloadSizeMismatch,
+    /// Frame addressed to room ID 0.
+    ZeroRoom,
+    /// Epoch beyond the highest a group can reach.
+    EpochOverflow,
+    /// Payload that does not decode for its opcode.
+    GarbagePayload,
+    /// Header claims another user as sender.
+    SpoofedSender(u64),
+}
+
+/// Operations outside the sender's permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfPolicy {
+    /// Commit to a group the sender is not a member of.
+    Commit,
+    /// Kick a user without the admin role.
+    Kick {
+        /// User to kick.
+        user_id: u64,
+    },
+    /// Close the room without being its owner.
+    CloseRoom,
+}
+
+/// Scriptable misbehaving client.
+///
+/// Each method returns the frame to send, so tests choose where it goes:
+/// a server driver, an honest client, or a scenario step.
+#[derive(Debug, Clone)]
+pub struct ByzantineClient {
+    /// User ID the client authenticates as.
+    sender_id: u64,
+    /// Frames observed on the wire, oldest first.
+    captured: Vec<Frame>,
+}
+
+impl ByzantineClient {
+    /// Create a client authenticating as `sender_id`.
+    pub fn new(sender_id: u64) -> Self {
+        Self { sender_id, captured: Vec::new() }
+    }
+
+    /// User ID the client authenticates as.
+    pub fn sender_id(&self) -> u64 {
+        self.sender_id
+    }
+
+    /// Well-formed Hello, so the server authenticates the session.
+    pub fn hello(&self) -> Frame {
+        Payload::Hello(Hello {
+            version: 1,
+            capabilities: vec![],
+            sender_id: Some(self.sender_id),
+            auth_token: None,
+        })
+        .into_frame(FrameHeader::new(Opcode::Hello))
+        .expect("Hello encodes")
+    }
+
+    /// Record a frame seen on the wire for later replay or rewriting.
+    pub fn capture(&mut self, frame: &Frame) {
+        self.captured.push(frame.clone());
+    }
+
+    /// Frames captured so far, oldest first.
+    pub fn captured(&self) -> &[Frame] {
+        &self.captured
+    }
+
+    /// Application message to `room_id` broken by `malformation`.
+    pub fn malformed(&self, room_id: u128, malformation: Malformation) -> Frame {
+        let mut header = self.header(Opcode::AppMessage, room_id);
+        let payload = vec![0xAB; 16];
+
+        match malformation {
+            Malformation::PayloadSizeMismatch => {
+                let mut frame = Frame::new(header, payload);
+                frame.header.set_payload_size(frame.header.payload_size() + 1);
+                return frame;
+            },
+            Malformation::ZeroRoom => header.set_room_id(0),
+            Malformation::EpochOverflow => header.set_epoch(MAX_EPOCH + 1),
+            Malformation::GarbagePayload => {
+                // Moderation payloads are decoded by the server, unlike
+                // ciphertexts
+                return Frame::new(self.header(Opcode::Kick, room_id), payload);
+            },
+            Malformation::SpoofedSender(user_id) => header.set_sender_id(user_id),
+        }
+
+        Frame::new(header, payload)
+    }
+
+    /// Copy of `frame` claiming to be from `epoch`.
+    ///
+    /// The payload is untouched, so the frame still carries a valid
+    /// ciphertext for its original epoch.
+    pub fn stale_epoch(&self, frame: &Frame, epoch: u64) -> Frame {
+        let mut header = frame.header;
+        header.set_epoch(epoch);
+        Frame::new(header, frame.payload.clone())
+    }
+
+    /// Captured frame `index` resent as if sequenced at `log_index`.
+    ///
+    /// The original sender is kept, as a replayed ciphertext still names
+    /// whoever encrypted it. `None` if nothing was captured at `index`.
+    pub fn replay(&self, index: usize, log_index: u64) -> Option<Frame> {
+        let mut frame = self.captured.get(index)?.clone();
+        frame.header.set_log_index(log_index);
+        Some(frame)
+    }
+
+    /// Frame to `room_id` attempting `violation`.
+    pub fn violate(&self, room_id: u128, violation: OutOfPolicy) -> Frame {
+        let moderator_id = self.sender_id;
+        let (opcode, payload) = match violation {
+            OutOfPolicy::Commit => {
+                return Frame::new(self.header(Opcode::Commit, room_id), vec![0; 32]);
+            },
+            OutOfPolicy::Kick { user_id } => {
+                (Opcode::Kick, Payload::Kick(Kick { user_id, reason: String::new(), moderator_id }))
+            },
+            OutOfPolicy::CloseRoom => (
+                Opcode::CloseRoom,
+                Payload::CloseRoom(CloseRoom { reason: String::new(), moderator_id }),
+            ),
+        };
+        payload.into_frame(self.header(opcode, room_id)).expect("moderation payload encodes")
+    }
+
+    /// Header for a frame from this client to `room_id`.
+    fn header(&self, opcode: Opcode, room_id: u128) -> FrameHeader {
+        let mut header = FrameHeader::new(opcode);
+        header.set_room_id(room_id);
+        header.set_sender_id(self.sender_id);
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::payloads::ErrorPayload;
+    use lockframe_server::{DriverConfig, MemoryStorage, ServerAction, ServerDriver, ServerEvent};
+
+    use super::*;
+    use crate::SimEnv;
+
+    const ROOM: u128 = 0x00AB_CDEF;
+    const OWNER: u64 = 1;
+    const OWNER_SESSION: u64 = 100;
+    const BYZANTINE_SESSION: u64 = 200;
+
+    type Server = ServerDriver<SimEnv, MemoryStorage>;
+
+    /// Server with a room created by `OWNER`, which `byzantine` has been
+    /// welcomed into if `member`.
+    fn server_with_room(byzantine: &ByzantineClient, member: bool) -> Server {
+        let mut server =
+            ServerDriver::new(SimEnv::new(), MemoryStorage::new(), DriverConfig::default());
+        for (session_id, hello) in [
+            (OWNER_SESSION, ByzantineClient::new(OWNER).hello()),
+            (BYZANTINE_SESSION, byzantine.hello()),
+        ] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.process_event(ServerEvent::FrameReceived { session_id, frame: hello }).unwrap();
+        }
+        server.create_room(ROOM, OWNER_SESSION).unwrap();
+
+        if member {
+            let mut header = FrameHeader::new(Opcode::Welcome);
+            header.set_room_id(ROOM);
+            header.set_sender_id(OWNER);
+            header.set_recipient_id(byzantine.sender_id());
+            let welcome = Frame::new(header, vec![0xDE, 0xAD]);
+            send(&mut server, OWNER_SESSION, welcome);
+        }
+        server
+    }
+
+    fn send(
+        server: &mut Server,
+        session_id: u64,
+        frame: Frame,
+    ) -> Vec<ServerAction<tokio::time::Instant>> {
+        server.process_event(ServerEvent::FrameReceived { session_id, frame }).unwrap()
+    }
+
+    /// Error code sent back to `session_id`, if any.
+    fn error_code(actions: &[ServerAction<tokio::time::Instant>], session_id: u64) -> Option<u16> {
+        actions.iter().find_map(|action| match action {
+            ServerAction::SendToSession { session_id: s, frame }
+                if *s == session_id && frame.header.opcode_enum() == Some(Opcode::Error) =>
+            {
+                match Payload::from_frame(frame) {
+                    Ok(Payload::Error(error)) => Some(error.code),
+                    _ => None,
+                }
+            },
+            _ => None,
+        })
+    }
+
+    /// Log index the server assigns to the owner's next message.
+    fn next_log_index(server: &mut Server) -> u64 {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(ROOM);
+        header.set_sender_id(OWNER);
+        let actions = send(server, OWNER_SESSION, Frame::new(header, vec![1, 2, 3]));
+        actions
+            .iter()
+            .find_map(|action| match action {
+                ServerAction::Broadcast { frame, .. } => Some(frame.header.log_index()),
+                _ => None,
+            })
+            .expect("owner's message is sequenced")
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected_without_taking_a_log_index() {
+        let byzantine = ByzantineClient::new(2);
+        let mut server = server_with_room(&byzantine, true);
+        let before = next_log_index(&mut server);
+
+        // Frames failing room lookup or sequencer validation are logged and
+        // dropped by the runtime without closing the session
+        for malformation in
+            [Malformation::PayloadSizeMismatch, Malformation::ZeroRoom, Malformation::EpochOverflow]
+        {
+            let frame = byzantine.malformed(ROOM, malformation);
+            let result = server
+                .process_event(ServerEvent::FrameReceived { session_id: BYZANTINE_SESSION, frame });
+            assert!(result.is_err(), "{malformation:?}");
+        }
+
+        for (malformation, code) in [
+            (Malformation::GarbagePayload, ErrorPayload::PERMISSION_DENIED),
+            (Malformation::SpoofedSender(OWNER), ErrorPayload::PERMISSION_DENIED),
+        ] {
+            let actions =
+                send(&mut server, BYZANTINE_SESSION, byzantine.malformed(ROOM, malformation));
+            assert_eq!(error_code(&actions, BYZANTINE_SESSION), Some(code), "{malformation:?}");
+        }
+
+        assert_eq!(server.connection_count(), 2);
+        assert_eq!(next_log_index(&mut server), before + 1);
+    }
+
+    #[test]
+    fn out_of_policy_operations_are_denied() {
+        let stranger = ByzantineClient::new(3);
+        let mut server = server_with_room(&stranger, false);
+        let actions =
+            send(&mut server, BYZANTINE_SESSION, stranger.violate(ROOM, OutOfPolicy::Commit));
+        assert_eq!(error_code(&actions, BYZANTINE_SESSION), Some(ErrorPayload::NOT_A_MEMBER));
+
+        let member = ByzantineClient::new(2);
+        let mut server = server_with_room(&member, true);
+        for violation in [OutOfPolicy::Kick { user_id: OWNER }, OutOfPolicy::CloseRoom] {
+            let actions = send(&mut server, BYZANTINE_SESSION, member.violate(ROOM, violation));
+            assert_eq!(
+                error_code(&actions, BYZANTINE_SESSION),
+                Some(ErrorPayload::PERMISSION_DENIED),
+                "{violation:?}"
+            );
+        }
+
+        assert!(server.has_room(ROOM));
+        next_log_index(&mut server);
+    }
+
+    #[test]
+    fn replayed_frames_are_resequenced_for_clients_to_detect() {
+        let mut byzantine = ByzantineClient::new(2);
+        let mut server = server_with_room(&byzantine, true);
+
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(ROOM);
+        header.set_sender_id(byzantine.sender_id());
+        let actions = send(&mut server, BYZANTINE_SESSION, Frame::new(header, vec![7; 8]));
+        for action in &actions {
+            if let ServerAction::Broadcast { frame, .. } = action {
+                byzantine.capture(frame);
+            }
+        }
+        let original = byzantine.captured()[0].header.log_index();
+
+        let replay = byzantine.replay(0, original).unwrap();
+        let actions = send(&mut server, BYZANTINE_SESSION, replay);
+        let replayed = actions.iter().find_map(|action| match action {
+            ServerAction::Broadcast { frame, .. } => Some(frame.header.log_index()),
+            _ => None,
+        });
+
+        assert_eq!(replayed, Some(original + 1));
+        assert!(byzantine.replay(1, 0).is_none());
+    }
+}
@@ -0,0 +1,492 @@
+//! Captures of real network sessions.
+//!
+//! A [`Capture`] holds every frame of one or more sessions: which session it
+//! belonged to, which way it went and when, relative to the start of the
+//! capture. Captures are stored as JSON with frames hex-encoded, so an
+//! incident capture can be attached to a bug and checked in as a test
+//! fixture.
+//!
+//! [`RecordingTransport`] wraps any `Transport`, production QUIC included,
+//! and records the frames crossing its streams into a [`Recorder`]. A
+//! [`ReplayTransport`](crate::ReplayTransport) plays a capture back inside
+//! the harness.
+
+#![allow(clippy::disallowed_types, reason = "Synchronous in-memory operations only")]
+
+use std::{
+    io,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use lockframe_core::transport::{Transport, TransportConnection};
+use lockframe_proto::{Frame, FrameHeader};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Instant,
+};
+
+/// Version of the capture format.
+pub const CAPTURE_VERSION: u32 = 1;
+
+/// Which way a captured frame went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    /// Sent by the client.
+    ToServer,
+    /// Sent by the server.
+    ToClient,
+}
+
+/// A frame seen on a captured session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedFrame {
+    /// Session the frame was sent on, numbered from 1 in the order the
+    /// sessions were opened.
+    pub session: u64,
+    /// Time since the capture started.
+    pub at: Duration,
+    /// Which way the frame went.
+    pub direction: Direction,
+    /// The encoded frame.
+    #[serde(with = "hex_bytes")]
+    pub frame: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// Decode the captured frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the captured bytes are not a valid frame.
+    pub fn decode(&self) -> Result<Frame, String> {
+        Frame::decode(&self.frame).map_err(|e| e.to_string())
+    }
+}
+
+/// Frames of one or more sessions, in the order they were seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capture {
+    /// Format version, [`CAPTURE_VERSION`] when written.
+    pub version: u32,
+    /// Captured frames, oldest first.
+    pub frames: Vec<CapturedFrame>,
+}
+
+impl Capture {
+    /// Capture of `frames`.
+    pub fn new(frames: Vec<CapturedFrame>) -> Self {
+        Self { version: CAPTURE_VERSION, frames }
+    }
+
+    /// Load a capture written by [`Capture::write`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or has a newer
+    /// format version.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        let capture: Self = serde_json::from_str(&json)
+            .map_err(|e| format!("cannot parse {}: {e}", path.display()))?;
+        if capture.version > CAPTURE_VERSION {
+            return Err(format!(
+                "{} has capture version {}, newest supported is {CAPTURE_VERSION}",
+                path.display(),
+                capture.version
+            ));
+        }
+        Ok(capture)
+    }
+
+    /// Write the capture as JSON to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("cannot write {}: {e}", path.display()))
+    }
+
+    /// Sessions in the capture, in ascending order.
+    pub fn sessions(&self) -> Vec<u64> {
+        let mut sessions: Vec<u64> = self.frames.iter().map(|frame| frame.session).collect();
+        sessions.sort_unstable();
+        sessions.dedup();
+        sessions
+    }
+
+    /// Frames of `session` that went `direction`, oldest first.
+    pub fn frames(
+        &self,
+        session: u64,
+        direction: Direction,
+    ) -> impl Iterator<Item = &CapturedFrame> {
+        self.frames
+            .iter()
+            .filter(move |frame| frame.session == session && frame.direction == direction)
+    }
+}
+
+/// Collects frames into a [`Capture`], timestamped from its creation.
+///
+/// Clones record into the same capture.
+#[derive(Clone)]
+pub struct Recorder {
+    start: Instant,
+    frames: Arc<Mutex<Vec<CapturedFrame>>>,
+}
+
+impl Recorder {
+    /// Recorder whose capture starts now.
+    ///
+    /// Under turmoil, time is the simulation's.
+    pub fn new() -> Self {
+        Self { start: Instant::now(), frames: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Record `frame`, going `direction` on `session`, as seen now.
+    pub fn record(&self, session: u64, direction: Direction, frame: &Frame) {
+        let mut encoded = Vec::with_capacity(FrameHeader::SIZE + frame.payload.len());
+        if frame.encode(&mut encoded).is_err() {
+            tracing::warn!(session, "not recording a frame that does not encode");
+            return;
+        }
+        let at = self.start.elapsed();
+        lock(&self.frames).push(CapturedFrame { session, at, direction, frame: encoded });
+    }
+
+    /// Everything recorded so far.
+    pub fn capture(&self) -> Capture {
+        Capture::new(lock(&self.frames).clone())
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| {
+        // SAFETY: Turmoil is single threaded. Mutex can only be poisoned if another
+        // thread panics while holding the lock.
+        unreachable!("capture mutex poisoned in single-threaded context: {}", e)
+    })
+}
+
+/// Splits a byte stream into frames.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    buffer: Vec<u8>,
+    /// The stream stopped making sense; nothing more is parsed.
+    broken: bool,
+}
+
+impl FrameParser {
+    /// Add bytes from the stream and return the frames they complete.
+    ///
+    /// Bytes that are not a frame end parsing: they and everything after
+    /// them are ignored.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        if self.broken {
+            return Vec::new();
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        let mut frames = Vec::new();
+        while self.buffer.len() >= FrameHeader::SIZE {
+            let Ok(header) = FrameHeader::from_bytes(&self.buffer) else {
+                self.broken = true;
+                break;
+            };
+            let total = FrameHeader::SIZE + header.payload_size() as usize;
+            if self.buffer.len() < total {
+                break;
+            }
+            let Ok(frame) = Frame::decode(&self.buffer[..total]) else {
+                self.broken = true;
+                break;
+            };
+            frames.push(frame);
+            self.buffer.drain(..total);
+        }
+        frames
+    }
+}
+
+/// Where a stream's frames go.
+#[derive(Clone)]
+struct Tap {
+    recorder: Recorder,
+    session: u64,
+    direction: Direction,
+}
+
+impl Tap {
+    fn observe(&self, parser: &mut FrameParser, bytes: &[u8]) {
+        for frame in parser.push(bytes) {
+            self.recorder.record(self.session, self.direction, &frame);
+        }
+    }
+}
+
+/// Transport recording the frames of every session it opens.
+///
+/// Sessions are numbered in the order they are accepted or connected.
+/// Frames written on an accepted session go to the client, and frames
+/// written on a connected one go to the server.
+pub struct RecordingTransport<T> {
+    inner: T,
+    recorder: Recorder,
+    next_session: AtomicU64,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Record the sessions of `inner` into `recorder`.
+    pub fn new(inner: T, recorder: Recorder) -> Self {
+        Self { inner, recorder, next_session: AtomicU64::new(1) }
+    }
+
+    /// Recorder the sessions are recorded into.
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    /// Transport being recorded.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn wrap(&self, inner: T::Connection, sends: Direction) -> RecordingConnection<T::Connection> {
+        let session = self.next_session.fetch_add(1, Ordering::Relaxed);
+        RecordingConnection { inner, recorder: self.recorder.clone(), session, sends }
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for RecordingTransport<T> {
+    type Connection = RecordingConnection<T::Connection>;
+
+    async fn accept(&self) -> io::Result<Self::Connection> {
+        let connection = self.inner.accept().await?;
+        Ok(self.wrap(connection, Direction::ToClient))
+    }
+
+    async fn connect(&self, remote: SocketAddr) -> io::Result<Self::Connection> {
+        let connection = self.inner.connect(remote).await?;
+        Ok(self.wrap(connection, Direction::ToServer))
+    }
+}
+
+/// Connection opened by a [`RecordingTransport`].
+pub struct RecordingConnection<C> {
+    inner: C,
+    recorder: Recorder,
+    session: u64,
+    /// Direction of frames written on this side.
+    sends: Direction,
+}
+
+impl<C> RecordingConnection<C> {
+    /// Session number of the connection in the capture.
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    fn tap(&self, direction: Direction) -> Tap {
+        Tap { recorder: self.recorder.clone(), session: self.session, direction }
+    }
+
+    fn wrap<W, R>(&self, (send, recv): (W, R)) -> (RecordingStream<W>, RecordingStream<R>) {
+        let receives = match self.sends {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        };
+        (
+            RecordingStream::new(send, self.tap(self.sends)),
+            RecordingStream::new(recv, self.tap(receives)),
+        )
+    }
+}
+
+#[async_trait]
+impl<C: TransportConnection> TransportConnection for RecordingConnection<C> {
+    type SendStream = RecordingStream<C::SendStream>;
+    type RecvStream = RecordingStream<C::RecvStream>;
+
+    async fn open_bi(&self) -> io::Result<(Self::SendStream, Self::RecvStream)> {
+        Ok(self.wrap(self.inner.open_bi().await?))
+    }
+
+    async fn accept_bi(&self) -> io::Result<Option<(Self::SendStream, Self::RecvStream)>> {
+        Ok(self.inner.accept_bi().await?.map(|streams| self.wrap(streams)))
+    }
+
+    fn close(&self, error_code: u64, reason: &str) {
+        self.inner.close(error_code, reason);
+    }
+}
+
+/// Stream half recording the frames that pass through it.
+pub struct RecordingStream<S> {
+    inner: S,
+    tap: Tap,
+    parser: FrameParser,
+}
+
+impl<S> RecordingStream<S> {
+    fn new(inner: S, tap: Tap) -> Self {
+        Self { inner, tap, parser: FrameParser::default() }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for RecordingStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if matches!(result, Poll::Ready(Ok(()))) {
+            this.tap.observe(&mut this.parser, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for RecordingStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            this.tap.observe(&mut this.parser, &buf[..written]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Serde for bytes as a lowercase hex string.
+mod hex_bytes {
+    use std::fmt::Write;
+
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut hex = String::with_capacity(bytes.len() * 2);
+        for byte in bytes {
+            let _ = write!(hex, "{byte:02x}");
+        }
+        serializer.serialize_str(&hex)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if !hex.len().is_multiple_of(2) {
+            return Err(D::Error::custom("hex string has an odd length"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::Opcode;
+
+    use super::*;
+
+    fn frame(opcode: Opcode, payload: &[u8]) -> Frame {
+        Frame::new(FrameHeader::new(opcode), payload.to_vec())
+    }
+
+    fn encoded(frame: &Frame) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        frame.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn parser_splits_frames_across_writes() {
+        let first = frame(Opcode::Ping, b"one");
+        let second = frame(Opcode::Pong, b"two!");
+        let mut stream = encoded(&first);
+        stream.extend(encoded(&second));
+
+        let mut parser = FrameParser::default();
+        let mut frames = Vec::new();
+        for chunk in stream.chunks(50) {
+            frames.extend(parser.push(chunk));
+        }
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].payload.as_ref(), b"one");
+        assert_eq!(frames[1].header.opcode_enum(), Some(Opcode::Pong));
+    }
+
+    #[test]
+    fn parser_stops_at_garbage() {
+        let mut parser = FrameParser::default();
+        assert!(parser.push(&[0xFF; FrameHeader::SIZE]).is_empty());
+        assert!(parser.push(&encoded(&frame(Opcode::Ping, b""))).is_empty());
+    }
+
+    #[test]
+    fn capture_round_trips_through_json() {
+        let capture = Capture::new(vec![
+            CapturedFrame {
+                session: 1,
+                at: Duration::from_millis(3),
+                direction: Direction::ToServer,
+                frame: encoded(&frame(Opcode::Hello, b"hi")),
+            },
+            CapturedFrame {
+                session: 2,
+                at: Duration::from_millis(9),
+                direction: Direction::ToClient,
+                frame: encoded(&frame(Opcode::Ping, b"")),
+            },
+        ]);
+
+        let json = serde_json::to_string(&capture).unwrap();
+        assert!(json.contains(r#""frame":""#), "{json}");
+        let parsed: Capture = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed, capture);
+        assert_eq!(parsed.sessions(), vec![1, 2]);
+        let hello = parsed.frames(1, Direction::ToServer).next().unwrap().decode().unwrap();
+        assert_eq!(hello.payload.as_ref(), b"hi");
+    }
+}
@@ -0,0 +1,581 @@
+//! Conformance kit for client implementations.
+//!
+//! A client written in another language is validated against the real
+//! server by wrapping it, through a thin FFI shim, in the
+//! [`ConformantClient`] trait: frames in, frames out, and a few queries on
+//! its state. [`run_conformance`] then plays a canned set of cases against a
+//! `ServerDriver`, each on a fresh server with fresh clients:
+//!
+//! - handshake: the server authenticates the client's Hello
+//! - `create_room`: a room the client creates exists on the server
+//! - membership: a client adds another, and both reach the same epoch
+//! - messaging: members read each other's messages
+//! - sync: a member that missed messages while offline catches up
+//!
+//! The server runs in-process and frames are delivered in order, so a
+//! failing case is deterministic and names what went wrong. An error frame
+//! from the server fails the case, since a conformant client never provokes
+//! one in these flows.
+//!
+//! [`ReferenceClient`] adapts our own client, and is both the model for a
+//! shim and the suite's own regression test.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use lockframe_client::{Client, ClientAction, ClientEvent, ClientIdentity};
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode, Payload,
+    payloads::session::{Hello, SyncRequest},
+};
+use lockframe_server::{DriverConfig, MemoryStorage, ServerAction, ServerDriver, ServerEvent};
+
+use crate::SimEnv;
+
+/// Room every case uses.
+pub const CONFORMANCE_ROOM: u128 = 0xC0F0_4A11_C0F0_4A11;
+
+/// Most frames the server processes in one exchange before a case is failed
+/// for never settling.
+const MAX_FRAMES: usize = 10_000;
+
+/// Frames a sync asks for.
+const SYNC_LIMIT: u64 = 100;
+
+/// A client under test.
+///
+/// Each operation returns the frames the client wants sent to the server,
+/// in order. Errors fail the case with their message.
+pub trait ConformantClient {
+    /// User ID the client authenticates as.
+    fn sender_id(&self) -> u64;
+
+    /// Hello frame opening a session.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot produce one.
+    fn hello(&mut self) -> Result<Frame, String>;
+
+    /// Create `room_id`, with the client as its only member.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot create the room.
+    fn create_room(&mut self, room_id: u128) -> Result<Vec<Frame>, String>;
+
+    /// Publish a key package, so other members can add the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot generate one.
+    fn publish_key_package(&mut self) -> Result<Vec<Frame>, String>;
+
+    /// Add `user_id` to `room_id` using their published key package.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot start the add.
+    fn add_member(&mut self, room_id: u128, user_id: u64) -> Result<Vec<Frame>, String>;
+
+    /// Encrypt and send `plaintext` to `room_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot send to the room.
+    fn send_message(&mut self, room_id: u128, plaintext: &[u8]) -> Result<Vec<Frame>, String>;
+
+    /// Ask the server for what the client missed in `room_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot request a sync.
+    fn sync(&mut self, room_id: u128) -> Result<Vec<Frame>, String>;
+
+    /// Process a frame from the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client rejects the frame.
+    fn receive(&mut self, frame: Frame) -> Result<Vec<Frame>, String>;
+
+    /// Client's epoch in `room_id`, or `None` if not a member.
+    fn epoch(&self, room_id: u128) -> Option<u64>;
+
+    /// Plaintexts of messages from other members of `room_id` the client
+    /// decrypted since last asked, in log order.
+    fn take_messages(&mut self, room_id: u128) -> Vec<Vec<u8>>;
+}
+
+/// Outcome of one conformance case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    /// Case name.
+    pub name: &'static str,
+    /// Why the case failed, or `None` if it passed.
+    pub failure: Option<String>,
+}
+
+impl CaseResult {
+    /// Whether the case passed.
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Outcome of a conformance run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// Every case, in the order run.
+    pub cases: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    /// Whether every case passed.
+    pub fn is_conformant(&self) -> bool {
+        self.cases.iter().all(CaseResult::passed)
+    }
+
+    /// Cases that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases.iter().filter(|case| !case.passed())
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            match &case.failure {
+                None => writeln!(f, "PASS {}", case.name)?,
+                Some(reason) => writeln!(f, "FAIL {}: {reason}", case.name)?,
+            }
+        }
+        let passed = self.cases.iter().filter(|case| case.passed()).count();
+        write!(f, "{passed}/{} cases passed", self.cases.len())
+    }
+}
+
+type Case<C> = fn(&mut Net<C>) -> Result<(), String>;
+
+/// Run every conformance case, creating clients with `new_client`, which is
+/// given the user ID to authenticate as.
+pub fn run_conformance<C, F>(mut new_client: F) -> ConformanceReport
+where
+    C: ConformantClient,
+    F: FnMut(u64) -> C,
+{
+    let cases: [(&'static str, Case<C>); 5] = [
+        ("handshake", handshake),
+        ("create_room", create_room),
+        ("membership", membership),
+        ("messaging", messaging),
+        ("sync", sync),
+    ];
+
+    let cases = cases
+        .into_iter()
+        .map(|(name, case)| {
+            let mut net = Net::new(vec![new_client(ALICE), new_client(BOB)]);
+            CaseResult { name, failure: case(&mut net).err() }
+        })
+        .collect();
+    ConformanceReport { cases }
+}
+
+const ALICE: u64 = 1;
+const BOB: u64 = 2;
+
+fn handshake<C: ConformantClient>(net: &mut Net<C>) -> Result<(), String> {
+    for client in [ALICE, BOB] {
+        net.connect(client)?;
+        if !net.received(client).contains(&Opcode::HelloReply) {
+            return Err(format!("client {client} got no HelloReply"));
+        }
+    }
+    Ok(())
+}
+
+/// The server only learns of a room from its first commit, so a new room is
+/// the creator's alone.
+fn create_room<C: ConformantClient>(net: &mut Net<C>) -> Result<(), String> {
+    net.connect(ALICE)?;
+    net.act(ALICE, |client| client.create_room(CONFORMANCE_ROOM))?;
+
+    match net.client(ALICE).epoch(CONFORMANCE_ROOM) {
+        Some(_) => Ok(()),
+        None => Err("creator is not a member of its room".into()),
+    }
+}
+
+/// Alice creates the room and adds Bob.
+fn membership<C: ConformantClient>(net: &mut Net<C>) -> Result<(), String> {
+    net.connect(ALICE)?;
+    net.connect(BOB)?;
+    net.act(ALICE, |client| client.create_room(CONFORMANCE_ROOM))?;
+    net.act(BOB, C::publish_key_package)?;
+    net.act(ALICE, |client| client.add_member(CONFORMANCE_ROOM, BOB))?;
+
+    let alice = net.client(ALICE).epoch(CONFORMANCE_ROOM);
+    let bob = net.client(BOB).epoch(CONFORMANCE_ROOM);
+    match (alice, bob) {
+        (Some(a), Some(b)) if a == b && a > 0 => {},
+        (_, None) => return Err("added member did not join the room".into()),
+        _ => return Err(format!("epochs diverged after add: adder {alice:?}, added {bob:?}")),
+    }
+
+    if !net.server.has_room(CONFORMANCE_ROOM) {
+        return Err("server has no room after the first commit".into());
+    }
+    let bob_session = net.session(BOB);
+    if !net.server.sessions_in_room(CONFORMANCE_ROOM).any(|session| session == bob_session) {
+        return Err("server did not subscribe the added member".into());
+    }
+    Ok(())
+}
+
+fn messaging<C: ConformantClient>(net: &mut Net<C>) -> Result<(), String> {
+    membership(net)?;
+
+    net.act(ALICE, |client| client.send_message(CONFORMANCE_ROOM, b"hello bob"))?;
+    expect_messages(net, BOB, &[b"hello bob"])?;
+
+    net.act(BOB, |client| client.send_message(CONFORMANCE_ROOM, b"hello alice"))?;
+    expect_messages(net, ALICE, &[b"hello alice"])
+}
+
+/// Bob misses messages while offline and syncs them on return.
+fn sync<C: ConformantClient>(net: &mut Net<C>) -> Result<(), String> {
+    membership(net)?;
+
+    net.set_online(BOB, false);
+    let missed: [&[u8]; 3] = [b"one", b"two", b"three"];
+    for message in missed {
+        net.act(ALICE, |client| client.send_message(CONFORMANCE_ROOM, message))?;
+    }
+    net.set_online(BOB, true);
+    expect_messages(net, BOB, &[])?;
+
+    net.act(BOB, |client| client.sync(CONFORMANCE_ROOM))?;
+    expect_messages(net, BOB, &missed)
+}
+
+fn expect_messages<C: ConformantClient>(
+    net: &mut Net<C>,
+    user_id: u64,
+    expected: &[&[u8]],
+) -> Result<(), String> {
+    let actual = net.client_mut(user_id).take_messages(CONFORMANCE_ROOM);
+    if actual == expected {
+        return Ok(());
+    }
+    Err(format!(
+        "client {user_id} decrypted {:?}, expected {:?}",
+        actual.iter().map(|m| String::from_utf8_lossy(m)).collect::<Vec<_>>(),
+        expected.iter().map(|m| String::from_utf8_lossy(m)).collect::<Vec<_>>()
+    ))
+}
+
+/// A client and its session.
+struct Peer<C> {
+    client: C,
+    session_id: u64,
+    online: bool,
+    /// Opcodes of every frame delivered to the client.
+    received: Vec<Opcode>,
+}
+
+/// Server and clients of one case. Clients are indexed by user ID, which is
+/// also their session ID.
+struct Net<C> {
+    server: ServerDriver<SimEnv, MemoryStorage>,
+    peers: HashMap<u64, Peer<C>>,
+}
+
+impl<C: ConformantClient> Net<C> {
+    fn new(clients: Vec<C>) -> Self {
+        let server =
+            ServerDriver::new(SimEnv::new(), MemoryStorage::new(), DriverConfig::default());
+        let peers = clients
+            .into_iter()
+            .map(|client| {
+                let id = client.sender_id();
+                (id, Peer { client, session_id: id, online: true, received: Vec::new() })
+            })
+            .collect();
+        Self { server, peers }
+    }
+
+    fn peer(&self, user_id: u64) -> &Peer<C> {
+        &self.peers[&user_id]
+    }
+
+    fn peer_mut(&mut self, user_id: u64) -> &mut Peer<C> {
+        self.peers.get_mut(&user_id).unwrap()
+    }
+
+    fn client(&self, user_id: u64) -> &C {
+        &self.peer(user_id).client
+    }
+
+    fn client_mut(&mut self, user_id: u64) -> &mut C {
+        &mut self.peer_mut(user_id).client
+    }
+
+    fn session(&self, user_id: u64) -> u64 {
+        self.peer(user_id).session_id
+    }
+
+    fn received(&self, user_id: u64) -> &[Opcode] {
+        &self.peer(user_id).received
+    }
+
+    /// Take a client offline or back online. Frames for an offline client
+    /// are lost.
+    fn set_online(&mut self, user_id: u64, online: bool) {
+        self.peer_mut(user_id).online = online;
+    }
+
+    /// Open the client's session and send its Hello.
+    fn connect(&mut self, user_id: u64) -> Result<(), String> {
+        let session_id = self.session(user_id);
+        self.server
+            .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+            .map_err(|e| format!("server refused connection: {e}"))?;
+        self.act(user_id, |client| client.hello().map(|hello| vec![hello]))
+    }
+
+    /// Have the client act, then exchange frames until nothing is left in
+    /// flight.
+    fn act(
+        &mut self,
+        user_id: u64,
+        action: impl FnOnce(&mut C) -> Result<Vec<Frame>, String>,
+    ) -> Result<(), String> {
+        let frames = action(self.client_mut(user_id))
+            .map_err(|e| format!("client {user_id} failed: {e}"))?;
+        let mut to_server: VecDeque<(u64, Frame)> =
+            frames.into_iter().map(|frame| (user_id, frame)).collect();
+
+        let mut processed = 0;
+        while let Some((from, frame)) = to_server.pop_front() {
+            processed += 1;
+            if processed > MAX_FRAMES {
+                return Err(format!("exchange did not settle within {MAX_FRAMES} frames"));
+            }
+
+            let opcode = frame.header.opcode_enum();
+            let session_id = self.session(from);
+            let actions = self
+                .server
+                .process_event(ServerEvent::FrameReceived { session_id, frame })
+                .map_err(|e| format!("server failed on {opcode:?} from client {from}: {e}"))?;
+
+            for (to, frame) in deliveries(actions) {
+                let Some(peer) = self.peers.get_mut(&to) else { continue };
+                if !peer.online {
+                    continue;
+                }
+                let received = frame.header.opcode_enum();
+                if received == Some(Opcode::Error) {
+                    return Err(format!(
+                        "server sent client {to} an error after {opcode:?} from client {from}"
+                    ));
+                }
+                if let Some(received) = received {
+                    peer.received.push(received);
+                }
+                let replies = peer
+                    .client
+                    .receive(frame)
+                    .map_err(|e| format!("client {to} rejected {received:?}: {e}"))?;
+                to_server.extend(replies.into_iter().map(|reply| (to, reply)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Frames the server sends, with the session each goes to.
+fn deliveries<I>(actions: Vec<ServerAction<I>>) -> Vec<(u64, Frame)> {
+    let mut out = Vec::new();
+    for action in actions {
+        match action {
+            ServerAction::SendToSession { session_id, frame } => out.push((session_id, frame)),
+            ServerAction::Broadcast { session_ids, frame } => {
+                out.extend(session_ids.into_iter().map(|session_id| (session_id, frame.clone())));
+            },
+            _ => {},
+        }
+    }
+    out
+}
+
+/// [`ConformantClient`] over our own client.
+pub struct ReferenceClient {
+    client: Client<SimEnv>,
+    /// Decrypted plaintexts not yet taken, per room.
+    messages: HashMap<u128, Vec<Vec<u8>>>,
+}
+
+impl ReferenceClient {
+    /// Client authenticating as `sender_id`.
+    pub fn new(sender_id: u64) -> Self {
+        Self {
+            client: Client::new(SimEnv::new(), ClientIdentity::new(sender_id)),
+            messages: HashMap::new(),
+        }
+    }
+
+    fn handle(&mut self, event: ClientEvent<tokio::time::Instant>) -> Result<Vec<Frame>, String> {
+        let actions = self.client.handle(event).map_err(|e| e.to_string())?;
+        let mut frames = Vec::new();
+        for action in actions {
+            match action {
+                ClientAction::Send(frame) => frames.push(frame),
+                ClientAction::DeliverMessage { room_id, plaintext, .. } => {
+                    self.messages.entry(room_id).or_default().push(plaintext);
+                },
+                ClientAction::RequestSync { room_id, .. } => frames.push(sync_request(room_id)?),
+                _ => {},
+            }
+        }
+        Ok(frames)
+    }
+}
+
+fn sync_request(room_id: u128) -> Result<Frame, String> {
+    let mut frame = Payload::SyncRequest(SyncRequest::new(0, SYNC_LIMIT))
+        .into_frame(FrameHeader::new(Opcode::SyncRequest))
+        .map_err(|e| e.to_string())?;
+    frame.header.set_room_id(room_id);
+    Ok(frame)
+}
+
+impl ConformantClient for ReferenceClient {
+    fn sender_id(&self) -> u64 {
+        self.client.sender_id()
+    }
+
+    fn hello(&mut self) -> Result<Frame, String> {
+        Payload::Hello(Hello {
+            version: 1,
+            capabilities: vec![],
+            sender_id: Some(self.client.sender_id()),
+            auth_token: None,
+        })
+        .into_frame(FrameHeader::new(Opcode::Hello))
+        .map_err(|e| e.to_string())
+    }
+
+    fn create_room(&mut self, room_id: u128) -> Result<Vec<Frame>, String> {
+        self.handle(ClientEvent::CreateRoom { room_id })
+    }
+
+    fn publish_key_package(&mut self) -> Result<Vec<Frame>, String> {
+        self.handle(ClientEvent::PublishKeyPackage)
+    }
+
+    fn add_member(&mut self, room_id: u128, user_id: u64) -> Result<Vec<Frame>, String> {
+        self.handle(ClientEvent::FetchAndAddMember { room_id, user_id })
+    }
+
+    fn send_message(&mut self, room_id: u128, plaintext: &[u8]) -> Result<Vec<Frame>, String> {
+        self.handle(ClientEvent::SendMessage { room_id, plaintext: plaintext.to_vec() })
+    }
+
+    fn sync(&mut self, room_id: u128) -> Result<Vec<Frame>, String> {
+        Ok(vec![sync_request(room_id)?])
+    }
+
+    fn receive(&mut self, frame: Frame) -> Result<Vec<Frame>, String> {
+        self.handle(ClientEvent::FrameReceived(frame))
+    }
+
+    fn epoch(&self, room_id: u128) -> Option<u64> {
+        self.client.epoch(room_id)
+    }
+
+    fn take_messages(&mut self, room_id: u128) -> Vec<Vec<u8>> {
+        self.messages.remove(&room_id).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Client that says Ping where it should say Hello and otherwise does
+    /// nothing.
+    struct Mute(u64);
+
+    impl ConformantClient for Mute {
+        fn sender_id(&self) -> u64 {
+            self.0
+        }
+
+        fn hello(&mut self) -> Result<Frame, String> {
+            Ok(Frame::new(FrameHeader::new(Opcode::Ping), Vec::new()))
+        }
+
+        fn create_room(&mut self, _room_id: u128) -> Result<Vec<Frame>, String> {
+            Ok(Vec::new())
+        }
+
+        fn publish_key_package(&mut self) -> Result<Vec<Frame>, String> {
+            Ok(Vec::new())
+        }
+
+        fn add_member(&mut self, _room_id: u128, _user_id: u64) -> Result<Vec<Frame>, String> {
+            Err("unsupported".into())
+        }
+
+        fn send_message(
+            &mut self,
+            _room_id: u128,
+            _plaintext: &[u8],
+        ) -> Result<Vec<Frame>, String> {
+            Ok(Vec::new())
+        }
+
+        fn sync(&mut self, _room_id: u128) -> Result<Vec<Frame>, String> {
+            Ok(Vec::new())
+        }
+
+        fn receive(&mut self, _frame: Frame) -> Result<Vec<Frame>, String> {
+            Ok(Vec::new())
+        }
+
+        fn epoch(&self, _room_id: u128) -> Option<u64> {
+            None
+        }
+
+        fn take_messages(&mut self, _room_id: u128) -> Vec<Vec<u8>> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn server_learns_of_a_room_from_its_first_commit() {
+        let mut net = Net::new(vec![ReferenceClient::new(ALICE), ReferenceClient::new(BOB)]);
+        create_room(&mut net).unwrap();
+        assert!(!net.server.has_room(CONFORMANCE_ROOM));
+
+        net.connect(BOB).unwrap();
+        net.act(BOB, ReferenceClient::publish_key_package).unwrap();
+        net.act(ALICE, |client| client.add_member(CONFORMANCE_ROOM, BOB)).unwrap();
+        assert!(net.server.has_room(CONFORMANCE_ROOM));
+    }
+
+    #[test]
+    fn nonconformant_client_fails_every_case() {
+        let report = run_conformance(Mute);
+
+        assert!(!report.is_conformant());
+        assert_eq!(report.failures().count(), 5, "{report}");
+        let handshake = report.cases[0].failure.as_deref().unwrap();
+        assert!(handshake.starts_with("server failed on Some(Ping) from client 1"), "{handshake}");
+        assert!(report.to_string().ends_with("0/5 cases passed"));
+    }
+}
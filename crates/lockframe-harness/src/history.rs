@@ -0,0 +1,512 @@
+//! Operation histories and room-log linearizability.
+//!
+//! A [`History`] records, in the order they happen, the messages clients
+//! send, the log indices the server assigns them and the messages clients
+//! deliver. [`History::check`] verifies that each room's history is
+//! linearizable. There must be one total order of messages per room, and
+//! every client observes a contiguous run of it, in order. Each message is
+//! delivered only after it was sent, and sequenced in its sender's send
+//! order.
+//!
+//! A client that joins late starts its run past the start of the log, and one
+//! that falls behind ends it early. Both observe a prefix of the order from
+//! where they joined. What is ruled out is skipping, repeating or reordering
+//! messages, or two clients disagreeing about what sits at a log index.
+//!
+//! Only application messages are recorded. Commits take log indices too, so
+//! the order may have holes no client is expected to fill.
+//!
+//! Proptests record into a `History` directly. Turmoil hosts share one
+//! through a [`SharedHistory`] and check it once the simulation ends.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use lockframe_client::ClientAction;
+use lockframe_core::mls::RoomId;
+use lockframe_proto::{Frame, Opcode};
+use lockframe_server::ServerAction;
+use tokio::sync::Mutex;
+
+use crate::invariants::{InvariantKind, InvariantResult, Violation};
+
+/// A [`History`] shared between simulated hosts.
+pub type SharedHistory = Arc<Mutex<History>>;
+
+/// One recorded step of a history. Clients are named by sender ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryEvent {
+    /// A client joined a room, starting a new run of observations.
+    Joined {
+        /// Joining client.
+        client: u64,
+        /// Room joined.
+        room_id: RoomId,
+    },
+    /// A client submitted a message.
+    Send {
+        /// Sending client.
+        client: u64,
+        /// Room sent to.
+        room_id: RoomId,
+        /// `request_id` of the sent frame.
+        request_id: u32,
+        /// Plaintext sent.
+        content: Vec<u8>,
+    },
+    /// The server confirmed one of a client's messages.
+    Sequenced {
+        /// Client whose message was sequenced.
+        client: u64,
+        /// Room of the message.
+        room_id: RoomId,
+        /// `request_id` of the sent frame.
+        request_id: u32,
+        /// Log index the server assigned.
+        log_index: u64,
+    },
+    /// The server relayed a message to the room.
+    Relayed {
+        /// Room of the message.
+        room_id: RoomId,
+        /// Log index the server assigned.
+        log_index: u64,
+        /// Sender named in the frame.
+        sender: u64,
+    },
+    /// A client delivered another member's message.
+    Delivered {
+        /// Receiving client.
+        client: u64,
+        /// Room of the message.
+        room_id: RoomId,
+        /// Log index of the message.
+        log_index: u64,
+        /// Sender of the message.
+        sender: u64,
+        /// Decrypted plaintext.
+        content: Vec<u8>,
+    },
+    /// A client left or was removed from a room, ending its run.
+    Removed {
+        /// Removed client.
+        client: u64,
+        /// Room removed from.
+        room_id: RoomId,
+    },
+}
+
+/// Concurrent operations and responses, in the order they happened.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    events: Vec<HistoryEvent>,
+}
+
+impl History {
+    /// Create an empty history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty history to share between hosts.
+    pub fn shared() -> SharedHistory {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Events recorded so far, oldest first.
+    pub fn events(&self) -> &[HistoryEvent] {
+        &self.events
+    }
+
+    /// Record an event.
+    pub fn push(&mut self, event: HistoryEvent) {
+        self.events.push(event);
+    }
+
+    /// Record `client` joining `room_id`. Only needed for rejoins: a client's
+    /// first delivery in a room starts its run.
+    pub fn joined(&mut self, client: u64, room_id: RoomId) {
+        self.push(HistoryEvent::Joined { client, room_id });
+    }
+
+    /// Record `client` sending `content`, taking the `request_id` from the
+    /// message frame in `actions`, the result of its `SendMessage`.
+    ///
+    /// Nothing is recorded if `actions` holds no message frame.
+    pub fn sent(
+        &mut self,
+        client: u64,
+        room_id: RoomId,
+        content: Vec<u8>,
+        actions: &[ClientAction],
+    ) {
+        let request_id = actions.iter().find_map(|action| match action {
+            ClientAction::Send(frame) if is_message(frame) => Some(frame.header.request_id()),
+            _ => None,
+        });
+        if let Some(request_id) = request_id {
+            self.push(HistoryEvent::Send { client, room_id, request_id, content });
+        }
+    }
+
+    /// Record the deliveries, confirmations and room removals in a client's
+    /// actions.
+    pub fn record_client(&mut self, client: u64, actions: &[ClientAction]) {
+        for action in actions {
+            match action {
+                ClientAction::DeliverMessage {
+                    room_id, sender_id, plaintext, log_index, ..
+                } => {
+                    self.push(HistoryEvent::Delivered {
+                        client,
+                        room_id: *room_id,
+                        log_index: *log_index,
+                        sender: *sender_id,
+                        content: plaintext.clone(),
+                    });
+                },
+                ClientAction::MessageSequenced { room_id, request_id, log_index } => {
+                    self.push(HistoryEvent::Sequenced {
+                        client,
+                        room_id: *room_id,
+                        request_id: *request_id,
+                        log_index: *log_index,
+                    });
+                },
+                ClientAction::RoomRemoved { room_id, .. } => {
+                    self.push(HistoryEvent::Removed { client, room_id: *room_id });
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Record the messages a server relays in its actions.
+    pub fn record_server<I>(&mut self, actions: &[ServerAction<I>]) {
+        for action in actions {
+            if let ServerAction::SendToSession { frame, .. } | ServerAction::Broadcast { frame, .. } =
+                action
+                && is_message(frame)
+            {
+                self.push(HistoryEvent::Relayed {
+                    room_id: frame.header.room_id(),
+                    log_index: frame.header.log_index(),
+                    sender: frame.header.sender_id(),
+                });
+            }
+        }
+    }
+
+    /// Check that every room's log is linearizable.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first violation found.
+    pub fn check(&self) -> InvariantResult {
+        let mut checker = Checker::new(&self.events);
+        for event in &self.events {
+            checker.apply(event)?;
+        }
+        checker.check_runs()?;
+        checker.check_send_order()
+    }
+}
+
+/// Sends of one client to one room, as request ID and plaintext.
+type Sends = Vec<(u32, Vec<u8>)>;
+
+fn is_message(frame: &Frame) -> bool {
+    frame.header.opcode_enum() == Some(Opcode::AppMessage)
+}
+
+fn violation(message: String) -> Violation {
+    Violation { invariant: InvariantKind::Linearizability, message }
+}
+
+/// A message at a position of a room's total order.
+struct Entry {
+    sender: u64,
+    /// Plaintext, once a client has delivered or sent it.
+    content: Option<Vec<u8>>,
+}
+
+/// Replays a history, building each room's total order.
+struct Checker {
+    /// Sender and room pairs with sends recorded. Deliveries of other
+    /// senders' messages cannot be matched to a send.
+    tracked: HashSet<(u64, RoomId)>,
+    /// Each room's total order, by log index.
+    order: HashMap<RoomId, BTreeMap<u64, Entry>>,
+    /// Sends so far, in order, per sender and room.
+    sends: HashMap<(u64, RoomId), Sends>,
+    /// Log indices each client observed per room, one run per membership.
+    runs: HashMap<(u64, RoomId), Vec<Vec<u64>>>,
+}
+
+impl Checker {
+    fn new(events: &[HistoryEvent]) -> Self {
+        let tracked = events
+            .iter()
+            .filter_map(|event| match event {
+                HistoryEvent::Send { client, room_id, .. } => Some((*client, *room_id)),
+                _ => None,
+            })
+            .collect();
+        Self { tracked, order: HashMap::new(), sends: HashMap::new(), runs: HashMap::new() }
+    }
+
+    fn apply(&mut self, event: &HistoryEvent) -> InvariantResult {
+        match event {
+            HistoryEvent::Joined { client, room_id } => {
+                self.runs.entry((*client, *room_id)).or_default().push(Vec::new());
+            },
+            HistoryEvent::Send { client, room_id, request_id, content } => {
+                self.sends
+                    .entry((*client, *room_id))
+                    .or_default()
+                    .push((*request_id, content.clone()));
+            },
+            HistoryEvent::Sequenced { client, room_id, request_id, log_index } => {
+                let content = self
+                    .sends
+                    .get(&(*client, *room_id))
+                    .and_then(|sends| sends.iter().find(|(id, _)| id == request_id))
+                    .map(|(_, content)| content.clone())
+                    .ok_or_else(|| {
+                        violation(format!(
+                            "client {client} had request {request_id} sequenced in room \
+                             {room_id:032x} without sending it"
+                        ))
+                    })?;
+                self.place(*room_id, *log_index, *client, Some(content))?;
+                self.observe(*client, *room_id, *log_index)?;
+            },
+            HistoryEvent::Relayed { room_id, log_index, sender } => {
+                self.place(*room_id, *log_index, *sender, None)?;
+            },
+            HistoryEvent::Delivered { client, room_id, log_index, sender, content } => {
+                let sent = self
+                    .sends
+                    .get(&(*sender, *room_id))
+                    .is_some_and(|sends| sends.iter().any(|(_, c)| c == content));
+                if self.tracked.contains(&(*sender, *room_id)) && !sent {
+                    return Err(violation(format!(
+                        "client {client} delivered log index {log_index} in room \
+                         {room_id:032x} before sender {sender} sent it"
+                    )));
+                }
+                self.place(*room_id, *log_index, *sender, Some(content.clone()))?;
+                self.observe(*client, *room_id, *log_index)?;
+            },
+            // Deliveries after a removal are for the oracles to judge; a
+            // rejoin is recorded as `Joined`
+            HistoryEvent::Removed { .. } => {},
+        }
+        Ok(())
+    }
+
+    /// Put a message at `log_index`, or check it agrees with the one there.
+    fn place(
+        &mut self,
+        room_id: RoomId,
+        log_index: u64,
+        sender: u64,
+        content: Option<Vec<u8>>,
+    ) -> InvariantResult {
+        let entry = self
+            .order
+            .entry(room_id)
+            .or_default()
+            .entry(log_index)
+            .or_insert(Entry { sender, content: None });
+
+        let conflict = entry.sender != sender
+            || matches!((&entry.content, &content), (Some(a), Some(b)) if a != b);
+        if conflict {
+            return Err(violation(format!(
+                "conflicting messages at log index {log_index} in room {room_id:032x}"
+            )));
+        }
+        if entry.content.is_none() {
+            entry.content = content;
+        }
+        Ok(())
+    }
+
+    /// Extend a client's current run with `log_index`.
+    fn observe(&mut self, client: u64, room_id: RoomId, log_index: u64) -> InvariantResult {
+        let runs = self.runs.entry((client, room_id)).or_default();
+        if runs.is_empty() {
+            runs.push(Vec::new());
+        }
+        let run = runs.last_mut().expect("a run was just ensured");
+
+        if let Some(&last) = run.last()
+            && log_index <= last
+        {
+            return Err(violation(format!(
+                "client {client} observed log index {log_index} after {last} in room \
+                 {room_id:032x}"
+            )));
+        }
+        run.push(log_index);
+        Ok(())
+    }
+
+    /// Every run holds every known message between its first and last.
+    fn check_runs(&self) -> InvariantResult {
+        for (&(client, room_id), runs) in &self.runs {
+            let Some(order) = self.order.get(&room_id) else { continue };
+            for run in runs {
+                let (Some(&first), Some(&last)) = (run.first(), run.last()) else { continue };
+                let skipped = order
+                    .range(first..=last)
+                    .find(|(index, entry)| {
+                        entry.content.is_some() && run.binary_search(index).is_err()
+                    })
+                    .map(|(index, _)| *index);
+                if let Some(index) = skipped {
+                    return Err(violation(format!(
+                        "client {client} skipped log index {index} in room {room_id:032x}"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Each sender's messages are ordered as it sent them.
+    fn check_send_order(&self) -> InvariantResult {
+        for (&(sender, room_id), sends) in &self.sends {
+            let Some(order) = self.order.get(&room_id) else { continue };
+            let mut next = 0;
+            for (index, entry) in order {
+                let Some(content) = entry.content.as_ref().filter(|_| entry.sender == sender)
+                else {
+                    continue;
+                };
+                let position = sends[next..].iter().position(|(_, sent)| sent == content);
+                let Some(position) = position else {
+                    return Err(violation(format!(
+                        "log index {index} in room {room_id:032x} is out of sender {sender}'s \
+                         send order"
+                    )));
+                };
+                next += position + 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOM: RoomId = 0x00AB_CDEF;
+    const ALICE: u64 = 1;
+    const BOB: u64 = 2;
+    const CAROL: u64 = 3;
+
+    fn send(client: u64, request_id: u32, content: &[u8]) -> HistoryEvent {
+        HistoryEvent::Send { client, room_id: ROOM, request_id, content: content.to_vec() }
+    }
+
+    fn sequenced(client: u64, request_id: u32, log_index: u64) -> HistoryEvent {
+        HistoryEvent::Sequenced { client, room_id: ROOM, request_id, log_index }
+    }
+
+    fn delivered(client: u64, log_index: u64, sender: u64, content: &[u8]) -> HistoryEvent {
+        HistoryEvent::Delivered {
+            client,
+            room_id: ROOM,
+            log_index,
+            sender,
+            content: content.to_vec(),
+        }
+    }
+
+    fn history(events: impl IntoIterator<Item = HistoryEvent>) -> History {
+        let mut history = History::new();
+        for event in events {
+            history.push(event);
+        }
+        history
+    }
+
+    /// Alice and Bob send concurrently. Carol falls behind, and a commit
+    /// takes log index 1.
+    fn concurrent() -> Vec<HistoryEvent> {
+        vec![
+            send(ALICE, 1, b"a1"),
+            send(BOB, 1, b"b1"),
+            send(ALICE, 2, b"a2"),
+            HistoryEvent::Relayed { room_id: ROOM, log_index: 0, sender: BOB },
+            sequenced(BOB, 1, 0),
+            delivered(ALICE, 0, BOB, b"b1"),
+            sequenced(ALICE, 1, 2),
+            delivered(BOB, 2, ALICE, b"a1"),
+            sequenced(ALICE, 2, 3),
+            delivered(BOB, 3, ALICE, b"a2"),
+            delivered(CAROL, 0, BOB, b"b1"),
+            delivered(CAROL, 2, ALICE, b"a1"),
+        ]
+    }
+
+    #[test]
+    fn concurrent_history_is_linearizable() {
+        history(concurrent()).check().unwrap();
+    }
+
+    #[test]
+    fn disagreement_about_a_log_index_is_a_conflict() {
+        let mut events = concurrent();
+        events.push(delivered(CAROL, 3, ALICE, b"a1"));
+
+        let violation = history(events).check().unwrap_err();
+        assert_eq!(violation.invariant, InvariantKind::Linearizability);
+        assert!(violation.message.contains("conflicting"), "{violation}");
+    }
+
+    #[test]
+    fn skipped_and_repeated_messages_are_violations() {
+        let mut skipped = concurrent();
+        skipped.retain(|event| *event != delivered(CAROL, 2, ALICE, b"a1"));
+        skipped.push(delivered(CAROL, 3, ALICE, b"a2"));
+        let violation = history(skipped).check().unwrap_err();
+        assert!(violation.message.contains("skipped log index 2"), "{violation}");
+
+        let mut repeated = concurrent();
+        repeated.push(delivered(CAROL, 2, ALICE, b"a1"));
+        let violation = history(repeated).check().unwrap_err();
+        assert!(violation.message.contains("after 2"), "{violation}");
+    }
+
+    #[test]
+    fn messages_must_follow_send_order_and_be_sent_first() {
+        let reordered = history([
+            send(ALICE, 1, b"a1"),
+            send(ALICE, 2, b"a2"),
+            sequenced(ALICE, 2, 0),
+            sequenced(ALICE, 1, 1),
+        ]);
+        let violation = reordered.check().unwrap_err();
+        assert!(violation.message.contains("send order"), "{violation}");
+
+        let early = history([delivered(BOB, 0, ALICE, b"a1"), send(ALICE, 1, b"a1")]);
+        let violation = early.check().unwrap_err();
+        assert!(violation.message.contains("before sender"), "{violation}");
+    }
+
+    #[test]
+    fn rejoining_starts_a_new_run() {
+        let mut events = concurrent();
+        events.retain(|event| *event != delivered(CAROL, 2, ALICE, b"a1"));
+        events.push(HistoryEvent::Joined { client: CAROL, room_id: ROOM });
+        events.push(delivered(CAROL, 3, ALICE, b"a2"));
+
+        history(events).check().unwrap();
+    }
+}
@@ -37,6 +37,9 @@ pub enum InvariantKind {
     NoLogGaps,
     /// All clients must observe the same total ordering of messages.
     TotalOrdering,
+    /// Every client must observe a prefix of one total order per room,
+    /// across a concurrent history.
+    Linearizability,
 }
 
 impl InvariantKind {
@@ -49,6 +52,7 @@ impl InvariantKind {
             Self::TreeHashConvergence => "tree_hash_convergence",
             Self::NoLogGaps => "no_log_gaps",
             Self::TotalOrdering => "total_ordering",
+            Self::Linearizability => "linearizability",
         }
     }
 }
@@ -15,20 +15,69 @@
 //! checks. Invariants verify WHAT must be true across all execution paths, not
 //! specific scenarios. Use [`InvariantRegistry::standard()`] for common
 //! App/Bridge invariants.
+//!
+//! # Linearizability
+//!
+//! The `history` module records concurrent sends, server responses and
+//! deliveries as a [`History`], and checks that each room's log is
+//! linearizable: every client observes a prefix of one total order.
+//!
+//! # Benchmarks
+//!
+//! The `bench` module measures server throughput and message latency in
+//! virtual time under a fixed seed and topology, reporting JSON a test can
+//! hold to a baseline.
+//!
+//! # Allocation Budgets
+//!
+//! With the `alloc-tracking` feature, the `alloc_tracking` module counts
+//! what code allocates, so tests can hold hot paths to a byte budget.
+//!
+//! # Conformance
+//!
+//! The `conformance` module runs canned handshake, membership, messaging and
+//! sync cases against the server for any [`ConformantClient`], so client
+//! implementations in other languages can be validated through an FFI shim.
+//!
+//! # Capture and Replay
+//!
+//! The `capture` module records the frames of real sessions through a
+//! [`RecordingTransport`], and [`ReplayTransport`] plays them back against
+//! new code, so incident captures become regression tests.
 
 #![allow(clippy::print_stdout, clippy::print_stderr, clippy::dbg_macro)]
 #![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
 
+pub mod alloc_tracking;
+pub mod bench;
+pub mod byzantine;
+pub mod capture;
 pub mod cluster;
+pub mod conformance;
+pub mod history;
 pub mod invariants;
 pub mod model;
+pub mod replay_transport;
 pub mod scenario;
 pub mod sim_driver;
 pub mod sim_env;
 pub mod sim_server;
+pub mod sim_storage;
 pub mod sim_transport;
 
+pub use alloc_tracking::{AllocStats, TrackingAllocator};
+pub use bench::{BENCH_DIR_VAR, BenchReport, Benchmark, StorageCost, Topology};
+pub use byzantine::{ByzantineClient, Malformation, OutOfPolicy};
+pub use capture::{
+    CAPTURE_VERSION, Capture, CapturedFrame, Direction, FrameParser, Recorder, RecordingConnection,
+    RecordingStream, RecordingTransport,
+};
 pub use cluster::TestCluster;
+pub use conformance::{
+    CONFORMANCE_ROOM, CaseResult, ConformanceReport, ConformantClient, ReferenceClient,
+    run_conformance,
+};
+pub use history::{History, HistoryEvent, SharedHistory};
 pub use invariants::{
     ActiveRoomInRooms, ClientSnapshot, EpochMonotonicity, Invariant, InvariantKind,
     InvariantRegistry, InvariantResult, MembershipConsistency, RoomSnapshot, SystemSnapshot,
@@ -38,7 +87,13 @@ pub use model::{
     ClientId, ErrorProperties, ModelClient, ModelMessage, ModelRoomId, ModelServer, ModelWorld,
     ObservableState, Operation, OperationError, OperationResult, PendingMessage, SmallMessage,
 };
+pub use replay_transport::{ReplayConnection, ReplaySink, ReplayStream, ReplayTransport};
 pub use sim_driver::{SimDriver, SimDriverError};
 pub use sim_env::SimEnv;
 pub use sim_server::{SharedSimServer, SimServer, create_shared_server};
+pub use sim_storage::{SimStorage, SimStorageStats};
 pub use sim_transport::SimTransport;
+
+#[cfg(feature = "alloc-tracking")]
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
@@ -37,6 +37,15 @@ impl ModelRoomState {
     }
 }
 
+/// Delivery held for a crashed client until it restarts.
+#[derive(Debug, Clone)]
+enum HeldDelivery {
+    /// Application message.
+    Message(ModelRoomId, ModelMessage),
+    /// Commit advancing the room's epoch.
+    Commit(ModelRoomId),
+}
+
 /// Model client state.
 ///
 /// Tracks room memberships and message state without real cryptography.
@@ -50,12 +59,23 @@ pub struct ModelClient {
     partitioned: bool,
     /// Whether client has disconnected.
     disconnected: bool,
+    /// Whether client has crashed and not yet restarted.
+    crashed: bool,
+    /// Deliveries held while crashed, in arrival order.
+    held: Vec<HeldDelivery>,
 }
 
 impl ModelClient {
     /// Create a new model client.
     pub fn new(id: ClientId) -> Self {
-        Self { id, rooms: HashMap::new(), partitioned: false, disconnected: false }
+        Self {
+            id,
+            rooms: HashMap::new(),
+            partitioned: false,
+            disconnected: false,
+            crashed: false,
+            held: Vec::new(),
+        }
     }
 
     /// Client identifier.
@@ -117,8 +137,12 @@ impl ModelClient {
     }
 
     /// Receive a message (called by `ModelWorld` after server processes).
+    ///
+    /// Held until restart if crashed.
     pub fn receive_message(&mut self, room_id: ModelRoomId, message: ModelMessage) {
-        if let Some(room) = self.rooms.get_mut(&room_id) {
+        if self.crashed {
+            self.held.push(HeldDelivery::Message(room_id, message));
+        } else if let Some(room) = self.rooms.get_mut(&room_id) {
             room.messages.push(message);
         }
     }
@@ -146,8 +170,12 @@ impl ModelClient {
     }
 
     /// Advance epoch for a room (after commit).
+    ///
+    /// Held until restart if crashed.
     pub fn advance_epoch(&mut self, room_id: ModelRoomId) {
-        if let Some(room) = self.rooms.get_mut(&room_id) {
+        if self.crashed {
+            self.held.push(HeldDelivery::Commit(room_id));
+        } else if let Some(room) = self.rooms.get_mut(&room_id) {
             room.epoch += 1;
         }
     }
@@ -162,6 +190,27 @@ impl ModelClient {
         self.disconnected
     }
 
+    /// Whether client has crashed and not yet restarted.
+    pub fn is_crashed(&self) -> bool {
+        self.crashed
+    }
+
+    /// Crash the client. Memberships survive, as they are persisted.
+    pub fn crash(&mut self) {
+        self.crashed = true;
+    }
+
+    /// Restart after a crash, processing held deliveries in order.
+    pub fn restart(&mut self) {
+        self.crashed = false;
+        for delivery in std::mem::take(&mut self.held) {
+            match delivery {
+                HeldDelivery::Message(room_id, message) => self.receive_message(room_id, message),
+                HeldDelivery::Commit(room_id) => self.advance_epoch(room_id),
+            }
+        }
+    }
+
     /// Partition client from server.
     pub fn partition(&mut self) {
         self.partitioned = true;
@@ -4,6 +4,7 @@
 //! randomly by proptest and applied to both the model and real implementation.
 
 use arbitrary::Arbitrary;
+use lockframe_client::ClientError;
 
 /// Client identifier (0-indexed).
 pub type ClientId = u8;
@@ -109,6 +110,32 @@ pub enum Operation {
         /// Client disconnecting.
         client_id: ClientId,
     },
+
+    /// Crash a client, dropping its in-memory state.
+    ///
+    /// The client keeps its memberships, as persisted, but cannot act until
+    /// restarted. Frames sent to it meanwhile are held for the restart.
+    CrashClient {
+        /// Client to crash.
+        client_id: ClientId,
+    },
+
+    /// Restart a crashed client from its storage.
+    ///
+    /// Held frames are delivered in order. No-op if the client is running.
+    RestartClient {
+        /// Client to restart.
+        client_id: ClientId,
+    },
+
+    /// Crash the server, dropping its in-memory state.
+    ///
+    /// Operations that go through the server fail until it restarts.
+    /// Sequenced messages are persisted and delivered after the restart.
+    CrashServer,
+
+    /// Restart the server from its storage. Running clients reconnect.
+    RestartServer,
 }
 
 /// Small message content for testing.
@@ -174,6 +201,9 @@ pub enum OperationError {
     /// No `GroupInfo` available for external join.
     NoGroupInfo,
 
+    /// Invitee has no published `KeyPackage` to fetch.
+    NoKeyPackage,
+
     /// Epoch mismatch (message from wrong epoch).
     EpochMismatch {
         /// Expected epoch.
@@ -187,6 +217,16 @@ pub enum OperationError {
 
     /// Client is already disconnected.
     Disconnected,
+
+    /// Client has crashed and not yet restarted.
+    Crashed,
+
+    /// Server has crashed and not yet restarted.
+    ServerUnavailable,
+
+    /// The real client rejected the operation, classified by the client's
+    /// own error properties.
+    Client(ErrorProperties),
 }
 
 /// Error classification properties for comparison.
@@ -213,16 +253,32 @@ impl OperationError {
             Self::RoomNotFound
             | Self::RoomAlreadyExists
             | Self::AlreadyMember
-            | Self::NoGroupInfo => ErrorProperties { is_fatal: false, is_retryable: false },
+            | Self::NoGroupInfo
+            | Self::NoKeyPackage => ErrorProperties { is_fatal: false, is_retryable: false },
 
-            // Retryable errors: sync can fix, or wait for partition heal
-            Self::EpochMismatch { .. } | Self::Partitioned => {
-                ErrorProperties { is_fatal: false, is_retryable: true }
-            },
+            // Retryable errors: sync can fix, or wait for partition heal or restart
+            Self::EpochMismatch { .. }
+            | Self::Partitioned
+            | Self::Crashed
+            | Self::ServerUnavailable => ErrorProperties { is_fatal: false, is_retryable: true },
+
+            Self::Client(properties) => properties.clone(),
         }
     }
 }
 
+impl From<&ClientError> for ErrorProperties {
+    fn from(error: &ClientError) -> Self {
+        Self { is_fatal: error.is_fatal(), is_retryable: error.is_retryable() }
+    }
+}
+
+impl From<&ClientError> for OperationError {
+    fn from(error: &ClientError) -> Self {
+        Self::Client(error.into())
+    }
+}
+
 impl OperationResult {
     /// Check if operation succeeded.
     pub fn is_ok(&self) -> bool {
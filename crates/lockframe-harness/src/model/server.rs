@@ -1,7 +1,8 @@
 //! Model server state machine.
 //!
-//! Simplified server that tracks rooms and assigns log indices.
-//! The server is the source of truth for message ordering.
+//! Simplified server that tracks rooms, assigns log indices and holds
+//! published `KeyPackages`. The server is the source of truth for message
+//! ordering.
 
 use std::collections::{HashMap, HashSet};
 
@@ -24,6 +25,9 @@ struct ServerRoomState {
     next_log_index: u64,
     /// Current epoch.
     epoch: u64,
+    /// Whether a member has published the room's `GroupInfo`. Lost once the
+    /// last member leaves, as nobody is left to publish a new one.
+    group_info: bool,
 }
 
 impl ServerRoomState {
@@ -31,7 +35,14 @@ impl ServerRoomState {
         let mut members = HashSet::new();
         members.insert(creator);
 
-        Self { creator, members, messages: Vec::new(), next_log_index: 0, epoch: 0 }
+        Self {
+            creator,
+            members,
+            messages: Vec::new(),
+            next_log_index: 0,
+            epoch: 0,
+            group_info: true,
+        }
     }
 }
 
@@ -58,12 +69,14 @@ pub struct ModelServer {
     rooms: HashMap<ModelRoomId, ServerRoomState>,
     /// Messages waiting for delivery.
     pending_deliveries: Vec<PendingMessage>,
+    /// Published `KeyPackages` not yet fetched, per client.
+    key_packages: HashMap<ClientId, usize>,
 }
 
 impl ModelServer {
     /// Create a new model server.
     pub fn new() -> Self {
-        Self { rooms: HashMap::new(), pending_deliveries: Vec::new() }
+        Self { rooms: HashMap::new(), pending_deliveries: Vec::new(), key_packages: HashMap::new() }
     }
 
     /// Number of messages waiting for delivery.
@@ -101,6 +114,11 @@ impl ModelServer {
         self.rooms.get(&room_id).map(|r| r.epoch)
     }
 
+    /// Whether an external joiner can fetch the room's `GroupInfo`.
+    pub fn has_group_info(&self, room_id: ModelRoomId) -> bool {
+        self.rooms.get(&room_id).is_some_and(|r| r.group_info)
+    }
+
     /// Create a new room.
     pub fn create_room(
         &mut self,
@@ -156,7 +174,8 @@ impl ModelServer {
 
     /// Remove a member from a room.
     ///
-    /// Rooms persist even when all members leave.
+    /// Rooms persist even when all members leave, but lose their
+    /// `GroupInfo`.
     pub fn remove_member(
         &mut self,
         room_id: ModelRoomId,
@@ -167,6 +186,9 @@ impl ModelServer {
         if !room.members.remove(&client_id) {
             return Err(OperationError::NotMember);
         }
+        if room.members.is_empty() {
+            room.group_info = false;
+        }
 
         Ok(())
     }
@@ -178,6 +200,28 @@ impl ModelServer {
         }
     }
 
+    /// Store a `KeyPackage` published by a client.
+    pub fn publish_key_package(&mut self, client_id: ClientId) {
+        *self.key_packages.entry(client_id).or_insert(0) += 1;
+    }
+
+    /// Hand out one of a client's `KeyPackages`. Each is fetched at most
+    /// once.
+    pub fn fetch_key_package(&mut self, client_id: ClientId) -> Result<(), OperationError> {
+        match self.key_packages.get_mut(&client_id) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                Ok(())
+            },
+            _ => Err(OperationError::NoKeyPackage),
+        }
+    }
+
+    /// Number of a client's published `KeyPackages` not yet fetched.
+    pub fn key_package_count(&self, client_id: ClientId) -> usize {
+        self.key_packages.get(&client_id).copied().unwrap_or(0)
+    }
+
     /// Advance epoch for a room.
     pub fn advance_epoch(&mut self, room_id: ModelRoomId) {
         if let Some(room) = self.rooms.get_mut(&room_id) {
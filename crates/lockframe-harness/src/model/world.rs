@@ -4,6 +4,8 @@
 //! and applies operations. It's the oracle against which the real
 //! implementation is verified.
 
+use std::collections::HashSet;
+
 use super::{
     client::{ModelClient, ModelMessage},
     operation::{ClientId, ModelRoomId, Operation, OperationError, OperationResult},
@@ -36,6 +38,11 @@ pub struct ModelWorld {
     clients: Vec<ModelClient>,
     /// Model server.
     server: ModelServer,
+    /// Whether the server has crashed and not yet restarted.
+    server_down: bool,
+    /// Rooms with a departure no running member could commit yet. Every
+    /// remaining member is crashed; the first to restart commits it.
+    deferred_departures: HashSet<ModelRoomId>,
 }
 
 impl ModelWorld {
@@ -43,7 +50,12 @@ impl ModelWorld {
     pub fn new(num_clients: usize) -> Self {
         let clients = (0..num_clients).map(|i| ModelClient::new(i as ClientId)).collect();
 
-        Self { clients, server: ModelServer::new() }
+        Self {
+            clients,
+            server: ModelServer::new(),
+            server_down: false,
+            deferred_departures: HashSet::new(),
+        }
     }
 
     /// Number of clients in the world.
@@ -86,7 +98,8 @@ impl ModelWorld {
                 self.apply_remove_member(*remover_id, *target_id, *room_id)
             },
             Operation::AdvanceTime { .. } => {
-                // Model doesn't track time
+                // Model doesn't track time. Heartbeats keep connections
+                // open, so timeouts must not change observable state
                 OperationResult::Ok
             },
             Operation::DeliverPending => {
@@ -96,6 +109,39 @@ impl ModelWorld {
             Operation::Partition { client_id } => self.apply_partition(*client_id),
             Operation::HealPartition { client_id } => self.apply_heal_partition(*client_id),
             Operation::Disconnect { client_id } => self.apply_disconnect(*client_id),
+            Operation::CrashClient { client_id } => self.apply_crash_client(*client_id),
+            Operation::RestartClient { client_id } => self.apply_restart_client(*client_id),
+            Operation::CrashServer => {
+                self.server_down = true;
+                OperationResult::Ok
+            },
+            Operation::RestartServer => {
+                self.server_down = false;
+                OperationResult::Ok
+            },
+        }
+    }
+
+    /// Whether the server has crashed and not yet restarted.
+    pub fn is_server_down(&self) -> bool {
+        self.server_down
+    }
+
+    /// Error for an operation `actors` must take part in, if any of them has
+    /// crashed or the server is down.
+    fn unavailable(&self, actors: &[ClientId]) -> Option<OperationError> {
+        if actors.iter().any(|&id| self.clients[id as usize].is_crashed()) {
+            return Some(OperationError::Crashed);
+        }
+        self.server_down.then_some(OperationError::ServerUnavailable)
+    }
+
+    /// Defer a departure from `room_id` if it has members left but none of
+    /// them is running to commit it.
+    fn defer_departure_if_unattended(&mut self, room_id: ModelRoomId) {
+        let members: Vec<ClientId> = self.server.members(room_id).into_iter().flatten().collect();
+        if !members.is_empty() && members.iter().all(|&id| self.clients[id as usize].is_crashed()) {
+            self.deferred_departures.insert(room_id);
         }
     }
 
@@ -148,10 +194,14 @@ impl ModelWorld {
     /// Note: Each client can independently create a room with the same ID.
     /// There is no centralized room registry - room creation is local.
     fn apply_create_room(&mut self, client_id: ClientId, room_id: ModelRoomId) -> OperationResult {
-        let Some(client) = self.clients.get_mut(client_id as usize) else {
+        if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
-        };
+        }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
+        let client = &mut self.clients[client_id as usize];
         if client.is_disconnected() {
             return OperationResult::Error(OperationError::Disconnected);
         }
@@ -185,6 +235,9 @@ impl ModelWorld {
         if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
         }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
         let client = &self.clients[client_id as usize];
         if client.is_partitioned() {
@@ -205,7 +258,14 @@ impl ModelWorld {
     }
 
     /// Deliver all pending messages to their recipients.
+    ///
+    /// Waits for the server if it is down. Crashed recipients hold their
+    /// messages until they restart.
     fn apply_deliver_pending(&mut self) {
+        if self.server_down {
+            return;
+        }
+
         let pending = self.server.take_pending();
 
         for pending_msg in pending {
@@ -230,17 +290,21 @@ impl ModelWorld {
     ///
     /// Self-initiated departure. Advances epoch.
     fn apply_leave_room(&mut self, client_id: ClientId, room_id: ModelRoomId) -> OperationResult {
-        let Some(client) = self.clients.get_mut(client_id as usize) else {
+        if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
-        };
+        }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
-        let result = client.leave_room(room_id);
+        let result = self.clients[client_id as usize].leave_room(room_id);
         if result.is_err() {
             return result;
         }
 
         let _ = self.server.remove_member(room_id, client_id);
         self.server.advance_epoch(room_id);
+        self.defer_departure_if_unattended(room_id);
 
         for other_client in &mut self.clients {
             if other_client.is_member(room_id) {
@@ -253,7 +317,8 @@ impl ModelWorld {
 
     /// Apply add member operation.
     ///
-    /// Inviter adds invitee to the room. Advances epoch.
+    /// Invitee publishes a `KeyPackage`, inviter fetches it and commits the
+    /// add, then invitee joins from the Welcome. Advances epoch.
     fn apply_add_member(
         &mut self,
         sender_id: ClientId,
@@ -266,6 +331,9 @@ impl ModelWorld {
         if recipient_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
         }
+        if let Some(e) = self.unavailable(&[sender_id, recipient_id]) {
+            return OperationResult::Error(e);
+        }
 
         if !self.clients[sender_id as usize].is_member(room_id) {
             return OperationResult::Error(OperationError::NotMember);
@@ -275,6 +343,14 @@ impl ModelWorld {
             return OperationResult::Error(OperationError::AlreadyMember);
         }
 
+        // A disconnected invitee has no session to publish a KeyPackage over
+        if !self.clients[recipient_id as usize].is_disconnected() {
+            self.server.publish_key_package(recipient_id);
+        }
+        if let Err(e) = self.server.fetch_key_package(recipient_id) {
+            return OperationResult::Error(e);
+        }
+
         self.server.advance_epoch(room_id);
         let new_epoch = self.server.epoch(room_id).unwrap_or(0);
 
@@ -302,11 +378,20 @@ impl ModelWorld {
             return OperationResult::Error(OperationError::InvalidClient);
         }
 
+        if let Some(e) = self.unavailable(&[joiner_id]) {
+            return OperationResult::Error(e);
+        }
+
         if self.clients[joiner_id as usize].is_member(room_id) {
             return OperationResult::Error(OperationError::AlreadyMember);
         }
 
-        if self.server.epoch(room_id).is_none() {
+        // The GroupInfo is stale until a member commits the deferred departure
+        if self.deferred_departures.contains(&room_id) {
+            return OperationResult::Error(OperationError::Crashed);
+        }
+
+        if !self.server.has_group_info(room_id) {
             return OperationResult::Error(OperationError::NoGroupInfo);
         }
 
@@ -344,6 +429,9 @@ impl ModelWorld {
         if remover_id == target_id {
             return OperationResult::Error(OperationError::CannotRemoveSelf);
         }
+        if let Some(e) = self.unavailable(&[remover_id, target_id]) {
+            return OperationResult::Error(e);
+        }
 
         if !self.clients[remover_id as usize].is_member(room_id) {
             return OperationResult::Error(OperationError::NotMember);
@@ -387,6 +475,9 @@ impl ModelWorld {
             return OperationResult::Error(OperationError::InvalidClient);
         };
 
+        if client.is_crashed() {
+            return OperationResult::Error(OperationError::Crashed);
+        }
         if client.is_disconnected() {
             return OperationResult::Error(OperationError::Disconnected);
         }
@@ -401,6 +492,9 @@ impl ModelWorld {
             return OperationResult::Error(OperationError::InvalidClient);
         };
 
+        if client.is_crashed() {
+            return OperationResult::Error(OperationError::Crashed);
+        }
         if client.is_disconnected() {
             return OperationResult::Error(OperationError::Disconnected);
         }
@@ -411,10 +505,14 @@ impl ModelWorld {
 
     /// Disconnect a client, removing from all rooms.
     fn apply_disconnect(&mut self, client_id: ClientId) -> OperationResult {
-        let Some(client) = self.clients.get_mut(client_id as usize) else {
+        if client_id as usize >= self.clients.len() {
             return OperationResult::Error(OperationError::InvalidClient);
-        };
+        }
+        if let Some(e) = self.unavailable(&[client_id]) {
+            return OperationResult::Error(e);
+        }
 
+        let client = &mut self.clients[client_id as usize];
         if client.is_disconnected() {
             return OperationResult::Error(OperationError::Disconnected);
         }
@@ -435,9 +533,41 @@ impl ModelWorld {
                         other_client.advance_epoch(room_id);
                     }
                 }
+                self.defer_departure_if_unattended(room_id);
             }
         }
 
         OperationResult::Ok
     }
+
+    /// Crash a client. No-op if it has already crashed.
+    fn apply_crash_client(&mut self, client_id: ClientId) -> OperationResult {
+        let Some(client) = self.clients.get_mut(client_id as usize) else {
+            return OperationResult::Error(OperationError::InvalidClient);
+        };
+
+        client.crash();
+        OperationResult::Ok
+    }
+
+    /// Restart a crashed client, which then commits any departures deferred
+    /// in its rooms. No-op if it is running.
+    fn apply_restart_client(&mut self, client_id: ClientId) -> OperationResult {
+        let Some(client) = self.clients.get_mut(client_id as usize) else {
+            return OperationResult::Error(OperationError::InvalidClient);
+        };
+
+        if !client.is_crashed() {
+            return OperationResult::Ok;
+        }
+        if self.server_down {
+            return OperationResult::Error(OperationError::ServerUnavailable);
+        }
+
+        client.restart();
+        let client = &self.clients[client_id as usize];
+        self.deferred_departures.retain(|&room_id| !client.is_member(room_id));
+
+        OperationResult::Ok
+    }
 }
@@ -0,0 +1,223 @@
+//! Transport replaying captured sessions.
+//!
+//! A [`ReplayTransport`] plays a [`Capture`] back to the code under test.
+//! Accepting a connection replays the next captured session's client frames,
+//! for testing a server. Connecting replays its server frames, for testing a
+//! client. Frames arrive at their captured times, measured from the creation
+//! of the transport, so under turmoil a replay is deterministic and takes no
+//! real time.
+//!
+//! What the code under test writes back is kept per session, to compare
+//! with what the captured peer received. An incident capture thereby becomes
+//! a regression test against new code.
+
+#![allow(clippy::disallowed_types, reason = "Synchronous in-memory operations only")]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use lockframe_core::transport::{Transport, TransportConnection};
+use lockframe_proto::Frame;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+use crate::capture::{Capture, Direction, FrameParser};
+
+/// Bytes written back on each replayed session.
+type Written = Arc<Mutex<HashMap<u64, Vec<u8>>>>;
+
+/// Transport replaying the sessions of a [`Capture`].
+pub struct ReplayTransport {
+    capture: Capture,
+    start: Instant,
+    /// Sessions not yet accepted, in order.
+    to_accept: Mutex<VecDeque<u64>>,
+    /// Sessions not yet connected, in order.
+    to_connect: Mutex<VecDeque<u64>>,
+    written: Written,
+}
+
+impl ReplayTransport {
+    /// Replay `capture`, with its clock starting now.
+    pub fn new(capture: Capture) -> Self {
+        let sessions: VecDeque<u64> = capture.sessions().into();
+        Self {
+            capture,
+            start: Instant::now(),
+            to_accept: Mutex::new(sessions.clone()),
+            to_connect: Mutex::new(sessions),
+            written: Arc::default(),
+        }
+    }
+
+    /// Frames the code under test wrote on `session`, oldest first.
+    pub fn written(&self, session: u64) -> Vec<Frame> {
+        let written = lock(&self.written);
+        written.get(&session).map(|bytes| FrameParser::default().push(bytes)).unwrap_or_default()
+    }
+
+    /// Connection replaying the frames `session` saw going `direction`.
+    fn replay(&self, session: u64, direction: Direction) -> ReplayConnection {
+        let frames = self
+            .capture
+            .frames(session, direction)
+            .map(|frame| (frame.at, frame.frame.clone()))
+            .collect();
+        ReplayConnection {
+            session,
+            stream: Mutex::new(Some(ReplayStream::new(frames, self.start))),
+            sink: ReplaySink { session, written: Arc::clone(&self.written) },
+        }
+    }
+}
+
+fn next_session(queue: &Mutex<VecDeque<u64>>) -> io::Result<u64> {
+    lock(queue)
+        .pop_front()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no captured sessions left"))
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    type Connection = ReplayConnection;
+
+    async fn accept(&self) -> io::Result<Self::Connection> {
+        Ok(self.replay(next_session(&self.to_accept)?, Direction::ToServer))
+    }
+
+    async fn connect(&self, _remote: SocketAddr) -> io::Result<Self::Connection> {
+        Ok(self.replay(next_session(&self.to_connect)?, Direction::ToClient))
+    }
+}
+
+/// A replayed session, with one bidirectional stream.
+pub struct ReplayConnection {
+    session: u64,
+    /// Taken by the first `open_bi` or `accept_bi`.
+    stream: Mutex<Option<ReplayStream>>,
+    sink: ReplaySink,
+}
+
+impl ReplayConnection {
+    /// Captured session the connection replays.
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    fn take_streams(&self) -> Option<(ReplaySink, ReplayStream)> {
+        lock(&self.stream).take().map(|stream| (self.sink.clone(), stream))
+    }
+}
+
+#[async_trait]
+impl TransportConnection for ReplayConnection {
+    type SendStream = ReplaySink;
+    type RecvStream = ReplayStream;
+
+    async fn open_bi(&self) -> io::Result<(Self::SendStream, Self::RecvStream)> {
+        self.take_streams().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Unsupported, "a replayed session has one stream")
+        })
+    }
+
+    async fn accept_bi(&self) -> io::Result<Option<(Self::SendStream, Self::RecvStream)>> {
+        // The captured session had one stream; once it is taken the peer has
+        // nothing more to open
+        Ok(self.take_streams())
+    }
+
+    fn close(&self, _error_code: u64, _reason: &str) {}
+}
+
+/// Receive half of a replayed session, yielding captured frames at their
+/// captured times and ending after the last.
+pub struct ReplayStream {
+    /// Captured time and encoded bytes of the frames still to come.
+    frames: VecDeque<(Duration, Vec<u8>)>,
+    start: Instant,
+    /// Bytes of the current frame not yet read.
+    pending: Vec<u8>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl ReplayStream {
+    fn new(frames: VecDeque<(Duration, Vec<u8>)>, start: Instant) -> Self {
+        Self { frames, start, pending: Vec::new(), sleep: None }
+    }
+}
+
+impl AsyncRead for ReplayStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.pending.is_empty() {
+                let n = this.pending.len().min(buf.remaining());
+                buf.put_slice(&this.pending[..n]);
+                this.pending.drain(..n);
+                return Poll::Ready(Ok(()));
+            }
+            let Some(&(at, _)) = this.frames.front() else {
+                // Every frame was read: the captured peer is done
+                return Poll::Ready(Ok(()));
+            };
+
+            let deadline = this.start + at;
+            let sleep =
+                this.sleep.get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            ready!(sleep.as_mut().poll(cx));
+            this.sleep = None;
+            if let Some((_, bytes)) = this.frames.pop_front() {
+                this.pending = bytes;
+            }
+        }
+    }
+}
+
+/// Send half of a replayed session, keeping what is written.
+#[derive(Clone)]
+pub struct ReplaySink {
+    session: u64,
+    written: Written,
+}
+
+impl AsyncWrite for ReplaySink {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        lock(&self.written).entry(self.session).or_default().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| {
+        // SAFETY: Turmoil is single threaded. Mutex can only be poisoned if another
+        // thread panics while holding the lock.
+        unreachable!("ReplayTransport mutex poisoned in single-threaded context: {}", e)
+    })
+}
@@ -1,29 +1,41 @@
 //! Scenario builder API.
 //!
 //! Provides a declarative API for constructing scenario tests that enforce
-//! the Oracle Pattern.
+//! the Oracle Pattern. A scenario runs the handshake, then its [`Step`]s in
+//! order, then the oracle.
 
-use std::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use lockframe_core::{
-    connection::{Connection, ConnectionAction, ConnectionConfig},
+    connection::{Connection, ConnectionAction, ConnectionConfig, ConnectionState},
     env::Environment,
 };
 use tokio::time::Instant;
 
 use crate::{
-    SimEnv,
-    scenario::{OracleFn, World},
+    SimEnv, alloc_tracking,
+    scenario::{
+        Actor, OracleFn, Step, World,
+        repro::{REPRO_DIR_VAR, Repro, ReproStep},
+    },
 };
 
 /// Scenario builder.
 ///
-/// Construct a scenario by configuring client and server, optionally
-/// advancing time, and adding an oracle verification function.
+/// Construct a scenario by configuring client and server, adding steps to
+/// run after the handshake, optionally advancing time, and adding an oracle
+/// verification function.
 pub struct Scenario {
     client_config: ConnectionConfig,
     server_config: ConnectionConfig,
+    steps: Vec<Step>,
     time_advance: Option<Duration>,
+    seed: u64,
+    golden: Option<PathBuf>,
+    allocation_budget: Option<u64>,
 }
 
 impl Scenario {
@@ -33,7 +45,11 @@ impl Scenario {
         Self {
             client_config: ConnectionConfig::default(),
             server_config: ConnectionConfig::default(),
+            steps: Vec::new(),
             time_advance: None,
+            seed: 0,
+            golden: None,
+            allocation_budget: None,
         }
     }
 
@@ -51,13 +67,29 @@ impl Scenario {
         self
     }
 
-    /// Advance virtual time after handshake completion.
+    /// Seed the RNG deciding which frames injected faults hit. Runs with the
+    /// same seed and steps hit the same frames.
+    #[must_use]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Add a step to run after the handshake, after those already added.
+    #[must_use]
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Advance virtual time after the handshake and steps.
     ///
     /// This allows testing timeout behavior. The scenario will:
-    /// 1. Execute the handshake
+    /// 1. Execute the handshake and steps
     /// 2. Advance time by the specified duration
     /// 3. Call `tick()` on both connections
-    /// 4. Process any resulting actions (Close, `SendFrame`, etc.)
+    /// 4. Process any resulting actions (Close, `SendFrame`, etc.), leaving
+    ///    sent frames in flight
     /// 5. Run the oracle
     #[must_use]
     pub fn with_time_advance(mut self, duration: Duration) -> Self {
@@ -65,6 +97,67 @@ impl Scenario {
         self
     }
 
+    /// Compare the frames delivered during the run with the golden
+    /// transcript at `path`, failing with a line diff if they differ.
+    ///
+    /// See [`golden`](crate::scenario::golden) for the format and how to
+    /// update it.
+    #[must_use]
+    pub fn with_golden(mut self, path: impl Into<PathBuf>) -> Self {
+        self.golden = Some(path.into());
+        self
+    }
+
+    /// Fail if running the handshake and steps allocates more than
+    /// `max_bytes`.
+    ///
+    /// Only enforced with the `alloc-tracking` feature; without it nothing
+    /// is counted.
+    #[must_use]
+    pub fn with_allocation_budget(mut self, max_bytes: u64) -> Self {
+        self.allocation_budget = Some(max_bytes);
+        self
+    }
+
+    /// Scenario saved in the repro at `path`, ready for an oracle. Checks
+    /// are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repro cannot be loaded.
+    pub fn from_repro(path: impl AsRef<Path>) -> Result<Self, String> {
+        let repro = Repro::load(path)?;
+        let mut steps = Vec::new();
+        for step in repro.steps {
+            steps.extend(step.into_step()?);
+        }
+
+        Ok(Self {
+            client_config: repro.client_config,
+            server_config: repro.server_config,
+            steps,
+            time_advance: repro.time_advance,
+            seed: repro.seed,
+            golden: None,
+            allocation_budget: None,
+        })
+    }
+
+    /// Repro of this scenario failing with `failure`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a step's frame does not encode.
+    pub fn to_repro(&self, failure: impl Into<String>) -> Result<Repro, String> {
+        Ok(Repro {
+            client_config: self.client_config.clone(),
+            server_config: self.server_config.clone(),
+            steps: self.steps.iter().map(ReproStep::from_step).collect::<Result<_, _>>()?,
+            time_advance: self.time_advance,
+            ..Repro::new(self.seed, failure)
+        })
+    }
+
     /// Set the oracle function and return a runnable scenario.
     ///
     /// The oracle is mandatory - you cannot run a scenario without
@@ -94,14 +187,52 @@ impl RunnableScenario {
     /// 2. Server handles Hello and sends `HelloReply`
     /// 3. Client handles `HelloReply` and transitions to Authenticated
     ///
+    /// Then runs each step, delivering the frames in flight after each.
+    ///
     /// If `time_advance` is set, advances time and calls `tick()` on both
     /// connections to process timeouts and heartbeats.
     ///
-    /// Finally, the oracle is invoked to verify global consistency.
+    /// Finally, the oracle is invoked to verify global consistency, and the
+    /// transcript is compared with the golden file if one is set.
+    ///
+    /// On failure, a repro is written to the directory named by
+    /// [`REPRO_DIR_VAR`], if set, and the error names it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handshake fails, a step fails or its check
+    /// does not hold, the oracle rejects the final world, the run exceeds its
+    /// allocation budget, or the transcript differs from the golden file.
     pub fn run(self) -> Result<(), String> {
+        let (result, allocated) = alloc_tracking::measure(|| self.execute());
+        let result = result.and_then(|world| {
+            (self.oracle)(&world)?;
+            if let Some(budget) = self.scenario.allocation_budget {
+                allocated.within(budget)?;
+            }
+            match &self.scenario.golden {
+                Some(path) => world.transcript().check_golden(path),
+                None => Ok(()),
+            }
+        });
+        let Err(failure) = result else {
+            return Ok(());
+        };
+        let Some(dir) = std::env::var_os(REPRO_DIR_VAR) else {
+            return Err(failure);
+        };
+
+        match self.scenario.to_repro(&failure).and_then(|repro| repro.write(dir)) {
+            Ok(path) => Err(format!("{failure} (repro: {})", path.display())),
+            Err(e) => Err(format!("{failure} (repro not written: {e})")),
+        }
+    }
+
+    /// Run the handshake and steps, returning the world for the oracle.
+    fn execute(&self) -> Result<World<Instant>, String> {
         let mut world: World<Instant> = World::new();
         let env = SimEnv::new();
-        let now = env.now();
+        let mut now = env.now();
 
         let client = Connection::new(now, self.scenario.client_config.clone());
         let mut server = Connection::new(now, self.scenario.server_config.clone());
@@ -109,17 +240,21 @@ impl RunnableScenario {
 
         world.set_client(client);
         world.set_server(server);
+        world.seed_faults(self.scenario.seed);
 
         self.execute_handshake(&mut world, now)?;
 
+        for (index, step) in self.scenario.steps.iter().enumerate() {
+            now = Self::execute_step(&mut world, step, now)
+                .map_err(|e| format!("step {index} ({step:?}) failed: {e}"))?;
+        }
+
         if let Some(advance) = self.scenario.time_advance {
             let future = now + advance;
-            self.tick_connections(&mut world, future);
+            Self::tick_connections(&mut world, future);
         }
 
-        (self.oracle)(&world)?;
-
-        Ok(())
+        Ok(world)
     }
 
     /// Execute the handshake between client and server.
@@ -136,7 +271,7 @@ impl RunnableScenario {
         };
 
         world.record_client_frame_sent();
-        world.record_server_frame_received();
+        world.record_delivery(Actor::Client, &hello_frame);
 
         let hello_reply_frame = {
             let server = world.server_mut();
@@ -155,7 +290,7 @@ impl RunnableScenario {
         };
 
         world.record_server_frame_sent();
-        world.record_client_frame_received();
+        world.record_delivery(Actor::Server, &hello_reply_frame);
 
         {
             let client = world.client_mut();
@@ -173,49 +308,97 @@ impl RunnableScenario {
         Ok(())
     }
 
+    /// Execute one step, then deliver the frames in flight.
+    ///
+    /// Returns the virtual time after the step.
+    fn execute_step(
+        world: &mut World<Instant>,
+        step: &Step,
+        now: Instant,
+    ) -> Result<Instant, String> {
+        let mut now = now;
+        match step {
+            Step::Send { from, frame } => world.send(*from, frame.clone()),
+            Step::AdvanceTime(duration) => {
+                now += *duration;
+                Self::tick_connections(world, now);
+            },
+            Step::Partition { a, b } => world.partition(*a, *b),
+            Step::Heal { a, b } => world.heal(*a, *b),
+            Step::Inject(fault) => world.inject(*fault),
+            Step::Crash(actor) => world.crash(*actor),
+            Step::CheckInvariant(check) => check(world)?,
+        }
+        Self::deliver(world, now)?;
+        Ok(now)
+    }
+
+    /// Deliver frames in flight, and the replies they cause, until none are
+    /// left. Frames between partitioned actors or to closed connections are
+    /// dropped, and injected faults apply to the rest.
+    ///
+    /// Receivers refusing a frame a fault hit is expected and only counted.
+    fn deliver(world: &mut World<Instant>, now: Instant) -> Result<(), String> {
+        while let Some((from, frame, faulted)) = world.next_in_flight() {
+            let to = from.peer();
+            if world.is_partitioned(from, to)
+                || world.connection(to).state() == ConnectionState::Closed
+            {
+                world.record_frame_dropped();
+                continue;
+            }
+            let (frame, faulted) =
+                if faulted { (Some(frame), true) } else { world.apply_faults(from, frame) };
+            let Some(frame) = frame else {
+                continue;
+            };
+
+            world.record_delivery(from, &frame);
+            match world.connection_mut(to).handle_frame(&frame, now) {
+                Ok(actions) => Self::process_actions(world, to, actions),
+                Err(_) if faulted => world.record_frame_rejected(),
+                Err(e) => {
+                    return Err(format!(
+                        "{to:?} failed to handle {:?}: {e}",
+                        frame.header.opcode_enum()
+                    ));
+                },
+            }
+        }
+        Ok(())
+    }
+
     /// Tick both connections at the given time and process resulting actions.
-    fn tick_connections(&self, world: &mut World<Instant>, now: Instant) {
+    fn tick_connections(world: &mut World<Instant>, now: Instant) {
         let client_actions = world.client_mut().tick(now);
-        self.process_actions(world, &Actor::Client, client_actions);
+        Self::process_actions(world, Actor::Client, client_actions);
 
         let server_actions = world.server_mut().tick(now);
-        self.process_actions(world, &Actor::Server, server_actions);
+        Self::process_actions(world, Actor::Server, server_actions);
     }
 
     /// Process actions returned by `tick()` or other connection methods.
-    fn process_actions(
-        &self,
-        world: &mut World<Instant>,
-        actor: &Actor,
-        actions: Vec<ConnectionAction>,
-    ) {
+    fn process_actions(world: &mut World<Instant>, actor: Actor, actions: Vec<ConnectionAction>) {
         for action in actions {
             match action {
                 ConnectionAction::Close { .. } => {
                     // Connection closed - this is expected for timeout tests
                     // Oracle will verify the state
                 },
-                ConnectionAction::SendFrame(_frame) => {
-                    // Record frame sent
-                    match actor {
-                        Actor::Client => world.record_client_frame_sent(),
-                        Actor::Server => world.record_server_frame_sent(),
-                    }
-                },
+                ConnectionAction::SendFrame(frame) => world.send(actor, frame),
             }
         }
     }
 }
 
-/// Actor identifier for action processing.
-enum Actor {
-    Client,
-    Server,
-}
-
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use lockframe_proto::{Frame, FrameHeader, Opcode};
+
     use super::*;
+    use crate::scenario::{Fault, NetworkEvent};
 
     #[test]
     fn scenario_requires_oracle() {
@@ -234,4 +417,96 @@ mod tests {
 
         scenario.run().expect("scenario should succeed");
     }
+
+    fn ping() -> Frame {
+        Frame::new(FrameHeader::new(Opcode::Ping), Vec::new())
+    }
+
+    #[test]
+    fn steps_deliver_frames_between_actors() {
+        let result = Scenario::new()
+            .step(Step::Send { from: Actor::Client, frame: ping() })
+            .step(Step::CheckInvariant(Box::new(|world| {
+                // Ping answered with a Pong
+                assert_eq!(world.server_frames_received(), 2);
+                assert_eq!(world.client_frames_received(), 2);
+                assert_eq!(world.in_flight().count(), 0);
+                Ok(())
+            })))
+            .step(Step::Partition { a: Actor::Server, b: Actor::Client })
+            .step(Step::Send { from: Actor::Client, frame: ping() })
+            .step(Step::AdvanceTime(Duration::from_secs(5)))
+            .oracle(Box::new(|world| {
+                assert!(world.is_partitioned(Actor::Client, Actor::Server));
+                assert_eq!(world.server_frames_received(), 2);
+                // The second Ping, then both heartbeats
+                assert_eq!(world.frames_dropped(), 3);
+                assert!(world.all_authenticated());
+                Ok(())
+            }))
+            .run();
+
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[test]
+    fn duplicates_are_delivered_once_more_and_recorded() {
+        let result = Scenario::new()
+            .step(Step::Partition { a: Actor::Client, b: Actor::Server })
+            .step(Step::Heal { a: Actor::Client, b: Actor::Server })
+            .step(Step::Inject(Fault::Duplicate { rate: 1.0 }))
+            .step(Step::Send { from: Actor::Client, frame: ping() })
+            .oracle(Box::new(|world| {
+                // Two Pings, each answered with a Pong delivered twice
+                assert_eq!(world.server_frames_received(), 3);
+                assert_eq!(world.client_frames_received(), 5);
+                let duplicated = world
+                    .network_events()
+                    .iter()
+                    .filter(|event| matches!(event, NetworkEvent::Duplicated { .. }))
+                    .count();
+                assert_eq!(duplicated, 3);
+                Ok(())
+            }))
+            .run();
+
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[test]
+    fn same_seed_hits_the_same_frames() {
+        let run = |seed| {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let captured = Arc::clone(&events);
+            let mut scenario = Scenario::new()
+                .with_seed(seed)
+                .step(Step::Inject(Fault::Duplicate { rate: 0.5 }))
+                .step(Step::Inject(Fault::Reorder { rate: 0.5 }));
+            for _ in 0..8 {
+                scenario = scenario.step(Step::Send { from: Actor::Client, frame: ping() });
+            }
+            scenario
+                .oracle(Box::new(move |world| {
+                    *captured.lock().unwrap() = world.network_events().to_vec();
+                    Ok(())
+                }))
+                .run()
+                .unwrap();
+            Arc::try_unwrap(events).unwrap().into_inner().unwrap()
+        };
+
+        assert_eq!(run(3), run(3));
+        assert!(run(3).len() > 2, "some frames are hit");
+    }
+
+    #[test]
+    fn failed_check_names_the_step() {
+        let result = Scenario::new()
+            .step(Step::AdvanceTime(Duration::from_secs(1)))
+            .step(Step::CheckInvariant(Box::new(|_| Err("broken".to_string()))))
+            .oracle(Box::new(|_| Ok(())))
+            .run();
+
+        assert_eq!(result, Err("step 1 (CheckInvariant) failed: broken".to_string()));
+    }
 }
@@ -0,0 +1,127 @@
+//! Network faults.
+//!
+//! Faults act on frames as the scenario delivers them. Each active fault hits
+//! a frame with its rate, decided by an RNG seeded from the scenario, so a
+//! run with the same seed and steps hits the same frames. Every hit is
+//! recorded as a [`NetworkEvent`] to show what happened.
+//!
+//! A frame is hit by at most one fault, and copies or frames already held
+//! back are delivered as they are.
+
+use lockframe_proto::Frame;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::{Actor, NetworkEvent};
+
+/// A fault that can be injected with [`crate::scenario::Step::Inject`].
+///
+/// Rates are probabilities from 0.0 (never, turning the fault off) to 1.0
+/// (every frame).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Fault {
+    /// Deliver frames twice.
+    Duplicate {
+        /// Share of frames duplicated.
+        rate: f64,
+    },
+    /// Hold frames back until after the next frame in flight.
+    Reorder {
+        /// Share of frames held back.
+        rate: f64,
+    },
+    /// Flip one bit in the payload of frames that have one.
+    Corrupt {
+        /// Share of frames corrupted.
+        rate: f64,
+    },
+    /// Drop frames on the way.
+    Loss {
+        /// Share of frames lost.
+        rate: f64,
+    },
+}
+
+/// What a fault did to a frame about to be delivered.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Hit {
+    /// Deliver the frame, and a copy later.
+    Duplicate,
+    /// Put the frame behind the next one.
+    Reorder,
+    /// Deliver the frame with a flipped bit.
+    Corrupt(Frame),
+    /// Drop the frame.
+    Lose,
+}
+
+/// Active faults and the RNG deciding which frames they hit.
+pub(crate) struct Faults {
+    rng: ChaCha20Rng,
+    duplicate: f64,
+    reorder: f64,
+    corrupt: f64,
+    loss: f64,
+}
+
+impl Faults {
+    /// No faults, hitting frames by `seed` once injected.
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            duplicate: 0.0,
+            reorder: 0.0,
+            corrupt: 0.0,
+            loss: 0.0,
+        }
+    }
+
+    /// Set a fault's rate, replacing its previous one.
+    pub(crate) fn inject(&mut self, fault: Fault) {
+        match fault {
+            Fault::Duplicate { rate } => self.duplicate = rate.clamp(0.0, 1.0),
+            Fault::Reorder { rate } => self.reorder = rate.clamp(0.0, 1.0),
+            Fault::Corrupt { rate } => self.corrupt = rate.clamp(0.0, 1.0),
+            Fault::Loss { rate } => self.loss = rate.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Decide whether a fault hits `frame`, sent by `from`. Reordering needs
+    /// another frame in flight to go behind.
+    ///
+    /// Returns the hit and the event recording it.
+    pub(crate) fn roll(
+        &mut self,
+        from: Actor,
+        frame: &Frame,
+        can_reorder: bool,
+    ) -> Option<(Hit, NetworkEvent)> {
+        // Always draw for every fault, so one fault's rate doesn't shift
+        // which frames the others hit
+        let duplicate = self.rng.gen_bool(self.duplicate);
+        let reorder = self.rng.gen_bool(self.reorder);
+        let corrupt = self.rng.gen_bool(self.corrupt);
+        let position = self.rng.r#gen::<usize>();
+        let bit = self.rng.gen_range(0..8u8);
+        let lost = self.rng.gen_bool(self.loss);
+
+        if lost {
+            return Some((Hit::Lose, NetworkEvent::Lost { from }));
+        }
+        if corrupt && !frame.payload.is_empty() {
+            let byte = position % frame.payload.len();
+            let mut payload = frame.payload.to_vec();
+            payload[byte] ^= 1 << bit;
+            let corrupted = Frame { header: frame.header, payload: payload.into() };
+            return Some((Hit::Corrupt(corrupted), NetworkEvent::Corrupted { from, byte, bit }));
+        }
+        if reorder && can_reorder {
+            return Some((Hit::Reorder, NetworkEvent::Reordered { from }));
+        }
+        if duplicate {
+            return Some((Hit::Duplicate, NetworkEvent::Duplicated { from }));
+        }
+        None
+    }
+}
@@ -0,0 +1,203 @@
+//! Golden transcripts of scenario frames.
+//!
+//! A scenario run with
+//! [`Scenario::with_golden`](crate::scenario::Scenario::with_golden)
+//! records every frame delivered, in order, as a [`FrameTranscript`] and
+//! compares it to a checked-in golden file: one line per frame with its
+//! direction, opcode, sender, room and log index. A change in protocol
+//! chattiness, such as an extra ping or a duplicated commit, then shows up
+//! as a line diff in the failing test and in review.
+//!
+//! Set [`GOLDEN_UPDATE_VAR`] to write the transcripts instead of comparing
+//! them, after checking the change is intended.
+
+use std::{
+    fmt::{self, Write as _},
+    path::Path,
+};
+
+use lockframe_proto::Frame;
+
+use crate::scenario::Actor;
+
+/// Environment variable that, when set, makes golden scenarios write their
+/// transcript to the golden file instead of comparing against it.
+pub const GOLDEN_UPDATE_VAR: &str = "LOCKFRAME_UPDATE_GOLDEN";
+
+/// One frame of a transcript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRecord {
+    /// Actor that sent the frame.
+    pub from: Actor,
+    /// Opcode name, or its number if unknown.
+    pub opcode: String,
+    /// Sender ID from the header.
+    pub sender_id: u64,
+    /// Room ID from the header.
+    pub room_id: u128,
+    /// Log index from the header.
+    pub log_index: u64,
+}
+
+impl FrameRecord {
+    /// Record of `frame` sent by `from`.
+    pub fn new(from: Actor, frame: &Frame) -> Self {
+        let header = &frame.header;
+        let opcode = match header.opcode_enum() {
+            Some(opcode) => format!("{opcode:?}"),
+            None => format!("0x{:04x}", header.opcode()),
+        };
+        Self {
+            from,
+            opcode,
+            sender_id: header.sender_id(),
+            room_id: header.room_id(),
+            log_index: header.log_index(),
+        }
+    }
+}
+
+impl fmt::Display for FrameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.from {
+            Actor::Client => "client -> server",
+            Actor::Server => "server -> client",
+        };
+        write!(
+            f,
+            "{direction} {:<16} sender={} room={:#x} log={}",
+            self.opcode, self.sender_id, self.room_id, self.log_index
+        )
+    }
+}
+
+/// Frames delivered during a scenario, in delivery order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameTranscript {
+    /// Records in order.
+    pub records: Vec<FrameRecord>,
+}
+
+impl FrameTranscript {
+    /// Append `frame`, sent by `from`.
+    pub fn record(&mut self, from: Actor, frame: &Frame) {
+        self.records.push(FrameRecord::new(from, frame));
+    }
+
+    /// Golden file contents: one line per frame.
+    pub fn render(&self) -> String {
+        self.records.iter().fold(String::new(), |mut out, record| {
+            let _ = writeln!(out, "{record}");
+            out
+        })
+    }
+
+    /// Compare with the golden file at `path`, or write it if
+    /// [`GOLDEN_UPDATE_VAR`] is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns a line diff if the transcript differs from the golden file,
+    /// or an error if the file cannot be read or written.
+    pub fn check_golden(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let actual = self.render();
+
+        if std::env::var_os(GOLDEN_UPDATE_VAR).is_some() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            return std::fs::write(path, actual)
+                .map_err(|e| format!("cannot write {}: {e}", path.display()));
+        }
+
+        let expected = std::fs::read_to_string(path).map_err(|e| {
+            format!(
+                "cannot read golden {}: {e} (set {GOLDEN_UPDATE_VAR} to create it)",
+                path.display()
+            )
+        })?;
+        if expected == actual {
+            return Ok(());
+        }
+        Err(format!(
+            "transcript differs from golden {} (- golden, + actual; set {GOLDEN_UPDATE_VAR} to \
+             accept):\n{}",
+            path.display(),
+            diff(&expected, &actual)
+        ))
+    }
+}
+
+/// Line diff of `expected` and `actual`, marking removed lines `-`, added
+/// lines `+` and common lines with a space.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            let _ = writeln!(out, "  {}", old[i]);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            let _ = writeln!(out, "+ {}", new[j]);
+            j += 1;
+        } else {
+            let _ = writeln!(out, "- {}", old[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::{FrameHeader, Opcode};
+
+    use super::*;
+
+    fn frame(opcode: Opcode, log_index: u64) -> Frame {
+        let mut header = FrameHeader::new(opcode);
+        header.set_room_id(0x42);
+        header.set_sender_id(7);
+        header.set_log_index(log_index);
+        Frame::new(header, Vec::new())
+    }
+
+    #[test]
+    fn render_has_one_line_per_frame() {
+        let mut transcript = FrameTranscript::default();
+        transcript.record(Actor::Client, &frame(Opcode::AppMessage, 3));
+        transcript.record(Actor::Server, &frame(Opcode::Pong, 0));
+
+        assert_eq!(
+            transcript.render(),
+            "client -> server AppMessage       sender=7 room=0x42 log=3\n\
+             server -> client Pong             sender=7 room=0x42 log=0\n"
+        );
+    }
+
+    #[test]
+    fn diff_marks_extra_and_missing_lines() {
+        let golden = "hello\nping\nreply\n";
+        let actual = "hello\nping\nping\nreply\n";
+
+        assert_eq!(diff(golden, actual), "  hello\n  ping\n+ ping\n  reply\n");
+        assert_eq!(diff(actual, golden), "  hello\n  ping\n- ping\n  reply\n");
+    }
+}
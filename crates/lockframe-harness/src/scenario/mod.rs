@@ -2,14 +2,29 @@
 //!
 //! Declarative API for writing scenario-based tests that follow the Oracle
 //! Pattern. Scenarios automatically handle network I/O, action execution, and
-//! enforce oracle verification.
+//! enforce oracle verification. A [`SeedRunner`] runs a scenario across many
+//! seeds to measure how often it fails, and a [`FaultSchedule`] runs it under
+//! chaos that proptest can generate and shrink. A scenario can also be held
+//! to a golden transcript of the frames it exchanges.
 
 mod actor;
 mod builder;
+mod fault;
+pub mod golden;
 pub mod oracle;
+mod repro;
+mod runner;
+mod schedule;
+mod step;
 mod world;
 
 pub use actor::{ClientActor, ServerActor};
 pub use builder::{RunnableScenario, Scenario};
-pub use oracle::OracleFn;
-pub use world::World;
+pub use fault::Fault;
+pub use golden::{FrameRecord, FrameTranscript, GOLDEN_UPDATE_VAR};
+pub use oracle::{OracleFn, RoomObservations};
+pub use repro::{REPRO_DIR_VAR, Repro, ReproStep, TurmoilConfig, replay};
+pub use runner::{LatencySummary, RunResult, SeedOutcome, SeedReport, SeedRunner};
+pub use schedule::{ChaosEvent, FaultSchedule, ScheduledEvent, fault_schedule};
+pub use step::{Actor, CheckFn, Step};
+pub use world::{NetworkEvent, World};
@@ -2,8 +2,27 @@
 //!
 //! Oracle functions run at the end of scenarios to verify global consistency.
 //! They receive a snapshot of the entire world state and assert invariants.
+//!
+//! Beyond the connection oracles, room oracles check what a run did to its
+//! rooms, observed as [`RoomObservations`]: whether members agree on the
+//! epoch, whether every accepted message reached every member exactly once,
+//! and whether removed clients stopped receiving plaintext. Both kinds
+//! compose with [`all_of`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use lockframe_core::mls::RoomId;
+
+use crate::{
+    history::{History, HistoryEvent},
+    invariants::SystemSnapshot,
+    scenario::World,
+};
 
-use crate::scenario::World;
+/// Oracle over observed state `W`.
+///
+/// Returns `Ok(())` if all invariants hold, `Err(message)` otherwise.
+pub type Oracle<W> = Box<dyn FnOnce(&W) -> Result<(), String>>;
 
 /// Oracle function type.
 ///
@@ -12,7 +31,29 @@ use crate::scenario::World;
 /// - `Err(message)` if verification fails
 ///
 /// Generic over `I` (Instant type) to support virtual time in tests.
-pub type OracleFn<I = std::time::Instant> = Box<dyn FnOnce(&World<I>) -> Result<(), String>>;
+pub type OracleFn<I = std::time::Instant> = Oracle<World<I>>;
+
+/// Oracle over the rooms of a run.
+pub type RoomOracleFn = Oracle<RoomObservations>;
+
+/// What a run did to its rooms, for room oracles.
+///
+/// Clients are named by sender ID in both the snapshot and the history.
+#[derive(Debug, Clone, Default)]
+pub struct RoomObservations {
+    /// Every client's state at the end of the run.
+    pub snapshot: SystemSnapshot,
+    /// Messages sent, sequenced and delivered, and room removals, in the
+    /// order they happened.
+    pub history: History,
+}
+
+impl RoomObservations {
+    /// Observations of `snapshot` and `history`.
+    pub fn new(snapshot: SystemSnapshot, history: History) -> Self {
+        Self { snapshot, history }
+    }
+}
 
 /// Create an oracle that verifies all actors are authenticated.
 pub fn all_authenticated() -> OracleFn {
@@ -36,8 +77,153 @@ pub fn session_ids_match() -> OracleFn {
     })
 }
 
+/// Create an oracle that verifies every client in a room is at the same
+/// epoch.
+pub fn epoch_agreement() -> RoomOracleFn {
+    Box::new(|observed| {
+        let mut epochs: BTreeMap<RoomId, BTreeMap<u64, u64>> = BTreeMap::new();
+        for client in &observed.snapshot.clients {
+            for (room_id, room) in &client.rooms {
+                epochs.entry(*room_id).or_default().insert(client.id, room.epoch);
+            }
+        }
+
+        for (room_id, members) in epochs {
+            let mut distinct: Vec<u64> = members.values().copied().collect();
+            distinct.sort_unstable();
+            distinct.dedup();
+            if distinct.len() > 1 {
+                return Err(format!(
+                    "members of room {room_id:032x} disagree on the epoch: {members:?}"
+                ));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Create an oracle that verifies every message the server accepted was
+/// delivered exactly once to every live member of its room but the sender.
+///
+/// A message counts as accepted once its sender saw it sequenced. A member
+/// is live from its `Joined` event, or from the start of the history if it
+/// has none, until it is removed, and owes the messages sequenced in that
+/// time. Late joiners therefore need a `Joined` event. No client may deliver
+/// a message twice in one membership.
+pub fn message_conservation() -> RoomOracleFn {
+    Box::new(|observed| {
+        let events = observed.history.events();
+        // Position in the history each live member joined at
+        let mut live: BTreeMap<(RoomId, u64), usize> = BTreeMap::new();
+        let mut removed: HashSet<(RoomId, u64)> = HashSet::new();
+        let mut accepted: Vec<(usize, RoomId, u64, u64)> = Vec::new();
+        let mut deliveries: HashMap<(RoomId, u64, u64), usize> = HashMap::new();
+
+        for (position, event) in events.iter().enumerate() {
+            match event {
+                HistoryEvent::Joined { client, room_id } => {
+                    removed.remove(&(*room_id, *client));
+                    live.insert((*room_id, *client), position);
+                    deliveries.retain(|(room, member, _), _| (room, member) != (room_id, client));
+                },
+                HistoryEvent::Removed { client, room_id } => {
+                    live.remove(&(*room_id, *client));
+                    removed.insert((*room_id, *client));
+                },
+                HistoryEvent::Send { client, room_id, .. } => {
+                    join_from_start(&mut live, &removed, *room_id, *client);
+                },
+                HistoryEvent::Sequenced { client, room_id, log_index, .. } => {
+                    join_from_start(&mut live, &removed, *room_id, *client);
+                    accepted.push((position, *room_id, *log_index, *client));
+                },
+                HistoryEvent::Relayed { .. } => {},
+                HistoryEvent::Delivered { client, room_id, log_index, .. } => {
+                    join_from_start(&mut live, &removed, *room_id, *client);
+                    let count = deliveries.entry((*room_id, *client, *log_index)).or_default();
+                    *count += 1;
+                    if *count > 1 {
+                        return Err(format!(
+                            "client {client} delivered log index {log_index} in room \
+                             {room_id:032x} {count} times"
+                        ));
+                    }
+                },
+            }
+        }
+
+        for (sequenced_at, room_id, log_index, sender) in accepted {
+            let owed = live.iter().filter(|&(&(room, member), &joined_at)| {
+                room == room_id && member != sender && joined_at <= sequenced_at
+            });
+            for (&(_, member), _) in owed {
+                if !deliveries.contains_key(&(room_id, member, log_index)) {
+                    return Err(format!(
+                        "client {member} never delivered log index {log_index} in room \
+                         {room_id:032x}"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Make `client` a member of `room_id` from the start of the history, unless
+/// it is one already or was removed.
+fn join_from_start(
+    live: &mut BTreeMap<(RoomId, u64), usize>,
+    removed: &HashSet<(RoomId, u64)>,
+    room_id: RoomId,
+    client: u64,
+) {
+    if !removed.contains(&(room_id, client)) {
+        live.entry((room_id, client)).or_insert(0);
+    }
+}
+
+/// Create an oracle that verifies no client holds plaintext for a room it
+/// was removed from: it delivers nothing there until it rejoins, and does
+/// not keep the room's state.
+pub fn no_plaintext_after_removal() -> RoomOracleFn {
+    Box::new(|observed| {
+        let mut removed: HashSet<(RoomId, u64)> = HashSet::new();
+        for event in observed.history.events() {
+            match event {
+                HistoryEvent::Removed { client, room_id } => {
+                    removed.insert((*room_id, *client));
+                },
+                HistoryEvent::Joined { client, room_id } => {
+                    removed.remove(&(*room_id, *client));
+                },
+                HistoryEvent::Delivered { client, room_id, log_index, .. }
+                    if removed.contains(&(*room_id, *client)) =>
+                {
+                    return Err(format!(
+                        "client {client} delivered log index {log_index} in room \
+                         {room_id:032x} after it was removed"
+                    ));
+                },
+                _ => {},
+            }
+        }
+
+        for client in &observed.snapshot.clients {
+            let kept =
+                client.rooms.keys().find(|room_id| removed.contains(&(**room_id, client.id)));
+            if let Some(room_id) = kept {
+                return Err(format!(
+                    "client {} still holds room {room_id:032x} after it was removed",
+                    client.id
+                ));
+            }
+        }
+        Ok(())
+    })
+}
+
 /// Combine multiple oracles into one.
-pub fn all_of(oracles: Vec<OracleFn>) -> OracleFn {
+pub fn all_of<W: 'static>(oracles: Vec<Oracle<W>>) -> Oracle<W> {
     Box::new(move |world| {
         for oracle in oracles {
             oracle(world)?;
@@ -45,3 +231,127 @@ pub fn all_of(oracles: Vec<OracleFn>) -> OracleFn {
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invariants::{ClientSnapshot, RoomSnapshot};
+
+    const ROOM: RoomId = 0x00AB_CDEF;
+    const ALICE: u64 = 1;
+    const BOB: u64 = 2;
+    const CAROL: u64 = 3;
+
+    fn sequenced(client: u64, log_index: u64) -> HistoryEvent {
+        HistoryEvent::Sequenced { client, room_id: ROOM, request_id: 0, log_index }
+    }
+
+    fn delivered(client: u64, log_index: u64, sender: u64) -> HistoryEvent {
+        HistoryEvent::Delivered {
+            client,
+            room_id: ROOM,
+            log_index,
+            sender,
+            content: log_index.to_be_bytes().to_vec(),
+        }
+    }
+
+    fn observed(events: Vec<HistoryEvent>) -> RoomObservations {
+        let mut history = History::new();
+        for event in events {
+            history.push(event);
+        }
+        RoomObservations::new(SystemSnapshot::empty(), history)
+    }
+
+    fn at_epochs(epochs: &[(u64, u64)]) -> RoomObservations {
+        let clients = epochs
+            .iter()
+            .map(|&(id, epoch)| {
+                ClientSnapshot::new(id).with_room(ROOM, RoomSnapshot::with_epoch(epoch))
+            })
+            .collect();
+        RoomObservations::new(SystemSnapshot::from_clients(clients), History::new())
+    }
+
+    #[test]
+    fn epoch_agreement_rejects_a_member_behind() {
+        assert!(epoch_agreement()(&at_epochs(&[(ALICE, 3), (BOB, 3)])).is_ok());
+
+        let err = epoch_agreement()(&at_epochs(&[(ALICE, 3), (BOB, 2)])).unwrap_err();
+        assert!(err.contains("disagree on the epoch"), "{err}");
+    }
+
+    #[test]
+    fn conservation_requires_every_member_to_deliver() {
+        let events = vec![
+            HistoryEvent::Joined { client: BOB, room_id: ROOM },
+            HistoryEvent::Joined { client: CAROL, room_id: ROOM },
+            sequenced(ALICE, 0),
+            delivered(BOB, 0, ALICE),
+        ];
+
+        let err = message_conservation()(&observed(events)).unwrap_err();
+        assert!(err.contains("client 3 never delivered log index 0"), "{err}");
+    }
+
+    #[test]
+    fn conservation_rejects_duplicate_delivery() {
+        let events = vec![sequenced(ALICE, 0), delivered(BOB, 0, ALICE), delivered(BOB, 0, ALICE)];
+
+        let err = message_conservation()(&observed(events)).unwrap_err();
+        assert!(err.contains("delivered log index 0"), "{err}");
+        assert!(err.contains("2 times"), "{err}");
+    }
+
+    #[test]
+    fn conservation_spares_late_joiners_and_removed_members() {
+        let events = vec![
+            HistoryEvent::Joined { client: BOB, room_id: ROOM },
+            sequenced(ALICE, 0),
+            delivered(BOB, 0, ALICE),
+            HistoryEvent::Removed { client: BOB, room_id: ROOM },
+            HistoryEvent::Joined { client: CAROL, room_id: ROOM },
+            sequenced(ALICE, 1),
+            delivered(CAROL, 1, ALICE),
+        ];
+
+        assert_eq!(message_conservation()(&observed(events)), Ok(()));
+    }
+
+    #[test]
+    fn removed_client_must_not_deliver_until_it_rejoins() {
+        let removed = HistoryEvent::Removed { client: BOB, room_id: ROOM };
+        let rejoined = HistoryEvent::Joined { client: BOB, room_id: ROOM };
+
+        let err = no_plaintext_after_removal()(&observed(vec![
+            removed.clone(),
+            delivered(BOB, 4, ALICE),
+        ]))
+        .unwrap_err();
+        assert!(err.contains("after it was removed"), "{err}");
+
+        let events = vec![removed, rejoined, delivered(BOB, 4, ALICE)];
+        assert_eq!(no_plaintext_after_removal()(&observed(events)), Ok(()));
+    }
+
+    #[test]
+    fn removed_client_must_drop_room_state() {
+        let mut observations = at_epochs(&[(ALICE, 2), (BOB, 2)]);
+        observations.history.push(HistoryEvent::Removed { client: BOB, room_id: ROOM });
+
+        let err = no_plaintext_after_removal()(&observations).unwrap_err();
+        assert!(err.contains("client 2 still holds room"), "{err}");
+    }
+
+    #[test]
+    fn all_of_reports_the_first_failing_room_oracle() {
+        let mut observations = at_epochs(&[(ALICE, 1), (BOB, 2)]);
+        observations.history.push(sequenced(ALICE, 0));
+
+        let oracle =
+            all_of(vec![epoch_agreement(), message_conservation(), no_plaintext_after_removal()]);
+        let err = oracle(&observations).unwrap_err();
+        assert!(err.contains("disagree on the epoch"), "{err}");
+    }
+}
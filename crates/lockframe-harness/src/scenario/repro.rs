@@ -0,0 +1,312 @@
+//! Failure reproduction bundles.
+//!
+//! A [`Repro`] is everything needed to re-run a failed simulation: the seed,
+//! connection configs, steps in order with frames as their encoded bytes,
+//! turmoil settings and the version of the crates that failed. It is stored
+//! as JSON.
+//!
+//! When [`REPRO_DIR_VAR`] is set, a failing scenario writes its repro there
+//! and names the file in its error, so CI can keep it as an artifact. Re-run
+//! it locally with [`replay`], or load it with [`Scenario::from_repro`] to
+//! check it against the original oracle.
+//!
+//! Checks are closures and cannot be saved. A replay skips them, so a
+//! failure found by a check reproduces only once the check is added back.
+
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use lockframe_core::connection::ConnectionConfig;
+use lockframe_proto::Frame;
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::{Actor, Fault, Scenario, Step};
+
+/// Environment variable naming the directory failing scenarios write their
+/// repro to.
+pub const REPRO_DIR_VAR: &str = "LOCKFRAME_REPRO_DIR";
+
+/// Version of the crates writing repros. Workspace crates share it.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A failed run, saved for replay.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repro {
+    /// Seed for the fault RNG.
+    pub seed: u64,
+    /// Client connection config.
+    pub client_config: ConnectionConfig,
+    /// Server connection config.
+    pub server_config: ConnectionConfig,
+    /// Steps run after the handshake, in order.
+    pub steps: Vec<ReproStep>,
+    /// Time advanced after the steps.
+    pub time_advance: Option<Duration>,
+    /// Simulation settings, for runs under turmoil.
+    pub turmoil: Option<TurmoilConfig>,
+    /// Crate versions that produced the failure, by crate name.
+    pub versions: BTreeMap<String, String>,
+    /// Error the run failed with.
+    pub failure: String,
+}
+
+/// A [`Step`] as saved in a repro.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ReproStep {
+    /// [`Step::Send`], with the frame encoded.
+    Send {
+        /// Sending actor.
+        from: Actor,
+        /// Encoded frame.
+        frame: Vec<u8>,
+    },
+    /// [`Step::AdvanceTime`].
+    AdvanceTime {
+        /// Time advanced.
+        duration: Duration,
+    },
+    /// [`Step::Partition`].
+    Partition {
+        /// One side of the partition.
+        a: Actor,
+        /// The other side.
+        b: Actor,
+    },
+    /// [`Step::Heal`].
+    Heal {
+        /// One side of the partition.
+        a: Actor,
+        /// The other side.
+        b: Actor,
+    },
+    /// [`Step::Inject`].
+    Inject {
+        /// Fault injected.
+        fault: Fault,
+    },
+    /// [`Step::Crash`].
+    Crash {
+        /// Actor crashed.
+        actor: Actor,
+    },
+    /// [`Step::CheckInvariant`], skipped on replay.
+    Check,
+}
+
+impl ReproStep {
+    /// Save `step`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a sent frame does not encode.
+    pub fn from_step(step: &Step) -> Result<Self, String> {
+        Ok(match step {
+            Step::Send { from, frame } => {
+                let mut bytes = Vec::new();
+                frame.encode(&mut bytes).map_err(|e| format!("frame does not encode: {e}"))?;
+                Self::Send { from: *from, frame: bytes }
+            },
+            Step::AdvanceTime(duration) => Self::AdvanceTime { duration: *duration },
+            Step::Partition { a, b } => Self::Partition { a: *a, b: *b },
+            Step::Heal { a, b } => Self::Heal { a: *a, b: *b },
+            Step::Inject(fault) => Self::Inject { fault: *fault },
+            Step::Crash(actor) => Self::Crash { actor: *actor },
+            Step::CheckInvariant(_) => Self::Check,
+        })
+    }
+
+    /// The step to run, or `None` for a check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a saved frame does not decode.
+    pub fn into_step(self) -> Result<Option<Step>, String> {
+        Ok(Some(match self {
+            Self::Send { from, frame } => {
+                let frame =
+                    Frame::decode(&frame).map_err(|e| format!("frame does not decode: {e}"))?;
+                Step::Send { from, frame }
+            },
+            Self::AdvanceTime { duration } => Step::AdvanceTime(duration),
+            Self::Partition { a, b } => Step::Partition { a, b },
+            Self::Heal { a, b } => Step::Heal { a, b },
+            Self::Inject { fault } => Step::Inject(fault),
+            Self::Crash { actor } => Step::Crash(actor),
+            Self::Check => return Ok(None),
+        }))
+    }
+}
+
+/// Turmoil simulation settings, as passed to [`turmoil::Builder`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TurmoilConfig {
+    /// Seed for turmoil's RNG.
+    pub rng_seed: u64,
+    /// Simulated wall clock at the start, in seconds since the Unix epoch.
+    pub epoch_secs: u64,
+    /// How long the simulation may run.
+    pub simulation_duration: Duration,
+    /// Virtual time per step.
+    pub tick_duration: Duration,
+    /// Least latency added to a message.
+    pub min_message_latency: Duration,
+    /// Most latency added to a message.
+    pub max_message_latency: Duration,
+    /// Chance a link fails per step.
+    pub fail_rate: f64,
+    /// Chance a failed link repairs per step.
+    pub repair_rate: f64,
+}
+
+impl TurmoilConfig {
+    /// Turmoil's defaults, with a fixed seed and start time.
+    #[must_use]
+    pub fn new(rng_seed: u64) -> Self {
+        Self {
+            rng_seed,
+            epoch_secs: 0,
+            simulation_duration: Duration::from_secs(10),
+            tick_duration: Duration::from_millis(1),
+            min_message_latency: Duration::ZERO,
+            max_message_latency: Duration::from_millis(100),
+            fail_rate: 0.0,
+            repair_rate: 1.0,
+        }
+    }
+
+    /// Builder for a simulation with these settings.
+    pub fn builder(&self) -> turmoil::Builder {
+        let mut builder = turmoil::Builder::new();
+        builder
+            .rng_seed(self.rng_seed)
+            .epoch(SystemTime::UNIX_EPOCH + Duration::from_secs(self.epoch_secs))
+            .simulation_duration(self.simulation_duration)
+            .tick_duration(self.tick_duration)
+            .min_message_latency(self.min_message_latency)
+            .max_message_latency(self.max_message_latency)
+            .fail_rate(self.fail_rate)
+            .repair_rate(self.repair_rate);
+        builder
+    }
+}
+
+impl Repro {
+    /// Repro of a run that failed with `failure`, without steps or turmoil
+    /// settings.
+    pub fn new(seed: u64, failure: impl Into<String>) -> Self {
+        let versions = BTreeMap::from([("lockframe".to_string(), VERSION.to_string())]);
+        Self {
+            seed,
+            client_config: ConnectionConfig::default(),
+            server_config: ConnectionConfig::default(),
+            steps: Vec::new(),
+            time_advance: None,
+            turmoil: None,
+            versions,
+            failure: failure.into(),
+        }
+    }
+
+    /// Load a repro written by [`Repro::write`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a repro.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        let repro: Self = serde_json::from_str(&json).map_err(|e| format!("invalid repro: {e}"))?;
+
+        if repro.versions.get("lockframe").is_some_and(|version| version != VERSION) {
+            tracing::warn!(
+                "Repro {} was written by lockframe {:?}, replaying with {VERSION}",
+                path.display(),
+                repro.versions["lockframe"]
+            );
+        }
+        Ok(repro)
+    }
+
+    /// Write the repro as JSON into `dir`, named after its contents.
+    ///
+    /// Returns the file written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&self, dir: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut hasher = DefaultHasher::new();
+        json.hash(&mut hasher);
+        let path = dir.as_ref().join(format!("repro-{}-{:016x}.json", self.seed, hasher.finish()));
+
+        std::fs::create_dir_all(dir.as_ref()).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Re-run the scenario saved at `path`, without an oracle.
+///
+/// Fails as the original run did if a step fails. A failure found by the
+/// oracle needs it back: load the repro with [`Scenario::from_repro`].
+///
+/// # Errors
+///
+/// Returns an error if the repro cannot be loaded or the scenario fails.
+pub fn replay(path: impl AsRef<Path>) -> Result<(), String> {
+    Scenario::from_repro(path)?.oracle(Box::new(|_| Ok(()))).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::{FrameHeader, Opcode};
+
+    use super::*;
+
+    fn repro() -> Repro {
+        let frame = Frame::new(FrameHeader::new(Opcode::Ping), vec![1, 2, 3]);
+        let scenario = Scenario::new()
+            .with_seed(9)
+            .step(Step::Inject(Fault::Corrupt { rate: 0.5 }))
+            .step(Step::Send { from: Actor::Client, frame })
+            .step(Step::CheckInvariant(Box::new(|_| Ok(()))))
+            .step(Step::AdvanceTime(Duration::from_secs(3)))
+            .with_time_advance(Duration::from_secs(1));
+        let mut repro = scenario.to_repro("oracle failed").unwrap();
+        repro.turmoil = Some(TurmoilConfig::new(4));
+        repro
+    }
+
+    #[test]
+    fn repro_round_trips_through_a_file() {
+        let dir = std::env::temp_dir().join(format!("lockframe-repro-{}", std::process::id()));
+        let repro = repro();
+
+        let path = repro.write(&dir).unwrap();
+        assert_eq!(Repro::load(&path).unwrap(), repro);
+        assert_eq!(repro.write(&dir).unwrap(), path, "same repro, same file");
+
+        let steps = Scenario::from_repro(&path).unwrap().to_repro("").unwrap().steps;
+        assert_eq!(
+            steps,
+            repro.steps[..2].iter().chain(&repro.steps[3..]).cloned().collect::<Vec<_>>()
+        );
+
+        replay(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn turmoil_config_builds_a_simulation() {
+        let mut sim = TurmoilConfig::new(4).builder().build();
+        sim.client("client", async { Ok(()) });
+        sim.run().unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+//! Running a scenario across many seeds.
+//!
+//! One seed shows whether a run can fail, not how often. A [`SeedRunner`]
+//! runs a scenario once per seed on a pool of threads and aggregates the
+//! outcomes into a [`SeedReport`]: the pass rate, the failing seeds with
+//! their errors, and the distribution of the latencies the runs measured.
+//!
+//! Each run gets only its seed, so a failing seed re-runs alone with the
+//! same result.
+
+use std::{
+    fmt,
+    ops::Range,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Outcome of one run: the latencies it measured, or why it failed.
+pub type RunResult = Result<Vec<Duration>, String>;
+
+/// Runs a scenario once per seed in parallel.
+#[derive(Debug, Clone)]
+pub struct SeedRunner {
+    seeds: Range<u64>,
+    threads: usize,
+}
+
+impl SeedRunner {
+    /// Run each seed in `seeds`, on as many threads as the machine has.
+    #[must_use]
+    pub fn new(seeds: Range<u64>) -> Self {
+        let threads = thread::available_parallelism().map_or(1, usize::from);
+        Self { seeds, threads }
+    }
+
+    /// Run on `threads` threads, at least one.
+    #[must_use]
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Run `scenario` once per seed.
+    ///
+    /// A run passes by returning the latencies it measured, which may be
+    /// none, and fails by returning an error. A panicking run fails with
+    /// its panic message.
+    pub fn run<F>(&self, scenario: F) -> SeedReport
+    where
+        F: Fn(u64) -> RunResult + Sync,
+    {
+        let next = AtomicU64::new(self.seeds.start);
+        let outcomes = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    loop {
+                        let seed = next.fetch_add(1, Ordering::Relaxed);
+                        if seed >= self.seeds.end {
+                            break;
+                        }
+                        let result = run_seed(&scenario, seed);
+                        outcomes.lock().unwrap().push(SeedOutcome { seed, result });
+                    }
+                });
+            }
+        });
+
+        let mut outcomes = outcomes.into_inner().unwrap();
+        outcomes.sort_by_key(|outcome| outcome.seed);
+        SeedReport { outcomes }
+    }
+}
+
+/// Run one seed, turning a panic into a failure.
+fn run_seed<F>(scenario: &F, seed: u64) -> RunResult
+where
+    F: Fn(u64) -> RunResult,
+{
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario(seed))).unwrap_or_else(
+        |panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "non-string panic".to_string());
+            Err(format!("panicked: {message}"))
+        },
+    )
+}
+
+/// Outcome of the run with one seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedOutcome {
+    /// Seed the run was given.
+    pub seed: u64,
+    /// What the run returned.
+    pub result: RunResult,
+}
+
+/// Aggregated outcomes of a [`SeedRunner`], ordered by seed.
+///
+/// Displays as a summary naming every failing seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedReport {
+    outcomes: Vec<SeedOutcome>,
+}
+
+impl SeedReport {
+    /// Every run's outcome, ordered by seed.
+    pub fn outcomes(&self) -> &[SeedOutcome] {
+        &self.outcomes
+    }
+
+    /// Number of runs that passed.
+    pub fn passed(&self) -> usize {
+        self.outcomes.iter().filter(|outcome| outcome.result.is_ok()).count()
+    }
+
+    /// Number of runs that failed.
+    pub fn failed(&self) -> usize {
+        self.outcomes.len() - self.passed()
+    }
+
+    /// Share of runs that passed, from 0.0 to 1.0. 1.0 without runs.
+    pub fn pass_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 1.0;
+        }
+        self.passed() as f64 / self.outcomes.len() as f64
+    }
+
+    /// Seeds whose run failed, in ascending order.
+    pub fn failing_seeds(&self) -> Vec<u64> {
+        self.failures().map(|(seed, _)| seed).collect()
+    }
+
+    /// Seeds whose run failed, with their errors.
+    pub fn failures(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.outcomes.iter().filter_map(|outcome| match &outcome.result {
+            Ok(_) => None,
+            Err(e) => Some((outcome.seed, e.as_str())),
+        })
+    }
+
+    /// Distribution of the latencies passing runs measured. `None` if they
+    /// measured none.
+    pub fn latency(&self) -> Option<LatencySummary> {
+        let mut samples: Vec<Duration> = self
+            .outcomes
+            .iter()
+            .filter_map(|outcome| outcome.result.as_ref().ok())
+            .flatten()
+            .copied()
+            .collect();
+        samples.sort_unstable();
+        LatencySummary::from_sorted(&samples)
+    }
+
+    /// Log the report, as a warning if any run failed.
+    pub fn log(&self) {
+        if self.failed() > 0 {
+            tracing::warn!("{self}");
+        } else {
+            tracing::info!("{self}");
+        }
+    }
+}
+
+impl fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} seeds: {} passed, {} failed ({:.1}% pass)",
+            self.outcomes.len(),
+            self.passed(),
+            self.failed(),
+            self.pass_rate() * 100.0
+        )?;
+        if let Some(latency) = self.latency() {
+            write!(f, "\nlatency: {latency}")?;
+        }
+        if self.failed() > 0 {
+            let seeds: Vec<String> = self.failing_seeds().iter().map(u64::to_string).collect();
+            write!(f, "\nfailing seeds: {}", seeds.join(", "))?;
+            for (seed, error) in self.failures() {
+                write!(f, "\n  seed {seed}: {error}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Distribution of latency samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// Number of samples.
+    pub count: usize,
+    /// Smallest sample.
+    pub min: Duration,
+    /// Median.
+    pub p50: Duration,
+    /// 90th percentile.
+    pub p90: Duration,
+    /// 99th percentile.
+    pub p99: Duration,
+    /// Largest sample.
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summary of `samples`, sorted ascending. `None` if empty.
+    pub(crate) fn from_sorted(samples: &[Duration]) -> Option<Self> {
+        let (&min, &max) = (samples.first()?, samples.last()?);
+        // Nearest rank: the smallest sample at or above the percentile
+        let percentile = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+
+        Some(Self {
+            count: samples.len(),
+            min,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max,
+        })
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n={} min={:?} p50={:?} p90={:?} p99={:?} max={:?}",
+            self.count, self.min, self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_seeds_are_collected_in_order() {
+        let report = SeedRunner::new(0..20).with_threads(4).run(|seed| {
+            if seed % 7 == 3 { Err(format!("bad seed {seed}")) } else { Ok(Vec::new()) }
+        });
+
+        assert_eq!(report.outcomes().len(), 20);
+        assert_eq!(report.failing_seeds(), vec![3, 10, 17]);
+        assert_eq!(report.passed(), 17);
+        assert!((report.pass_rate() - 0.85).abs() < f64::EPSILON);
+        assert!(report.latency().is_none());
+
+        let text = report.to_string();
+        assert!(text.starts_with("20 seeds: 17 passed, 3 failed (85.0% pass)"), "{text}");
+        assert!(text.contains("failing seeds: 3, 10, 17"), "{text}");
+        assert!(text.contains("seed 10: bad seed 10"), "{text}");
+    }
+
+    #[test]
+    fn report_does_not_depend_on_thread_count() {
+        let scenario = |seed: u64| {
+            if seed.is_multiple_of(5) {
+                return Err("divisible by five".to_string());
+            }
+            Ok(vec![Duration::from_millis(seed)])
+        };
+
+        let single = SeedRunner::new(0..50).with_threads(1).run(scenario);
+        let parallel = SeedRunner::new(0..50).with_threads(8).run(scenario);
+        assert_eq!(single, parallel);
+    }
+
+    #[test]
+    fn latency_percentiles_use_nearest_rank() {
+        let report = SeedRunner::new(1..101)
+            .run(|seed| Ok(vec![Duration::from_millis(seed), Duration::from_millis(seed)]));
+
+        let latency = report.latency().unwrap();
+        assert_eq!(latency.count, 200);
+        assert_eq!(latency.min, Duration::from_millis(1));
+        assert_eq!(latency.p50, Duration::from_millis(50));
+        assert_eq!(latency.p90, Duration::from_millis(90));
+        assert_eq!(latency.p99, Duration::from_millis(99));
+        assert_eq!(latency.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn panicking_run_fails_its_seed() {
+        let report = SeedRunner::new(0..3).run(|seed| {
+            assert_ne!(seed, 1, "seed one");
+            Ok(Vec::new())
+        });
+
+        assert_eq!(report.failing_seeds(), vec![1]);
+        assert!(report.failures().all(|(_, e)| e.contains("seed one")));
+    }
+}
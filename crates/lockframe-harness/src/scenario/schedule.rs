@@ -0,0 +1,175 @@
+//! Fault schedules for chaos testing.
+//!
+//! A [`FaultSchedule`] is a whole chaos run as one value: when to partition
+//! the actors, how frame loss and other faults change over time, and where
+//! actors crash. [`fault_schedule`] generates them for proptest, which
+//! shrinks a failing schedule by dropping events, shortening the gaps
+//! between them, lowering rates and picking earlier kinds of event. A
+//! failure is then reported as the few events that cause it rather than the
+//! whole generated script.
+//!
+//! A schedule runs as scenario steps via [`FaultSchedule::apply`].
+
+use std::time::Duration;
+
+use proptest::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::{Actor, Fault, Scenario, Step};
+
+/// One event of a fault schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChaosEvent {
+    /// Set a fault's rate, e.g. the share of frames lost from now on.
+    Inject(Fault),
+    /// Partition the client from the server.
+    Partition,
+    /// Heal the partition.
+    Heal,
+    /// Crash an actor.
+    Crash(Actor),
+}
+
+/// A [`ChaosEvent`] and how long after the previous one it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    /// Virtual time advanced before the event.
+    pub after: Duration,
+    /// What happens.
+    pub event: ChaosEvent,
+}
+
+/// Chaos to run a scenario under: events in the order they happen.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaultSchedule {
+    /// Events in order.
+    pub events: Vec<ScheduledEvent>,
+}
+
+impl FaultSchedule {
+    /// Virtual time from the first event's gap to the last event.
+    pub fn duration(&self) -> Duration {
+        self.events.iter().map(|scheduled| scheduled.after).sum()
+    }
+
+    /// Steps running the schedule: each gap as a time advance, then its
+    /// event.
+    pub fn steps(&self) -> Vec<Step> {
+        let mut steps = Vec::new();
+        for scheduled in &self.events {
+            if !scheduled.after.is_zero() {
+                steps.push(Step::AdvanceTime(scheduled.after));
+            }
+            steps.push(match scheduled.event {
+                ChaosEvent::Inject(fault) => Step::Inject(fault),
+                ChaosEvent::Partition => Step::Partition { a: Actor::Client, b: Actor::Server },
+                ChaosEvent::Heal => Step::Heal { a: Actor::Client, b: Actor::Server },
+                ChaosEvent::Crash(actor) => Step::Crash(actor),
+            });
+        }
+        steps
+    }
+
+    /// `scenario` with the schedule's steps added after its own.
+    #[must_use]
+    pub fn apply(&self, scenario: Scenario) -> Scenario {
+        self.steps().into_iter().fold(scenario, Scenario::step)
+    }
+}
+
+/// Strategy for fault schedules of up to `max_events` events, each at most
+/// `max_gap` after the previous one.
+pub fn fault_schedule(
+    max_events: usize,
+    max_gap: Duration,
+) -> impl Strategy<Value = FaultSchedule> {
+    let max_gap_ms = u64::try_from(max_gap.as_millis()).unwrap_or(u64::MAX);
+    let scheduled = (0..=max_gap_ms, chaos_event()).prop_map(|(after_ms, event)| ScheduledEvent {
+        after: Duration::from_millis(after_ms),
+        event,
+    });
+
+    prop::collection::vec(scheduled, 0..=max_events).prop_map(|events| FaultSchedule { events })
+}
+
+/// Strategy for a single event. Earlier kinds are simpler, so shrinking
+/// prefers them: loss over other faults, and crashes only if needed.
+fn chaos_event() -> impl Strategy<Value = ChaosEvent> {
+    let rate = 0.0..=1.0f64;
+    let fault = prop_oneof![
+        rate.clone().prop_map(|rate| Fault::Loss { rate }),
+        rate.clone().prop_map(|rate| Fault::Duplicate { rate }),
+        rate.clone().prop_map(|rate| Fault::Reorder { rate }),
+        rate.prop_map(|rate| Fault::Corrupt { rate }),
+    ];
+    let actor = prop_oneof![Just(Actor::Client), Just(Actor::Server)];
+
+    prop_oneof![
+        4 => fault.prop_map(ChaosEvent::Inject),
+        2 => Just(ChaosEvent::Partition),
+        2 => Just(ChaosEvent::Heal),
+        1 => actor.prop_map(ChaosEvent::Crash),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::test_runner::{Config, TestError, TestRunner};
+
+    use super::*;
+
+    #[test]
+    fn steps_advance_time_between_events() {
+        let schedule = FaultSchedule {
+            events: vec![
+                ScheduledEvent {
+                    after: Duration::ZERO,
+                    event: ChaosEvent::Inject(Fault::Loss { rate: 0.02 }),
+                },
+                ScheduledEvent { after: Duration::from_secs(5), event: ChaosEvent::Partition },
+                ScheduledEvent {
+                    after: Duration::from_secs(2),
+                    event: ChaosEvent::Crash(Actor::Server),
+                },
+            ],
+        };
+
+        let steps: Vec<String> = schedule.steps().iter().map(|step| format!("{step:?}")).collect();
+        assert_eq!(steps, [
+            "Inject(Loss { rate: 0.02 })",
+            "AdvanceTime(5s)",
+            "Partition { a: Client, b: Server }",
+            "AdvanceTime(2s)",
+            "Crash(Server)",
+        ]);
+        assert_eq!(schedule.duration(), Duration::from_secs(7));
+    }
+
+    #[test]
+    fn failing_schedule_shrinks_to_its_cause() {
+        let mut runner = TestRunner::new(Config { failure_persistence: None, ..Config::default() });
+        let result = runner.run(&fault_schedule(20, Duration::from_secs(30)), |schedule| {
+            schedule
+                .apply(Scenario::new())
+                .oracle(Box::new(|world| {
+                    if world.all_authenticated() {
+                        Ok(())
+                    } else {
+                        Err("connection closed".to_string())
+                    }
+                }))
+                .run()
+                .map_err(TestCaseError::fail)
+        });
+
+        // Heartbeats keep the session open through loss and partitions, so
+        // only a crash closes it
+        let Err(TestError::Fail(_, minimal)) = result else {
+            panic!("expected a failing schedule, got {result:?}");
+        };
+        assert_eq!(minimal.events, [ScheduledEvent {
+            after: Duration::ZERO,
+            event: ChaosEvent::Crash(Actor::Client)
+        }]);
+    }
+}
@@ -0,0 +1,91 @@
+//! Scenario steps.
+//!
+//! Steps run in order between the handshake and the oracle. After each step
+//! the network delivers every frame in flight, including the replies they
+//! cause, so the next step starts from a quiet network.
+
+use std::{fmt, time::Duration};
+
+use lockframe_proto::Frame;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::scenario::{Fault, World};
+
+/// One side of the scenario's connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Actor {
+    /// The client connection.
+    Client,
+    /// The server connection.
+    Server,
+}
+
+impl Actor {
+    /// The actor at the other end of the connection.
+    #[must_use]
+    pub fn peer(self) -> Self {
+        match self {
+            Self::Client => Self::Server,
+            Self::Server => Self::Client,
+        }
+    }
+}
+
+/// Check run by [`Step::CheckInvariant`].
+///
+/// Returns `Err(message)` if the world is not as expected.
+pub type CheckFn<I = Instant> = Box<dyn Fn(&World<I>) -> Result<(), String>>;
+
+/// An operation executed mid-scenario.
+pub enum Step {
+    /// Send a frame from an actor to its peer.
+    Send {
+        /// Sending actor.
+        from: Actor,
+        /// Frame to send.
+        frame: Frame,
+    },
+    /// Advance virtual time and tick both connections.
+    AdvanceTime(Duration),
+    /// Drop frames between two actors from now on.
+    Partition {
+        /// One side of the partition.
+        a: Actor,
+        /// The other side.
+        b: Actor,
+    },
+    /// Deliver frames between two partitioned actors again.
+    Heal {
+        /// One side of the partition.
+        a: Actor,
+        /// The other side.
+        b: Actor,
+    },
+    /// Set a fault's rate for frames delivered from now on.
+    Inject(Fault),
+    /// Crash an actor: its connection closes without a word to the peer.
+    Crash(Actor),
+    /// Verify the world at this point of the scenario.
+    CheckInvariant(CheckFn),
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Send { from, frame } => f
+                .debug_struct("Send")
+                .field("from", from)
+                .field("opcode", &frame.header.opcode_enum())
+                .finish(),
+            Self::AdvanceTime(duration) => f.debug_tuple("AdvanceTime").field(duration).finish(),
+            Self::Partition { a, b } => {
+                f.debug_struct("Partition").field("a", a).field("b", b).finish()
+            },
+            Self::Heal { a, b } => f.debug_struct("Heal").field("a", a).field("b", b).finish(),
+            Self::Inject(fault) => f.debug_tuple("Inject").field(fault).finish(),
+            Self::Crash(actor) => f.debug_tuple("Crash").field(actor).finish(),
+            Self::CheckInvariant(_) => f.write_str("CheckInvariant"),
+        }
+    }
+}
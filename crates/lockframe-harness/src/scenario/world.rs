@@ -3,25 +3,96 @@
 //! The World manages a single client-server connection pair during scenario
 //! execution, tracks metrics, and provides oracle verification helpers.
 //!
+//! Frames sent during scenario steps wait in flight until the scenario
+//! delivers them, and are dropped between partitioned actors. Injected
+//! [`Fault`]s duplicate, reorder, corrupt or lose them on the way.
+//!
 //! Note: We currently support only 1:1 scenarios (one client, one server).
 //! Multi-actor scenarios will require turmoil integration for proper network
 //! simulation.
 
-use std::{ops::Sub, time::Duration};
+use std::{collections::VecDeque, ops::Sub, time::Duration};
 
 use lockframe_core::connection::{Connection, ConnectionState};
+use lockframe_proto::Frame;
+
+use crate::scenario::{
+    Actor, Fault,
+    fault::{Faults, Hit},
+    golden::FrameTranscript,
+};
 
 /// Network events that occurred during scenario execution.
+///
+/// Together with the scenario's seed, they record what the network did for
+/// reproducing a run.
 #[derive(Debug, Clone, PartialEq)]
 pub enum NetworkEvent {
-    /// Network partition between client and server
-    Partition,
+    /// Network partition between two actors
+    Partition {
+        /// One side of the partition
+        a: Actor,
+        /// The other side
+        b: Actor,
+    },
     /// Network partition healed
-    PartitionHealed,
+    PartitionHealed {
+        /// One side of the partition
+        a: Actor,
+        /// The other side
+        b: Actor,
+    },
     /// Packet loss injected
-    PacketLoss { rate: f64 },
+    PacketLoss {
+        /// Share of packets lost
+        rate: f64,
+    },
     /// Latency injected
-    Latency { min_ms: u64, max_ms: u64 },
+    Latency {
+        /// Least added latency
+        min_ms: u64,
+        /// Most added latency
+        max_ms: u64,
+    },
+    /// A fault's rate was set
+    FaultInjected(Fault),
+    /// A frame was delivered twice
+    Duplicated {
+        /// Sender of the frame
+        from: Actor,
+    },
+    /// A frame was held back behind the next one
+    Reordered {
+        /// Sender of the frame
+        from: Actor,
+    },
+    /// A bit in a frame's payload was flipped
+    Corrupted {
+        /// Sender of the frame
+        from: Actor,
+        /// Payload byte changed
+        byte: usize,
+        /// Bit flipped in that byte
+        bit: u8,
+    },
+    /// A frame was lost on the way
+    Lost {
+        /// Sender of the frame
+        from: Actor,
+    },
+    /// An actor crashed, closing its connection
+    Crashed {
+        /// Actor that crashed
+        actor: Actor,
+    },
+}
+
+/// A frame on its way to the sender's peer.
+struct InFlight {
+    from: Actor,
+    frame: Frame,
+    /// A fault already hit the frame, so no other will
+    faulted: bool,
 }
 
 /// World state containing single client-server pair and metrics.
@@ -38,6 +109,12 @@ where
     server_frames_sent: usize,
     server_frames_received: usize,
     network_events: Vec<NetworkEvent>,
+    in_flight: VecDeque<InFlight>,
+    partitions: Vec<(Actor, Actor)>,
+    faults: Faults,
+    frames_dropped: usize,
+    frames_rejected: usize,
+    transcript: FrameTranscript,
 }
 
 impl<I> World<I>
@@ -54,6 +131,12 @@ where
             server_frames_sent: 0,
             server_frames_received: 0,
             network_events: Vec::new(),
+            in_flight: VecDeque::new(),
+            partitions: Vec::new(),
+            faults: Faults::new(0),
+            frames_dropped: 0,
+            frames_rejected: 0,
+            transcript: FrameTranscript::default(),
         }
     }
 
@@ -121,6 +204,146 @@ where
         self.server_frames_received += 1;
     }
 
+    /// Connection of `actor`.
+    ///
+    /// Panics if it has not been set.
+    pub fn connection(&self, actor: Actor) -> &Connection<I> {
+        match actor {
+            Actor::Client => self.client(),
+            Actor::Server => self.server(),
+        }
+    }
+
+    /// Mutable connection of `actor`.
+    ///
+    /// Panics if it has not been set.
+    pub(crate) fn connection_mut(&mut self, actor: Actor) -> &mut Connection<I> {
+        match actor {
+            Actor::Client => self.client_mut(),
+            Actor::Server => self.server_mut(),
+        }
+    }
+
+    /// Send a frame from `from` to its peer, leaving it in flight until
+    /// delivered.
+    pub(crate) fn send(&mut self, from: Actor, frame: Frame) {
+        match from {
+            Actor::Client => self.record_client_frame_sent(),
+            Actor::Server => self.record_server_frame_sent(),
+        }
+        self.in_flight.push_back(InFlight { from, frame, faulted: false });
+    }
+
+    /// Take the oldest frame in flight, with its sender and whether a fault
+    /// hit it.
+    pub(crate) fn next_in_flight(&mut self) -> Option<(Actor, Frame, bool)> {
+        self.in_flight.pop_front().map(|entry| (entry.from, entry.frame, entry.faulted))
+    }
+
+    /// Seed the RNG deciding which frames faults hit.
+    pub(crate) fn seed_faults(&mut self, seed: u64) {
+        self.faults = Faults::new(seed);
+    }
+
+    /// Set a fault's rate.
+    pub(crate) fn inject(&mut self, fault: Fault) {
+        self.faults.inject(fault);
+        self.record_network_event(NetworkEvent::FaultInjected(fault));
+    }
+
+    /// Apply faults to `frame`, taken from flight and sent by `from`.
+    ///
+    /// Returns the frame to deliver now, if any, and whether a fault hit it.
+    /// Duplicates are queued and held-back frames go behind the next frame
+    /// in flight.
+    pub(crate) fn apply_faults(&mut self, from: Actor, frame: Frame) -> (Option<Frame>, bool) {
+        let Some((hit, event)) = self.faults.roll(from, &frame, !self.in_flight.is_empty()) else {
+            return (Some(frame), false);
+        };
+        self.record_network_event(event);
+        match hit {
+            Hit::Duplicate => {
+                let copy = InFlight { from, frame: frame.clone(), faulted: true };
+                self.in_flight.push_back(copy);
+                (Some(frame), true)
+            },
+            Hit::Reorder => {
+                self.in_flight.insert(1, InFlight { from, frame, faulted: true });
+                (None, true)
+            },
+            Hit::Corrupt(corrupted) => (Some(corrupted), true),
+            Hit::Lose => {
+                self.record_frame_dropped();
+                (None, true)
+            },
+        }
+    }
+
+    /// Record that a frame was received by `actor`.
+    pub(crate) fn record_frame_received(&mut self, actor: Actor) {
+        match actor {
+            Actor::Client => self.record_client_frame_received(),
+            Actor::Server => self.record_server_frame_received(),
+        }
+    }
+
+    /// Record that `frame`, sent by `from`, reached its receiver.
+    pub(crate) fn record_delivery(&mut self, from: Actor, frame: &Frame) {
+        self.record_frame_received(from.peer());
+        self.transcript.record(from, frame);
+    }
+
+    /// Record that a frame was lost on the way.
+    pub(crate) fn record_frame_dropped(&mut self) {
+        self.frames_dropped += 1;
+    }
+
+    /// Record that a frame a fault hit was refused by its receiver.
+    pub(crate) fn record_frame_rejected(&mut self) {
+        self.frames_rejected += 1;
+    }
+
+    /// Drop frames between `a` and `b` from now on.
+    pub(crate) fn partition(&mut self, a: Actor, b: Actor) {
+        if !self.is_partitioned(a, b) {
+            self.partitions.push((a, b));
+        }
+        self.record_network_event(NetworkEvent::Partition { a, b });
+    }
+
+    /// Crash `actor`. Its connection closes without telling the peer, and
+    /// frames to it are dropped from now on.
+    pub(crate) fn crash(&mut self, actor: Actor) {
+        self.connection_mut(actor).close();
+        self.record_network_event(NetworkEvent::Crashed { actor });
+    }
+
+    /// Deliver frames between `a` and `b` again.
+    pub(crate) fn heal(&mut self, a: Actor, b: Actor) {
+        self.partitions.retain(|&pair| pair != (a, b) && pair != (b, a));
+        self.record_network_event(NetworkEvent::PartitionHealed { a, b });
+    }
+
+    /// Whether frames between `a` and `b` are dropped.
+    pub fn is_partitioned(&self, a: Actor, b: Actor) -> bool {
+        self.partitions.iter().any(|&pair| pair == (a, b) || pair == (b, a))
+    }
+
+    /// Frames sent but not yet delivered, with their senders.
+    pub fn in_flight(&self) -> impl Iterator<Item = (Actor, &Frame)> {
+        self.in_flight.iter().map(|entry| (entry.from, &entry.frame))
+    }
+
+    /// Number of frames lost on the way.
+    pub fn frames_dropped(&self) -> usize {
+        self.frames_dropped
+    }
+
+    /// Number of frames a fault hit that their receiver refused.
+    pub fn frames_rejected(&self) -> usize {
+        self.frames_rejected
+    }
+
     /// Record a network event.
     pub fn record_network_event(&mut self, event: NetworkEvent) {
         self.network_events.push(event);
@@ -146,6 +369,11 @@ where
         self.server_frames_received
     }
 
+    /// Frames delivered so far, in delivery order.
+    pub fn transcript(&self) -> &FrameTranscript {
+        &self.transcript
+    }
+
     /// All network events that occurred during scenario execution.
     pub fn network_events(&self) -> &[NetworkEvent] {
         &self.network_events
@@ -8,11 +8,11 @@
 #![allow(clippy::disallowed_types, reason = "Synchronous locking operations only")]
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     sync::{Arc, Mutex},
 };
 
-use lockframe_app::{App, AppAction, AppEvent, Driver};
+use lockframe_app::{AccountId, App, AppAction, AppEvent, Driver, NoopNotifier};
 use lockframe_proto::Frame;
 
 use crate::invariants::{ClientSnapshot, InvariantRegistry, RoomSnapshot, SystemSnapshot};
@@ -35,9 +35,9 @@ impl std::error::Error for SimDriverError {}
 #[derive(Default)]
 struct SharedState {
     pending_events: VecDeque<AppEvent>,
-    incoming_frames: VecDeque<Frame>,
-    outgoing_frames: Vec<Frame>,
-    connected: bool,
+    incoming_frames: VecDeque<(AccountId, Frame)>,
+    outgoing_frames: Vec<(AccountId, Frame)>,
+    connected: HashSet<AccountId>,
 }
 
 /// Simulation driver for deterministic testing.
@@ -47,6 +47,7 @@ struct SharedState {
 pub struct SimDriver {
     state: Arc<Mutex<SharedState>>,
     invariants: Option<InvariantRegistry>,
+    notifier: NoopNotifier,
 }
 
 impl Default for SimDriver {
@@ -58,7 +59,11 @@ impl Default for SimDriver {
 impl SimDriver {
     /// Create a new simulation driver.
     pub fn new() -> Self {
-        Self { state: Arc::new(Mutex::new(SharedState::default())), invariants: None }
+        Self {
+            state: Arc::new(Mutex::new(SharedState::default())),
+            invariants: None,
+            notifier: NoopNotifier,
+        }
     }
 
     /// Enable invariant checking.
@@ -74,10 +79,15 @@ impl SimDriver {
         state.pending_events.push_back(event);
     }
 
-    /// Inject a frame from the server.
+    /// Inject a frame from the server of the first account.
     pub fn inject_frame(&self, frame: Frame) {
+        self.inject_frame_for(AccountId::default(), frame);
+    }
+
+    /// Inject a frame from an account's server.
+    pub fn inject_frame_for(&self, account: AccountId, frame: Frame) {
         let mut state = self.state.lock().unwrap();
-        state.incoming_frames.push_back(frame);
+        state.incoming_frames.push_back((account, frame));
     }
 
     /// Inject a tick event.
@@ -86,8 +96,13 @@ impl SimDriver {
         state.pending_events.push_back(AppEvent::Tick);
     }
 
-    /// Take all captured outgoing frames.
+    /// Take all captured outgoing frames, whichever account sent them.
     pub fn take_outgoing(&self) -> Vec<Frame> {
+        self.take_outgoing_for().into_iter().map(|(_, frame)| frame).collect()
+    }
+
+    /// Take all captured outgoing frames with the account that sent each.
+    pub fn take_outgoing_for(&self) -> Vec<(AccountId, Frame)> {
         let mut state = self.state.lock().unwrap();
         std::mem::take(&mut state.outgoing_frames)
     }
@@ -132,6 +147,7 @@ impl SimDriver {
 impl Driver for SimDriver {
     type Error = SimDriverError;
     type Instant = std::time::Instant;
+    type Notifier = NoopNotifier;
 
     async fn poll_event(&mut self, app: &mut App) -> Result<Vec<AppAction>, Self::Error> {
         let mut state = self.state.lock().unwrap();
@@ -144,22 +160,22 @@ impl Driver for SimDriver {
         }
     }
 
-    async fn send_frame(&mut self, frame: Frame) -> Result<(), Self::Error> {
-        self.state.lock().unwrap().outgoing_frames.push(frame);
+    async fn send_frame(&mut self, account: AccountId, frame: Frame) -> Result<(), Self::Error> {
+        self.state.lock().unwrap().outgoing_frames.push((account, frame));
         Ok(())
     }
 
-    async fn recv_frame(&mut self) -> Option<Frame> {
+    async fn recv_frame(&mut self) -> Option<(AccountId, Frame)> {
         self.state.lock().unwrap().incoming_frames.pop_front()
     }
 
-    async fn connect(&mut self, _addr: &str) -> Result<(), Self::Error> {
-        self.state.lock().unwrap().connected = true;
+    async fn connect(&mut self, account: AccountId, _addr: &str) -> Result<(), Self::Error> {
+        self.state.lock().unwrap().connected.insert(account);
         Ok(())
     }
 
-    fn is_connected(&self) -> bool {
-        self.state.lock().unwrap().connected
+    fn is_connected(&self, account: AccountId) -> bool {
+        self.state.lock().unwrap().connected.contains(&account)
     }
 
     #[allow(clippy::disallowed_methods)]
@@ -171,6 +187,10 @@ impl Driver for SimDriver {
         Ok(())
     }
 
+    fn notifier(&mut self) -> &mut Self::Notifier {
+        &mut self.notifier
+    }
+
     fn stop(&mut self) {}
 }
 
@@ -217,7 +237,7 @@ mod tests {
             Vec::new(),
         );
 
-        driver.send_frame(frame).await.unwrap();
+        driver.send_frame(AccountId::default(), frame).await.unwrap();
 
         let captured = driver.take_outgoing();
         assert_eq!(captured.len(), 1);
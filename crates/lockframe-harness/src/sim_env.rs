@@ -3,6 +3,7 @@
 #![allow(clippy::disallowed_types, reason = "Synchronous in-memory operations only")]
 
 use std::{
+    collections::BTreeMap,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -17,6 +18,19 @@ use rand_chacha::ChaCha20Rng;
 /// via `turmoil::sleep()`. `random_bytes()` uses `ChaCha20Rng` seeded with a
 /// fixed value (0 by default), ensuring reproducible test runs and easier
 /// debugging.
+///
+/// Outside a turmoil simulation, use [`SimEnv::with_manual_clock`] and move
+/// time with [`SimEnv::advance`].
+///
+/// # Scheduled Callbacks
+///
+/// Tick-based components are driven by scheduling callbacks with
+/// [`SimEnv::schedule_at`] or [`SimEnv::schedule_every`] and then running
+/// time forward with [`SimEnv::run_for`], which fires each callback once
+/// its time is reached. By default time jumps straight to the next
+/// callback; [`SimEnv::set_step`] makes it move in fixed steps instead, so
+/// callbacks fire at the first step boundary at or after their time, as
+/// with a coarse timer.
 #[derive(Clone)]
 pub struct SimEnv {
     /// Seeded RNG for deterministic random bytes
@@ -25,6 +39,38 @@ pub struct SimEnv {
     /// across clones (important for proper RNG sequence).
     /// Note: Turmoil is single-threaded, so this Mutex will never block.
     rng: Arc<Mutex<ChaCha20Rng>>,
+    /// Manually advanced time, shared across clones. `None` reads turmoil's
+    /// virtual time.
+    clock: Option<Arc<Mutex<tokio::time::Instant>>>,
+    /// Callbacks waiting for their time, shared across clones.
+    scheduler: Arc<Mutex<Scheduler>>,
+}
+
+/// A scheduled callback.
+enum Callback {
+    Once(Box<dyn FnOnce() + Send>),
+    /// Runs every `period`, first at its scheduled time.
+    Every {
+        period: Duration,
+        f: Box<dyn FnMut() + Send>,
+    },
+}
+
+/// Callbacks by due time, ties in scheduling order.
+#[derive(Default)]
+struct Scheduler {
+    callbacks: BTreeMap<(tokio::time::Instant, u64), Callback>,
+    next_seq: u64,
+    /// Time moved per step of [`SimEnv::run_for`]. `None` jumps to the next
+    /// callback.
+    step: Option<Duration>,
+}
+
+impl Scheduler {
+    fn insert(&mut self, at: tokio::time::Instant, callback: Callback) {
+        self.callbacks.insert((at, self.next_seq), callback);
+        self.next_seq += 1;
+    }
 }
 
 impl SimEnv {
@@ -41,10 +87,147 @@ impl SimEnv {
     /// Use this when you want to test different random scenarios while
     /// maintaining reproducibility.
     pub fn with_seed(seed: u64) -> Self {
-        Self { rng: Arc::new(Mutex::new(ChaCha20Rng::seed_from_u64(seed))) }
+        Self {
+            rng: Arc::new(Mutex::new(ChaCha20Rng::seed_from_u64(seed))),
+            clock: None,
+            scheduler: Arc::default(),
+        }
+    }
+
+    /// Create a `SimEnv` whose time stands still until [`SimEnv::advance`]d.
+    ///
+    /// Use this to drive timeouts without a turmoil simulation, as
+    /// model-based tests do.
+    pub fn with_manual_clock(seed: u64) -> Self {
+        Self {
+            clock: Some(Arc::new(Mutex::new(tokio::time::Instant::now()))),
+            ..Self::with_seed(seed)
+        }
+    }
+
+    /// Move the manual clock forward for this environment and its clones,
+    /// firing scheduled callbacks as their time is reached.
+    ///
+    /// Does nothing without a manual clock: turmoil time moves with
+    /// `sleep()` or [`SimEnv::run_for`].
+    pub fn advance(&self, duration: Duration) {
+        let Some(clock) = &self.clock else {
+            return;
+        };
+        let deadline = self.now() + duration;
+        self.fire_due();
+        while self.now() < deadline {
+            let delta = self.next_delta(deadline);
+            *lock(clock) += delta;
+            self.fire_due();
+        }
+    }
+
+    /// Run `f` once time reaches `at`.
+    ///
+    /// Callbacks only fire while time is run forward with
+    /// [`SimEnv::run_for`], [`SimEnv::run_until`] or [`SimEnv::advance`].
+    pub fn schedule_at(&self, at: tokio::time::Instant, f: impl FnOnce() + Send + 'static) {
+        lock(&self.scheduler).insert(at, Callback::Once(Box::new(f)));
+    }
+
+    /// Run `f` every `period`, first one `period` from now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn schedule_every(&self, period: Duration, f: impl FnMut() + Send + 'static) {
+        assert!(!period.is_zero(), "a repeating callback needs a non-zero period");
+        let first = self.now() + period;
+        lock(&self.scheduler).insert(first, Callback::Every { period, f: Box::new(f) });
+    }
+
+    /// Move time in steps of `step` while running, rather than jumping to
+    /// the next callback. `None` restores jumping.
+    pub fn set_step(&self, step: Option<Duration>) {
+        lock(&self.scheduler).step = step.filter(|step| !step.is_zero());
+    }
+
+    /// Number of callbacks waiting for their time.
+    pub fn pending_callbacks(&self) -> usize {
+        lock(&self.scheduler).callbacks.len()
+    }
+
+    /// Run time forward by `duration`, firing callbacks as their time is
+    /// reached.
+    pub async fn run_for(&self, duration: Duration) {
+        self.run_until(self.now() + duration).await;
+    }
+
+    /// Run time forward to `deadline`, firing callbacks as their time is
+    /// reached. Callbacks due by the deadline all fire, including ones
+    /// scheduled by other callbacks.
+    pub async fn run_until(&self, deadline: tokio::time::Instant) {
+        if self.clock.is_some() {
+            self.advance(deadline.saturating_duration_since(self.now()));
+            return;
+        }
+        self.fire_due();
+        while self.now() < deadline {
+            tokio::time::sleep(self.next_delta(deadline)).await;
+            self.fire_due();
+        }
+    }
+
+    /// How far to move time next on the way to `deadline`: one step, or up
+    /// to the next callback.
+    fn next_delta(&self, deadline: tokio::time::Instant) -> Duration {
+        let now = self.now();
+        let remaining = deadline.saturating_duration_since(now);
+        let scheduler = lock(&self.scheduler);
+        match (scheduler.step, scheduler.callbacks.keys().next()) {
+            (Some(step), _) => step.min(remaining),
+            (None, Some(&(next, _))) if next < deadline => next.saturating_duration_since(now),
+            (None, _) => remaining,
+        }
+    }
+
+    /// Fire every callback due by now, in time order. The lock is released
+    /// while a callback runs so it can schedule more.
+    fn fire_due(&self) {
+        loop {
+            let now = self.now();
+            let due = {
+                let mut scheduler = lock(&self.scheduler);
+                match scheduler.callbacks.first_key_value() {
+                    Some((&(at, _), _)) if at <= now => scheduler.callbacks.pop_first(),
+                    _ => None,
+                }
+            };
+            let Some(((at, _), callback)) = due else {
+                return;
+            };
+
+            match callback {
+                Callback::Once(f) => f(),
+                Callback::Every { period, mut f } => {
+                    f();
+                    // Catch up by period, not by step, so the callback keeps
+                    // its phase
+                    let mut next = at + period;
+                    while next <= now {
+                        next += period;
+                    }
+                    lock(&self.scheduler).insert(next, Callback::Every { period, f });
+                },
+            }
+        }
     }
 }
 
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| {
+        // SAFETY: Turmoil is single threaded. Mutex can only be poisoned if another
+        // thread panics while holding the lock.
+        unreachable!("SimEnv mutex poisoned in single-threaded context: {}", e)
+    })
+}
+
 impl Default for SimEnv {
     fn default() -> Self {
         Self::new()
@@ -55,7 +238,10 @@ impl Environment for SimEnv {
     type Instant = tokio::time::Instant;
 
     fn now(&self) -> Self::Instant {
-        tokio::time::Instant::now()
+        match &self.clock {
+            Some(clock) => *lock(clock),
+            None => tokio::time::Instant::now(),
+        }
     }
 
     async fn sleep(&self, duration: Duration) {
@@ -63,14 +249,7 @@ impl Environment for SimEnv {
     }
 
     fn random_bytes(&self, dest: &mut [u8]) {
-        self.rng
-            .lock()
-            .unwrap_or_else(|e| {
-                // SAFETY: Turmoil is single threaded. Mutex can only be poisoned if another
-                // thread panics while holding the lock.
-                unreachable!("RNG mutex poisoned in single-threaded context: {}", e)
-            })
-            .fill_bytes(dest);
+        lock(&self.rng).fill_bytes(dest);
     }
 
     fn wall_clock_secs(&self) -> u64 {
@@ -82,6 +261,10 @@ impl Environment for SimEnv {
 
 #[cfg(test)]
 mod tests {
+    use lockframe_core::connection::{
+        Connection, ConnectionAction, ConnectionConfig, ConnectionState,
+    };
+
     use super::*;
 
     #[test]
@@ -103,6 +286,102 @@ mod tests {
         sim.run().expect("simulation failed");
     }
 
+    #[test]
+    fn manual_clock_moves_only_when_advanced() {
+        let env = SimEnv::with_manual_clock(0);
+        let clone = env.clone();
+        let start = env.now();
+
+        assert_eq!(env.now(), start);
+
+        clone.advance(Duration::from_secs(3));
+        assert_eq!(env.now() - start, Duration::from_secs(3));
+
+        // Turmoil time is not moved by hand
+        SimEnv::new().advance(Duration::from_secs(3));
+    }
+
+    #[test]
+    fn scheduled_callbacks_fire_in_time_order() {
+        let env = SimEnv::with_manual_clock(0);
+        let start = env.now();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+
+        for (label, at_ms) in [("late", 30), ("early", 10), ("tie", 10), ("after", 90)] {
+            let fired = Arc::clone(&fired);
+            let clock = env.clone();
+            env.schedule_at(start + Duration::from_millis(at_ms), move || {
+                lock(&fired).push((label, clock.now() - start));
+            });
+        }
+
+        env.advance(Duration::from_millis(50));
+
+        assert_eq!(*lock(&fired), [
+            ("early", Duration::from_millis(10)),
+            ("tie", Duration::from_millis(10)),
+            ("late", Duration::from_millis(30)),
+        ]);
+        assert_eq!(env.now() - start, Duration::from_millis(50));
+        assert_eq!(env.pending_callbacks(), 1);
+    }
+
+    #[test]
+    fn step_quantizes_repeating_callbacks() {
+        let env = SimEnv::with_manual_clock(0);
+        let start = env.now();
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let clock = env.clone();
+        let recorded = Arc::clone(&ticks);
+        env.schedule_every(Duration::from_millis(30), move || {
+            lock(&recorded).push(clock.now() - start);
+        });
+
+        env.set_step(Some(Duration::from_millis(100)));
+        env.advance(Duration::from_millis(300));
+
+        // A 100ms step sees at most one 30ms tick per step
+        let ms = |ms| Duration::from_millis(ms);
+        assert_eq!(*lock(&ticks), [ms(100), ms(200), ms(300)]);
+    }
+
+    #[test]
+    fn scheduler_drives_connection_ticks() {
+        let mut sim = turmoil::Builder::new().build();
+
+        sim.client("test", async {
+            let env = SimEnv::new();
+            let config = ConnectionConfig {
+                handshake_timeout: Duration::from_secs(3),
+                ..ConnectionConfig::default()
+            };
+            let conn = Arc::new(Mutex::new(Connection::new(env.now(), config)));
+            let start = env.now();
+            lock(&conn).send_hello(start)?;
+            let closed_at = Arc::new(Mutex::new(None));
+
+            let (clock, ticked, closed) = (env.clone(), Arc::clone(&conn), Arc::clone(&closed_at));
+            env.schedule_every(Duration::from_secs(1), move || {
+                for action in lock(&ticked).tick(clock.now()) {
+                    if matches!(action, ConnectionAction::Close { .. }) {
+                        *lock(&closed) = Some(clock.now() - start);
+                    }
+                }
+            });
+            let handshake_timeout = lock(&conn).handshake_timeout();
+            env.run_for(handshake_timeout * 2).await;
+
+            // Ticks once a second notice the timeout within a second
+            let closed_at = lock(&closed_at).expect("handshake never timed out");
+            assert!(closed_at > handshake_timeout, "{closed_at:?}");
+            assert!(closed_at <= handshake_timeout + Duration::from_secs(1), "{closed_at:?}");
+            assert_eq!(lock(&conn).state(), ConnectionState::Closed);
+            Ok(())
+        });
+
+        sim.run().expect("simulation failed");
+    }
+
     #[test]
     fn sim_env_rng_is_deterministic() {
         // Run the same test twice with same seed, verify same output
@@ -12,9 +12,7 @@ use std::{
 };
 
 use lockframe_proto::Frame;
-use lockframe_server::{
-    DriverConfig, LogLevel, MemoryStorage, ServerAction, ServerDriver, ServerEvent,
-};
+use lockframe_server::{DriverConfig, MemoryStorage, ServerAction, ServerDriver, ServerEvent};
 use tokio::{
     io::{AsyncWriteExt, WriteHalf},
     sync::Mutex,
@@ -74,7 +72,7 @@ impl SimServer {
 
         let actions = self
             .driver
-            .process_event(ServerEvent::ConnectionAccepted { session_id })
+            .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
             .map_err(|e| io::Error::other(e.to_string()))?;
 
         let (_reader, writer) = tokio::io::split(stream);
@@ -117,9 +115,19 @@ impl SimServer {
                     self.close_connection(session_id, &reason);
                 },
 
-                ServerAction::Log { level, message, .. } => {
-                    self.log(level, &message);
-                },
+                ServerAction::Log(event) => event.emit(),
+
+                // Replies and drain completion are only produced for
+                // `ServerEvent::Admin` and `ServerEvent::BeginShutdown`, which
+                // the simulation never sends. Its audit trail stays in memory
+                // and it has no network to post webhooks to. Frame writes are
+                // only left to the runtime with pipelined persistence, which
+                // the simulation does not enable
+                ServerAction::AdminReply(_)
+                | ServerAction::DrainComplete
+                | ServerAction::PersistFrame { .. }
+                | ServerAction::Audit(_)
+                | ServerAction::DeliverWebhook(_) => {},
             }
         }
 
@@ -147,16 +155,6 @@ impl SimServer {
         });
     }
 
-    /// Log a message.
-    fn log(&self, level: LogLevel, message: &str) {
-        match level {
-            LogLevel::Debug => tracing::debug!("{}", message),
-            LogLevel::Info => tracing::info!("{}", message),
-            LogLevel::Warn => tracing::warn!("{}", message),
-            LogLevel::Error => tracing::error!("{}", message),
-        }
-    }
-
     /// Process a received frame from a connection.
     ///
     /// Call this when a frame is read from the connection.
@@ -179,8 +177,8 @@ impl SimServer {
             .map_err(|e| io::Error::other(e.to_string()))?;
 
         for action in actions {
-            if let ServerAction::Log { level, message, .. } = action {
-                self.log(level, &message);
+            if let ServerAction::Log(event) = action {
+                event.emit();
             }
         }
 
@@ -255,7 +253,10 @@ mod tests {
             let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
 
             // Need a connection first - use driver directly
-            let _ = server.driver.process_event(ServerEvent::ConnectionAccepted { session_id: 1 });
+            let _ = server.driver.process_event(ServerEvent::ConnectionAccepted {
+                session_id: 1,
+                peer_identity: None,
+            });
 
             server.create_room(room_id, 1)?;
             assert!(server.has_room(room_id));
@@ -0,0 +1,515 @@
+//! Server storage with injectable faults for deterministic testing.
+//!
+//! [`SimStorage`] implements the server `Storage` trait over another storage
+//! (in memory by default) and degrades it on demand: slow reads and writes,
+//! transient I/O errors, a full disk, and torn writes that persist part of
+//! the data before failing. Faults are drawn from a seeded RNG, so a run
+//! with the same seed and the same calls fails the same way.
+//!
+//! Clones share their faults and statistics. Keep a clone outside the
+//! `ServerDriver` or `RoomManager` under test to change faults mid-run and
+//! inspect what happened.
+
+#![allow(clippy::disallowed_types, reason = "Synchronous in-memory operations only")]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use lockframe_core::mls::MlsGroupState;
+use lockframe_proto::Frame;
+use lockframe_server::{MemoryStorage, Storage, StorageError, storage::StoredRoomMetadata};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::SimEnv;
+
+/// Counts of what a [`SimStorage`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimStorageStats {
+    /// Read operations attempted.
+    pub reads: usize,
+    /// Write operations attempted.
+    pub writes: usize,
+    /// Operations failed with a transient error.
+    pub transient_errors: usize,
+    /// Writes refused because the disk was full.
+    pub disk_full_errors: usize,
+    /// Writes that persisted part of their data, then failed.
+    pub torn_writes: usize,
+    /// Latency charged across all operations.
+    pub latency: Duration,
+}
+
+/// Faults to inject and what was injected so far.
+struct Faults {
+    rng: ChaCha20Rng,
+    read_latency: Duration,
+    write_latency: Duration,
+    error_rate: f64,
+    torn_write_rate: f64,
+    disk_full: bool,
+    stats: SimStorageStats,
+}
+
+/// Kind of storage operation, deciding which faults can hit it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    /// Write of bytes that can be persisted in part.
+    TearableWrite,
+}
+
+/// What happens to an operation.
+enum Fault {
+    Fail(StorageError),
+    Tear,
+}
+
+/// Storage that injects latency, transient errors, a full disk and torn
+/// writes into another storage.
+///
+/// Starts without faults. Rates are probabilities from 0.0 (never) to 1.0
+/// (every operation).
+#[derive(Clone)]
+pub struct SimStorage<S: Storage = MemoryStorage> {
+    inner: S,
+    faults: Arc<Mutex<Faults>>,
+    /// Clock charged with latency. `None` only counts it.
+    clock: Option<SimEnv>,
+}
+
+impl SimStorage {
+    /// Fault-injecting in-memory storage drawing faults from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self::wrap(MemoryStorage::new(), seed)
+    }
+}
+
+impl<S: Storage> SimStorage<S> {
+    /// Inject faults into `inner`, drawing them from `seed`.
+    pub fn wrap(inner: S, seed: u64) -> Self {
+        let faults = Faults {
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            read_latency: Duration::ZERO,
+            write_latency: Duration::ZERO,
+            error_rate: 0.0,
+            torn_write_rate: 0.0,
+            disk_full: false,
+            stats: SimStorageStats::default(),
+        };
+        Self { inner, faults: Arc::new(Mutex::new(faults)), clock: None }
+    }
+
+    /// Charge latency to `env`'s clock, so time passes while the server
+    /// waits on the disk.
+    ///
+    /// Only a [`SimEnv::with_manual_clock`] moves: turmoil time cannot be
+    /// advanced from synchronous code, so under turmoil latency is only
+    /// counted.
+    #[must_use]
+    pub fn with_clock(mut self, env: SimEnv) -> Self {
+        self.clock = Some(env);
+        self
+    }
+
+    /// Storage the faults are injected into, to check what it holds.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Take `read` for every read and `write` for every write from now on.
+    pub fn set_latency(&self, read: Duration, write: Duration) {
+        let mut faults = lock(&self.faults);
+        faults.read_latency = read;
+        faults.write_latency = write;
+    }
+
+    /// Fail this share of operations with a transient I/O error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not in [0.0, 1.0].
+    pub fn set_error_rate(&self, rate: f64) {
+        assert_rate(rate);
+        lock(&self.faults).error_rate = rate;
+    }
+
+    /// Tear this share of writes.
+    ///
+    /// A torn frame or group info write persists the first half of its
+    /// bytes, then fails. Other writes cannot tear.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not in [0.0, 1.0].
+    pub fn set_torn_write_rate(&self, rate: f64) {
+        assert_rate(rate);
+        lock(&self.faults).torn_write_rate = rate;
+    }
+
+    /// Refuse every write while `full`, as a disk out of space does. Reads
+    /// still succeed.
+    pub fn set_disk_full(&self, full: bool) {
+        lock(&self.faults).disk_full = full;
+    }
+
+    /// Remove every fault. Statistics are kept.
+    pub fn heal(&self) {
+        let mut faults = lock(&self.faults);
+        faults.read_latency = Duration::ZERO;
+        faults.write_latency = Duration::ZERO;
+        faults.error_rate = 0.0;
+        faults.torn_write_rate = 0.0;
+        faults.disk_full = false;
+    }
+
+    /// What the storage did so far, across all clones.
+    pub fn stats(&self) -> SimStorageStats {
+        lock(&self.faults).stats
+    }
+
+    /// Run a read, injecting latency and transient errors.
+    fn read<T>(&self, op: impl FnOnce(&S) -> Result<T, StorageError>) -> Result<T, StorageError> {
+        match self.fault(Access::Read) {
+            Some(Fault::Fail(err)) => Err(err),
+            Some(Fault::Tear) | None => op(&self.inner),
+        }
+    }
+
+    /// Run a write that cannot tear.
+    fn write<T>(&self, op: impl FnOnce(&S) -> Result<T, StorageError>) -> Result<T, StorageError> {
+        match self.fault(Access::Write) {
+            Some(Fault::Fail(err)) => Err(err),
+            Some(Fault::Tear) | None => op(&self.inner),
+        }
+    }
+
+    /// Run a write that can tear: `torn` persists part of it.
+    fn tearable_write(
+        &self,
+        op: impl FnOnce(&S) -> Result<(), StorageError>,
+        torn: impl FnOnce(&S) -> Result<(), StorageError>,
+    ) -> Result<(), StorageError> {
+        match self.fault(Access::TearableWrite) {
+            Some(Fault::Fail(err)) => Err(err),
+            Some(Fault::Tear) => {
+                torn(&self.inner)?;
+                Err(StorageError::Io("torn write (simulated)".to_string()))
+            },
+            None => op(&self.inner),
+        }
+    }
+
+    /// Charge an operation's latency and decide its fault, if any.
+    fn fault(&self, access: Access) -> Option<Fault> {
+        let mut faults = lock(&self.faults);
+        let latency = if access == Access::Read {
+            faults.stats.reads += 1;
+            faults.read_latency
+        } else {
+            faults.stats.writes += 1;
+            faults.write_latency
+        };
+        faults.stats.latency += latency;
+
+        let (error_rate, torn_write_rate) = (faults.error_rate, faults.torn_write_rate);
+        let fault = if faults.roll(error_rate) {
+            faults.stats.transient_errors += 1;
+            Some(Fault::Fail(StorageError::Io("transient I/O error (simulated)".to_string())))
+        } else if access != Access::Read && faults.disk_full {
+            faults.stats.disk_full_errors += 1;
+            Some(Fault::Fail(StorageError::Io("no space left on device (simulated)".to_string())))
+        } else if access == Access::TearableWrite && faults.roll(torn_write_rate) {
+            faults.stats.torn_writes += 1;
+            Some(Fault::Tear)
+        } else {
+            None
+        };
+
+        drop(faults);
+        self.charge(latency);
+        fault
+    }
+
+    fn charge(&self, latency: Duration) {
+        if let Some(clock) = &self.clock
+            && !latency.is_zero()
+        {
+            clock.advance(latency);
+        }
+    }
+}
+
+impl Faults {
+    /// True with probability `rate`. Draws nothing for a zero rate, so
+    /// turning a fault off does not shift the draws of the others.
+    fn roll(&mut self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.r#gen::<f64>() < rate
+    }
+}
+
+fn assert_rate(rate: f64) {
+    assert!((0.0..=1.0).contains(&rate), "rate must be between 0.0 and 1.0, got {rate}");
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| {
+        // SAFETY: Turmoil is single threaded. Mutex can only be poisoned if another
+        // thread panics while holding the lock.
+        unreachable!("SimStorage mutex poisoned in single-threaded context: {}", e)
+    })
+}
+
+/// First half of `frame`'s payload under its header.
+fn torn_frame(frame: &Frame) -> Frame {
+    let half = frame.payload.len() / 2;
+    Frame::new(frame.header, frame.payload.slice(..half))
+}
+
+impl<S: Storage> Storage for SimStorage<S> {
+    fn store_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        self.tearable_write(
+            |inner| inner.store_frame(room_id, log_index, frame),
+            |inner| inner.store_frame(room_id, log_index, &torn_frame(frame)),
+        )
+    }
+
+    fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.read(|inner| inner.latest_log_index(room_id))
+    }
+
+    fn load_frames(
+        &self,
+        room_id: u128,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<Frame>, StorageError> {
+        self.read(|inner| inner.load_frames(room_id, from, limit))
+    }
+
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.read(|inner| inner.earliest_log_index(room_id))
+    }
+
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.read(|inner| inner.stored_bytes(room_id))
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        self.write(|inner| inner.truncate_frames(room_id, first_kept, tombstone))
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        self.tearable_write(
+            |inner| inner.replace_frame(room_id, log_index, frame),
+            |inner| inner.replace_frame(room_id, log_index, &torn_frame(frame)),
+        )
+    }
+
+    fn offload_history(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.write(|inner| inner.offload_history(room_id))
+    }
+
+    fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
+        self.write(|inner| inner.store_mls_state(room_id, state))
+    }
+
+    fn load_mls_state(&self, room_id: u128) -> Result<Option<MlsGroupState>, StorageError> {
+        self.read(|inner| inner.load_mls_state(room_id))
+    }
+
+    fn store_group_info(
+        &self,
+        room_id: u128,
+        epoch: u64,
+        group_info: &[u8],
+    ) -> Result<(), StorageError> {
+        self.tearable_write(
+            |inner| inner.store_group_info(room_id, epoch, group_info),
+            |inner| inner.store_group_info(room_id, epoch, &group_info[..group_info.len() / 2]),
+        )
+    }
+
+    fn load_group_info(&self, room_id: u128) -> Result<Option<(u64, Vec<u8>)>, StorageError> {
+        self.read(|inner| inner.load_group_info(room_id))
+    }
+
+    fn list_rooms(&self) -> Result<Vec<u128>, StorageError> {
+        self.read(Storage::list_rooms)
+    }
+
+    fn create_room(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        self.write(|inner| inner.create_room(room_id, metadata))
+    }
+
+    fn load_room_metadata(
+        &self,
+        room_id: u128,
+    ) -> Result<Option<StoredRoomMetadata>, StorageError> {
+        self.read(|inner| inner.load_room_metadata(room_id))
+    }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        self.write(|inner| inner.update_room_metadata(room_id, metadata))
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.write(Storage::flush)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_core::env::Environment;
+    use lockframe_proto::{FrameHeader, Opcode};
+    use lockframe_server::{DriverConfig, ServerAction, ServerDriver, ServerEvent};
+
+    use super::*;
+    use crate::ByzantineClient;
+
+    const ROOM: u128 = 0x00AB_CDEF;
+    const OWNER: u64 = 1;
+    const OWNER_SESSION: u64 = 100;
+
+    fn frame(log_index: u64, payload: &[u8]) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(ROOM);
+        header.set_sender_id(OWNER);
+        header.set_log_index(log_index);
+        Frame::new(header, Bytes::copy_from_slice(payload))
+    }
+
+    #[test]
+    fn latency_moves_the_manual_clock() {
+        let env = SimEnv::with_manual_clock(0);
+        let storage = SimStorage::new(0).with_clock(env.clone());
+        storage.set_latency(Duration::from_millis(1), Duration::from_millis(5));
+        let start = env.now();
+
+        storage.store_frame(ROOM, 0, &frame(0, b"hello")).unwrap();
+        storage.load_frames(ROOM, 0, 10).unwrap();
+
+        assert_eq!(env.now() - start, Duration::from_millis(6));
+        let stats = storage.stats();
+        assert_eq!((stats.reads, stats.writes), (1, 1));
+        assert_eq!(stats.latency, Duration::from_millis(6));
+    }
+
+    #[test]
+    fn transient_errors_follow_the_seed() {
+        let failures = |seed| {
+            let storage = SimStorage::new(seed);
+            storage.set_error_rate(0.5);
+            (0..64).map(|_| storage.list_rooms().is_err()).collect::<Vec<_>>()
+        };
+
+        let run = failures(7);
+        assert_eq!(run, failures(7));
+        assert_ne!(run, failures(8));
+        assert!(run.iter().any(|&failed| failed) && run.iter().any(|&failed| !failed));
+    }
+
+    #[test]
+    fn full_disk_refuses_writes_but_serves_reads() {
+        let storage = SimStorage::new(0);
+        storage.store_frame(ROOM, 0, &frame(0, b"first")).unwrap();
+
+        storage.set_disk_full(true);
+        let err = storage.store_frame(ROOM, 1, &frame(1, b"second")).unwrap_err();
+        assert!(err.to_string().contains("no space left"), "{err}");
+        assert_eq!(storage.latest_log_index(ROOM).unwrap(), Some(0));
+
+        storage.set_disk_full(false);
+        storage.store_frame(ROOM, 1, &frame(1, b"second")).unwrap();
+        assert_eq!(storage.stats().disk_full_errors, 1);
+    }
+
+    #[test]
+    fn torn_write_persists_half_a_frame() {
+        let storage = SimStorage::new(0);
+        storage.set_torn_write_rate(1.0);
+
+        assert!(storage.store_frame(ROOM, 0, &frame(0, b"12345678")).is_err());
+
+        let stored = storage.inner().load_frames(ROOM, 0, 1).unwrap();
+        assert_eq!(stored[0].payload.as_ref(), b"1234");
+        assert_eq!(stored[0].header.payload_size(), 4);
+        assert_eq!(storage.stats().torn_writes, 1);
+    }
+
+    /// Log index a broadcast in `actions` carries, if any.
+    fn broadcast_index(actions: &[ServerAction<tokio::time::Instant>]) -> Option<u64> {
+        actions.iter().find_map(|action| match action {
+            ServerAction::Broadcast { frame, .. } => Some(frame.header.log_index()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn driver_keeps_log_contiguous_across_full_disk() {
+        let storage = SimStorage::new(0);
+        let mut server = ServerDriver::new(SimEnv::new(), storage.clone(), DriverConfig::default());
+        let hello = ByzantineClient::new(OWNER).hello();
+        server
+            .process_event(ServerEvent::ConnectionAccepted {
+                session_id: OWNER_SESSION,
+                peer_identity: None,
+            })
+            .unwrap();
+        server
+            .process_event(ServerEvent::FrameReceived { session_id: OWNER_SESSION, frame: hello })
+            .unwrap();
+        server.create_room(ROOM, OWNER_SESSION).unwrap();
+
+        let mut send = |payload: &[u8]| {
+            let frame = frame(0, payload);
+            let actions = server
+                .process_event(ServerEvent::FrameReceived { session_id: OWNER_SESSION, frame })
+                .unwrap();
+            broadcast_index(&actions)
+        };
+
+        assert_eq!(send(b"before"), Some(0));
+        storage.set_disk_full(true);
+        send(b"lost");
+        storage.set_disk_full(false);
+        send(b"after");
+
+        // The frame that failed to persist leaves no gap behind it
+        let stored: Vec<(u64, Bytes)> = storage
+            .inner()
+            .load_frames(ROOM, 0, 10)
+            .unwrap()
+            .into_iter()
+            .map(|frame| (frame.header.log_index(), frame.payload))
+            .collect();
+        assert_eq!(stored, [(0, Bytes::from("before")), (1, Bytes::from("after"))]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Allocation budget tests.
+//!
+//! Run with `--features alloc-tracking`. Budgets are set a little above what
+//! the hot paths allocate today, so a change that adds a copy per frame
+//! fails here; lower them as the zero-copy work lands.
+
+use lockframe_harness::{
+    ByzantineClient, SimEnv, SimStorage,
+    alloc_tracking::measure,
+    scenario::{Actor, Scenario, Step},
+};
+use lockframe_proto::{Frame, FrameHeader, Opcode};
+use lockframe_server::{DriverConfig, ServerDriver, ServerEvent};
+
+const ROOM: u128 = 1;
+const FRAMES: u64 = 10_000;
+
+/// Server with two members in [`ROOM`], on sessions 1 and 2.
+fn server_with_room() -> Result<ServerDriver<SimEnv, SimStorage>, String> {
+    let env = SimEnv::with_manual_clock(0);
+    let storage = SimStorage::new(0);
+    let mut server = ServerDriver::new(env, storage, DriverConfig::default());
+
+    for id in [1, 2] {
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: id, peer_identity: None })
+            .map_err(|e| e.to_string())?;
+        server
+            .process_event(ServerEvent::FrameReceived {
+                session_id: id,
+                frame: ByzantineClient::new(id).hello(),
+            })
+            .map_err(|e| e.to_string())?;
+    }
+    server.create_room(ROOM, 1).map_err(|e| e.to_string())?;
+
+    let mut header = FrameHeader::new(Opcode::Welcome);
+    header.set_room_id(ROOM);
+    header.set_sender_id(1);
+    header.set_recipient_id(2);
+    server
+        .process_event(ServerEvent::FrameReceived {
+            session_id: 1,
+            frame: Frame::new(header, vec![0xBE, 0xEF]),
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(server)
+}
+
+fn message() -> Frame {
+    let mut header = FrameHeader::new(Opcode::AppMessage);
+    header.set_room_id(ROOM);
+    header.set_sender_id(1);
+    Frame::new(header, vec![0u8; 64])
+}
+
+#[test]
+fn routing_frames_stays_within_budget() {
+    let mut server = server_with_room().unwrap();
+    let frames: Vec<Frame> = (0..FRAMES).map(|_| message()).collect();
+
+    let (routed, stats) = measure(|| {
+        let mut routed = 0;
+        for frame in frames {
+            let actions =
+                server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap();
+            routed += actions.len();
+        }
+        routed
+    });
+
+    assert!(routed as u64 >= FRAMES, "every frame is routed");
+    // About 2.9 KB in 6 allocations per frame today
+    let per_frame = stats.bytes_allocated / FRAMES;
+    assert!(stats.within(FRAMES * 3500).is_ok(), "{per_frame} bytes per frame: {stats}");
+    assert!(stats.allocations <= FRAMES * 7, "{stats}");
+}
+
+#[test]
+fn scenario_allocation_budget_is_enforced() {
+    let scenario = || {
+        Scenario::new().step(Step::Send {
+            from: Actor::Client,
+            frame: Frame::new(FrameHeader::new(Opcode::Ping), Vec::new()),
+        })
+    };
+
+    let within = scenario().with_allocation_budget(1 << 20).oracle(Box::new(|_| Ok(()))).run();
+    assert!(within.is_ok(), "{within:?}");
+
+    let over = scenario().with_allocation_budget(0).oracle(Box::new(|_| Ok(()))).run();
+    assert!(over.unwrap_err().contains("over the budget"));
+}
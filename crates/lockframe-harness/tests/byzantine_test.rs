@@ -0,0 +1,97 @@
+//! Honest clients against a Byzantine peer.
+//!
+//! Alice and Bob share a room through `TestCluster`. A `ByzantineClient`
+//! captures Alice's messages and hands Bob tampered copies, which Bob must
+//! drop without delivering them or leaving the room's epoch.
+
+use lockframe_client::{ClientAction, ClientEvent};
+use lockframe_core::mls::RoomId;
+use lockframe_harness::{ByzantineClient, TestCluster};
+use lockframe_proto::{Frame, Opcode};
+
+const ROOM_ID: RoomId = 0x0002_0002_0002_0002_0002_0002_0002_0002;
+const ALICE: usize = 0;
+const BOB: usize = 1;
+
+/// Alice and Bob in `ROOM_ID`, at the same epoch.
+fn cluster() -> Result<TestCluster, String> {
+    let mut cluster = TestCluster::new(7, 2);
+    cluster.create_room(ROOM_ID)?;
+    cluster.join_via_welcome(ROOM_ID, BOB)?;
+    Ok(cluster)
+}
+
+/// Send a message from Alice, deliver it to Bob and capture it.
+fn alice_sends(
+    cluster: &mut TestCluster,
+    byzantine: &mut ByzantineClient,
+) -> Result<Frame, String> {
+    let actions = cluster.clients[ALICE]
+        .handle(ClientEvent::SendMessage { room_id: ROOM_ID, plaintext: b"hello".to_vec() })
+        .map_err(|e| format!("send failed: {e}"))?;
+    let frame = actions
+        .into_iter()
+        .find_map(|action| match action {
+            ClientAction::Send(frame) if frame.header.opcode_enum() == Some(Opcode::AppMessage) => {
+                Some(frame)
+            },
+            _ => None,
+        })
+        .ok_or("no AppMessage frame")?;
+
+    let actions = cluster.clients[BOB]
+        .handle(ClientEvent::FrameReceived(frame.clone()))
+        .map_err(|e| format!("receive failed: {e}"))?;
+    if !delivered(&actions) {
+        return Err("Bob did not receive the genuine message".to_string());
+    }
+
+    byzantine.capture(&frame);
+    Ok(frame)
+}
+
+fn delivered(actions: &[ClientAction]) -> bool {
+    actions.iter().any(|action| matches!(action, ClientAction::DeliverMessage { .. }))
+}
+
+fn assert_converged(cluster: &TestCluster) {
+    let epochs = cluster.epochs(ROOM_ID);
+    assert_eq!(epochs.len(), 2);
+    assert_eq!(epochs[0].1, epochs[1].1, "epochs diverged: {epochs:?}");
+}
+
+#[test]
+fn replayed_ciphertext_is_detected_not_delivered() {
+    let mut cluster = cluster().unwrap();
+    let mut byzantine = ByzantineClient::new(99);
+    let frame = alice_sends(&mut cluster, &mut byzantine).unwrap();
+
+    let replay = byzantine.replay(0, frame.header.log_index() + 1).unwrap();
+    let actions = cluster.clients[BOB].handle(ClientEvent::FrameReceived(replay)).unwrap();
+
+    let alice_id = cluster.clients[ALICE].sender_id();
+    assert!(actions.iter().any(|action| matches!(
+        action,
+        ClientAction::ReplayDetected { sender_id, .. } if *sender_id == alice_id
+    )));
+    assert!(!delivered(&actions));
+    assert_converged(&cluster);
+}
+
+#[test]
+fn stale_epoch_frame_is_not_delivered() {
+    let mut cluster = cluster().unwrap();
+    let mut byzantine = ByzantineClient::new(99);
+    let frame = alice_sends(&mut cluster, &mut byzantine).unwrap();
+    let epoch = frame.header.epoch();
+    assert!(epoch > 0, "joining moved the room past epoch 0");
+
+    let mut stale = byzantine.stale_epoch(&frame, epoch - 1);
+    stale.header.set_log_index(frame.header.log_index() + 1);
+    let actions =
+        cluster.clients[BOB].handle(ClientEvent::FrameReceived(stale)).unwrap_or_default();
+
+    assert!(!delivered(&actions));
+    assert_eq!(cluster.clients[BOB].epoch(ROOM_ID), Some(epoch));
+    assert_converged(&cluster);
+}
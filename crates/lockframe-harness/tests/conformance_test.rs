@@ -0,0 +1,10 @@
+//! Our own client against the conformance kit.
+
+use lockframe_harness::{ReferenceClient, run_conformance};
+
+#[test]
+fn reference_client_is_conformant() {
+    let report = run_conformance(ReferenceClient::new);
+
+    assert!(report.is_conformant(), "{report}");
+}
@@ -0,0 +1,62 @@
+//! Golden transcript tests.
+//!
+//! Each scenario is held to the frames it exchanged when its golden file was
+//! written, so any change in what the protocol sends shows up here as a diff.
+//! Run with `LOCKFRAME_UPDATE_GOLDEN=1` to rewrite the files after checking
+//! the change is intended.
+
+use std::{path::PathBuf, time::Duration};
+
+use lockframe_core::connection::DEFAULT_HEARTBEAT_INTERVAL;
+use lockframe_harness::scenario::{Actor, Fault, Scenario, Step};
+use lockframe_proto::{Frame, FrameHeader, Opcode};
+
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{name}.txt"))
+}
+
+fn ping() -> Frame {
+    Frame::new(FrameHeader::new(Opcode::Ping), Vec::new())
+}
+
+/// Handshake, then one ping answered once.
+#[test]
+fn ping_after_handshake() {
+    let result = Scenario::new()
+        .step(Step::Send { from: Actor::Client, frame: ping() })
+        .with_golden(golden("ping_after_handshake"))
+        .oracle(Box::new(|world| {
+            if world.all_authenticated() { Ok(()) } else { Err("not authenticated".into()) }
+        }))
+        .run();
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+/// Each heartbeat interval costs one ping and one pong per side.
+#[test]
+fn heartbeats_over_two_intervals() {
+    let result = Scenario::new()
+        .step(Step::AdvanceTime(DEFAULT_HEARTBEAT_INTERVAL))
+        .step(Step::AdvanceTime(DEFAULT_HEARTBEAT_INTERVAL))
+        .with_golden(golden("heartbeats_over_two_intervals"))
+        .oracle(Box::new(|_| Ok(())))
+        .run();
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+/// Duplicated frames show up in the transcript as repeated lines.
+#[test]
+fn duplicated_ping() {
+    let result = Scenario::new()
+        .step(Step::Inject(Fault::Duplicate { rate: 1.0 }))
+        .step(Step::Send { from: Actor::Client, frame: ping() })
+        .step(Step::Inject(Fault::Duplicate { rate: 0.0 }))
+        .step(Step::AdvanceTime(Duration::from_secs(1)))
+        .with_golden(golden("duplicated_ping"))
+        .oracle(Box::new(|_| Ok(())))
+        .run();
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
@@ -0,0 +1,49 @@
+//! Performance regression tests.
+//!
+//! Benchmarks run in virtual time under a fixed seed and topology, so their
+//! numbers only change when the server's sequencer or broadcast path does.
+//! The baselines leave headroom for small changes; a regression beyond them
+//! fails here. Set `LOCKFRAME_BENCH_DIR` to keep the JSON reports.
+
+use std::time::Duration;
+
+use lockframe_harness::{BenchReport, Benchmark, Topology};
+
+fn run(name: &str, topology: Topology) -> Result<BenchReport, String> {
+    let report = Benchmark::new(name).with_seed(42).with_topology(topology).run()?;
+    report.save()?;
+    Ok(report)
+}
+
+/// Under light load each message costs one write and its delivery waits
+/// for little more than the network.
+#[test]
+fn light_load_stays_at_network_latency() {
+    let topology = Topology::default();
+    let report = run("light", topology.clone()).unwrap();
+
+    assert_eq!(report.frames_routed, report.messages_sent * topology.members_per_room);
+    assert!(report.storage.writes_per_message <= 1.0, "{:?}", report.storage);
+    assert!(report.storage.reads_per_message <= 0.01, "{:?}", report.storage);
+
+    let latency = report.latency.unwrap();
+    assert!(latency.p99 <= 2 * topology.link_latency + Duration::from_millis(1), "{latency}");
+}
+
+/// With every message sent at once the server is the bottleneck, so
+/// throughput measures the cost of routing a frame.
+#[test]
+fn saturated_server_throughput() {
+    let topology = Topology {
+        messages_per_member: 50,
+        send_interval: Duration::ZERO,
+        jitter: Duration::from_millis(1),
+        ..Topology::default()
+    };
+    let report = run("saturated", topology).unwrap();
+
+    // Measured at 35.5k frames/s and 356ms p99
+    assert!(report.frames_per_second >= 32_000.0, "{}", report.frames_per_second);
+    let latency = report.latency.unwrap();
+    assert!(latency.p99 <= Duration::from_millis(400), "{latency}");
+}
@@ -0,0 +1,264 @@
+//! Property tests for the sender keys layer under adversarial schedules.
+//!
+//! Several senders encrypt with their `SymmetricRatchet` while a single
+//! receiver decrypts whatever the network hands it. Generated schedules
+//! interleave sends, counter skips (messages the sender never delivers),
+//! out-of-order deliveries, replays and epoch rotations. A reference model
+//! predicts what the receiver must accept:
+//!
+//! 1. Round-trip: an accepted message decrypts to exactly what was sent
+//! 2. Skip limit: a message more than `MAX_SKIP` generations ahead is rejected
+//!    and leaves the receiver's ratchet where it was
+//! 3. Forward secrecy: a message behind the receiver's ratchet, or from another
+//!    epoch, is rejected
+//! 4. No key reuse: no message key encrypts two distinct messages, and no
+//!    generation is ever decrypted twice
+
+use std::collections::{HashMap, HashSet};
+
+use lockframe_crypto::{
+    EncryptedMessage, MAX_SKIP, NONCE_RANDOM_SIZE, SenderKeyError, SymmetricRatchet,
+    decrypt_message, derive_sender_key_seed, encrypt_message,
+};
+use proptest::prelude::*;
+
+const SENDERS: u32 = 3;
+
+/// One step of a schedule.
+#[derive(Debug, Clone)]
+enum Op {
+    /// A sender encrypts and sends a message.
+    Send { sender: u32, payload: Vec<u8> },
+    /// A sender burns generations without sending anything.
+    Skip { sender: u32, count: u32 },
+    /// The network delivers one of the messages in flight.
+    Deliver { pick: usize },
+    /// The network delivers an already delivered message again.
+    Replay { pick: usize },
+    /// A commit moves everyone to the next epoch.
+    Rotate,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let sender = 0..SENDERS;
+    prop_oneof![
+        4 => (sender.clone(), prop::collection::vec(any::<u8>(), 0..64))
+            .prop_map(|(sender, payload)| Op::Send { sender, payload }),
+        1 => (sender.clone(), 0..=4u32).prop_map(|(sender, count)| Op::Skip { sender, count }),
+        // Rare, since each generation skipped costs an HMAC
+        1 => (sender, MAX_SKIP - 2..=MAX_SKIP + 2)
+            .prop_map(|(sender, count)| Op::Skip { sender, count }),
+        4 => any::<usize>().prop_map(|pick| Op::Deliver { pick }),
+        1 => any::<usize>().prop_map(|pick| Op::Replay { pick }),
+        1 => Just(Op::Rotate),
+    ]
+}
+
+/// A message on the wire and what it should decrypt to.
+struct InFlight {
+    message: EncryptedMessage,
+    plaintext: Vec<u8>,
+}
+
+/// Ratchets of every sender for one epoch.
+fn epoch_ratchets(epoch: u64) -> HashMap<u32, SymmetricRatchet> {
+    (0..SENDERS)
+        .map(|sender| {
+            let seed = derive_sender_key_seed(b"epoch secret", epoch, sender);
+            (sender, SymmetricRatchet::new(&seed))
+        })
+        .collect()
+}
+
+/// Senders, the receiver and the network between them.
+struct Room {
+    epoch: u64,
+    senders: HashMap<u32, SymmetricRatchet>,
+    receiver: HashMap<u32, SymmetricRatchet>,
+    in_flight: Vec<InFlight>,
+    delivered: Vec<InFlight>,
+    /// Every message key a sender has encrypted with.
+    keys_used: HashSet<[u8; 32]>,
+    /// Every `(epoch, sender, generation)` the receiver decrypted.
+    decrypted: HashSet<(u64, u32, u32)>,
+    next_nonce: u64,
+}
+
+impl Room {
+    fn new() -> Self {
+        Self {
+            epoch: 0,
+            senders: epoch_ratchets(0),
+            receiver: epoch_ratchets(0),
+            in_flight: Vec::new(),
+            delivered: Vec::new(),
+            keys_used: HashSet::new(),
+            decrypted: HashSet::new(),
+            next_nonce: 0,
+        }
+    }
+
+    fn apply(&mut self, op: Op) -> Result<(), TestCaseError> {
+        match op {
+            Op::Send { sender, payload } => self.send(sender, payload),
+            Op::Skip { sender, count } => {
+                let ratchet = self.sender(sender)?;
+                for _ in 0..count {
+                    ratchet.advance().map_err(|e| TestCaseError::fail(e.to_string()))?;
+                }
+                Ok(())
+            },
+            Op::Deliver { pick } => {
+                if self.in_flight.is_empty() {
+                    return Ok(());
+                }
+                let in_flight = self.in_flight.remove(pick % self.in_flight.len());
+                // A message rejected for being too far ahead is not a replay
+                // when delivered again, so only accepted ones are kept
+                if self.deliver(&in_flight)? {
+                    self.delivered.push(in_flight);
+                }
+                Ok(())
+            },
+            Op::Replay { pick } => {
+                if self.delivered.is_empty() {
+                    return Ok(());
+                }
+                let index = pick % self.delivered.len();
+                let message = self.delivered[index].message.clone();
+                let result = self.receive(&message);
+                prop_assert!(result.is_err(), "replay of {message:?} was accepted");
+                Ok(())
+            },
+            Op::Rotate => {
+                self.epoch += 1;
+                self.senders = epoch_ratchets(self.epoch);
+                self.receiver = epoch_ratchets(self.epoch);
+                Ok(())
+            },
+        }
+    }
+
+    fn sender(&mut self, sender: u32) -> Result<&mut SymmetricRatchet, TestCaseError> {
+        self.senders
+            .get_mut(&sender)
+            .ok_or_else(|| TestCaseError::fail(format!("no ratchet for sender {sender}")))
+    }
+
+    fn send(&mut self, sender: u32, payload: Vec<u8>) -> Result<(), TestCaseError> {
+        let epoch = self.epoch;
+        let nonce = self.next_nonce.to_le_bytes();
+        self.next_nonce += 1;
+
+        let key = self.sender(sender)?.advance().map_err(|e| TestCaseError::fail(e.to_string()))?;
+        prop_assert!(self.keys_used.insert(*key.key()), "message key reused: {epoch}/{sender}");
+
+        let mut random = [0u8; NONCE_RANDOM_SIZE];
+        random.copy_from_slice(&nonce[..NONCE_RANDOM_SIZE]);
+        let message = encrypt_message(&payload, &key, epoch, sender, random);
+        self.in_flight.push(InFlight { message, plaintext: payload });
+        Ok(())
+    }
+
+    /// Decrypt as the receiver would: the sender's ratchet for the current
+    /// epoch, advanced to the message's generation.
+    fn receive(&mut self, message: &EncryptedMessage) -> Result<Vec<u8>, SenderKeyError> {
+        if message.epoch != self.epoch {
+            return Err(SenderKeyError::EpochMismatch {
+                expected: self.epoch,
+                actual: message.epoch,
+            });
+        }
+        let ratchet = self
+            .receiver
+            .get_mut(&message.sender_index)
+            .ok_or(SenderKeyError::UnknownSender { sender_index: message.sender_index })?;
+        let key = ratchet.advance_to(message.generation)?;
+        decrypt_message(message, &key)
+    }
+
+    /// Deliver a message, returning whether the receiver accepted it.
+    fn deliver(&mut self, in_flight: &InFlight) -> Result<bool, TestCaseError> {
+        let message = &in_flight.message;
+        let before = self.receiver.get(&message.sender_index).map(SymmetricRatchet::generation);
+        let result = self.receive(message);
+        let after = self.receiver.get(&message.sender_index).map(SymmetricRatchet::generation);
+
+        let expected = match before {
+            _ if message.epoch != self.epoch => Expect::Reject,
+            Some(at) if message.generation < at => Expect::Reject,
+            Some(at) if message.generation - at > MAX_SKIP => Expect::RejectUnchanged,
+            Some(_) => Expect::Accept,
+            None => Expect::Reject,
+        };
+
+        match (expected, result) {
+            (Expect::Accept, Ok(plaintext)) => {
+                prop_assert_eq!(&plaintext, &in_flight.plaintext);
+                prop_assert_eq!(after, Some(message.generation + 1));
+                let id = (message.epoch, message.sender_index, message.generation);
+                prop_assert!(self.decrypted.insert(id), "generation decrypted twice: {id:?}");
+                return Ok(true);
+            },
+            (Expect::Accept, Err(e)) => {
+                return Err(TestCaseError::fail(format!("in-order message rejected: {e}")));
+            },
+            (Expect::RejectUnchanged, result) => {
+                prop_assert!(
+                    matches!(result, Err(SenderKeyError::RatchetTooFarBehind { .. })),
+                    "message {} generations ahead: {result:?}",
+                    message.generation - before.unwrap_or_default()
+                );
+                prop_assert_eq!(after, before, "rejected skip moved the ratchet");
+            },
+            (Expect::Reject, result) => {
+                prop_assert!(result.is_err(), "stale message {message:?} was accepted");
+                prop_assert_eq!(after, before, "rejected message moved the ratchet");
+            },
+        }
+        Ok(false)
+    }
+}
+
+/// What the receiver must do with a delivered message.
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    Accept,
+    Reject,
+    /// Too far ahead: rejected without advancing the ratchet.
+    RejectUnchanged,
+}
+
+fn run(ops: Vec<Op>) -> Result<(), TestCaseError> {
+    let mut room = Room::new();
+    for op in ops {
+        room.apply(op)?;
+    }
+    // Whatever is left in flight is dropped; every key was still used once
+    prop_assert_eq!(room.keys_used.len() as u64, room.next_nonce);
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(100))]
+
+    /// Arbitrary interleavings of sends, skips, reordering, replays and
+    /// rotations keep the receiver consistent with the model.
+    #[test]
+    fn prop_ratchet_schedules_match_model(ops in prop::collection::vec(op(), 0..60)) {
+        run(ops)?;
+    }
+
+    /// Delivering a window of messages in any order accepts the newest of
+    /// those seen so far and never decrypts a message twice.
+    #[test]
+    fn prop_reordered_window(
+        sends in 1..20usize,
+        picks in prop::collection::vec(any::<usize>(), 20),
+    ) {
+        let mut ops: Vec<Op> =
+            (0..sends).map(|i| Op::Send { sender: 0, payload: vec![i as u8] }).collect();
+        ops.extend(picks.into_iter().map(|pick| Op::Deliver { pick }));
+        run(ops)?;
+    }
+}
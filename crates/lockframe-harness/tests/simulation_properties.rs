@@ -1,15 +1,16 @@
 //! Property-based tests for simulation framework determinism
 //!
 //! These tests verify that the scenario/simulation framework produces
-//! deterministic results across multiple runs with the same inputs.
+//! deterministic results across multiple runs with the same inputs, and
+//! measure how scenarios fare across many seeds.
 
 use std::{
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use lockframe_core::connection::ConnectionState;
-use lockframe_harness::scenario::Scenario;
+use lockframe_core::connection::{ConnectionState, DEFAULT_HEARTBEAT_INTERVAL};
+use lockframe_harness::scenario::{Fault, RunResult, Scenario, SeedRunner, Step};
 use proptest::prelude::*;
 
 /// Captured state from a scenario run
@@ -70,3 +71,47 @@ fn prop_all_simulations_deterministic() {
         );
     });
 }
+
+/// Keep a session open for ten minutes of heartbeats under 2% frame loss,
+/// returning the gaps between the times the client heard from the server.
+fn heartbeats_under_loss(seed: u64) -> RunResult {
+    // (frames the client had received, time since it last received one)
+    let state = Arc::new(Mutex::new((0, Duration::ZERO)));
+    let gaps = Arc::new(Mutex::new(Vec::new()));
+
+    let mut scenario =
+        Scenario::new().with_seed(seed).step(Step::Inject(Fault::Loss { rate: 0.02 }));
+    for _ in 0..30 {
+        let state = Arc::clone(&state);
+        let gaps = Arc::clone(&gaps);
+        scenario = scenario.step(Step::AdvanceTime(DEFAULT_HEARTBEAT_INTERVAL)).step(
+            Step::CheckInvariant(Box::new(move |world| {
+                let mut state = state.lock().map_err(|e| e.to_string())?;
+                state.1 += DEFAULT_HEARTBEAT_INTERVAL;
+                if world.client_frames_received() > state.0 {
+                    gaps.lock().map_err(|e| e.to_string())?.push(state.1);
+                    *state = (world.client_frames_received(), Duration::ZERO);
+                }
+                Ok(())
+            })),
+        );
+    }
+
+    scenario
+        .oracle(Box::new(|world| {
+            if world.all_authenticated() { Ok(()) } else { Err("session closed".to_string()) }
+        }))
+        .run()?;
+    let gaps = gaps.lock().map_err(|e| e.to_string())?.clone();
+    Ok(gaps)
+}
+
+#[test]
+fn heartbeats_survive_loss_across_seeds() {
+    let report = SeedRunner::new(0..200).run(heartbeats_under_loss);
+    report.log();
+
+    assert_eq!(report.failed(), 0, "{report}");
+    let latency = report.latency().expect("every run hears from the server");
+    assert_eq!(latency.p50, DEFAULT_HEARTBEAT_INTERVAL, "{report}");
+}
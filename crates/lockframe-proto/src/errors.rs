@@ -2,8 +2,44 @@
 //!
 //! All errors are structured, testable, and provide actionable information.
 
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// Short ID tagging a failure where it first happened.
+///
+/// The side that fails logs the ID and sends it along with the error, so an
+/// error shown as "send failed (err 7f3a)" can be found in the other side's
+/// logs. IDs are random and only 16 bits: they tell failures apart within a
+/// session's logs, not globally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ErrorId(u16);
+
+impl ErrorId {
+    /// Wrap a raw ID.
+    pub const fn new(id: u16) -> Self {
+        Self(id)
+    }
+
+    /// ID from caller-provided random bytes.
+    pub const fn from_random(bytes: [u8; 2]) -> Self {
+        Self(u16::from_be_bytes(bytes))
+    }
+
+    /// Raw ID.
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for ErrorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}", self.0)
+    }
+}
+
 /// Protocol-level errors that can occur during frame parsing and validation.
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum ProtocolError {
@@ -69,6 +105,46 @@ pub enum ProtocolError {
     /// Invalid flag combination
     #[error("invalid flags: {0:#04x}")]
     InvalidFlags(u8),
+
+    /// Epoch beyond the highest a group can reach
+    #[error("epoch {epoch} exceeds maximum {max}")]
+    EpochOutOfRange {
+        /// Epoch in the header
+        epoch: u64,
+        /// Highest valid epoch
+        max: u64,
+    },
+
+    /// Header field not carried by the frame's opcode
+    #[error("{field} is not carried by opcode {opcode:#06x}")]
+    WrongContextField {
+        /// Field accessed
+        field: &'static str,
+        /// Opcode of the frame
+        opcode: u16,
+    },
+
+    // Peer errors
+    /// Peer answered with an Error frame
+    #[error("rejected by peer ({code:#06x}): {message}")]
+    Rejected {
+        /// Error code from the Error frame
+        code: u16,
+        /// Peer's description of the failure
+        message: String,
+        /// ID the peer logged the failure under, if it sent one
+        error_id: Option<ErrorId>,
+    },
+}
+
+impl ProtocolError {
+    /// ID the failure was logged under by the peer, if known.
+    pub fn error_id(&self) -> Option<ErrorId> {
+        match self {
+            Self::Rejected { error_id, .. } => *error_id,
+            _ => None,
+        }
+    }
 }
 
 /// Convenient Result type alias for protocol operations
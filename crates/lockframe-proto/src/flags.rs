@@ -32,12 +32,17 @@ bitflags! {
         /// Can be redacted by moderators
         const REDACTABLE = 0b0100_0000;
 
-        /// Reserved for future use
-        const RESERVED = 0b1000_0000;
+        /// `request_id` is an idempotency key: a repeat from the same sender
+        /// in the same room is a retry, not a new frame
+        const IDEMPOTENT = 0b1000_0000;
     }
 }
 
 impl FrameFlags {
+    /// Flags no implementation decodes yet. A frame carrying them would be
+    /// misread by every receiver, so headers with them do not validate.
+    pub const UNSUPPORTED: Self = Self::COMPRESSED.union(Self::FRAGMENTED);
+
     /// Create flags from raw byte value
     ///
     /// This function is infallible because `bitflags` represents flags as a
@@ -82,7 +82,7 @@ impl Frame {
             "invariant: payload length fits in u32 (bounded by isize::MAX and protocol limit)",
         );
 
-        header.payload_size = payload_len.to_be_bytes();
+        header.set_payload_size(payload_len);
 
         debug_assert_eq!(header.payload_size(), payload_len);
 
@@ -220,12 +220,7 @@ mod tests {
 
     #[test]
     fn frame_with_payload() {
-        // Create valid header
-        let mut bytes = [0u8; 128];
-        bytes[0..4].copy_from_slice(&FrameHeader::MAGIC.to_be_bytes());
-        bytes[4] = FrameHeader::VERSION;
-        let mut header = *FrameHeader::from_bytes(&bytes).unwrap();
-        header.opcode = Opcode::Ping.to_u16().to_be_bytes();
+        let header = FrameHeader::new(Opcode::Ping);
 
         // Create frame (payload_size set automatically)
         let payload_bytes = vec![1, 2, 3, 4];
@@ -245,12 +240,8 @@ mod tests {
     #[test]
     fn reject_truncated_frame() {
         // Create header claiming 100 bytes of payload
-        let mut bytes = [0u8; 128];
-        bytes[0..4].copy_from_slice(&FrameHeader::MAGIC.to_be_bytes());
-        bytes[4] = FrameHeader::VERSION;
-
-        let mut header = *FrameHeader::from_bytes(&bytes).unwrap();
-        header.payload_size = 100u32.to_be_bytes();
+        let mut header = FrameHeader::new(Opcode::Ping);
+        header.set_payload_size(100);
 
         let header_bytes = header.to_bytes();
 
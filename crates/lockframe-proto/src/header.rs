@@ -29,8 +29,7 @@ use crate::{
 /// valid, preventing undefined behavior. The signature field binds the entire
 /// header to an MLS epoch. Verification happens separately after parsing to
 /// allow routing before authentication. The `log_index` provides a monotonic
-/// sequence number   per room. Combined with the `hlc_timestamp`, this prevents
-/// replay attacks.
+/// sequence number per room, which prevents replay attacks.
 ///
 /// - Epoch Isolation: The `epoch` field ensures frames cannot be replayed
 ///   across different MLS group generations, even if the signature verifies.
@@ -40,14 +39,14 @@ pub struct FrameHeader {
     // CACHE LINE 1: Routing/Sequencing (bytes 0-63)---
 
     // Protocol identification (8 bytes: 0-7)
-    magic: [u8; 4],             // 0x4C4F4652 ("LOFR" in ASCII)
-    version: u8,                // 0x01
-    flags: u8,                  // FrameFlags bitfield
-    pub(crate) opcode: [u8; 2], // u16 operation code
+    magic: [u8; 4],  // 0x4C4F4652 ("LOFR" in ASCII)
+    version: u8,     // 0x01
+    flags: u8,       // FrameFlags bitfield
+    opcode: [u8; 2], // u16 operation code
 
     // Request/payload metadata (8 bytes: 8-15)
-    request_id: [u8; 4], // u32 client nonce (4B concurrent requests sufficient)
-    pub(crate) payload_size: [u8; 4], // u32 payload length (moved for alignment)
+    request_id: [u8; 4],   // u32 client nonce (4B concurrent requests sufficient)
+    payload_size: [u8; 4], // u32 payload length (moved for alignment)
 
     // Routing context (24 bytes: 16-39)
     room_id: [u8; 16],  // UUID (128-bit)
@@ -58,7 +57,7 @@ pub struct FrameHeader {
     //   - Sequenced frames (AppMessage, Commit, Proposal): log_index (sequence number)
     //   - Welcome frames: recipient_id (target member for routing)
     context_id: [u8; 8],
-    hlc_timestamp: [u8; 8], // u64 hybrid logical clock
+    expires_at: [u8; 8], // u64 Unix seconds the frame may be deleted at (0 = never)
 
     // MLS binding (8 bytes: 56-63)
     epoch: [u8; 8], // u64 MLS epoch (uniquely identifies key generation)
@@ -83,6 +82,9 @@ impl FrameHeader {
     /// Maximum payload size (16 MB)
     pub const MAX_PAYLOAD_SIZE: u32 = 16 * 1024 * 1024;
 
+    /// Highest MLS epoch a header may carry
+    pub const MAX_EPOCH: u64 = 1_000_000;
+
     /// Create a new header with the specified opcode.
     #[must_use]
     pub fn new(opcode: Opcode) -> Self {
@@ -142,13 +144,7 @@ impl FrameHeader {
             return Err(ProtocolError::UnsupportedVersion(header.version));
         }
 
-        let payload_size = u32::from_be_bytes(header.payload_size);
-        if payload_size > Self::MAX_PAYLOAD_SIZE {
-            return Err(ProtocolError::PayloadTooLarge {
-                size: payload_size as usize,
-                max: Self::MAX_PAYLOAD_SIZE as usize,
-            });
-        }
+        Self::check_payload_size(header.payload_size())?;
 
         Ok(header)
     }
@@ -242,10 +238,19 @@ impl FrameHeader {
         u64::from_be_bytes(self.context_id)
     }
 
-    /// Hybrid Logical Clock timestamp for causality and replay protection.
+    /// Unix time (seconds) after which the frame is deleted from server
+    /// storage and receivers drop its content. `None` if it never expires.
+    ///
+    /// Covered by the sender's signature, so the server cannot extend it.
+    #[must_use]
+    pub fn expires_at(&self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.expires_at)).filter(|&secs| secs != 0)
+    }
+
+    /// Whether the frame has expired at `now_secs` (Unix seconds).
     #[must_use]
-    pub fn hlc_timestamp(&self) -> u64 {
-        u64::from_be_bytes(self.hlc_timestamp)
+    pub fn is_expired(&self, now_secs: u64) -> bool {
+        self.expires_at().is_some_and(|expires_at| expires_at <= now_secs)
     }
 
     /// MLS epoch number (increments on membership changes).
@@ -266,6 +271,58 @@ impl FrameHeader {
         &self.signature
     }
 
+    /// Log index, or an error on a Welcome frame, which carries
+    /// `recipient_id` in its place.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::WrongContextField` if the frame is a Welcome
+    pub fn try_log_index(&self) -> Result<u64> {
+        self.check_context_field("log_index", false)?;
+        Ok(u64::from_be_bytes(self.context_id))
+    }
+
+    /// Welcome recipient, or an error on any other frame, which carries
+    /// `log_index` in its place.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::WrongContextField` if the frame is not a Welcome
+    pub fn try_recipient_id(&self) -> Result<u64> {
+        self.check_context_field("recipient_id", true)?;
+        Ok(u64::from_be_bytes(self.context_id))
+    }
+
+    /// Check for every out-of-range value the header can carry.
+    ///
+    /// [`Self::from_bytes`] only checks what is needed to find the payload;
+    /// this also rejects unknown opcodes, epochs beyond
+    /// [`Self::MAX_EPOCH`] and [`FrameFlags::UNSUPPORTED`] flags. The server
+    /// calls it before routing a frame.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::InvalidMagic` if magic number is invalid
+    /// - `ProtocolError::UnsupportedVersion` if protocol version is unsupported
+    /// - `ProtocolError::InvalidOpcode` if the opcode is unknown
+    /// - `ProtocolError::PayloadTooLarge` if payload size exceeds maximum
+    /// - `ProtocolError::EpochOutOfRange` if the epoch exceeds maximum
+    /// - `ProtocolError::InvalidFlags` if unsupported flags are set
+    pub fn validate(&self) -> Result<()> {
+        if self.magic() != Self::MAGIC {
+            return Err(ProtocolError::InvalidMagic);
+        }
+        if self.version != Self::VERSION {
+            return Err(ProtocolError::UnsupportedVersion(self.version));
+        }
+        if self.opcode_enum().is_none() {
+            return Err(ProtocolError::InvalidOpcode(self.opcode()));
+        }
+        Self::check_payload_size(self.payload_size())?;
+        Self::check_epoch(self.epoch())?;
+        Self::check_flags(self.flags())
+    }
+
     /// Bytes to sign (excludes mutable `context_id` and signature itself).
     ///
     /// Returns bytes 0-39 + 48-63 (56 bytes total).
@@ -278,6 +335,11 @@ impl FrameHeader {
         data
     }
 
+    /// Update operation code.
+    pub fn set_opcode(&mut self, opcode: Opcode) {
+        self.opcode = opcode.to_u16().to_be_bytes();
+    }
+
     /// Update room UUID.
     pub fn set_room_id(&mut self, room_id: u128) {
         self.room_id = room_id.to_be_bytes();
@@ -307,6 +369,28 @@ impl FrameHeader {
         self.context_id = recipient_id.to_be_bytes();
     }
 
+    /// Assign log index, or fail on a Welcome frame.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::WrongContextField` if the frame is a Welcome
+    pub fn try_set_log_index(&mut self, log_index: u64) -> Result<()> {
+        self.check_context_field("log_index", false)?;
+        self.context_id = log_index.to_be_bytes();
+        Ok(())
+    }
+
+    /// Set the Welcome routing target, or fail on any other frame.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::WrongContextField` if the frame is not a Welcome
+    pub fn try_set_recipient_id(&mut self, recipient_id: u64) -> Result<()> {
+        self.check_context_field("recipient_id", true)?;
+        self.context_id = recipient_id.to_be_bytes();
+        Ok(())
+    }
+
     /// Update sender identifier.
     pub fn set_sender_id(&mut self, sender_id: u64) {
         self.sender_id = sender_id.to_be_bytes();
@@ -317,6 +401,17 @@ impl FrameHeader {
         self.epoch = epoch.to_be_bytes();
     }
 
+    /// Update MLS epoch, rejecting epochs beyond [`Self::MAX_EPOCH`].
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::EpochOutOfRange` if the epoch exceeds maximum
+    pub fn try_set_epoch(&mut self, epoch: u64) -> Result<()> {
+        Self::check_epoch(epoch)?;
+        self.set_epoch(epoch);
+        Ok(())
+    }
+
     /// Set Ed25519 signature (computed over [`Self::signing_data()`]).
     pub fn set_signature(&mut self, signature: [u8; 64]) {
         self.signature = signature;
@@ -327,20 +422,91 @@ impl FrameHeader {
         self.request_id = request_id.to_be_bytes();
     }
 
+    /// Client-chosen key identifying a send across retries.
+    ///
+    /// `Some(request_id)` when the `IDEMPOTENT` flag is set.
+    #[must_use]
+    pub fn idempotency_key(&self) -> Option<u32> {
+        self.flags().contains(FrameFlags::IDEMPOTENT).then(|| self.request_id())
+    }
+
+    /// Mark the frame as retry-safe under `key`, stored in `request_id`.
+    pub fn set_idempotency_key(&mut self, key: u32) {
+        self.set_request_id(key);
+        self.set_flags(self.flags() | FrameFlags::IDEMPOTENT);
+    }
+
     /// Update frame processing flags.
     pub fn set_flags(&mut self, flags: FrameFlags) {
         self.flags = flags.to_byte();
     }
 
-    /// Set HLC timestamp for causality tracking.
-    pub fn set_hlc_timestamp(&mut self, timestamp: u64) {
-        self.hlc_timestamp = timestamp.to_be_bytes();
+    /// Update frame processing flags, rejecting
+    /// [`FrameFlags::UNSUPPORTED`] ones.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::InvalidFlags` if unsupported flags are set
+    pub fn try_set_flags(&mut self, flags: FrameFlags) -> Result<()> {
+        Self::check_flags(flags)?;
+        self.set_flags(flags);
+        Ok(())
+    }
+
+    /// Set the expiry time (Unix seconds), or clear it with `None`. Must be
+    /// set before signing.
+    pub fn set_expires_at(&mut self, expires_at: Option<u64>) {
+        self.expires_at = expires_at.unwrap_or(0).to_be_bytes();
     }
 
     /// Set payload size (must be set before signing if signature is used).
     pub fn set_payload_size(&mut self, size: u32) {
         self.payload_size = size.to_be_bytes();
     }
+
+    /// Set payload size, rejecting sizes beyond [`Self::MAX_PAYLOAD_SIZE`].
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::PayloadTooLarge` if the size exceeds maximum
+    pub fn try_set_payload_size(&mut self, size: u32) -> Result<()> {
+        Self::check_payload_size(size)?;
+        self.set_payload_size(size);
+        Ok(())
+    }
+
+    /// Whether `context_id` holds `field`: `recipient_id` for Welcome,
+    /// `log_index` for everything else.
+    fn check_context_field(&self, field: &'static str, welcome: bool) -> Result<()> {
+        if (self.opcode_enum() == Some(Opcode::Welcome)) == welcome {
+            return Ok(());
+        }
+        Err(ProtocolError::WrongContextField { field, opcode: self.opcode() })
+    }
+
+    fn check_payload_size(size: u32) -> Result<()> {
+        if size > Self::MAX_PAYLOAD_SIZE {
+            return Err(ProtocolError::PayloadTooLarge {
+                size: size as usize,
+                max: Self::MAX_PAYLOAD_SIZE as usize,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_epoch(epoch: u64) -> Result<()> {
+        if epoch > Self::MAX_EPOCH {
+            return Err(ProtocolError::EpochOutOfRange { epoch, max: Self::MAX_EPOCH });
+        }
+        Ok(())
+    }
+
+    fn check_flags(flags: FrameFlags) -> Result<()> {
+        if flags.intersects(FrameFlags::UNSUPPORTED) {
+            return Err(ProtocolError::InvalidFlags(flags.to_byte()));
+        }
+        Ok(())
+    }
 }
 
 // Manual Debug implementation (can't derive due to packed repr)
@@ -359,7 +525,7 @@ impl std::fmt::Debug for FrameHeader {
             .field("room_id", &format!("{:#034x}", self.room_id()))
             .field("sender_id", &self.sender_id())
             .field(context_label, &context_value)
-            .field("hlc_timestamp", &self.hlc_timestamp())
+            .field("expires_at", &self.expires_at())
             .field("epoch", &self.epoch())
             .field("payload_size", &self.payload_size())
             .finish_non_exhaustive()
@@ -401,7 +567,7 @@ mod tests {
                 arbitrary_bytes::<16>(),       // room_id
                 arbitrary_bytes::<8>(),        // sender_id
                 arbitrary_bytes::<8>(),        // context_id
-                arbitrary_bytes::<8>(),        // hlc_timestamp
+                arbitrary_bytes::<8>(),        // expires_at
                 arbitrary_bytes::<8>(),        // epoch
                 0u32..=Self::MAX_PAYLOAD_SIZE, // payload_size
                 arbitrary_bytes::<64>(),       // signature
@@ -414,7 +580,7 @@ mod tests {
                         room_id,
                         sender_id,
                         context_id,
-                        hlc_timestamp,
+                        expires_at,
                         epoch,
                         payload_size,
                         signature,
@@ -429,7 +595,7 @@ mod tests {
                             room_id,
                             sender_id,
                             context_id,
-                            hlc_timestamp,
+                            expires_at,
                             epoch,
                             signature,
                         }
@@ -462,6 +628,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn idempotency_key_requires_flag() {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_request_id(7);
+        assert_eq!(header.idempotency_key(), None);
+
+        header.set_flags(FrameFlags::PRIORITY);
+        header.set_idempotency_key(42);
+        assert_eq!(header.idempotency_key(), Some(42));
+        assert!(header.flags().contains(FrameFlags::PRIORITY));
+    }
+
+    #[test]
+    fn expiry_is_signed_and_optional() {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        assert_eq!(header.expires_at(), None);
+        assert!(!header.is_expired(u64::MAX));
+
+        let unsigned = header.signing_data();
+        header.set_expires_at(Some(1_000));
+        assert_eq!(header.expires_at(), Some(1_000));
+        assert!(!header.is_expired(999));
+        assert!(header.is_expired(1_000));
+        assert_ne!(header.signing_data(), unsigned);
+
+        header.set_expires_at(None);
+        assert_eq!(header.expires_at(), None);
+    }
+
     #[test]
     fn reject_short_buffer() {
         let short_buf = [0u8; 100];
@@ -491,15 +686,82 @@ mod tests {
 
     #[test]
     fn reject_oversized_payload() {
-        let mut buf = [0u8; 128];
-        buf[0..4].copy_from_slice(&FrameHeader::MAGIC.to_be_bytes());
-        buf[4] = FrameHeader::VERSION;
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_payload_size(FrameHeader::MAX_PAYLOAD_SIZE + 1);
 
-        // Set payload_size to exceed maximum (at offset 12-15)
-        let oversized = FrameHeader::MAX_PAYLOAD_SIZE + 1;
-        buf[12..16].copy_from_slice(&oversized.to_be_bytes());
-
-        let result = FrameHeader::from_bytes(&buf);
+        let bytes = header.to_bytes();
+        let result = FrameHeader::from_bytes(&bytes);
         assert!(matches!(result, Err(ProtocolError::PayloadTooLarge { .. })));
     }
+
+    #[test]
+    fn context_field_follows_opcode() {
+        let mut message = FrameHeader::new(Opcode::AppMessage);
+        message.try_set_log_index(9).unwrap();
+        assert_eq!(message.try_log_index(), Ok(9));
+        assert_eq!(
+            message.try_recipient_id(),
+            Err(ProtocolError::WrongContextField {
+                field: "recipient_id",
+                opcode: Opcode::AppMessage.to_u16()
+            })
+        );
+
+        let mut welcome = FrameHeader::new(Opcode::Welcome);
+        welcome.try_set_recipient_id(4).unwrap();
+        assert_eq!(welcome.try_recipient_id(), Ok(4));
+        assert!(welcome.try_set_log_index(1).is_err());
+        assert_eq!(welcome.try_recipient_id(), Ok(4));
+    }
+
+    #[test]
+    fn checked_setters_reject_out_of_range_values() {
+        let mut header = FrameHeader::new(Opcode::Commit);
+
+        assert_eq!(
+            header.try_set_epoch(FrameHeader::MAX_EPOCH + 1),
+            Err(ProtocolError::EpochOutOfRange {
+                epoch: FrameHeader::MAX_EPOCH + 1,
+                max: FrameHeader::MAX_EPOCH
+            })
+        );
+        assert!(matches!(
+            header.try_set_payload_size(FrameHeader::MAX_PAYLOAD_SIZE + 1),
+            Err(ProtocolError::PayloadTooLarge { .. })
+        ));
+        assert_eq!(
+            header.try_set_flags(FrameFlags::COMPRESSED | FrameFlags::PRIORITY),
+            Err(ProtocolError::InvalidFlags(0b0000_0101))
+        );
+        assert_eq!(header, FrameHeader::new(Opcode::Commit), "rejected values were written");
+
+        header.try_set_epoch(FrameHeader::MAX_EPOCH).unwrap();
+        header.try_set_payload_size(FrameHeader::MAX_PAYLOAD_SIZE).unwrap();
+        header.try_set_flags(FrameFlags::PRIORITY).unwrap();
+        assert_eq!(header.epoch(), FrameHeader::MAX_EPOCH);
+        assert_eq!(header.payload_size(), FrameHeader::MAX_PAYLOAD_SIZE);
+        assert_eq!(header.flags(), FrameFlags::PRIORITY);
+    }
+
+    #[test]
+    fn validate_rejects_what_parsing_lets_through() {
+        let header = FrameHeader::new(Opcode::AppMessage);
+        assert_eq!(header.validate(), Ok(()));
+
+        let mut epoch = header;
+        epoch.set_epoch(FrameHeader::MAX_EPOCH + 1);
+        assert!(matches!(epoch.validate(), Err(ProtocolError::EpochOutOfRange { .. })));
+
+        let mut flags = header;
+        flags.set_flags(FrameFlags::FRAGMENTED);
+        assert_eq!(flags.validate(), Err(ProtocolError::InvalidFlags(0b0000_0010)));
+
+        let mut opcode = header;
+        opcode.opcode = 0xFFFFu16.to_be_bytes();
+        assert_eq!(opcode.validate(), Err(ProtocolError::InvalidOpcode(0xFFFF)));
+
+        for header in [epoch, flags, opcode] {
+            assert!(FrameHeader::from_bytes(&header.to_bytes()).is_ok());
+        }
+    }
 }
@@ -22,7 +22,7 @@ pub mod header;
 pub mod opcodes;
 pub mod payloads;
 
-pub use errors::{ProtocolError, Result};
+pub use errors::{ErrorId, ProtocolError, Result};
 pub use flags::FrameFlags;
 pub use frame::Frame;
 pub use header::FrameHeader;
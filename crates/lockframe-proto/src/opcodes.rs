@@ -13,6 +13,7 @@
 //! - `0x3000-0x3FFF`: Moderation (content/user management)
 //! - `0x4000-0x4FFF`: Federation (inter-server communication)
 //! - `0x5000-0x5FFF`: Storage (content-addressed storage)
+//! - `0x6000-0x6FFF`: Administration (operator control of a server)
 
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
@@ -46,6 +47,24 @@ pub enum Opcode {
     SyncRequest = 0x0006,
     /// Sync response with frames (server → client)
     SyncResponse = 0x0007,
+    /// Tombstone replacing history removed by retention (server → client)
+    HistoryTruncated = 0x0008,
+    /// Ask whether users are online (client → server)
+    PresenceQuery = 0x0009,
+    /// Online status of the queried users (server → client)
+    PresenceReply = 0x000A,
+    /// Frames queued for this session were dropped (server → client)
+    LaggedBehind = 0x000B,
+    /// List a room in the server's directory or remove it (client → server)
+    DirectoryPublish = 0x000C,
+    /// Search the room directory (client → server)
+    DirectorySearch = 0x000D,
+    /// One page of room directory results (server → client)
+    DirectoryResults = 0x000E,
+    /// Invitation to join a room, relayed to the invitee
+    Invite = 0x000F,
+    /// Message from the server's operator (server → client)
+    ServerNotice = 0x0010,
     /// Error frame
     Error = 0x00FF,
 
@@ -72,6 +91,8 @@ pub enum Opcode {
     KeyPackageFetch = 0x1009,
     /// Request `GroupInfo` for external join (client → server)
     GroupInfoRequest = 0x100A,
+    /// `KeyPackage` pool running low (server → client)
+    KeyPackageLowStock = 0x100B,
 
     // Application Messages (0x2000-0x2FFF)
     /// Encrypted application message
@@ -86,7 +107,7 @@ pub enum Opcode {
     AppDelete = 0x2004,
     /// Typing indicator
     Typing = 0x2005,
-    /// Presence/online status
+    /// Presence change of a room member (server → client)
     Presence = 0x2006,
 
     // Moderation (0x3000-0x3FFF)
@@ -102,8 +123,12 @@ pub enum Opcode {
     Mute = 0x3004,
     /// Pin message
     Pin = 0x3005,
-    /// Report content
+    /// Report a message for moderator review
     Report = 0x3006,
+    /// Change a member's room role
+    SetRole = 0x3007,
+    /// Close a room for good (owner only)
+    CloseRoom = 0x3008,
 
     // Federation (0x4000-0x4FFF)
     /// Federated log append
@@ -126,6 +151,12 @@ pub enum Opcode {
     CASDelete = 0x5002,
     /// Storage proof/attestation
     CASProof = 0x5003,
+
+    // Administration (0x6000-0x6FFF)
+    /// Operator request, accepted only on admin sessions
+    AdminRequest = 0x6000,
+    /// Reply to an operator request
+    AdminResponse = 0x6001,
 }
 
 impl Opcode {
@@ -135,6 +166,13 @@ impl Opcode {
         self as u16
     }
 
+    /// Whether this is an MLS operation (`0x1000-0x1FFF`), the frames a
+    /// client needs to advance its group state.
+    #[must_use]
+    pub const fn is_mls(self) -> bool {
+        self.to_u16() & 0xF000 == 0x1000
+    }
+
     /// Convert from raw u16 value
     ///
     /// Returns `None` if the value doesn't correspond to a known opcode.
@@ -158,6 +196,15 @@ impl Opcode {
             0x0005 => Some(Self::Pong),
             0x0006 => Some(Self::SyncRequest),
             0x0007 => Some(Self::SyncResponse),
+            0x0008 => Some(Self::HistoryTruncated),
+            0x0009 => Some(Self::PresenceQuery),
+            0x000A => Some(Self::PresenceReply),
+            0x000B => Some(Self::LaggedBehind),
+            0x000C => Some(Self::DirectoryPublish),
+            0x000D => Some(Self::DirectorySearch),
+            0x000E => Some(Self::DirectoryResults),
+            0x000F => Some(Self::Invite),
+            0x0010 => Some(Self::ServerNotice),
             0x00FF => Some(Self::Error),
 
             0x1000 => Some(Self::KeyPackage),
@@ -171,6 +218,7 @@ impl Opcode {
             0x1008 => Some(Self::KeyPackagePublish),
             0x1009 => Some(Self::KeyPackageFetch),
             0x100A => Some(Self::GroupInfoRequest),
+            0x100B => Some(Self::KeyPackageLowStock),
 
             0x2000 => Some(Self::AppMessage),
             0x2001 => Some(Self::AppReceipt),
@@ -187,6 +235,8 @@ impl Opcode {
             0x3004 => Some(Self::Mute),
             0x3005 => Some(Self::Pin),
             0x3006 => Some(Self::Report),
+            0x3007 => Some(Self::SetRole),
+            0x3008 => Some(Self::CloseRoom),
 
             0x4000 => Some(Self::FedAppend),
             0x4001 => Some(Self::FedSync),
@@ -199,6 +249,9 @@ impl Opcode {
             0x5002 => Some(Self::CASDelete),
             0x5003 => Some(Self::CASProof),
 
+            0x6000 => Some(Self::AdminRequest),
+            0x6001 => Some(Self::AdminResponse),
+
             _ => None,
         }
     }
@@ -219,6 +272,15 @@ mod tests {
             Opcode::Pong,
             Opcode::SyncRequest,
             Opcode::SyncResponse,
+            Opcode::HistoryTruncated,
+            Opcode::PresenceQuery,
+            Opcode::PresenceReply,
+            Opcode::LaggedBehind,
+            Opcode::DirectoryPublish,
+            Opcode::DirectorySearch,
+            Opcode::DirectoryResults,
+            Opcode::Invite,
+            Opcode::ServerNotice,
             Opcode::Error,
             // MLS Operations
             Opcode::KeyPackage,
@@ -232,6 +294,7 @@ mod tests {
             Opcode::KeyPackagePublish,
             Opcode::KeyPackageFetch,
             Opcode::GroupInfoRequest,
+            Opcode::KeyPackageLowStock,
             // Application Messages
             Opcode::AppMessage,
             Opcode::AppReceipt,
@@ -248,6 +311,8 @@ mod tests {
             Opcode::Mute,
             Opcode::Pin,
             Opcode::Report,
+            Opcode::SetRole,
+            Opcode::CloseRoom,
             // Federation
             Opcode::FedAppend,
             Opcode::FedSync,
@@ -259,6 +324,9 @@ mod tests {
             Opcode::CASGet,
             Opcode::CASDelete,
             Opcode::CASProof,
+            // Administration
+            Opcode::AdminRequest,
+            Opcode::AdminResponse,
         ];
 
         for opcode in all_opcodes {
@@ -268,6 +336,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn mls_range() {
+        assert!(Opcode::Commit.is_mls());
+        assert!(Opcode::KeyPackageLowStock.is_mls());
+        assert!(!Opcode::AppMessage.is_mls());
+        assert!(!Opcode::SyncRequest.is_mls());
+    }
+
     #[test]
     fn invalid_opcode() {
         assert_eq!(Opcode::from_u16(0x9999), None);
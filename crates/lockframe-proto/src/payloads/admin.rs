@@ -0,0 +1,447 @@
+//! Server administration payload types.
+//!
+//! Operators inspect and control a running server by sending `AdminRequest`
+//! frames on a session whose Hello carried the server's admin token. The
+//! server answers each with an `AdminResponse`, or an Error frame if the
+//! request fails.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::session::ServerNotice;
+use crate::Opcode;
+
+/// Operator request (operator → server)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdminRequest {
+    /// List every room the server knows about
+    ListRooms,
+
+    /// Describe one room
+    RoomInfo {
+        /// Room to describe
+        room_id: u128,
+    },
+
+    /// Disconnect a session
+    KickSession {
+        /// Session to disconnect
+        session_id: u64,
+        /// Reason given to the session
+        reason: String,
+    },
+
+    /// Stop routing a room and unsubscribe its sessions
+    CloseRoom {
+        /// Room to close
+        room_id: u128,
+    },
+
+    /// Override a room's retention policy
+    SetRetention {
+        /// Room to configure
+        room_id: u128,
+        /// Drop frames older than this many seconds (None = no age limit)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        max_age_secs: Option<u64>,
+        /// Keep at most this many recent frames (None = no count limit)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        max_frames: Option<u64>,
+    },
+
+    /// Share members' presence changes with a room
+    SetPresence {
+        /// Room to configure
+        room_id: u128,
+        /// Whether the room receives `Presence` frames
+        enabled: bool,
+    },
+
+    /// Change the kind of a room, and so how its frames are handled
+    SetRoomKind {
+        /// Room to configure
+        room_id: u128,
+        /// Kind the room becomes
+        kind: RoomKind,
+    },
+
+    /// Change how every room of a kind handles an opcode
+    SetFrameRule {
+        /// Kind of room the rule applies to
+        kind: RoomKind,
+        /// Opcode the rule applies to
+        opcode: Opcode,
+        /// How the frame is handled (None = the server's default)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        rule: Option<FrameRule>,
+    },
+
+    /// Server-wide counters
+    Stats,
+
+    /// Forward a room's events to a webhook, or stop forwarding them
+    SetWebhook {
+        /// Room to configure
+        room_id: u128,
+        /// Where to send events (None = use the server's default webhook)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        webhook: Option<WebhookTarget>,
+    },
+
+    /// Page through the audit log, oldest entry first
+    ExportAudit {
+        /// Only entries with a larger sequence number (None = from the start)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        after: Option<u64>,
+        /// Most entries to return (0 = server default)
+        limit: u32,
+    },
+
+    /// Send a notice to every session, or to the sessions of the notice's
+    /// room
+    SendNotice(ServerNotice),
+}
+
+impl AdminRequest {
+    /// Room the request targets, if any.
+    #[must_use]
+    pub fn room_id(&self) -> Option<u128> {
+        match self {
+            Self::RoomInfo { room_id }
+            | Self::CloseRoom { room_id }
+            | Self::SetRetention { room_id, .. }
+            | Self::SetPresence { room_id, .. }
+            | Self::SetRoomKind { room_id, .. }
+            | Self::SetWebhook { room_id, .. } => Some(*room_id),
+            Self::SendNotice(notice) => notice.room_id,
+            Self::ListRooms
+            | Self::KickSession { .. }
+            | Self::SetFrameRule { .. }
+            | Self::Stats
+            | Self::ExportAudit { .. } => None,
+        }
+    }
+}
+
+/// Reply to an [`AdminRequest`] (server → operator)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdminResponse {
+    /// Reply to `ListRooms`
+    Rooms {
+        /// Room IDs in ascending order
+        room_ids: Vec<u128>,
+    },
+
+    /// Reply to `RoomInfo`
+    RoomInfo(RoomInfo),
+
+    /// Reply to `Stats`
+    Stats(ServerStats),
+
+    /// Reply to `ExportAudit`
+    Audit {
+        /// Entries in sequence order
+        entries: Vec<AuditEntry>,
+        /// Pass as `after` for the next page (None = no more entries)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        next: Option<u64>,
+    },
+
+    /// The request was applied
+    Done,
+}
+
+/// Description of a room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    /// Room ID
+    pub room_id: u128,
+    /// User who created the room
+    pub creator: u64,
+    /// Unix timestamp (seconds) when the room was created
+    pub created_at_secs: u64,
+    /// Index of the newest frame (None if the log is empty)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub latest_log_index: Option<u64>,
+    /// Members in the room's ACL
+    pub members: u32,
+    /// Sessions currently subscribed
+    pub subscribers: u32,
+    /// Retention age limit in seconds (None = no age limit)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub retention_max_age_secs: Option<u64>,
+    /// Retention frame limit (None = no count limit)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub retention_max_frames: Option<u64>,
+    /// Kind of room, which decides how its frames are handled
+    #[serde(default)]
+    pub kind: RoomKind,
+}
+
+/// Kind of room, which decides how the server handles its frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RoomKind {
+    /// Every frame is stored
+    #[default]
+    Standard,
+    /// Messages are relayed to members but never stored
+    Ephemeral,
+    /// Only owners post messages; other members read
+    Broadcast,
+}
+
+/// What the server does with a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameHandling {
+    /// Sequence, store and relay it
+    Persist,
+    /// Relay it without a log index, storing nothing
+    Route,
+    /// Refuse it with an error
+    Reject,
+}
+
+/// How a kind of room handles one opcode, by the sender's role
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRule {
+    /// Handling for frames from the room's owner
+    pub owner: FrameHandling,
+    /// Handling for frames from everyone else
+    pub others: FrameHandling,
+}
+
+impl FrameRule {
+    /// Rule handling frames the same whoever sends them.
+    #[must_use]
+    pub fn all(handling: FrameHandling) -> Self {
+        Self { owner: handling, others: handling }
+    }
+}
+
+/// Server-wide counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStats {
+    /// Open connections
+    pub connections: u64,
+    /// Sessions that completed the handshake with a user ID
+    pub authenticated_sessions: u64,
+    /// Rooms being routed
+    pub rooms: u64,
+    /// Unclaimed `KeyPackages` held by the server
+    pub key_packages: u64,
+}
+
+/// An HTTP endpoint room events are posted to
+///
+/// Each request body is signed with HMAC-SHA256 under `secret` so the
+/// receiver can check it came from this server. Events never carry message
+/// content.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookTarget {
+    /// `http://` or `https://` URL to post to
+    pub url: String,
+    /// Key the request body is signed with
+    pub secret: Vec<u8>,
+    /// Events to send (empty = all)
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookTarget {
+    /// Whether this target wants `event`.
+    #[must_use]
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+impl fmt::Debug for WebhookTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookTarget")
+            .field("url", &self.url)
+            .field("secret", &format_args!("<redacted {} bytes>", self.secret.len()))
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+/// Kind of room event a webhook can receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    /// A room was created
+    RoomCreated,
+    /// A room's member count changed
+    MembersChanged,
+    /// A room stored another batch of messages
+    MessageVolume,
+}
+
+/// One record in the server's audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, increasing by one per entry
+    pub seq: u64,
+    /// Unix timestamp (seconds) when the event happened
+    pub at_secs: u64,
+    /// What happened
+    pub event: AuditEvent,
+}
+
+/// Administrative or security-relevant event.
+///
+/// Events identify sessions, users, rooms and log indices but never carry
+/// message content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditEvent {
+    /// A session failed authentication
+    AuthFailed {
+        /// Session that failed
+        session_id: u64,
+        /// Why it was refused
+        reason: String,
+    },
+
+    /// A session without the admin token sent an `AdminRequest`
+    AdminDenied {
+        /// Session that sent the request
+        session_id: u64,
+    },
+
+    /// A request was refused because a server limit was reached
+    LimitExceeded {
+        /// Session whose request was refused
+        session_id: u64,
+        /// Room the limit applies to (None = server-wide)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        room_id: Option<u128>,
+        /// Name of the limit
+        limit: String,
+    },
+
+    /// An operator disconnected a session
+    SessionKicked {
+        /// Session disconnected
+        session_id: u64,
+        /// Reason given to the session
+        reason: String,
+    },
+
+    /// A moderator removed or banned a member
+    MemberRemoved {
+        /// Room the member was removed from
+        room_id: u128,
+        /// Member removed
+        user_id: u64,
+        /// Moderator who removed them
+        moderator_id: u64,
+        /// Whether the member was also banned
+        banned: bool,
+    },
+
+    /// A room was closed
+    RoomClosed {
+        /// Room closed
+        room_id: u128,
+        /// Owner who closed it (None = an operator)
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        closed_by: Option<u64>,
+    },
+
+    /// A member reported a message for moderator review
+    MessageReported {
+        /// Room the message is in
+        room_id: u128,
+        /// Log index of the reported message
+        message_log_index: u64,
+        /// Member who reported it
+        reporter_id: u64,
+        /// Reporter's reason
+        reason: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(value, &mut buf).unwrap();
+        ciborium::de::from_reader(&buf[..]).unwrap()
+    }
+
+    #[test]
+    fn admin_request_serde() {
+        let request = AdminRequest::SetRetention {
+            room_id: u128::MAX,
+            max_age_secs: Some(86_400),
+            max_frames: None,
+        };
+        assert_eq!(round_trip(&request), request);
+        assert_eq!(round_trip(&AdminRequest::ListRooms), AdminRequest::ListRooms);
+    }
+
+    #[test]
+    fn admin_response_serde() {
+        let response = AdminResponse::RoomInfo(RoomInfo {
+            room_id: 0x1234,
+            creator: 42,
+            created_at_secs: 1_700_000_000,
+            latest_log_index: Some(99),
+            members: 3,
+            subscribers: 2,
+            retention_max_age_secs: None,
+            retention_max_frames: Some(1000),
+            kind: RoomKind::Broadcast,
+        });
+        assert_eq!(round_trip(&response), response);
+    }
+
+    #[test]
+    fn frame_rule_round_trip() {
+        let request = AdminRequest::SetFrameRule {
+            kind: RoomKind::Ephemeral,
+            opcode: Opcode::AppMessage,
+            rule: Some(FrameRule { owner: FrameHandling::Persist, others: FrameHandling::Route }),
+        };
+        assert_eq!(round_trip(&request), request);
+        assert_eq!(request.room_id(), None);
+
+        let request = AdminRequest::SetRoomKind { room_id: 7, kind: RoomKind::Broadcast };
+        assert_eq!(round_trip(&request), request);
+        assert_eq!(request.room_id(), Some(7));
+    }
+
+    #[test]
+    fn audit_export_round_trip() {
+        let response = AdminResponse::Audit {
+            entries: vec![AuditEntry {
+                seq: 7,
+                at_secs: 1_700_000_000,
+                event: AuditEvent::MessageReported {
+                    room_id: 0x1234,
+                    message_log_index: 42,
+                    reporter_id: 9,
+                    reason: "spam".into(),
+                },
+            }],
+            next: Some(7),
+        };
+        assert_eq!(round_trip(&response), response);
+    }
+
+    #[test]
+    fn webhook_secret_is_redacted() {
+        let request = AdminRequest::SetWebhook {
+            room_id: 1,
+            webhook: Some(WebhookTarget {
+                url: "https://hooks.example/lockframe".into(),
+                secret: b"hunter2".to_vec(),
+                events: vec![WebhookEvent::MembersChanged],
+            }),
+        };
+        assert_eq!(round_trip(&request), request);
+        assert!(!format!("{request:?}").contains("hunter2"));
+    }
+}
@@ -5,6 +5,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::{ProtocolError, Result};
+
 /// Encrypted application message
 ///
 /// Primary message type for user-to-user communication. Messages are encrypted
@@ -103,6 +105,216 @@ pub struct Reaction {
     pub add: bool,
 }
 
+/// Reference from a reply to the message it answers.
+///
+/// The preview hash lets receivers notice that the message they hold under
+/// `target_log_index` is not what the replier saw, such as when it was edited
+/// since. It is a checksum, not a commitment: it is not collision resistant
+/// and is only as trustworthy as the replier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ReplyTo {
+    /// Log index of the message being replied to
+    pub target_log_index: u64,
+    /// [`ReplyTo::preview_hash`] of the target's content as the replier saw it
+    pub preview_hash: u64,
+}
+
+impl ReplyTo {
+    /// Reference the message at `target_log_index` with content `content`.
+    #[must_use]
+    pub fn new(target_log_index: u64, content: &[u8]) -> Self {
+        Self { target_log_index, preview_hash: Self::preview_hash(content) }
+    }
+
+    /// 64-bit FNV-1a hash of a message's content.
+    #[must_use]
+    pub fn preview_hash(content: &[u8]) -> u64 {
+        content.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    /// Whether `content` is what the replier saw.
+    #[must_use]
+    pub fn matches(&self, content: &[u8]) -> bool {
+        self.preview_hash == Self::preview_hash(content)
+    }
+}
+
+/// An encrypted file shared in a room.
+///
+/// The file is sealed under a key of its own (see
+/// `lockframe_crypto::attachment`), and that key travels here, inside the
+/// message's own encryption. The ciphertext is carried inline until blob
+/// storage exists behind the reserved `CAS*` opcodes, at which point it can
+/// be fetched by `hash` instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// File name as the sender gave it. Not a path; receivers must not
+    /// trust it as one.
+    pub filename: String,
+    /// Size of the decrypted file in bytes
+    pub size: u64,
+    /// SHA-256 of `ciphertext`
+    pub hash: [u8; 32],
+    /// Key the file is sealed under
+    pub key: [u8; 32],
+    /// Nonce the file is sealed with
+    pub nonce: [u8; 24],
+    /// Sealed file content
+    pub ciphertext: Vec<u8>,
+}
+
+/// Current version of the application message envelope.
+///
+/// Bumped when [`AppMessageBody`] changes incompatibly. Receivers reject
+/// envelopes from newer versions rather than misinterpret them.
+pub const APP_MESSAGE_VERSION: u8 = 1;
+
+/// Decrypted content of an application message.
+///
+/// This is the plaintext sealed inside [`EncryptedMessage::ciphertext`]. The
+/// server never sees it, so every kind of content is indistinguishable on the
+/// wire and is sequenced like any other `AppMessage`.
+///
+/// Replies, edits, deletes, reactions, and receipts reference their target by
+/// the log index the server assigned it. Receivers only apply edits and deletes
+/// if the target was sent by the same member.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AppMessageBody {
+    /// Plain message text.
+    Text(Vec<u8>),
+
+    /// Message text answering an earlier message.
+    Reply {
+        /// Message being replied to
+        reply_to: ReplyTo,
+        /// Reply text
+        text: Vec<u8>,
+    },
+
+    /// Replace the content of an earlier message.
+    Edit {
+        /// Log index of the message being edited
+        target_log_index: u64,
+        /// Replacement content
+        new_text: Vec<u8>,
+    },
+
+    /// Retract an earlier message.
+    Delete {
+        /// Log index of the message being deleted
+        target_log_index: u64,
+    },
+
+    /// Add or remove a reaction on an earlier message.
+    Reaction(Reaction),
+
+    /// Delivery or read receipt for an earlier message.
+    Receipt(Receipt),
+
+    /// Sender started or stopped composing.
+    Typing {
+        /// True while composing, false once stopped
+        active: bool,
+    },
+
+    /// An encrypted file.
+    Attachment(Attachment),
+
+    /// Application-defined content not understood by the protocol.
+    Custom {
+        /// Identifies the content type (e.g. `"example.com/poll"`)
+        type_url: String,
+        /// Opaque content bytes
+        bytes: Vec<u8>,
+    },
+
+    /// Content in a current-version envelope that could not be read, such
+    /// as a kind this build does not know or a malformed body. Produced by
+    /// decoding, never sent.
+    Unsupported,
+}
+
+/// Versioned wrapper serialized as the encrypted plaintext.
+///
+/// `sent_at` was added without a version bump: older receivers ignore it and
+/// envelopes from older senders decode with `None`.
+#[derive(Serialize, Deserialize)]
+struct AppMessageEnvelope<B> {
+    version: u8,
+    body: B,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sent_at: Option<u64>,
+}
+
+impl AppMessageBody {
+    /// Serialize to a versioned CBOR envelope for encryption.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        self.encode_envelope(None)
+    }
+
+    /// Serialize with the sender's wall clock time (Unix milliseconds).
+    ///
+    /// The timestamp is encrypted with the body, so only members see it. It
+    /// is the sender's claim and may be skewed; receivers should correct it
+    /// before display.
+    pub fn encode_at(&self, sent_at: u64) -> Result<Vec<u8>> {
+        self.encode_envelope(Some(sent_at))
+    }
+
+    /// Whether the body only matters while fresh. Ephemeral bodies are sent
+    /// in frames flagged [`crate::FrameFlags::EPHEMERAL`], which the server
+    /// relays without storing.
+    #[must_use]
+    pub fn is_ephemeral(&self) -> bool {
+        matches!(self, Self::Typing { .. })
+    }
+
+    fn encode_envelope(&self, sent_at: Option<u64>) -> Result<Vec<u8>> {
+        let envelope = AppMessageEnvelope { version: APP_MESSAGE_VERSION, body: self, sent_at };
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&envelope, &mut buf)
+            .map_err(|e| ProtocolError::CborEncode(e.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Deserialize from decrypted envelope bytes.
+    ///
+    /// A supported envelope whose body does not decode yields
+    /// [`Self::Unsupported`] rather than an error, so it is never mistaken
+    /// for bytes that are not an envelope at all.
+    ///
+    /// # Errors
+    ///
+    /// - `ProtocolError::CborDecode` if the bytes are not an envelope
+    /// - `ProtocolError::UnsupportedVersion` if the envelope is from a newer
+    ///   version
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Self::decode_with_sent_at(bytes).map(|(body, _)| body)
+    }
+
+    /// Deserialize along with the sender's claimed send time, if present.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::decode`].
+    pub fn decode_with_sent_at(bytes: &[u8]) -> Result<(Self, Option<u64>)> {
+        // The body is decoded on its own, so a body that fails still leaves
+        // a recognizable envelope
+        let envelope: AppMessageEnvelope<ciborium::Value> = ciborium::de::from_reader(bytes)
+            .map_err(|e| ProtocolError::CborDecode(e.to_string()))?;
+
+        if envelope.version > APP_MESSAGE_VERSION {
+            return Err(ProtocolError::UnsupportedVersion(envelope.version));
+        }
+
+        let body = envelope.body.deserialized().unwrap_or(Self::Unsupported);
+        Ok((body, envelope.sent_at))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,4 +363,99 @@ mod tests {
         let cbor = ciborium::ser::into_writer(&receipt, Vec::new());
         assert!(cbor.is_ok());
     }
+
+    #[test]
+    fn app_message_body_round_trip() {
+        let bodies = [
+            AppMessageBody::Text(b"hello".to_vec()),
+            AppMessageBody::Reply { reply_to: ReplyTo::new(3, b"lunch?"), text: b"sure".to_vec() },
+            AppMessageBody::Edit { target_log_index: 7, new_text: b"hello, world".to_vec() },
+            AppMessageBody::Delete { target_log_index: 7 },
+            AppMessageBody::Reaction(Reaction {
+                message_log_index: 7,
+                content: "+1".to_string(),
+                add: true,
+            }),
+            AppMessageBody::Receipt(Receipt {
+                message_log_index: 7,
+                kind: ReceiptType::Read,
+                timestamp: 1_234_567_890,
+            }),
+            AppMessageBody::Typing { active: true },
+            AppMessageBody::Attachment(Attachment {
+                filename: "cat.png".to_string(),
+                size: 3,
+                hash: [1; 32],
+                key: [2; 32],
+                nonce: [3; 24],
+                ciphertext: vec![4; 19],
+            }),
+            AppMessageBody::Custom { type_url: "example.com/poll".to_string(), bytes: vec![1, 2] },
+        ];
+
+        for body in bodies {
+            let encoded = body.encode().unwrap();
+            assert_eq!(AppMessageBody::decode(&encoded).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn sent_at_round_trips_and_is_optional() {
+        let body = AppMessageBody::Text(b"hello".to_vec());
+
+        let stamped = body.encode_at(1_700_000_000_000).unwrap();
+        assert_eq!(
+            AppMessageBody::decode_with_sent_at(&stamped).unwrap(),
+            (body.clone(), Some(1_700_000_000_000))
+        );
+        assert_eq!(AppMessageBody::decode(&stamped).unwrap(), body);
+
+        let unstamped = body.encode().unwrap();
+        assert_eq!(AppMessageBody::decode_with_sent_at(&unstamped).unwrap(), (body, None));
+    }
+
+    #[test]
+    fn reply_preview_hash_detects_changed_targets() {
+        let reply_to = ReplyTo::new(3, b"lunch?");
+        assert!(reply_to.matches(b"lunch?"));
+        assert!(!reply_to.matches(b"lunch at 1?"));
+        // Known FNV-1a vectors
+        assert_eq!(ReplyTo::preview_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(ReplyTo::preview_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn app_message_body_rejects_raw_bytes() {
+        assert!(AppMessageBody::decode(b"hello").is_err());
+    }
+
+    #[test]
+    fn unreadable_bodies_in_current_envelopes_are_unsupported() {
+        let unknown = ciborium::Value::Map(vec![("Poll".into(), "lunch?".into())]);
+        let malformed = ciborium::Value::Map(vec![("Typing".into(), "yes".into())]);
+
+        for body in [unknown, malformed] {
+            let envelope = AppMessageEnvelope { version: APP_MESSAGE_VERSION, body, sent_at: None };
+            let mut encoded = Vec::new();
+            ciborium::ser::into_writer(&envelope, &mut encoded).unwrap();
+
+            assert_eq!(AppMessageBody::decode(&encoded), Ok(AppMessageBody::Unsupported));
+        }
+    }
+
+    #[test]
+    fn app_message_body_rejects_newer_version() {
+        let envelope = AppMessageEnvelope {
+            version: APP_MESSAGE_VERSION + 1,
+            body: AppMessageBody::Typing { active: false },
+            sent_at: None,
+        };
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&envelope, &mut encoded).unwrap();
+
+        assert_eq!(
+            AppMessageBody::decode(&encoded),
+            Err(ProtocolError::UnsupportedVersion(APP_MESSAGE_VERSION + 1))
+        );
+    }
 }
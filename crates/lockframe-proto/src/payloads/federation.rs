@@ -0,0 +1,49 @@
+//! Server-to-server federation payload types.
+//!
+//! Each room is homed on exactly one server, which alone sequences it. A
+//! peer server relays its local users' frames for that room to the home with
+//! `FedAppend`, and the home relays every sequenced frame back to each peer
+//! that has members in the room. A frame the home refuses is answered with
+//! `FedNack` so the peer can tell the sender.
+
+use serde::{Deserialize, Serialize};
+
+/// A frame relayed between servers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FedAppend {
+    /// Room the frame belongs to
+    pub room_id: u128,
+    /// Server IDs the frame has passed through, origin first. A server that
+    /// finds itself here drops the frame.
+    pub hops: Vec<u64>,
+    /// The encoded frame. Unsequenced when relayed towards the home,
+    /// sequenced when relayed from it.
+    pub frame: Vec<u8>,
+}
+
+/// The home server refused a relayed frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FedNack {
+    /// Room the frame targeted
+    pub room_id: u128,
+    /// Sender of the refused frame
+    pub sender_id: u64,
+    /// Why it was refused
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fed_append_serde() {
+        let append = FedAppend { room_id: 0xabcd, hops: vec![1, 2], frame: vec![0; 128] };
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&append, &mut buf).unwrap();
+        let decoded: FedAppend = ciborium::de::from_reader(&buf[..]).unwrap();
+
+        assert_eq!(append, decoded);
+    }
+}
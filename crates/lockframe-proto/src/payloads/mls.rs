@@ -151,6 +151,19 @@ pub struct KeyPackageFetchPayload {
     pub hash_ref: Vec<u8>,
 }
 
+/// Warn a client that its published `KeyPackages` are running out.
+///
+/// Sent by the server to the pool owner after a fetch leaves fewer than the
+/// server's low-stock threshold. The client should publish fresh
+/// `KeyPackages` until `remaining` reaches `target`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPackageLowStockPayload {
+    /// Unexpired `KeyPackages` the server still holds for this client.
+    pub remaining: u32,
+    /// Pool size the server will accept.
+    pub target: u32,
+}
+
 /// Request `GroupInfo` for external join.
 ///
 /// Sent by a client who wants to join a room via external commit.
@@ -221,6 +234,17 @@ mod tests {
         assert_eq!(publish, decoded);
     }
 
+    #[test]
+    fn key_package_low_stock_serde() {
+        let low_stock = KeyPackageLowStockPayload { remaining: 1, target: 32 };
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&low_stock, &mut buf).unwrap();
+
+        let decoded: KeyPackageLowStockPayload = ciborium::de::from_reader(&buf[..]).unwrap();
+        assert_eq!(low_stock, decoded);
+    }
+
     #[test]
     fn key_package_fetch_serde() {
         // Request (empty key_package_bytes)
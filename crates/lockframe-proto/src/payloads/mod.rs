@@ -14,17 +14,19 @@
 //! Each payload variant maps to exactly one opcode (enforced by match
 //! exhaustiveness). Round-trip encoding must produce identical values.
 
+pub mod admin;
 pub mod app;
+pub mod federation;
 pub mod mls;
 pub mod moderation;
 pub mod session;
 
 use bytes::BufMut;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use crate::{
     Frame, FrameHeader, Opcode,
-    errors::{ProtocolError, Result},
+    errors::{ErrorId, ProtocolError, Result},
 };
 
 /// All possible frame payloads
@@ -68,6 +70,24 @@ pub enum Payload {
     SyncRequest(session::SyncRequest),
     /// Server sync response
     SyncResponse(session::SyncResponse),
+    /// Tombstone for history removed by retention
+    HistoryTruncated(session::HistoryTruncated),
+    /// Online status lookup
+    PresenceQuery(session::PresenceQuery),
+    /// Online status of queried users
+    PresenceReply(session::PresenceReply),
+    /// Queued frames were dropped for a slow session
+    LaggedBehind(session::LaggedBehind),
+    /// List a room in the directory or remove its listing
+    DirectoryPublish(session::DirectoryPublish),
+    /// Room directory search
+    DirectorySearch(session::DirectorySearch),
+    /// Page of room directory results
+    DirectoryResults(session::DirectoryResults),
+    /// Invitation to join a room
+    Invite(session::Invite),
+    /// Message from the server's operator
+    ServerNotice(session::ServerNotice),
 
     // MLS Operations
     /// Key package upload
@@ -84,6 +104,8 @@ pub enum Payload {
     KeyPackageFetch(mls::KeyPackageFetchPayload),
     /// Request `GroupInfo` for external join
     GroupInfoRequest(mls::GroupInfoRequest),
+    /// Tell a client its `KeyPackage` pool is running low
+    KeyPackageLowStock(mls::KeyPackageLowStockPayload),
     /// `GroupInfo` response for external join
     GroupInfo(mls::GroupInfoPayload),
 
@@ -94,6 +116,8 @@ pub enum Payload {
     AppReceipt(app::Receipt),
     /// Message reaction
     AppReaction(app::Reaction),
+    /// Room member came online or went offline
+    Presence(session::PresenceStatus),
 
     // Moderation
     /// Redact message content
@@ -102,6 +126,26 @@ pub enum Payload {
     Ban(moderation::Ban),
     /// Kick user
     Kick(moderation::Kick),
+    /// Unban user
+    Unban(moderation::Unban),
+    /// Change a member's role
+    SetRole(moderation::SetRole),
+    /// Close a room permanently
+    CloseRoom(moderation::CloseRoom),
+    /// Report a message for moderator review
+    ReportMessage(moderation::ReportMessage),
+
+    // Federation
+    /// Frame relayed between servers
+    FedAppend(federation::FedAppend),
+    /// Relayed frame refused by the home server
+    FedNack(federation::FedNack),
+
+    // Administration
+    /// Operator request
+    AdminRequest(admin::AdminRequest),
+    /// Reply to an operator request
+    AdminResponse(admin::AdminResponse),
 
     // Error frame
     /// Error response
@@ -118,6 +162,9 @@ pub struct ErrorPayload {
     /// Optional retry-after duration in seconds.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retry_after: Option<u64>,
+    /// Identifies this failure in the sender's logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_id: Option<ErrorId>,
 }
 
 impl ErrorPayload {
@@ -135,10 +182,39 @@ impl ErrorPayload {
     pub const SEQUENCER_ERROR: u16 = 0x0006;
     /// `KeyPackage` not found in registry.
     pub const KEYPACKAGE_NOT_FOUND: u16 = 0x0007;
+    /// Sender is not a member of the room.
+    pub const NOT_A_MEMBER: u16 = 0x0008;
+    /// Sender's role does not permit the operation.
+    pub const PERMISSION_DENIED: u16 = 0x0009;
+    /// Room was closed by its owner and accepts no more frames.
+    pub const ROOM_CLOSED: u16 = 0x000A;
+    /// Session has not authenticated, or its credentials were refused.
+    pub const UNAUTHENTICATED: u16 = 0x000B;
+    /// Room has used up its storage quota.
+    pub const QUOTA_EXCEEDED: u16 = 0x000C;
+    /// Recipient of a frame routed to a user is not connected.
+    pub const RECIPIENT_OFFLINE: u16 = 0x000D;
+
+    /// Tag the error with the ID it was logged under.
+    #[must_use]
+    pub fn with_error_id(mut self, error_id: ErrorId) -> Self {
+        self.error_id = Some(error_id);
+        self
+    }
+
+    /// The rejection as a [`ProtocolError`], for the receiving side.
+    pub fn into_error(self) -> ProtocolError {
+        ProtocolError::Rejected { code: self.code, message: self.message, error_id: self.error_id }
+    }
 
     /// Create a frame rejection error.
     pub fn frame_rejected(reason: impl Into<String>) -> Self {
-        Self { code: Self::FRAME_REJECTED, message: reason.into(), retry_after: None }
+        Self {
+            code: Self::FRAME_REJECTED,
+            message: reason.into(),
+            retry_after: None,
+            error_id: None,
+        }
     }
 
     /// Create a room not found error.
@@ -147,27 +223,88 @@ impl ErrorPayload {
             code: Self::ROOM_NOT_FOUND,
             message: format!("room not found: {room_id:032x}"),
             retry_after: None,
+            error_id: None,
         }
     }
 
     /// Create a storage error.
     pub fn storage_error(msg: impl Into<String>) -> Self {
-        Self { code: Self::STORAGE_ERROR, message: msg.into(), retry_after: None }
+        Self { code: Self::STORAGE_ERROR, message: msg.into(), retry_after: None, error_id: None }
     }
 
     /// Create an invalid payload error.
     pub fn invalid_payload(msg: impl Into<String>) -> Self {
-        Self { code: Self::INVALID_PAYLOAD, message: msg.into(), retry_after: None }
+        Self { code: Self::INVALID_PAYLOAD, message: msg.into(), retry_after: None, error_id: None }
     }
 
     /// Create an MLS error.
     pub fn mls_error(msg: impl Into<String>) -> Self {
-        Self { code: Self::MLS_ERROR, message: msg.into(), retry_after: None }
+        Self { code: Self::MLS_ERROR, message: msg.into(), retry_after: None, error_id: None }
     }
 
     /// Create a sequencer error.
     pub fn sequencer_error(msg: impl Into<String>) -> Self {
-        Self { code: Self::SEQUENCER_ERROR, message: msg.into(), retry_after: None }
+        Self { code: Self::SEQUENCER_ERROR, message: msg.into(), retry_after: None, error_id: None }
+    }
+
+    /// Create a not-a-member error.
+    pub fn not_a_member(room_id: u128) -> Self {
+        Self {
+            code: Self::NOT_A_MEMBER,
+            message: format!("not a member of room {room_id:032x}"),
+            retry_after: None,
+            error_id: None,
+        }
+    }
+
+    /// Create a room closed error.
+    pub fn room_closed(room_id: u128) -> Self {
+        Self {
+            code: Self::ROOM_CLOSED,
+            message: format!("room closed: {room_id:032x}"),
+            retry_after: None,
+            error_id: None,
+        }
+    }
+
+    /// Create a quota exceeded error.
+    pub fn quota_exceeded(room_id: u128) -> Self {
+        Self {
+            code: Self::QUOTA_EXCEEDED,
+            message: format!("storage quota exceeded in room {room_id:032x}"),
+            retry_after: None,
+            error_id: None,
+        }
+    }
+
+    /// Create a permission denied error.
+    pub fn permission_denied(reason: impl Into<String>) -> Self {
+        Self {
+            code: Self::PERMISSION_DENIED,
+            message: reason.into(),
+            retry_after: None,
+            error_id: None,
+        }
+    }
+
+    /// Create an unauthenticated error.
+    pub fn unauthenticated(reason: impl Into<String>) -> Self {
+        Self {
+            code: Self::UNAUTHENTICATED,
+            message: reason.into(),
+            retry_after: None,
+            error_id: None,
+        }
+    }
+
+    /// Create a recipient offline error.
+    pub fn recipient_offline(user_id: u64) -> Self {
+        Self {
+            code: Self::RECIPIENT_OFFLINE,
+            message: format!("user {user_id} is not connected"),
+            retry_after: None,
+            error_id: None,
+        }
     }
 
     /// Create a `KeyPackage` not found error.
@@ -176,6 +313,7 @@ impl ErrorPayload {
             code: Self::KEYPACKAGE_NOT_FOUND,
             message: format!("no KeyPackage for user {user_id}"),
             retry_after: None,
+            error_id: None,
         }
     }
 }
@@ -192,6 +330,15 @@ impl Payload {
             Self::Pong => Opcode::Pong,
             Self::SyncRequest(_) => Opcode::SyncRequest,
             Self::SyncResponse(_) => Opcode::SyncResponse,
+            Self::HistoryTruncated(_) => Opcode::HistoryTruncated,
+            Self::PresenceQuery(_) => Opcode::PresenceQuery,
+            Self::PresenceReply(_) => Opcode::PresenceReply,
+            Self::LaggedBehind(_) => Opcode::LaggedBehind,
+            Self::DirectoryPublish(_) => Opcode::DirectoryPublish,
+            Self::DirectorySearch(_) => Opcode::DirectorySearch,
+            Self::DirectoryResults(_) => Opcode::DirectoryResults,
+            Self::Invite(_) => Opcode::Invite,
+            Self::ServerNotice(_) => Opcode::ServerNotice,
             Self::KeyPackage(_) => Opcode::KeyPackage,
             Self::Proposal(_) => Opcode::Proposal,
             Self::Commit(_) => Opcode::Commit,
@@ -199,13 +346,23 @@ impl Payload {
             Self::KeyPackagePublish(_) => Opcode::KeyPackagePublish,
             Self::KeyPackageFetch(_) => Opcode::KeyPackageFetch,
             Self::GroupInfoRequest(_) => Opcode::GroupInfoRequest,
+            Self::KeyPackageLowStock(_) => Opcode::KeyPackageLowStock,
             Self::GroupInfo(_) => Opcode::GroupInfo,
             Self::AppMessage(_) => Opcode::AppMessage,
             Self::AppReceipt(_) => Opcode::AppReceipt,
             Self::AppReaction(_) => Opcode::AppReaction,
+            Self::Presence(_) => Opcode::Presence,
             Self::Redact(_) => Opcode::Redact,
             Self::Ban(_) => Opcode::Ban,
             Self::Kick(_) => Opcode::Kick,
+            Self::Unban(_) => Opcode::Unban,
+            Self::SetRole(_) => Opcode::SetRole,
+            Self::CloseRoom(_) => Opcode::CloseRoom,
+            Self::ReportMessage(_) => Opcode::Report,
+            Self::FedAppend(_) => Opcode::FedAppend,
+            Self::FedNack(_) => Opcode::FedNack,
+            Self::AdminRequest(_) => Opcode::AdminRequest,
+            Self::AdminResponse(_) => Opcode::AdminResponse,
             Self::Error(_) => Opcode::Error,
         }
     }
@@ -239,6 +396,15 @@ impl Payload {
             Self::Ping | Self::Pong => Ok(()), // Zero-byte payloads
             Self::SyncRequest(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::SyncResponse(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::HistoryTruncated(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::PresenceQuery(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::PresenceReply(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::LaggedBehind(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::DirectoryPublish(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::DirectorySearch(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::DirectoryResults(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::Invite(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::ServerNotice(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::KeyPackage(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::Proposal(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::Commit(inner) => ciborium::ser::into_writer(inner, &mut writer),
@@ -246,13 +412,23 @@ impl Payload {
             Self::KeyPackagePublish(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::KeyPackageFetch(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::GroupInfoRequest(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::KeyPackageLowStock(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::GroupInfo(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::AppMessage(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::AppReceipt(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::AppReaction(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::Presence(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::Redact(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::Ban(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::Kick(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::Unban(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::SetRole(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::CloseRoom(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::ReportMessage(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::FedAppend(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::FedNack(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::AdminRequest(inner) => ciborium::ser::into_writer(inner, &mut writer),
+            Self::AdminResponse(inner) => ciborium::ser::into_writer(inner, &mut writer),
             Self::Error(inner) => ciborium::ser::into_writer(inner, &mut writer),
         }
         .map_err(|e| ProtocolError::CborEncode(e.to_string()))
@@ -287,88 +463,47 @@ impl Payload {
         }
 
         let payload = match opcode {
-            Opcode::Hello => Self::Hello(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::HelloReply => Self::HelloReply(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Goodbye => Self::Goodbye(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
+            Opcode::Hello => Self::Hello(from_cbor(bytes)?),
+            Opcode::HelloReply => Self::HelloReply(from_cbor(bytes)?),
+            Opcode::Goodbye => Self::Goodbye(from_cbor(bytes)?),
             Opcode::Ping => Self::Ping,
             Opcode::Pong => Self::Pong,
-            Opcode::SyncRequest => Self::SyncRequest(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::SyncResponse => Self::SyncResponse(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::KeyPackage => Self::KeyPackage(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Proposal => Self::Proposal(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Commit => Self::Commit(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Welcome => Self::Welcome(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::KeyPackagePublish => Self::KeyPackagePublish(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::KeyPackageFetch => Self::KeyPackageFetch(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::GroupInfoRequest => Self::GroupInfoRequest(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::GroupInfo => Self::GroupInfo(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::AppMessage => Self::AppMessage(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::AppReceipt => Self::AppReceipt(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::AppReaction => Self::AppReaction(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Redact => Self::Redact(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Ban => Self::Ban(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Kick => Self::Kick(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
-            Opcode::Error => Self::Error(
-                ciborium::de::from_reader(bytes)
-                    .map_err(|e| ProtocolError::CborDecode(e.to_string()))?,
-            ),
+            Opcode::SyncRequest => Self::SyncRequest(from_cbor(bytes)?),
+            Opcode::SyncResponse => Self::SyncResponse(from_cbor(bytes)?),
+            Opcode::HistoryTruncated => Self::HistoryTruncated(from_cbor(bytes)?),
+            Opcode::PresenceQuery => Self::PresenceQuery(from_cbor(bytes)?),
+            Opcode::PresenceReply => Self::PresenceReply(from_cbor(bytes)?),
+            Opcode::LaggedBehind => Self::LaggedBehind(from_cbor(bytes)?),
+            Opcode::DirectoryPublish => Self::DirectoryPublish(from_cbor(bytes)?),
+            Opcode::DirectorySearch => Self::DirectorySearch(from_cbor(bytes)?),
+            Opcode::DirectoryResults => Self::DirectoryResults(from_cbor(bytes)?),
+            Opcode::Invite => Self::Invite(from_cbor(bytes)?),
+            Opcode::ServerNotice => Self::ServerNotice(from_cbor(bytes)?),
+            Opcode::KeyPackage => Self::KeyPackage(from_cbor(bytes)?),
+            Opcode::Proposal => Self::Proposal(from_cbor(bytes)?),
+            Opcode::Commit => Self::Commit(from_cbor(bytes)?),
+            Opcode::Welcome => Self::Welcome(from_cbor(bytes)?),
+            Opcode::KeyPackagePublish => Self::KeyPackagePublish(from_cbor(bytes)?),
+            Opcode::KeyPackageFetch => Self::KeyPackageFetch(from_cbor(bytes)?),
+            Opcode::GroupInfoRequest => Self::GroupInfoRequest(from_cbor(bytes)?),
+            Opcode::KeyPackageLowStock => Self::KeyPackageLowStock(from_cbor(bytes)?),
+            Opcode::GroupInfo => Self::GroupInfo(from_cbor(bytes)?),
+            Opcode::AppMessage => Self::AppMessage(from_cbor(bytes)?),
+            Opcode::AppReceipt => Self::AppReceipt(from_cbor(bytes)?),
+            Opcode::AppReaction => Self::AppReaction(from_cbor(bytes)?),
+            Opcode::Presence => Self::Presence(from_cbor(bytes)?),
+            Opcode::Redact => Self::Redact(from_cbor(bytes)?),
+            Opcode::Ban => Self::Ban(from_cbor(bytes)?),
+            Opcode::Kick => Self::Kick(from_cbor(bytes)?),
+            Opcode::Unban => Self::Unban(from_cbor(bytes)?),
+            Opcode::SetRole => Self::SetRole(from_cbor(bytes)?),
+            Opcode::CloseRoom => Self::CloseRoom(from_cbor(bytes)?),
+            Opcode::Report => Self::ReportMessage(from_cbor(bytes)?),
+            Opcode::FedAppend => Self::FedAppend(from_cbor(bytes)?),
+            Opcode::FedNack => Self::FedNack(from_cbor(bytes)?),
+            Opcode::AdminRequest => Self::AdminRequest(from_cbor(bytes)?),
+            Opcode::AdminResponse => Self::AdminResponse(from_cbor(bytes)?),
+            Opcode::Error => Self::Error(from_cbor(bytes)?),
             _ => {
                 return Err(ProtocolError::CborDecode(format!(
                     "Unsupported opcode: {:#06x}",
@@ -393,7 +528,7 @@ impl Payload {
     pub fn into_frame(self, mut header: FrameHeader) -> Result<Frame> {
         let mut buf = Vec::new();
         self.encode(&mut buf)?;
-        header.opcode = self.opcode().to_u16().to_be_bytes();
+        header.set_opcode(self.opcode());
         Ok(Frame::new(header, buf))
     }
 
@@ -416,6 +551,11 @@ impl Payload {
     }
 }
 
+/// Decode a CBOR payload body.
+fn from_cbor<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    ciborium::de::from_reader(bytes).map_err(|e| ProtocolError::CborDecode(e.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -424,14 +564,11 @@ mod tests {
     fn payload_ping_round_trip() {
         let payload = Payload::Ping;
 
-        // Create valid header
-        let mut bytes = [0u8; 128];
-        bytes[0..4].copy_from_slice(&FrameHeader::MAGIC.to_be_bytes());
-        bytes[4] = FrameHeader::VERSION;
-        let header = *FrameHeader::from_bytes(&bytes).unwrap();
-
         // Convert to frame and back
-        let frame = payload.clone().into_frame(header).expect("should create frame");
+        let frame = payload
+            .clone()
+            .into_frame(FrameHeader::new(Opcode::Ping))
+            .expect("should create frame");
         let decoded = Payload::from_frame(&frame).expect("should parse payload");
         assert_eq!(payload, decoded);
     }
@@ -442,16 +579,14 @@ mod tests {
             code: 0x00FF,
             message: "Test error".to_string(),
             retry_after: Some(30),
+            error_id: Some(ErrorId::new(0x7f3a)),
         });
 
-        // Create valid header
-        let mut bytes = [0u8; 128];
-        bytes[0..4].copy_from_slice(&FrameHeader::MAGIC.to_be_bytes());
-        bytes[4] = FrameHeader::VERSION;
-        let header = *FrameHeader::from_bytes(&bytes).unwrap();
-
         // Convert to frame and back
-        let frame = payload.clone().into_frame(header).expect("should create frame");
+        let frame = payload
+            .clone()
+            .into_frame(FrameHeader::new(Opcode::Error))
+            .expect("should create frame");
         let decoded = Payload::from_frame(&frame).expect("should parse payload");
         assert_eq!(payload, decoded);
     }
@@ -55,6 +55,69 @@ pub struct Kick {
     pub moderator_id: u64,
 }
 
+/// Lift a ban
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unban {
+    /// User ID to unban
+    pub user_id: u64,
+
+    /// Moderator ID
+    pub moderator_id: u64,
+}
+
+/// Report a message for moderator review
+///
+/// Sent by any member. The server records the report in its audit log
+/// instead of sequencing it, so other members never learn who reported what.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportMessage {
+    /// Log index of the reported message
+    pub message_log_index: u64,
+
+    /// Reason given by the reporter
+    pub reason: String,
+}
+
+/// A member's standing in a room, lowest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RoomRole {
+    /// Can send and read messages
+    Member,
+    /// Can also moderate members below admin
+    Admin,
+    /// Room creator. Can also promote and demote admins
+    Owner,
+}
+
+/// Change a member's role
+///
+/// Only the owner can change roles, and ownership cannot be transferred.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetRole {
+    /// Member whose role changes
+    pub user_id: u64,
+
+    /// New role
+    pub role: RoomRole,
+
+    /// Moderator ID
+    pub moderator_id: u64,
+}
+
+/// Close a room permanently
+///
+/// Only the owner can close a room. Once sequenced the frame is the room's
+/// last log entry: the server refuses further frames, and clients that see
+/// it, live or through sync, drop their local state for the room.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CloseRoom {
+    /// Reason shown to members
+    pub reason: String,
+
+    /// Moderator ID
+    pub moderator_id: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +142,43 @@ mod tests {
         let cbor = ciborium::ser::into_writer(&ban, Vec::new());
         assert!(cbor.is_ok());
     }
+
+    #[test]
+    fn set_role_serde() {
+        let set_role = SetRole { user_id: 42, role: RoomRole::Admin, moderator_id: 1 };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&set_role, &mut bytes).unwrap();
+
+        let decoded: SetRole = ciborium::de::from_reader(&bytes[..]).unwrap();
+        assert_eq!(set_role, decoded);
+    }
+
+    #[test]
+    fn close_room_serde() {
+        let close = CloseRoom { reason: "Archived".to_string(), moderator_id: 1 };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&close, &mut bytes).unwrap();
+
+        let decoded: CloseRoom = ciborium::de::from_reader(&bytes[..]).unwrap();
+        assert_eq!(close, decoded);
+    }
+
+    #[test]
+    fn report_message_serde() {
+        let report = ReportMessage { message_log_index: 12, reason: "Harassment".to_string() };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&report, &mut bytes).unwrap();
+
+        let decoded: ReportMessage = ciborium::de::from_reader(&bytes[..]).unwrap();
+        assert_eq!(report, decoded);
+    }
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(RoomRole::Member < RoomRole::Admin);
+        assert!(RoomRole::Admin < RoomRole::Owner);
+    }
 }
@@ -99,6 +99,31 @@ impl std::fmt::Debug for HelloReply {
 pub struct Goodbye {
     /// Reason for disconnect (for logging/debugging)
     pub reason: String,
+    /// Machine-readable reason, one of the `Goodbye::*` codes
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub code: Option<u16>,
+    /// Seconds to wait before reconnecting
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub retry_after: Option<u64>,
+}
+
+impl Goodbye {
+    /// The server is shutting down or restarting.
+    pub const SERVER_SHUTDOWN: u16 = 0x0001;
+
+    /// Create a goodbye with only a human-readable reason.
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self { reason: reason.into(), code: None, retry_after: None }
+    }
+
+    /// Create the goodbye a draining server sends its sessions.
+    pub fn server_shutdown(retry_after: u64) -> Self {
+        Self {
+            reason: "server shutting down".to_string(),
+            code: Some(Self::SERVER_SHUTDOWN),
+            retry_after: Some(retry_after),
+        }
+    }
 }
 
 /// Client request for missing frames (epoch sync)
@@ -109,6 +134,16 @@ pub struct Goodbye {
 /// Client detects epoch mismatch, sends `SyncRequest` with `from_log_index`,
 /// server responds with `SyncResponse` containing frames, and client processes
 /// frames in order to catch up.
+///
+/// # Filters
+///
+/// By default every frame from `from_log_index` on is returned. A rejoining
+/// client can narrow that with `control_only` to fetch just the MLS frames it
+/// needs to reach the current epoch, then backfill application messages with
+/// a plain request. `senders` and `epochs` narrow further. Filters combine:
+/// a frame is returned only if it passes all of them. `HistoryTruncated`
+/// tombstones always pass, so a filtered sync still learns about removed
+/// history.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SyncRequest {
     /// Start replaying frames from this log index (inclusive).
@@ -123,6 +158,81 @@ pub struct SyncRequest {
     /// Default: 100 frames per batch.
     #[serde(default = "default_limit")]
     pub limit: u64,
+
+    /// Only frames from these senders, each from its own cursor. Empty means
+    /// every sender.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub senders: Vec<SenderCursor>,
+
+    /// Only frames whose epoch falls within this range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epochs: Option<EpochRange>,
+
+    /// Only MLS frames (`0x1xxx` opcodes), skipping application messages.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub control_only: bool,
+}
+
+impl SyncRequest {
+    /// Unfiltered request for up to `limit` frames from `from_log_index`.
+    #[must_use]
+    pub fn new(from_log_index: u64, limit: u64) -> Self {
+        Self { from_log_index, limit, senders: Vec::new(), epochs: None, control_only: false }
+    }
+
+    /// Request only MLS frames, for catching up group state.
+    #[must_use]
+    pub fn control_only(mut self) -> Self {
+        self.control_only = true;
+        self
+    }
+
+    /// Request only frames from epochs `from..=to`.
+    #[must_use]
+    pub fn in_epochs(mut self, from: u64, to: u64) -> Self {
+        self.epochs = Some(EpochRange { from, to });
+        self
+    }
+
+    /// Request frames from `sender_id` starting at `from_log_index`. May be
+    /// repeated; once any sender is given, other senders' frames are skipped.
+    #[must_use]
+    pub fn from_sender(mut self, sender_id: u64, from_log_index: u64) -> Self {
+        self.senders.push(SenderCursor { sender_id, from_log_index });
+        self
+    }
+
+    /// Whether any filter is set.
+    #[must_use]
+    pub fn is_filtered(&self) -> bool {
+        !self.senders.is_empty() || self.epochs.is_some() || self.control_only
+    }
+}
+
+/// Where to resume one sender's frames in a filtered [`SyncRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SenderCursor {
+    /// Sender whose frames to include
+    pub sender_id: u64,
+    /// Lowest log index to return for this sender (inclusive)
+    pub from_log_index: u64,
+}
+
+/// Inclusive range of MLS epochs in a filtered [`SyncRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochRange {
+    /// Lowest epoch to include
+    pub from: u64,
+    /// Highest epoch to include
+    pub to: u64,
+}
+
+impl EpochRange {
+    /// Whether `epoch` is within the range.
+    #[must_use]
+    pub fn contains(&self, epoch: u64) -> bool {
+        (self.from..=self.to).contains(&epoch)
+    }
 }
 
 fn default_limit() -> u64 {
@@ -133,7 +243,8 @@ fn default_limit() -> u64 {
 ///
 /// Contains a batch of frames for the client to process in order.
 /// If `has_more` is true, the client should send another `SyncRequest`
-/// with `from_log_index` = last frame's `log_index` + 1.
+/// with `from_log_index` = `next_log_index`, or the last frame's
+/// `log_index` + 1 when the server did not send one.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SyncResponse {
     /// Frames in `log_index` order.
@@ -151,6 +262,192 @@ pub struct SyncResponse {
     ///
     /// After processing all frames, client epoch should match this.
     pub server_epoch: u64,
+
+    /// Log index the next request should start from.
+    ///
+    /// Set for filtered requests, where the server may scan past frames it
+    /// does not return and the last returned frame says little about where
+    /// the scan stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_log_index: Option<u64>,
+}
+
+/// Tombstone for history removed by the server's retention policy
+///
+/// Stored in the room's log at `first_log_index - 1`, replacing the frames
+/// before it. A client syncing from an index below `first_log_index` receives
+/// this frame first and learns that earlier history no longer exists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryTruncated {
+    /// Lowest log index still available after the tombstone.
+    pub first_log_index: u64,
+}
+
+/// Online status of a user
+///
+/// Sent alone in a `Presence` frame when a member of a room that shares
+/// presence connects or disconnects, and in bulk in a `PresenceReply`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceStatus {
+    /// User the status describes
+    pub user_id: u64,
+    /// Whether the user has an authenticated session
+    pub online: bool,
+    /// Unix time the user's last session closed. `None` while online or if
+    /// the server has not seen the user since it started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen_secs: Option<u64>,
+}
+
+/// Ask for the online status of users
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceQuery {
+    /// Users to look up
+    pub user_ids: Vec<u64>,
+}
+
+/// Online status of queried users
+///
+/// Users the server's privacy setting hides from the querier are left out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceReply {
+    /// One entry per visible queried user
+    pub users: Vec<PresenceStatus>,
+}
+
+/// Notice that the server dropped frames queued for this session
+///
+/// Sent when a session reads too slowly to keep up and its send queue fills.
+/// The oldest queued frames are dropped to bound server memory; this frame
+/// takes their place so the client knows to resync the affected rooms.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaggedBehind {
+    /// Number of frames dropped, including ones that were not stored in any
+    /// room's log (presence, typing and the like)
+    pub dropped: u64,
+    /// Rooms with dropped sequenced frames, and where to resync each from
+    pub rooms: Vec<RoomGap>,
+}
+
+/// List a room in the server's room directory, or remove its listing
+///
+/// Sent with the room's ID in the header. Only the room's owner and admins
+/// may change the listing. Listings are public: the name is stored and
+/// served in plaintext to anyone searching the directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryPublish {
+    /// Name to list the room under. `None` removes the listing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Search the room directory
+///
+/// Listed rooms are returned in room ID order, one page at a time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectorySearch {
+    /// Case-insensitive text the room name must contain. Empty matches
+    /// every listed room.
+    pub query: String,
+    /// Continue after this room, taken from a previous page's `next`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<u128>,
+    /// Most rooms wanted. The server may return fewer.
+    pub limit: u32,
+}
+
+/// A listed room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryEntry {
+    /// Room to join
+    pub room_id: u128,
+    /// Name the room is listed under
+    pub name: String,
+    /// Members the server knows of
+    pub member_count: u32,
+}
+
+/// One page of directory search results
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryResults {
+    /// Matching rooms in room ID order
+    pub rooms: Vec<DirectoryEntry>,
+    /// Cursor for the next page, if there are more matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<u128>,
+}
+
+/// Invitation to join a room
+///
+/// A member sends it for the server to relay to the invitee's session. The
+/// invitee joins by external commit if it accepts. The invite is signed
+/// with the inviter's MLS signature key for the room, so it can't be
+/// altered in transit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invite {
+    /// Room the invitee is asked to join
+    pub room_id: u128,
+    /// User who sent the invite
+    pub inviter: u64,
+    /// User invited
+    pub invitee: u64,
+    /// Unix time in seconds after which the invite is void
+    pub expires_at: u64,
+    /// Inviter's Ed25519 signature public key for the room
+    pub signer_key: Vec<u8>,
+    /// Ed25519 signature over [`Invite::signed_content`]
+    pub signature: Vec<u8>,
+}
+
+impl Invite {
+    /// Bytes the signature covers: every field but the key and signature,
+    /// in a fixed layout.
+    #[must_use]
+    pub fn signed_content(&self) -> Vec<u8> {
+        let mut content = b"lockframe-invite-v1".to_vec();
+        content.extend_from_slice(&self.room_id.to_be_bytes());
+        content.extend_from_slice(&self.inviter.to_be_bytes());
+        content.extend_from_slice(&self.invitee.to_be_bytes());
+        content.extend_from_slice(&self.expires_at.to_be_bytes());
+        content
+    }
+}
+
+/// Lowest log index of a room's frames dropped from a session's queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomGap {
+    /// Room the frames belonged to
+    pub room_id: u128,
+    /// Log index of the first dropped frame
+    pub from_log_index: u64,
+}
+
+/// Message from the server's operator, such as a maintenance window or a
+/// policy change
+///
+/// Sent to every session on the server, or to the sessions of one room. The
+/// text comes from the operator, not a member, and is not end-to-end
+/// encrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerNotice {
+    /// What the notice is about
+    pub kind: NoticeKind,
+    /// Text to show
+    pub text: String,
+    /// Room the notice is for (None = every session on the server)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub room_id: Option<u128>,
+}
+
+/// What a [`ServerNotice`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NoticeKind {
+    /// General announcement
+    Info,
+    /// Planned downtime or degraded service
+    Maintenance,
+    /// Change to the server's rules or terms
+    Policy,
 }
 
 #[cfg(test)]
@@ -172,7 +469,7 @@ mod tests {
 
     #[test]
     fn sync_request_serde() {
-        let request = SyncRequest { from_log_index: 42, limit: 50 };
+        let request = SyncRequest::new(42, 50);
 
         let mut bytes = Vec::new();
         ciborium::ser::into_writer(&request, &mut bytes).expect("encode");
@@ -184,7 +481,7 @@ mod tests {
     #[test]
     fn sync_request_default_limit() {
         // Encode without limit field
-        let request_no_limit = SyncRequest { from_log_index: 10, limit: default_limit() };
+        let request_no_limit = SyncRequest::new(10, default_limit());
 
         let mut bytes = Vec::new();
         ciborium::ser::into_writer(&request_no_limit, &mut bytes).expect("encode");
@@ -193,12 +490,37 @@ mod tests {
         assert_eq!(decoded.limit, 100); // default
     }
 
+    #[test]
+    fn filtered_sync_request_serde() {
+        let request = SyncRequest::new(0, 50).control_only().in_epochs(3, 7).from_sender(9, 12);
+        assert!(request.is_filtered());
+        assert!(!SyncRequest::new(0, 50).is_filtered());
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&request, &mut bytes).expect("encode");
+
+        let decoded: SyncRequest = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(request, decoded);
+    }
+
+    #[test]
+    fn history_truncated_serde() {
+        let tombstone = HistoryTruncated { first_log_index: 1_000 };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&tombstone, &mut bytes).expect("encode");
+
+        let decoded: HistoryTruncated = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(tombstone, decoded);
+    }
+
     #[test]
     fn sync_response_serde() {
         let response = SyncResponse {
             frames: vec![vec![1, 2, 3], vec![4, 5, 6]],
             has_more: true,
             server_epoch: 5,
+            next_log_index: Some(9),
         };
 
         let mut bytes = Vec::new();
@@ -207,4 +529,111 @@ mod tests {
         let decoded: SyncResponse = ciborium::de::from_reader(&bytes[..]).expect("decode");
         assert_eq!(response, decoded);
     }
+
+    #[test]
+    fn lagged_behind_serde() {
+        let notice =
+            LaggedBehind { dropped: 12, rooms: vec![RoomGap { room_id: 7, from_log_index: 40 }] };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&notice, &mut bytes).expect("encode");
+
+        let decoded: LaggedBehind = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(notice, decoded);
+    }
+
+    #[test]
+    fn presence_reply_serde() {
+        let reply = PresenceReply {
+            users: vec![
+                PresenceStatus { user_id: 1, online: true, last_seen_secs: None },
+                PresenceStatus { user_id: 2, online: false, last_seen_secs: Some(1_700_000_000) },
+            ],
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&reply, &mut bytes).expect("encode");
+
+        let decoded: PresenceReply = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(reply, decoded);
+    }
+
+    #[test]
+    fn directory_results_serde() {
+        let results = DirectoryResults {
+            rooms: vec![DirectoryEntry {
+                room_id: u128::MAX,
+                name: "rust".into(),
+                member_count: 3,
+            }],
+            next: Some(u128::MAX),
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&results, &mut bytes).expect("encode");
+
+        let decoded: DirectoryResults = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(results, decoded);
+    }
+
+    #[test]
+    fn invite_signature_covers_every_term() {
+        let invite = Invite {
+            room_id: 1,
+            inviter: 2,
+            invitee: 3,
+            expires_at: 4,
+            signer_key: vec![5; 32],
+            signature: vec![6; 64],
+        };
+        let content = invite.signed_content();
+        for altered in [
+            Invite { room_id: 9, ..invite.clone() },
+            Invite { inviter: 9, ..invite.clone() },
+            Invite { invitee: 9, ..invite.clone() },
+            Invite { expires_at: 9, ..invite.clone() },
+        ] {
+            assert_ne!(altered.signed_content(), content);
+        }
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&invite, &mut bytes).expect("encode");
+        let decoded: Invite = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(invite, decoded);
+    }
+
+    #[test]
+    fn server_notice_room_is_optional() {
+        let notice = ServerNotice {
+            kind: NoticeKind::Maintenance,
+            text: "Down for upgrades at 02:00 UTC".to_string(),
+            room_id: None,
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&notice, &mut bytes).expect("encode");
+        let decoded: ServerNotice = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(decoded, notice);
+
+        let room_notice = ServerNotice { room_id: Some(u128::MAX), ..notice };
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&room_notice, &mut bytes).expect("encode");
+        let decoded: ServerNotice = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(decoded, room_notice);
+    }
+
+    #[test]
+    fn goodbye_hint_is_optional() {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&Goodbye::new("bye"), &mut bytes).expect("encode");
+        let decoded: Goodbye = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(decoded, Goodbye::new("bye"));
+
+        let shutdown = Goodbye::server_shutdown(30);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&shutdown, &mut bytes).expect("encode");
+        let decoded: Goodbye = ciborium::de::from_reader(&bytes[..]).expect("decode");
+        assert_eq!(decoded.code, Some(Goodbye::SERVER_SHUTDOWN));
+        assert_eq!(decoded.retry_after, Some(30));
+    }
 }
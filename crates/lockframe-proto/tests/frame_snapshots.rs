@@ -101,7 +101,7 @@ fn snapshot_hello_reply_frame_with_challenge() {
 
 #[test]
 fn snapshot_goodbye_frame() {
-    let goodbye = Payload::Goodbye(Goodbye { reason: "client shutdown".to_string() });
+    let goodbye = Payload::Goodbye(Goodbye::new("client shutdown"));
 
     let frame = goodbye
         .into_frame(FrameHeader::new(Opcode::Goodbye))
@@ -290,6 +290,7 @@ fn snapshot_error_frame() {
         code: 400,
         message: "Invalid request".to_string(),
         retry_after: None,
+        error_id: None,
     });
 
     let frame =
@@ -304,6 +305,7 @@ fn snapshot_error_frame_with_retry() {
         code: 429,
         message: "Rate limit exceeded".to_string(),
         retry_after: Some(60),
+        error_id: None,
     });
 
     let frame =
@@ -0,0 +1,353 @@
+//! Room membership and roles.
+//!
+//! The server never sees MLS group state, so it keeps its own view of who
+//! belongs to each room, built from authenticated sender IDs: the creator
+//! becomes owner, Welcome recipients and external joiners become members, and
+//! moderation frames (Kick, Ban, Unban, `SetRole`) edit the list once they are
+//! sequenced. Frames from anyone else are rejected before sequencing so a
+//! stranger who learns a `room_id` cannot write to its log.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use lockframe_proto::{
+    Frame, Opcode, Payload,
+    payloads::{admin::RoomKind, moderation::RoomRole},
+};
+use serde::{Deserialize, Serialize};
+
+/// Members, roles and bans for one room.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomAcl {
+    members: BTreeMap<u64, RoomRole>,
+    banned: BTreeSet<u64>,
+}
+
+/// Why a sender may not perform an operation.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Denial {
+    /// Sender is not in the member list.
+    #[error("user {0} is not a member")]
+    NotMember(u64),
+
+    /// Sender is banned from the room.
+    #[error("user {0} is banned")]
+    Banned(u64),
+
+    /// Sender's role is below what the operation needs.
+    #[error("{opcode:?} requires {required:?}")]
+    InsufficientRole {
+        /// Operation attempted
+        opcode: Opcode,
+        /// Lowest role allowed to perform it
+        required: RoomRole,
+    },
+
+    /// Target's role is not below the sender's.
+    #[error("cannot moderate user {0} with an equal or higher role")]
+    Outranked(u64),
+
+    /// Operation is never allowed.
+    #[error("{0}")]
+    Forbidden(&'static str),
+
+    /// The room's kind does not take this frame.
+    #[error("{kind:?} rooms do not accept {opcode:?} from this sender")]
+    RoomPolicy {
+        /// Operation attempted
+        opcode: Opcode,
+        /// Kind of the room
+        kind: RoomKind,
+    },
+
+    /// The room's kind only relays this frame, but its sender did not flag
+    /// it ephemeral, so members would take it for a sequenced one.
+    #[error("{kind:?} rooms only relay {opcode:?} frames flagged ephemeral")]
+    NotEphemeral {
+        /// Operation attempted
+        opcode: Opcode,
+        /// Kind of the room
+        kind: RoomKind,
+    },
+
+    /// Moderation payload could not be decoded.
+    #[error("invalid moderation payload: {0}")]
+    InvalidPayload(String),
+}
+
+/// Membership change carried by an authorized frame, applied once the frame
+/// is sequenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AclChange {
+    /// Add as a plain member.
+    Admit(u64),
+    /// Remove without banning.
+    Remove(u64),
+    /// Remove and refuse future joins.
+    Ban(u64),
+    /// Allow future joins again.
+    Unban(u64),
+    /// Change an existing member's role.
+    SetRole(u64, RoomRole),
+}
+
+impl RoomAcl {
+    /// ACL for a new room with a single owner.
+    pub fn with_owner(owner: u64) -> Self {
+        Self { members: BTreeMap::from([(owner, RoomRole::Owner)]), banned: BTreeSet::new() }
+    }
+
+    /// Role of a member, or `None` for non-members.
+    pub fn role(&self, user_id: u64) -> Option<RoomRole> {
+        self.members.get(&user_id).copied()
+    }
+
+    /// Whether the user is a member.
+    pub fn is_member(&self, user_id: u64) -> bool {
+        self.members.contains_key(&user_id)
+    }
+
+    /// Whether the user is banned.
+    pub fn is_banned(&self, user_id: u64) -> bool {
+        self.banned.contains(&user_id)
+    }
+
+    /// Members and their roles, ordered by user ID.
+    pub fn members(&self) -> impl Iterator<Item = (u64, RoomRole)> + '_ {
+        self.members.iter().map(|(&user_id, &role)| (user_id, role))
+    }
+
+    /// Check whether `sender` may submit `frame`, returning the membership
+    /// change it carries.
+    pub(crate) fn authorize(
+        &self,
+        sender: u64,
+        frame: &Frame,
+    ) -> Result<Option<AclChange>, Denial> {
+        if self.is_banned(sender) {
+            return Err(Denial::Banned(sender));
+        }
+
+        let Some(opcode) = frame.header.opcode_enum() else {
+            return Err(Denial::Forbidden("unknown opcode"));
+        };
+
+        // External commits are how non-members join
+        if opcode == Opcode::ExternalCommit {
+            return Ok((!self.is_member(sender)).then_some(AclChange::Admit(sender)));
+        }
+
+        let role = self.role(sender).ok_or(Denial::NotMember(sender))?;
+        let require = |required: RoomRole| {
+            if role >= required {
+                Ok(())
+            } else {
+                Err(Denial::InsufficientRole { opcode, required })
+            }
+        };
+
+        match opcode {
+            Opcode::Redact | Opcode::Mute | Opcode::Pin | Opcode::DirectoryPublish => {
+                require(RoomRole::Admin).map(|()| None)
+            },
+            Opcode::CloseRoom => require(RoomRole::Owner).map(|()| None),
+            Opcode::Kick | Opcode::Ban | Opcode::Unban => {
+                require(RoomRole::Admin)?;
+                let change = match decode(frame)? {
+                    Payload::Kick(kick) => AclChange::Remove(kick.user_id),
+                    Payload::Ban(ban) => AclChange::Ban(ban.user_id),
+                    Payload::Unban(unban) => AclChange::Unban(unban.user_id),
+                    _ => {
+                        return Err(Denial::InvalidPayload(format!(
+                            "unexpected {opcode:?} payload"
+                        )));
+                    },
+                };
+                self.check_outranks(role, change)?;
+                Ok(Some(change))
+            },
+            Opcode::SetRole => {
+                require(RoomRole::Owner)?;
+                let Payload::SetRole(set_role) = decode(frame)? else {
+                    return Err(Denial::InvalidPayload("unexpected SetRole payload".to_string()));
+                };
+                if set_role.role == RoomRole::Owner {
+                    return Err(Denial::Forbidden("ownership cannot be transferred"));
+                }
+                if !self.is_member(set_role.user_id) {
+                    return Err(Denial::NotMember(set_role.user_id));
+                }
+                let change = AclChange::SetRole(set_role.user_id, set_role.role);
+                self.check_outranks(role, change)?;
+                Ok(Some(change))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Check whether `sender` may admit `user_id` with a Welcome.
+    pub(crate) fn authorize_welcome(&self, sender: u64, user_id: u64) -> Result<AclChange, Denial> {
+        self.authorize_invite(sender, user_id).map(|()| AclChange::Admit(user_id))
+    }
+
+    /// Check whether `sender` may invite `user_id` to join.
+    pub(crate) fn authorize_invite(&self, sender: u64, user_id: u64) -> Result<(), Denial> {
+        if self.is_banned(sender) {
+            return Err(Denial::Banned(sender));
+        }
+        if !self.is_member(sender) {
+            return Err(Denial::NotMember(sender));
+        }
+        if self.is_banned(user_id) {
+            return Err(Denial::Banned(user_id));
+        }
+        Ok(())
+    }
+
+    /// Apply a change returned by an authorization check.
+    pub(crate) fn apply(&mut self, change: AclChange) {
+        match change {
+            AclChange::Admit(user_id) => {
+                self.members.entry(user_id).or_insert(RoomRole::Member);
+            },
+            AclChange::Remove(user_id) => {
+                self.members.remove(&user_id);
+            },
+            AclChange::Ban(user_id) => {
+                self.members.remove(&user_id);
+                self.banned.insert(user_id);
+            },
+            AclChange::Unban(user_id) => {
+                self.banned.remove(&user_id);
+            },
+            AclChange::SetRole(user_id, role) => {
+                if let Some(current) = self.members.get_mut(&user_id) {
+                    *current = role;
+                }
+            },
+        }
+    }
+
+    /// Moderators may only act on users ranked strictly below them.
+    fn check_outranks(&self, role: RoomRole, change: AclChange) -> Result<(), Denial> {
+        let target = match change {
+            AclChange::Remove(user_id)
+            | AclChange::Ban(user_id)
+            | AclChange::Unban(user_id)
+            | AclChange::SetRole(user_id, _) => user_id,
+            AclChange::Admit(_) => return Ok(()),
+        };
+        match self.role(target) {
+            Some(target_role) if target_role >= role => Err(Denial::Outranked(target)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn decode(frame: &Frame) -> Result<Payload, Denial> {
+    Payload::from_frame(frame).map_err(|e| Denial::InvalidPayload(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::{
+        FrameHeader,
+        payloads::moderation::{Ban, Kick, SetRole},
+    };
+
+    use super::*;
+
+    const OWNER: u64 = 1;
+    const ADMIN: u64 = 2;
+    const MEMBER: u64 = 3;
+    const STRANGER: u64 = 4;
+
+    fn acl() -> RoomAcl {
+        let mut acl = RoomAcl::with_owner(OWNER);
+        acl.apply(AclChange::Admit(ADMIN));
+        acl.apply(AclChange::SetRole(ADMIN, RoomRole::Admin));
+        acl.apply(AclChange::Admit(MEMBER));
+        acl
+    }
+
+    fn frame(opcode: Opcode) -> Frame {
+        Frame::new(FrameHeader::new(opcode), Vec::new())
+    }
+
+    fn kick(user_id: u64) -> Frame {
+        Payload::Kick(Kick { user_id, reason: String::new(), moderator_id: 0 })
+            .into_frame(FrameHeader::new(Opcode::Kick))
+            .unwrap()
+    }
+
+    #[test]
+    fn members_may_send_strangers_may_not() {
+        let acl = acl();
+        assert_eq!(acl.authorize(MEMBER, &frame(Opcode::AppMessage)), Ok(None));
+        assert_eq!(
+            acl.authorize(STRANGER, &frame(Opcode::AppMessage)),
+            Err(Denial::NotMember(STRANGER))
+        );
+        assert_eq!(
+            acl.authorize(STRANGER, &frame(Opcode::ExternalCommit)),
+            Ok(Some(AclChange::Admit(STRANGER)))
+        );
+    }
+
+    #[test]
+    fn moderation_requires_admin_and_rank() {
+        let acl = acl();
+        assert!(matches!(
+            acl.authorize(MEMBER, &kick(ADMIN)),
+            Err(Denial::InsufficientRole { required: RoomRole::Admin, .. })
+        ));
+        assert_eq!(acl.authorize(ADMIN, &kick(MEMBER)), Ok(Some(AclChange::Remove(MEMBER))));
+        assert_eq!(acl.authorize(ADMIN, &kick(OWNER)), Err(Denial::Outranked(OWNER)));
+        assert_eq!(acl.authorize(OWNER, &kick(ADMIN)), Ok(Some(AclChange::Remove(ADMIN))));
+    }
+
+    #[test]
+    fn banned_users_cannot_rejoin() {
+        let mut acl = acl();
+        let ban = Payload::Ban(Ban {
+            user_id: MEMBER,
+            reason: String::new(),
+            duration_secs: None,
+            moderator_id: ADMIN,
+        })
+        .into_frame(FrameHeader::new(Opcode::Ban))
+        .unwrap();
+
+        let change = acl.authorize(ADMIN, &ban).unwrap().unwrap();
+        acl.apply(change);
+
+        assert!(!acl.is_member(MEMBER));
+        assert_eq!(
+            acl.authorize(MEMBER, &frame(Opcode::ExternalCommit)),
+            Err(Denial::Banned(MEMBER))
+        );
+        assert_eq!(acl.authorize_welcome(OWNER, MEMBER), Err(Denial::Banned(MEMBER)));
+    }
+
+    #[test]
+    fn only_owner_changes_roles() {
+        let acl = acl();
+        let promote = |user_id, role| {
+            Payload::SetRole(SetRole { user_id, role, moderator_id: 0 })
+                .into_frame(FrameHeader::new(Opcode::SetRole))
+                .unwrap()
+        };
+
+        assert_eq!(
+            acl.authorize(OWNER, &promote(MEMBER, RoomRole::Admin)),
+            Ok(Some(AclChange::SetRole(MEMBER, RoomRole::Admin)))
+        );
+        assert!(matches!(
+            acl.authorize(ADMIN, &promote(MEMBER, RoomRole::Admin)),
+            Err(Denial::InsufficientRole { required: RoomRole::Owner, .. })
+        ));
+        assert!(matches!(
+            acl.authorize(OWNER, &promote(MEMBER, RoomRole::Owner)),
+            Err(Denial::Forbidden(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+//! Operator access to a running server.
+//!
+//! Admin operations reach the driver two ways: an embedder can send
+//! [`ServerEvent::Admin`](crate::ServerEvent::Admin) directly, or an operator
+//! can connect like any client and send `AdminRequest` frames. Frames are only
+//! accepted on sessions whose Hello presented the configured [`AdminToken`];
+//! with no token configured the frame path is disabled entirely.
+
+use std::fmt;
+
+/// Shared secret that marks a session as an admin session.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdminToken(Vec<u8>);
+
+impl AdminToken {
+    /// Create a token from raw bytes.
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self(bytes.into())
+    }
+
+    /// Whether `presented` equals this token.
+    ///
+    /// Runs in time independent of where the inputs differ, so the token
+    /// cannot be recovered byte by byte from response timing.
+    pub fn matches(&self, presented: &[u8]) -> bool {
+        tokens_match(&self.0, presented)
+    }
+}
+
+/// Compare a presented secret with the expected one in constant time.
+pub(crate) fn tokens_match(expected: &[u8], presented: &[u8]) -> bool {
+    if presented.len() != expected.len() {
+        return false;
+    }
+    expected.iter().zip(presented).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+impl fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AdminToken(<redacted {} bytes>)", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_matching() {
+        let token = AdminToken::new(b"secret".to_vec());
+        assert!(token.matches(b"secret"));
+        assert!(!token.matches(b"secreT"));
+        assert!(!token.matches(b"secret!"));
+        assert!(!token.matches(b""));
+        assert_eq!(format!("{token:?}"), "AdminToken(<redacted 6 bytes>)");
+    }
+}
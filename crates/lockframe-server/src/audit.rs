@@ -0,0 +1,118 @@
+//! Audit trail.
+//!
+//! Failed authentication, limit trips, kicks, room closures, refused admin
+//! requests and members' message reports are appended to the [`AuditLog`]
+//! with a sequence number and wall-clock time. Entries name sessions, users,
+//! rooms and log indices, never message content, so operators can review
+//! them without access to conversations.
+//!
+//! The driver keeps the newest entries in memory for
+//! `AdminRequest::ExportAudit` and hands every new entry to the runtime as
+//! [`ServerAction::Audit`](crate::ServerAction::Audit), which appends it to
+//! durable storage.
+
+use std::collections::VecDeque;
+
+use lockframe_proto::payloads::admin::{AuditEntry, AuditEvent};
+
+/// Entries kept in memory for export. Older entries remain in the runtime's
+/// durable copy.
+const RETAINED_ENTRIES: usize = 10_000;
+
+/// Most entries returned in one export page.
+pub(crate) const MAX_EXPORT_PAGE: usize = 500;
+
+/// Longest report reason kept, in bytes.
+pub(crate) const MAX_REASON_BYTES: usize = 512;
+
+/// Append-only log of audit entries.
+#[derive(Debug, Default)]
+pub(crate) struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    next_seq: u64,
+}
+
+impl AuditLog {
+    /// Append an event, returning the entry recorded for it.
+    pub(crate) fn record(&mut self, at_secs: u64, event: AuditEvent) -> AuditEntry {
+        let entry = AuditEntry { seq: self.next_seq, at_secs, event };
+        self.next_seq += 1;
+        if self.entries.len() == RETAINED_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+        entry
+    }
+
+    /// Retained entries after sequence number `after`, oldest first, and the
+    /// cursor for the next page if more remain.
+    pub(crate) fn export(&self, after: Option<u64>, limit: u32) -> (Vec<AuditEntry>, Option<u64>) {
+        let limit = match usize::try_from(limit) {
+            Ok(0) | Err(_) => MAX_EXPORT_PAGE,
+            Ok(limit) => limit.min(MAX_EXPORT_PAGE),
+        };
+        let start = after.map_or(0, |after| self.entries.partition_point(|e| e.seq <= after));
+
+        let mut entries: Vec<AuditEntry> =
+            self.entries.iter().skip(start).take(limit + 1).cloned().collect();
+        let more = entries.len() > limit;
+        entries.truncate(limit);
+        let next = entries.last().filter(|_| more).map(|entry| entry.seq);
+        (entries, next)
+    }
+}
+
+/// Shorten a reason to at most [`MAX_REASON_BYTES`], on a character boundary.
+pub(crate) fn truncate_reason(mut reason: String) -> String {
+    if reason.len() > MAX_REASON_BYTES {
+        let end = (0..=MAX_REASON_BYTES).rev().find(|&i| reason.is_char_boundary(i)).unwrap_or(0);
+        reason.truncate(end);
+    }
+    reason
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(session_id: u64) -> AuditEvent {
+        AuditEvent::AdminDenied { session_id }
+    }
+
+    #[test]
+    fn export_pages_in_sequence_order() {
+        let mut log = AuditLog::default();
+        for session_id in 0..5 {
+            log.record(1_000 + session_id, denied(session_id));
+        }
+
+        let (page, next) = log.export(None, 2);
+        assert_eq!(page.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(next, Some(1));
+
+        let (page, next) = log.export(next, 10);
+        assert_eq!(page.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(page[0].at_secs, 1_002);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn oldest_entries_age_out_of_memory() {
+        let mut log = AuditLog::default();
+        for session_id in 0..=RETAINED_ENTRIES as u64 {
+            log.record(0, denied(session_id));
+        }
+
+        let (page, _) = log.export(None, 1);
+        assert_eq!(page[0].seq, 1);
+    }
+
+    #[test]
+    fn long_reasons_are_truncated() {
+        assert_eq!(truncate_reason("spam".into()), "spam");
+        let long = "é".repeat(MAX_REASON_BYTES);
+        let truncated = truncate_reason(long);
+        assert!(truncated.len() <= MAX_REASON_BYTES);
+        assert!(truncated.chars().all(|c| c == 'é'));
+    }
+}
@@ -0,0 +1,517 @@
+//! Client authentication.
+//!
+//! With an [`Authenticator`] configured, a Hello frame's `auth_token` is
+//! validated before the session is bound to a user. The principal the token
+//! names becomes the session's user, so every later room operation is
+//! authorized as that user. A Hello claiming a different `sender_id` is
+//! refused, and until a Hello is accepted the session may only send
+//! session-layer frames.
+//!
+//! Three authenticators ship with the server:
+//!
+//! - [`StaticTokens`]: a fixed table of opaque tokens
+//! - [`HmacTokens`]: self-contained tokens signed with a secret shared with
+//!   whatever issues them
+//! - [`OidcJwt`]: JWTs from an `OpenID Connect` provider, verified against the
+//!   provider's published signing keys
+//!
+//! Admin and federation tokens are checked first and bypass the
+//! authenticator. With no authenticator configured, sessions are identified
+//! by the `sender_id` they claim.
+//!
+//! Independently of tokens, a deployment can have the transport verify TLS
+//! client certificates. The certificate a connection presented arrives with
+//! it as a [`PeerIdentity`], and the driver can refuse connections without
+//! one before they send a Hello.
+
+use std::{collections::HashMap, fmt};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::{digest, hmac, signature};
+use serde::Deserialize;
+
+use crate::admin::tokens_match;
+
+/// Identity an auth token was issued to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Principal {
+    /// User the session acts as
+    pub user_id: u64,
+}
+
+/// TLS client certificate a connection presented, already verified by the
+/// transport against the configured client CA.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    certificate: Vec<u8>,
+    fingerprint: [u8; 32],
+}
+
+impl PeerIdentity {
+    /// Identity for a DER-encoded leaf certificate.
+    pub fn from_der(certificate: Vec<u8>) -> Self {
+        let mut fingerprint = [0u8; 32];
+        fingerprint.copy_from_slice(digest::digest(&digest::SHA256, &certificate).as_ref());
+        Self { certificate, fingerprint }
+    }
+
+    /// DER encoding of the certificate.
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    /// SHA-256 of the certificate's DER encoding, for pinning devices.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        self.fingerprint
+    }
+}
+
+impl fmt::Debug for PeerIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PeerIdentity(")?;
+        for byte in &self.fingerprint {
+            write!(f, "{byte:02x}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Why a token was refused.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AuthError {
+    /// The Hello carried no token
+    #[error("no auth token presented")]
+    Missing,
+    /// The token is not in the format the authenticator expects
+    #[error("malformed token: {0}")]
+    Malformed(String),
+    /// The token is unknown or its signature does not verify
+    #[error("invalid token")]
+    Invalid,
+    /// The token's lifetime has ended
+    #[error("token expired")]
+    Expired,
+    /// The token is genuine but its claims are not acceptable here
+    #[error("token rejected: {0}")]
+    Rejected(String),
+}
+
+/// Validates Hello auth tokens.
+pub trait Authenticator: Send + Sync + fmt::Debug {
+    /// Validate `token`, returning who it was issued to.
+    ///
+    /// `now_secs` is the current Unix time, for checking expiry.
+    ///
+    /// # Errors
+    ///
+    /// An [`AuthError`] saying why the token was refused.
+    fn authenticate(&self, token: &[u8], now_secs: u64) -> Result<Principal, AuthError>;
+}
+
+/// A fixed table of opaque tokens, each naming one user.
+#[derive(Clone, Default)]
+pub struct StaticTokens {
+    tokens: Vec<(Vec<u8>, u64)>,
+}
+
+impl StaticTokens {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a token that authenticates as `user_id`.
+    #[must_use]
+    pub fn with_token(mut self, token: impl Into<Vec<u8>>, user_id: u64) -> Self {
+        self.tokens.push((token.into(), user_id));
+        self
+    }
+}
+
+impl Authenticator for StaticTokens {
+    fn authenticate(&self, token: &[u8], _now_secs: u64) -> Result<Principal, AuthError> {
+        // Compare against every entry so timing does not reveal which matched
+        self.tokens
+            .iter()
+            .fold(
+                None,
+                |found, (expected, user_id)| {
+                    if tokens_match(expected, token) { Some(*user_id) } else { found }
+                },
+            )
+            .map(|user_id| Principal { user_id })
+            .ok_or(AuthError::Invalid)
+    }
+}
+
+impl fmt::Debug for StaticTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StaticTokens(<{} tokens>)", self.tokens.len())
+    }
+}
+
+/// Tokens of the form `<user_id>.<expires_at>.<signature>`, where the
+/// signature is the unpadded base64url HMAC-SHA256 of `<user_id>.<expires_at>`
+/// under a shared secret and `expires_at` is a Unix time.
+#[derive(Clone)]
+pub struct HmacTokens {
+    key: hmac::Key,
+}
+
+impl HmacTokens {
+    /// Create an authenticator for tokens signed with `secret`.
+    pub fn new(secret: &[u8]) -> Self {
+        Self { key: hmac::Key::new(hmac::HMAC_SHA256, secret) }
+    }
+
+    /// Issue a token for `user_id` that expires at `expires_at`.
+    pub fn issue(&self, user_id: u64, expires_at: u64) -> Vec<u8> {
+        let claims = format!("{user_id}.{expires_at}");
+        let tag = hmac::sign(&self.key, claims.as_bytes());
+        format!("{claims}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref())).into_bytes()
+    }
+}
+
+impl Authenticator for HmacTokens {
+    fn authenticate(&self, token: &[u8], now_secs: u64) -> Result<Principal, AuthError> {
+        let malformed =
+            || AuthError::Malformed("expected <user_id>.<expires_at>.<signature>".into());
+
+        let token = std::str::from_utf8(token).map_err(|_| malformed())?;
+        let (claims, tag) = token.rsplit_once('.').ok_or_else(malformed)?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).map_err(|_| malformed())?;
+        hmac::verify(&self.key, claims.as_bytes(), &tag).map_err(|_| AuthError::Invalid)?;
+
+        let (user_id, expires_at) = claims.split_once('.').ok_or_else(malformed)?;
+        let user_id = user_id.parse().map_err(|_| malformed())?;
+        let expires_at: u64 = expires_at.parse().map_err(|_| malformed())?;
+        if now_secs >= expires_at {
+            return Err(AuthError::Expired);
+        }
+        Ok(Principal { user_id })
+    }
+}
+
+impl fmt::Debug for HmacTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HmacTokens(<redacted>)")
+    }
+}
+
+/// JWTs issued by an `OpenID Connect` provider.
+///
+/// Tokens must be signed with RS256 or ES256 by a key from the provider's
+/// JWKS document, name the expected issuer and audience, and be within their
+/// `nbf`/`exp` window. The user ID is read from a numeric claim, `sub` by
+/// default. Fetching and refreshing the JWKS document is left to the
+/// embedder; rebuild the authenticator when the provider rotates keys.
+#[derive(Clone)]
+pub struct OidcJwt {
+    issuer: String,
+    audience: String,
+    keys: HashMap<String, VerificationKey>,
+    user_id_claim: String,
+    leeway_secs: u64,
+}
+
+/// A public key from a JWKS document.
+#[derive(Clone)]
+enum VerificationKey {
+    /// RSA modulus and exponent, big-endian
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    /// Uncompressed P-256 point
+    EcP256(Vec<u8>),
+}
+
+/// One entry of a JWKS document. Fields for key types we do not support are
+/// ignored.
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: String,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    #[serde(default)]
+    kid: String,
+}
+
+/// The claims checked on every token. The user ID claim is looked up
+/// separately since its name is configurable.
+#[derive(Deserialize)]
+struct StandardClaims {
+    iss: String,
+    aud: Audience,
+    exp: u64,
+    #[serde(default)]
+    nbf: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, audience: &str) -> bool {
+        match self {
+            Self::One(aud) => aud == audience,
+            Self::Many(auds) => auds.iter().any(|aud| aud == audience),
+        }
+    }
+}
+
+impl OidcJwt {
+    /// Create an authenticator from the provider's JWKS document.
+    ///
+    /// # Errors
+    ///
+    /// `AuthError::Malformed` if the document cannot be parsed or holds no
+    /// RSA or P-256 keys.
+    pub fn from_jwks(
+        issuer: impl Into<String>,
+        audience: impl Into<String>,
+        jwks: &[u8],
+    ) -> Result<Self, AuthError> {
+        let set: JwkSet = serde_json::from_slice(jwks)
+            .map_err(|e| AuthError::Malformed(format!("invalid JWKS: {e}")))?;
+
+        let mut keys = HashMap::new();
+        for jwk in set.keys {
+            if let Some(key) = VerificationKey::from_jwk(&jwk) {
+                keys.insert(jwk.kid, key);
+            }
+        }
+        if keys.is_empty() {
+            return Err(AuthError::Malformed("JWKS holds no usable keys".into()));
+        }
+
+        Ok(Self {
+            issuer: issuer.into(),
+            audience: audience.into(),
+            keys,
+            user_id_claim: "sub".into(),
+            leeway_secs: 60,
+        })
+    }
+
+    /// Read the user ID from `claim` instead of `sub`.
+    #[must_use]
+    pub fn with_user_id_claim(mut self, claim: impl Into<String>) -> Self {
+        self.user_id_claim = claim.into();
+        self
+    }
+
+    /// Clock skew tolerated when checking `exp` and `nbf`. Defaults to 60
+    /// seconds.
+    #[must_use]
+    pub fn with_leeway(mut self, leeway_secs: u64) -> Self {
+        self.leeway_secs = leeway_secs;
+        self
+    }
+
+    fn key_for(&self, kid: &str) -> Option<&VerificationKey> {
+        match self.keys.get(kid) {
+            Some(key) => Some(key),
+            // A token without a `kid` is only unambiguous with a single key
+            None if kid.is_empty() && self.keys.len() == 1 => self.keys.values().next(),
+            None => None,
+        }
+    }
+
+    fn check_claims(&self, claims: &[u8], now_secs: u64) -> Result<Principal, AuthError> {
+        let invalid = |e: serde_json::Error| AuthError::Malformed(format!("invalid claims: {e}"));
+        let claims: serde_json::Value = serde_json::from_slice(claims).map_err(invalid)?;
+        let standard = StandardClaims::deserialize(&claims).map_err(invalid)?;
+
+        if standard.iss != self.issuer {
+            return Err(AuthError::Rejected(format!("unexpected issuer {}", standard.iss)));
+        }
+        if !standard.aud.contains(&self.audience) {
+            return Err(AuthError::Rejected("token is for another audience".into()));
+        }
+        if now_secs >= standard.exp.saturating_add(self.leeway_secs) {
+            return Err(AuthError::Expired);
+        }
+        if standard.nbf.is_some_and(|nbf| now_secs.saturating_add(self.leeway_secs) < nbf) {
+            return Err(AuthError::Rejected("token is not yet valid".into()));
+        }
+
+        let user_id = match claims.get(&self.user_id_claim) {
+            Some(serde_json::Value::Number(n)) => n.as_u64(),
+            Some(serde_json::Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        user_id.map(|user_id| Principal { user_id }).ok_or_else(|| {
+            AuthError::Rejected(format!("claim {} is not a user ID", self.user_id_claim))
+        })
+    }
+}
+
+impl Authenticator for OidcJwt {
+    fn authenticate(&self, token: &[u8], now_secs: u64) -> Result<Principal, AuthError> {
+        let malformed = |what: &str| AuthError::Malformed(format!("invalid JWT {what}"));
+
+        let token = std::str::from_utf8(token).map_err(|_| malformed("encoding"))?;
+        let (signed, signature) = token.rsplit_once('.').ok_or_else(|| malformed("structure"))?;
+        let (header, claims) = signed.split_once('.').ok_or_else(|| malformed("structure"))?;
+
+        let header = URL_SAFE_NO_PAD.decode(header).map_err(|_| malformed("header"))?;
+        let header: JwtHeader = serde_json::from_slice(&header).map_err(|_| malformed("header"))?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| malformed("signature"))?;
+
+        let key = self.key_for(&header.kid).ok_or(AuthError::Invalid)?;
+        key.verify(&header.alg, signed.as_bytes(), &signature)?;
+
+        let claims = URL_SAFE_NO_PAD.decode(claims).map_err(|_| malformed("claims"))?;
+        self.check_claims(&claims, now_secs)
+    }
+}
+
+impl fmt::Debug for OidcJwt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcJwt")
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("keys", &self.keys.len())
+            .field("user_id_claim", &self.user_id_claim)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VerificationKey {
+    fn from_jwk(jwk: &Jwk) -> Option<Self> {
+        let decode = |field: &Option<String>| URL_SAFE_NO_PAD.decode(field.as_deref()?).ok();
+        match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => Some(Self::Rsa { n: decode(&jwk.n)?, e: decode(&jwk.e)? }),
+            ("EC", Some("P-256")) => {
+                let mut point = vec![0x04];
+                point.extend(decode(&jwk.x)?);
+                point.extend(decode(&jwk.y)?);
+                Some(Self::EcP256(point))
+            },
+            _ => None,
+        }
+    }
+
+    fn verify(&self, alg: &str, message: &[u8], sig: &[u8]) -> Result<(), AuthError> {
+        let verified = match (alg, self) {
+            ("RS256", Self::Rsa { n, e }) => signature::RsaPublicKeyComponents { n, e }.verify(
+                &signature::RSA_PKCS1_2048_8192_SHA256,
+                message,
+                sig,
+            ),
+            ("ES256", Self::EcP256(point)) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, sig)
+            },
+            _ => return Err(AuthError::Rejected(format!("algorithm {alg} not accepted"))),
+        };
+        verified.map_err(|_| AuthError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{
+        rand::SystemRandom,
+        signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair},
+    };
+
+    use super::*;
+
+    const NOW: u64 = 1_704_067_200;
+
+    #[test]
+    fn static_tokens() {
+        let auth =
+            StaticTokens::new().with_token(b"alice".to_vec(), 1).with_token(b"bob".to_vec(), 2);
+        assert_eq!(auth.authenticate(b"bob", NOW), Ok(Principal { user_id: 2 }));
+        assert_eq!(auth.authenticate(b"carol", NOW), Err(AuthError::Invalid));
+        assert_eq!(format!("{auth:?}"), "StaticTokens(<2 tokens>)");
+    }
+
+    #[test]
+    fn hmac_tokens() {
+        let auth = HmacTokens::new(b"shared secret");
+        let token = auth.issue(42, NOW + 60);
+        assert_eq!(auth.authenticate(&token, NOW), Ok(Principal { user_id: 42 }));
+        assert_eq!(auth.authenticate(&token, NOW + 60), Err(AuthError::Expired));
+
+        // Claims cannot be altered without the secret
+        let forged = String::from_utf8(token).unwrap().replacen("42", "43", 1);
+        assert_eq!(auth.authenticate(forged.as_bytes(), NOW), Err(AuthError::Invalid));
+        let other = HmacTokens::new(b"another secret").issue(42, NOW + 60);
+        assert_eq!(auth.authenticate(&other, NOW), Err(AuthError::Invalid));
+        assert!(matches!(auth.authenticate(b"garbage", NOW), Err(AuthError::Malformed(_))));
+    }
+
+    /// An ES256 provider: its JWKS document and a token signer.
+    fn provider() -> (Vec<u8>, impl Fn(serde_json::Value) -> Vec<u8>) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        let point = pair.public_key().as_ref();
+        let jwks = serde_json::json!({ "keys": [{
+            "kty": "EC", "crv": "P-256", "kid": "k1",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        }] });
+
+        let sign = move |claims: serde_json::Value| {
+            let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","kid":"k1"}"#);
+            let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let signed = format!("{header}.{claims}");
+            let sig = pair.sign(&SystemRandom::new(), signed.as_bytes()).unwrap();
+            format!("{signed}.{}", URL_SAFE_NO_PAD.encode(sig.as_ref())).into_bytes()
+        };
+        (jwks.to_string().into_bytes(), sign)
+    }
+
+    #[test]
+    fn oidc_jwt() {
+        let (jwks, sign) = provider();
+        let auth = OidcJwt::from_jwks("https://idp.example", "lockframe", &jwks).unwrap();
+
+        let claims = |iss: &str, exp: u64| serde_json::json!({ "iss": iss, "aud": ["lockframe"], "exp": exp, "sub": "7" });
+        let token = sign(claims("https://idp.example", NOW + 300));
+        assert_eq!(auth.authenticate(&token, NOW), Ok(Principal { user_id: 7 }));
+
+        let expired = sign(claims("https://idp.example", NOW - 300));
+        assert_eq!(auth.authenticate(&expired, NOW), Err(AuthError::Expired));
+        let foreign = sign(claims("https://evil.example", NOW + 300));
+        assert!(matches!(auth.authenticate(&foreign, NOW), Err(AuthError::Rejected(_))));
+
+        // A token signed by another provider's key does not verify
+        let (_, other_sign) = provider();
+        let forged = other_sign(claims("https://idp.example", NOW + 300));
+        assert_eq!(auth.authenticate(&forged, NOW), Err(AuthError::Invalid));
+
+        let by_uid = auth.with_user_id_claim("uid");
+        let token = sign(serde_json::json!({
+            "iss": "https://idp.example", "aud": "lockframe", "exp": NOW + 300, "uid": 99,
+        }));
+        assert_eq!(by_uid.authenticate(&token, NOW), Ok(Principal { user_id: 99 }));
+    }
+}
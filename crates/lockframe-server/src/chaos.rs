@@ -0,0 +1,112 @@
+//! Fault injection for soak tests.
+//!
+//! The simulation harness finds ordering bugs under a virtual network, but
+//! says little about how a real binary behaves when storage stalls or
+//! subscribers fall behind. With a [`ChaosConfig`] set, the production
+//! runtime holds up or drops frames as they pass from the accept stage to
+//! the persist stage and from the persist stage to the broadcast stage (see
+//! the `pipeline` module), so queue buildup and recovery can be watched
+//! under real load.
+//!
+//! A frame dropped before persisting fails like any write: the frames of
+//! its room queued behind it are dropped too, and the driver resequences the
+//! room from storage so the next frame reuses the lost index. The log stays
+//! contiguous; the senders of the dropped frames get no echo. A frame
+//! dropped before broadcasting is stored but never sent, so subscribers only
+//! see it through sync. Delays and drops are drawn from the
+//! [`Environment`](lockframe_core::env::Environment) RNG.
+
+use std::time::Duration;
+
+use lockframe_core::env::Environment;
+
+/// Faults injected between pipeline stages. Never enable in production.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosConfig {
+    /// Faults for sequenced frames on their way to storage
+    pub persist: ChaosFault,
+    /// Faults for stored frames on their way to subscribers
+    pub broadcast: ChaosFault,
+}
+
+/// Faults injected at one point of the pipeline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosFault {
+    /// Longest a frame is held up. Each frame waits a random time up to it.
+    pub max_delay: Duration,
+    /// Frames dropped per thousand
+    pub drop_per_mille: u32,
+}
+
+/// Injects the faults of a [`ChaosConfig`] using an environment's clock and
+/// RNG.
+#[derive(Debug, Clone)]
+pub(crate) struct Chaos<E> {
+    config: ChaosConfig,
+    env: E,
+}
+
+impl<E: Environment> Chaos<E> {
+    pub(crate) fn new(config: ChaosConfig, env: E) -> Self {
+        Self { config, env }
+    }
+
+    /// Hold up a frame on its way to storage. Returns false if it is to be
+    /// dropped.
+    pub(crate) async fn before_persist(&self) -> bool {
+        self.inject(self.config.persist).await
+    }
+
+    /// Hold up a stored frame on its way to subscribers. Returns false if it
+    /// is to be dropped.
+    pub(crate) async fn before_broadcast(&self) -> bool {
+        self.inject(self.config.broadcast).await
+    }
+
+    async fn inject(&self, fault: ChaosFault) -> bool {
+        match self.draw(fault) {
+            Some(delay) if delay.is_zero() => true,
+            Some(delay) => {
+                self.env.sleep(delay).await;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// How long one frame waits, or `None` if it is dropped.
+    fn draw(&self, fault: ChaosFault) -> Option<Duration> {
+        if fault.drop_per_mille > 0
+            && self.env.random_u64() % 1000 < u64::from(fault.drop_per_mille)
+        {
+            return None;
+        }
+        let max_nanos = u64::try_from(fault.max_delay.as_nanos()).unwrap_or(u64::MAX);
+        if max_nanos == 0 {
+            return Some(Duration::ZERO);
+        }
+        Some(Duration::from_nanos(self.env.random_u64() % max_nanos.saturating_add(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_core::env::test_utils::MockEnv;
+
+    use super::*;
+
+    #[test]
+    fn faults_stay_within_their_limits() {
+        let fault = ChaosFault { max_delay: Duration::from_millis(5), drop_per_mille: 250 };
+        let chaos =
+            Chaos::new(ChaosConfig { persist: fault, ..Default::default() }, MockEnv::new());
+
+        let draws: Vec<_> = (0..1000).map(|_| chaos.draw(fault)).collect();
+        let dropped = draws.iter().filter(|draw| draw.is_none()).count();
+        assert!((150..350).contains(&dropped), "dropped {dropped} of 1000");
+        assert!(draws.iter().flatten().all(|delay| *delay <= fault.max_delay));
+
+        // A fault-free stage passes everything straight through
+        assert_eq!(chaos.draw(chaos.config.broadcast), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,110 @@
+//! Room directory.
+//!
+//! Rooms are unlisted until their owner or an admin publishes a listing with
+//! a `DirectoryPublish` frame. The listing name is kept in the room's
+//! metadata, so it survives restarts, and `DirectorySearch` pages through
+//! listed rooms in room ID order. Closed rooms drop out of the directory.
+
+use lockframe_proto::payloads::session::{DirectoryEntry, DirectoryResults, DirectorySearch};
+
+use crate::room_manager::RoomManager;
+
+/// Longest listing name accepted, in bytes.
+pub(crate) const MAX_NAME_BYTES: usize = 64;
+
+/// Most rooms returned in one page.
+pub(crate) const MAX_PAGE: usize = 50;
+
+/// A listing name with surrounding whitespace removed, if it is acceptable.
+pub(crate) fn listing_name(name: &str) -> Result<String, &'static str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("listing name is empty");
+    }
+    if name.len() > MAX_NAME_BYTES {
+        return Err("listing name is too long");
+    }
+    if name.chars().any(char::is_control) {
+        return Err("listing name contains control characters");
+    }
+    Ok(name.to_string())
+}
+
+/// One page of listed rooms matching `search`.
+pub(crate) fn search(rooms: &RoomManager, search: &DirectorySearch) -> DirectoryResults {
+    let query = search.query.trim().to_lowercase();
+    let limit = match usize::try_from(search.limit) {
+        Ok(0) | Err(_) => MAX_PAGE,
+        Ok(limit) => limit.min(MAX_PAGE),
+    };
+
+    let mut matches: Vec<DirectoryEntry> = rooms
+        .room_ids()
+        .filter(|&room_id| search.after.is_none_or(|after| room_id > after))
+        .filter_map(|room_id| {
+            let metadata = rooms.metadata(room_id)?;
+            let name = metadata.listing.as_ref().filter(|_| metadata.closed_at.is_none())?;
+            name.to_lowercase().contains(&query).then(|| DirectoryEntry {
+                room_id,
+                name: name.clone(),
+                member_count: u32::try_from(metadata.acl.members().count()).unwrap_or(u32::MAX),
+            })
+        })
+        .collect();
+    matches.sort_unstable_by_key(|entry| entry.room_id);
+
+    let more = matches.len() > limit;
+    matches.truncate(limit);
+    let next = matches.last().filter(|_| more).map(|entry| entry.room_id);
+    DirectoryResults { rooms: matches, next }
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_core::env::test_utils::MockEnv;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn listed_rooms(names: &[(u128, &str)]) -> RoomManager {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut rooms = RoomManager::new();
+        for &(room_id, name) in names {
+            rooms.create_room(room_id, 1, &env, &storage).unwrap();
+            rooms.set_listing(room_id, Some(name.to_string()), &storage).unwrap();
+        }
+        rooms.create_room(99, 1, &env, &storage).unwrap();
+        rooms
+    }
+
+    #[test]
+    fn search_pages_through_matching_rooms() {
+        let rooms = listed_rooms(&[(3, "Rust help"), (1, "rustaceans"), (2, "Go"), (4, "RUST")]);
+        let query = |after, limit| DirectorySearch { query: "rust".into(), after, limit };
+
+        let page = search(&rooms, &query(None, 2));
+        let ids: Vec<u128> = page.rooms.iter().map(|entry| entry.room_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(page.next, Some(3));
+        assert_eq!(page.rooms[0].member_count, 1);
+
+        let page = search(&rooms, &query(page.next, 2));
+        assert_eq!(page.rooms.len(), 1);
+        assert_eq!(page.rooms[0].name, "RUST");
+        assert_eq!(page.next, None);
+
+        // Unlisted rooms never appear
+        let everything =
+            search(&rooms, &DirectorySearch { query: String::new(), after: None, limit: 0 });
+        assert_eq!(everything.rooms.len(), 4);
+    }
+
+    #[test]
+    fn listing_names_are_validated() {
+        assert_eq!(listing_name("  lobby "), Ok("lobby".to_string()));
+        assert!(listing_name("   ").is_err());
+        assert!(listing_name(&"x".repeat(MAX_NAME_BYTES + 1)).is_err());
+        assert!(listing_name("bell\u{7}").is_err());
+    }
+}
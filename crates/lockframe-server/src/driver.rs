@@ -3,28 +3,51 @@
 //! Ties together connection state machines, `RoomManager` (MLS validation +
 //! sequencing), `ConnectionRegistry` (session-to-room mapping), and storage.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use lockframe_core::{
     connection::{Connection, ConnectionAction, ConnectionConfig},
     env::Environment,
 };
 use lockframe_proto::{
-    Frame, FrameHeader, Opcode, Payload,
+    ErrorId, Frame, FrameHeader, Opcode, Payload,
     payloads::{
         ErrorPayload,
-        mls::{GroupInfoPayload, KeyPackageFetchPayload},
-        session::SyncResponse,
+        admin::{AdminRequest, AdminResponse, AuditEntry, AuditEvent, RoomInfo, ServerStats},
+        federation::{FedAppend, FedNack},
+        mls::{
+            GroupInfoPayload, KeyPackageFetchPayload, KeyPackageLowStockPayload,
+            KeyPackagePublishRequest,
+        },
+        moderation::ReportMessage,
+        session::{
+            DirectoryPublish, Goodbye, PresenceQuery, PresenceReply, PresenceStatus, ServerNotice,
+            SyncResponse,
+        },
     },
 };
 
 use crate::{
-    RoomError,
-    key_package_registry::{KeyPackageEntry, KeyPackageRegistry, StoreResult},
+    Denial, RoomError,
+    admin::AdminToken,
+    audit::{self, AuditLog},
+    auth::{AuthError, Authenticator, PeerIdentity, Principal},
+    chaos::ChaosConfig,
+    directory,
+    expiry::Expiry,
+    federation::{Federation, FederationConfig},
+    intercept::{FrameContext, FrameInterceptor, Intercept, Interceptors},
+    key_package_store::{
+        Claimed, KeyPackageEntry, KeyPackageStore, KeyPackageStoreConfig, StoreResult,
+    },
+    log::{LogEvent, LogLevel, LogTarget},
+    presence::{MAX_QUERY_USERS, Presence, PresenceConfig, PresenceVisibility},
     registry::{ConnectionRegistry, SessionInfo},
+    retention::{Retention, RetentionConfig, RetentionPolicy},
     room_manager::{RoomAction, RoomManager},
     server_error::ServerError,
     storage::{Storage, StorageError},
+    webhook::{self, WebhookConfig, WebhookDelivery, Webhooks},
 };
 
 /// Server configuration
@@ -34,11 +57,58 @@ pub struct ServerConfig {
     pub connection: ConnectionConfig,
     /// Maximum concurrent connections
     pub max_connections: usize,
+    /// History retention and compaction schedule
+    pub retention: RetentionConfig,
+    /// `KeyPackage` pool limits and lifetime
+    pub key_packages: KeyPackageStoreConfig,
+    /// Token that makes a session an admin session when presented in Hello.
+    /// `None` disables admin frames.
+    pub admin_token: Option<AdminToken>,
+    /// Validates the `auth_token` of client Hellos. `None` trusts the
+    /// `sender_id` a client claims.
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    /// Close connections that presented no TLS client certificate before
+    /// they can send a Hello
+    pub require_client_certificate: bool,
+    /// Most bytes of history one room may store. `None` is unlimited.
+    pub room_quota_bytes: Option<u64>,
+    /// Number of shards rooms are partitioned across. Only
+    /// [`ServerEvent::FrameBatch`] processes shards in parallel.
+    pub room_shards: usize,
+    /// Peer servers and the rooms they home
+    pub federation: FederationConfig,
+    /// Who may see whether users are online
+    pub presence: PresenceConfig,
+    /// Reconnect delay suggested to clients when the server shuts down
+    pub shutdown_retry_after: Duration,
+    /// How long a shutdown waits for sessions to leave before closing them
+    pub drain_timeout: Duration,
+    /// Room events forwarded to HTTP endpoints
+    pub webhooks: WebhookConfig,
+    /// Delays and drops the runtime injects between pipeline stages, for
+    /// soak tests. `None` injects none. Cannot change while running.
+    pub chaos: Option<ChaosConfig>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
-        Self { connection: ConnectionConfig::default(), max_connections: 10_000 }
+        Self {
+            connection: ConnectionConfig::default(),
+            max_connections: 10_000,
+            retention: RetentionConfig::default(),
+            key_packages: KeyPackageStoreConfig::default(),
+            admin_token: None,
+            authenticator: None,
+            require_client_certificate: false,
+            room_quota_bytes: None,
+            room_shards: 1,
+            federation: FederationConfig::default(),
+            presence: PresenceConfig::default(),
+            shutdown_retry_after: Duration::from_secs(5),
+            drain_timeout: Duration::from_secs(30),
+            webhooks: WebhookConfig::default(),
+            chaos: None,
+        }
     }
 }
 
@@ -51,6 +121,8 @@ pub enum ServerEvent {
     ConnectionAccepted {
         /// Unique connection ID assigned by the runtime
         session_id: u64,
+        /// TLS client certificate the connection presented, if any
+        peer_identity: Option<PeerIdentity>,
     },
 
     /// A frame was received from a connection
@@ -61,6 +133,16 @@ pub enum ServerEvent {
         frame: Frame,
     },
 
+    /// Several frames read together, possibly from different sessions.
+    ///
+    /// Application messages are sequenced as one batch so rooms on different
+    /// shards are processed in parallel. Every other frame is handled as if
+    /// it arrived in its own `FrameReceived`, in order.
+    FrameBatch {
+        /// `(session_id, frame)` pairs in arrival order
+        frames: Vec<(u64, Frame)>,
+    },
+
     /// A connection was closed (by peer or error)
     ConnectionClosed {
         /// Connection that was closed
@@ -71,6 +153,47 @@ pub enum ServerEvent {
 
     /// Periodic tick for timeout checking
     Tick,
+
+    /// Operator request from the embedding runtime
+    Admin {
+        /// Operation to perform
+        request: AdminRequest,
+    },
+
+    /// Replace the driver configuration without restarting.
+    ///
+    /// The new configuration is validated first and ignored if invalid.
+    /// Timeouts apply to existing sessions from the next `Tick`; connection
+    /// limits apply to new connections only. `room_shards` cannot change
+    /// while the server runs.
+    ConfigUpdated(Box<ServerConfig>),
+
+    /// A [`ServerAction::PersistFrame`] could not be written.
+    ///
+    /// The room is resequenced from storage, so its next frame reuses
+    /// `log_index`. The runtime must send this only once none of the room's
+    /// frames is waiting to be written or being written, or the index could
+    /// be handed out twice. Frames of the room queued behind the failed one
+    /// are not written, and are listed in `dropped`.
+    PersistFailed {
+        /// Room the frame belongs to
+        room_id: u128,
+        /// Log index the frame was given
+        log_index: u64,
+        /// Why the write failed
+        error: StorageError,
+        /// Log indices of the room's later frames dropped behind the failed
+        /// one. Their senders get no echo.
+        dropped: Vec<u64>,
+    },
+
+    /// Start draining the server.
+    ///
+    /// New connections are refused and every session is sent a Goodbye
+    /// asking it to reconnect later. Sessions keep being served until they
+    /// leave or [`ServerConfig::drain_timeout`] passes (checked on `Tick`),
+    /// then storage is flushed and [`ServerAction::DrainComplete`] is emitted.
+    BeginShutdown,
 }
 
 /// Actions that the server driver produces.
@@ -104,28 +227,45 @@ pub enum ServerAction<I = std::time::Instant> {
         reason: String,
     },
 
-    /// Log a message (for debugging/monitoring)
-    Log {
-        /// Log level
-        level: LogLevel,
-        /// Message to log
-        message: String,
-        /// When the event occurred
-        timestamp: I,
+    /// Emit a structured log event (for debugging/monitoring)
+    Log(LogEvent<I>),
+
+    /// Result of a [`ServerEvent::Admin`] request
+    AdminReply(AdminResponse),
+
+    /// Append an entry to the durable audit log. Entries arrive in sequence
+    /// order.
+    Audit(AuditEntry),
+
+    /// Post a signed room event to a webhook, retrying on failure
+    DeliverWebhook(WebhookDelivery),
+
+    /// Write a sequenced frame to storage, then execute `then`.
+    ///
+    /// Only produced with [`ServerDriver::set_pipelined_persistence`]. The
+    /// frames of a room must be written in the order they were produced, and
+    /// none of `then` may run before the write succeeds. A failed write is
+    /// reported back with [`ServerEvent::PersistFailed`].
+    PersistFrame {
+        /// Room the frame belongs to
+        room_id: u128,
+        /// Log index the frame was given
+        log_index: u64,
+        /// Frame to store
+        frame: Frame,
+        /// Actions waiting for the frame to be stored, such as its broadcast
+        then: Vec<ServerAction<I>>,
     },
+
+    /// A shutdown finished draining and storage has been flushed. Emitted
+    /// once; the runtime can exit after executing the actions before it.
+    DrainComplete,
 }
 
-/// Log levels for server actions
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum LogLevel {
-    /// Debug information
-    Debug,
-    /// Informational message
-    Info,
-    /// Warning
-    Warn,
-    /// Error
-    Error,
+impl<I> From<LogEvent<I>> for ServerAction<I> {
+    fn from(event: LogEvent<I>) -> Self {
+        Self::Log(event)
+    }
 }
 
 /// Action-based server driver.
@@ -142,12 +282,32 @@ where
     pub(crate) registry: ConnectionRegistry,
     /// Room manager (routing + sequencing)
     room_manager: RoomManager,
-    /// `KeyPackage` registry for publish/fetch operations
-    key_package_registry: KeyPackageRegistry,
+    /// `KeyPackage` pools for publish/claim operations
+    key_package_store: KeyPackageStore,
     /// Storage backend
     storage: S,
     /// Environment (time, RNG)
     env: E,
+    /// History retention policies and compaction state
+    retention: Retention<E::Instant>,
+    /// Frames waiting to expire
+    expiry: Expiry,
+    /// Peer links and remote room membership
+    federation: Federation,
+    /// Last-seen times and rooms sharing presence
+    presence: Presence,
+    /// Administrative and security-relevant events
+    audit: AuditLog,
+    /// Webhook targets and event counters
+    webhooks: Webhooks,
+    /// Embedder hooks by opcode
+    interceptors: Interceptors<E::Instant>,
+    /// When `BeginShutdown` was processed
+    draining_since: Option<E::Instant>,
+    /// Whether `DrainComplete` has been emitted
+    drained: bool,
+    /// Whether sequenced frames are left to the runtime to store
+    pipelined: bool,
     /// Server configuration
     config: ServerConfig,
 }
@@ -159,17 +319,52 @@ where
 {
     /// Create a new server driver.
     pub fn new(env: E, storage: S, config: ServerConfig) -> Self {
+        let mut room_manager = RoomManager::with_shards(config.room_shards);
+        room_manager.set_room_quota(config.room_quota_bytes);
+
         Self {
             connections: HashMap::new(),
             registry: ConnectionRegistry::new(),
-            room_manager: RoomManager::new(),
-            key_package_registry: KeyPackageRegistry::new(),
+            room_manager,
+            key_package_store: KeyPackageStore::new(config.key_packages),
             storage,
             env,
+            retention: Retention::new(config.retention),
+            expiry: Expiry::default(),
+            federation: Federation::new(config.federation.clone()),
+            presence: Presence::new(config.presence),
+            audit: AuditLog::default(),
+            webhooks: Webhooks::new(config.webhooks.clone()),
+            interceptors: Interceptors::default(),
+            draining_since: None,
+            drained: false,
+            pipelined: false,
             config,
         }
     }
 
+    /// Leave writing sequenced frames to the runtime.
+    ///
+    /// Instead of storing each frame while routing it, the driver emits a
+    /// [`ServerAction::PersistFrame`] holding the frame's broadcast, so a
+    /// runtime can write one frame while already-stored frames go out.
+    /// Storage then trails the sequencer by the frames in flight: sync
+    /// requests and retries only see frames once they are written. Set this
+    /// before the first event.
+    pub fn set_pipelined_persistence(&mut self, enabled: bool) {
+        self.pipelined = enabled;
+    }
+
+    /// Run `interceptor` around every frame with `opcode`, after any already
+    /// registered for it. See [`FrameInterceptor`].
+    pub fn add_interceptor(
+        &mut self,
+        opcode: Opcode,
+        interceptor: Arc<dyn FrameInterceptor<E::Instant>>,
+    ) {
+        self.interceptors.register(opcode, interceptor);
+    }
+
     /// Process a server event and return actions to execute.
     ///
     /// This is the main entry point for the server driver.
@@ -178,58 +373,201 @@ where
         event: ServerEvent,
     ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
         match event {
-            ServerEvent::ConnectionAccepted { session_id } => {
-                Ok(self.handle_connection_accepted(session_id))
+            ServerEvent::ConnectionAccepted { session_id, peer_identity } => {
+                Ok(self.handle_connection_accepted(session_id, peer_identity))
             },
             ServerEvent::FrameReceived { session_id, frame } => {
                 self.handle_frame_received(session_id, frame)
             },
+            ServerEvent::FrameBatch { frames } => Ok(self.handle_frame_batch(frames)),
             ServerEvent::ConnectionClosed { session_id, reason } => {
                 Ok(self.handle_connection_closed(session_id, &reason))
             },
             ServerEvent::Tick => Ok(self.handle_tick()),
+            ServerEvent::ConfigUpdated(config) => Ok(self.handle_config_updated(*config)),
+            ServerEvent::PersistFailed { room_id, log_index, error, dropped } => {
+                Ok(self.persist_failed(room_id, log_index, &error, &dropped))
+            },
+            ServerEvent::Admin { request } => {
+                let mut actions = Vec::new();
+                let response = self.handle_admin_request(request, &mut actions)?;
+                actions.push(ServerAction::AdminReply(response));
+                Ok(actions)
+            },
+            ServerEvent::BeginShutdown => Ok(self.handle_begin_shutdown()),
         }
     }
 
     /// Handle a new connection being accepted.
-    fn handle_connection_accepted(&mut self, session_id: u64) -> Vec<ServerAction<E::Instant>> {
+    fn handle_connection_accepted(
+        &mut self,
+        session_id: u64,
+        peer_identity: Option<PeerIdentity>,
+    ) -> Vec<ServerAction<E::Instant>> {
         let now = self.env.now();
 
-        if self.connections.len() >= self.config.max_connections {
+        if self.draining_since.is_some() {
             return vec![ServerAction::CloseConnection {
                 session_id,
-                reason: "max connections exceeded".to_string(),
+                reason: "server shutting down".to_string(),
             }];
         }
 
+        if self.connections.len() >= self.config.max_connections {
+            let limit = "max_connections".to_string();
+            return vec![
+                ServerAction::CloseConnection {
+                    session_id,
+                    reason: "max connections exceeded".to_string(),
+                },
+                self.audit(AuditEvent::LimitExceeded { session_id, room_id: None, limit }),
+            ];
+        }
+
+        if self.config.require_client_certificate && peer_identity.is_none() {
+            let reason = "client certificate required".to_string();
+            return vec![
+                LogEvent::warn(LogTarget::Connection, "connection without client certificate", now)
+                    .session(session_id)
+                    .into(),
+                self.audit(AuditEvent::AuthFailed { session_id, reason: reason.clone() }),
+                ServerAction::CloseConnection { session_id, reason },
+            ];
+        }
+
         let mut conn = Connection::new(now, self.config.connection.clone());
         conn.set_session_id(session_id);
 
         self.connections.insert(session_id, conn);
-        self.registry.register_session(session_id, SessionInfo::new());
+        self.registry
+            .register_session(session_id, SessionInfo { peer_identity, ..SessionInfo::new() });
+
+        vec![
+            LogEvent::debug(LogTarget::Connection, "connection accepted", now)
+                .session(session_id)
+                .into(),
+        ]
+    }
+
+    /// Validate and apply a new configuration.
+    fn handle_config_updated(&mut self, config: ServerConfig) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
 
-        vec![ServerAction::Log {
-            level: LogLevel::Debug,
-            message: format!("connection {session_id} accepted, session_id={session_id}"),
-            timestamp: now,
-        }]
+        if let Err(reason) = self.check_config(&config) {
+            return vec![
+                LogEvent::warn(LogTarget::Admin, "config update rejected", now)
+                    .field("reason", reason)
+                    .into(),
+            ];
+        }
+
+        for conn in self.connections.values_mut() {
+            conn.set_config(config.connection.clone());
+        }
+        self.room_manager.set_room_quota(config.room_quota_bytes);
+        self.key_package_store.set_config(config.key_packages);
+        self.retention.set_config(config.retention);
+        self.federation.set_config(config.federation.clone());
+        self.presence.set_config(config.presence);
+        self.webhooks.set_config(config.webhooks.clone());
+        self.config = config;
+
+        vec![LogEvent::info(LogTarget::Admin, "config updated", now).into()]
     }
 
-    /// Handle a frame received from a connection.
-    #[allow(clippy::too_many_lines)]
+    /// Why a configuration cannot replace the current one, if it cannot.
+    fn check_config(&self, config: &ServerConfig) -> Result<(), &'static str> {
+        let connection = &config.connection;
+        if config.room_shards.max(1) != self.config.room_shards.max(1) {
+            return Err("room_shards cannot change while running");
+        }
+        if config.chaos != self.config.chaos {
+            return Err("chaos cannot change while running");
+        }
+        if config.max_connections == 0 {
+            return Err("max_connections must be at least 1");
+        }
+        if connection.handshake_timeout.is_zero() || connection.idle_timeout.is_zero() {
+            return Err("connection timeouts must be positive");
+        }
+        if connection.heartbeat_interval.is_zero()
+            || connection.heartbeat_interval >= connection.idle_timeout
+        {
+            return Err("heartbeat_interval must be positive and shorter than idle_timeout");
+        }
+        if config.key_packages.pool_size == 0 || config.key_packages.max_users == 0 {
+            return Err("key package pools must hold at least one entry");
+        }
+        if config.retention.compaction_interval.is_zero() {
+            return Err("compaction_interval must be positive");
+        }
+        Ok(())
+    }
+
+    /// Handle a frame received from a connection, running any interceptors
+    /// registered for its opcode around it.
     fn handle_frame_received(
         &mut self,
         session_id: u64,
         frame: Frame,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        // Out-of-range headers never reach routing or interceptors
+        frame.header.validate()?;
+
+        let interceptors = self.interceptors.for_opcode(frame.header.opcode_enum()).to_vec();
+        let intercepted = frame.header.opcode_enum().filter(|&opcode| {
+            !interceptors.is_empty()
+                && !is_session_layer(Some(opcode))
+                && self.connections.contains_key(&session_id)
+                && self.is_authenticated(session_id)
+        });
+        let Some(opcode) = intercepted else {
+            return self.dispatch_frame(session_id, frame);
+        };
+
+        let context = FrameContext { session_id, user_id: self.session_user(session_id), opcode };
+        let mut frame = frame;
+        for interceptor in &interceptors {
+            match interceptor.before(&context, frame) {
+                Intercept::Continue(next) => frame = next,
+                Intercept::Reject(actions) => return Ok(actions),
+            }
+        }
+
+        let processed = frame.clone();
+        let mut actions = self.dispatch_frame(session_id, frame)?;
+        for interceptor in &interceptors {
+            let injected = interceptor.after(&context, &processed, &actions);
+            actions.extend(injected);
+        }
+        Ok(actions)
+    }
+
+    /// Process a frame after interception.
+    #[allow(clippy::too_many_lines)]
+    fn dispatch_frame(
+        &mut self,
+        session_id: u64,
+        frame: Frame,
     ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
         let now = self.env.now();
         let mut actions = Vec::new();
+        let opcode = frame.header.opcode_enum();
+
+        let mut principal = None;
+        if opcode == Some(Opcode::Hello) && self.connections.contains_key(&session_id) {
+            match self.authenticate_hello(&frame) {
+                Ok(authenticated) => principal = authenticated,
+                Err(e) => return Ok(self.refuse_session(session_id, &e.to_string(), true)),
+            }
+        } else if !is_session_layer(opcode) && !self.is_authenticated(session_id) {
+            return Ok(self.refuse_session(session_id, "Session not authenticated", false));
+        }
 
         let conn = self
             .connections
             .get_mut(&session_id)
             .ok_or(ServerError::SessionNotFound(session_id))?;
-        let opcode = frame.header.opcode_enum();
 
         match opcode {
             Some(Opcode::Hello | Opcode::Ping | Opcode::Pong | Opcode::Goodbye) => {
@@ -251,10 +589,44 @@ where
 
                 if opcode == Some(Opcode::Hello) {
                     // Update session with authenticated user_id for reverse lookup
-                    let user_id = conn.client_sender_id().or_else(|| conn.session_id());
+                    let user_id = principal
+                        .map(|principal| principal.user_id)
+                        .or_else(|| conn.client_sender_id())
+                        .or_else(|| conn.session_id());
                     if let Some(user_id) = user_id {
-                        let new_info = SessionInfo::authenticated(user_id);
-                        self.registry.update_session_info(session_id, new_info);
+                        let admin = self.presents_admin_token(&frame);
+                        let peer_identity = self
+                            .registry
+                            .sessions(session_id)
+                            .and_then(|info| info.peer_identity.clone());
+                        let new_info = SessionInfo {
+                            admin,
+                            peer_identity,
+                            ..SessionInfo::authenticated(user_id)
+                        };
+                        if self.registry.update_session_info(session_id, new_info) {
+                            let status = self.presence.status(user_id, true);
+                            actions.extend(self.announce_presence(session_id, &status));
+                        }
+
+                        if admin {
+                            actions.push(
+                                LogEvent::info(LogTarget::Admin, "admin session opened", now)
+                                    .session(session_id)
+                                    .field("user_id", user_id)
+                                    .into(),
+                            );
+                        }
+                    }
+
+                    if let Some(server_id) = self.presents_peer_token(&frame) {
+                        self.federation.peer_connected(server_id, session_id);
+                        actions.push(
+                            LogEvent::info(LogTarget::Federation, "peer connected", now)
+                                .session(session_id)
+                                .field("server_id", server_id)
+                                .into(),
+                        );
                     }
                 }
             },
@@ -288,22 +660,72 @@ where
                 actions.extend(fetch_actions);
             },
 
+            Some(Opcode::PresenceQuery) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_presence_query(session_id, &frame));
+            },
+
+            Some(Opcode::DirectoryPublish) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_directory_publish(session_id, &frame));
+            },
+
+            Some(Opcode::DirectorySearch) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_directory_search(session_id, &frame));
+            },
+
+            Some(Opcode::Invite) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_invite(session_id, &frame));
+            },
+
+            Some(Opcode::Report) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_report(session_id, &frame));
+            },
+
+            Some(Opcode::AdminRequest) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_admin_frame(session_id, &frame));
+            },
+
+            Some(Opcode::ServerNotice) => {
+                // Relaying one would let a member speak as the operator
+                let error = ErrorPayload::permission_denied("only the server sends notices");
+                let log = LogEvent::warn(LogTarget::Admin, "client sent a server notice", now);
+                actions.extend(self.error_reply(session_id, None, error, log));
+            },
+
+            Some(Opcode::FedAppend | Opcode::FedNack) => {
+                conn.update_activity(now);
+                actions.extend(self.handle_federation_frame(session_id, &frame)?);
+            },
+
             Some(Opcode::Welcome) => {
                 let room_id = frame.header.room_id();
                 let recipient_id = frame.header.recipient_id();
                 conn.update_activity(now);
 
+                let inviter = self.session_user(session_id);
+                if let Err(e) =
+                    self.room_manager.admit_member(room_id, inviter, recipient_id, &self.storage)
+                {
+                    return Ok(self.make_error_response(session_id, room_id, &e.into()));
+                }
+                actions.extend(self.webhook_member_count(room_id));
+
                 if let Some(recipient_session_id) = self.registry.session_id_for_user(recipient_id)
                 {
                     self.registry.subscribe(recipient_session_id, room_id);
 
-                    actions.push(ServerAction::Log {
-                        level: LogLevel::Debug,
-                        message: format!(
-                            "session {recipient_session_id} (user {recipient_id}) subscribed to room {room_id:032x} via Welcome"
-                        ),
-                        timestamp: now,
-                    });
+                    actions.push(
+                        LogEvent::debug(LogTarget::Room, "subscribed via Welcome", now)
+                            .room(room_id)
+                            .session(recipient_session_id)
+                            .field("user_id", recipient_id)
+                            .into(),
+                    );
 
                     // Route directly to the recipient without going through sequencing
                     actions.push(ServerAction::SendToSession {
@@ -311,23 +733,18 @@ where
                         frame,
                     });
                 } else {
-                    actions.push(ServerAction::Log {
-                        level: LogLevel::Warn,
-                        message: format!(
-                            "Welcome recipient {recipient_id} not connected, cannot subscribe to room {room_id:032x}"
-                        ),
-                        timestamp: now,
-                    });
+                    actions.push(
+                        LogEvent::warn(LogTarget::Room, "Welcome recipient not connected", now)
+                            .room(room_id)
+                            .field("recipient_id", recipient_id)
+                            .into(),
+                    );
                 }
             },
 
             Some(Opcode::AppMessage) => {
                 conn.update_activity(now);
-                let room_actions = self.room_manager.process_frame(frame, now, &self.storage)?;
-
-                for room_action in room_actions {
-                    actions.extend(self.process_room_action(room_action, session_id));
-                }
+                actions.extend(self.sequence_room_frame(session_id, frame)?);
             },
 
             _ => {
@@ -337,34 +754,326 @@ where
 
                 let is_commit =
                     opcode == Some(Opcode::Commit) || opcode == Some(Opcode::ExternalCommit);
-                if is_commit && !self.room_manager.has_room(room_id) {
+                let homed_here = self.federation.remote_home(room_id).is_none();
+                if is_commit && homed_here && !self.room_manager.has_room(room_id) {
                     // GroupInfo publish should create the room, but this is a fallback
                     let create_actions = self.create_room(room_id, session_id)?;
                     actions.extend(create_actions);
                 }
 
-                if opcode == Some(Opcode::ExternalCommit) {
+                // Subscribe before sequencing so the joiner sees its own commit,
+                // unless the ACL is going to refuse it
+                let user_id = self.session_user(session_id);
+                let banned =
+                    self.room_manager.acl(room_id).is_some_and(|acl| acl.is_banned(user_id));
+                if opcode == Some(Opcode::ExternalCommit) && !banned {
                     self.registry.subscribe(session_id, room_id);
-                    actions.push(ServerAction::Log {
-                        level: LogLevel::Debug,
-                        message: format!(
-                            "session {session_id} subscribed to room {room_id:032x} via ExternalCommit"
-                        ),
-                        timestamp: now,
-                    });
+                    actions.push(
+                        LogEvent::debug(LogTarget::Room, "subscribed via ExternalCommit", now)
+                            .room(room_id)
+                            .session(session_id)
+                            .into(),
+                    );
                 }
 
-                let room_actions = self.room_manager.process_frame(frame, now, &self.storage)?;
-
-                for room_action in room_actions {
-                    actions.extend(self.process_room_action(room_action, session_id));
-                }
+                actions.extend(self.sequence_room_frame(session_id, frame)?);
             },
         }
 
         Ok(actions)
     }
 
+    /// Check a Hello's `auth_token` with the configured authenticator.
+    ///
+    /// `Ok(None)` if no authenticator is configured, or the Hello carries an
+    /// admin or peer token, which are checked on their own.
+    fn authenticate_hello(&self, frame: &Frame) -> Result<Option<Principal>, AuthError> {
+        let Some(authenticator) = &self.config.authenticator else {
+            return Ok(None);
+        };
+        if self.presents_admin_token(frame) || self.presents_peer_token(frame).is_some() {
+            return Ok(None);
+        }
+
+        let hello = match Payload::from_frame(frame) {
+            Ok(Payload::Hello(hello)) => hello,
+            Ok(_) => return Err(AuthError::Malformed("expected Hello payload".into())),
+            Err(e) => return Err(AuthError::Malformed(e.to_string())),
+        };
+        let token = hello.auth_token.as_deref().ok_or(AuthError::Missing)?;
+        let principal = authenticator.authenticate(token, self.env.wall_clock_secs())?;
+        if hello.sender_id.is_some_and(|claimed| claimed != principal.user_id) {
+            return Err(AuthError::Rejected("sender_id does not match token".into()));
+        }
+        Ok(Some(principal))
+    }
+
+    /// Whether a session may send frames beyond the session layer: always
+    /// without an authenticator, otherwise once its Hello is accepted.
+    fn is_authenticated(&self, session_id: u64) -> bool {
+        self.config.authenticator.is_none()
+            || self.registry.sessions(session_id).is_some_and(|info| info.user_id.is_some())
+    }
+
+    /// Answer a session that failed authentication with an Error frame,
+    /// closing it if `close` is set.
+    fn refuse_session(
+        &mut self,
+        session_id: u64,
+        reason: &str,
+        close: bool,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let log =
+            LogEvent::warn(LogTarget::Connection, "session not authenticated", self.env.now())
+                .field("reason", reason);
+        let mut actions =
+            self.error_reply(session_id, None, ErrorPayload::unauthenticated(reason), log);
+        if close {
+            let reason = reason.to_string();
+            actions.push(self.audit(AuditEvent::AuthFailed { session_id, reason }));
+            let reason = "authentication failed".to_string();
+            actions.push(ServerAction::CloseConnection { session_id, reason });
+        }
+        actions
+    }
+
+    /// Append an event to the audit log, returning the action that hands the
+    /// entry to the runtime.
+    fn audit(&mut self, event: AuditEvent) -> ServerAction<E::Instant> {
+        ServerAction::Audit(self.audit.record(self.env.wall_clock_secs(), event))
+    }
+
+    /// Webhook delivery for a change in a room's member count, if the room
+    /// has a target that wants one.
+    fn webhook_member_count(&mut self, room_id: u128) -> Option<ServerAction<E::Instant>> {
+        let members = self.room_manager.acl(room_id)?.members().count();
+        let members = u32::try_from(members).unwrap_or(u32::MAX);
+        let at_secs = self.env.wall_clock_secs();
+        self.webhooks.member_count(room_id, members, at_secs).map(ServerAction::DeliverWebhook)
+    }
+
+    /// User a session authenticated as, falling back to the session ID for
+    /// sessions that skipped the handshake.
+    fn session_user(&self, session_id: u64) -> u64 {
+        self.registry.sessions(session_id).and_then(|info| info.user_id).unwrap_or(session_id)
+    }
+
+    /// Sequence and route a room frame.
+    ///
+    /// The header's sender must be the session's user, since `RoomManager`
+    /// authorizes frames by that field. Authorization failures are answered
+    /// with an Error frame rather than failing the event.
+    fn sequence_room_frame(
+        &mut self,
+        session_id: u64,
+        frame: Frame,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        let now = self.env.now();
+        let room_id = frame.header.room_id();
+
+        let user_id = self.session_user(session_id);
+        if frame.header.sender_id() != user_id {
+            let error = RoomError::AccessDenied {
+                room_id,
+                reason: Denial::Forbidden("sender_id does not match session"),
+            };
+            return Ok(self.make_error_response(session_id, room_id, &error.into()));
+        }
+
+        if let Some(home) = self.federation.remote_home(room_id) {
+            return Ok(self.forward_to_home(session_id, home, &frame));
+        }
+
+        let target = moderated_user(&frame);
+        let before = self.moderation_snapshot(room_id, target);
+        let result = self.room_manager.process_frame(frame, now, &self.storage);
+        let after = self.moderation_snapshot(room_id, target);
+        if after.closed {
+            self.presence.set_room(room_id, false);
+        }
+
+        let mut actions = self.route_room_result(session_id, room_id, result)?;
+        if after.closed && !before.closed {
+            self.webhooks.forget(room_id);
+            actions.push(self.audit(AuditEvent::RoomClosed { room_id, closed_by: Some(user_id) }));
+        } else {
+            actions.extend(self.webhook_member_count(room_id));
+        }
+        if let Some(target) = target
+            && ((before.member && !after.member) || (after.banned && !before.banned))
+        {
+            actions.push(self.audit(AuditEvent::MemberRemoved {
+                room_id,
+                user_id: target,
+                moderator_id: user_id,
+                banned: after.banned,
+            }));
+        }
+        Ok(actions)
+    }
+
+    /// Room state a moderation frame can change, so sequencing it can be
+    /// audited by comparing before and after. Refused frames change nothing.
+    fn moderation_snapshot(&self, room_id: u128, target: Option<u64>) -> ModerationSnapshot {
+        let closed =
+            self.room_manager.metadata(room_id).is_some_and(|room| room.closed_at.is_some());
+        let acl = self.room_manager.acl(room_id);
+        let member = target.zip(acl).is_some_and(|(user_id, acl)| acl.is_member(user_id));
+        let banned = target.zip(acl).is_some_and(|(user_id, acl)| acl.is_banned(user_id));
+        ModerationSnapshot { closed, member, banned }
+    }
+
+    /// Turn the outcome of sequencing one frame into actions.
+    fn route_room_result(
+        &mut self,
+        session_id: u64,
+        room_id: u128,
+        result: Result<Vec<RoomAction<E::Instant>>, RoomError>,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        let room_actions = match result {
+            Ok(room_actions) => room_actions,
+            Err(e @ RoomError::QuotaExceeded { .. }) => {
+                let limit = "room_quota".to_string();
+                let audit = self.audit(AuditEvent::LimitExceeded {
+                    session_id,
+                    room_id: Some(room_id),
+                    limit,
+                });
+                let mut actions = self.make_error_response(session_id, room_id, &e.into());
+                actions.push(audit);
+                return Ok(actions);
+            },
+            Err(e @ (RoomError::AccessDenied { .. } | RoomError::RoomClosed(_))) => {
+                return Ok(self.make_error_response(session_id, room_id, &e.into()));
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut actions = Vec::new();
+        let mut persisted = true;
+        // Position in `actions` of a frame left to the runtime to store, whose
+        // broadcast has to wait for it
+        let mut pending = None;
+        for room_action in room_actions {
+            match room_action {
+                // A frame that never reached storage must not reach
+                // subscribers, since its log index will be handed out again
+                RoomAction::Broadcast { .. } if !persisted => {},
+                RoomAction::Broadcast { .. } if pending.is_some() => {
+                    let routed = self.process_room_action(room_action, session_id);
+                    if let Some(ServerAction::PersistFrame { then, .. }) =
+                        pending.and_then(|i| actions.get_mut(i))
+                    {
+                        then.extend(routed);
+                    }
+                },
+                RoomAction::PersistFrame { room_id, log_index, frame, .. } if self.pipelined => {
+                    let mut then = Vec::new();
+                    if frame.header.opcode_enum() == Some(Opcode::AppMessage) {
+                        let at_secs = self.env.wall_clock_secs();
+                        let delivery = self.webhooks.message_stored(room_id, at_secs);
+                        then.extend(delivery.map(ServerAction::DeliverWebhook));
+                    }
+                    pending = Some(actions.len());
+                    actions.push(ServerAction::PersistFrame { room_id, log_index, frame, then });
+                },
+                RoomAction::PersistFrame { ref frame, .. } => {
+                    let is_message = frame.header.opcode_enum() == Some(Opcode::AppMessage);
+                    let failure = self.process_room_action(room_action, session_id);
+                    persisted = failure.is_empty();
+                    actions.extend(failure);
+                    if persisted && is_message {
+                        let at_secs = self.env.wall_clock_secs();
+                        let delivery = self.webhooks.message_stored(room_id, at_secs);
+                        actions.extend(delivery.map(ServerAction::DeliverWebhook));
+                    }
+                },
+                _ => actions.extend(self.process_room_action(room_action, session_id)),
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Handle a batch of frames.
+    ///
+    /// Runs of application messages are collected and sequenced together;
+    /// any other frame flushes the run first so per-session order holds.
+    /// A frame that fails is logged and does not abort the rest.
+    fn handle_frame_batch(&mut self, frames: Vec<(u64, Frame)>) -> Vec<ServerAction<E::Instant>> {
+        let mut actions = Vec::new();
+        let mut run = Vec::new();
+
+        for (session_id, frame) in frames {
+            if self.can_batch(session_id, &frame) {
+                run.push((session_id, frame));
+                continue;
+            }
+
+            actions.extend(self.sequence_batch(std::mem::take(&mut run)));
+            let result = self.handle_frame_received(session_id, frame);
+            actions.extend(self.batch_result(session_id, result));
+        }
+
+        actions.extend(self.sequence_batch(run));
+        actions
+    }
+
+    /// Whether a frame can join a parallel batch: an unintercepted
+    /// application message from a live session whose header sender is the
+    /// session's user.
+    fn can_batch(&self, session_id: u64, frame: &Frame) -> bool {
+        frame.header.opcode_enum() == Some(Opcode::AppMessage)
+            && frame.header.validate().is_ok()
+            && self.interceptors.for_opcode(Some(Opcode::AppMessage)).is_empty()
+            && self.connections.contains_key(&session_id)
+            && self.is_authenticated(session_id)
+            && frame.header.sender_id() == self.session_user(session_id)
+    }
+
+    /// Sequence a run of batchable frames across shards.
+    fn sequence_batch(&mut self, run: Vec<(u64, Frame)>) -> Vec<ServerAction<E::Instant>> {
+        if run.is_empty() {
+            return Vec::new();
+        }
+
+        let now = self.env.now();
+        let mut senders = Vec::with_capacity(run.len());
+        let mut frames = Vec::with_capacity(run.len());
+        for (session_id, frame) in run {
+            if let Some(conn) = self.connections.get_mut(&session_id) {
+                conn.update_activity(now);
+            }
+            senders.push((session_id, frame.header.room_id()));
+            frames.push(frame);
+        }
+
+        let results = self.room_manager.process_batch(frames, now, &self.storage);
+
+        let mut actions = Vec::new();
+        for ((session_id, room_id), result) in senders.into_iter().zip(results) {
+            let routed = self.route_room_result(session_id, room_id, result);
+            actions.extend(self.batch_result(session_id, routed));
+        }
+        actions
+    }
+
+    /// Unwrap one frame's result inside a batch, logging failures.
+    fn batch_result(
+        &self,
+        session_id: u64,
+        result: Result<Vec<ServerAction<E::Instant>>, ServerError>,
+    ) -> Vec<ServerAction<E::Instant>> {
+        match result {
+            Ok(actions) => actions,
+            Err(e) => vec![
+                LogEvent::warn(LogTarget::Connection, "frame processing failed", self.env.now())
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ],
+        }
+    }
+
     /// Handle a sync request from a client.
     fn handle_sync_request(
         &mut self,
@@ -376,22 +1085,35 @@ where
 
         let result = (|| -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
             let payload = Payload::from_frame(&frame.clone())?;
-            let (from_log_index, limit) = match payload {
-                Payload::SyncRequest(req) => (req.from_log_index, req.limit as usize),
-                _ => {
-                    return Err(ServerError::Protocol("expected SyncRequest payload".to_string()));
-                },
+            let Payload::SyncRequest(request) = payload else {
+                return Err(ServerError::Protocol("expected SyncRequest payload".to_string()));
             };
 
-            let room_action = self.room_manager.handle_sync_request(
+            let user_id = self.session_user(session_id);
+            if let Some(acl) = self.room_manager.acl(room_id)
+                && !acl.is_member(user_id)
+            {
+                let reason = Denial::NotMember(user_id);
+                return Err(RoomError::AccessDenied { room_id, reason }.into());
+            }
+
+            let mut room_action = self.room_manager.handle_filtered_sync_request(
                 room_id,
                 session_id,
-                from_log_index,
-                limit,
+                &request,
                 now,
                 &self.storage,
             )?;
 
+            // Expired frames keep their content until the next sweep, but are
+            // never served
+            if let RoomAction::SendSyncResponse { frames, .. } = &mut room_action {
+                let now_secs = self.env.wall_clock_secs();
+                frames.retain(|bytes| {
+                    FrameHeader::from_bytes(bytes).is_ok_and(|header| !header.is_expired(now_secs))
+                });
+            }
+
             Ok(self.process_room_action(room_action, session_id))
         })();
 
@@ -410,30 +1132,66 @@ where
         let error_payload = match error {
             ServerError::Room(room_err) => match room_err {
                 RoomError::RoomNotFound(_) => ErrorPayload::room_not_found(room_id),
+                RoomError::RoomClosed(_) => ErrorPayload::room_closed(room_id),
+                RoomError::QuotaExceeded { .. } => ErrorPayload::quota_exceeded(room_id),
                 RoomError::Storage(e) => ErrorPayload::storage_error(e.to_string()),
                 RoomError::Sequencing(e) => ErrorPayload::sequencer_error(e.to_string()),
                 RoomError::RoomAlreadyExists(e) => ErrorPayload::frame_rejected(e.to_string()),
+                RoomError::AccessDenied { reason: Denial::NotMember(_), .. } => {
+                    ErrorPayload::not_a_member(room_id)
+                },
+                RoomError::AccessDenied { reason, .. } => {
+                    ErrorPayload::permission_denied(reason.to_string())
+                },
             },
             ServerError::Protocol(msg) => ErrorPayload::invalid_payload(msg),
             _ => ErrorPayload::frame_rejected(error.to_string()),
         };
 
-        let error_msg = error_payload.message.clone();
-        let error = Payload::Error(error_payload);
-        match error.into_frame(FrameHeader::new(Opcode::Error)) {
+        let target = match error {
+            ServerError::Room(RoomError::Sequencing(_)) => LogTarget::Sequencer,
+            _ => LogTarget::Room,
+        };
+        let log = LogEvent::warn(target, "request failed", self.env.now())
+            .room(room_id)
+            .field("error", &error_payload.message);
+        self.error_reply(session_id, Some(room_id), error_payload, log)
+    }
+
+    /// Send an Error frame to a session, logging `log` alongside it.
+    ///
+    /// The frame and the log carry the same fresh [`ErrorId`], so a failure
+    /// the client shows can be found here.
+    fn error_reply(
+        &self,
+        session_id: u64,
+        room_id: Option<u128>,
+        error: ErrorPayload,
+        log: LogEvent<E::Instant>,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let target = log.target;
+        let mut id = [0u8; 2];
+        self.env.random_bytes(&mut id);
+        let error_id = ErrorId::from_random(id);
+
+        match Payload::Error(error.with_error_id(error_id))
+            .into_frame(FrameHeader::new(Opcode::Error))
+        {
             Ok(mut frame) => {
-                frame.header.set_room_id(room_id);
-                vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                    level: LogLevel::Warn,
-                    message: format!("sync request failed for {session_id}: {error_msg}"),
-                    timestamp: self.env.now(),
-                }]
+                if let Some(room_id) = room_id {
+                    frame.header.set_room_id(room_id);
+                }
+                vec![
+                    ServerAction::SendToSession { session_id, frame },
+                    log.session(session_id).field("error_id", error_id).into(),
+                ]
             },
-            Err(e) => vec![ServerAction::Log {
-                level: LogLevel::Error,
-                message: format!("failed to encode error response: {e}"),
-                timestamp: self.env.now(),
-            }],
+            Err(e) => vec![
+                LogEvent::error(target, "failed to encode error response", self.env.now())
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ],
         }
     }
 
@@ -445,110 +1203,101 @@ where
     ) -> Vec<ServerAction<E::Instant>> {
         let now = self.env.now();
 
-        let user_id = if let Some(info) = self.registry.sessions(session_id) {
-            if let Some(id) = info.user_id {
-                id
-            } else {
-                let error =
-                    Payload::Error(ErrorPayload::frame_rejected("Session not authenticated"));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!(
-                                "KeyPackagePublish from unauthenticated session {session_id}"
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!("failed to encode error response: {e}"),
-                        timestamp: now,
-                    }],
-                };
-            }
-        } else {
-            let error = Payload::Error(ErrorPayload::frame_rejected("Unknown session"));
-            return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                Ok(frame) => {
-                    vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                        level: LogLevel::Warn,
-                        message: format!("KeyPackagePublish from unknown session {session_id}"),
-                        timestamp: now,
-                    }]
-                },
-                Err(e) => vec![ServerAction::Log {
-                    level: LogLevel::Error,
-                    message: format!("failed to encode error response: {e}"),
-                    timestamp: now,
-                }],
-            };
+        let user_id = match self.registry.sessions(session_id).map(|info| info.user_id) {
+            Some(Some(id)) => id,
+            Some(None) => {
+                let error = ErrorPayload::frame_rejected("Session not authenticated");
+                let log = LogEvent::warn(
+                    LogTarget::KeyPackages,
+                    "KeyPackagePublish from unauthenticated session",
+                    now,
+                );
+                return self.error_reply(session_id, None, error, log);
+            },
+            None => {
+                let error = ErrorPayload::frame_rejected("Unknown session");
+                let log = LogEvent::warn(
+                    LogTarget::KeyPackages,
+                    "KeyPackagePublish from unknown session",
+                    now,
+                );
+                return self.error_reply(session_id, None, error, log);
+            },
         };
 
         let payload = match Payload::from_frame(&frame.clone()) {
             Ok(Payload::KeyPackagePublish(req)) => req,
             Ok(_) => {
-                let error = Payload::Error(ErrorPayload::invalid_payload(
-                    "Expected KeyPackagePublish payload",
-                ));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!(
-                                "expected KeyPackagePublish payload from session {session_id}"
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!("failed to encode error response: {e}"),
-                        timestamp: now,
-                    }],
-                };
+                let error = ErrorPayload::invalid_payload("Expected KeyPackagePublish payload");
+                let log = LogEvent::warn(
+                    LogTarget::KeyPackages,
+                    "expected KeyPackagePublish payload",
+                    now,
+                );
+                return self.error_reply(session_id, None, error, log);
             },
             Err(e) => {
-                let error = Payload::Error(ErrorPayload::invalid_payload(format!(
+                let error = ErrorPayload::invalid_payload(format!(
                     "Failed to decode KeyPackagePublish: {e}"
-                )));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!("failed to decode KeyPackagePublish: {e}"),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!("failed to encode error response: {e}"),
-                        timestamp: now,
-                    }],
-                };
+                ));
+                let log = LogEvent::warn(
+                    LogTarget::KeyPackages,
+                    "failed to decode KeyPackagePublish",
+                    now,
+                )
+                .field("error", e);
+                return self.error_reply(session_id, None, error, log);
             },
         };
 
-        let store_result = self
-            .key_package_registry
-            .store(user_id, KeyPackageEntry::new(payload.key_package_bytes, payload.hash_ref));
+        vec![self.store_key_package(user_id, payload)]
+    }
 
-        let mut actions = vec![ServerAction::Log {
-            level: LogLevel::Info,
-            message: format!("KeyPackage published for user {user_id}"),
-            timestamp: now,
-        }];
+    /// Add a published `KeyPackage` to the owner's pool.
+    fn store_key_package(
+        &self,
+        user_id: u64,
+        payload: KeyPackagePublishRequest,
+    ) -> ServerAction<E::Instant> {
+        let now_secs = self.env.wall_clock_secs();
+        let expires_at_secs = now_secs.saturating_add(self.config.key_packages.ttl_secs);
+        let entry =
+            KeyPackageEntry::new(payload.key_package_bytes, payload.hash_ref, expires_at_secs);
+
+        let (level, message) = match self.key_package_store.publish(user_id, entry, now_secs) {
+            StoreResult::Success => (LogLevel::Info, "KeyPackage published"),
+            StoreResult::Evicted => {
+                (LogLevel::Debug, "KeyPackage published, evicting another user's pool")
+            },
+            StoreResult::Duplicate => (LogLevel::Debug, "duplicate KeyPackage ignored"),
+            StoreResult::PoolFull => (LogLevel::Warn, "KeyPackage pool full, entry dropped"),
+            StoreResult::Full => (LogLevel::Warn, "KeyPackage store full, entry dropped"),
+        };
 
-        if store_result == StoreResult::Evicted {
-            actions.push(ServerAction::Log {
-                level: LogLevel::Debug,
-                message: format!("KeyPackage registry evicted an entry for user {user_id}"),
-                timestamp: now,
-            });
+        LogEvent::new(level, LogTarget::KeyPackages, message, self.env.now())
+            .field("user_id", user_id)
+            .into()
+    }
+
+    /// Tell a `KeyPackage` owner their pool is running low, if they are
+    /// connected and below the threshold.
+    fn key_package_low_stock(
+        &self,
+        user_id: u64,
+        remaining: usize,
+    ) -> Option<ServerAction<E::Instant>> {
+        let config = self.config.key_packages;
+        if remaining >= config.low_stock_threshold {
+            return None;
         }
+        let session_id = self.registry.session_id_for_user(user_id)?;
 
-        actions
+        let notice = Payload::KeyPackageLowStock(KeyPackageLowStockPayload {
+            remaining: u32::try_from(remaining).unwrap_or(u32::MAX),
+            target: u32::try_from(config.pool_size).unwrap_or(u32::MAX),
+        });
+        let frame = notice.into_frame(FrameHeader::new(Opcode::KeyPackageLowStock)).ok()?;
+        Some(ServerAction::SendToSession { session_id, frame })
     }
 
     /// Handle `KeyPackage` fetch request.
@@ -562,57 +1311,44 @@ where
         let request = match Payload::from_frame(&frame.clone()) {
             Ok(Payload::KeyPackageFetch(req)) => req,
             Ok(_) => {
-                let error = Payload::Error(ErrorPayload::invalid_payload(
-                    "Expected KeyPackageFetch payload",
-                ));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!(
-                                "unexpected payload type in KeyPackageFetch frame from session {session_id}"
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!(
-                            "failed to encode error response for KeyPackageFetch: {e}"
-                        ),
-                        timestamp: now,
-                    }],
-                };
+                let error = ErrorPayload::invalid_payload("Expected KeyPackageFetch payload");
+                let log = LogEvent::warn(
+                    LogTarget::KeyPackages,
+                    "unexpected payload type in KeyPackageFetch frame",
+                    now,
+                );
+                return self.error_reply(session_id, None, error, log);
             },
             Err(e) => {
-                let error = Payload::Error(ErrorPayload::invalid_payload(format!(
-                    "Failed to decode KeyPackageFetch: {e}"
-                )));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!(
-                                "failed to decode KeyPackageFetch from session {session_id}: {e}"
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!(
-                            "failed to encode error response for KeyPackageFetch: {e}"
-                        ),
-                        timestamp: now,
-                    }],
-                };
+                let error =
+                    ErrorPayload::invalid_payload(format!("Failed to decode KeyPackageFetch: {e}"));
+                let log =
+                    LogEvent::warn(LogTarget::KeyPackages, "failed to decode KeyPackageFetch", now)
+                        .field("error", e);
+                return self.error_reply(session_id, None, error, log);
             },
         };
 
-        // Fetch (and consume) from registry
-        if let Some(entry) = self.key_package_registry.take(request.user_id) {
+        // Claim removes the entry, so no other fetch can be handed it
+        let claimed = self.key_package_store.claim(request.user_id, self.env.wall_clock_secs());
+        let remaining = claimed.as_ref().map_or(0, |c| c.remaining);
+        let mut actions = self.key_package_fetch_response(session_id, request.user_id, claimed);
+        actions.extend(self.key_package_low_stock(request.user_id, remaining));
+        actions
+    }
+
+    /// Build the reply to a `KeyPackage` fetch.
+    fn key_package_fetch_response(
+        &self,
+        session_id: u64,
+        user_id: u64,
+        claimed: Option<Claimed>,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+
+        if let Some(Claimed { entry, .. }) = claimed {
             let response = Payload::KeyPackageFetch(KeyPackageFetchPayload {
-                user_id: request.user_id,
+                user_id,
                 key_package_bytes: entry.key_package_bytes,
                 hash_ref: entry.hash_ref,
             });
@@ -620,39 +1356,27 @@ where
             match response.into_frame(FrameHeader::new(Opcode::KeyPackageFetch)) {
                 Ok(response_frame) => vec![
                     ServerAction::SendToSession { session_id, frame: response_frame },
-                    ServerAction::Log {
-                        level: LogLevel::Debug,
-                        message: format!("KeyPackage fetched for user {}", request.user_id),
-                        timestamp: now,
-                    },
+                    LogEvent::debug(LogTarget::KeyPackages, "KeyPackage fetched", now)
+                        .session(session_id)
+                        .field("user_id", user_id)
+                        .into(),
+                ],
+                Err(e) => vec![
+                    LogEvent::error(
+                        LogTarget::KeyPackages,
+                        "failed to encode KeyPackageFetch response",
+                        now,
+                    )
+                    .field("error", e)
+                    .into(),
                 ],
-                Err(e) => vec![ServerAction::Log {
-                    level: LogLevel::Error,
-                    message: format!("failed to encode KeyPackageFetch response: {e}"),
-                    timestamp: now,
-                }],
             }
         } else {
             // No KeyPackage found - return error
-            let error = Payload::Error(ErrorPayload::keypackage_not_found(request.user_id));
-
-            match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                Ok(frame) => {
-                    vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                        level: LogLevel::Debug,
-                        message: format!(
-                            "no KeyPackage found for user {} (requested by session {})",
-                            request.user_id, session_id
-                        ),
-                        timestamp: now,
-                    }]
-                },
-                Err(e) => vec![ServerAction::Log {
-                    level: LogLevel::Error,
-                    message: format!("failed to encode error response for KeyPackageFetch: {e}"),
-                    timestamp: now,
-                }],
-            }
+            let error = ErrorPayload::keypackage_not_found(user_id);
+            let log = LogEvent::debug(LogTarget::KeyPackages, "no KeyPackage found", now)
+                .field("user_id", user_id);
+            self.error_reply(session_id, None, error, log)
         }
     }
 
@@ -672,50 +1396,61 @@ where
         let payload = match Payload::from_frame(&frame.clone()) {
             Ok(Payload::GroupInfo(info)) => info,
             Ok(_) => {
-                return vec![ServerAction::Log {
-                    level: LogLevel::Warn,
-                    message: format!(
-                        "unexpected payload type in GroupInfo frame from session {session_id}"
-                    ),
-                    timestamp: now,
-                }];
+                return vec![
+                    LogEvent::warn(
+                        LogTarget::GroupInfo,
+                        "unexpected payload type in GroupInfo frame",
+                        now,
+                    )
+                    .session(session_id)
+                    .into(),
+                ];
             },
             Err(e) => {
-                return vec![ServerAction::Log {
-                    level: LogLevel::Warn,
-                    message: format!("failed to decode GroupInfo from session {session_id}: {e}"),
-                    timestamp: now,
-                }];
+                return vec![
+                    LogEvent::warn(LogTarget::GroupInfo, "failed to decode GroupInfo", now)
+                        .session(session_id)
+                        .field("error", e)
+                        .into(),
+                ];
             },
         };
 
+        // Anyone may publish for a room that doesn't exist yet; once it does,
+        // only members may replace its GroupInfo
+        let user_id = self.session_user(session_id);
+        if let Some(acl) = self.room_manager.acl(payload.room_id)
+            && !acl.is_member(user_id)
+        {
+            let error = RoomError::AccessDenied {
+                room_id: payload.room_id,
+                reason: Denial::NotMember(user_id),
+            };
+            return self.make_error_response(session_id, payload.room_id, &error.into());
+        }
+
         if let Err(e) =
             self.storage.store_group_info(payload.room_id, payload.epoch, &payload.group_info_bytes)
         {
-            return vec![ServerAction::Log {
-                level: LogLevel::Error,
-                message: format!(
-                    "failed to store GroupInfo for room {:032x}: {}",
-                    payload.room_id, e
-                ),
-                timestamp: now,
-            }];
+            return vec![
+                LogEvent::error(LogTarget::GroupInfo, "failed to store GroupInfo", now)
+                    .room(payload.room_id)
+                    .field("error", e)
+                    .into(),
+            ];
         }
 
-        actions.push(ServerAction::Log {
-            level: LogLevel::Debug,
-            message: format!(
-                "stored GroupInfo for room {:032x} at epoch {}",
-                payload.room_id, payload.epoch
-            ),
-            timestamp: now,
-        });
+        actions.push(
+            LogEvent::debug(LogTarget::GroupInfo, "stored GroupInfo", now)
+                .room(payload.room_id)
+                .field("epoch", payload.epoch)
+                .into(),
+        );
 
         actions
     }
 
     /// Handle `GroupInfo` request (fetch `GroupInfo` for external joiners).
-    #[allow(clippy::too_many_lines)] // TODO: we should refactor this
     fn handle_group_info_request(
         &self,
         session_id: u64,
@@ -726,50 +1461,22 @@ where
         let request = match Payload::from_frame(&frame.clone()) {
             Ok(Payload::GroupInfoRequest(req)) => req,
             Ok(_) => {
-                let error = Payload::Error(ErrorPayload::invalid_payload(
-                    "Expected GroupInfoRequest payload",
-                ));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!(
-                                "unexpected payload type in GroupInfoRequest frame from session {session_id}"
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!(
-                            "failed to encode error response for GroupInfoRequest: {e}"
-                        ),
-                        timestamp: now,
-                    }],
-                };
+                let error = ErrorPayload::invalid_payload("Expected GroupInfoRequest payload");
+                let log = LogEvent::warn(
+                    LogTarget::GroupInfo,
+                    "unexpected payload type in GroupInfoRequest frame",
+                    now,
+                );
+                return self.error_reply(session_id, None, error, log);
             },
             Err(e) => {
-                let error = Payload::Error(ErrorPayload::invalid_payload(format!(
+                let error = ErrorPayload::invalid_payload(format!(
                     "Failed to decode GroupInfoRequest: {e}"
-                )));
-                return match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!(
-                                "failed to decode GroupInfoRequest from session {session_id}: {e}"
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!(
-                            "failed to encode error response for GroupInfoRequest: {e}"
-                        ),
-                        timestamp: now,
-                    }],
-                };
+                ));
+                let log =
+                    LogEvent::warn(LogTarget::GroupInfo, "failed to decode GroupInfoRequest", now)
+                        .field("error", e);
+                return self.error_reply(session_id, None, error, log);
             },
         };
 
@@ -784,530 +1491,2808 @@ where
                 match response.into_frame(FrameHeader::new(Opcode::GroupInfo)) {
                     Ok(response_frame) => vec![
                         ServerAction::SendToSession { session_id, frame: response_frame },
-                        ServerAction::Log {
-                            level: LogLevel::Debug,
-                            message: format!(
-                                "GroupInfo fetched for room {:032x} at epoch {}",
-                                request.room_id, epoch
-                            ),
-                            timestamp: now,
-                        },
+                        LogEvent::debug(LogTarget::GroupInfo, "GroupInfo fetched", now)
+                            .room(request.room_id)
+                            .session(session_id)
+                            .field("epoch", epoch)
+                            .into(),
+                    ],
+                    Err(e) => vec![
+                        LogEvent::error(
+                            LogTarget::GroupInfo,
+                            "failed to encode GroupInfo response",
+                            now,
+                        )
+                        .room(request.room_id)
+                        .field("error", e)
+                        .into(),
                     ],
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!("failed to encode GroupInfo response: {e}"),
-                        timestamp: now,
-                    }],
                 }
             },
             Ok(None) => {
-                let error = Payload::Error(ErrorPayload::room_not_found(request.room_id));
-                match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => {
-                        vec![ServerAction::SendToSession { session_id, frame }, ServerAction::Log {
-                            level: LogLevel::Debug,
-                            message: format!(
-                                "no GroupInfo found for room {:032x} (requested by session {})",
-                                request.room_id, session_id
-                            ),
-                            timestamp: now,
-                        }]
-                    },
-                    Err(e) => vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!(
-                            "failed to encode error response for GroupInfoRequest: {e}"
-                        ),
-                        timestamp: now,
-                    }],
-                }
+                let error = ErrorPayload::room_not_found(request.room_id);
+                let log = LogEvent::debug(LogTarget::GroupInfo, "no GroupInfo found", now)
+                    .room(request.room_id);
+                self.error_reply(session_id, None, error, log)
             },
-            Err(e) => vec![ServerAction::Log {
-                level: LogLevel::Error,
-                message: format!(
-                    "failed to load GroupInfo for room {:032x}: {}",
-                    request.room_id, e
-                ),
-                timestamp: now,
-            }],
+            Err(e) => vec![
+                LogEvent::error(LogTarget::GroupInfo, "failed to load GroupInfo", now)
+                    .room(request.room_id)
+                    .field("error", e)
+                    .into(),
+            ],
         }
     }
 
-    /// Handle a connection being closed.
-    fn handle_connection_closed(
+    /// Whether a Hello frame carries the configured admin token.
+    fn presents_admin_token(&self, frame: &Frame) -> bool {
+        let Some(token) = &self.config.admin_token else {
+            return false;
+        };
+        match Payload::from_frame(frame) {
+            Ok(Payload::Hello(hello)) => {
+                hello.auth_token.as_deref().is_some_and(|presented| token.matches(presented))
+            },
+            _ => false,
+        }
+    }
+
+    /// Handle a `Report` frame from a room member, recording it in the audit
+    /// log for moderators. Nothing is sequenced or sent to the room.
+    fn handle_report(&mut self, session_id: u64, frame: &Frame) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let room_id = frame.header.room_id();
+
+        let Ok(Payload::ReportMessage(ReportMessage { message_log_index, reason })) =
+            Payload::from_frame(frame)
+        else {
+            let error = ErrorPayload::invalid_payload("expected ReportMessage payload");
+            let log = LogEvent::warn(LogTarget::Room, "invalid report", now);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        };
+
+        let reporter_id = self.session_user(session_id);
+        let denial = match self.room_manager.acl(room_id) {
+            Some(acl) if acl.is_member(reporter_id) => None,
+            Some(_) => {
+                Some(RoomError::AccessDenied { room_id, reason: Denial::NotMember(reporter_id) })
+            },
+            None => Some(RoomError::RoomNotFound(room_id)),
+        };
+        if let Some(e) = denial {
+            return self.make_error_response(session_id, room_id, &e.into());
+        }
+
+        let latest = match self.storage.latest_log_index(room_id) {
+            Ok(latest) => latest,
+            Err(e) => return self.make_error_response(session_id, room_id, &e.into()),
+        };
+        if latest.is_none_or(|latest| message_log_index > latest) {
+            let error = ErrorPayload::invalid_payload("no message at that log index");
+            let log = LogEvent::debug(LogTarget::Room, "report for unknown message", now)
+                .room(room_id)
+                .field("log_index", message_log_index);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        }
+
+        vec![
+            LogEvent::info(LogTarget::Room, "message reported", now)
+                .room(room_id)
+                .session(session_id)
+                .field("log_index", message_log_index)
+                .into(),
+            self.audit(AuditEvent::MessageReported {
+                room_id,
+                message_log_index,
+                reporter_id,
+                reason: audit::truncate_reason(reason),
+            }),
+        ]
+    }
+
+    /// Handle an `AdminRequest` frame, answering with an `AdminResponse` frame.
+    ///
+    /// Only sessions that presented the admin token may send these.
+    fn handle_admin_frame(
         &mut self,
         session_id: u64,
-        reason: &str,
+        frame: &Frame,
     ) -> Vec<ServerAction<E::Instant>> {
         let now = self.env.now();
-        let mut actions = Vec::new();
 
-        if let Some(mut conn) = self.connections.remove(&session_id) {
-            conn.close();
+        if !self.registry.sessions(session_id).is_some_and(|info| info.admin) {
+            let error = ErrorPayload::permission_denied("admin access required");
+            let log = LogEvent::warn(LogTarget::Admin, "admin request denied", now);
+            let mut actions = self.error_reply(session_id, None, error, log);
+            actions.push(self.audit(AuditEvent::AdminDenied { session_id }));
+            return actions;
         }
 
-        if let Some((_info, rooms)) = self.registry.unregister_session(session_id) {
-            actions.push(ServerAction::Log {
-                level: LogLevel::Info,
-                message: format!(
-                    "connection {} closed: {}, was in {} rooms",
+        let request = match Payload::from_frame(frame) {
+            Ok(Payload::AdminRequest(request)) => request,
+            Ok(_) => {
+                let error = ErrorPayload::invalid_payload("expected AdminRequest payload");
+                let log = LogEvent::warn(LogTarget::Admin, "invalid admin request", now);
+                return self.error_reply(session_id, None, error, log);
+            },
+            Err(e) => {
+                let log = LogEvent::warn(LogTarget::Admin, "invalid admin request", now)
+                    .field("error", &e);
+                return self.error_reply(
                     session_id,
-                    reason,
-                    rooms.len()
-                ),
-                timestamp: now,
-            });
-        }
+                    None,
+                    ErrorPayload::invalid_payload(e.to_string()),
+                    log,
+                );
+            },
+        };
+
+        let room_id = request.room_id();
+        let mut side_effects = Vec::new();
+        let response = match self.handle_admin_request(request, &mut side_effects) {
+            Ok(response) => response,
+            Err(e) => return self.make_error_response(session_id, room_id.unwrap_or(0), &e),
+        };
 
+        let mut actions = match Payload::AdminResponse(response)
+            .into_frame(FrameHeader::new(Opcode::AdminResponse))
+        {
+            Ok(frame) => vec![ServerAction::SendToSession { session_id, frame }],
+            Err(e) => vec![
+                LogEvent::error(LogTarget::Admin, "failed to encode AdminResponse", now)
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ],
+        };
+        actions.extend(side_effects);
         actions
     }
 
-    /// Handle periodic tick for timeout checking.
-    fn handle_tick(&mut self) -> Vec<ServerAction<E::Instant>> {
+    /// Perform an admin operation, returning the reply and pushing the
+    /// actions that carry out its side effects.
+    #[allow(clippy::too_many_lines)]
+    fn handle_admin_request(
+        &mut self,
+        request: AdminRequest,
+        actions: &mut Vec<ServerAction<E::Instant>>,
+    ) -> Result<AdminResponse, ServerError> {
         let now = self.env.now();
-        let mut actions = Vec::new();
 
-        let session_ids: Vec<u64> = self.connections.keys().copied().collect();
+        match request {
+            AdminRequest::ListRooms => {
+                let mut room_ids: Vec<u128> = self.room_manager.room_ids().collect();
+                room_ids.sort_unstable();
+                Ok(AdminResponse::Rooms { room_ids })
+            },
 
-        for session_id in session_ids {
-            if let Some(conn) = self.connections.get_mut(&session_id) {
-                let conn_actions = conn.tick(now);
+            AdminRequest::RoomInfo { room_id } => {
+                Ok(AdminResponse::RoomInfo(self.room_info(room_id)?))
+            },
 
-                for action in conn_actions {
-                    match action {
-                        ConnectionAction::SendFrame(f) => {
-                            actions.push(ServerAction::SendToSession { session_id, frame: f });
-                        },
-                        ConnectionAction::Close { reason } => {
-                            actions.push(ServerAction::CloseConnection { session_id, reason });
-                        },
-                    }
+            AdminRequest::KickSession { session_id, reason } => {
+                if !self.connections.contains_key(&session_id) {
+                    return Err(ServerError::SessionNotFound(session_id));
                 }
-            }
-        }
+                let log = LogEvent::info(LogTarget::Admin, "session kicked", now)
+                    .session(session_id)
+                    .field("reason", &reason);
+                actions.push(
+                    self.audit(AuditEvent::SessionKicked { session_id, reason: reason.clone() }),
+                );
+                actions.push(ServerAction::CloseConnection { session_id, reason });
+                actions.push(log.into());
+                Ok(AdminResponse::Done)
+            },
 
-        actions
-    }
+            AdminRequest::CloseRoom { room_id } => {
+                self.room_manager.close_room(room_id)?;
+                self.presence.set_room(room_id, false);
+                let session_ids: Vec<u64> = self.sessions_in_room(room_id).collect();
+                for &session_id in &session_ids {
+                    self.registry.unsubscribe(session_id, room_id);
+                }
+                let log = LogEvent::info(LogTarget::Admin, "room closed", now)
+                    .room(room_id)
+                    .field("sessions", session_ids.len());
+                actions.push(log.into());
+                actions.push(self.audit(AuditEvent::RoomClosed { room_id, closed_by: None }));
+                self.webhooks.forget(room_id);
+                Ok(AdminResponse::Done)
+            },
 
-    /// Convert a `RoomAction` to `ServerActions`.
-    fn process_room_action(
-        &mut self,
-        room_action: RoomAction<E::Instant>,
-        sender_session_id: u64,
-    ) -> Vec<ServerAction<E::Instant>> {
-        match room_action {
-            RoomAction::Broadcast { room_id, frame, exclude_sender, .. } => {
-                if frame.header.opcode_enum() == Some(Opcode::Welcome) {
-                    let recipient_id = frame.header.recipient_id();
-                    if let Some(session_id) = self.registry.session_id_for_user(recipient_id) {
-                        return vec![ServerAction::SendToSession { session_id, frame }];
-                    }
-                    return vec![ServerAction::Log {
-                        level: LogLevel::Warn,
-                        message: format!(
-                            "Welcome recipient {recipient_id} not connected (room {room_id})"
-                        ),
-                        timestamp: self.env.now(),
-                    }];
+            AdminRequest::SetRetention { room_id, max_age_secs, max_frames } => {
+                if !self.room_manager.has_room(room_id) {
+                    return Err(RoomError::RoomNotFound(room_id).into());
                 }
+                let policy =
+                    RetentionPolicy { max_age: max_age_secs.map(Duration::from_secs), max_frames };
+                self.set_retention_policy(room_id, policy);
+                let log = LogEvent::info(LogTarget::Admin, "retention policy set", now)
+                    .room(room_id)
+                    .field("max_age_secs", format!("{max_age_secs:?}"))
+                    .field("max_frames", format!("{max_frames:?}"));
+                actions.push(log.into());
+                Ok(AdminResponse::Done)
+            },
 
-                let mut session_ids: Vec<u64> = self.sessions_in_room(room_id).collect();
-                if exclude_sender
-                    && let Some(pos) = session_ids.iter().position(|&id| id == sender_session_id)
-                {
-                    session_ids.remove(pos);
+            AdminRequest::SetPresence { room_id, enabled } => {
+                if !self.room_manager.has_room(room_id) {
+                    return Err(RoomError::RoomNotFound(room_id).into());
                 }
+                self.set_room_presence(room_id, enabled);
+                let log = LogEvent::info(LogTarget::Admin, "presence sharing set", now)
+                    .room(room_id)
+                    .field("enabled", enabled);
+                actions.push(log.into());
+                Ok(AdminResponse::Done)
+            },
 
-                vec![ServerAction::Broadcast { session_ids, frame }]
+            AdminRequest::SetRoomKind { room_id, kind } => {
+                self.room_manager.set_room_kind(room_id, kind, &self.storage)?;
+                let log = LogEvent::info(LogTarget::Admin, "room kind set", now)
+                    .room(room_id)
+                    .field("kind", format!("{kind:?}"));
+                actions.push(log.into());
+                Ok(AdminResponse::Done)
             },
 
-            RoomAction::PersistFrame { room_id, log_index, frame, .. } => {
-                if let Err(e) = self.storage.store_frame(room_id, log_index, &frame) {
-                    // Sequencer state drifted from storage. Re-initialize
-                    // room state from storage on next frame to sync
-                    if let StorageError::Conflict { .. } = e {
-                        self.clear_room_sequencer(room_id);
-                    }
+            AdminRequest::SetFrameRule { kind, opcode, rule } => {
+                self.room_manager.set_frame_rule(kind, opcode, rule);
+                let log = LogEvent::info(LogTarget::Admin, "frame rule set", now)
+                    .field("kind", format!("{kind:?}"))
+                    .field("opcode", format!("{opcode:?}"))
+                    .field("rule", format!("{rule:?}"));
+                actions.push(log.into());
+                Ok(AdminResponse::Done)
+            },
 
-                    return vec![ServerAction::Log {
-                        level: LogLevel::Error,
-                        message: format!("Failed to persist frame: {e}"),
-                        timestamp: self.env.now(),
-                    }];
+            AdminRequest::SetWebhook { room_id, webhook } => {
+                if !self.room_manager.has_room(room_id) {
+                    return Err(RoomError::RoomNotFound(room_id).into());
+                }
+                if let Some(target) = &webhook {
+                    webhook::check_target(target)
+                        .map_err(|reason| ServerError::Protocol(reason.to_string()))?;
                 }
-                vec![]
+                let log = LogEvent::info(LogTarget::Admin, "webhook set", now)
+                    .room(room_id)
+                    .field("url", webhook.as_ref().map_or("", |target| target.url.as_str()));
+                self.webhooks.set_target(room_id, webhook);
+                actions.push(log.into());
+                Ok(AdminResponse::Done)
             },
 
-            RoomAction::Reject { sender_id, reason, processed_at } => {
-                let error = Payload::Error(ErrorPayload::frame_rejected(&reason));
-                match error.into_frame(FrameHeader::new(Opcode::Error)) {
-                    Ok(frame) => vec![
-                        ServerAction::SendToSession { session_id: sender_id, frame },
-                        ServerAction::Log {
-                            level: LogLevel::Warn,
-                            message: format!("rejected frame from {sender_id}: {reason}"),
-                            timestamp: processed_at,
-                        },
-                    ],
-                    Err(_) => vec![ServerAction::Log {
-                        level: LogLevel::Warn,
-                        message: format!("rejected frame from {sender_id}: {reason}"),
-                        timestamp: processed_at,
-                    }],
-                }
+            AdminRequest::ExportAudit { after, limit } => {
+                let (entries, next) = self.audit.export(after, limit);
+                Ok(AdminResponse::Audit { entries, next })
             },
 
-            RoomAction::SendSyncResponse { sender_id, room_id, frames, has_more, .. } => {
-                // Server doesn't track epoch - set to 0, clients determine epoch from frames
-                let response =
-                    Payload::SyncResponse(SyncResponse { frames, has_more, server_epoch: 0 });
+            AdminRequest::SendNotice(notice) => {
+                actions.extend(self.send_notice(notice)?);
+                Ok(AdminResponse::Done)
+            },
 
-                match response.into_frame(FrameHeader::new(Opcode::SyncResponse)) {
-                    Ok(mut frame) => {
-                        frame.header.set_room_id(room_id);
-                        vec![ServerAction::SendToSession { session_id: sender_id, frame }]
-                    },
-                    Err(e) => {
-                        vec![ServerAction::Log {
-                            level: LogLevel::Error,
-                            message: format!("failed to encode SyncResponse: {e}"),
-                            timestamp: self.env.now(),
-                        }]
-                    },
+            AdminRequest::Stats => {
+                let stats = ServerStats {
+                    connections: self.connections.len() as u64,
+                    authenticated_sessions: self.registry.authenticated_count() as u64,
+                    rooms: self.room_manager.room_count() as u64,
+                    key_packages: self.key_package_store.count() as u64,
+                };
+                Ok(AdminResponse::Stats(stats))
+            },
+        }
+    }
+
+    /// Broadcast an operator notice for `AdminRequest::SendNotice`: to the
+    /// room's sessions if it names one, otherwise to every client session.
+    /// Peer servers are not sent notices.
+    fn send_notice(
+        &self,
+        notice: ServerNotice,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        let mut log = LogEvent::info(LogTarget::Admin, "notice sent", self.env.now())
+            .field("kind", format!("{:?}", notice.kind));
+        let mut header = FrameHeader::new(Opcode::ServerNotice);
+        let session_ids: Vec<u64> = match notice.room_id {
+            Some(room_id) => {
+                if !self.room_manager.has_room(room_id) {
+                    return Err(RoomError::RoomNotFound(room_id).into());
                 }
+                header.set_room_id(room_id);
+                log = log.room(room_id);
+                self.sessions_in_room(room_id).collect()
             },
+            None => self
+                .registry
+                .authenticated_sessions()
+                .filter(|&session_id| self.federation.peer_of(session_id).is_none())
+                .collect(),
+        };
+
+        let frame = Payload::ServerNotice(notice)
+            .into_frame(header)
+            .map_err(|e| ServerError::Protocol(e.to_string()))?;
+        let log = log.field("sessions", session_ids.len());
+        Ok(vec![ServerAction::Broadcast { session_ids, frame }, log.into()])
+    }
+
+    /// Describe a room for `AdminRequest::RoomInfo`.
+    fn room_info(&self, room_id: u128) -> Result<RoomInfo, ServerError> {
+        let metadata =
+            self.room_manager.metadata(room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        let policy = self.retention_policy(room_id);
+
+        Ok(RoomInfo {
+            room_id,
+            creator: metadata.creator,
+            created_at_secs: metadata.created_at_secs,
+            latest_log_index: self.storage.latest_log_index(room_id)?,
+            members: u32::try_from(metadata.acl.members().count()).unwrap_or(u32::MAX),
+            subscribers: u32::try_from(self.registry.room_session_count(room_id))
+                .unwrap_or(u32::MAX),
+            retention_max_age_secs: policy.max_age.map(|age| age.as_secs()),
+            retention_max_frames: policy.max_frames,
+            kind: metadata.kind,
+        })
+    }
+
+    /// Peer server whose token a Hello frame carries, if any.
+    fn presents_peer_token(&self, frame: &Frame) -> Option<u64> {
+        match Payload::from_frame(frame) {
+            Ok(Payload::Hello(hello)) => self.federation.authenticate(hello.auth_token.as_deref()?),
+            _ => None,
         }
     }
 
-    /// Create a new room.
+    /// Relay a local user's frame for a remote room to the room's home.
     ///
-    /// The creator is automatically subscribed to the room.
-    pub fn create_room(
+    /// The session is subscribed locally so the home's relay of the
+    /// sequenced frame reaches it.
+    fn forward_to_home(
         &mut self,
-        room_id: u128,
-        creator_session_id: u64,
-    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        session_id: u64,
+        home: u64,
+        frame: &Frame,
+    ) -> Vec<ServerAction<E::Instant>> {
         let now = self.env.now();
+        let room_id = frame.header.room_id();
 
-        let info = self
-            .registry
-            .sessions(creator_session_id)
-            .ok_or(ServerError::SessionNotFound(creator_session_id))?;
-
-        let user_id = info.user_id.unwrap_or(creator_session_id);
-
-        self.room_manager.create_room(room_id, user_id, &self.env, &self.storage)?;
-        self.registry.subscribe(creator_session_id, room_id);
+        let Some(peer_session) = self.federation.peer_session(home) else {
+            let error = ErrorPayload::frame_rejected("room's home server is unreachable");
+            let log = LogEvent::warn(LogTarget::Federation, "home server not connected", now)
+                .field("server_id", home);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        };
 
-        Ok(vec![ServerAction::Log {
-            level: LogLevel::Info,
-            message: format!("room {room_id:032x} created by session {creator_session_id}"),
-            timestamp: now,
-        }])
+        match self.federation.wrap(room_id, frame, &[]) {
+            Ok(relay) => {
+                self.registry.subscribe(session_id, room_id);
+                vec![ServerAction::SendToSession { session_id: peer_session, frame: relay }]
+            },
+            Err(e) => vec![
+                LogEvent::error(LogTarget::Federation, "failed to encode FedAppend", now)
+                    .room(room_id)
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ],
+        }
     }
 
-    /// Subscribe a session to a room.
-    pub fn subscribe_to_room(&mut self, session_id: u64, room_id: u128) -> bool {
-        self.registry.subscribe(session_id, room_id)
-    }
+    /// Relay a sequenced frame to every connected peer with members in the
+    /// room. Only rooms homed here are relayed.
+    fn relay_to_peers(&self, room_id: u128, frame: &Frame) -> Vec<ServerAction<E::Instant>> {
+        let peer_sessions = self.federation.relay_sessions(room_id);
+        if peer_sessions.is_empty() {
+            return Vec::new();
+        }
 
-    /// Unsubscribe a session from a room.
-    pub fn unsubscribe_from_room(&mut self, session_id: u64, room_id: u128) -> bool {
-        self.registry.unsubscribe(session_id, room_id)
+        match self.federation.wrap(room_id, frame, &[]) {
+            Ok(relay) => vec![ServerAction::Broadcast { session_ids: peer_sessions, frame: relay }],
+            Err(e) => vec![
+                LogEvent::error(
+                    LogTarget::Federation,
+                    "failed to encode FedAppend",
+                    self.env.now(),
+                )
+                .room(room_id)
+                .field("error", e)
+                .into(),
+            ],
+        }
     }
 
-    /// All sessions subscribed to a room.
-    pub fn sessions_in_room(&self, room_id: u128) -> impl Iterator<Item = u64> + '_ {
-        self.registry.sessions_in_room(room_id)
-    }
+    /// Handle a `FedAppend` or `FedNack` frame.
+    ///
+    /// Only authenticated peer sessions may send these.
+    fn handle_federation_frame(
+        &mut self,
+        session_id: u64,
+        frame: &Frame,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        let now = self.env.now();
 
-    /// Number of active connections.
-    pub fn connection_count(&self) -> usize {
-        self.connections.len()
-    }
+        let Some(peer) = self.federation.peer_of(session_id) else {
+            let error = ErrorPayload::permission_denied("peer access required");
+            let log = LogEvent::warn(LogTarget::Federation, "federation frame denied", now);
+            return Ok(self.error_reply(session_id, None, error, log));
+        };
 
-    /// Room exists and is initialized.
-    pub fn has_room(&self, room_id: u128) -> bool {
-        self.room_manager.has_room(room_id)
+        match Payload::from_frame(frame) {
+            Ok(Payload::FedAppend(append)) => self.handle_fed_append(session_id, peer, &append),
+            Ok(Payload::FedNack(nack)) => Ok(self.handle_fed_nack(nack)),
+            Ok(_) => Ok(vec![
+                LogEvent::warn(LogTarget::Federation, "unexpected federation payload", now)
+                    .session(session_id)
+                    .into(),
+            ]),
+            Err(e) => Ok(vec![
+                LogEvent::warn(LogTarget::Federation, "invalid federation frame", now)
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ]),
+        }
     }
 
-    /// Current MLS epoch for a room.
+    /// Handle a frame relayed by a peer.
     ///
-    /// Returns `None` - server is routing-only and doesn't track MLS epoch.
-    /// Clients own their MLS state and track epochs.
-    pub fn room_epoch(&self, _room_id: u128) -> Option<u64> {
-        None
+    /// Rooms homed here sequence it as if a local session sent it and
+    /// answer refusals with `FedNack`. Sequenced frames from a remote room's
+    /// home go to local subscribers. Anything else is passed on towards the
+    /// room's home.
+    fn handle_fed_append(
+        &mut self,
+        session_id: u64,
+        peer: u64,
+        append: &FedAppend,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        let now = self.env.now();
+        let room_id = append.room_id;
+
+        if let Err(e) = self.federation.check_path(append) {
+            return Ok(vec![
+                LogEvent::warn(LogTarget::Federation, "relay dropped", now)
+                    .room(room_id)
+                    .session(session_id)
+                    .field("reason", e)
+                    .into(),
+            ]);
+        }
+
+        let frame = match Frame::decode(&append.frame) {
+            Ok(frame) if frame.header.room_id() == room_id => frame,
+            Ok(_) => return Ok(self.fed_nack(session_id, room_id, 0, "room mismatch")),
+            Err(e) => return Ok(self.fed_nack(session_id, room_id, 0, &e.to_string())),
+        };
+        let sender_id = frame.header.sender_id();
+
+        match self.federation.remote_home(room_id) {
+            None => {
+                self.federation.add_remote_member(room_id, peer);
+                match self.room_manager.process_frame(frame, now, &self.storage) {
+                    Ok(room_actions) => {
+                        self.route_room_result(session_id, room_id, Ok(room_actions))
+                    },
+                    Err(e) => Ok(self.fed_nack(session_id, room_id, sender_id, &e.to_string())),
+                }
+            },
+            Some(home) if home == peer => {
+                let session_ids = self.sessions_in_room(room_id).collect();
+                Ok(vec![ServerAction::Broadcast { session_ids, frame }])
+            },
+            Some(home) => {
+                let Some(home_session) = self.federation.peer_session(home) else {
+                    return Ok(self.fed_nack(session_id, room_id, sender_id, "home unreachable"));
+                };
+                match self.federation.wrap(room_id, &frame, &append.hops) {
+                    Ok(relay) => Ok(vec![ServerAction::SendToSession {
+                        session_id: home_session,
+                        frame: relay,
+                    }]),
+                    Err(e) => Ok(self.fed_nack(session_id, room_id, sender_id, &e.to_string())),
+                }
+            },
+        }
     }
 
-    /// Storage backend for frame/state persistence.
-    pub fn storage(&self) -> &S {
-        &self.storage
+    /// Tell the local sender of a refused relay why it was refused.
+    fn handle_fed_nack(&self, nack: FedNack) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let log = LogEvent::warn(LogTarget::Federation, "relay refused by home", now)
+            .room(nack.room_id)
+            .field("sender_id", nack.sender_id)
+            .field("reason", &nack.reason);
+
+        match self.registry.session_id_for_user(nack.sender_id) {
+            Some(session_id) => self.error_reply(
+                session_id,
+                Some(nack.room_id),
+                ErrorPayload::frame_rejected(nack.reason),
+                log,
+            ),
+            None => vec![log.into()],
+        }
     }
 
-    /// Clear sequencer state for a room.
-    ///
-    /// This is useful when we detect a log index conflict and need to
-    /// re-initialize the sequencer from storage.
-    pub fn clear_room_sequencer(&mut self, room_id: u128) -> bool {
-        self.room_manager.clear_room_sequencer(room_id)
+    /// Refuse a relayed frame.
+    fn fed_nack(
+        &self,
+        session_id: u64,
+        room_id: u128,
+        sender_id: u64,
+        reason: &str,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let log: ServerAction<E::Instant> =
+            LogEvent::warn(LogTarget::Federation, "relay refused", now)
+                .room(room_id)
+                .session(session_id)
+                .field("sender_id", sender_id)
+                .field("reason", reason)
+                .into();
+
+        match Federation::nack(room_id, sender_id, reason.to_string()) {
+            Ok(frame) => vec![ServerAction::SendToSession { session_id, frame }, log],
+            Err(e) => vec![
+                log,
+                LogEvent::error(LogTarget::Federation, "failed to encode FedNack", now)
+                    .field("error", e)
+                    .into(),
+            ],
+        }
     }
 
-    /// Recover all room state from storage.
-    ///
-    /// Called during server startup to restore rooms that were persisted
-    /// before a restart. After this call, the server is ready to accept
-    /// connections for all previously active rooms.
-    ///
-    /// # Errors
+    /// Send a user's new status to the other subscribers of every room that
+    /// shares presence and has the user as a member.
+    fn announce_presence(
+        &self,
+        session_id: u64,
+        status: &PresenceStatus,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let rooms: Vec<u128> = self
+            .presence
+            .rooms()
+            .filter(|&room_id| self.presence.shares_with(room_id))
+            .filter(|&room_id| {
+                self.room_manager.acl(room_id).is_some_and(|acl| acl.is_member(status.user_id))
+            })
+            .collect();
+
+        let mut actions = Vec::new();
+        for room_id in rooms {
+            let mut header = FrameHeader::new(Opcode::Presence);
+            header.set_room_id(room_id);
+            match Payload::Presence(status.clone()).into_frame(header) {
+                Ok(frame) => {
+                    let session_ids =
+                        self.sessions_in_room(room_id).filter(|&id| id != session_id).collect();
+                    actions.push(ServerAction::Broadcast { session_ids, frame });
+                },
+                Err(e) => actions.push(
+                    LogEvent::error(
+                        LogTarget::Presence,
+                        "failed to encode Presence",
+                        self.env.now(),
+                    )
+                    .room(room_id)
+                    .field("error", e)
+                    .into(),
+                ),
+            }
+        }
+        actions
+    }
+
+    /// Handle a `PresenceQuery`, answering with the status of every queried
+    /// user the server's visibility setting lets the session see.
+    fn handle_presence_query(
+        &self,
+        session_id: u64,
+        frame: &Frame,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+
+        if self.presence.visibility() == PresenceVisibility::Off {
+            let error = ErrorPayload::permission_denied("presence is disabled");
+            let log = LogEvent::debug(LogTarget::Presence, "presence query refused", now);
+            return self.error_reply(session_id, None, error, log);
+        }
+
+        let query: PresenceQuery = match Payload::from_frame(frame) {
+            Ok(Payload::PresenceQuery(query)) => query,
+            Ok(_) => {
+                let error = ErrorPayload::invalid_payload("expected PresenceQuery payload");
+                let log = LogEvent::warn(LogTarget::Presence, "invalid presence query", now);
+                return self.error_reply(session_id, None, error, log);
+            },
+            Err(e) => {
+                let log = LogEvent::warn(LogTarget::Presence, "invalid presence query", now)
+                    .field("error", &e);
+                return self.error_reply(
+                    session_id,
+                    None,
+                    ErrorPayload::invalid_payload(e.to_string()),
+                    log,
+                );
+            },
+        };
+
+        let querier = self.session_user(session_id);
+        let users = query
+            .user_ids
+            .into_iter()
+            .take(MAX_QUERY_USERS)
+            .filter(|&user_id| self.presence_visible(querier, user_id))
+            .map(|user_id| {
+                let online = self.registry.session_id_for_user(user_id).is_some();
+                self.presence.status(user_id, online)
+            })
+            .collect();
+
+        match Payload::PresenceReply(PresenceReply { users })
+            .into_frame(FrameHeader::new(Opcode::PresenceReply))
+        {
+            Ok(frame) => vec![ServerAction::SendToSession { session_id, frame }],
+            Err(e) => vec![
+                LogEvent::error(LogTarget::Presence, "failed to encode PresenceReply", now)
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ],
+        }
+    }
+
+    /// Handle a `DirectoryPublish`, listing or unlisting the room named in
+    /// the header if the session's user is one of its admins.
+    fn handle_directory_publish(
+        &mut self,
+        session_id: u64,
+        frame: &Frame,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let room_id = frame.header.room_id();
+
+        let Ok(Payload::DirectoryPublish(DirectoryPublish { name })) = Payload::from_frame(frame)
+        else {
+            let error = ErrorPayload::invalid_payload("expected DirectoryPublish payload");
+            let log = LogEvent::warn(LogTarget::Room, "invalid directory listing", now);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        };
+        let name = match name.as_deref().map(directory::listing_name).transpose() {
+            Ok(name) => name,
+            Err(reason) => {
+                let log = LogEvent::debug(LogTarget::Room, "invalid directory listing", now)
+                    .room(room_id)
+                    .field("reason", reason);
+                return self.error_reply(
+                    session_id,
+                    Some(room_id),
+                    ErrorPayload::invalid_payload(reason),
+                    log,
+                );
+            },
+        };
+
+        let user_id = self.session_user(session_id);
+        let authorized = match self.room_manager.acl(room_id) {
+            Some(acl) => acl
+                .authorize(user_id, frame)
+                .map_err(|reason| RoomError::AccessDenied { room_id, reason }),
+            None => Err(RoomError::RoomNotFound(room_id)),
+        };
+        let listed = name.is_some();
+        if let Err(e) =
+            authorized.and_then(|_| self.room_manager.set_listing(room_id, name, &self.storage))
+        {
+            return self.make_error_response(session_id, room_id, &e.into());
+        }
+
+        let message = if listed { "room listed" } else { "room unlisted" };
+        vec![LogEvent::info(LogTarget::Room, message, now).room(room_id).session(session_id).into()]
+    }
+
+    /// Handle a `DirectorySearch`, answering with one page of listed rooms.
+    fn handle_directory_search(
+        &self,
+        session_id: u64,
+        frame: &Frame,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+
+        let Ok(Payload::DirectorySearch(search)) = Payload::from_frame(frame) else {
+            let error = ErrorPayload::invalid_payload("expected DirectorySearch payload");
+            let log = LogEvent::warn(LogTarget::Room, "invalid directory search", now);
+            return self.error_reply(session_id, None, error, log);
+        };
+
+        let results = directory::search(&self.room_manager, &search);
+        match Payload::DirectoryResults(results)
+            .into_frame(FrameHeader::new(Opcode::DirectoryResults))
+        {
+            Ok(frame) => vec![ServerAction::SendToSession { session_id, frame }],
+            Err(e) => vec![
+                LogEvent::error(LogTarget::Room, "failed to encode DirectoryResults", now)
+                    .session(session_id)
+                    .field("error", e)
+                    .into(),
+            ],
+        }
+    }
+
+    /// Handle an `Invite`, relaying it to the invitee's session.
     ///
-    /// - `ServerError::Storage` if storage enumeration fails
-    /// - `ServerError::Room` if room recovery fails
-    pub fn recover_from_storage(&mut self) -> Result<usize, ServerError> {
-        let room_ids = self.storage.list_rooms()?;
+    /// The sender must be the inviter and a member of the room, and the
+    /// invitee must not be banned. Checking the signature is left to the
+    /// invitee.
+    fn handle_invite(&self, session_id: u64, frame: &Frame) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let room_id = frame.header.room_id();
 
-        let room_count = room_ids.len();
+        let Ok(Payload::Invite(invite)) = Payload::from_frame(frame) else {
+            let error = ErrorPayload::invalid_payload("expected Invite payload");
+            let log = LogEvent::warn(LogTarget::Room, "invalid invite", now);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        };
 
-        tracing::info!(room_count, "Recovering rooms from storage");
+        let user_id = self.session_user(session_id);
+        if invite.inviter != user_id || invite.room_id != room_id {
+            let error = ErrorPayload::permission_denied("invite names another inviter or room");
+            let log = LogEvent::warn(LogTarget::Room, "invite refused", now).room(room_id);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        }
+        if invite.expires_at <= self.env.wall_clock_secs() {
+            let error = ErrorPayload::invalid_payload("invite has expired");
+            let log = LogEvent::debug(LogTarget::Room, "expired invite", now).room(room_id);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        }
 
-        for room_id in room_ids {
-            self.room_manager.recover_room(room_id, &self.storage)?;
+        let authorized = match self.room_manager.acl(room_id) {
+            Some(acl) => acl
+                .authorize_invite(user_id, invite.invitee)
+                .map_err(|reason| RoomError::AccessDenied { room_id, reason }),
+            None => Err(RoomError::RoomNotFound(room_id)),
+        };
+        if let Err(e) = authorized {
+            return self.make_error_response(session_id, room_id, &e.into());
         }
 
-        tracing::info!(room_count, "Room recovery complete");
+        let Some(recipient) = self.registry.session_id_for_user(invite.invitee) else {
+            let error = ErrorPayload::recipient_offline(invite.invitee);
+            let log = LogEvent::debug(LogTarget::Room, "invitee not connected", now)
+                .room(room_id)
+                .field("invitee", invite.invitee);
+            return self.error_reply(session_id, Some(room_id), error, log);
+        };
 
-        Ok(room_count)
+        vec![
+            ServerAction::SendToSession { session_id: recipient, frame: frame.clone() },
+            LogEvent::debug(LogTarget::Room, "invite relayed", now)
+                .room(room_id)
+                .session(session_id)
+                .field("invitee", invite.invitee)
+                .into(),
+        ]
     }
 
-    /// Access the room manager (for testing).
-    #[cfg(test)]
-    pub fn room_manager(&self) -> &RoomManager {
-        &self.room_manager
+    /// Whether `querier` may see `user_id`'s presence.
+    fn presence_visible(&self, querier: u64, user_id: u64) -> bool {
+        match self.presence.visibility() {
+            PresenceVisibility::Off => false,
+            PresenceVisibility::Everyone => true,
+            PresenceVisibility::RoomMembers => {
+                querier == user_id
+                    || self.room_manager.room_ids().any(|room_id| {
+                        self.room_manager
+                            .acl(room_id)
+                            .is_some_and(|acl| acl.is_member(querier) && acl.is_member(user_id))
+                    })
+            },
+        }
     }
-}
 
-#[allow(clippy::missing_fields_in_debug)]
-impl<E, S> std::fmt::Debug for ServerDriver<E, S>
-where
-    E: Environment,
-    S: Storage,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ServerDriver")
-            .field("connection_count", &self.connections.len())
-            .field("session_count", &self.registry.session_count())
-            .finish()
+    /// Handle a connection being closed.
+    fn handle_connection_closed(
+        &mut self,
+        session_id: u64,
+        reason: &str,
+    ) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let mut actions = Vec::new();
+
+        if let Some(mut conn) = self.connections.remove(&session_id) {
+            conn.close();
+        }
+
+        if let Some(server_id) = self.federation.session_closed(session_id) {
+            actions.push(
+                LogEvent::info(LogTarget::Federation, "peer disconnected", now)
+                    .session(session_id)
+                    .field("server_id", server_id)
+                    .into(),
+            );
+        }
+
+        if let Some((info, rooms)) = self.registry.unregister_session(session_id) {
+            actions.push(
+                LogEvent::info(LogTarget::Connection, "connection closed", now)
+                    .session(session_id)
+                    .field("reason", reason)
+                    .field("rooms", rooms.len())
+                    .into(),
+            );
+
+            if let Some(user_id) = info.user_id.filter(|_| info.authenticated) {
+                let status = self.presence.went_offline(user_id, self.env.wall_clock_secs());
+                actions.extend(self.announce_presence(session_id, &status));
+            }
+        }
+
+        if self.draining_since.is_some() && self.connections.is_empty() {
+            actions.extend(self.finish_drain(now));
+        }
+
+        actions
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use bytes::Bytes;
-    use lockframe_core::env::test_utils::MockEnv;
-    use lockframe_proto::FrameHeader;
+    /// Start draining: say Goodbye to every session and refuse new ones.
+    fn handle_begin_shutdown(&mut self) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        if self.draining_since.is_some() {
+            return Vec::new();
+        }
+        self.draining_since = Some(now);
+
+        let mut actions = vec![
+            LogEvent::info(LogTarget::Connection, "shutdown started", now)
+                .field("sessions", self.connections.len())
+                .into(),
+        ];
+
+        let goodbye =
+            Payload::Goodbye(Goodbye::server_shutdown(self.config.shutdown_retry_after.as_secs()))
+                .into_frame(FrameHeader::new(Opcode::Goodbye));
+        match goodbye {
+            Ok(frame) => {
+                let session_ids = self.connections.keys().copied().collect();
+                actions.push(ServerAction::Broadcast { session_ids, frame });
+            },
+            Err(e) => actions.push(
+                LogEvent::error(LogTarget::Connection, "failed to encode Goodbye", now)
+                    .field("error", e)
+                    .into(),
+            ),
+        }
+
+        if self.connections.is_empty() {
+            actions.extend(self.finish_drain(now));
+        }
+        actions
+    }
+
+    /// Flush storage and emit `DrainComplete`, once.
+    fn finish_drain(&mut self, now: E::Instant) -> Vec<ServerAction<E::Instant>> {
+        if self.drained {
+            return Vec::new();
+        }
+        self.drained = true;
+
+        let mut actions = Vec::new();
+        if let Err(e) = self.storage.flush() {
+            actions.push(
+                LogEvent::error(LogTarget::Connection, "failed to flush storage", now)
+                    .field("error", e)
+                    .into(),
+            );
+        }
+        actions.push(LogEvent::info(LogTarget::Connection, "drain complete", now).into());
+        actions.push(ServerAction::DrainComplete);
+        actions
+    }
+
+    /// Handle periodic tick for timeout checking.
+    fn handle_tick(&mut self) -> Vec<ServerAction<E::Instant>> {
+        let now = self.env.now();
+        let mut actions = Vec::new();
+
+        let session_ids: Vec<u64> = self.connections.keys().copied().collect();
+
+        for session_id in session_ids {
+            if let Some(conn) = self.connections.get_mut(&session_id) {
+                let conn_actions = conn.tick(now);
+
+                for action in conn_actions {
+                    match action {
+                        ConnectionAction::SendFrame(f) => {
+                            actions.push(ServerAction::SendToSession { session_id, frame: f });
+                        },
+                        ConnectionAction::Close { reason } => {
+                            actions.push(ServerAction::CloseConnection { session_id, reason });
+                        },
+                    }
+                }
+            }
+        }
+
+        if self.retention.start_pass(now) {
+            actions.extend(self.compact_rooms(now));
+        }
+
+        if let Some(since) = self.draining_since
+            && !self.drained
+            && now - since >= self.config.drain_timeout
+        {
+            for &session_id in self.connections.keys() {
+                actions.push(ServerAction::CloseConnection {
+                    session_id,
+                    reason: "server shut down".to_string(),
+                });
+            }
+            actions.extend(self.finish_drain(now));
+        }
+
+        actions
+    }
+
+    /// Apply each room's retention policy, truncating history that exceeds it.
+    fn compact_rooms(&mut self, now: E::Instant) -> Vec<ServerAction<E::Instant>> {
+        let room_ids: Vec<u128> = self.room_manager.room_ids().collect();
+        let now_secs = self.env.wall_clock_secs();
+
+        let mut actions = Vec::new();
+        for room_id in room_ids {
+            match self.retention.compact_room(room_id, now, &self.storage) {
+                Ok(None) => {},
+                Ok(Some(first_kept)) => {
+                    self.room_manager.forget_room_usage(room_id);
+                    actions.push(
+                        LogEvent::info(LogTarget::Retention, "history truncated", now)
+                            .room(room_id)
+                            .field("first_kept", first_kept)
+                            .into(),
+                    );
+                },
+                Err(e) => actions.push(
+                    LogEvent::warn(LogTarget::Retention, "compaction failed", now)
+                        .room(room_id)
+                        .field("error", e)
+                        .into(),
+                ),
+            }
+
+            match self.expiry.sweep_room(room_id, now_secs, &self.storage) {
+                Ok(0) => {},
+                Ok(frames) => {
+                    self.room_manager.forget_room_usage(room_id);
+                    actions.push(
+                        LogEvent::info(LogTarget::Retention, "messages expired", now)
+                            .room(room_id)
+                            .field("frames", frames)
+                            .into(),
+                    );
+                },
+                Err(e) => actions.push(
+                    LogEvent::warn(LogTarget::Retention, "expiry failed", now)
+                        .room(room_id)
+                        .field("error", e)
+                        .into(),
+                ),
+            }
+
+            // Whatever history is left may move to a colder tier
+            match self.storage.offload_history(room_id) {
+                Ok(0) => {},
+                Ok(frames) => {
+                    self.room_manager.forget_room_usage(room_id);
+                    actions.push(
+                        LogEvent::info(LogTarget::Retention, "history offloaded", now)
+                            .room(room_id)
+                            .field("frames", frames)
+                            .into(),
+                    );
+                },
+                Err(e) => actions.push(
+                    LogEvent::warn(LogTarget::Retention, "offload failed", now)
+                        .room(room_id)
+                        .field("error", e)
+                        .into(),
+                ),
+            }
+        }
+        actions
+    }
+
+    /// Convert a `RoomAction` to `ServerActions`.
+    fn process_room_action(
+        &mut self,
+        room_action: RoomAction<E::Instant>,
+        sender_session_id: u64,
+    ) -> Vec<ServerAction<E::Instant>> {
+        match room_action {
+            RoomAction::Broadcast { room_id, frame, exclude_sender, .. } => {
+                if frame.header.opcode_enum() == Some(Opcode::Welcome) {
+                    let recipient_id = frame.header.recipient_id();
+                    if let Some(session_id) = self.registry.session_id_for_user(recipient_id) {
+                        return vec![ServerAction::SendToSession { session_id, frame }];
+                    }
+                    return vec![
+                        LogEvent::warn(
+                            LogTarget::Room,
+                            "Welcome recipient not connected",
+                            self.env.now(),
+                        )
+                        .room(room_id)
+                        .field("recipient_id", recipient_id)
+                        .into(),
+                    ];
+                }
+
+                let mut session_ids: Vec<u64> = self.sessions_in_room(room_id).collect();
+                if exclude_sender
+                    && let Some(pos) = session_ids.iter().position(|&id| id == sender_session_id)
+                {
+                    session_ids.remove(pos);
+                }
+
+                let relays = self.relay_to_peers(room_id, &frame);
+                let mut actions = vec![ServerAction::Broadcast { session_ids, frame }];
+                actions.extend(relays);
+                actions
+            },
+
+            RoomAction::PersistFrame { room_id, log_index, frame, .. } => {
+                match self.storage.store_frame(room_id, log_index, &frame) {
+                    Ok(()) => vec![],
+                    Err(e) => self.persist_failed(room_id, log_index, &e, &[]),
+                }
+            },
+
+            RoomAction::Duplicate { room_id, log_index, frame, processed_at } => {
+                // A peer would relay the original to all its subscribers
+                // again, which already received it
+                if self.federation.peer_of(sender_session_id).is_some() {
+                    return vec![
+                        LogEvent::debug(
+                            LogTarget::Federation,
+                            "dropped relayed retry",
+                            processed_at,
+                        )
+                        .room(room_id)
+                        .field("log_index", log_index)
+                        .into(),
+                    ];
+                }
+                vec![ServerAction::SendToSession { session_id: sender_session_id, frame }]
+            },
+
+            RoomAction::QuotaWarning { room_id, used_bytes, limit_bytes, processed_at } => {
+                vec![
+                    LogEvent::warn(LogTarget::Room, "room nearing storage quota", processed_at)
+                        .room(room_id)
+                        .field("used_bytes", used_bytes)
+                        .field("limit_bytes", limit_bytes)
+                        .into(),
+                ]
+            },
+
+            RoomAction::Reject { sender_id, reason, processed_at } => {
+                let log = LogEvent::warn(LogTarget::Sequencer, "frame rejected", processed_at)
+                    .field("sender_id", sender_id)
+                    .field("reason", &reason);
+                self.error_reply(sender_id, None, ErrorPayload::frame_rejected(&reason), log)
+            },
+
+            RoomAction::SendSyncResponse {
+                sender_id,
+                room_id,
+                frames,
+                has_more,
+                next_log_index,
+                ..
+            } => {
+                // Server doesn't track epoch - set to 0, clients determine epoch from frames
+                let response = SyncResponse { frames, has_more, server_epoch: 0, next_log_index };
+                self.send_sync_response(sender_id, room_id, response)
+            },
+        }
+    }
+
+    /// Recover from a frame that could not be stored.
+    fn persist_failed(
+        &mut self,
+        room_id: u128,
+        log_index: u64,
+        error: &StorageError,
+        dropped: &[u64],
+    ) -> Vec<ServerAction<E::Instant>> {
+        // The sequencer already counted this index. Re-initialize room state
+        // from storage on next frame so the index is reused rather than left
+        // as a gap
+        self.clear_room_sequencer(room_id);
+
+        let mut actions = vec![
+            LogEvent::error(LogTarget::Sequencer, "failed to persist frame", self.env.now())
+                .room(room_id)
+                .field("log_index", log_index)
+                .field("error", error)
+                .into(),
+        ];
+        if let (Some(first), Some(last)) = (dropped.first(), dropped.last()) {
+            actions.push(
+                LogEvent::warn(
+                    LogTarget::Sequencer,
+                    "dropped frames queued behind a failed write",
+                    self.env.now(),
+                )
+                .room(room_id)
+                .field("count", dropped.len())
+                .field("first", first)
+                .field("last", last)
+                .into(),
+            );
+        }
+        actions
+    }
+
+    /// Encode a `SyncResponse` for one session.
+    fn send_sync_response(
+        &self,
+        session_id: u64,
+        room_id: u128,
+        response: SyncResponse,
+    ) -> Vec<ServerAction<E::Instant>> {
+        match Payload::SyncResponse(response).into_frame(FrameHeader::new(Opcode::SyncResponse)) {
+            Ok(mut frame) => {
+                frame.header.set_room_id(room_id);
+                vec![ServerAction::SendToSession { session_id, frame }]
+            },
+            Err(e) => vec![
+                LogEvent::error(LogTarget::Sync, "failed to encode SyncResponse", self.env.now())
+                    .room(room_id)
+                    .field("error", e)
+                    .into(),
+            ],
+        }
+    }
+
+    /// Create a new room.
+    ///
+    /// The creator is automatically subscribed to the room.
+    pub fn create_room(
+        &mut self,
+        room_id: u128,
+        creator_session_id: u64,
+    ) -> Result<Vec<ServerAction<E::Instant>>, ServerError> {
+        let now = self.env.now();
+
+        let info = self
+            .registry
+            .sessions(creator_session_id)
+            .ok_or(ServerError::SessionNotFound(creator_session_id))?;
+
+        let user_id = info.user_id.unwrap_or(creator_session_id);
+
+        self.room_manager.create_room(room_id, user_id, &self.env, &self.storage)?;
+        self.registry.subscribe(creator_session_id, room_id);
+
+        let mut actions = vec![
+            LogEvent::info(LogTarget::Room, "room created", now)
+                .room(room_id)
+                .session(creator_session_id)
+                .field("creator", user_id)
+                .into(),
+        ];
+        let at_secs = self.env.wall_clock_secs();
+        actions
+            .extend(self.webhooks.room_created(room_id, at_secs).map(ServerAction::DeliverWebhook));
+        actions.extend(self.webhook_member_count(room_id));
+        Ok(actions)
+    }
+
+    /// Subscribe a session to a room.
+    pub fn subscribe_to_room(&mut self, session_id: u64, room_id: u128) -> bool {
+        self.registry.subscribe(session_id, room_id)
+    }
+
+    /// Unsubscribe a session from a room.
+    pub fn unsubscribe_from_room(&mut self, session_id: u64, room_id: u128) -> bool {
+        self.registry.unsubscribe(session_id, room_id)
+    }
+
+    /// All sessions subscribed to a room.
+    pub fn sessions_in_room(&self, room_id: u128) -> impl Iterator<Item = u64> + '_ {
+        self.registry.sessions_in_room(room_id)
+    }
+
+    /// Override the retention policy for a room.
+    ///
+    /// Takes effect on the next compaction pass.
+    pub fn set_retention_policy(&mut self, room_id: u128, policy: RetentionPolicy) {
+        self.retention.set_policy(room_id, policy);
+    }
+
+    /// Retention policy applied to a room.
+    pub fn retention_policy(&self, room_id: u128) -> RetentionPolicy {
+        self.retention.policy(room_id)
+    }
+
+    /// Share members' presence changes with a room, or stop sharing them.
+    ///
+    /// Has no effect while presence visibility is off.
+    pub fn set_room_presence(&mut self, room_id: u128, enabled: bool) {
+        self.presence.set_room(room_id, enabled);
+    }
+
+    /// Whether [`ServerEvent::BeginShutdown`] has been processed.
+    pub fn is_draining(&self) -> bool {
+        self.draining_since.is_some()
+    }
+
+    /// Number of active connections.
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Room exists and is initialized.
+    pub fn has_room(&self, room_id: u128) -> bool {
+        self.room_manager.has_room(room_id)
+    }
+
+    /// Current MLS epoch for a room.
+    ///
+    /// Returns `None` - server is routing-only and doesn't track MLS epoch.
+    /// Clients own their MLS state and track epochs.
+    pub fn room_epoch(&self, _room_id: u128) -> Option<u64> {
+        None
+    }
+
+    /// Storage backend for frame/state persistence.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Clear sequencer state for a room.
+    ///
+    /// This is useful when we detect a log index conflict and need to
+    /// re-initialize the sequencer from storage.
+    pub fn clear_room_sequencer(&mut self, room_id: u128) -> bool {
+        self.room_manager.clear_room_sequencer(room_id)
+    }
+
+    /// Recover all room state from storage.
+    ///
+    /// Called during server startup to restore rooms that were persisted
+    /// before a restart. After this call, the server is ready to accept
+    /// connections for all previously active rooms.
+    ///
+    /// # Errors
+    ///
+    /// - `ServerError::Storage` if storage enumeration fails
+    /// - `ServerError::Room` if room recovery fails
+    pub fn recover_from_storage(&mut self) -> Result<usize, ServerError> {
+        let room_ids = self.storage.list_rooms()?;
+
+        let room_count = room_ids.len();
+
+        tracing::info!(room_count, "Recovering rooms from storage");
+
+        for room_id in room_ids {
+            self.room_manager.recover_room(room_id, &self.storage)?;
+        }
+
+        tracing::info!(room_count, "Room recovery complete");
+
+        Ok(room_count)
+    }
+
+    /// Access the room manager (for testing).
+    #[cfg(test)]
+    pub fn room_manager(&self) -> &RoomManager {
+        &self.room_manager
+    }
+}
+
+/// Member a `Kick` or `Ban` frame removes.
+fn moderated_user(frame: &Frame) -> Option<u64> {
+    if !matches!(frame.header.opcode_enum(), Some(Opcode::Kick | Opcode::Ban)) {
+        return None;
+    }
+    match Payload::from_frame(frame).ok()? {
+        Payload::Kick(kick) => Some(kick.user_id),
+        Payload::Ban(ban) => Some(ban.user_id),
+        _ => None,
+    }
+}
+
+/// Room state compared around sequencing a frame to audit moderation.
+#[derive(Debug, Clone, Copy)]
+struct ModerationSnapshot {
+    closed: bool,
+    member: bool,
+    banned: bool,
+}
+
+/// Whether frames with this opcode are handled by the connection itself
+/// rather than acting on rooms or server state.
+fn is_session_layer(opcode: Option<Opcode>) -> bool {
+    matches!(opcode, Some(Opcode::Hello | Opcode::Ping | Opcode::Pong | Opcode::Goodbye))
+}
+
+#[allow(clippy::missing_fields_in_debug)]
+impl<E, S> std::fmt::Debug for ServerDriver<E, S>
+where
+    E: Environment,
+    S: Storage,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerDriver")
+            .field("connection_count", &self.connections.len())
+            .field("session_count", &self.registry.session_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_core::env::test_utils::MockEnv;
+    use lockframe_proto::{
+        FrameFlags, FrameHeader,
+        payloads::{
+            moderation::CloseRoom,
+            session::{DirectoryEntry, DirectorySearch, NoticeKind},
+        },
+    };
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn server_accepts_connection() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let actions = server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+
+        assert_eq!(server.connection_count(), 1);
+        assert!(matches!(&actions[0], ServerAction::Log(log) if log.level == LogLevel::Debug));
+    }
+
+    #[test]
+    fn server_rejects_when_max_connections_exceeded() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let config = ServerConfig { max_connections: 2, ..Default::default() };
+        let mut server = ServerDriver::new(env, storage, config);
+
+        // Accept two connections
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+
+        // Third should be rejected
+        let actions = server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 3, peer_identity: None })
+            .unwrap();
+
+        assert_eq!(server.connection_count(), 2);
+        assert!(matches!(actions[0], ServerAction::CloseConnection { .. }));
+    }
+
+    #[test]
+    fn server_requires_client_certificate_when_configured() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let config = ServerConfig { require_client_certificate: true, ..Default::default() };
+        let mut server = ServerDriver::new(env, storage, config);
+
+        let actions = server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        assert_eq!(server.connection_count(), 0);
+        assert!(actions.iter().any(|action| matches!(
+            action,
+            ServerAction::CloseConnection { session_id: 1, reason } if reason.contains("certificate")
+        )));
+
+        let identity = PeerIdentity::from_der(b"device certificate".to_vec());
+        server
+            .process_event(ServerEvent::ConnectionAccepted {
+                session_id: 2,
+                peer_identity: Some(identity.clone()),
+            })
+            .unwrap();
+        assert_eq!(server.connection_count(), 1);
+        assert_eq!(
+            server.registry.sessions(2).and_then(|info| info.peer_identity.as_ref()),
+            Some(&identity)
+        );
+    }
+
+    #[test]
+    fn server_handles_connection_closed() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        assert_eq!(server.connection_count(), 1);
+
+        server
+            .process_event(ServerEvent::ConnectionClosed {
+                session_id: 1,
+                reason: "client disconnect".to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(server.connection_count(), 0);
+    }
+
+    #[test]
+    fn server_creates_room() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        // Accept connection first
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+
+        // Create room
+        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
+        let actions = server.create_room(room_id, 1).unwrap();
+
+        assert!(server.has_room(room_id));
+        assert!(matches!(&actions[0], ServerAction::Log(log) if log.message == "room created"));
+
+        // Creator should be subscribed
+        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
+        assert_eq!(sessions, vec![1]);
+    }
+
+    #[test]
+    fn server_create_room_fails_for_unknown_session() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
+        let result = server.create_room(room_id, 999);
+
+        assert!(matches!(result, Err(ServerError::SessionNotFound(999))));
+    }
+
+    #[test]
+    fn server_subscribe_and_unsubscribe() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
+
+        // Accept connections
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+
+        // Create room (subscribes conn 1)
+        server.create_room(room_id, 1).unwrap();
+
+        // Subscribe conn 2
+        assert!(server.subscribe_to_room(2, room_id));
+
+        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
+        assert_eq!(sessions.len(), 2);
+
+        // Unsubscribe conn 2
+        assert!(server.unsubscribe_from_room(2, room_id));
+
+        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[test]
+    fn welcome_frame_subscribes_receiver_to_room() {
+        use bytes::Bytes;
+        use lockframe_proto::FrameHeader;
+
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
+        let user_id_1 = 1001; // Conn 1's user ID (room creator)
+        let user_id_2 = 2002; // Conn 2's user ID (Welcome recipient)
+
+        // Accept two connections
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+
+        // Complete Hello handshake for both to set their user_ids
+        // Conn 1 handshake
+        server.registry.update_session_info(1, SessionInfo::authenticated(user_id_1));
+        // Conn 2 handshake
+        server.registry.update_session_info(2, SessionInfo::authenticated(user_id_2));
+
+        // Conn 1 creates the room
+        server.create_room(room_id, 1).unwrap();
+
+        // Verify only conn 1 is subscribed
+        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
+        assert_eq!(sessions, vec![1]);
+
+        // Conn 1 sends a Welcome frame to add conn 2
+        // The Welcome frame has recipient_id = user_id_2
+        //
+        // Note: The Welcome payload doesn't need to be valid MLS for this test
+        // because we're testing the subscription logic, not MLS processing.
+        // The room_manager.process_frame will fail, but subscription happens first.
+        let mut header = FrameHeader::new(Opcode::Welcome);
+        header.set_room_id(room_id);
+        header.set_recipient_id(user_id_2);
+        header.set_sender_id(user_id_1);
+        let welcome_frame = Frame::new(header, Bytes::from("fake welcome"));
+
+        // Process the Welcome from conn 1 - it will fail MLS validation but
+        // subscription should happen first
+        let _ = server
+            .process_event(ServerEvent::FrameReceived { session_id: 1, frame: welcome_frame });
+
+        // Verify conn 2 is now subscribed (even if MLS processing failed)
+        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.contains(&1));
+        assert!(sessions.contains(&2));
+    }
+
+    #[test]
+    fn invites_are_relayed_from_members_only() {
+        use lockframe_proto::payloads::session::Invite;
+
+        let env = MockEnv::with_crypto_rng();
+        let expires_at = env.wall_clock_secs() + 60;
+        let mut server = ServerDriver::new(env, MemoryStorage::new(), ServerConfig::default());
+        let room_id = 0x1234;
+        for (session_id, user_id) in [(1, 1001), (2, 2002), (3, 3003)] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.registry.update_session_info(session_id, SessionInfo::authenticated(user_id));
+        }
+        server.create_room(room_id, 1).unwrap();
+
+        let invite_from = |inviter, invitee| {
+            let invite = Invite {
+                room_id,
+                inviter,
+                invitee,
+                expires_at,
+                signer_key: vec![0; 32],
+                signature: vec![0; 64],
+            };
+            let mut header = FrameHeader::new(Opcode::Invite);
+            header.set_room_id(room_id);
+            Payload::Invite(invite).into_frame(header).unwrap()
+        };
+        let sent_to = |actions: &[ServerAction<_>]| -> Vec<(u64, Option<Opcode>)> {
+            actions
+                .iter()
+                .filter_map(|action| match action {
+                    ServerAction::SendToSession { session_id, frame } => {
+                        Some((*session_id, frame.header.opcode_enum()))
+                    },
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let actions = server
+            .process_event(ServerEvent::FrameReceived {
+                session_id: 1,
+                frame: invite_from(1001, 2002),
+            })
+            .unwrap();
+        assert_eq!(sent_to(&actions), vec![(2, Some(Opcode::Invite))]);
+        // Relaying an invite doesn't make the invitee a member
+        assert!(!server.room_manager().acl(room_id).unwrap().is_member(2002));
+
+        // Session 3 isn't a member, nor can it invite in someone else's name
+        for frame in [invite_from(3003, 2002), invite_from(1001, 2002)] {
+            let actions =
+                server.process_event(ServerEvent::FrameReceived { session_id: 3, frame }).unwrap();
+            assert_eq!(sent_to(&actions), vec![(3, Some(Opcode::Error))]);
+        }
+    }
+
+    #[test]
+    fn server_driver_recovery() {
+        use crate::storage::StoredRoomMetadata;
+
+        let storage = MemoryStorage::new();
+
+        // Pre-populate storage with rooms (explicit ROOMS table + frames)
+        for room_id in [100u128, 200, 300] {
+            // Create room in ROOMS table
+            let metadata = StoredRoomMetadata::new(room_id as u64, 0);
+            storage.create_room(room_id, &metadata).unwrap();
+
+            // Add frames
+            for i in 0..3 {
+                let mut header = FrameHeader::new(Opcode::AppMessage);
+                header.set_room_id(room_id);
+                header.set_sender_id(room_id as u64);
+                header.set_log_index(i);
+                let frame = Frame::new(header, Bytes::new());
+                storage.store_frame(room_id, i, &frame).unwrap();
+            }
+        }
+
+        // Create driver
+        let env = MockEnv::with_crypto_rng();
+        let mut driver = ServerDriver::new(env, storage, ServerConfig::default());
+
+        // Recover from storage
+        let count = driver.recover_from_storage().unwrap();
+
+        assert_eq!(count, 3);
+        assert!(driver.room_manager().has_room(100));
+        assert!(driver.room_manager().has_room(200));
+        assert!(driver.room_manager().has_room(300));
+    }
+
+    #[test]
+    fn non_member_frames_are_rejected() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let room_id = 0x1234;
+        let (owner, stranger) = (1001, 2002);
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(owner));
+        server.registry.update_session_info(2, SessionInfo::authenticated(stranger));
+        server.create_room(room_id, 1).unwrap();
+
+        let message = |sender_id| {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_sender_id(sender_id);
+            Frame::new(header, Bytes::from("hi"))
+        };
+        let error_code = |actions: &[ServerAction<_>]| {
+            actions.iter().find_map(|action| match action {
+                ServerAction::SendToSession { session_id: 2, frame } => {
+                    match Payload::from_frame(frame) {
+                        Ok(Payload::Error(error)) => Some(error.code),
+                        _ => None,
+                    }
+                },
+                _ => None,
+            })
+        };
+
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 2, frame: message(stranger) })
+            .unwrap();
+        assert_eq!(error_code(&actions), Some(ErrorPayload::NOT_A_MEMBER));
+
+        // Claiming to be the owner doesn't help
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 2, frame: message(owner) })
+            .unwrap();
+        assert_eq!(error_code(&actions), Some(ErrorPayload::PERMISSION_DENIED));
+        assert_eq!(server.storage().latest_log_index(room_id).unwrap(), None);
+
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 1, frame: message(owner) })
+            .unwrap();
+        assert!(actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+    }
+
+    #[test]
+    fn out_of_range_headers_are_rejected_before_routing() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let (room_id, owner) = (0x1234, 1001);
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(owner));
+        server.create_room(room_id, 1).unwrap();
+
+        let message = |corrupt: fn(&mut FrameHeader)| {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_sender_id(owner);
+            corrupt(&mut header);
+            Frame::new(header, Bytes::from("hi"))
+        };
+
+        for corrupt in [
+            (|header: &mut FrameHeader| header.set_epoch(FrameHeader::MAX_EPOCH + 1))
+                as fn(&mut FrameHeader),
+            |header| header.set_flags(FrameFlags::COMPRESSED),
+        ] {
+            let frame = message(corrupt);
+            let result = server.process_event(ServerEvent::FrameReceived { session_id: 1, frame });
+            assert!(matches!(result, Err(ServerError::Protocol(_))), "{result:?}");
+
+            // Batched frames are checked too, and reported without sequencing
+            let frame = message(corrupt);
+            let actions =
+                server.process_event(ServerEvent::FrameBatch { frames: vec![(1, frame)] }).unwrap();
+            assert!(!actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+        }
+        assert_eq!(server.storage().latest_log_index(room_id).unwrap(), None);
+    }
+
+    #[test]
+    fn admin_event_inspects_and_closes_rooms() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let room_id = 0x1234;
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(1001));
+        server.create_room(room_id, 1).unwrap();
+
+        let mut admin = |request| {
+            let actions = server.process_event(ServerEvent::Admin { request })?;
+            Ok::<_, ServerError>(actions.into_iter().find_map(|action| match action {
+                ServerAction::AdminReply(response) => Some(response),
+                _ => None,
+            }))
+        };
+
+        assert_eq!(
+            admin(AdminRequest::ListRooms).unwrap(),
+            Some(AdminResponse::Rooms { room_ids: vec![room_id] })
+        );
+        let Some(AdminResponse::RoomInfo(info)) =
+            admin(AdminRequest::RoomInfo { room_id }).unwrap()
+        else {
+            panic!("expected RoomInfo");
+        };
+        assert_eq!((info.creator, info.members, info.subscribers), (1001, 1, 1));
+
+        assert_eq!(admin(AdminRequest::CloseRoom { room_id }).unwrap(), Some(AdminResponse::Done));
+        assert!(matches!(
+            admin(AdminRequest::RoomInfo { room_id }),
+            Err(ServerError::Room(RoomError::RoomNotFound(_)))
+        ));
+        assert!(!server.has_room(room_id));
+        assert_eq!(server.sessions_in_room(room_id).count(), 0);
+    }
+
+    #[test]
+    fn notices_reach_every_session_or_one_room() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+
+        let room_id = 0x1234;
+        for session_id in [1, 2, 3] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.registry.update_session_info(session_id, SessionInfo::authenticated(session_id));
+        }
+        server.create_room(room_id, 1).unwrap();
+
+        let mut notify = |room_id| {
+            let notice = ServerNotice {
+                kind: NoticeKind::Maintenance,
+                text: "Restarting at 02:00 UTC".to_string(),
+                room_id,
+            };
+            let actions = server
+                .process_event(ServerEvent::Admin { request: AdminRequest::SendNotice(notice) });
+            let mut actions = actions.unwrap().into_iter();
+            let Some(ServerAction::Broadcast { mut session_ids, frame }) =
+                actions.find(|action| matches!(action, ServerAction::Broadcast { .. }))
+            else {
+                panic!("expected a Broadcast");
+            };
+            assert!(matches!(Payload::from_frame(&frame), Ok(Payload::ServerNotice(_))));
+            session_ids.sort_unstable();
+            session_ids
+        };
+
+        assert_eq!(notify(None), vec![1, 2, 3]);
+        assert_eq!(notify(Some(room_id)), vec![1]);
+
+        // Members cannot forge one
+        let notice = ServerNotice {
+            kind: NoticeKind::Policy,
+            text: "Send me your keys".into(),
+            room_id: None,
+        };
+        let mut header = FrameHeader::new(Opcode::ServerNotice);
+        header.set_room_id(room_id);
+        let frame = Payload::ServerNotice(notice).into_frame(header).unwrap();
+        let actions =
+            server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap();
+        assert!(!actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+        assert!(actions.iter().any(|action| matches!(
+            action,
+            ServerAction::SendToSession { session_id: 1, frame }
+                if frame.header.opcode_enum() == Some(Opcode::Error)
+        )));
+        assert_eq!(server.storage().latest_log_index(room_id).unwrap(), None);
+    }
+
+    #[test]
+    fn error_replies_carry_the_logged_error_id() {
+        let mut server = ServerDriver::new(
+            MockEnv::with_crypto_rng(),
+            MemoryStorage::new(),
+            ServerConfig::default(),
+        );
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(1));
+
+        let notice = ServerNotice { kind: NoticeKind::Policy, text: "hi".into(), room_id: None };
+        let frame = Payload::ServerNotice(notice)
+            .into_frame(FrameHeader::new(Opcode::ServerNotice))
+            .unwrap();
+        let actions =
+            server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap();
+
+        let sent = actions.iter().find_map(|action| match action {
+            ServerAction::SendToSession { frame, .. } => match Payload::from_frame(frame) {
+                Ok(Payload::Error(error)) => error.error_id,
+                _ => None,
+            },
+            _ => None,
+        });
+        let logged = actions.iter().find_map(|action| match action {
+            ServerAction::Log(log) => log.get("error_id").map(str::to_owned),
+            _ => None,
+        });
+        assert_eq!(sent.map(|id| id.to_string()), logged);
+        assert!(sent.is_some());
+    }
+
+    #[test]
+    fn admin_frames_require_token() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let config = ServerConfig {
+            admin_token: Some(AdminToken::new(b"hunter2".to_vec())),
+            ..Default::default()
+        };
+        let mut server = ServerDriver::new(env, storage, config);
+
+        for (session_id, auth_token) in
+            [(1, Some(b"hunter2".to_vec())), (2, Some(b"guess".to_vec()))]
+        {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            let hello = Payload::Hello(lockframe_proto::payloads::session::Hello {
+                version: 1,
+                capabilities: vec![],
+                sender_id: Some(1000 + session_id),
+                auth_token,
+            });
+            let frame = hello.into_frame(FrameHeader::new(Opcode::Hello)).unwrap();
+            server.process_event(ServerEvent::FrameReceived { session_id, frame }).unwrap();
+        }
+
+        let mut request = |session_id| {
+            let frame = Payload::AdminRequest(AdminRequest::Stats)
+                .into_frame(FrameHeader::new(Opcode::AdminRequest))
+                .unwrap();
+            let actions =
+                server.process_event(ServerEvent::FrameReceived { session_id, frame }).unwrap();
+            actions.into_iter().find_map(|action| match action {
+                ServerAction::SendToSession { frame, .. } => Payload::from_frame(&frame).ok(),
+                _ => None,
+            })
+        };
+
+        let Some(Payload::AdminResponse(AdminResponse::Stats(stats))) = request(1) else {
+            panic!("expected Stats response");
+        };
+        assert_eq!((stats.connections, stats.authenticated_sessions), (2, 2));
+
+        let Some(Payload::Error(error)) = request(2) else {
+            panic!("expected Error response");
+        };
+        assert_eq!(error.code, ErrorPayload::PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn frame_batch_sequences_rooms_across_shards() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let config = ServerConfig { room_shards: 4, ..Default::default() };
+        let mut server = ServerDriver::new(env, storage, config);
+
+        let rooms: Vec<u128> = (1..=4).collect();
+        for (session_id, &room_id) in (1..).zip(&rooms) {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.registry.update_session_info(session_id, SessionInfo::authenticated(session_id));
+            server.create_room(room_id, session_id).unwrap();
+        }
+
+        let frames = (1..).zip(&rooms).flat_map(|(session_id, &room_id)| {
+            (0..3).map(move |_| {
+                let mut header = FrameHeader::new(Opcode::AppMessage);
+                header.set_room_id(room_id);
+                header.set_sender_id(session_id);
+                (session_id, Frame::new(header, Bytes::from("hi")))
+            })
+        });
+        let actions =
+            server.process_event(ServerEvent::FrameBatch { frames: frames.collect() }).unwrap();
+
+        let broadcasts =
+            actions.iter().filter(|action| matches!(action, ServerAction::Broadcast { .. }));
+        assert_eq!(broadcasts.count(), 12);
+        for room_id in rooms {
+            assert_eq!(server.storage().latest_log_index(room_id).unwrap(), Some(2));
+        }
+    }
+
+    #[test]
+    fn authenticator_binds_sessions_to_token_principal() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let tokens = crate::StaticTokens::new().with_token(b"alice".to_vec(), 1001);
+        let config = ServerConfig { authenticator: Some(Arc::new(tokens)), ..Default::default() };
+        let mut server = ServerDriver::new(env, storage, config);
+
+        let hello = |sender_id, token: &[u8]| {
+            Payload::Hello(lockframe_proto::payloads::session::Hello {
+                version: 1,
+                capabilities: vec![],
+                sender_id,
+                auth_token: Some(token.to_vec()),
+            })
+            .into_frame(FrameHeader::new(Opcode::Hello))
+            .unwrap()
+        };
+        let refusal = |actions: &[ServerAction<_>]| {
+            let code = actions.iter().find_map(|action| match action {
+                ServerAction::SendToSession { frame, .. } => match Payload::from_frame(frame) {
+                    Ok(Payload::Error(error)) => Some(error.code),
+                    _ => None,
+                },
+                _ => None,
+            });
+            let closed =
+                actions.iter().any(|action| matches!(action, ServerAction::CloseConnection { .. }));
+            (code, closed)
+        };
+
+        for session_id in 1..=4 {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+        }
+
+        // Nothing beyond the session layer before a Hello is accepted
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(0x1234);
+        header.set_sender_id(1);
+        let frame = Frame::new(header, Bytes::from("hi"));
+        let actions =
+            server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap();
+        assert_eq!(refusal(&actions), (Some(ErrorPayload::UNAUTHENTICATED), false));
+
+        // Unknown tokens and impersonation close the connection
+        for (session_id, frame) in [(2, hello(None, b"mallory")), (3, hello(Some(2002), b"alice"))]
+        {
+            let actions =
+                server.process_event(ServerEvent::FrameReceived { session_id, frame }).unwrap();
+            assert_eq!(refusal(&actions), (Some(ErrorPayload::UNAUTHENTICATED), true));
+        }
+
+        // The session acts as the token's user, whatever it did not claim
+        let frame = hello(None, b"alice");
+        server.process_event(ServerEvent::FrameReceived { session_id: 4, frame }).unwrap();
+        assert_eq!(server.registry.sessions(4).and_then(|info| info.user_id), Some(1001));
+    }
+
+    #[test]
+    fn federation_relays_frames_through_home_server() {
+        let room_id = 0x1234;
+        let (home_user, remote_user) = (1001, 2002);
+        let peer_link = 9;
+        let federated = |server_id, peer_id, token: &[u8], remote_rooms| ServerConfig {
+            federation: FederationConfig {
+                server_id,
+                peers: vec![crate::FederationPeer { server_id: peer_id, token: token.to_vec() }],
+                remote_rooms,
+            },
+            ..Default::default()
+        };
+        let connect_peer = |server: &mut ServerDriver<MockEnv, MemoryStorage>, token: &[u8]| {
+            server
+                .process_event(ServerEvent::ConnectionAccepted {
+                    session_id: peer_link,
+                    peer_identity: None,
+                })
+                .unwrap();
+            let hello = Payload::Hello(lockframe_proto::payloads::session::Hello {
+                version: 1,
+                capabilities: vec![],
+                sender_id: None,
+                auth_token: Some(token.to_vec()),
+            });
+            let frame = hello.into_frame(FrameHeader::new(Opcode::Hello)).unwrap();
+            server
+                .process_event(ServerEvent::FrameReceived { session_id: peer_link, frame })
+                .unwrap();
+        };
+        let sent = |actions: Vec<ServerAction<_>>, to: u64| {
+            actions.into_iter().find_map(|action| match action {
+                ServerAction::SendToSession { session_id, frame } if session_id == to => {
+                    Some(frame)
+                },
+                ServerAction::Broadcast { session_ids, frame } if session_ids == [to] => {
+                    Some(frame)
+                },
+                _ => None,
+            })
+        };
+
+        let mut home = ServerDriver::new(
+            MockEnv::with_crypto_rng(),
+            MemoryStorage::new(),
+            federated(1, 2, b"from-remote", HashMap::new()),
+        );
+        let mut remote = ServerDriver::new(
+            MockEnv::with_crypto_rng(),
+            MemoryStorage::new(),
+            federated(2, 1, b"from-home", HashMap::from([(room_id, 1)])),
+        );
+        connect_peer(&mut home, b"from-remote");
+        connect_peer(&mut remote, b"from-home");
+
+        home.process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        home.registry.update_session_info(1, SessionInfo::authenticated(home_user));
+        home.create_room(room_id, 1).unwrap();
+        let mut header = FrameHeader::new(Opcode::Welcome);
+        header.set_room_id(room_id);
+        header.set_sender_id(home_user);
+        header.set_recipient_id(remote_user);
+        let welcome = Frame::new(header, Bytes::from("welcome"));
+        home.process_event(ServerEvent::FrameReceived { session_id: 1, frame: welcome }).unwrap();
+
+        remote
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 5, peer_identity: None })
+            .unwrap();
+        remote.registry.update_session_info(5, SessionInfo::authenticated(remote_user));
+
+        // The remote user's message is forwarded to the home rather than
+        // sequenced locally
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(remote_user);
+        let message = Frame::new(header, Bytes::from("hi"));
+        let actions = remote
+            .process_event(ServerEvent::FrameReceived { session_id: 5, frame: message })
+            .unwrap();
+        let forwarded = sent(actions, peer_link).unwrap();
+        assert_eq!(forwarded.header.opcode_enum(), Some(Opcode::FedAppend));
+        assert!(!remote.has_room(room_id));
+
+        // The home sequences it, delivers it locally and relays it back
+        let actions = home
+            .process_event(ServerEvent::FrameReceived { session_id: peer_link, frame: forwarded })
+            .unwrap();
+        assert_eq!(sent(actions.clone(), 1).unwrap().header.log_index(), 0);
+        let relay = sent(actions, peer_link).unwrap();
+        assert_eq!(home.storage().latest_log_index(room_id).unwrap(), Some(0));
+
+        // A relay that already passed through the home is dropped there
+        let actions = home
+            .process_event(ServerEvent::FrameReceived {
+                session_id: peer_link,
+                frame: relay.clone(),
+            })
+            .unwrap();
+        assert!(sent(actions, 1).is_none());
+        assert_eq!(home.storage().latest_log_index(room_id).unwrap(), Some(0));
+
+        // The remote delivers the sequenced frame to its own subscribers
+        let actions = remote
+            .process_event(ServerEvent::FrameReceived { session_id: peer_link, frame: relay })
+            .unwrap();
+        let delivered = sent(actions, 5).unwrap();
+        assert_eq!(delivered.header.log_index(), 0);
+        assert_eq!(&delivered.payload[..], b"hi");
+    }
+
+    #[test]
+    fn presence_is_shared_with_room_members() {
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let config = ServerConfig {
+            presence: PresenceConfig { visibility: PresenceVisibility::RoomMembers },
+            ..Default::default()
+        };
+        let mut server = ServerDriver::new(env, storage, config);
+
+        let room_id = 0x1234;
+        let (owner, guest) = (1001, 2002);
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(owner));
+        server.create_room(room_id, 1).unwrap();
+        server.set_room_presence(room_id, true);
+
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+        let hello = Payload::Hello(lockframe_proto::payloads::session::Hello {
+            version: 1,
+            capabilities: vec![],
+            sender_id: Some(guest),
+            auth_token: None,
+        });
+        let frame = hello.into_frame(FrameHeader::new(Opcode::Hello)).unwrap();
+        server.process_event(ServerEvent::FrameReceived { session_id: 2, frame }).unwrap();
+
+        let mut header = FrameHeader::new(Opcode::Welcome);
+        header.set_room_id(room_id);
+        header.set_recipient_id(guest);
+        let welcome = Frame::new(header, Bytes::from("welcome"));
+        server.process_event(ServerEvent::FrameReceived { session_id: 1, frame: welcome }).unwrap();
+
+        let query = |server: &mut ServerDriver<MockEnv, MemoryStorage>| {
+            let frame = Payload::PresenceQuery(PresenceQuery { user_ids: vec![guest, 3003] })
+                .into_frame(FrameHeader::new(Opcode::PresenceQuery))
+                .unwrap();
+            let actions =
+                server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap();
+            actions.into_iter().find_map(|action| match action {
+                ServerAction::SendToSession { frame, .. } => Payload::from_frame(&frame).ok(),
+                _ => None,
+            })
+        };
+
+        // Strangers are left out of the reply
+        let Some(Payload::PresenceReply(reply)) = query(&mut server) else {
+            panic!("expected PresenceReply");
+        };
+        assert_eq!(reply.users, vec![PresenceStatus {
+            user_id: guest,
+            online: true,
+            last_seen_secs: None
+        }]);
+
+        let actions = server
+            .process_event(ServerEvent::ConnectionClosed { session_id: 2, reason: "bye".into() })
+            .unwrap();
+        let offline = actions.iter().find_map(|action| match action {
+            ServerAction::Broadcast { session_ids, frame } if session_ids == &[1] => {
+                Payload::from_frame(frame).ok()
+            },
+            _ => None,
+        });
+        let Some(Payload::Presence(status)) = offline else {
+            panic!("expected Presence broadcast");
+        };
+        assert!(!status.online && status.last_seen_secs.is_some());
 
-    use super::*;
-    use crate::storage::MemoryStorage;
+        let Some(Payload::PresenceReply(reply)) = query(&mut server) else {
+            panic!("expected PresenceReply");
+        };
+        assert_eq!(reply.users, vec![status]);
+    }
 
     #[test]
-    fn server_accepts_connection() {
+    fn shutdown_drains_sessions_then_completes() {
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
-        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+        let mut server = ServerDriver::new(env.clone(), storage, ServerConfig::default());
+        let drained = |actions: &[ServerAction<_>]| {
+            actions.iter().any(|action| matches!(action, ServerAction::DrainComplete))
+        };
 
-        let actions =
-            server.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
+        for session_id in [1, 2] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+        }
 
-        assert_eq!(server.connection_count(), 1);
-        assert!(matches!(actions[0], ServerAction::Log { level: LogLevel::Debug, .. }));
+        let actions = server.process_event(ServerEvent::BeginShutdown).unwrap();
+        let goodbye = actions.iter().find_map(|action| match action {
+            ServerAction::Broadcast { session_ids, frame } if session_ids.len() == 2 => {
+                Payload::from_frame(frame).ok()
+            },
+            _ => None,
+        });
+        let Some(Payload::Goodbye(goodbye)) = goodbye else {
+            panic!("expected Goodbye broadcast");
+        };
+        assert_eq!(goodbye.code, Some(Goodbye::SERVER_SHUTDOWN));
+        assert_eq!(goodbye.retry_after, Some(5));
+        assert!(!drained(&actions));
+
+        // New connections are refused while draining
+        let actions = server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 3, peer_identity: None })
+            .unwrap();
+        assert!(matches!(actions[0], ServerAction::CloseConnection { session_id: 3, .. }));
+
+        let actions = server
+            .process_event(ServerEvent::ConnectionClosed { session_id: 1, reason: "ack".into() })
+            .unwrap();
+        assert!(!drained(&actions));
+
+        // Stragglers are closed once the drain timeout passes
+        env.advance_time(Duration::from_secs(30));
+        let actions = server.process_event(ServerEvent::Tick).unwrap();
+        assert!(
+            actions.iter().any(|action| matches!(action, ServerAction::CloseConnection {
+                session_id: 2,
+                ..
+            }))
+        );
+        assert!(drained(&actions));
+
+        let actions = server.process_event(ServerEvent::Tick).unwrap();
+        assert!(!drained(&actions));
     }
 
     #[test]
-    fn server_rejects_when_max_connections_exceeded() {
+    fn config_updates_apply_to_existing_sessions() {
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
-        let config = ServerConfig { max_connections: 2, ..Default::default() };
-        let mut server = ServerDriver::new(env, storage, config);
-
-        // Accept two connections
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 2 }).unwrap();
+        let mut server = ServerDriver::new(env.clone(), storage, ServerConfig::default());
+        let logged = |actions: &[ServerAction<_>], message: &str| {
+            actions
+                .iter()
+                .any(|action| matches!(action, ServerAction::Log(log) if log.message == message))
+        };
 
-        // Third should be rejected
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        let hello = Payload::Hello(lockframe_proto::payloads::session::Hello {
+            version: 1,
+            capabilities: vec![],
+            sender_id: Some(1),
+            auth_token: None,
+        })
+        .into_frame(FrameHeader::new(Opcode::Hello))
+        .unwrap();
+        server.process_event(ServerEvent::FrameReceived { session_id: 1, frame: hello }).unwrap();
+
+        // Invalid configurations are ignored
+        let resharded = ServerConfig { room_shards: 8, ..Default::default() };
         let actions =
-            server.process_event(ServerEvent::ConnectionAccepted { session_id: 3 }).unwrap();
+            server.process_event(ServerEvent::ConfigUpdated(Box::new(resharded))).unwrap();
+        assert!(logged(&actions, "config update rejected"));
 
-        assert_eq!(server.connection_count(), 2);
-        assert!(matches!(actions[0], ServerAction::CloseConnection { .. }));
+        let connection = ConnectionConfig {
+            idle_timeout: Duration::from_secs(10),
+            heartbeat_interval: Duration::from_secs(2),
+            ..Default::default()
+        };
+        let config = ServerConfig { connection, max_connections: 1, ..Default::default() };
+        let actions = server.process_event(ServerEvent::ConfigUpdated(Box::new(config))).unwrap();
+        assert!(logged(&actions, "config updated"));
+
+        // The new limit applies to new connections only
+        let actions = server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+        assert!(matches!(actions[0], ServerAction::CloseConnection { session_id: 2, .. }));
+
+        // The shorter idle timeout applies to the session already open
+        env.advance_time(Duration::from_secs(11));
+        let actions = server.process_event(ServerEvent::Tick).unwrap();
+        assert!(
+            actions.iter().any(|action| matches!(action, ServerAction::CloseConnection {
+                session_id: 1,
+                ..
+            }))
+        );
     }
 
     #[test]
-    fn server_handles_connection_closed() {
+    fn retried_frames_are_sequenced_once() {
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
         let mut server = ServerDriver::new(env, storage, ServerConfig::default());
 
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
-        assert_eq!(server.connection_count(), 1);
-
+        let room_id = 0x1234;
         server
-            .process_event(ServerEvent::ConnectionClosed {
-                session_id: 1,
-                reason: "client disconnect".to_string(),
-            })
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
             .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(1001));
+        server.create_room(room_id, 1).unwrap();
 
-        assert_eq!(server.connection_count(), 0);
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(1001);
+        header.set_idempotency_key(42);
+        let frame = Frame::new(header, Bytes::from("hi"));
+        let send = |server: &mut ServerDriver<_, _>| {
+            server
+                .process_event(ServerEvent::FrameReceived { session_id: 1, frame: frame.clone() })
+                .unwrap()
+        };
+
+        let actions = send(&mut server);
+        assert!(actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+
+        // The retry, even after sequencer state is reloaded, only goes back
+        // to the sender
+        server.clear_room_sequencer(room_id);
+        let actions = send(&mut server);
+        assert!(!actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+        let original = actions.iter().find_map(|action| match action {
+            ServerAction::SendToSession { session_id: 1, frame } => Some(frame),
+            _ => None,
+        });
+        assert_eq!(original.map(|frame| frame.header.log_index()), Some(0));
+        assert_eq!(server.storage().latest_log_index(room_id).unwrap(), Some(0));
     }
 
     #[test]
-    fn server_creates_room() {
+    fn pipelined_frames_are_broadcast_after_the_runtime_stores_them() {
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
-        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
-
-        // Accept connection first
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
+        let mut server = ServerDriver::new(env, storage.clone(), ServerConfig::default());
+        server.set_pipelined_persistence(true);
 
-        // Create room
-        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
-        let actions = server.create_room(room_id, 1).unwrap();
+        let room_id = 0x1234;
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(1001));
+        server.create_room(room_id, 1).unwrap();
 
-        assert!(server.has_room(room_id));
-        assert!(matches!(actions[0], ServerAction::Log { level: LogLevel::Info, .. }));
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(1001);
+        let frame = Frame::new(header, Bytes::from("hi"));
+        let send = |server: &mut ServerDriver<_, _>| {
+            let event = ServerEvent::FrameReceived { session_id: 1, frame: frame.clone() };
+            let actions = server.process_event(event).unwrap();
+            assert!(!actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+            actions
+                .into_iter()
+                .find_map(|action| match action {
+                    ServerAction::PersistFrame { log_index, then, .. } => Some((log_index, then)),
+                    _ => None,
+                })
+                .unwrap()
+        };
 
-        // Creator should be subscribed
-        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
-        assert_eq!(sessions, vec![1]);
+        // The broadcast waits inside the write, which is left to the runtime
+        let (log_index, then) = send(&mut server);
+        assert_eq!(log_index, 0);
+        assert!(
+            matches!(then.as_slice(), [ServerAction::Broadcast { session_ids, .. }] if session_ids == &[1])
+        );
+        assert_eq!(storage.latest_log_index(room_id).unwrap(), None);
+        assert_eq!(send(&mut server).0, 1);
+
+        // Neither write landed, so the room starts over from storage
+        let error = StorageError::Conflict { expected: 0, got: 1 };
+        let actions = server
+            .process_event(ServerEvent::PersistFailed {
+                room_id,
+                log_index: 0,
+                error,
+                dropped: vec![1],
+            })
+            .unwrap();
+        assert!(
+            matches!(&actions[0], ServerAction::Log(log) if log.message == "failed to persist frame")
+        );
+        assert!(matches!(&actions[1], ServerAction::Log(log) if log.get("count") == Some("1")));
+        assert_eq!(send(&mut server).0, 0);
     }
 
     #[test]
-    fn server_create_room_fails_for_unknown_session() {
+    fn closed_rooms_refuse_frames_and_sync_the_tombstone() {
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
-        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+        let mut server = ServerDriver::new(env.clone(), storage.clone(), ServerConfig::default());
+
+        let room_id = 0x1234;
+        let (owner, member) = (1001, 2002);
+        for (session_id, user_id) in [(1, owner), (2, member)] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.registry.update_session_info(session_id, SessionInfo::authenticated(user_id));
+        }
+        server.create_room(room_id, 1).unwrap();
+        server.room_manager.admit_member(room_id, owner, member, &storage).unwrap();
 
-        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
-        let result = server.create_room(room_id, 999);
+        let close = |sender_id| {
+            let mut header = FrameHeader::new(Opcode::CloseRoom);
+            header.set_room_id(room_id);
+            header.set_sender_id(sender_id);
+            Payload::CloseRoom(CloseRoom { reason: "done".into(), moderator_id: sender_id })
+                .into_frame(header)
+                .unwrap()
+        };
+        let error_code = |actions: &[ServerAction<_>]| {
+            actions.iter().find_map(|action| match action {
+                ServerAction::SendToSession { frame, .. } => match Payload::from_frame(frame) {
+                    Ok(Payload::Error(error)) => Some(error.code),
+                    _ => None,
+                },
+                _ => None,
+            })
+        };
 
-        assert!(matches!(result, Err(ServerError::SessionNotFound(999))));
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 2, frame: close(member) })
+            .unwrap();
+        assert_eq!(error_code(&actions), Some(ErrorPayload::PERMISSION_DENIED));
+
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 1, frame: close(owner) })
+            .unwrap();
+        assert!(actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(member);
+        let message = Frame::new(header, Bytes::from("hi"));
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 2, frame: message })
+            .unwrap();
+        assert_eq!(error_code(&actions), Some(ErrorPayload::ROOM_CLOSED));
+
+        // The room stays closed across restarts, and syncing past the end of
+        // its log returns the tombstone
+        let mut server = ServerDriver::new(env.clone(), storage, ServerConfig::default());
+        server.recover_from_storage().unwrap();
+        let action = server
+            .room_manager()
+            .handle_sync_request(room_id, member, 5, 10, env.now(), server.storage())
+            .unwrap();
+        let RoomAction::SendSyncResponse { frames, has_more, .. } = action else {
+            panic!("expected SendSyncResponse");
+        };
+        let tombstone = Frame::decode(&frames[0]).unwrap();
+        assert_eq!(tombstone.header.opcode_enum(), Some(Opcode::CloseRoom));
+        assert!(!has_more);
     }
 
     #[test]
-    fn server_subscribe_and_unsubscribe() {
+    fn admins_list_rooms_that_anyone_can_find() {
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
-        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
-
-        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
-
-        // Accept connections
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 2 }).unwrap();
-
-        // Create room (subscribes conn 1)
+        let mut server = ServerDriver::new(env.clone(), storage.clone(), ServerConfig::default());
+
+        let room_id = 0x1234;
+        let (owner, member) = (1001, 2002);
+        for (session_id, user_id) in [(1, owner), (2, member)] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.registry.update_session_info(session_id, SessionInfo::authenticated(user_id));
+        }
         server.create_room(room_id, 1).unwrap();
+        server.room_manager.admit_member(room_id, owner, member, &storage).unwrap();
 
-        // Subscribe conn 2
-        assert!(server.subscribe_to_room(2, room_id));
-
-        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
-        assert_eq!(sessions.len(), 2);
+        let publish = |name: &str| {
+            let mut header = FrameHeader::new(Opcode::DirectoryPublish);
+            header.set_room_id(room_id);
+            Payload::DirectoryPublish(DirectoryPublish { name: Some(name.into()) })
+                .into_frame(header)
+                .unwrap()
+        };
+        let search = |server: &mut ServerDriver<_, _>| {
+            let frame = Payload::DirectorySearch(DirectorySearch {
+                query: "LOBBY".into(),
+                after: None,
+                limit: 10,
+            })
+            .into_frame(FrameHeader::new(Opcode::DirectorySearch))
+            .unwrap();
+            let actions =
+                server.process_event(ServerEvent::FrameReceived { session_id: 2, frame }).unwrap();
+            actions.iter().find_map(|action| match action {
+                ServerAction::SendToSession { frame, .. } => match Payload::from_frame(frame) {
+                    Ok(Payload::DirectoryResults(results)) => Some(results),
+                    _ => None,
+                },
+                _ => None,
+            })
+        };
 
-        // Unsubscribe conn 2
-        assert!(server.unsubscribe_from_room(2, room_id));
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 2, frame: publish("lobby") })
+            .unwrap();
+        assert!(actions.iter().any(|action| matches!(
+            action,
+            ServerAction::SendToSession { frame, .. }
+                if frame.header.opcode_enum() == Some(Opcode::Error)
+        )));
+        assert_eq!(search(&mut server).map(|results| results.rooms.len()), Some(0));
 
-        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
-        assert_eq!(sessions.len(), 1);
+        server
+            .process_event(ServerEvent::FrameReceived { session_id: 1, frame: publish(" Lobby ") })
+            .unwrap();
+        let results = search(&mut server).unwrap();
+        assert_eq!(results.rooms, vec![DirectoryEntry {
+            room_id,
+            name: "Lobby".into(),
+            member_count: 2
+        }]);
+
+        // Listings survive a restart
+        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+        server.recover_from_storage().unwrap();
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(2, SessionInfo::authenticated(member));
+        assert_eq!(search(&mut server).map(|results| results.rooms.len()), Some(1));
     }
 
     #[test]
-    fn welcome_frame_subscribes_receiver_to_room() {
-        use bytes::Bytes;
-        use lockframe_proto::FrameHeader;
+    fn reports_and_moderation_are_audited_without_content() {
+        use lockframe_proto::payloads::{
+            admin::{AuditEntry, AuditEvent},
+            moderation::ReportMessage,
+        };
 
         let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
-        let mut server = ServerDriver::new(env, storage, ServerConfig::default());
+        let mut server = ServerDriver::new(env, storage.clone(), ServerConfig::default());
+
+        let room_id = 0x1234;
+        let (owner, member, stranger) = (1001, 2002, 3003);
+        for (session_id, user_id) in [(1, owner), (2, member), (3, stranger)] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+            server.registry.update_session_info(session_id, SessionInfo::authenticated(user_id));
+        }
+        server.create_room(room_id, 1).unwrap();
+        server.room_manager.admit_member(room_id, owner, member, &storage).unwrap();
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        storage.store_frame(room_id, 0, &Frame::new(header, b"ciphertext".to_vec())).unwrap();
 
-        let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
-        let user_id_1 = 1001; // Conn 1's user ID (room creator)
-        let user_id_2 = 2002; // Conn 2's user ID (Welcome recipient)
+        let report = |session_id, message_log_index| {
+            let mut header = FrameHeader::new(Opcode::Report);
+            header.set_room_id(room_id);
+            let frame =
+                Payload::ReportMessage(ReportMessage { message_log_index, reason: "spam".into() })
+                    .into_frame(header)
+                    .unwrap();
+            ServerEvent::FrameReceived { session_id, frame }
+        };
+        let refused = |actions: &[ServerAction<_>]| {
+            actions.iter().any(|action| {
+                matches!(action, ServerAction::SendToSession { frame, .. }
+                    if frame.header.opcode_enum() == Some(Opcode::Error))
+            })
+        };
 
-        // Accept two connections
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
-        server.process_event(ServerEvent::ConnectionAccepted { session_id: 2 }).unwrap();
+        // Only members can report, and only messages that exist
+        assert!(refused(&server.process_event(report(3, 0)).unwrap()));
+        assert!(refused(&server.process_event(report(2, 5)).unwrap()));
+        let actions = server.process_event(report(2, 0)).unwrap();
+        assert!(!refused(&actions));
+        // Reports are never sequenced into the room
+        assert!(!actions.iter().any(|action| matches!(action, ServerAction::Broadcast { .. })));
+        assert_eq!(storage.latest_log_index(room_id).unwrap(), Some(0));
+
+        // Non-admin sessions cannot export the audit log
+        let frame = Payload::AdminRequest(AdminRequest::ExportAudit { after: None, limit: 0 })
+            .into_frame(FrameHeader::new(Opcode::AdminRequest))
+            .unwrap();
+        let actions =
+            server.process_event(ServerEvent::FrameReceived { session_id: 2, frame }).unwrap();
+        assert!(refused(&actions));
 
-        // Complete Hello handshake for both to set their user_ids
-        // Conn 1 handshake
-        server.registry.update_session_info(1, SessionInfo::authenticated(user_id_1));
-        // Conn 2 handshake
-        server.registry.update_session_info(2, SessionInfo::authenticated(user_id_2));
+        server
+            .process_event(ServerEvent::Admin { request: AdminRequest::CloseRoom { room_id } })
+            .unwrap();
 
-        // Conn 1 creates the room
-        server.create_room(room_id, 1).unwrap();
+        let actions = server
+            .process_event(ServerEvent::Admin {
+                request: AdminRequest::ExportAudit { after: None, limit: 0 },
+            })
+            .unwrap();
+        let Some(ServerAction::AdminReply(AdminResponse::Audit { entries, next: None })) =
+            actions.into_iter().next()
+        else {
+            panic!("expected audit export");
+        };
+        let events: Vec<AuditEvent> =
+            entries.into_iter().map(|AuditEntry { event, .. }| event).collect();
+        assert_eq!(events, vec![
+            AuditEvent::MessageReported {
+                room_id,
+                message_log_index: 0,
+                reporter_id: member,
+                reason: "spam".into(),
+            },
+            AuditEvent::AdminDenied { session_id: 2 },
+            AuditEvent::RoomClosed { room_id, closed_by: None },
+        ]);
+    }
 
-        // Verify only conn 1 is subscribed
-        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
-        assert_eq!(sessions, vec![1]);
+    #[test]
+    fn interceptors_can_reject_frames_and_inject_actions() {
+        /// Closes sessions that send "spam" and copies every sequenced
+        /// message to session 2.
+        #[derive(Debug)]
+        struct SpamFilter;
+
+        impl<I> FrameInterceptor<I> for SpamFilter {
+            fn before(&self, context: &FrameContext, frame: Frame) -> Intercept<I> {
+                if frame.payload.as_ref() == b"spam" {
+                    let reason = "spam".to_string();
+                    Intercept::Reject(vec![ServerAction::CloseConnection {
+                        session_id: context.session_id,
+                        reason,
+                    }])
+                } else {
+                    Intercept::Continue(frame)
+                }
+            }
 
-        // Conn 1 sends a Welcome frame to add conn 2
-        // The Welcome frame has recipient_id = user_id_2
-        //
-        // Note: The Welcome payload doesn't need to be valid MLS for this test
-        // because we're testing the subscription logic, not MLS processing.
-        // The room_manager.process_frame will fail, but subscription happens first.
-        let mut header = FrameHeader::new(Opcode::Welcome);
-        header.set_room_id(room_id);
-        header.set_recipient_id(user_id_2);
-        header.set_sender_id(user_id_1);
-        let welcome_frame = Frame::new(header, Bytes::from("fake welcome"));
+            fn after(
+                &self,
+                _context: &FrameContext,
+                _frame: &Frame,
+                actions: &[ServerAction<I>],
+            ) -> Vec<ServerAction<I>> {
+                actions
+                    .iter()
+                    .filter_map(|action| match action {
+                        ServerAction::Broadcast { frame, .. } => {
+                            Some(ServerAction::SendToSession {
+                                session_id: 2,
+                                frame: frame.clone(),
+                            })
+                        },
+                        _ => None,
+                    })
+                    .collect()
+            }
+        }
 
-        // Process the Welcome from conn 1 - it will fail MLS validation but
-        // subscription should happen first
-        let _ = server
-            .process_event(ServerEvent::FrameReceived { session_id: 1, frame: welcome_frame });
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env, storage.clone(), ServerConfig::default());
+        server.add_interceptor(Opcode::AppMessage, Arc::new(SpamFilter));
+
+        let (room_id, user_id) = (0x1234, 1001);
+        for session_id in [1, 2] {
+            server
+                .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+                .unwrap();
+        }
+        server.registry.update_session_info(1, SessionInfo::authenticated(user_id));
+        server.create_room(room_id, 1).unwrap();
+        let message = |content: &'static str| {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_sender_id(user_id);
+            (1, Frame::new(header, Bytes::from(content)))
+        };
 
-        // Verify conn 2 is now subscribed (even if MLS processing failed)
-        let sessions: Vec<_> = server.sessions_in_room(room_id).collect();
-        assert_eq!(sessions.len(), 2);
-        assert!(sessions.contains(&1));
-        assert!(sessions.contains(&2));
+        let (session_id, frame) = message("spam");
+        let actions =
+            server.process_event(ServerEvent::FrameReceived { session_id, frame }).unwrap();
+        assert!(matches!(actions.as_slice(), [ServerAction::CloseConnection {
+            session_id: 1,
+            ..
+        }]));
+        assert_eq!(storage.latest_log_index(room_id).unwrap(), None);
+
+        // Batched messages are intercepted one at a time
+        let frames = vec![message("hello"), message("spam")];
+        let actions = server.process_event(ServerEvent::FrameBatch { frames }).unwrap();
+        assert_eq!(storage.latest_log_index(room_id).unwrap(), Some(0));
+        let copied = actions.iter().filter(|action| {
+            matches!(action, ServerAction::SendToSession { session_id: 2, frame }
+                if frame.payload.as_ref() == b"hello")
+        });
+        assert_eq!(copied.count(), 1);
+        assert!(
+            actions.iter().any(|action| matches!(action, ServerAction::CloseConnection {
+                session_id: 1,
+                ..
+            }))
+        );
     }
 
     #[test]
-    fn server_driver_recovery() {
-        use crate::storage::StoredRoomMetadata;
+    fn expired_frames_are_not_synced_and_lose_their_content() {
+        use lockframe_proto::payloads::session::SyncRequest;
 
+        let env = MockEnv::with_crypto_rng();
         let storage = MemoryStorage::new();
+        let mut server = ServerDriver::new(env.clone(), storage.clone(), ServerConfig::default());
 
-        // Pre-populate storage with rooms (explicit ROOMS table + frames)
-        for room_id in [100u128, 200, 300] {
-            // Create room in ROOMS table
-            let metadata = StoredRoomMetadata { creator: room_id as u64, created_at_secs: 0 };
-            storage.create_room(room_id, &metadata).unwrap();
+        let (room_id, user_id) = (0x1234, 1001);
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(user_id));
+        server.create_room(room_id, 1).unwrap();
 
-            // Add frames
-            for i in 0..3 {
-                let mut header = FrameHeader::new(Opcode::AppMessage);
-                header.set_room_id(room_id);
-                header.set_sender_id(room_id as u64);
-                header.set_log_index(i);
-                let frame = Frame::new(header, Bytes::new());
-                storage.store_frame(room_id, i, &frame).unwrap();
-            }
+        let now_secs = env.wall_clock_secs();
+        for expires_at in [Some(now_secs), None, Some(now_secs + 60)] {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_sender_id(user_id);
+            header.set_expires_at(expires_at);
+            let frame = Frame::new(header, Bytes::from("secret"));
+            server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap();
         }
 
-        // Create driver
-        let env = MockEnv::with_crypto_rng();
-        let mut driver = ServerDriver::new(env, storage, ServerConfig::default());
-
-        // Recover from storage
-        let count = driver.recover_from_storage().unwrap();
-
-        assert_eq!(count, 3);
-        assert!(driver.room_manager().has_room(100));
-        assert!(driver.room_manager().has_room(200));
-        assert!(driver.room_manager().has_room(300));
+        let mut header = FrameHeader::new(Opcode::SyncRequest);
+        header.set_room_id(room_id);
+        let request = Payload::SyncRequest(SyncRequest::new(0, 10)).into_frame(header).unwrap();
+        let actions = server
+            .process_event(ServerEvent::FrameReceived { session_id: 1, frame: request })
+            .unwrap();
+        let synced = actions.iter().find_map(|action| match action {
+            ServerAction::SendToSession { frame, .. } => match Payload::from_frame(frame) {
+                Ok(Payload::SyncResponse(response)) => Some(response.frames),
+                _ => None,
+            },
+            _ => None,
+        });
+        let indices: Vec<u64> = synced
+            .unwrap()
+            .iter()
+            .map(|bytes| Frame::decode(bytes).unwrap().header.log_index())
+            .collect();
+        assert_eq!(indices, vec![1, 2]);
+
+        // The next compaction pass strips the expired frame from storage
+        server.process_event(ServerEvent::Tick).unwrap();
+        let stored = storage.load_frames(room_id, 0, 10).unwrap();
+        assert!(stored[0].payload.is_empty());
+        assert_eq!(stored[1].payload, Bytes::from("secret"));
+        assert_eq!(stored[2].payload, Bytes::from("secret"));
     }
 
     #[test]
@@ -1331,7 +4316,7 @@ mod tests {
         let sender_id = 42u64;
 
         // Create room in ROOMS table
-        let metadata = StoredRoomMetadata { creator: sender_id, created_at_secs: 0 };
+        let metadata = StoredRoomMetadata::new(sender_id, 0);
         storage.create_room(room_id, &metadata).unwrap();
 
         // Pre-populate storage with 3 frames
@@ -1350,7 +4335,9 @@ mod tests {
         driver.recover_from_storage().unwrap();
 
         // Accept a connection and process a new frame
-        driver.process_event(ServerEvent::ConnectionAccepted { session_id: 1 }).unwrap();
+        driver
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
         driver.registry.update_session_info(1, SessionInfo::authenticated(sender_id));
         driver.subscribe_to_room(1, room_id);
 
@@ -1370,4 +4357,80 @@ mod tests {
         assert_eq!(stored_frames.len(), 1);
         assert_eq!(stored_frames[0], frame);
     }
+
+    #[test]
+    fn room_events_are_delivered_to_webhooks() {
+        use lockframe_proto::payloads::admin::{WebhookEvent, WebhookTarget};
+
+        let env = MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let config = ServerConfig {
+            webhooks: WebhookConfig { volume_every: 2, ..Default::default() },
+            ..Default::default()
+        };
+        let mut server = ServerDriver::new(env, storage, config);
+        let (room_id, owner, member) = (0x77, 1001, 2002);
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None })
+            .unwrap();
+        server.registry.update_session_info(1, SessionInfo::authenticated(owner));
+
+        let deliveries = |actions: Vec<ServerAction<_>>| -> Vec<serde_json::Value> {
+            actions
+                .into_iter()
+                .filter_map(|action| match action {
+                    ServerAction::DeliverWebhook(delivery) => {
+                        assert!(delivery.signature.starts_with("sha256="));
+                        serde_json::from_slice(&delivery.body).ok()
+                    },
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // Nothing is sent without a target
+        assert!(deliveries(server.create_room(room_id, 1).unwrap()).is_empty());
+
+        let target = |url: &str| WebhookTarget {
+            url: url.into(),
+            secret: b"key".to_vec(),
+            events: vec![WebhookEvent::MembersChanged, WebhookEvent::MessageVolume],
+        };
+        let set = |server: &mut ServerDriver<_, _>, webhook| {
+            server.process_event(ServerEvent::Admin {
+                request: AdminRequest::SetWebhook { room_id, webhook },
+            })
+        };
+        assert!(set(&mut server, Some(target("gopher://hooks.example"))).is_err());
+        set(&mut server, Some(target("https://hooks.example/in"))).unwrap();
+
+        let mut header = FrameHeader::new(Opcode::Welcome);
+        header.set_room_id(room_id);
+        header.set_sender_id(owner);
+        header.set_recipient_id(member);
+        let welcome = Frame::new(header, Bytes::from("welcome"));
+        let events = deliveries(
+            server
+                .process_event(ServerEvent::FrameReceived { session_id: 1, frame: welcome })
+                .unwrap(),
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["event"], "members_changed");
+        assert_eq!(events[0]["members"], 2);
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_sender_id(owner);
+            let frame = Frame::new(header, Bytes::from("ciphertext"));
+            events.extend(deliveries(
+                server.process_event(ServerEvent::FrameReceived { session_id: 1, frame }).unwrap(),
+            ));
+        }
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["event"], "message_volume");
+        assert_eq!(events[0]["messages"], 2);
+        assert!(!events[0].to_string().contains("ciphertext"));
+    }
 }
@@ -0,0 +1,144 @@
+//! Disappearing messages.
+//!
+//! Senders stamp frames with an `expires_at` time in the header, following
+//! their room's policy. Once that time passes the frame is left out of sync
+//! responses, and the next compaction pass strips its payload from storage.
+//! The header stays behind so log indices remain contiguous; sync never
+//! serves it because it is expired.
+//!
+//! Expiring frames are found by scanning each room's log from where the
+//! previous scan stopped, so after a restart every room is read once from
+//! its earliest frame.
+
+use std::collections::{BTreeSet, HashMap};
+
+use bytes::Bytes;
+use lockframe_proto::Frame;
+
+use crate::storage::{Storage, StorageError};
+
+/// Frames read per storage call while scanning.
+const SCAN_BATCH: usize = 1024;
+
+/// Most frames scanned per room in one pass, bounding the time a pass can
+/// take right after a restart.
+const SCAN_LIMIT: usize = 64 * SCAN_BATCH;
+
+/// Pending expiries and scan progress, per room.
+#[derive(Debug, Default)]
+pub(crate) struct Expiry {
+    rooms: HashMap<u128, RoomExpiry>,
+}
+
+#[derive(Debug, Default)]
+struct RoomExpiry {
+    /// Next log index to scan
+    scanned_to: u64,
+    /// `(expires_at, log_index)` of frames still holding content
+    pending: BTreeSet<(u64, u64)>,
+}
+
+impl Expiry {
+    /// Strip every frame in a room that has expired at `now_secs`, after
+    /// scanning frames stored since the last pass. Returns how many frames
+    /// were stripped.
+    pub(crate) fn sweep_room(
+        &mut self,
+        room_id: u128,
+        now_secs: u64,
+        storage: &impl Storage,
+    ) -> Result<u64, StorageError> {
+        let room = self.rooms.entry(room_id).or_default();
+        room.scan(room_id, storage)?;
+
+        let mut stripped = 0;
+        while let Some(&(expires_at, log_index)) = room.pending.first()
+            && expires_at <= now_secs
+        {
+            // Retention may have removed the frame since it was scanned
+            let frame = storage.load_frames(room_id, log_index, 1)?.into_iter().next();
+            if let Some(frame) = frame.filter(|frame| frame.header.log_index() == log_index) {
+                storage.replace_frame(room_id, log_index, &strip(frame))?;
+                stripped += 1;
+            }
+            room.pending.pop_first();
+        }
+        Ok(stripped)
+    }
+}
+
+impl RoomExpiry {
+    fn scan(&mut self, room_id: u128, storage: &impl Storage) -> Result<(), StorageError> {
+        let mut scanned = 0;
+        while scanned < SCAN_LIMIT {
+            let frames = match storage.load_frames(room_id, self.scanned_to, SCAN_BATCH) {
+                Ok(frames) => frames,
+                Err(StorageError::NotFound { .. }) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let Some(last) = frames.last() else {
+                return Ok(());
+            };
+
+            self.scanned_to = last.header.log_index() + 1;
+            scanned += frames.len();
+            self.pending.extend(
+                frames.iter().filter(|frame| !frame.payload.is_empty()).filter_map(|frame| {
+                    Some((frame.header.expires_at()?, frame.header.log_index()))
+                }),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// An expired frame with its content removed.
+fn strip(mut frame: Frame) -> Frame {
+    frame.header.set_payload_size(0);
+    frame.payload = Bytes::new();
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::{FrameHeader, Opcode};
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const ROOM: u128 = 7;
+
+    fn frame(log_index: u64, expires_at: Option<u64>) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(ROOM);
+        header.set_log_index(log_index);
+        header.set_expires_at(expires_at);
+        Frame::new(header, Bytes::from_static(b"secret"))
+    }
+
+    #[test]
+    fn expired_frames_lose_their_content() {
+        let storage = MemoryStorage::new();
+        let mut expiry = Expiry::default();
+        storage.store_frame(ROOM, 0, &frame(0, None)).unwrap();
+        storage.store_frame(ROOM, 1, &frame(1, Some(100))).unwrap();
+        storage.store_frame(ROOM, 2, &frame(2, Some(200))).unwrap();
+
+        assert_eq!(expiry.sweep_room(ROOM, 50, &storage).unwrap(), 0);
+        assert_eq!(expiry.sweep_room(ROOM, 100, &storage).unwrap(), 1);
+
+        // Frames stored after a pass are picked up by the next one
+        storage.store_frame(ROOM, 3, &frame(3, Some(150))).unwrap();
+        assert_eq!(expiry.sweep_room(ROOM, 500, &storage).unwrap(), 2);
+        assert_eq!(expiry.sweep_room(ROOM, 500, &storage).unwrap(), 0);
+
+        let frames = storage.load_frames(ROOM, 0, 10).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].payload.as_ref(), b"secret");
+        assert!(frames[1..].iter().all(|frame| frame.payload.is_empty()));
+        assert_eq!(frames[3].header.expires_at(), Some(150));
+
+        // A restarted server rescans and finds nothing left to strip
+        assert_eq!(Expiry::default().sweep_room(ROOM, 500, &storage).unwrap(), 0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Server-to-server federation (prototype).
+//!
+//! Servers peer over the ordinary frame protocol: a peer connects like a
+//! client and presents its shared token in Hello, after which its session may
+//! carry `FedAppend` and `FedNack` frames. Each room is homed on one server.
+//! The home sequences the room as usual; other servers learn where a room
+//! lives from [`FederationConfig::remote_rooms`] and forward their users'
+//! frames for it to the home instead of sequencing them locally.
+//!
+//! The home records which peers have sent frames for a room and relays every
+//! sequenced frame of that room to them, and each peer broadcasts it to its
+//! own subscribers. Only the home relays onwards, and every relay carries the
+//! servers it has passed through, so a frame is dropped rather than looping
+//! if it ever reaches a server twice.
+//!
+//! A server only writes to peers over connections they opened to it, so each
+//! pair of peers must connect to each other. The QUIC runtime does not dial
+//! peers yet. Remote history catch-up is not covered either, and user IDs
+//! share one namespace across servers.
+
+use std::collections::{BTreeSet, HashMap};
+
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode, Payload,
+    payloads::federation::{FedAppend, FedNack},
+};
+
+use crate::admin::tokens_match;
+
+/// Longest relay path accepted. Home-to-peer relays never exceed two hops.
+const MAX_HOPS: usize = 4;
+
+/// A server allowed to peer with this one.
+#[derive(Clone)]
+pub struct FederationPeer {
+    /// The peer's server ID
+    pub server_id: u64,
+    /// Secret the peer presents as its Hello `auth_token`
+    pub token: Vec<u8>,
+}
+
+impl std::fmt::Debug for FederationPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FederationPeer")
+            .field("server_id", &self.server_id)
+            .field("token", &format_args!("<redacted {} bytes>", self.token.len()))
+            .finish()
+    }
+}
+
+/// Federation settings.
+#[derive(Debug, Clone, Default)]
+pub struct FederationConfig {
+    /// This server's ID, recorded in relay paths
+    pub server_id: u64,
+    /// Servers allowed to peer
+    pub peers: Vec<FederationPeer>,
+    /// Rooms homed on a peer (`room_id` → peer `server_id`)
+    pub remote_rooms: HashMap<u128, u64>,
+}
+
+/// Why a relayed frame was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum RelayError {
+    /// The frame already passed through this server.
+    #[error("relay loop")]
+    Loop,
+    /// The relay path is longer than any legitimate route.
+    #[error("relay path too long")]
+    TooManyHops,
+}
+
+/// Peer links and remote room membership.
+#[derive(Debug, Default)]
+pub(crate) struct Federation {
+    config: FederationConfig,
+    /// Authenticated peer links (`server_id` → `session_id`)
+    peer_sessions: HashMap<u64, u64>,
+    /// Peers with members in rooms homed here
+    remote_members: HashMap<u128, BTreeSet<u64>>,
+}
+
+impl Federation {
+    pub(crate) fn new(config: FederationConfig) -> Self {
+        Self { config, ..Self::default() }
+    }
+
+    /// Replace the peer list. Established peer links stay up.
+    pub(crate) fn set_config(&mut self, config: FederationConfig) {
+        self.config = config;
+    }
+
+    /// Peer whose token matches `presented`, if any.
+    pub(crate) fn authenticate(&self, presented: &[u8]) -> Option<u64> {
+        self.config
+            .peers
+            .iter()
+            .find(|peer| tokens_match(&peer.token, presented))
+            .map(|peer| peer.server_id)
+    }
+
+    pub(crate) fn peer_connected(&mut self, server_id: u64, session_id: u64) {
+        self.peer_sessions.insert(server_id, session_id);
+    }
+
+    /// Forget a closed session, returning the peer it belonged to.
+    pub(crate) fn session_closed(&mut self, session_id: u64) -> Option<u64> {
+        let server_id = self.peer_of(session_id)?;
+        self.peer_sessions.remove(&server_id);
+        Some(server_id)
+    }
+
+    /// Peer a session belongs to, if it is a peer link.
+    pub(crate) fn peer_of(&self, session_id: u64) -> Option<u64> {
+        self.peer_sessions
+            .iter()
+            .find(|&(_, &id)| id == session_id)
+            .map(|(&server_id, _)| server_id)
+    }
+
+    /// Session of a connected peer.
+    pub(crate) fn peer_session(&self, server_id: u64) -> Option<u64> {
+        self.peer_sessions.get(&server_id).copied()
+    }
+
+    /// Peer a room is homed on, or `None` for rooms homed here.
+    pub(crate) fn remote_home(&self, room_id: u128) -> Option<u64> {
+        self.config.remote_rooms.get(&room_id).copied()
+    }
+
+    /// Record that a peer has members in a room homed here.
+    pub(crate) fn add_remote_member(&mut self, room_id: u128, server_id: u64) {
+        self.remote_members.entry(room_id).or_default().insert(server_id);
+    }
+
+    /// Sessions of connected peers with members in a room homed here.
+    pub(crate) fn relay_sessions(&self, room_id: u128) -> Vec<u64> {
+        self.remote_members
+            .get(&room_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|server_id| self.peer_session(*server_id))
+            .collect()
+    }
+
+    /// Reject relays that already passed through this server or took an
+    /// implausibly long path.
+    pub(crate) fn check_path(&self, append: &FedAppend) -> Result<(), RelayError> {
+        if append.hops.contains(&self.config.server_id) {
+            return Err(RelayError::Loop);
+        }
+        if append.hops.len() >= MAX_HOPS {
+            return Err(RelayError::TooManyHops);
+        }
+        Ok(())
+    }
+
+    /// Wrap a frame for relay, extending the path it arrived with.
+    pub(crate) fn wrap(
+        &self,
+        room_id: u128,
+        frame: &Frame,
+        hops: &[u64],
+    ) -> Result<Frame, lockframe_proto::ProtocolError> {
+        let mut bytes = Vec::with_capacity(FrameHeader::SIZE + frame.payload.len());
+        frame.encode(&mut bytes)?;
+
+        let mut hops = hops.to_vec();
+        hops.push(self.config.server_id);
+
+        let mut header = FrameHeader::new(Opcode::FedAppend);
+        header.set_room_id(room_id);
+        Payload::FedAppend(FedAppend { room_id, hops, frame: bytes }).into_frame(header)
+    }
+
+    /// Build a `FedNack` for a refused relay.
+    pub(crate) fn nack(
+        room_id: u128,
+        sender_id: u64,
+        reason: String,
+    ) -> Result<Frame, lockframe_proto::ProtocolError> {
+        let mut header = FrameHeader::new(Opcode::FedNack);
+        header.set_room_id(room_id);
+        Payload::FedNack(FedNack { room_id, sender_id, reason }).into_frame(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn federation(server_id: u64) -> Federation {
+        Federation::new(FederationConfig {
+            server_id,
+            peers: vec![FederationPeer { server_id: 2, token: b"peer-2".to_vec() }],
+            remote_rooms: HashMap::from([(0xaa, 2)]),
+        })
+    }
+
+    #[test]
+    fn peers_authenticate_by_token() {
+        let federation = federation(1);
+        assert_eq!(federation.authenticate(b"peer-2"), Some(2));
+        assert_eq!(federation.authenticate(b"peer-3"), None);
+    }
+
+    #[test]
+    fn relay_paths_cannot_loop() {
+        let federation = federation(1);
+        let append = |hops: Vec<u64>| FedAppend { room_id: 0xaa, hops, frame: Vec::new() };
+
+        assert_eq!(federation.check_path(&append(vec![2])), Ok(()));
+        assert_eq!(federation.check_path(&append(vec![2, 1])), Err(RelayError::Loop));
+        assert_eq!(federation.check_path(&append(vec![5, 6, 7, 8])), Err(RelayError::TooManyHops));
+    }
+}
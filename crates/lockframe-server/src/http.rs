@@ -0,0 +1,170 @@
+//! Webhook delivery over HTTP/1.1.
+//!
+//! Just enough of a client to POST a JSON body and read the status line:
+//! one request per connection, TLS for `https://` URLs verified against the
+//! Mozilla root set. Requests run on the blocking pool so a slow endpoint
+//! never holds up the runtime.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use crate::webhook::{SIGNATURE_HEADER, WebhookDelivery};
+
+/// Limit on connecting, sending and waiting for the response of one attempt.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static TLS_CONFIG: LazyLock<Arc<rustls::ClientConfig>> = LazyLock::new(|| {
+    let roots = webpki_roots::TLS_SERVER_ROOTS.iter().cloned().collect::<rustls::RootCertStore>();
+    Arc::new(rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+});
+
+/// Post `delivery`, retrying on failure until its attempts run out.
+pub(crate) async fn deliver(delivery: WebhookDelivery) {
+    let delivery = Arc::new(delivery);
+    let mut delays = delivery.retry_delays().collect::<Vec<_>>().into_iter();
+
+    loop {
+        let attempt = Arc::clone(&delivery);
+        let result = tokio::task::spawn_blocking(move || post(&attempt))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+
+        match (result, delays.next()) {
+            (Ok(()), _) => return,
+            (Err(e), Some(delay)) => {
+                tracing::debug!("webhook {} failed, retrying in {:?}: {}", delivery.url, delay, e);
+                tokio::time::sleep(delay).await;
+            },
+            (Err(e), None) => {
+                tracing::warn!(
+                    "webhook {} dropped after {} attempts: {}",
+                    delivery.url,
+                    delivery.max_attempts,
+                    e
+                );
+                return;
+            },
+        }
+    }
+}
+
+/// Make one attempt. Any 2xx status is success.
+fn post(delivery: &WebhookDelivery) -> io::Result<()> {
+    let url = Url::parse(&delivery.url)?;
+    let addr = (url.host, url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no address"))?;
+    let tcp = TcpStream::connect_timeout(&addr, ATTEMPT_TIMEOUT)?;
+    tcp.set_read_timeout(Some(ATTEMPT_TIMEOUT))?;
+    tcp.set_write_timeout(Some(ATTEMPT_TIMEOUT))?;
+
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: lockframe-server\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n{}: {}\r\n\
+         Connection: close\r\n\r\n",
+        url.path,
+        url.authority,
+        delivery.body.len(),
+        SIGNATURE_HEADER,
+        delivery.signature,
+    );
+
+    let status = if url.tls {
+        let name = rustls::pki_types::ServerName::try_from(url.host.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let conn = rustls::ClientConnection::new(Arc::clone(&TLS_CONFIG), name)
+            .map_err(io::Error::other)?;
+        exchange(rustls::StreamOwned::new(conn, tcp), head.as_bytes(), &delivery.body)?
+    } else {
+        exchange(tcp, head.as_bytes(), &delivery.body)?
+    };
+
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("endpoint answered {status}")))
+    }
+}
+
+/// Send a request and return the response's status code.
+fn exchange(mut stream: impl Read + Write, head: &[u8], body: &[u8]) -> io::Result<u16> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed status line"))
+}
+
+/// The parts of a webhook URL a request needs.
+#[derive(Debug, PartialEq, Eq)]
+struct Url<'a> {
+    tls: bool,
+    /// `host[:port]` as written, for the Host header
+    authority: &'a str,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    fn parse(url: &'a str) -> io::Result<Self> {
+        let invalid = |reason| io::Error::new(io::ErrorKind::InvalidInput, reason);
+
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(invalid("URL must be http:// or https://"));
+        };
+
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |at| rest.split_at(at));
+        let default_port = if tls { 443 } else { 80 };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| invalid("invalid port"))?)
+            },
+            _ => (authority, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid("URL has no host"));
+        }
+
+        Ok(Self { tls, authority, host, port, path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_split_for_the_request() {
+        let url = Url::parse("https://hooks.example/in?room=1").unwrap();
+        assert_eq!(url, Url {
+            tls: true,
+            authority: "hooks.example",
+            host: "hooks.example",
+            port: 443,
+            path: "/in?room=1"
+        });
+
+        let url = Url::parse("http://[::1]:8080").unwrap();
+        assert_eq!((url.host, url.port, url.path), ("::1", 8080, "/"));
+
+        assert!(Url::parse("ftp://hooks.example").is_err());
+        assert!(Url::parse("http://:80/").is_err());
+    }
+}
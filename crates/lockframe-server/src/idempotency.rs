@@ -0,0 +1,108 @@
+//! Deduplication of retried frames.
+//!
+//! A client that loses its connection before seeing the echo of a send cannot
+//! tell whether the server sequenced it, so it sends the frame again. Frames
+//! flagged `IDEMPOTENT` carry a client-chosen key in `request_id`; together
+//! with the room and the sender it identifies one send, and a repeat is
+//! answered with the frame already in the log instead of being sequenced a
+//! second time.
+//!
+//! Each room remembers the keys of its last [`WINDOW`] keyed frames. Nothing
+//! extra is persisted: the keys are part of the stored frame headers, so the
+//! window is rebuilt from the tail of the room's log whenever the room's
+//! sequencer state is reloaded. Retries across a restart are caught as long
+//! as the original is among the room's last [`WINDOW`] frames.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::storage::{Storage, StorageError};
+
+/// Keyed frames remembered per room, and frames scanned to rebuild them.
+pub(crate) const WINDOW: usize = 256;
+
+/// Recent idempotency keys of one room.
+#[derive(Debug, Default)]
+pub(crate) struct IdempotencyWindow {
+    /// `(sender_id, key)` → log index of the original
+    keys: HashMap<(u64, u32), u64>,
+    /// Keys oldest first, for eviction
+    order: VecDeque<(u64, u32)>,
+}
+
+impl IdempotencyWindow {
+    /// Rebuild a room's window from the tail of its log.
+    pub(crate) fn load(room_id: u128, storage: &impl Storage) -> Result<Self, StorageError> {
+        let mut window = Self::default();
+        let Some(latest) = storage.latest_log_index(room_id)? else {
+            return Ok(window);
+        };
+
+        let from = latest.saturating_add(1).saturating_sub(WINDOW as u64);
+        for frame in storage.load_frames(room_id, from, WINDOW)? {
+            if let Some(key) = frame.header.idempotency_key() {
+                window.record(frame.header.sender_id(), key, frame.header.log_index());
+            }
+        }
+        Ok(window)
+    }
+
+    /// Log index of an earlier frame with the same sender and key.
+    pub(crate) fn lookup(&self, sender_id: u64, key: u32) -> Option<u64> {
+        self.keys.get(&(sender_id, key)).copied()
+    }
+
+    /// Remember a sequenced frame's key, forgetting the oldest if full.
+    pub(crate) fn record(&mut self, sender_id: u64, key: u32, log_index: u64) {
+        if self.keys.insert((sender_id, key), log_index).is_some() {
+            return;
+        }
+        self.order.push_back((sender_id, key));
+        if self.order.len() > WINDOW
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.keys.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_proto::{Frame, FrameHeader, Opcode};
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn window_evicts_oldest_keys() {
+        let mut window = IdempotencyWindow::default();
+        for key in 0..=WINDOW as u32 {
+            window.record(1, key, u64::from(key));
+        }
+
+        assert_eq!(window.lookup(1, 0), None);
+        assert_eq!(window.lookup(1, 1), Some(1));
+        assert_eq!(window.lookup(2, 1), None);
+    }
+
+    #[test]
+    fn window_is_rebuilt_from_storage() {
+        let storage = MemoryStorage::new();
+        let room_id = 0x1234;
+        for log_index in 0..3 {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_sender_id(7);
+            header.set_log_index(log_index);
+            if log_index > 0 {
+                header.set_idempotency_key(100 + log_index as u32);
+            }
+            storage.store_frame(room_id, log_index, &Frame::new(header, Bytes::new())).unwrap();
+        }
+
+        let window = IdempotencyWindow::load(room_id, &storage).unwrap();
+        assert_eq!(window.lookup(7, 102), Some(2));
+        assert_eq!(window.lookup(7, 101), Some(1));
+        assert_eq!(window.lookup(7, 100), None);
+    }
+}
@@ -0,0 +1,98 @@
+//! Embedder hooks around frame processing.
+//!
+//! A [`FrameInterceptor`] registered for an opcode sees every frame with that
+//! opcode from an authenticated session, before and after the driver
+//! processes it. Before, it can pass the frame on unchanged, rewrite it, or
+//! reject it with actions of its own, such as an Error reply. After, it sees
+//! the actions the driver produced and can add more, such as a log event
+//! that feeds a webhook.
+//!
+//! Interceptors registered for the same opcode run in registration order:
+//! each `before` receives the frame the previous one returned, and the first
+//! rejection stops the chain. Session-layer frames (Hello, Ping, Pong,
+//! Goodbye) and frames from sessions that have not authenticated are never
+//! intercepted.
+//!
+//! Application messages with an interceptor registered are processed one at
+//! a time rather than in parallel batches, so the hooks see each frame's own
+//! actions.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use lockframe_proto::{Frame, Opcode};
+
+use crate::ServerAction;
+
+/// The session a frame arrived on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameContext {
+    /// Session the frame arrived on
+    pub session_id: u64,
+    /// User the session authenticated as
+    pub user_id: u64,
+    /// Opcode the interceptor was registered for
+    pub opcode: Opcode,
+}
+
+/// Outcome of [`FrameInterceptor::before`].
+#[derive(Debug)]
+pub enum Intercept<I = std::time::Instant> {
+    /// Process this frame, which may differ from the one received
+    Continue(Frame),
+    /// Drop the frame and execute these actions instead
+    Reject(Vec<ServerAction<I>>),
+}
+
+/// Hooks run around the processing of frames with one opcode.
+///
+/// Both hooks default to doing nothing, so an interceptor implements only
+/// the ones it needs.
+pub trait FrameInterceptor<I = std::time::Instant>: Send + Sync + fmt::Debug {
+    /// Inspect a frame before the driver processes it.
+    fn before(&self, _context: &FrameContext, frame: Frame) -> Intercept<I> {
+        Intercept::Continue(frame)
+    }
+
+    /// Inspect the actions the driver produced for `frame`, returning any to
+    /// execute after them.
+    fn after(
+        &self,
+        _context: &FrameContext,
+        _frame: &Frame,
+        _actions: &[ServerAction<I>],
+    ) -> Vec<ServerAction<I>> {
+        Vec::new()
+    }
+}
+
+/// Interceptors by the opcode they are registered for.
+pub(crate) struct Interceptors<I> {
+    by_opcode: HashMap<Opcode, Vec<Arc<dyn FrameInterceptor<I>>>>,
+}
+
+impl<I> Default for Interceptors<I> {
+    fn default() -> Self {
+        Self { by_opcode: HashMap::new() }
+    }
+}
+
+impl<I> Interceptors<I> {
+    /// Run `interceptor` for every frame with `opcode`, after any already
+    /// registered for it.
+    pub(crate) fn register(&mut self, opcode: Opcode, interceptor: Arc<dyn FrameInterceptor<I>>) {
+        self.by_opcode.entry(opcode).or_default().push(interceptor);
+    }
+
+    /// Interceptors registered for `opcode`, in registration order.
+    pub(crate) fn for_opcode(&self, opcode: Option<Opcode>) -> &[Arc<dyn FrameInterceptor<I>>] {
+        opcode.and_then(|opcode| self.by_opcode.get(&opcode)).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl<I> fmt::Debug for Interceptors<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.by_opcode.iter().map(|(opcode, list)| (opcode, list.len())))
+            .finish()
+    }
+}
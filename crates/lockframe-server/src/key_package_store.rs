@@ -0,0 +1,326 @@
+//! `KeyPackage` store for publishing and claiming MLS `KeyPackages`.
+//!
+//! Each user publishes a pool of `KeyPackages`. A fetch claims exactly one
+//! entry from the owner's pool and removes it under the same lock, so two
+//! concurrent fetches can never be handed the same `KeyPackage`. Entries
+//! expire after [`KeyPackageStoreConfig::ttl_secs`] and are pruned lazily.
+//! When a claim leaves a pool below
+//! [`KeyPackageStoreConfig::low_stock_threshold`] the driver tells the owner to
+//! publish more.
+
+#![allow(clippy::disallowed_types, reason = "Synchronous in-memory operations only")]
+#![allow(clippy::expect_used, reason = "Mutex poisoning should cause a panic")]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// Limits and lifetimes for a [`KeyPackageStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPackageStoreConfig {
+    /// Maximum `KeyPackages` held per user.
+    pub pool_size: usize,
+    /// Maximum users with a pool. The least recently published pool is
+    /// evicted to make room.
+    pub max_users: usize,
+    /// Seconds a `KeyPackage` stays claimable after publication.
+    pub ttl_secs: u64,
+    /// Owners are notified when a claim leaves fewer than this many.
+    pub low_stock_threshold: usize,
+}
+
+impl Default for KeyPackageStoreConfig {
+    fn default() -> Self {
+        Self { pool_size: 32, max_users: 1000, ttl_secs: 30 * 24 * 60 * 60, low_stock_threshold: 4 }
+    }
+}
+
+/// Result type for `KeyPackage` publish operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreResult {
+    /// `KeyPackage` was added to the owner's pool.
+    Success,
+    /// `KeyPackage` was added and another user's pool was evicted.
+    Evicted,
+    /// A `KeyPackage` with the same hash reference is already pooled.
+    Duplicate,
+    /// The owner's pool is full.
+    PoolFull,
+    /// Store is full and no pool could be evicted.
+    Full,
+}
+
+/// Stored `KeyPackage` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPackageEntry {
+    /// Serialized MLS `KeyPackage`.
+    pub key_package_bytes: Vec<u8>,
+    /// `KeyPackage` hash reference.
+    pub hash_ref: Vec<u8>,
+    /// Wall-clock second after which the entry can no longer be claimed.
+    pub expires_at_secs: u64,
+}
+
+impl KeyPackageEntry {
+    /// Create a new `KeyPackageEntry`.
+    pub fn new(key_package_bytes: Vec<u8>, hash_ref: Vec<u8>, expires_at_secs: u64) -> Self {
+        Self { key_package_bytes, hash_ref, expires_at_secs }
+    }
+
+    fn is_expired(&self, now_secs: u64) -> bool {
+        now_secs >= self.expires_at_secs
+    }
+}
+
+/// A `KeyPackage` claimed from a pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claimed {
+    /// The claimed entry, now removed from the store.
+    pub entry: KeyPackageEntry,
+    /// Unexpired entries left in the owner's pool.
+    pub remaining: usize,
+}
+
+/// In-memory per-user `KeyPackage` pools.
+///
+/// Thread-safe via Arc<Mutex<_>>. Clone shares the same underlying storage.
+#[derive(Clone)]
+pub struct KeyPackageStore {
+    inner: Arc<Mutex<KeyPackageStoreInner>>,
+}
+
+/// Internal state for `KeyPackageStore`.
+struct KeyPackageStoreInner {
+    config: KeyPackageStoreConfig,
+    /// Pools indexed by `user_id`, oldest entry first.
+    pools: HashMap<u64, VecDeque<KeyPackageEntry>>,
+    /// LRU tracking - ordered list of `user_ids` (most recent publish at back).
+    lru_order: VecDeque<u64>,
+}
+
+impl KeyPackageStoreInner {
+    /// Drop expired entries from a user's pool, returning what is left.
+    fn prune(&mut self, user_id: u64, now_secs: u64) -> usize {
+        let Some(pool) = self.pools.get_mut(&user_id) else {
+            return 0;
+        };
+        pool.retain(|entry| !entry.is_expired(now_secs));
+        let remaining = pool.len();
+
+        if remaining == 0 {
+            self.pools.remove(&user_id);
+            self.lru_order.retain(|&id| id != user_id);
+        }
+        remaining
+    }
+}
+
+impl Default for KeyPackageStore {
+    fn default() -> Self {
+        Self::new(KeyPackageStoreConfig::default())
+    }
+}
+
+impl KeyPackageStore {
+    /// Create a new empty store.
+    pub fn new(config: KeyPackageStoreConfig) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(KeyPackageStoreInner {
+                config,
+                pools: HashMap::new(),
+                lru_order: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> KeyPackageStoreConfig {
+        self.inner.lock().expect("KeyPackageStore mutex poisoned").config
+    }
+
+    /// Replace the configuration. Pools already over the new limits shrink
+    /// as their owners publish or as pools are evicted.
+    pub fn set_config(&self, config: KeyPackageStoreConfig) {
+        self.inner.lock().expect("KeyPackageStore mutex poisoned").config = config;
+    }
+
+    /// Add a `KeyPackage` to a user's pool.
+    ///
+    /// Expired entries in the pool are pruned first. If the store holds
+    /// `max_users` pools, the least recently published one is evicted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned.
+    pub fn publish(&self, user_id: u64, entry: KeyPackageEntry, now_secs: u64) -> StoreResult {
+        let mut inner = self.inner.lock().expect("KeyPackageStore mutex poisoned");
+
+        let pooled = inner.prune(user_id, now_secs);
+        if let Some(pool) = inner.pools.get(&user_id) {
+            if pool.iter().any(|existing| existing.hash_ref == entry.hash_ref) {
+                return StoreResult::Duplicate;
+            }
+            if pooled >= inner.config.pool_size {
+                return StoreResult::PoolFull;
+            }
+        }
+
+        let is_new_user = pooled == 0;
+        let result = if is_new_user && inner.pools.len() >= inner.config.max_users {
+            match inner.lru_order.pop_front() {
+                Some(oldest_id) => {
+                    inner.pools.remove(&oldest_id);
+                    StoreResult::Evicted
+                },
+                None => return StoreResult::Full,
+            }
+        } else {
+            StoreResult::Success
+        };
+
+        inner.pools.entry(user_id).or_default().push_back(entry);
+        inner.lru_order.retain(|&id| id != user_id);
+        inner.lru_order.push_back(user_id);
+
+        result
+    }
+
+    /// Claim one unexpired `KeyPackage` from a user's pool.
+    ///
+    /// The oldest entry is removed and returned together with the number
+    /// left. Returns `None` if the pool is empty or fully expired.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned.
+    pub fn claim(&self, user_id: u64, now_secs: u64) -> Option<Claimed> {
+        let mut inner = self.inner.lock().expect("KeyPackageStore mutex poisoned");
+
+        if inner.prune(user_id, now_secs) == 0 {
+            return None;
+        }
+        let entry = inner.pools.get_mut(&user_id)?.pop_front()?;
+        let remaining = inner.prune(user_id, now_secs);
+
+        Some(Claimed { entry, remaining })
+    }
+
+    /// Number of unexpired `KeyPackages` in a user's pool.
+    pub fn available(&self, user_id: u64, now_secs: u64) -> usize {
+        let inner = self.inner.lock().expect("KeyPackageStore mutex poisoned");
+        inner
+            .pools
+            .get(&user_id)
+            .map_or(0, |pool| pool.iter().filter(|entry| !entry.is_expired(now_secs)).count())
+    }
+
+    /// Number of stored `KeyPackages` across all pools, including expired
+    /// entries not yet pruned.
+    pub fn count(&self) -> usize {
+        let inner = self.inner.lock().expect("KeyPackageStore mutex poisoned");
+        inner.pools.values().map(VecDeque::len).sum()
+    }
+
+    /// Number of users with a pool.
+    pub fn user_count(&self) -> usize {
+        let inner = self.inner.lock().expect("KeyPackageStore mutex poisoned");
+        inner.pools.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000;
+
+    fn entry(id: u8) -> KeyPackageEntry {
+        KeyPackageEntry::new(vec![id], vec![id, id], NOW + 100)
+    }
+
+    #[test]
+    fn claim_consumes_one_entry() {
+        let store = KeyPackageStore::default();
+        assert_eq!(store.publish(42, entry(1), NOW), StoreResult::Success);
+        assert_eq!(store.publish(42, entry(2), NOW), StoreResult::Success);
+
+        let first = store.claim(42, NOW).expect("should have entry");
+        assert_eq!(first.entry, entry(1));
+        assert_eq!(first.remaining, 1);
+
+        let second = store.claim(42, NOW).expect("should have entry");
+        assert_eq!(second.entry, entry(2));
+        assert_eq!(second.remaining, 0);
+
+        assert!(store.claim(42, NOW).is_none());
+        assert_eq!(store.user_count(), 0);
+    }
+
+    #[test]
+    fn claim_nonexistent_returns_none() {
+        let store = KeyPackageStore::default();
+        assert!(store.claim(999, NOW).is_none());
+    }
+
+    #[test]
+    fn expired_entries_are_not_claimable() {
+        let store = KeyPackageStore::default();
+        store.publish(42, KeyPackageEntry::new(vec![1], vec![1], NOW + 10), NOW);
+        store.publish(42, KeyPackageEntry::new(vec![2], vec![2], NOW + 20), NOW);
+
+        assert_eq!(store.available(42, NOW + 15), 1);
+        let claimed = store.claim(42, NOW + 15).expect("one entry still valid");
+        assert_eq!(claimed.entry.key_package_bytes, vec![2]);
+
+        store.publish(43, KeyPackageEntry::new(vec![3], vec![3], NOW + 10), NOW);
+        assert!(store.claim(43, NOW + 10).is_none());
+        assert_eq!(store.count(), 0);
+    }
+
+    #[test]
+    fn duplicate_hash_ref_rejected() {
+        let store = KeyPackageStore::default();
+        store.publish(42, entry(1), NOW);
+        assert_eq!(store.publish(42, entry(1), NOW), StoreResult::Duplicate);
+        assert_eq!(store.available(42, NOW), 1);
+    }
+
+    #[test]
+    fn pool_size_is_enforced() {
+        let store =
+            KeyPackageStore::new(KeyPackageStoreConfig { pool_size: 2, ..Default::default() });
+        store.publish(42, entry(1), NOW);
+        store.publish(42, entry(2), NOW);
+        assert_eq!(store.publish(42, entry(3), NOW), StoreResult::PoolFull);
+
+        // Claiming frees a slot
+        store.claim(42, NOW);
+        assert_eq!(store.publish(42, entry(3), NOW), StoreResult::Success);
+    }
+
+    #[test]
+    fn least_recently_published_pool_is_evicted() {
+        let store =
+            KeyPackageStore::new(KeyPackageStoreConfig { max_users: 2, ..Default::default() });
+        store.publish(1, entry(1), NOW);
+        store.publish(2, entry(2), NOW);
+        // Refresh user 1 so user 2 becomes the oldest
+        store.publish(1, entry(3), NOW);
+
+        assert_eq!(store.publish(3, entry(4), NOW), StoreResult::Evicted);
+        assert_eq!(store.available(1, NOW), 2);
+        assert_eq!(store.available(2, NOW), 0);
+        assert_eq!(store.available(3, NOW), 1);
+    }
+
+    #[test]
+    fn clone_shares_state() {
+        let store = KeyPackageStore::default();
+        let clone = store.clone();
+
+        store.publish(42, entry(1), NOW);
+        assert!(clone.claim(42, NOW).is_some());
+        assert!(store.claim(42, NOW).is_none());
+    }
+}
@@ -10,52 +10,108 @@
 //! pattern (see [`lockframe_core`] for details), while [`Server`] executes the
 //! actions using Quinn QUIC and Tokio async runtime.
 //!
+//! Sequenced frames are written to storage off the routing path: [`Server`]
+//! runs persistence and broadcast as separate stages so one frame's disk
+//! write overlaps the broadcast of frames already stored, while each room's
+//! frames still go out in log order.
+//!
 //! # Components
 //!
 //! - [`ServerDriver`]: Action-based orchestrator (pure logic, no I/O)
 //! - [`Server`]: Production runtime that executes `ServerDriver` actions
 //! - [`QuinnTransport`]: QUIC transport via Quinn library
 //! - [`SystemEnv`]: Production environment (real time, crypto RNG)
-
+//! - [`FrameInterceptor`]: Embedder hooks around frames with a given opcode
+
+mod acl;
+mod admin;
+mod audit;
+mod auth;
+mod chaos;
+mod directory;
 mod driver;
 mod error;
-mod key_package_registry;
+mod expiry;
+mod federation;
+mod http;
+mod idempotency;
+mod intercept;
+mod key_package_store;
+mod log;
+mod pipeline;
+mod policy;
+mod presence;
 mod registry;
+mod retention;
 mod room_manager;
+mod send_queue;
 pub mod sequencer;
 mod server_error;
+mod shard;
 pub mod storage;
+mod sync;
 mod system_env;
 mod transport;
-
-use std::{collections::HashMap, sync::Arc};
-
-use bytes::BytesMut;
-pub use driver::{LogLevel, ServerAction, ServerConfig as DriverConfig, ServerDriver, ServerEvent};
+mod webhook;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+pub use acl::{Denial, RoomAcl};
+pub use admin::AdminToken;
+pub use auth::{
+    AuthError, Authenticator, HmacTokens, OidcJwt, PeerIdentity, Principal, StaticTokens,
+};
+use bytes::{Bytes, BytesMut};
+use chaos::Chaos;
+pub use chaos::{ChaosConfig, ChaosFault};
+pub use driver::{ServerAction, ServerConfig as DriverConfig, ServerDriver, ServerEvent};
 pub use error::ServerError;
-pub use key_package_registry::{KeyPackageEntry, KeyPackageRegistry};
+pub use federation::{FederationConfig, FederationPeer};
+pub use intercept::{FrameContext, FrameInterceptor, Intercept};
+pub use key_package_store::{
+    Claimed, KeyPackageEntry, KeyPackageStore, KeyPackageStoreConfig, StoreResult,
+};
 use lockframe_core::env::Environment;
-use lockframe_proto::{Frame, FrameHeader};
+use lockframe_proto::{Frame, FrameHeader, Opcode, payloads::admin::AuditEntry};
+pub use log::{LogEvent, LogLevel, LogTarget};
+use pipeline::{BroadcastJob, PersistFailure, Pipeline};
+pub use presence::{PresenceConfig, PresenceVisibility};
 pub use registry::{ConnectionRegistry, SessionInfo};
+pub use retention::{RetentionConfig, RetentionPolicy};
 pub use room_manager::{RoomAction, RoomError, RoomManager, RoomMetadata};
+use send_queue::{Outbox, Outgoing};
 pub use sequencer::{Sequencer, SequencerAction, SequencerError};
 pub use server_error::{ExecutorError, ServerError as DriverError};
-pub use storage::{ChaoticStorage, MemoryStorage, Storage, StorageError};
+pub use storage::{
+    BlobStore, ChaoticStorage, FsBlobStore, MemoryBlobStore, MemoryStorage, SledConfig,
+    SledStorage, SqliteStorage, Storage, StorageError, TierConfig, TieredStorage, WalConfig,
+    WalStorage,
+};
 pub use system_env::SystemEnv;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, mpsc};
 pub use transport::{QuinnConnection, QuinnTransport};
+pub use webhook::{SIGNATURE_HEADER, WebhookConfig, WebhookDelivery};
 use zerocopy::FromBytes;
 
+/// How often a draining server checks whether sessions have left.
+const DRAIN_TICK: Duration = Duration::from_millis(100);
+
 /// Shared state for all connections.
 ///
 /// This holds connection and stream maps for message routing.
 struct SharedState {
     /// Map of session ID to QUIC connection (for closing)
     connections: RwLock<HashMap<u64, QuinnConnection>>,
-    /// Map of session ID to persistent outbound stream
-    /// All messages to a client go through this single stream, ensuring
-    /// ordering.
-    outbound_streams: RwLock<HashMap<u64, tokio::sync::Mutex<quinn::SendStream>>>,
+    /// Map of session ID to send queue. Each session's writer task drains
+    /// its queue into one persistent outbound stream, ensuring ordering.
+    outboxes: RwLock<HashMap<u64, Arc<Outbox>>>,
+    /// Bound on bytes queued for one session
+    send_queue_bytes: usize,
+    /// File audit entries are appended to
+    audit_log: Option<tokio::sync::Mutex<tokio::fs::File>>,
+    /// Persist stage sequenced frames are queued for. `None` stores them
+    /// while routing.
+    pipeline: Option<Pipeline>,
 }
 
 /// Server configuration for the production runtime.
@@ -67,8 +123,21 @@ pub struct ServerRuntimeConfig {
     pub cert_path: Option<String>,
     /// Path to TLS private key (PEM format)
     pub key_path: Option<String>,
+    /// Path to the CA certificates client certificates are verified against
+    /// (PEM format). `None` asks clients for no certificate.
+    pub client_ca_path: Option<String>,
     /// Driver configuration (timeouts, limits)
     pub driver: DriverConfig,
+    /// Most bytes queued for one session. A session that reads slower than
+    /// frames arrive loses the oldest and is sent `LaggedBehind`.
+    pub send_queue_bytes: usize,
+    /// File audit entries are appended to, one JSON object per line. `None`
+    /// only logs them.
+    pub audit_log_path: Option<String>,
+    /// Most frames waiting in each stage of the persist pipeline. Routing
+    /// waits while the stage is full. Zero stores each frame while routing
+    /// it, before its broadcast.
+    pub pipeline_depth: usize,
 }
 
 impl Default for ServerRuntimeConfig {
@@ -77,51 +146,181 @@ impl Default for ServerRuntimeConfig {
             bind_address: "0.0.0.0:4433".to_string(),
             cert_path: None,
             key_path: None,
+            client_ca_path: None,
             driver: DriverConfig::default(),
+            send_queue_bytes: send_queue::DEFAULT_QUEUE_BYTES,
+            audit_log_path: None,
+            pipeline_depth: pipeline::DEFAULT_PIPELINE_DEPTH,
         }
     }
 }
 
+/// Changes the driver configuration of a running [`Server`].
+#[derive(Debug, Clone)]
+pub struct ConfigHandle {
+    updates: mpsc::UnboundedSender<DriverConfig>,
+}
+
+impl ConfigHandle {
+    /// Ask the server to apply `config` to the running driver.
+    ///
+    /// The driver validates it first and logs whether it was applied; see
+    /// [`ServerEvent::ConfigUpdated`].
+    pub fn update(&self, config: DriverConfig) -> Result<(), ServerError> {
+        self.updates
+            .send(config)
+            .map_err(|_| ServerError::Internal("server is not running".to_string()))
+    }
+}
+
 /// Production Lockframe server.
 ///
 /// Wraps `ServerDriver` with Quinn QUIC transport and system environment.
-pub struct Server {
+pub struct Server<S: Storage = MemoryStorage> {
     /// The action-based server driver
-    driver: ServerDriver<SystemEnv, MemoryStorage>,
+    driver: ServerDriver<SystemEnv, S>,
+    /// Storage the persist stage writes to, shared with the driver
+    storage: S,
+    /// Bound on frames waiting in each pipeline stage, zero for none
+    pipeline_depth: usize,
+    /// Faults injected between pipeline stages, if any
+    chaos: Option<ChaosConfig>,
     /// QUIC endpoint
     transport: QuinnTransport,
     /// Environment
     env: SystemEnv,
+    /// Bound on bytes queued for one session
+    send_queue_bytes: usize,
+    /// Audit log file, opened for appending
+    audit_log: Option<std::fs::File>,
+    /// Sender half handed out by [`Server::config_handle`]
+    config_tx: mpsc::UnboundedSender<DriverConfig>,
+    /// Configuration updates waiting to be applied
+    config_rx: mpsc::UnboundedReceiver<DriverConfig>,
 }
 
 impl Server {
-    /// Create and bind a new server.
+    /// Create and bind a new server with in-memory storage.
+    ///
+    /// All rooms are lost when the server stops. Use
+    /// [`Server::bind_with_storage`] for durable storage.
     pub fn bind(config: ServerRuntimeConfig) -> Result<Self, ServerError> {
-        let env = SystemEnv::new();
-        let storage = MemoryStorage::new();
-        let driver = ServerDriver::new(env.clone(), storage, config.driver);
+        Self::bind_with_storage(config, MemoryStorage::new())
+    }
+}
 
-        let transport =
-            QuinnTransport::bind(&config.bind_address, config.cert_path, config.key_path)?;
+impl<S: Storage> Server<S> {
+    /// Create and bind a new server, recovering any rooms already in
+    /// `storage`.
+    pub fn bind_with_storage(config: ServerRuntimeConfig, storage: S) -> Result<Self, ServerError> {
+        let env = SystemEnv::new();
+        let chaos = config.driver.chaos;
+        if let Some(chaos) = chaos {
+            if config.pipeline_depth == 0 {
+                return Err(ServerError::Config("chaos needs the persist pipeline".to_string()));
+            }
+            tracing::warn!("Injecting faults between pipeline stages: {:?}", chaos);
+        }
+        let mut driver = ServerDriver::new(env.clone(), storage.clone(), config.driver);
+        driver.recover_from_storage()?;
+        driver.set_pipelined_persistence(config.pipeline_depth > 0);
+
+        let transport = QuinnTransport::bind(
+            &config.bind_address,
+            config.cert_path,
+            config.key_path,
+            config.client_ca_path,
+        )?;
+
+        let audit_log = config
+            .audit_log_path
+            .map(|path| {
+                std::fs::OpenOptions::new().create(true).append(true).open(&path).map_err(|e| {
+                    ServerError::Config(format!("failed to open audit log {path}: {e}"))
+                })
+            })
+            .transpose()?;
+
+        let (config_tx, config_rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            driver,
+            storage,
+            pipeline_depth: config.pipeline_depth,
+            chaos,
+            transport,
+            env,
+            send_queue_bytes: config.send_queue_bytes,
+            audit_log,
+            config_tx,
+            config_rx,
+        })
+    }
 
-        Ok(Self { driver, transport, env })
+    /// Run `interceptor` around every frame with `opcode`, after any already
+    /// registered for it. See [`FrameInterceptor`].
+    pub fn add_interceptor(&mut self, opcode: Opcode, interceptor: Arc<dyn FrameInterceptor>) {
+        self.driver.add_interceptor(opcode, interceptor);
     }
 
     /// Run the server, accepting connections and processing frames.
     ///
-    /// This method runs until the server is shut down or an error occurs.
+    /// This method runs until an error occurs.
     pub async fn run(self) -> Result<(), ServerError> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Run the server until `shutdown` completes, then drain it.
+    ///
+    /// Draining stops accepting connections, asks every session to leave,
+    /// and keeps serving them until they do or the driver's drain timeout
+    /// passes. Returns once storage has been flushed.
+    pub async fn run_until(
+        self,
+        shutdown: impl Future<Output = ()> + Send,
+    ) -> Result<(), ServerError> {
         tracing::info!("Server starting on {}", self.transport.local_addr()?);
 
         let env = self.env;
+        let mut config_rx = self.config_rx;
         let driver = Arc::new(tokio::sync::Mutex::new(self.driver));
+        let (pipeline, broadcasts, mut failures) = if self.pipeline_depth > 0 {
+            let chaos = self.chaos.map(|config| Chaos::new(config, env.clone()));
+            let (pipeline, broadcasts, failures) =
+                Pipeline::spawn(self.storage, self.pipeline_depth, chaos);
+            (Some(pipeline), Some(broadcasts), failures)
+        } else {
+            (None, None, mpsc::unbounded_channel().1)
+        };
         let shared = Arc::new(SharedState {
             connections: RwLock::new(HashMap::new()),
-            outbound_streams: RwLock::new(HashMap::new()),
+            outboxes: RwLock::new(HashMap::new()),
+            send_queue_bytes: self.send_queue_bytes,
+            audit_log: self
+                .audit_log
+                .map(|file| tokio::sync::Mutex::new(tokio::fs::File::from_std(file))),
+            pipeline,
         });
+        if let Some(broadcasts) = broadcasts {
+            tokio::spawn(run_broadcast(broadcasts, Arc::clone(&shared)));
+        }
 
+        let mut shutdown = std::pin::pin!(shutdown);
         loop {
-            match self.transport.accept().await {
+            let accepted = tokio::select! {
+                accepted = self.transport.accept() => accepted,
+                () = &mut shutdown => break,
+                Some(config) = config_rx.recv() => {
+                    let event = ServerEvent::ConfigUpdated(Box::new(config));
+                    let actions = process_event(&driver, &shared, event).await?;
+                    execute_actions(actions, &shared).await?;
+                    continue;
+                },
+                Some(failure) = failures.recv() => {
+                    report_persist_failure(&driver, &shared, failure).await?;
+                    continue;
+                },
+            };
+            match accepted {
                 Ok(conn) => {
                     let driver = Arc::clone(&driver);
                     let shared = Arc::clone(&shared);
@@ -138,6 +337,13 @@ impl Server {
                 },
             }
         }
+
+        drain(&driver, &shared, &mut failures).await
+    }
+
+    /// Handle for changing the driver configuration while the server runs.
+    pub fn config_handle(&self) -> ConfigHandle {
+        ConfigHandle { updates: self.config_tx.clone() }
     }
 
     /// Local address the server is bound to.
@@ -146,10 +352,38 @@ impl Server {
     }
 }
 
+/// Drain the driver, ticking it until it reports `DrainComplete`, then
+/// wait for the frames still in the pipeline.
+async fn drain<S: Storage>(
+    driver: &tokio::sync::Mutex<ServerDriver<SystemEnv, S>>,
+    shared: &SharedState,
+    failures: &mut mpsc::UnboundedReceiver<PersistFailure>,
+) -> Result<(), ServerError> {
+    let mut event = ServerEvent::BeginShutdown;
+    loop {
+        while let Ok(failure) = failures.try_recv() {
+            report_persist_failure(driver, shared, failure).await?;
+        }
+
+        let actions = process_event(driver, shared, event).await?;
+        let complete = actions.iter().any(|action| matches!(action, ServerAction::DrainComplete));
+        execute_actions(actions, shared).await?;
+        if complete {
+            if let Some(pipeline) = &shared.pipeline {
+                pipeline.flush().await;
+            }
+            return Ok(());
+        }
+
+        tokio::time::sleep(DRAIN_TICK).await;
+        event = ServerEvent::Tick;
+    }
+}
+
 /// Handle a single QUIC connection.
-async fn handle_connection(
+async fn handle_connection<S: Storage>(
     conn: QuinnConnection,
-    driver: Arc<tokio::sync::Mutex<ServerDriver<SystemEnv, MemoryStorage>>>,
+    driver: Arc<tokio::sync::Mutex<ServerDriver<SystemEnv, S>>>,
     shared: Arc<SharedState>,
     env: SystemEnv,
 ) -> Result<(), ServerError> {
@@ -171,15 +405,16 @@ async fn handle_connection(
         connections.insert(session_id, conn.clone());
     }
 
+    let outbox = Arc::new(Outbox::new(shared.send_queue_bytes));
     {
-        let mut streams = shared.outbound_streams.write().await;
-        streams.insert(session_id, tokio::sync::Mutex::new(outbound_stream));
+        let mut outboxes = shared.outboxes.write().await;
+        outboxes.insert(session_id, Arc::clone(&outbox));
     }
+    let writer = tokio::spawn(write_outbox(session_id, outbox, outbound_stream, conn.clone()));
 
-    let actions = {
-        let mut driver = driver.lock().await;
-        driver.process_event(ServerEvent::ConnectionAccepted { session_id })?
-    };
+    let peer_identity = conn.peer_identity();
+    let event = ServerEvent::ConnectionAccepted { session_id, peer_identity };
+    let actions = process_event(&driver, &shared, event).await?;
     execute_actions(actions, &shared).await?;
 
     loop {
@@ -207,28 +442,50 @@ async fn handle_connection(
     }
 
     {
-        let mut streams = shared.outbound_streams.write().await;
-        streams.remove(&session_id);
+        let mut outboxes = shared.outboxes.write().await;
+        outboxes.remove(&session_id);
     }
+    writer.abort();
 
-    let actions = {
-        let mut driver = driver.lock().await;
-        driver.process_event(ServerEvent::ConnectionClosed {
-            session_id,
-            reason: "connection closed".to_string(),
-        })?
-    };
+    let event =
+        ServerEvent::ConnectionClosed { session_id, reason: "connection closed".to_string() };
+    let actions = process_event(&driver, &shared, event).await?;
     execute_actions(actions, &shared).await?;
 
     Ok(())
 }
 
+/// Write a session's queued frames to its outbound stream as they arrive,
+/// closing the connection when asked to.
+async fn write_outbox(
+    session_id: u64,
+    outbox: Arc<Outbox>,
+    mut stream: quinn::SendStream,
+    conn: QuinnConnection,
+) {
+    loop {
+        match outbox.next().await {
+            Outgoing::Frames(batch) => {
+                if let Err(e) = stream.write_all(&batch).await {
+                    tracing::warn!("Write failed for {}: {}", session_id, e);
+                    return;
+                }
+            },
+            Outgoing::Close(reason) => {
+                let _ = stream.finish();
+                conn.close(0u32.into(), reason.as_bytes());
+                return;
+            },
+        }
+    }
+}
+
 /// Handle a single bidirectional stream.
-async fn handle_stream(
+async fn handle_stream<S: Storage>(
     session_id: u64,
     send: quinn::SendStream,
     mut recv: quinn::RecvStream,
-    driver: Arc<tokio::sync::Mutex<ServerDriver<SystemEnv, MemoryStorage>>>,
+    driver: Arc<tokio::sync::Mutex<ServerDriver<SystemEnv, S>>>,
     shared: &Arc<SharedState>,
 ) -> Result<(), ServerError> {
     drop(send); // not used for now
@@ -272,15 +529,13 @@ async fn handle_stream(
             },
         };
 
-        let actions = {
-            let mut driver = driver.lock().await;
-            match driver.process_event(ServerEvent::FrameReceived { session_id, frame }) {
-                Ok(actions) => actions,
-                Err(e) => {
-                    tracing::warn!("Frame processing error: {}", e);
-                    continue;
-                },
-            }
+        let event = ServerEvent::FrameReceived { session_id, frame };
+        let actions = match process_event(&driver, shared, event).await {
+            Ok(actions) => actions,
+            Err(e) => {
+                tracing::warn!("Frame processing error: {}", e);
+                continue;
+            },
         };
 
         execute_actions(actions, shared).await?;
@@ -289,6 +544,60 @@ async fn handle_stream(
     Ok(())
 }
 
+/// Process `event`, queueing the frame writes it produces before the driver
+/// is released so they reach the persist stage in log order.
+async fn process_event<S: Storage>(
+    driver: &tokio::sync::Mutex<ServerDriver<SystemEnv, S>>,
+    shared: &SharedState,
+    event: ServerEvent,
+) -> Result<Vec<ServerAction>, DriverError> {
+    let mut driver = driver.lock().await;
+    let actions = driver.process_event(event)?;
+    match &shared.pipeline {
+        Some(pipeline) => Ok(pipeline.submit(actions).await),
+        None => Ok(actions),
+    }
+}
+
+/// Tell the driver a frame could not be stored.
+///
+/// The room is settled first, with the driver locked so no new frame of it
+/// is sequenced meanwhile: once none of its frames is waiting or being
+/// written, the driver can resequence it from storage without handing out an
+/// index a write still claims.
+async fn report_persist_failure<S: Storage>(
+    driver: &tokio::sync::Mutex<ServerDriver<SystemEnv, S>>,
+    shared: &SharedState,
+    (room_id, log_index, error): PersistFailure,
+) -> Result<(), ServerError> {
+    let Some(pipeline) = &shared.pipeline else {
+        return Ok(());
+    };
+    let mut driver = driver.lock().await;
+    let dropped = pipeline.settle(room_id).await;
+    let event = ServerEvent::PersistFailed { room_id, log_index, error, dropped };
+    let actions = driver.process_event(event)?;
+    let actions = pipeline.submit(actions).await;
+    drop(driver);
+    execute_actions(actions, shared).await
+}
+
+/// Broadcast stage: execute the actions of stored frames in log order.
+async fn run_broadcast(mut jobs: mpsc::Receiver<BroadcastJob>, shared: Arc<SharedState>) {
+    while let Some(job) = jobs.recv().await {
+        match job {
+            BroadcastJob::Actions(actions) => {
+                if let Err(e) = execute_actions(actions, &shared).await {
+                    tracing::warn!("Broadcast failed: {}", e);
+                }
+            },
+            BroadcastJob::Done(done) => {
+                let _ = done.send(());
+            },
+        }
+    }
+}
+
 /// Execute server actions.
 async fn execute_actions(
     actions: Vec<ServerAction>,
@@ -297,51 +606,81 @@ async fn execute_actions(
     for action in actions {
         match action {
             ServerAction::SendToSession { session_id, frame } => {
-                let mut buf = Vec::new();
-                frame.encode(&mut buf).map_err(|e| ServerError::Protocol(e.to_string()))?;
-
-                let streams = shared.outbound_streams.read().await;
-                if let Some(stream_mutex) = streams.get(&session_id) {
-                    let mut stream = stream_mutex.lock().await;
-                    if let Err(e) = stream.write_all(&buf).await {
-                        tracing::warn!("SendToSession write failed for {}: {}", session_id, e);
-                    }
+                let buf = encode_frame(&frame)?;
+
+                let outboxes = shared.outboxes.read().await;
+                if let Some(outbox) = outboxes.get(&session_id) {
+                    outbox.push(buf).await;
                 } else {
                     tracing::warn!("SendToSession: session {} not found", session_id);
                 }
             },
 
             ServerAction::Broadcast { session_ids, frame } => {
-                let mut buf = Vec::new();
-                frame.encode(&mut buf).map_err(|e| ServerError::Protocol(e.to_string()))?;
+                let buf = encode_frame(&frame)?;
 
-                let streams = shared.outbound_streams.read().await;
+                let outboxes = shared.outboxes.read().await;
                 for session_id in session_ids {
-                    if let Some(stream_mutex) = streams.get(&session_id) {
-                        let mut stream = stream_mutex.lock().await;
-                        if let Err(e) = stream.write_all(&buf).await {
-                            tracing::warn!("Broadcast write failed for {}: {}", session_id, e);
-                        }
+                    if let Some(outbox) = outboxes.get(&session_id) {
+                        outbox.push(buf.clone()).await;
                     }
                 }
             },
 
             ServerAction::CloseConnection { session_id, reason } => {
                 tracing::info!("Closing connection {}: {}", session_id, reason);
-                let mut connections = shared.connections.write().await;
-                if let Some(conn) = connections.remove(&session_id) {
-                    conn.close(0u32.into(), reason.as_bytes());
+                let conn = shared.connections.write().await.remove(&session_id);
+                let outbox = shared.outboxes.read().await.get(&session_id).cloned();
+                match (outbox, conn) {
+                    (Some(outbox), _) => outbox.close(reason).await,
+                    (None, Some(conn)) => conn.close(0u32.into(), reason.as_bytes()),
+                    (None, None) => {},
+                }
+            },
+
+            ServerAction::Log(event) => event.emit(),
+
+            ServerAction::Audit(entry) => {
+                tracing::info!(target: "lockframe_server::audit", seq = entry.seq, event = ?entry.event);
+                if let Some(audit_log) = &shared.audit_log
+                    && let Err(e) = append_audit(audit_log, &entry).await
+                {
+                    tracing::error!("failed to append audit entry {}: {}", entry.seq, e);
                 }
             },
 
-            ServerAction::Log { level, message, .. } => match level {
-                LogLevel::Debug => tracing::debug!("{}", message),
-                LogLevel::Info => tracing::info!("{}", message),
-                LogLevel::Warn => tracing::warn!("{}", message),
-                LogLevel::Error => tracing::error!("{}", message),
+            ServerAction::DeliverWebhook(delivery) => {
+                tokio::spawn(http::deliver(delivery));
             },
+
+            // Admin replies are only produced for `ServerEvent::Admin`, which
+            // this runtime never sends. `drain` watches for `DrainComplete`,
+            // and `process_event` hands frame writes to the pipeline
+            ServerAction::AdminReply(_)
+            | ServerAction::DrainComplete
+            | ServerAction::PersistFrame { .. } => {},
         }
     }
 
     Ok(())
 }
+
+/// Append `entry` to the audit log as one line of JSON.
+async fn append_audit(
+    audit_log: &tokio::sync::Mutex<tokio::fs::File>,
+    entry: &AuditEntry,
+) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    let mut file = audit_log.lock().await;
+    file.write_all(&line).await?;
+    file.flush().await
+}
+
+fn encode_frame(frame: &Frame) -> Result<Bytes, ServerError> {
+    let mut buf = Vec::new();
+    frame.encode(&mut buf).map_err(|e| ServerError::Protocol(e.to_string()))?;
+    Ok(Bytes::from(buf))
+}
@@ -0,0 +1,234 @@
+//! Structured log events.
+//!
+//! The driver never writes logs itself; it returns [`LogEvent`]s inside
+//! [`ServerAction::Log`](crate::ServerAction::Log) and the runtime decides
+//! where they go. Each event has a fixed message, a [`LogTarget`] naming the
+//! subsystem, and the room, session and any other values as separate fields
+//! so they can be filtered and asserted on without parsing text.
+
+use std::fmt;
+
+/// Log levels for server actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Debug information
+    Debug,
+    /// Informational message
+    Info,
+    /// Warning
+    Warn,
+    /// Error
+    Error,
+}
+
+/// Subsystem an event originates from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogTarget {
+    /// Connection lifecycle and handshakes
+    Connection,
+    /// Room creation, membership and routing
+    Room,
+    /// Frame ordering and persistence
+    Sequencer,
+    /// Sync requests
+    Sync,
+    /// `KeyPackage` publish and fetch
+    KeyPackages,
+    /// `GroupInfo` publish and fetch
+    GroupInfo,
+    /// Retention and compaction
+    Retention,
+    /// Operator requests
+    Admin,
+    /// Peer links and relayed frames
+    Federation,
+    /// Online status queries and changes
+    Presence,
+}
+
+impl LogTarget {
+    /// Target string for `tracing` and other log sinks.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Connection => "lockframe_server::connection",
+            Self::Room => "lockframe_server::room",
+            Self::Sequencer => "lockframe_server::sequencer",
+            Self::Sync => "lockframe_server::sync",
+            Self::KeyPackages => "lockframe_server::key_packages",
+            Self::GroupInfo => "lockframe_server::group_info",
+            Self::Retention => "lockframe_server::retention",
+            Self::Admin => "lockframe_server::admin",
+            Self::Federation => "lockframe_server::federation",
+            Self::Presence => "lockframe_server::presence",
+        }
+    }
+}
+
+impl fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A structured log event.
+///
+/// Generic over `I` (Instant type) to support virtual time in tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent<I = std::time::Instant> {
+    /// Severity
+    pub level: LogLevel,
+    /// Subsystem
+    pub target: LogTarget,
+    /// What happened. Never contains IDs or error text; those are fields.
+    pub message: &'static str,
+    /// Room the event concerns
+    pub room_id: Option<u128>,
+    /// Session the event concerns
+    pub session_id: Option<u64>,
+    /// Additional key-value context
+    pub fields: Vec<(&'static str, String)>,
+    /// When the event occurred
+    pub timestamp: I,
+}
+
+impl<I> LogEvent<I> {
+    /// Create an event with no context fields.
+    pub fn new(level: LogLevel, target: LogTarget, message: &'static str, timestamp: I) -> Self {
+        Self {
+            level,
+            target,
+            message,
+            room_id: None,
+            session_id: None,
+            fields: Vec::new(),
+            timestamp,
+        }
+    }
+
+    /// Debug-level event.
+    pub fn debug(target: LogTarget, message: &'static str, timestamp: I) -> Self {
+        Self::new(LogLevel::Debug, target, message, timestamp)
+    }
+
+    /// Info-level event.
+    pub fn info(target: LogTarget, message: &'static str, timestamp: I) -> Self {
+        Self::new(LogLevel::Info, target, message, timestamp)
+    }
+
+    /// Warn-level event.
+    pub fn warn(target: LogTarget, message: &'static str, timestamp: I) -> Self {
+        Self::new(LogLevel::Warn, target, message, timestamp)
+    }
+
+    /// Error-level event.
+    pub fn error(target: LogTarget, message: &'static str, timestamp: I) -> Self {
+        Self::new(LogLevel::Error, target, message, timestamp)
+    }
+
+    /// Attach the room ID.
+    #[must_use]
+    pub fn room(mut self, room_id: u128) -> Self {
+        self.room_id = Some(room_id);
+        self
+    }
+
+    /// Attach the session ID.
+    #[must_use]
+    pub fn session(mut self, session_id: u64) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+
+    /// Attach a key-value field.
+    #[must_use]
+    pub fn field(mut self, key: &'static str, value: impl fmt::Display) -> Self {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    /// Value of a field, if present.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Forward the event to `tracing`.
+    ///
+    /// `tracing` targets must be static, so events are emitted under
+    /// `lockframe_server` with the subsystem in the `subsystem` field.
+    pub fn emit(&self) {
+        let subsystem = self.target.as_str();
+        let room_id = self.room_id.map(|room_id| format!("{room_id:032x}"));
+        let fields = Fields(&self.fields);
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    target: "lockframe_server",
+                    $level,
+                    subsystem,
+                    room_id = room_id.as_deref(),
+                    session_id = self.session_id,
+                    fields = %fields,
+                    "{}",
+                    self.message
+                )
+            };
+        }
+        match self.level {
+            LogLevel::Debug => emit!(tracing::Level::DEBUG),
+            LogLevel::Info => emit!(tracing::Level::INFO),
+            LogLevel::Warn => emit!(tracing::Level::WARN),
+            LogLevel::Error => emit!(tracing::Level::ERROR),
+        }
+    }
+}
+
+impl<I> fmt::Display for LogEvent<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.target, self.message)?;
+        if let Some(room_id) = self.room_id {
+            write!(f, " room_id={room_id:032x}")?;
+        }
+        if let Some(session_id) = self.session_id {
+            write!(f, " session_id={session_id}")?;
+        }
+        if !self.fields.is_empty() {
+            write!(f, " {}", Fields(&self.fields))?;
+        }
+        Ok(())
+    }
+}
+
+/// Space-separated `key=value` pairs.
+struct Fields<'a>(&'a [(&'static str, String)]);
+
+impl fmt::Display for Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_includes_context() {
+        let event = LogEvent::warn(LogTarget::Room, "frame rejected", ())
+            .room(0xab)
+            .session(7)
+            .field("reason", "not a member");
+
+        assert_eq!(event.get("reason"), Some("not a member"));
+        assert_eq!(
+            event.to_string(),
+            "lockframe_server::room: frame rejected \
+             room_id=000000000000000000000000000000ab session_id=7 reason=not a member"
+        );
+    }
+}
@@ -8,10 +8,53 @@
 //!
 //! # Start with TLS certificate (production)
 //! lockframe-server --bind 0.0.0.0:4433 --cert cert.pem --key key.pem
+//!
+//! # Persist rooms across restarts
+//! lockframe-server --bind 0.0.0.0:4433 --db lockframe.sqlite
+//!
+//! # Persist rooms in a log-structured store, for very long room histories
+//! lockframe-server --bind 0.0.0.0:4433 --db lockframe.sled --storage sled
+//!
+//! # Sync every write to a write-ahead log beside the database
+//! lockframe-server --bind 0.0.0.0:4433 --db lockframe.sled --storage sled --wal
+//!
+//! # Move all but the latest 10000 frames per room to a slower volume
+//! lockframe-server --bind 0.0.0.0:4433 --db lockframe.sqlite --cold-dir /mnt/archive \
+//!     --hot-frames 10000
+//!
+//! # Keep 30 days of history per room
+//! lockframe-server --bind 0.0.0.0:4433 --db lockframe.sqlite --retention-days 30
+//!
+//! # Require device certificates issued by our CA
+//! lockframe-server --bind 0.0.0.0:4433 --client-ca devices.pem --require-client-cert
+//!
+//! # Keep an audit trail of security-relevant events
+//! lockframe-server --bind 0.0.0.0:4433 --audit-log audit.jsonl
+//!
+//! # Post room events to a webhook, signed with the key in hook.key
+//! lockframe-server --bind 0.0.0.0:4433 --webhook-url https://hooks.example/lockframe \
+//!     --webhook-secret hook.key
+//!
+//! # Require HMAC-signed auth tokens
+//! lockframe-server --bind 0.0.0.0:4433 --auth-hmac-secret secret.key
+//!
+//! # Require JWTs from an OpenID Connect provider
+//! lockframe-server --bind 0.0.0.0:4433 --oidc-jwks jwks.json \
+//!     --oidc-issuer https://idp.example --oidc-audience lockframe
+//!
+//! # Soak test: hold frames up to 50ms between stages and drop 1 in 1000
+//! lockframe-server --bind 0.0.0.0:4433 --chaos-delay-ms 50 --chaos-drop-per-mille 1
 //! ```
 
-use clap::Parser;
-use lockframe_server::{DriverConfig, Server, ServerRuntimeConfig};
+use std::{sync::Arc, time::Duration};
+
+use clap::{Parser, ValueEnum};
+use lockframe_proto::payloads::admin::WebhookTarget;
+use lockframe_server::{
+    Authenticator, ChaosConfig, ChaosFault, DriverConfig, FsBlobStore, HmacTokens, OidcJwt,
+    RetentionConfig, RetentionPolicy, Server, ServerRuntimeConfig, SledStorage, SqliteStorage,
+    Storage, TierConfig, TieredStorage, WalConfig, WalStorage, WebhookConfig,
+};
 use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
 /// Lockframe protocol server
@@ -32,15 +75,110 @@ struct Args {
     #[arg(short, long)]
     key: Option<String>,
 
+    /// Ask clients for a certificate issued by a CA in this file (PEM format)
+    #[arg(long)]
+    client_ca: Option<String>,
+
+    /// Close connections that present no client certificate
+    #[arg(long, requires = "client_ca")]
+    require_client_cert: bool,
+
+    /// Path to the database. Rooms are kept in memory and lost on restart if
+    /// omitted.
+    #[arg(long)]
+    db: Option<String>,
+
+    /// Storage backend used with `--db`
+    #[arg(long, value_enum, default_value = "sqlite")]
+    storage: StorageBackend,
+
+    /// Log every write to `<db>.wal` and sync it before the write is
+    /// applied, so frames survive a crash even when the backend batches
+    /// its own syncs
+    #[arg(long, requires = "db")]
+    wal: bool,
+
+    /// Move older room history into segment files under this directory,
+    /// keeping only recent frames in `--db`
+    #[arg(long, requires = "db")]
+    cold_dir: Option<String>,
+
+    /// Frames per room kept in `--db` when `--cold-dir` is set
+    #[arg(long, requires = "cold_dir", default_value = "16384")]
+    hot_frames: u64,
+
     /// Maximum concurrent connections
     #[arg(long, default_value = "10000")]
     max_connections: usize,
 
+    /// Drop room history older than this many days. History is kept forever
+    /// if neither retention limit is set.
+    #[arg(long)]
+    retention_days: Option<u64>,
+
+    /// Keep at most this many frames of history per room
+    #[arg(long)]
+    retention_frames: Option<u64>,
+
+    /// Refuse new messages in a room once it stores this many megabytes of
+    /// history
+    #[arg(long)]
+    room_quota_mb: Option<u64>,
+
+    /// Require clients to present tokens signed with the secret in this file
+    #[arg(long, conflicts_with = "oidc_jwks")]
+    auth_hmac_secret: Option<String>,
+
+    /// Require clients to present JWTs signed by a key in this JWKS document
+    #[arg(long, requires_all = ["oidc_issuer", "oidc_audience"])]
+    oidc_jwks: Option<String>,
+
+    /// Issuer JWTs must name, with `--oidc-jwks`
+    #[arg(long)]
+    oidc_issuer: Option<String>,
+
+    /// Audience JWTs must name, with `--oidc-jwks`
+    #[arg(long)]
+    oidc_audience: Option<String>,
+
+    /// Append audit entries (auth failures, kicks, room closures, message
+    /// reports) to this file as JSON lines
+    #[arg(long)]
+    audit_log: Option<String>,
+
+    /// Post room events (creation, member count changes, message volume) to
+    /// this URL for rooms without a webhook of their own
+    #[arg(long, requires = "webhook_secret")]
+    webhook_url: Option<String>,
+
+    /// Sign webhook bodies with the key in this file, with `--webhook-url`
+    #[arg(long)]
+    webhook_secret: Option<String>,
+
+    /// Soak testing only: hold each frame up to this many milliseconds
+    /// before it is stored and again before it is broadcast
+    #[arg(long)]
+    chaos_delay_ms: Option<u64>,
+
+    /// Soak testing only: drop this many frames per thousand before they are
+    /// stored and again before they are broadcast
+    #[arg(long)]
+    chaos_drop_per_mille: Option<u32>,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, default_value = "info")]
     log_level: String,
 }
 
+/// Durable storage backends.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StorageBackend {
+    /// SQLite in WAL mode
+    Sqlite,
+    /// Sled log-structured store, for append-heavy rooms with long histories
+    Sled,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
@@ -57,18 +195,155 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         tracing::warn!("This is NOT suitable for production use!");
     }
 
+    let authenticator = authenticator(&args)?;
+    if authenticator.is_none() {
+        tracing::warn!("No authenticator configured - clients choose their own user IDs");
+    }
+
+    let chaos = chaos(&args);
+
+    let default_target = match (&args.webhook_url, &args.webhook_secret) {
+        (Some(url), Some(path)) => {
+            let secret = std::fs::read(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+            tracing::info!("Posting room events to {}", url);
+            Some(WebhookTarget {
+                url: url.clone(),
+                secret: secret.trim_ascii().to_vec(),
+                events: Vec::new(),
+            })
+        },
+        _ => None,
+    };
+
     let config = ServerRuntimeConfig {
         bind_address: args.bind,
         cert_path: args.cert,
         key_path: args.key,
-        driver: DriverConfig { max_connections: args.max_connections, ..Default::default() },
+        client_ca_path: args.client_ca,
+        driver: DriverConfig {
+            max_connections: args.max_connections,
+            retention: RetentionConfig {
+                default_policy: RetentionPolicy {
+                    max_age: args
+                        .retention_days
+                        .and_then(|days| RetentionPolicy::keep_days(days).max_age),
+                    max_frames: args.retention_frames,
+                },
+                ..Default::default()
+            },
+            authenticator,
+            require_client_certificate: args.require_client_cert,
+            room_quota_bytes: args.room_quota_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+            webhooks: WebhookConfig { default_target, ..Default::default() },
+            chaos,
+            ..Default::default()
+        },
+        audit_log_path: args.audit_log,
+        ..Default::default()
+    };
+
+    let Some(path) = args.db else {
+        tracing::warn!("No database provided - rooms will not survive a restart");
+        return run(Server::bind(config)?).await;
+    };
+
+    tracing::info!("Using {:?} storage at {}", args.storage, path);
+    let open_error = |e| format!("failed to open database {path}: {e}");
+    let wal = args.wal.then(|| WalConfig::beside(&path));
+    let cold = match &args.cold_dir {
+        Some(dir) => {
+            tracing::info!("Offloading history older than {} frames to {}", args.hot_frames, dir);
+            let store = FsBlobStore::open(dir).map_err(|e| format!("failed to open {dir}: {e}"))?;
+            Some((store, TierConfig { hot_frames: args.hot_frames, ..Default::default() }))
+        },
+        None => None,
+    };
+    match args.storage {
+        StorageBackend::Sqlite => {
+            serve(config, SqliteStorage::open(&path).map_err(open_error)?, cold, wal).await
+        },
+        StorageBackend::Sled => {
+            serve(config, SledStorage::open(&path).map_err(open_error)?, cold, wal).await
+        },
+    }
+}
+
+/// Build the authenticator selected on the command line, if any.
+fn authenticator(
+    args: &Args,
+) -> Result<Option<Arc<dyn Authenticator>>, Box<dyn std::error::Error>> {
+    if let Some(path) = &args.auth_hmac_secret {
+        let secret = std::fs::read(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+        tracing::info!("Requiring HMAC-signed auth tokens");
+        return Ok(Some(Arc::new(HmacTokens::new(secret.trim_ascii()))));
+    }
+
+    if let (Some(path), Some(issuer), Some(audience)) =
+        (&args.oidc_jwks, &args.oidc_issuer, &args.oidc_audience)
+    {
+        let jwks = std::fs::read(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+        let oidc = OidcJwt::from_jwks(issuer.as_str(), audience.as_str(), &jwks)
+            .map_err(|e| format!("failed to load {path}: {e}"))?;
+        tracing::info!("Requiring JWTs issued by {}", issuer);
+        return Ok(Some(Arc::new(oidc)));
+    }
+
+    Ok(None)
+}
+
+/// Faults to inject between pipeline stages, if either chaos flag is set.
+fn chaos(args: &Args) -> Option<ChaosConfig> {
+    if args.chaos_delay_ms.is_none() && args.chaos_drop_per_mille.is_none() {
+        return None;
+    }
+    let fault = ChaosFault {
+        max_delay: Duration::from_millis(args.chaos_delay_ms.unwrap_or(0)),
+        drop_per_mille: args.chaos_drop_per_mille.unwrap_or(0),
     };
+    Some(ChaosConfig { persist: fault, broadcast: fault })
+}
+
+async fn serve<S: Storage>(
+    config: ServerRuntimeConfig,
+    storage: S,
+    cold: Option<(FsBlobStore, TierConfig)>,
+    wal: Option<WalConfig>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match cold {
+        Some((store, tiers)) => {
+            serve_logged(config, TieredStorage::new(storage, store, tiers), wal).await
+        },
+        None => serve_logged(config, storage, wal).await,
+    }
+}
 
-    let server = Server::bind(config)?;
+async fn serve_logged<S: Storage>(
+    config: ServerRuntimeConfig,
+    storage: S,
+    wal: Option<WalConfig>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(wal) = wal else {
+        return run(Server::bind_with_storage(config, storage)?).await;
+    };
+
+    tracing::info!("Using write-ahead log at {}", wal.path.display());
+    let storage = WalStorage::open(storage, &wal)
+        .map_err(|e| format!("failed to open write-ahead log {}: {e}", wal.path.display()))?;
+    run(Server::bind_with_storage(config, storage)?).await
+}
 
+async fn run<S: Storage>(server: Server<S>) -> Result<(), Box<dyn std::error::Error>> {
     tracing::info!("Server listening on {}", server.local_addr()?);
 
-    server.run().await?;
+    server
+        .run_until(async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                tracing::error!("Failed to listen for shutdown signal: {}", e);
+                std::future::pending::<()>().await;
+            }
+            tracing::info!("Shutdown requested, draining connections");
+        })
+        .await?;
 
     Ok(())
 }
@@ -0,0 +1,742 @@
+//! Persist and broadcast stages.
+//!
+//! Routing a frame used to write it to storage before its broadcast could
+//! leave, so every disk write sat on the path every frame takes. With
+//! pipelined persistence the driver instead emits each sequenced frame as a
+//! [`ServerAction::PersistFrame`] holding its broadcast, and the runtime
+//! splits the work into stages joined by bounded channels:
+//!
+//! - accept: the driver sequences the frame and queues the write while it is
+//!   still locked, so writes are queued in log order
+//! - persist: one task queues writes per room and runs them on the blocking
+//!   thread pool, one at a time within a room and side by side across rooms,
+//!   passing each stored frame's actions on
+//! - broadcast: one task executes those actions in the order they arrive
+//!
+//! Frame N goes out while frame N+1 is being written, each room's frames
+//! reach both storage and subscribers in log order, and a room whose writes
+//! are slow holds up only its own frames. A full channel holds up the stage
+//! before it, down to routing.
+//!
+//! With a [`ChaosConfig`](crate::ChaosConfig) set, each write is held up or
+//! dropped before it starts and before its actions are passed on. A dropped
+//! write is handled like a failed one.
+//!
+//! A failed write leaves the frames of that room queued behind it pointing
+//! past the end of the log, so they are dropped instead of written. The
+//! runtime then [settles](Pipeline::settle) the room with the driver
+//! locked, waiting until none of its frames is queued or being written, and
+//! only then reports
+//! [`ServerEvent::PersistFailed`](crate::ServerEvent::PersistFailed) with
+//! the dropped indices. The driver resequences the room from storage,
+//! starting again at the failed index, which no write can still claim.
+
+use std::collections::{HashMap, VecDeque};
+
+use lockframe_core::env::Environment;
+use lockframe_proto::Frame;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinSet,
+};
+
+use crate::{ServerAction, Storage, StorageError, chaos::Chaos};
+
+/// Default bound on frames waiting in each stage.
+pub(crate) const DEFAULT_PIPELINE_DEPTH: usize = 1024;
+
+/// A frame write that failed: room ID, log index, and error.
+pub(crate) type PersistFailure = (u128, u64, StorageError);
+
+/// Failed writes, and the frames dropped behind them.
+#[derive(Debug, Default)]
+struct Failures {
+    /// Room ID → the write that failed, until the room's log continues from
+    /// it again
+    rooms: HashMap<u128, Failure>,
+}
+
+#[derive(Debug)]
+struct Failure {
+    /// Log index of the failed write
+    at: u64,
+    /// Log indices of later frames not written, until reported
+    dropped: Vec<u64>,
+}
+
+impl Failures {
+    /// Whether a frame may be written. Frames queued behind a failed write
+    /// are noted as dropped instead.
+    ///
+    /// Indices past a failed one were handed out before the driver learnt of
+    /// the failure. Once it has, the room starts again at the failed index
+    /// and the failure is forgotten.
+    fn admit(&mut self, room_id: u128, log_index: u64) -> bool {
+        if let Some(failure) = self.rooms.get_mut(&room_id) {
+            if log_index > failure.at {
+                failure.dropped.push(log_index);
+                return false;
+            }
+            self.rooms.remove(&room_id);
+        }
+        true
+    }
+
+    fn fail(&mut self, room_id: u128, log_index: u64) {
+        self.rooms.insert(room_id, Failure { at: log_index, dropped: Vec::new() });
+    }
+
+    /// Frames of the room dropped since the last call.
+    fn take_dropped(&mut self, room_id: u128) -> Vec<u64> {
+        self.rooms
+            .get_mut(&room_id)
+            .map(|failure| std::mem::take(&mut failure.dropped))
+            .unwrap_or_default()
+    }
+}
+
+/// A frame to write, and the actions waiting for it.
+struct FrameJob {
+    room_id: u128,
+    log_index: u64,
+    frame: Frame,
+    then: Vec<ServerAction>,
+}
+
+/// Work for the persist stage.
+enum PersistJob {
+    Frame(FrameJob),
+    /// Signal once none of the room's frames queued before is waiting or
+    /// being written, with the indices dropped behind a failure
+    Settle {
+        room_id: u128,
+        done: oneshot::Sender<Vec<u64>>,
+    },
+    /// Flush storage, then signal once everything queued before has gone
+    /// out
+    Flush(oneshot::Sender<()>),
+}
+
+/// Work for the broadcast stage.
+pub(crate) enum BroadcastJob {
+    /// Actions of a stored frame
+    Actions(Vec<ServerAction>),
+    /// Signal once the actions queued before have been executed
+    Done(oneshot::Sender<()>),
+}
+
+/// A room's work waiting for its turn.
+enum RoomWork {
+    Frame(FrameJob),
+    Settle(oneshot::Sender<Vec<u64>>),
+}
+
+/// Work queued for one room.
+#[derive(Default)]
+struct RoomQueue {
+    waiting: VecDeque<RoomWork>,
+    /// One of the room's frames is being written
+    writing: bool,
+}
+
+/// A finished write.
+struct Written {
+    room_id: u128,
+    log_index: u64,
+    then: Vec<ServerAction>,
+    result: Result<(), StorageError>,
+    /// Whether chaos let the actions through
+    deliver: bool,
+}
+
+/// Accepting end of the pipeline.
+pub(crate) struct Pipeline {
+    persist: mpsc::Sender<PersistJob>,
+}
+
+impl Pipeline {
+    /// Start the persist stage writing to `storage`, with `depth` frames
+    /// allowed to wait in each stage and `chaos` injecting faults, if set.
+    ///
+    /// Returns the pipeline, the broadcast stage's queue for the runtime to
+    /// execute, and failed writes for the runtime to report to the driver.
+    pub(crate) fn spawn<S: Storage, E: Environment>(
+        storage: S,
+        depth: usize,
+        chaos: Option<Chaos<E>>,
+    ) -> (Self, mpsc::Receiver<BroadcastJob>, mpsc::UnboundedReceiver<PersistFailure>) {
+        let (persist, jobs) = mpsc::channel(depth);
+        let (broadcast, broadcasts) = mpsc::channel(depth);
+        let (failures, failed) = mpsc::unbounded_channel();
+        let stage = PersistStage {
+            storage,
+            chaos,
+            depth,
+            failures: Failures::default(),
+            rooms: HashMap::new(),
+            queued: 0,
+            writes: JoinSet::new(),
+            broadcast,
+            failed: failures,
+        };
+        tokio::spawn(stage.run(jobs));
+        (Self { persist }, broadcasts, failed)
+    }
+
+    /// Queue the frame writes among `actions`, returning the rest.
+    ///
+    /// Call this before releasing the driver so writes are queued in the
+    /// order the driver sequenced them. Waits while the persist stage is
+    /// full.
+    pub(crate) async fn submit(&self, actions: Vec<ServerAction>) -> Vec<ServerAction> {
+        let mut rest = Vec::with_capacity(actions.len());
+        for action in actions {
+            match action {
+                ServerAction::PersistFrame { room_id, log_index, frame, then } => {
+                    let job = PersistJob::Frame(FrameJob { room_id, log_index, frame, then });
+                    if self.persist.send(job).await.is_err() {
+                        tracing::error!("persist stage stopped, frame {} dropped", log_index);
+                    }
+                },
+                action => rest.push(action),
+            }
+        }
+        rest
+    }
+
+    /// Wait until none of the room's frames queued so far is waiting or
+    /// being written.
+    ///
+    /// Returns the log indices of the room's frames dropped behind a failed
+    /// write since the last call.
+    pub(crate) async fn settle(&self, room_id: u128) -> Vec<u64> {
+        let (done, settled) = oneshot::channel();
+        if self.persist.send(PersistJob::Settle { room_id, done }).await.is_err() {
+            return Vec::new();
+        }
+        settled.await.unwrap_or_default()
+    }
+
+    /// Wait until every frame queued so far is stored and flushed and its
+    /// actions have been executed.
+    pub(crate) async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.persist.send(PersistJob::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+/// Persist stage: queues writes per room and passes stored frames' actions
+/// on.
+struct PersistStage<S, E> {
+    storage: S,
+    chaos: Option<Chaos<E>>,
+    /// Most frames queued or being written at once
+    depth: usize,
+    failures: Failures,
+    /// Rooms with work queued or a write in flight
+    rooms: HashMap<u128, RoomQueue>,
+    /// Frames queued or being written, across rooms
+    queued: usize,
+    writes: JoinSet<Written>,
+    broadcast: mpsc::Sender<BroadcastJob>,
+    failed: mpsc::UnboundedSender<PersistFailure>,
+}
+
+impl<S: Storage, E: Environment> PersistStage<S, E> {
+    async fn run(mut self, mut jobs: mpsc::Receiver<PersistJob>) {
+        let mut flush = None;
+        let mut closed = false;
+        loop {
+            // A flush waits for everything queued before it
+            if self.rooms.is_empty()
+                && let Some(done) = flush.take()
+            {
+                if !self.flush(done).await {
+                    return;
+                }
+                continue;
+            }
+            if closed && self.rooms.is_empty() {
+                return;
+            }
+
+            let accepting = !closed && flush.is_none() && self.queued < self.depth;
+            tokio::select! {
+                job = jobs.recv(), if accepting => match job {
+                    Some(PersistJob::Frame(job)) => {
+                        self.queued += 1;
+                        self.enqueue(job.room_id, RoomWork::Frame(job));
+                    },
+                    Some(PersistJob::Settle { room_id, done }) => {
+                        self.enqueue(room_id, RoomWork::Settle(done));
+                    },
+                    Some(PersistJob::Flush(done)) => flush = Some(done),
+                    None => closed = true,
+                },
+                Some(written) = self.writes.join_next() => match written {
+                    Ok(written) => {
+                        if !self.written(written).await {
+                            return;
+                        }
+                    },
+                    Err(e) => tracing::error!("persist task failed: {}", e),
+                },
+                else => return,
+            }
+        }
+    }
+
+    fn enqueue(&mut self, room_id: u128, work: RoomWork) {
+        self.rooms.entry(room_id).or_default().waiting.push_back(work);
+        self.advance(room_id);
+    }
+
+    /// Unless one of the room's frames is being written, start writing the
+    /// next, answering the settles and dropping the frames ahead of it.
+    fn advance(&mut self, room_id: u128) {
+        while let Some(room) = self.rooms.get_mut(&room_id)
+            && !room.writing
+        {
+            match room.waiting.pop_front() {
+                None => {
+                    self.rooms.remove(&room_id);
+                },
+                Some(RoomWork::Settle(done)) => {
+                    let _ = done.send(self.failures.take_dropped(room_id));
+                },
+                Some(RoomWork::Frame(job)) if self.failures.admit(room_id, job.log_index) => {
+                    room.writing = true;
+                    self.writes.spawn(write(self.storage.clone(), self.chaos.clone(), job));
+                },
+                Some(RoomWork::Frame(job)) => {
+                    self.queued -= 1;
+                    tracing::debug!(
+                        "dropped frame {} of room {:032x} queued behind a failed write",
+                        job.log_index,
+                        room_id
+                    );
+                },
+            }
+        }
+    }
+
+    /// Pass a finished write's actions on, or report its failure, and start
+    /// the room's next write. Returns false once the broadcast stage has
+    /// stopped.
+    async fn written(&mut self, written: Written) -> bool {
+        let Written { room_id, log_index, then, result, deliver } = written;
+        self.queued -= 1;
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.writing = false;
+        }
+
+        let next = match result {
+            Ok(()) if deliver => Some(BroadcastJob::Actions(then)),
+            Ok(()) => {
+                tracing::debug!(
+                    "chaos dropped broadcast of frame {} of room {:032x}",
+                    log_index,
+                    room_id
+                );
+                None
+            },
+            Err(e) => {
+                self.failures.fail(room_id, log_index);
+                let _ = self.failed.send((room_id, log_index, e));
+                None
+            },
+        };
+        // The room's next frame is only passed on after this one, so each
+        // room's actions keep log order
+        self.advance(room_id);
+        match next {
+            Some(job) => self.broadcast.send(job).await.is_ok(),
+            None => true,
+        }
+    }
+
+    async fn flush(&self, done: oneshot::Sender<()>) -> bool {
+        let storage = self.storage.clone();
+        match tokio::task::spawn_blocking(move || storage.flush()).await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => tracing::error!("failed to flush storage: {}", e),
+            Err(e) => tracing::error!("flush task failed: {}", e),
+        }
+        self.broadcast.send(BroadcastJob::Done(done)).await.is_ok()
+    }
+}
+
+/// Write one frame off the async runtime, with chaos injected around it.
+async fn write<S: Storage, E: Environment>(
+    storage: S,
+    chaos: Option<Chaos<E>>,
+    job: FrameJob,
+) -> Written {
+    let FrameJob { room_id, log_index, frame, then } = job;
+    let lost = match &chaos {
+        Some(chaos) => !chaos.before_persist().await,
+        None => false,
+    };
+    let result = if lost {
+        Err(StorageError::Io("write dropped by chaos".to_string()))
+    } else {
+        tokio::task::spawn_blocking(move || storage.store_frame(room_id, log_index, &frame))
+            .await
+            .unwrap_or_else(|e| Err(StorageError::Io(format!("write task failed: {e}"))))
+    };
+    let deliver = match (&result, &chaos) {
+        (Ok(()), Some(chaos)) => chaos.before_broadcast().await,
+        _ => true,
+    };
+    Written { room_id, log_index, then, result, deliver }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, mpsc as std_mpsc};
+
+    use bytes::Bytes;
+    use lockframe_core::{env::test_utils::MockEnv, mls::MlsGroupState};
+    use lockframe_proto::{FrameHeader, Opcode};
+
+    use super::*;
+    use crate::{ChaosConfig, ChaosFault, MemoryStorage, storage::StoredRoomMetadata};
+
+    fn frame(room_id: u128, log_index: u64) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_log_index(log_index);
+        Frame::new(header, Bytes::new())
+    }
+
+    /// A frame write whose broadcast sends the frame to session 0.
+    fn persist(room_id: u128, log_index: u64) -> ServerAction {
+        let frame = frame(room_id, log_index);
+        ServerAction::PersistFrame {
+            room_id,
+            log_index,
+            frame: frame.clone(),
+            then: vec![ServerAction::SendToSession { session_id: 0, frame }],
+        }
+    }
+
+    /// Flush the pipeline, standing in for the broadcast stage. Returns the
+    /// room and log index of each frame broadcast meanwhile.
+    async fn flush(
+        pipeline: &Pipeline,
+        jobs: &mut mpsc::Receiver<BroadcastJob>,
+    ) -> Vec<(u128, u64)> {
+        let broadcast = async {
+            let mut sent = Vec::new();
+            while let Some(job) = jobs.recv().await {
+                match job {
+                    BroadcastJob::Actions(actions) => {
+                        for action in actions {
+                            if let ServerAction::SendToSession { frame, .. } = action {
+                                sent.push((frame.header.room_id(), frame.header.log_index()));
+                            }
+                        }
+                    },
+                    BroadcastJob::Done(done) => {
+                        let _ = done.send(());
+                        break;
+                    },
+                }
+            }
+            sent
+        };
+        tokio::join!(pipeline.flush(), broadcast).1
+    }
+
+    fn spawn(
+        storage: impl Storage,
+    ) -> (Pipeline, mpsc::Receiver<BroadcastJob>, mpsc::UnboundedReceiver<PersistFailure>) {
+        Pipeline::spawn::<_, MockEnv>(storage, DEFAULT_PIPELINE_DEPTH, None)
+    }
+
+    /// A room whose next write waits for a release.
+    type Held = (u128, std_mpsc::Receiver<()>);
+
+    /// Memory storage that can refuse a write once, or hold up a room's next
+    /// write until released.
+    #[derive(Clone, Default)]
+    struct TestStorage {
+        inner: MemoryStorage,
+        refuse: Arc<Mutex<Vec<(u128, u64)>>>,
+        hold: Arc<Mutex<Option<Held>>>,
+    }
+
+    impl TestStorage {
+        fn refuse_once(&self, room_id: u128, log_index: u64) {
+            self.refuse.lock().unwrap().push((room_id, log_index));
+        }
+
+        /// Hold up the room's next write until the returned sender is
+        /// dropped.
+        fn hold(&self, room_id: u128) -> std_mpsc::Sender<()> {
+            let (release, held) = std_mpsc::channel();
+            *self.hold.lock().unwrap() = Some((room_id, held));
+            release
+        }
+    }
+
+    impl Storage for TestStorage {
+        fn store_frame(
+            &self,
+            room_id: u128,
+            log_index: u64,
+            frame: &Frame,
+        ) -> Result<(), StorageError> {
+            let held = self.hold.lock().unwrap().take_if(|(room, _)| *room == room_id);
+            if let Some((_, held)) = held {
+                let _ = held.recv();
+            }
+            let mut refuse = self.refuse.lock().unwrap();
+            if let Some(at) = refuse.iter().position(|&write| write == (room_id, log_index)) {
+                refuse.remove(at);
+                return Err(StorageError::Io("refused".to_string()));
+            }
+            drop(refuse);
+            self.inner.store_frame(room_id, log_index, frame)
+        }
+
+        fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+            self.inner.latest_log_index(room_id)
+        }
+
+        fn load_frames(
+            &self,
+            room_id: u128,
+            from: u64,
+            limit: usize,
+        ) -> Result<Vec<Frame>, StorageError> {
+            self.inner.load_frames(room_id, from, limit)
+        }
+
+        fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+            self.inner.earliest_log_index(room_id)
+        }
+
+        fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+            self.inner.stored_bytes(room_id)
+        }
+
+        fn truncate_frames(
+            &self,
+            room_id: u128,
+            first_kept: u64,
+            tombstone: &Frame,
+        ) -> Result<(), StorageError> {
+            self.inner.truncate_frames(room_id, first_kept, tombstone)
+        }
+
+        fn replace_frame(
+            &self,
+            room_id: u128,
+            log_index: u64,
+            frame: &Frame,
+        ) -> Result<(), StorageError> {
+            self.inner.replace_frame(room_id, log_index, frame)
+        }
+
+        fn store_mls_state(
+            &self,
+            room_id: u128,
+            state: &MlsGroupState,
+        ) -> Result<(), StorageError> {
+            self.inner.store_mls_state(room_id, state)
+        }
+
+        fn load_mls_state(&self, room_id: u128) -> Result<Option<MlsGroupState>, StorageError> {
+            self.inner.load_mls_state(room_id)
+        }
+
+        fn store_group_info(
+            &self,
+            room_id: u128,
+            epoch: u64,
+            group_info: &[u8],
+        ) -> Result<(), StorageError> {
+            self.inner.store_group_info(room_id, epoch, group_info)
+        }
+
+        fn load_group_info(&self, room_id: u128) -> Result<Option<(u64, Vec<u8>)>, StorageError> {
+            self.inner.load_group_info(room_id)
+        }
+
+        fn list_rooms(&self) -> Result<Vec<u128>, StorageError> {
+            self.inner.list_rooms()
+        }
+
+        fn create_room(
+            &self,
+            room_id: u128,
+            metadata: &StoredRoomMetadata,
+        ) -> Result<(), StorageError> {
+            self.inner.create_room(room_id, metadata)
+        }
+
+        fn load_room_metadata(
+            &self,
+            room_id: u128,
+        ) -> Result<Option<StoredRoomMetadata>, StorageError> {
+            self.inner.load_room_metadata(room_id)
+        }
+
+        fn update_room_metadata(
+            &self,
+            room_id: u128,
+            metadata: &StoredRoomMetadata,
+        ) -> Result<(), StorageError> {
+            self.inner.update_room_metadata(room_id, metadata)
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_behind_a_failed_write_are_dropped() {
+        let storage = MemoryStorage::new();
+        let (pipeline, mut jobs, mut failures) = spawn(storage.clone());
+        let (room, other) = (1, 2);
+
+        // Index 2 does not follow the log, and 3 was queued behind it
+        let actions = vec![
+            persist(room, 0),
+            persist(other, 0),
+            persist(room, 2),
+            persist(room, 3),
+            persist(other, 1),
+        ];
+        assert!(pipeline.submit(actions).await.is_empty());
+        assert_eq!(pipeline.settle(room).await, vec![3]);
+        let mut sent = flush(&pipeline, &mut jobs).await;
+
+        let (failed_room, failed_at, _) = failures.try_recv().unwrap();
+        assert_eq!((failed_room, failed_at), (room, 2));
+        assert!(failures.try_recv().is_err());
+        sent.sort_unstable();
+        assert_eq!(sent, vec![(room, 0), (other, 0), (other, 1)]);
+
+        // Resequenced, the room continues from storage
+        pipeline.submit(vec![persist(room, 1), persist(room, 2)]).await;
+        assert_eq!(flush(&pipeline, &mut jobs).await, vec![(room, 1), (room, 2)]);
+        assert_eq!(storage.latest_log_index(room).unwrap(), Some(2));
+        assert_eq!(storage.latest_log_index(other).unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn a_lost_write_fails_like_a_refused_one() {
+        let storage = MemoryStorage::new();
+        let chaos = ChaosConfig {
+            persist: ChaosFault { drop_per_mille: 1000, ..Default::default() },
+            ..Default::default()
+        };
+        let (pipeline, _jobs, mut failures) = Pipeline::spawn(
+            storage.clone(),
+            DEFAULT_PIPELINE_DEPTH,
+            Some(Chaos::new(chaos, MockEnv::new())),
+        );
+
+        pipeline.submit(vec![persist(1, 0), persist(1, 1)]).await;
+        assert_eq!(pipeline.settle(1).await, vec![1]);
+        assert!(matches!(failures.try_recv(), Ok((1, 0, StorageError::Io(_)))));
+        assert_eq!(storage.latest_log_index(1).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn settling_waits_for_writes_in_flight_before_the_room_is_resequenced() {
+        let storage = TestStorage::default();
+        storage.refuse_once(1, 1);
+        let release = storage.hold(1);
+        let (pipeline, mut jobs, mut failures) = spawn(storage.clone());
+
+        // Frame 0 is held mid-write, so 1 fails and 2 is dropped only after
+        // the settle is queued
+        pipeline.submit(vec![persist(1, 0), persist(1, 1), persist(1, 2)]).await;
+        let settled = tokio::spawn(async move {
+            let dropped = pipeline.settle(1).await;
+            (pipeline, dropped)
+        });
+        tokio::task::yield_now().await;
+        assert!(!settled.is_finished());
+        drop(release);
+
+        let (pipeline, dropped) = settled.await.unwrap();
+        assert_eq!(dropped, vec![2]);
+        assert!(matches!(failures.try_recv(), Ok((1, 1, _))));
+
+        // Resequenced from storage, the room reuses the failed index
+        let next = storage.latest_log_index(1).unwrap().map_or(0, |latest| latest + 1);
+        assert_eq!(next, 1);
+        pipeline.submit(vec![persist(1, 1), persist(1, 2)]).await;
+        assert_eq!(flush(&pipeline, &mut jobs).await, vec![(1, 0), (1, 1), (1, 2)]);
+
+        let stored: Vec<_> =
+            storage.load_frames(1, 0, 10).unwrap().iter().map(|f| f.header.log_index()).collect();
+        assert_eq!(stored, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn a_held_up_room_does_not_hold_up_others() {
+        let storage = TestStorage::default();
+        let release = storage.hold(1);
+        let (pipeline, mut jobs, _failures) = spawn(storage.clone());
+
+        pipeline.submit(vec![persist(1, 0), persist(2, 0), persist(2, 1)]).await;
+        pipeline.settle(2).await;
+        assert_eq!(storage.latest_log_index(2).unwrap(), Some(1));
+        assert_eq!(storage.latest_log_index(1).unwrap(), None);
+
+        drop(release);
+        assert_eq!(flush(&pipeline, &mut jobs).await, vec![(2, 0), (2, 1), (1, 0)]);
+    }
+
+    #[tokio::test]
+    async fn chaos_keeps_each_room_log_contiguous() {
+        let fault = ChaosFault { drop_per_mille: 100, ..Default::default() };
+        let chaos = Chaos::new(ChaosConfig { persist: fault, broadcast: fault }, MockEnv::new());
+        let storage = MemoryStorage::new();
+        let (pipeline, mut jobs, mut failures) =
+            Pipeline::spawn(storage.clone(), DEFAULT_PIPELINE_DEPTH, Some(chaos));
+
+        // Sequence frames the way the driver does, resequencing a room from
+        // storage once its failure is reported
+        let mut next: HashMap<u128, u64> = HashMap::new();
+        let mut failed = 0;
+        for round in 0..300 {
+            let room = round % 3;
+            let log_index = next.entry(room).or_default();
+            pipeline.submit(vec![persist(room, *log_index)]).await;
+            *log_index += 1;
+
+            // Let a few frames queue up before waiting for failures
+            if round % 10 != 9 {
+                continue;
+            }
+            for room in 0..3 {
+                pipeline.settle(room).await;
+            }
+            while let Ok((room, at, _)) = failures.try_recv() {
+                pipeline.settle(room).await;
+                let resumed = storage.latest_log_index(room).unwrap().map_or(0, |i| i + 1);
+                assert_eq!(resumed, at, "room {room} must reuse the failed index");
+                next.insert(room, resumed);
+                failed += 1;
+            }
+        }
+        let _ = flush(&pipeline, &mut jobs).await;
+
+        assert!(failed > 0);
+        for room in 0..3 {
+            let stored: Vec<u64> = storage
+                .load_frames(room, 0, 1000)
+                .unwrap()
+                .iter()
+                .map(|frame| frame.header.log_index())
+                .collect();
+            assert!(!stored.is_empty());
+            assert!(stored.iter().copied().eq(0..stored.len() as u64), "room {room}: {stored:?}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Frame handling by room kind.
+//!
+//! Every room has a [`RoomKind`]. The policy table says, for each kind and
+//! opcode, whether a frame is persisted (sequenced, stored and broadcast),
+//! only routed to subscribers, or rejected, and lets the room's owner be
+//! treated differently from everyone else. Out of the box, ephemeral rooms
+//! relay messages without storing them and broadcast rooms take messages
+//! from their owner only. A routed `AppMessage` must be flagged ephemeral by
+//! its sender, or members would take it for a sequenced one.
+//!
+//! MLS control frames and `CloseRoom` are always persisted whatever the
+//! table says: members must agree on the group's history, and a room must
+//! stay closable.
+
+use std::collections::HashMap;
+
+use lockframe_proto::{
+    Opcode,
+    payloads::admin::{FrameHandling, FrameRule, RoomKind},
+};
+
+/// How each kind of room handles each opcode.
+#[derive(Debug, Clone)]
+pub(crate) struct PolicyTable {
+    rules: HashMap<(RoomKind, Opcode), FrameRule>,
+}
+
+impl Default for PolicyTable {
+    fn default() -> Self {
+        let mut rules = HashMap::new();
+        rules.insert(
+            (RoomKind::Ephemeral, Opcode::AppMessage),
+            FrameRule::all(FrameHandling::Route),
+        );
+        rules.insert((RoomKind::Broadcast, Opcode::AppMessage), FrameRule {
+            owner: FrameHandling::Persist,
+            others: FrameHandling::Reject,
+        });
+        Self { rules }
+    }
+}
+
+impl PolicyTable {
+    /// Set how rooms of `kind` handle `opcode`. `None` restores the default.
+    pub(crate) fn set_rule(&mut self, kind: RoomKind, opcode: Opcode, rule: Option<FrameRule>) {
+        match rule.or_else(|| Self::default().rules.get(&(kind, opcode)).copied()) {
+            Some(rule) => self.rules.insert((kind, opcode), rule),
+            None => self.rules.remove(&(kind, opcode)),
+        };
+    }
+
+    /// How a room of `kind` handles `opcode` from its owner or from anyone
+    /// else. Opcodes without a rule are persisted.
+    pub(crate) fn handling(&self, kind: RoomKind, opcode: Opcode, is_owner: bool) -> FrameHandling {
+        if opcode.is_mls() || opcode == Opcode::CloseRoom {
+            return FrameHandling::Persist;
+        }
+        self.rules
+            .get(&(kind, opcode))
+            .map_or(FrameHandling::Persist, |rule| if is_owner { rule.owner } else { rule.others })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_frames_are_always_persisted() {
+        let mut table = PolicyTable::default();
+        table.set_rule(
+            RoomKind::Standard,
+            Opcode::Commit,
+            Some(FrameRule::all(FrameHandling::Reject)),
+        );
+        table.set_rule(
+            RoomKind::Standard,
+            Opcode::CloseRoom,
+            Some(FrameRule::all(FrameHandling::Route)),
+        );
+
+        assert_eq!(
+            table.handling(RoomKind::Standard, Opcode::Commit, false),
+            FrameHandling::Persist
+        );
+        assert_eq!(
+            table.handling(RoomKind::Standard, Opcode::CloseRoom, true),
+            FrameHandling::Persist
+        );
+    }
+
+    #[test]
+    fn clearing_a_rule_restores_the_default() {
+        let mut table = PolicyTable::default();
+        table.set_rule(
+            RoomKind::Ephemeral,
+            Opcode::AppMessage,
+            Some(FrameRule::all(FrameHandling::Persist)),
+        );
+        assert_eq!(
+            table.handling(RoomKind::Ephemeral, Opcode::AppMessage, false),
+            FrameHandling::Persist
+        );
+
+        table.set_rule(RoomKind::Ephemeral, Opcode::AppMessage, None);
+        assert_eq!(
+            table.handling(RoomKind::Ephemeral, Opcode::AppMessage, false),
+            FrameHandling::Route
+        );
+    }
+}
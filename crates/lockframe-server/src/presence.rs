@@ -0,0 +1,130 @@
+//! Online status of users.
+//!
+//! A user is online while they have an authenticated session; the registry
+//! already keeps one session per user, so presence only needs to remember
+//! when each user was last seen and which rooms share presence. Rooms opt in
+//! through [`AdminRequest::SetPresence`] and then receive a `Presence` frame
+//! whenever one of their members connects or disconnects.
+//!
+//! Presence reveals when people are active, so it is off unless the server
+//! sets a [`PresenceVisibility`]. Last-seen times live in memory and are
+//! forgotten on restart.
+//!
+//! [`AdminRequest::SetPresence`]: lockframe_proto::payloads::admin::AdminRequest::SetPresence
+
+use std::collections::{HashMap, HashSet};
+
+use lockframe_proto::payloads::session::PresenceStatus;
+
+/// Most users answered in one `PresenceQuery`; the rest are ignored.
+pub(crate) const MAX_QUERY_USERS: usize = 256;
+
+/// Who may learn whether a user is online.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PresenceVisibility {
+    /// Presence is not shared. Queries are refused and rooms never receive
+    /// `Presence` frames.
+    #[default]
+    Off,
+    /// Users who share a room with the user.
+    RoomMembers,
+    /// Any authenticated session.
+    Everyone,
+}
+
+/// Presence settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PresenceConfig {
+    /// Who may see presence
+    pub visibility: PresenceVisibility,
+}
+
+/// Last-seen times and opted-in rooms.
+#[derive(Debug, Default)]
+pub(crate) struct Presence {
+    config: PresenceConfig,
+    /// User ID → Unix time their last session closed
+    last_seen: HashMap<u64, u64>,
+    /// Rooms that receive `Presence` frames
+    rooms: HashSet<u128>,
+}
+
+impl Presence {
+    pub(crate) fn new(config: PresenceConfig) -> Self {
+        Self { config, ..Self::default() }
+    }
+
+    pub(crate) fn set_config(&mut self, config: PresenceConfig) {
+        self.config = config;
+    }
+
+    pub(crate) fn visibility(&self) -> PresenceVisibility {
+        self.config.visibility
+    }
+
+    /// Opt a room in or out of presence changes.
+    pub(crate) fn set_room(&mut self, room_id: u128, enabled: bool) {
+        if enabled {
+            self.rooms.insert(room_id);
+        } else {
+            self.rooms.remove(&room_id);
+        }
+    }
+
+    /// Rooms that opted in.
+    pub(crate) fn rooms(&self) -> impl Iterator<Item = u128> + '_ {
+        self.rooms.iter().copied()
+    }
+
+    /// Whether a room receives presence changes. Always `false` while
+    /// presence is off.
+    pub(crate) fn shares_with(&self, room_id: u128) -> bool {
+        self.config.visibility != PresenceVisibility::Off && self.rooms.contains(&room_id)
+    }
+
+    /// Record a user's session closing.
+    pub(crate) fn went_offline(&mut self, user_id: u64, now_secs: u64) -> PresenceStatus {
+        self.last_seen.insert(user_id, now_secs);
+        PresenceStatus { user_id, online: false, last_seen_secs: Some(now_secs) }
+    }
+
+    /// Status of a user, given whether they have a session now.
+    pub(crate) fn status(&self, user_id: u64, online: bool) -> PresenceStatus {
+        let last_seen_secs = if online { None } else { self.last_seen.get(&user_id).copied() };
+        PresenceStatus { user_id, online, last_seen_secs }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rooms_only_share_presence_when_enabled() {
+        let mut presence = Presence::default();
+        presence.set_room(1, true);
+        assert!(!presence.shares_with(1));
+
+        let mut presence =
+            Presence::new(PresenceConfig { visibility: PresenceVisibility::RoomMembers });
+        presence.set_room(1, true);
+        assert!(presence.shares_with(1));
+        presence.set_room(1, false);
+        assert!(!presence.shares_with(1));
+    }
+
+    #[test]
+    fn last_seen_is_kept_until_the_user_returns() {
+        let mut presence =
+            Presence::new(PresenceConfig { visibility: PresenceVisibility::Everyone });
+        assert_eq!(presence.status(7, false).last_seen_secs, None);
+
+        presence.went_offline(7, 1_000);
+        assert_eq!(presence.status(7, false).last_seen_secs, Some(1_000));
+        assert_eq!(presence.status(7, true), PresenceStatus {
+            user_id: 7,
+            online: true,
+            last_seen_secs: None
+        });
+    }
+}
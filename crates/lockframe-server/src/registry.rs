@@ -9,6 +9,8 @@
 
 use std::collections::{HashMap, HashSet};
 
+use crate::auth::PeerIdentity;
+
 /// Information about a registered session.
 #[derive(Debug, Clone)]
 pub struct SessionInfo {
@@ -16,6 +18,10 @@ pub struct SessionInfo {
     pub user_id: Option<u64>,
     /// Whether the session has completed handshake
     pub authenticated: bool,
+    /// Whether the session presented the admin token in its Hello
+    pub admin: bool,
+    /// TLS client certificate the connection presented
+    pub peer_identity: Option<PeerIdentity>,
 }
 
 impl Default for SessionInfo {
@@ -27,12 +33,12 @@ impl Default for SessionInfo {
 impl SessionInfo {
     /// Create a new unauthenticated session info.
     pub fn new() -> Self {
-        Self { user_id: None, authenticated: false }
+        Self { user_id: None, authenticated: false, admin: false, peer_identity: None }
     }
 
     /// Create an authenticated session info with user ID.
     pub fn authenticated(user_id: u64) -> Self {
-        Self { user_id: Some(user_id), authenticated: true }
+        Self { user_id: Some(user_id), authenticated: true, admin: false, peer_identity: None }
     }
 }
 
@@ -215,6 +221,16 @@ impl ConnectionRegistry {
         self.sessions.len()
     }
 
+    /// Number of sessions that completed the handshake.
+    pub fn authenticated_count(&self) -> usize {
+        self.sessions.values().filter(|info| info.authenticated).count()
+    }
+
+    /// Sessions that completed the handshake, in no particular order.
+    pub fn authenticated_sessions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.sessions.iter().filter(|(_, info)| info.authenticated).map(|(&id, _)| id)
+    }
+
     /// Number of sessions subscribed to a room.
     pub fn room_session_count(&self, room_id: u128) -> usize {
         self.room_subscriptions.get(&room_id).map_or(0, HashSet::len)
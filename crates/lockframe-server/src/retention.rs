@@ -0,0 +1,274 @@
+//! Storage retention and compaction.
+//!
+//! Rooms keep their whole log unless a [`RetentionPolicy`] bounds it by age,
+//! by frame count, or both. The driver runs a compaction pass on `Tick` once
+//! every [`RetentionConfig::compaction_interval`]; each pass truncates rooms
+//! whose history exceeds their policy and leaves a `HistoryTruncated`
+//! tombstone in place of the removed frames.
+//!
+//! Frames carry no server-side arrival time, so age is tracked with
+//! checkpoints: each pass records the room's next log index against the
+//! current instant. Every frame below a checkpoint's index was stored before
+//! that checkpoint, so once the checkpoint is older than `max_age` those
+//! frames are too. Checkpoints live in memory; after a restart history is
+//! kept until new checkpoints age, so frames are never removed early.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Sub,
+    time::Duration,
+};
+
+use lockframe_proto::{Frame, FrameHeader, Opcode, Payload, payloads::session::HistoryTruncated};
+
+use crate::storage::{Storage, StorageError};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How much history a room keeps.
+///
+/// When both limits are set the stricter one wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RetentionPolicy {
+    /// Drop frames older than this.
+    pub max_age: Option<Duration>,
+    /// Keep at most this many of the most recent frames.
+    pub max_frames: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Keep all history.
+    pub const FOREVER: Self = Self { max_age: None, max_frames: None };
+
+    /// Keep frames from the last `days` days.
+    pub fn keep_days(days: u64) -> Self {
+        Self {
+            max_age: Some(Duration::from_secs(days.saturating_mul(SECS_PER_DAY))),
+            max_frames: None,
+        }
+    }
+
+    /// Keep the most recent `frames` frames.
+    pub fn keep_frames(frames: u64) -> Self {
+        Self { max_age: None, max_frames: Some(frames) }
+    }
+
+    /// Whether this policy never removes anything.
+    pub fn is_forever(&self) -> bool {
+        self.max_age.is_none() && self.max_frames.is_none()
+    }
+}
+
+/// Retention settings for the server driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionConfig {
+    /// Policy for rooms without an override.
+    pub default_policy: RetentionPolicy,
+    /// Minimum time between compaction passes.
+    pub compaction_interval: Duration,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            default_policy: RetentionPolicy::FOREVER,
+            compaction_interval: Duration::from_hours(1),
+        }
+    }
+}
+
+/// Per-room retention state owned by the driver.
+pub(crate) struct Retention<I> {
+    config: RetentionConfig,
+    overrides: HashMap<u128, RetentionPolicy>,
+    /// (instant, next `log_index` at that instant), oldest first
+    checkpoints: HashMap<u128, VecDeque<(I, u64)>>,
+    last_pass: Option<I>,
+}
+
+impl<I> Retention<I>
+where
+    I: Copy + Ord + Sub<Output = Duration>,
+{
+    pub(crate) fn new(config: RetentionConfig) -> Self {
+        Self { config, overrides: HashMap::new(), checkpoints: HashMap::new(), last_pass: None }
+    }
+
+    /// Replace the default policy and compaction interval. Per-room
+    /// overrides are kept.
+    pub(crate) fn set_config(&mut self, config: RetentionConfig) {
+        self.config = config;
+    }
+
+    pub(crate) fn policy(&self, room_id: u128) -> RetentionPolicy {
+        self.overrides.get(&room_id).copied().unwrap_or(self.config.default_policy)
+    }
+
+    pub(crate) fn set_policy(&mut self, room_id: u128, policy: RetentionPolicy) {
+        self.overrides.insert(room_id, policy);
+    }
+
+    /// Whether a compaction pass is due. Marks the pass as started.
+    pub(crate) fn start_pass(&mut self, now: I) -> bool {
+        let due = self.last_pass.is_none_or(|last| now - last >= self.config.compaction_interval);
+        if due {
+            self.last_pass = Some(now);
+        }
+        due
+    }
+
+    /// Apply the room's policy, returning the new first kept index if the
+    /// room was truncated.
+    pub(crate) fn compact_room(
+        &mut self,
+        room_id: u128,
+        now: I,
+        storage: &impl Storage,
+    ) -> Result<Option<u64>, StorageError> {
+        let policy = self.policy(room_id);
+        let Some(latest) = storage.latest_log_index(room_id)? else {
+            return Ok(None);
+        };
+        let next_index = latest + 1;
+
+        let mut first_kept = 0;
+        if let Some(max_age) = policy.max_age {
+            let checkpoints = self.checkpoints.entry(room_id).or_default();
+            if checkpoints.back().is_none_or(|&(_, index)| index < next_index) {
+                checkpoints.push_back((now, next_index));
+            }
+            // Everything below the newest expired checkpoint is older than
+            // `max_age`; earlier checkpoints are subsumed by it
+            while let Some(&(at, index)) = checkpoints.front()
+                && now - at >= max_age
+            {
+                first_kept = first_kept.max(index);
+                checkpoints.pop_front();
+            }
+        } else {
+            self.checkpoints.remove(&room_id);
+        }
+        if let Some(max_frames) = policy.max_frames {
+            first_kept = first_kept.max(next_index.saturating_sub(max_frames));
+        }
+
+        // The tombstone occupies `first_kept - 1`; nothing to do unless it
+        // would replace at least one frame besides an existing tombstone
+        let earliest = storage.earliest_log_index(room_id)?.unwrap_or(0);
+        if first_kept == 0 || first_kept - 1 <= earliest {
+            return Ok(None);
+        }
+
+        storage.truncate_frames(room_id, first_kept, &tombstone_frame(room_id, first_kept))?;
+        Ok(Some(first_kept))
+    }
+}
+
+/// Build the frame that stands in for history before `first_kept`.
+pub(crate) fn tombstone_frame(room_id: u128, first_kept: u64) -> Frame {
+    let mut header = FrameHeader::new(Opcode::HistoryTruncated);
+    header.set_room_id(room_id);
+    header.set_log_index(first_kept - 1);
+
+    let payload = HistoryTruncated { first_log_index: first_kept };
+    // CBOR encoding of a single integer field into a Vec cannot fail
+    #[allow(clippy::expect_used)]
+    Payload::HistoryTruncated(payload).into_frame(header).expect("invariant: tombstone encodes")
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_core::env::test_utils::VirtualInstant;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const DAY: Duration = Duration::from_secs(SECS_PER_DAY);
+
+    fn at(secs: u64) -> VirtualInstant {
+        VirtualInstant::from_duration(Duration::from_secs(secs))
+    }
+
+    fn append(storage: &MemoryStorage, room_id: u128, count: u64) {
+        let start = storage.latest_log_index(room_id).unwrap().map_or(0, |latest| latest + 1);
+        for log_index in start..start + count {
+            let mut header = FrameHeader::new(Opcode::AppMessage);
+            header.set_room_id(room_id);
+            header.set_log_index(log_index);
+            storage.store_frame(room_id, log_index, &Frame::new(header, Bytes::new())).unwrap();
+        }
+    }
+
+    fn first_frame(storage: &MemoryStorage, room_id: u128) -> Frame {
+        storage.load_frames(room_id, 0, 1).unwrap().remove(0)
+    }
+
+    #[test]
+    fn forever_keeps_everything() {
+        let storage = MemoryStorage::new();
+        let mut retention = Retention::new(RetentionConfig::default());
+        append(&storage, 1, 100);
+
+        assert_eq!(retention.compact_room(1, at(0), &storage).unwrap(), None);
+        assert_eq!(retention.compact_room(1, at(365 * SECS_PER_DAY), &storage).unwrap(), None);
+        assert_eq!(storage.earliest_log_index(1).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn frame_limit_truncates_to_most_recent() {
+        let storage = MemoryStorage::new();
+        let mut retention = Retention::new(RetentionConfig::default());
+        retention.set_policy(1, RetentionPolicy::keep_frames(10));
+        append(&storage, 1, 100);
+
+        assert_eq!(retention.compact_room(1, at(0), &storage).unwrap(), Some(90));
+
+        let tombstone = first_frame(&storage, 1);
+        assert_eq!(tombstone.header.opcode_enum(), Some(Opcode::HistoryTruncated));
+        assert_eq!(tombstone.header.log_index(), 89);
+        assert_eq!(
+            Payload::decode(Opcode::HistoryTruncated, &tombstone.payload).unwrap(),
+            Payload::HistoryTruncated(HistoryTruncated { first_log_index: 90 })
+        );
+        assert_eq!(storage.latest_log_index(1).unwrap(), Some(99));
+
+        // Already within the limit
+        assert_eq!(retention.compact_room(1, at(1), &storage).unwrap(), None);
+    }
+
+    #[test]
+    fn age_limit_waits_for_checkpoint_to_expire() {
+        let storage = MemoryStorage::new();
+        let config =
+            RetentionConfig { default_policy: RetentionPolicy::keep_days(7), ..Default::default() };
+        let mut retention = Retention::new(config);
+
+        append(&storage, 1, 50);
+        assert_eq!(retention.compact_room(1, at(0), &storage).unwrap(), None);
+
+        append(&storage, 1, 50);
+        assert_eq!(retention.compact_room(1, at(3 * SECS_PER_DAY), &storage).unwrap(), None);
+
+        // Frames from before the first checkpoint are now a week old
+        let now = at(0) + 7 * DAY;
+        assert_eq!(retention.compact_room(1, now, &storage).unwrap(), Some(50));
+        assert_eq!(first_frame(&storage, 1).header.log_index(), 49);
+
+        let now = at(3 * SECS_PER_DAY) + 7 * DAY;
+        assert_eq!(retention.compact_room(1, now, &storage).unwrap(), Some(100));
+        assert_eq!(storage.load_frames(1, 0, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn passes_respect_interval() {
+        let mut retention = Retention::new(RetentionConfig {
+            compaction_interval: Duration::from_mins(1),
+            ..Default::default()
+        });
+
+        assert!(retention.start_pass(at(0)));
+        assert!(!retention.start_pass(at(59)));
+        assert!(retention.start_pass(at(60)));
+    }
+}
@@ -5,35 +5,81 @@
 //! Clients own the MLS group state; the server just sequences and broadcasts.
 //!
 //! Rooms must be explicitly created (no lazy creation) to prevent accidental
-//! rooms and enable future auth. `RoomMetadata` is an extension point for
-//! permissions/roles.
-
-use std::collections::HashMap;
+//! rooms. Each room carries a [`RoomAcl`]; frames from senders it does not
+//! admit are rejected before they are sequenced.
+//!
+//! An owner closes a room for good with a `CloseRoom` frame. The frame is
+//! sequenced like any other and becomes the room's tombstone: later frames
+//! are refused with [`RoomError::RoomClosed`], while sync keeps working so
+//! members can still read history and learn that the room is gone.
+//!
+//! With a quota set (see [`RoomManager::set_room_quota`]), each room may store
+//! a bounded number of bytes. Application frames that would exceed it are
+//! refused with [`RoomError::QuotaExceeded`], and [`RoomAction::QuotaWarning`]
+//! is returned once a room passes 80% of its quota. MLS control frames and
+//! `CloseRoom` are never refused, so a full room can still be managed.
+//!
+//! Each room has a [`RoomKind`] chosen at creation. A policy table (see the
+//! `policy` module and [`RoomManager::set_frame_rule`]) decides per kind and
+//! opcode whether frames are persisted, only routed to subscribers, or
+//! refused with [`RoomError::AccessDenied`]. Routed frames get no log index,
+//! so an `AppMessage` is only routed if its sender flagged it ephemeral.
+//!
+//! Rooms are partitioned across independent shards by a hash of the room ID
+//! (see the `shard` module). [`RoomManager::process_batch`] splits a batch of
+//! frames by shard and sequences the shards in parallel.
 
 use lockframe_core::env::Environment;
-use lockframe_proto::Frame;
+use lockframe_proto::{
+    Frame, Opcode,
+    payloads::{
+        admin::{FrameRule, RoomKind},
+        session::SyncRequest,
+    },
+};
 
 use crate::{
-    sequencer::{Sequencer, SequencerAction, SequencerError},
+    acl::{Denial, RoomAcl},
+    sequencer::SequencerError,
+    shard::{FrameResult, RoomShard},
     storage::{Storage, StorageError, StoredRoomMetadata},
+    sync,
 };
 
-/// Metadata about a room (extension point for future authorization)
+/// Metadata about a room
 #[derive(Debug, Clone)]
 pub struct RoomMetadata {
     /// User who created the room
     pub creator: u64, // UserId
     /// Unix timestamp (seconds since epoch) when room was created.
     pub created_at_secs: u64,
-    // Future: admins, members, permissions
+    /// Members, roles and bans
+    pub acl: RoomAcl,
+    /// Log index of the `CloseRoom` tombstone, once closed
+    pub closed_at: Option<u64>,
+    /// Name the room is listed under in the room directory, if listed
+    pub listing: Option<String>,
+    /// Kind of room, which decides how its frames are handled
+    pub kind: RoomKind,
+}
+
+impl RoomMetadata {
+    pub(crate) fn to_stored(&self) -> StoredRoomMetadata {
+        StoredRoomMetadata {
+            creator: self.creator,
+            created_at_secs: self.created_at_secs,
+            acl: self.acl.clone(),
+            closed_at: self.closed_at,
+            listing: self.listing.clone(),
+            kind: self.kind,
+        }
+    }
 }
 
 /// Routes frames between clients, assigns log indices.
 pub struct RoomManager {
-    /// Frame sequencer (assigns log indices)
-    sequencer: Sequencer,
-    /// Room metadata (for future authorization)
-    room_metadata: HashMap<u128, RoomMetadata>,
+    /// Room partitions, indexed by [`RoomManager::shard_of`]
+    shards: Vec<RoomShard>,
 }
 
 /// Actions returned by `RoomManager` for driver to execute.
@@ -75,6 +121,31 @@ pub enum RoomAction<I = std::time::Instant> {
         processed_at: I,
     },
 
+    /// Frame repeats an earlier send with the same idempotency key; reply to
+    /// the sender only, with the original from the log
+    Duplicate {
+        /// Room ID
+        room_id: u128,
+        /// Log index of the original
+        log_index: u64,
+        /// The original sequenced frame
+        frame: Frame,
+        /// When the retry was processed
+        processed_at: I,
+    },
+
+    /// Room just passed the warning threshold of its storage quota
+    QuotaWarning {
+        /// Room ID
+        room_id: u128,
+        /// Bytes the room now stores
+        used_bytes: u64,
+        /// The room's quota
+        limit_bytes: u64,
+        /// When the frame that crossed the threshold was processed
+        processed_at: I,
+    },
+
     /// Send sync response to client
     SendSyncResponse {
         /// Sender to reply to
@@ -85,6 +156,8 @@ pub enum RoomAction<I = std::time::Instant> {
         frames: Vec<Vec<u8>>,
         /// Whether more frames are available
         has_more: bool,
+        /// Where the next request should start, for filtered requests
+        next_log_index: Option<u64>,
         /// When the response was prepared
         processed_at: I,
     },
@@ -108,21 +181,114 @@ pub enum RoomError {
     /// Room already exists
     #[error("Room already exists: {0:032x}")]
     RoomAlreadyExists(u128),
+
+    /// Room was closed by its owner
+    #[error("Room closed: {0:032x}")]
+    RoomClosed(u128),
+
+    /// Frame would take the room past its storage quota
+    #[error("Room {room_id:032x} storage quota exceeded: {used_bytes} of {limit_bytes} bytes used")]
+    QuotaExceeded {
+        /// Room the frame targeted
+        room_id: u128,
+        /// Bytes the room stores
+        used_bytes: u64,
+        /// The room's quota
+        limit_bytes: u64,
+    },
+
+    /// Sender is not allowed to perform the operation
+    #[error("Access denied in room {room_id:032x}: {reason}")]
+    AccessDenied {
+        /// Room the frame targeted
+        room_id: u128,
+        /// Why the sender was refused
+        reason: Denial,
+    },
 }
 
 impl RoomManager {
-    /// Create a new `RoomManager`
+    /// Create a new `RoomManager` with a single shard
     pub fn new() -> Self {
-        Self { sequencer: Sequencer::new(), room_metadata: HashMap::new() }
+        Self::with_shards(1)
+    }
+
+    /// Create a `RoomManager` that partitions rooms across `count` shards
+    /// (at least one).
+    pub fn with_shards(count: usize) -> Self {
+        Self { shards: (0..count.max(1)).map(|_| RoomShard::default()).collect() }
+    }
+
+    /// Limit the bytes of history any one room may store. `None` removes
+    /// the limit.
+    pub fn set_room_quota(&mut self, max_bytes: Option<u64>) {
+        for shard in &mut self.shards {
+            shard.set_quota(max_bytes);
+        }
+    }
+
+    /// Set how rooms of `kind` handle `opcode`, for every room of that kind.
+    /// `None` restores the default. MLS control frames and `CloseRoom` are
+    /// always persisted regardless.
+    pub fn set_frame_rule(&mut self, kind: RoomKind, opcode: Opcode, rule: Option<FrameRule>) {
+        for shard in &mut self.shards {
+            shard.set_frame_rule(kind, opcode, rule);
+        }
+    }
+
+    /// Recount a room's stored bytes before its next frame, after storage
+    /// changed behind the room manager's back (e.g. history was truncated).
+    pub fn forget_room_usage(&mut self, room_id: u128) {
+        self.shard_mut(room_id).forget_usage(room_id);
+    }
+
+    /// Number of shards
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Shard that owns a room.
+    ///
+    /// Stable for a given shard count, so a room never moves between shards
+    /// while the server runs.
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "folding the room ID and reducing the hash are meant to truncate"
+    )]
+    pub fn shard_of(&self, room_id: u128) -> usize {
+        // Room IDs are random, but fold both halves and mix so that
+        // structured IDs still spread evenly
+        let folded = (room_id as u64) ^ ((room_id >> 64) as u64);
+        let mixed = folded.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+        (mixed % self.shards.len() as u64) as usize
+    }
+
+    fn shard(&self, room_id: u128) -> &RoomShard {
+        &self.shards[self.shard_of(room_id)]
+    }
+
+    fn shard_mut(&mut self, room_id: u128) -> &mut RoomShard {
+        let index = self.shard_of(room_id);
+        &mut self.shards[index]
     }
 
     /// Check if a room exists
     pub fn has_room(&self, room_id: u128) -> bool {
-        self.room_metadata.contains_key(&room_id)
+        self.shard(room_id).has_room(room_id)
     }
 
-    /// Creates a room with the specified ID and records the creator for
-    /// future authorization checks. Prevents duplicate room creation.
+    /// IDs of all known rooms
+    pub fn room_ids(&self) -> impl Iterator<Item = u128> + '_ {
+        self.shards.iter().flat_map(RoomShard::room_ids)
+    }
+
+    /// Number of known rooms
+    pub fn room_count(&self) -> usize {
+        self.shards.iter().map(RoomShard::room_count).sum()
+    }
+
+    /// Creates a standard room with the specified ID, owned by `creator`.
+    /// Prevents duplicate room creation.
     ///
     /// Persists room metadata to storage first, then updates in-memory state.
     /// The storage persistence is idempotent (won't overwrite existing rooms).
@@ -132,21 +298,117 @@ impl RoomManager {
         creator: u64,
         env: &impl Environment,
         storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        self.create_room_with_kind(room_id, creator, RoomKind::Standard, env, storage)
+    }
+
+    /// Creates a room of the given kind. See [`Self::create_room`].
+    pub fn create_room_with_kind(
+        &mut self,
+        room_id: u128,
+        creator: u64,
+        kind: RoomKind,
+        env: &impl Environment,
+        storage: &impl Storage,
     ) -> Result<(), RoomError> {
         if self.has_room(room_id) {
             return Err(RoomError::RoomAlreadyExists(room_id));
         }
 
         let created_at_secs = env.wall_clock_secs();
-        let stored_metadata = StoredRoomMetadata { creator, created_at_secs };
-        storage.create_room(room_id, &stored_metadata)?;
+        let metadata = RoomMetadata {
+            creator,
+            created_at_secs,
+            acl: RoomAcl::with_owner(creator),
+            closed_at: None,
+            listing: None,
+            kind,
+        };
+        storage.create_room(room_id, &metadata.to_stored())?;
 
-        let metadata = RoomMetadata { creator, created_at_secs };
-        self.room_metadata.insert(room_id, metadata);
+        self.shard_mut(room_id).insert_room(room_id, metadata);
 
         Ok(())
     }
 
+    /// Metadata of a room, or `None` if the room does not exist.
+    pub fn metadata(&self, room_id: u128) -> Option<&RoomMetadata> {
+        self.shard(room_id).metadata(room_id)
+    }
+
+    /// Stop routing a room, dropping its metadata and sequencer state.
+    ///
+    /// Storage is left untouched, so the room's history stays readable and
+    /// the room is recovered again on restart.
+    ///
+    /// # Errors
+    ///
+    /// - `RoomError::RoomNotFound` if the room doesn't exist
+    pub fn close_room(&mut self, room_id: u128) -> Result<(), RoomError> {
+        self.shard_mut(room_id).remove_room(room_id)
+    }
+
+    /// Membership of a room, or `None` if the room does not exist.
+    pub fn acl(&self, room_id: u128) -> Option<&RoomAcl> {
+        self.metadata(room_id).map(|metadata| &metadata.acl)
+    }
+
+    /// Admit `user_id` to a room on behalf of `inviter`, who must be a
+    /// member. Used when routing a Welcome.
+    ///
+    /// # Errors
+    ///
+    /// - `RoomError::RoomNotFound` if the room doesn't exist
+    /// - `RoomError::AccessDenied` if `inviter` is not a member or `user_id` is
+    ///   banned
+    /// - `RoomError::Storage` if the updated membership cannot be persisted
+    pub fn admit_member(
+        &mut self,
+        room_id: u128,
+        inviter: u64,
+        user_id: u64,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        let acl = self.acl(room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        let change = acl
+            .authorize_welcome(inviter, user_id)
+            .map_err(|reason| RoomError::AccessDenied { room_id, reason })?;
+        self.shard_mut(room_id).apply_acl_change(room_id, change, storage)
+    }
+
+    /// List a room in the room directory under `name`, or remove its
+    /// listing with `None`. The caller checks the sender may do so.
+    ///
+    /// # Errors
+    ///
+    /// - `RoomError::RoomNotFound` if the room doesn't exist
+    /// - `RoomError::RoomClosed` if the room was closed
+    /// - `RoomError::Storage` if the listing cannot be persisted
+    pub fn set_listing(
+        &mut self,
+        room_id: u128,
+        name: Option<String>,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        self.shard_mut(room_id).set_listing(room_id, name, storage)
+    }
+
+    /// Change the kind of a room, and so how its later frames are handled.
+    ///
+    /// # Errors
+    ///
+    /// - `RoomError::RoomNotFound` if the room doesn't exist
+    /// - `RoomError::RoomClosed` if the room was closed
+    /// - `RoomError::Storage` if the kind cannot be persisted
+    pub fn set_room_kind(
+        &mut self,
+        room_id: u128,
+        kind: RoomKind,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        self.shard_mut(room_id).set_kind(room_id, kind, storage)
+    }
+
     /// Handle a sync request from a client.
     ///
     /// Loads frames from storage starting at `from_log_index` and returns
@@ -159,12 +421,43 @@ impl RoomManager {
         limit: usize,
         now: I,
         storage: &impl Storage,
+    ) -> Result<RoomAction<I>, RoomError> {
+        let request = SyncRequest::new(from_log_index, limit as u64);
+        self.handle_filtered_sync_request(room_id, sender_id, &request, now, storage)
+    }
+
+    /// Handle a sync request that may carry filters.
+    ///
+    /// Unfiltered requests behave like [`Self::handle_sync_request`].
+    /// Filtered ones scan a bounded stretch of the log and report where the
+    /// scan stopped in `next_log_index`; see the `sync` module.
+    pub fn handle_filtered_sync_request<I: Copy>(
+        &self,
+        room_id: u128,
+        sender_id: u64,
+        request: &SyncRequest,
+        now: I,
+        storage: &impl Storage,
     ) -> Result<RoomAction<I>, RoomError> {
         if !self.has_room(room_id) {
             return Err(RoomError::RoomNotFound(room_id));
         }
 
-        let frames = storage.load_frames(room_id, from_log_index, limit)?;
+        let from_log_index = request.from_log_index;
+        let limit = usize::try_from(request.limit).unwrap_or(usize::MAX);
+        let closed_at = self.metadata(room_id).and_then(|metadata| metadata.closed_at);
+        let (frames, next_log_index) = if let Some(closed_at) = closed_at
+            && from_log_index > closed_at
+        {
+            // Past the end of a closed room's log, so answer with the
+            // tombstone itself and clients still learn the room is gone
+            (storage.load_frames(room_id, closed_at, 1)?, None)
+        } else if request.is_filtered() {
+            let filtered = sync::load_filtered(room_id, request, limit, storage)?;
+            (filtered.frames, Some(filtered.next_log_index))
+        } else {
+            (storage.load_frames(room_id, from_log_index, limit)?, None)
+        };
 
         let frame_bytes: Vec<Vec<u8>> = frames
             .iter()
@@ -177,10 +470,13 @@ impl RoomManager {
             .collect();
 
         let latest_index = storage.latest_log_index(room_id)?;
-        let last_loaded_index = if frames.is_empty() {
-            from_log_index.saturating_sub(1)
-        } else {
-            from_log_index + frames.len() as u64 - 1
+        // A tombstone can stand in for frames below `from_log_index`, so take
+        // the index from the last frame rather than counting from the start
+        let last_loaded_index = match next_log_index {
+            Some(next) => next.saturating_sub(1),
+            None => {
+                frames.last().map_or(from_log_index.saturating_sub(1), |f| f.header.log_index())
+            },
         };
         let has_more = latest_index.is_some_and(|latest| last_loaded_index < latest);
 
@@ -189,20 +485,23 @@ impl RoomManager {
             room_id,
             frames: frame_bytes,
             has_more,
+            next_log_index,
             processed_at: now,
         })
     }
 
-    /// Delegates to [`Sequencer::clear_room`] for recovery from storage
-    /// conflicts.
+    /// Delegates to [`Sequencer::clear_room`](crate::Sequencer::clear_room)
+    /// for recovery from storage conflicts.
     pub fn clear_room_sequencer(&mut self, room_id: u128) -> bool {
-        self.sequencer.clear_room(room_id)
+        self.shard_mut(room_id).clear_sequencer(room_id)
     }
 
     /// Recover a room from storage during server startup.
     ///
     /// Loads room metadata from the ROOMS table, then initializes
-    /// the sequencer with the correct `next_log_index` from frames.
+    /// the sequencer with the correct `next_log_index` from frames. Rooms
+    /// stored before membership was tracked come back with the creator as
+    /// sole owner; other members rejoin through a Welcome or external commit.
     ///
     /// # Errors
     ///
@@ -210,75 +509,101 @@ impl RoomManager {
     /// - `RoomError::Storage` if storage query fails
     /// - `RoomError::Sequencing` if sequencer initialization fails
     pub fn recover_room(&mut self, room_id: u128, storage: &impl Storage) -> Result<(), RoomError> {
-        if self.room_metadata.contains_key(&room_id) {
+        if self.has_room(room_id) {
             return Ok(());
         }
 
         let stored =
             storage.load_room_metadata(room_id)?.ok_or(RoomError::RoomNotFound(room_id))?;
 
-        let metadata =
-            RoomMetadata { creator: stored.creator, created_at_secs: stored.created_at_secs };
-        self.room_metadata.insert(room_id, metadata);
-
-        self.sequencer.initialize_room(room_id, storage)?;
+        let acl = if stored.acl == RoomAcl::default() {
+            RoomAcl::with_owner(stored.creator)
+        } else {
+            stored.acl
+        };
+        let metadata = RoomMetadata {
+            creator: stored.creator,
+            created_at_secs: stored.created_at_secs,
+            acl,
+            closed_at: stored.closed_at,
+            listing: stored.listing,
+            kind: stored.kind,
+        };
 
-        Ok(())
+        let shard = self.shard_mut(room_id);
+        shard.insert_room(room_id, metadata);
+        shard.initialize_sequencer(room_id, storage)
     }
 
     /// Process a frame through sequencing and routing.
     ///
     /// The server is a routing-only node - it does NOT participate in MLS.
     /// Clients own the MLS group state; the server just:
-    /// 1. Verifies room exists (metadata check)
-    /// 2. Sequences frames (assigns log index)
-    /// 3. Routes frames to room subscribers
+    /// 1. Verifies room exists (metadata check) and is not closed
+    /// 2. Checks the sender against the room's ACL and the frame against the
+    ///    policy for the room's kind, broadcasting routed-only frames as they
+    ///    are
+    /// 3. Answers retries of already sequenced frames (same sender and
+    ///    idempotency key) with [`RoomAction::Duplicate`]
+    /// 4. Checks application frames against the room's storage quota
+    /// 5. Sequences frames (assigns log index)
+    /// 6. Applies any membership change the frame carries
+    /// 7. Routes frames to room subscribers
+    ///
+    /// The sender is taken from the frame header; callers must ensure it
+    /// matches the authenticated session.
     pub fn process_frame<I: Copy>(
         &mut self,
         frame: Frame,
         now: I,
         storage: &impl Storage,
     ) -> Result<Vec<RoomAction<I>>, RoomError> {
-        // 1. Room must exist (check metadata)
         let room_id = frame.header.room_id();
-        if !self.has_room(room_id) {
-            return Err(RoomError::RoomNotFound(room_id));
+        self.shard_mut(room_id).process_frame(frame, now, storage)
+    }
+
+    /// Process a batch of frames, one result per frame in input order.
+    ///
+    /// Each frame is queued on its room's shard and every shard with work
+    /// drains its queue on its own thread. Frames for the same room are
+    /// processed in the order given; frames for rooms on different shards
+    /// run concurrently. With a single busy shard no thread is spawned.
+    pub fn process_batch<I: Copy + Send>(
+        &mut self,
+        frames: Vec<Frame>,
+        now: I,
+        storage: &impl Storage,
+    ) -> Vec<Result<Vec<RoomAction<I>>, RoomError>> {
+        let count = frames.len();
+        for (position, frame) in frames.into_iter().enumerate() {
+            self.shard_mut(frame.header.room_id()).enqueue(position, frame);
         }
 
-        // 2. Sequence the frame (assign log index)
-        let sequencer_actions = self.sequencer.process_frame(frame, storage)?;
-
-        // 3. Convert SequencerAction to RoomAction
-        let room_actions: Vec<RoomAction<I>> = sequencer_actions
-            .into_iter()
-            .filter_map(|action| match action {
-                SequencerAction::AcceptFrame { .. } => {
-                    // AcceptFrame is just validation, no storage needed
-                    // StoreFrame handles the actual persistence
-                    None
-                },
-                SequencerAction::StoreFrame { room_id, log_index, frame } => {
-                    Some(RoomAction::PersistFrame { room_id, log_index, frame, processed_at: now })
-                },
-                SequencerAction::BroadcastToRoom { room_id, frame } => {
-                    Some(RoomAction::Broadcast {
-                        room_id,
-                        frame,
-                        exclude_sender: false,
-                        processed_at: now,
-                    })
-                },
-                SequencerAction::RejectFrame { room_id: _, reason, original_frame } => {
-                    Some(RoomAction::Reject {
-                        sender_id: original_frame.header.sender_id(),
-                        reason,
-                        processed_at: now,
+        let mut busy: Vec<&mut RoomShard> =
+            self.shards.iter_mut().filter(|shard| shard.has_pending()).collect();
+        let drained: Vec<(usize, FrameResult<I>)> = match busy.as_mut_slice() {
+            [] => Vec::new(),
+            [shard] => shard.drain(now, storage),
+            _ => std::thread::scope(|scope| {
+                let workers: Vec<_> = busy
+                    .into_iter()
+                    .map(|shard| scope.spawn(move || shard.drain(now, storage)))
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| match worker.join() {
+                        Ok(results) => results,
+                        Err(panic) => std::panic::resume_unwind(panic),
                     })
-                },
-            })
-            .collect();
+                    .collect()
+            }),
+        };
 
-        Ok(room_actions)
+        let mut results: Vec<Option<FrameResult<I>>> = (0..count).map(|_| None).collect();
+        for (position, result) in drained {
+            results[position] = Some(result);
+        }
+        results.into_iter().flatten().collect()
     }
 }
 
@@ -291,8 +616,8 @@ impl Default for RoomManager {
 impl std::fmt::Debug for RoomManager {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RoomManager")
-            .field("room_count", &self.room_metadata.len())
-            .field("sequencer", &self.sequencer)
+            .field("room_count", &self.room_count())
+            .field("shard_count", &self.shards.len())
             .finish()
     }
 }
@@ -300,7 +625,7 @@ impl std::fmt::Debug for RoomManager {
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
-    use lockframe_proto::{FrameHeader, Opcode};
+    use lockframe_proto::{FrameFlags, FrameHeader, payloads::admin::FrameHandling};
 
     use super::*;
     use crate::storage::MemoryStorage;
@@ -314,6 +639,126 @@ mod tests {
         Frame::new(header, Bytes::new())
     }
 
+    #[test]
+    fn rooms_spread_across_shards() {
+        let manager = RoomManager::with_shards(4);
+        let mut per_shard = [0usize; 4];
+        for room_id in 0..256u128 {
+            per_shard[manager.shard_of(room_id)] += 1;
+        }
+        assert!(per_shard.iter().all(|&count| count > 32), "uneven spread: {per_shard:?}");
+        assert_eq!(RoomManager::with_shards(0).shard_count(), 1);
+    }
+
+    #[test]
+    fn batch_keeps_per_room_order() {
+        let env = lockframe_core::env::test_utils::MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut manager = RoomManager::with_shards(4);
+        let rooms: Vec<u128> = (1..=8).collect();
+        for &room_id in &rooms {
+            manager.create_room(room_id, 7, &env, &storage).unwrap();
+        }
+
+        let frames = (0..5)
+            .flat_map(|_| rooms.iter().map(|&room_id| create_test_frame(room_id, 7, 0)))
+            .collect();
+        let results = manager.process_batch(frames, (), &storage);
+        assert_eq!(results.len(), 40);
+
+        let mut next = std::collections::HashMap::new();
+        for result in results {
+            for action in result.unwrap() {
+                if let RoomAction::PersistFrame { room_id, log_index, .. } = action {
+                    let expected = next.entry(room_id).or_insert(0);
+                    assert_eq!(log_index, *expected);
+                    *expected += 1;
+                }
+            }
+        }
+        assert!(next.values().all(|&count| count == 5));
+    }
+
+    #[test]
+    fn quota_refuses_application_frames_once_full() {
+        let env = lockframe_core::env::test_utils::MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut manager = RoomManager::new();
+        let frame_len = (FrameHeader::SIZE + 100) as u64;
+        manager.set_room_quota(Some(10 * frame_len));
+        manager.create_room(1, 7, &env, &storage).unwrap();
+
+        let frame = |opcode| {
+            let mut header = FrameHeader::new(opcode);
+            header.set_room_id(1);
+            header.set_sender_id(7);
+            Frame::new(header, Bytes::from(vec![0u8; 100]))
+        };
+        let mut warnings = 0;
+        for _ in 0..10 {
+            let actions = manager.process_frame(frame(Opcode::AppMessage), (), &storage).unwrap();
+            warnings += actions
+                .iter()
+                .filter(|action| matches!(action, RoomAction::QuotaWarning { used_bytes, .. } if *used_bytes == 8 * frame_len))
+                .count();
+        }
+        assert_eq!(warnings, 1);
+
+        let result = manager.process_frame(frame(Opcode::AppMessage), (), &storage);
+        assert!(
+            matches!(result, Err(RoomError::QuotaExceeded { used_bytes, .. }) if used_bytes == 10 * frame_len)
+        );
+
+        // The group can still be managed
+        assert!(manager.process_frame(frame(Opcode::Commit), (), &storage).is_ok());
+    }
+
+    #[test]
+    fn room_kind_decides_how_messages_are_handled() {
+        let env = lockframe_core::env::test_utils::MockEnv::with_crypto_rng();
+        let storage = MemoryStorage::new();
+        let mut manager = RoomManager::new();
+        manager.create_room_with_kind(1, 7, RoomKind::Ephemeral, &env, &storage).unwrap();
+        manager.create_room_with_kind(2, 7, RoomKind::Broadcast, &env, &storage).unwrap();
+        manager.admit_member(2, 7, 8, &storage).unwrap();
+
+        // Ephemeral rooms relay messages without giving them a place in the
+        // log, so the sender must have flagged them ephemeral
+        let result = manager.process_frame(create_test_frame(1, 7, 0), (), &storage);
+        assert!(matches!(
+            result,
+            Err(RoomError::AccessDenied { reason: Denial::NotEphemeral { .. }, .. })
+        ));
+        let mut frame = create_test_frame(1, 7, 0);
+        frame.header.set_flags(FrameFlags::EPHEMERAL);
+        let actions = manager.process_frame(frame, (), &storage).unwrap();
+        assert!(matches!(actions.as_slice(), [RoomAction::Broadcast { .. }]));
+        assert_eq!(storage.latest_log_index(1).unwrap(), None);
+
+        // Broadcast rooms only take messages from their owner
+        let result = manager.process_frame(create_test_frame(2, 8, 0), (), &storage);
+        assert!(matches!(
+            result,
+            Err(RoomError::AccessDenied { reason: Denial::RoomPolicy { .. }, .. })
+        ));
+        let actions = manager.process_frame(create_test_frame(2, 7, 0), (), &storage).unwrap();
+        assert!(actions.iter().any(|action| matches!(action, RoomAction::PersistFrame { .. })));
+
+        // ...but members can still change the group
+        let mut header = FrameHeader::new(Opcode::Commit);
+        header.set_room_id(2);
+        header.set_sender_id(8);
+        assert!(manager.process_frame(Frame::new(header, Bytes::new()), (), &storage).is_ok());
+
+        // Operators can override the defaults
+        manager.set_frame_rule(
+            RoomKind::Broadcast,
+            Opcode::AppMessage,
+            Some(FrameRule::all(FrameHandling::Persist)),
+        );
+        assert!(manager.process_frame(create_test_frame(2, 8, 0), (), &storage).is_ok());
+    }
+
     #[test]
     fn test_room_manager_recover_room() {
         let storage = MemoryStorage::new();
@@ -321,7 +766,7 @@ mod tests {
         let creator = 42u64;
 
         // Pre-populate storage with room metadata and frames
-        let metadata = StoredRoomMetadata { creator, created_at_secs: 0 };
+        let metadata = StoredRoomMetadata::new(creator, 0);
         storage.create_room(room_id, &metadata).unwrap();
         for i in 0..5 {
             let frame = create_test_frame(room_id, creator, i);
@@ -343,7 +788,7 @@ mod tests {
         let creator = 1u64;
 
         // Pre-populate storage with room metadata and frame
-        let metadata = StoredRoomMetadata { creator, created_at_secs: 0 };
+        let metadata = StoredRoomMetadata::new(creator, 0);
         storage.create_room(room_id, &metadata).unwrap();
         let frame = create_test_frame(room_id, creator, 0);
         storage.store_frame(room_id, 0, &frame).unwrap();
@@ -376,7 +821,7 @@ mod tests {
         let creator = 42u64;
 
         // Pre-populate storage with room metadata
-        let metadata = StoredRoomMetadata { creator, created_at_secs: 0 };
+        let metadata = StoredRoomMetadata::new(creator, 0);
         storage.create_room(room_id, &metadata).unwrap();
 
         let mut room_manager = RoomManager::new();
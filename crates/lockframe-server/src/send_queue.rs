@@ -0,0 +1,236 @@
+//! Per-session send queues.
+//!
+//! The runtime never writes to a session's stream while handling an action.
+//! Frames are appended to the session's [`SendQueue`] and a writer task per
+//! session drains it, writing everything queued since its last write in one
+//! go. A broadcast therefore costs one queue push per member, however slow
+//! any member is.
+//!
+//! Queues are bounded in bytes. When a session falls so far behind that its
+//! queue is full, the oldest frames are dropped and the next write starts
+//! with a `LaggedBehind` frame listing where each affected room's log should
+//! be resynced from.
+//!
+//! Closing a session goes through its queue too, so frames sent just before
+//! the close (an Error, a Goodbye) are written before the connection closes.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use bytes::Bytes;
+use lockframe_proto::{
+    FrameFlags, FrameHeader, Opcode, Payload,
+    payloads::session::{LaggedBehind, RoomGap},
+};
+use tokio::sync::{Mutex, Notify};
+
+/// Default bound on bytes queued for one session.
+pub(crate) const DEFAULT_QUEUE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Encoded frames waiting to be written to one session.
+#[derive(Debug)]
+pub(crate) struct SendQueue {
+    /// Encoded frames, oldest first
+    frames: VecDeque<Bytes>,
+    /// Total length of `frames`
+    queued_bytes: usize,
+    /// Bound on `queued_bytes`
+    max_bytes: usize,
+    /// Frames dropped since the last `LaggedBehind`
+    dropped: u64,
+    /// Room ID → lowest log index dropped since the last `LaggedBehind`
+    gaps: BTreeMap<u128, u64>,
+}
+
+impl SendQueue {
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            queued_bytes: 0,
+            max_bytes,
+            dropped: 0,
+            gaps: BTreeMap::new(),
+        }
+    }
+
+    /// Queue an encoded frame, dropping the oldest frames if the queue
+    /// would exceed its bound. A frame is never dropped to make room for
+    /// itself, so one larger than the bound is still delivered.
+    pub(crate) fn push(&mut self, frame: Bytes) {
+        self.queued_bytes += frame.len();
+        self.frames.push_back(frame);
+
+        while self.queued_bytes > self.max_bytes && self.frames.len() > 1 {
+            let Some(oldest) = self.frames.pop_front() else {
+                break;
+            };
+            self.queued_bytes -= oldest.len();
+            self.record_drop(&oldest);
+        }
+    }
+
+    /// Take everything queued as one buffer, led by a `LaggedBehind` frame
+    /// if anything was dropped. `None` if nothing is queued.
+    pub(crate) fn take_batch(&mut self) -> Option<Vec<u8>> {
+        if self.frames.is_empty() && self.dropped == 0 {
+            return None;
+        }
+
+        let mut batch = Vec::with_capacity(self.queued_bytes);
+        if self.dropped > 0 {
+            let rooms = std::mem::take(&mut self.gaps)
+                .into_iter()
+                .map(|(room_id, from_log_index)| RoomGap { room_id, from_log_index })
+                .collect();
+            let notice = LaggedBehind { dropped: std::mem::take(&mut self.dropped), rooms };
+            let encoded = Payload::LaggedBehind(notice)
+                .into_frame(FrameHeader::new(Opcode::LaggedBehind))
+                .and_then(|frame| frame.encode(&mut batch));
+            if let Err(e) = encoded {
+                tracing::warn!("failed to encode LaggedBehind: {}", e);
+            }
+        }
+
+        for frame in self.frames.drain(..) {
+            batch.extend_from_slice(&frame);
+        }
+        self.queued_bytes = 0;
+        Some(batch)
+    }
+
+    fn record_drop(&mut self, frame: &[u8]) {
+        self.dropped += 1;
+
+        let Some(header) = FrameHeader::from_bytes(frame).ok() else {
+            return;
+        };
+        if is_logged(header.opcode_enum()) && !header.flags().contains(FrameFlags::EPHEMERAL) {
+            let from = self.gaps.entry(header.room_id()).or_insert(header.log_index());
+            *from = (*from).min(header.log_index());
+        }
+    }
+}
+
+/// Whether frames with this opcode may be stored in a room's log, and so can
+/// be fetched again with a `SyncRequest`. Errs towards resyncing too much.
+fn is_logged(opcode: Option<Opcode>) -> bool {
+    match opcode {
+        Some(Opcode::Typing | Opcode::Presence) | None => false,
+        Some(opcode) => (0x1000..0x4000).contains(&opcode.to_u16()),
+    }
+}
+
+/// What a session's writer should do next.
+#[derive(Debug)]
+pub(crate) enum Outgoing {
+    /// Write these bytes
+    Frames(Vec<u8>),
+    /// Everything is written; close the connection with this reason
+    Close(String),
+}
+
+/// A session's send queue and the signal that wakes its writer.
+#[derive(Debug)]
+pub(crate) struct Outbox {
+    state: Mutex<(SendQueue, Option<String>)>,
+    ready: Notify,
+}
+
+impl Outbox {
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        Self { state: Mutex::new((SendQueue::new(max_bytes), None)), ready: Notify::new() }
+    }
+
+    /// Queue a frame and wake the writer. Ignored once closing.
+    pub(crate) async fn push(&self, frame: Bytes) {
+        let mut state = self.state.lock().await;
+        if state.1.is_none() {
+            state.0.push(frame);
+            self.ready.notify_one();
+        }
+    }
+
+    /// Close the connection once everything queued so far is written.
+    pub(crate) async fn close(&self, reason: String) {
+        self.state.lock().await.1.get_or_insert(reason);
+        self.ready.notify_one();
+    }
+
+    /// Wait until there is something to do.
+    pub(crate) async fn next(&self) -> Outgoing {
+        loop {
+            {
+                let mut state = self.state.lock().await;
+                if let Some(batch) = state.0.take_batch() {
+                    return Outgoing::Frames(batch);
+                }
+                if let Some(reason) = state.1.take() {
+                    return Outgoing::Close(reason);
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lockframe_proto::Frame;
+
+    use super::*;
+
+    fn encoded(opcode: Opcode, room_id: u128, log_index: u64, len: usize) -> Bytes {
+        let mut header = FrameHeader::new(opcode);
+        header.set_room_id(room_id);
+        header.set_log_index(log_index);
+        let mut buf = Vec::new();
+        Frame::new(header, vec![0u8; len]).encode(&mut buf).unwrap();
+        Bytes::from(buf)
+    }
+
+    fn decode_all(mut batch: &[u8]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        while !batch.is_empty() {
+            let frame = Frame::decode(batch).unwrap();
+            batch = &batch[FrameHeader::SIZE + frame.payload.len()..];
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn queued_frames_are_coalesced() {
+        let mut queue = SendQueue::new(DEFAULT_QUEUE_BYTES);
+        assert_eq!(queue.take_batch(), None);
+
+        queue.push(encoded(Opcode::AppMessage, 1, 0, 10));
+        queue.push(encoded(Opcode::AppMessage, 1, 1, 10));
+        let frames = decode_all(&queue.take_batch().unwrap());
+        assert_eq!(frames.iter().map(|f| f.header.log_index()).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(queue.take_batch(), None);
+    }
+
+    #[test]
+    fn laggards_lose_oldest_frames_and_are_told() {
+        let frame_len = FrameHeader::SIZE + 100;
+        let mut queue = SendQueue::new(3 * frame_len);
+
+        queue.push(encoded(Opcode::Typing, 1, 0, 100));
+        for log_index in 5..10 {
+            queue.push(encoded(Opcode::AppMessage, 1, log_index, 100));
+        }
+
+        let frames = decode_all(&queue.take_batch().unwrap());
+        let Ok(Payload::LaggedBehind(notice)) = Payload::from_frame(&frames[0]) else {
+            panic!("expected LaggedBehind first");
+        };
+        assert_eq!(notice.dropped, 3);
+        assert_eq!(notice.rooms, vec![RoomGap { room_id: 1, from_log_index: 5 }]);
+        assert_eq!(frames[1..].iter().map(|f| f.header.log_index()).collect::<Vec<_>>(), vec![
+            7, 8, 9
+        ]);
+
+        // The notice is sent once
+        queue.push(encoded(Opcode::AppMessage, 1, 10, 100));
+        assert_eq!(decode_all(&queue.take_batch().unwrap()).len(), 1);
+    }
+}
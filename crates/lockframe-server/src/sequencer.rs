@@ -9,8 +9,7 @@
 
 use std::collections::{HashMap, hash_map};
 
-use lockframe_core::mls::MAX_EPOCH;
-use lockframe_proto::{Frame, FrameHeader};
+use lockframe_proto::{Frame, FrameFlags};
 use thiserror::Error;
 
 use crate::storage::{Storage, StorageError};
@@ -99,27 +98,11 @@ pub struct Sequencer {
 /// Validate frame structure at API boundary (before processing)
 ///
 /// Checks:
-/// - Magic number is correct
-/// - Version is supported
+/// - Header fields are in range (magic, version, opcode, epoch, flags)
 /// - Payload size matches header claim
 /// - Room ID is non-zero
-/// - Epoch is within reasonable bounds
 fn validate_frame_structure(frame: &Frame) -> Result<(), SequencerError> {
-    if frame.header.magic() != FrameHeader::MAGIC {
-        return Err(SequencerError::Validation(format!(
-            "invalid magic: got {:#010x}, expected {:#010x}",
-            frame.header.magic(),
-            FrameHeader::MAGIC
-        )));
-    }
-
-    if frame.header.version() != FrameHeader::VERSION {
-        return Err(SequencerError::Validation(format!(
-            "unsupported version: got {}, expected {}",
-            frame.header.version(),
-            FrameHeader::VERSION
-        )));
-    }
+    frame.header.validate().map_err(|e| SequencerError::Validation(e.to_string()))?;
 
     if frame.payload.len() != frame.header.payload_size() as usize {
         return Err(SequencerError::Validation(format!(
@@ -133,14 +116,6 @@ fn validate_frame_structure(frame: &Frame) -> Result<(), SequencerError> {
         return Err(SequencerError::Validation("room_id is zero (uninitialized?)".to_string()));
     }
 
-    if frame.header.epoch() > MAX_EPOCH {
-        return Err(SequencerError::Validation(format!(
-            "epoch {} exceeds MAX_EPOCH {}",
-            frame.header.epoch(),
-            MAX_EPOCH
-        )));
-    }
-
     Ok(())
 }
 
@@ -176,6 +151,13 @@ impl Sequencer {
             return Ok(vec![SequencerAction::BroadcastToRoom { room_id, frame }]);
         }
 
+        // Ephemeral frames (typing notifications) are relayed as they are. They
+        // take no log index, so skipping storage leaves no gap for clients to
+        // sync
+        if is_ephemeral(&frame) {
+            return Ok(vec![SequencerAction::BroadcastToRoom { room_id, frame }]);
+        }
+
         let room = match self.rooms.entry(room_id) {
             hash_map::Entry::Vacant(e) => {
                 let latest_index = storage.latest_log_index(room_id).map_err(|e| {
@@ -303,6 +285,13 @@ fn rebuild_frame_with_index(original: Frame, log_index: u64) -> Frame {
     Frame::new(new_header, original.payload)
 }
 
+/// Whether a frame is relayed without being sequenced or stored. MLS frames
+/// advance group state and are always logged, whatever their flags.
+pub(crate) fn is_ephemeral(frame: &Frame) -> bool {
+    frame.header.flags().contains(FrameFlags::EPHEMERAL)
+        && !frame.header.opcode_enum().is_some_and(lockframe_proto::Opcode::is_mls)
+}
+
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
@@ -366,6 +355,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ephemeral_frames_are_relayed_without_a_log_index() {
+        let mut sequencer = Sequencer::new();
+        let storage = MemoryStorage::new();
+
+        let mut typing = create_test_frame(100, 200, 0);
+        typing.header.set_flags(FrameFlags::EPHEMERAL);
+        let actions = sequencer.process_frame(typing, &storage).expect("sequencing failed");
+        assert!(matches!(actions[..], [SequencerAction::BroadcastToRoom { room_id: 100, .. }]));
+
+        let actions = sequencer.process_frame(create_test_frame(100, 200, 0), &storage).unwrap();
+        assert!(matches!(actions[0], SequencerAction::AcceptFrame { log_index: 0, .. }));
+
+        let mut header = FrameHeader::new(Opcode::Commit);
+        header.set_room_id(100);
+        header.set_flags(FrameFlags::EPHEMERAL);
+        let commit = Frame::new(header, Bytes::new());
+        let actions = sequencer.process_frame(commit, &storage).unwrap();
+        assert!(matches!(actions[0], SequencerAction::AcceptFrame { log_index: 1, .. }));
+    }
+
     #[test]
     fn test_sequential_frames() {
         let mut sequencer = Sequencer::new();
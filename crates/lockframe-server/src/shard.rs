@@ -0,0 +1,418 @@
+//! Room shards.
+//!
+//! [`RoomManager`](crate::RoomManager) partitions rooms across shards by a
+//! hash of the room ID. A shard owns everything needed to sequence its rooms:
+//! their metadata and ACLs, a [`Sequencer`] holding only those rooms, and a
+//! queue of frames waiting to be processed. Shards share nothing but storage,
+//! so a batch of frames can be split by shard and each queue drained on its
+//! own thread. Frames for one room always land in the same queue, which keeps
+//! per-room ordering intact.
+//!
+//! Each shard also keeps its rooms' idempotency windows and stored byte
+//! counts, which are cached and reloaded alongside sequencer state, and its
+//! own copy of the frame policy table.
+
+use std::collections::{HashMap, VecDeque, hash_map};
+
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode,
+    payloads::{
+        admin::{FrameHandling, FrameRule, RoomKind},
+        moderation::RoomRole,
+    },
+};
+
+use crate::{
+    acl::{AclChange, Denial},
+    idempotency::IdempotencyWindow,
+    policy::PolicyTable,
+    room_manager::{RoomAction, RoomError, RoomMetadata},
+    sequencer::{Sequencer, SequencerAction, is_ephemeral},
+    storage::Storage,
+};
+
+/// Result of processing one frame.
+pub(crate) type FrameResult<I> = Result<Vec<RoomAction<I>>, RoomError>;
+
+/// Rooms, sequencer state and pending frames for one partition.
+#[derive(Debug, Default)]
+pub(crate) struct RoomShard {
+    /// Frame sequencer for this shard's rooms
+    sequencer: Sequencer,
+    /// Metadata and membership for this shard's rooms
+    rooms: HashMap<u128, RoomMetadata>,
+    /// Recent idempotency keys, loaded lazily per room
+    idempotency: HashMap<u128, IdempotencyWindow>,
+    /// Bytes stored per room, loaded lazily
+    usage: HashMap<u128, u64>,
+    /// Most bytes a room may store, if limited
+    quota: Option<u64>,
+    /// How each kind of room handles each opcode
+    policy: PolicyTable,
+    /// Frames waiting to be processed, tagged with their batch position
+    queue: VecDeque<(usize, Frame)>,
+}
+
+impl RoomShard {
+    pub(crate) fn has_room(&self, room_id: u128) -> bool {
+        self.rooms.contains_key(&room_id)
+    }
+
+    pub(crate) fn room_ids(&self) -> impl Iterator<Item = u128> + '_ {
+        self.rooms.keys().copied()
+    }
+
+    pub(crate) fn room_count(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub(crate) fn metadata(&self, room_id: u128) -> Option<&RoomMetadata> {
+        self.rooms.get(&room_id)
+    }
+
+    pub(crate) fn insert_room(&mut self, room_id: u128, metadata: RoomMetadata) {
+        self.rooms.insert(room_id, metadata);
+    }
+
+    /// Drop a room's metadata and sequencer state.
+    pub(crate) fn remove_room(&mut self, room_id: u128) -> Result<(), RoomError> {
+        self.rooms.remove(&room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        self.clear_sequencer(room_id);
+        Ok(())
+    }
+
+    /// Drop a room's sequencer state, idempotency window and byte count;
+    /// all are reloaded from storage on the next frame.
+    pub(crate) fn clear_sequencer(&mut self, room_id: u128) -> bool {
+        self.idempotency.remove(&room_id);
+        self.usage.remove(&room_id);
+        self.sequencer.clear_room(room_id)
+    }
+
+    /// Limit every room to `quota` bytes. Byte counts are only tracked
+    /// while a quota is set.
+    pub(crate) fn set_quota(&mut self, quota: Option<u64>) {
+        self.quota = quota;
+        self.usage.clear();
+    }
+
+    /// Drop a room's cached byte count, e.g. after its history was
+    /// truncated.
+    pub(crate) fn forget_usage(&mut self, room_id: u128) {
+        self.usage.remove(&room_id);
+    }
+
+    /// Bytes a room stores, loaded from storage if not cached.
+    fn usage(&mut self, room_id: u128, storage: &impl Storage) -> Result<u64, RoomError> {
+        Ok(match self.usage.entry(room_id) {
+            hash_map::Entry::Occupied(entry) => *entry.get(),
+            hash_map::Entry::Vacant(entry) => *entry.insert(storage.stored_bytes(room_id)?),
+        })
+    }
+
+    pub(crate) fn initialize_sequencer(
+        &mut self,
+        room_id: u128,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        self.sequencer.initialize_room(room_id, storage)?;
+        Ok(())
+    }
+
+    /// Apply a membership change and persist it.
+    pub(crate) fn apply_acl_change(
+        &mut self,
+        room_id: u128,
+        change: AclChange,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        let metadata = self.rooms.get_mut(&room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+
+        let mut acl = metadata.acl.clone();
+        acl.apply(change);
+        if acl == metadata.acl {
+            return Ok(());
+        }
+
+        let updated = RoomMetadata { acl, ..metadata.clone() };
+        storage.update_room_metadata(room_id, &updated.to_stored())?;
+        *metadata = updated;
+        Ok(())
+    }
+
+    /// A room's idempotency window, rebuilt from storage if not cached.
+    fn idempotency_window(
+        &mut self,
+        room_id: u128,
+        storage: &impl Storage,
+    ) -> Result<&mut IdempotencyWindow, RoomError> {
+        Ok(match self.idempotency.entry(room_id) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(IdempotencyWindow::load(room_id, storage)?)
+            },
+        })
+    }
+
+    pub(crate) fn set_frame_rule(
+        &mut self,
+        kind: RoomKind,
+        opcode: Opcode,
+        rule: Option<FrameRule>,
+    ) {
+        self.policy.set_rule(kind, opcode, rule);
+    }
+
+    /// How the policy for a room's kind handles a frame.
+    ///
+    /// # Errors
+    ///
+    /// - `RoomError::AccessDenied` if the policy rejects the frame, or routes
+    ///   an `AppMessage` not flagged ephemeral
+    fn frame_handling(
+        &self,
+        metadata: &RoomMetadata,
+        frame: &Frame,
+    ) -> Result<FrameHandling, RoomError> {
+        let Some(opcode) = frame.header.opcode_enum() else {
+            return Ok(FrameHandling::Persist);
+        };
+        let kind = metadata.kind;
+        let is_owner = metadata.acl.role(frame.header.sender_id()) == Some(RoomRole::Owner);
+        let room_id = frame.header.room_id();
+        match self.policy.handling(kind, opcode, is_owner) {
+            FrameHandling::Reject => Err(RoomError::AccessDenied {
+                room_id,
+                reason: Denial::RoomPolicy { opcode, kind },
+            }),
+            // Routed frames get no log index. The flag is covered by the
+            // sender's signature, so it cannot be set here
+            FrameHandling::Route if opcode == Opcode::AppMessage && !is_ephemeral(frame) => {
+                Err(RoomError::AccessDenied {
+                    room_id,
+                    reason: Denial::NotEphemeral { opcode, kind },
+                })
+            },
+            handling => Ok(handling),
+        }
+    }
+
+    /// Change a room's kind and persist it.
+    pub(crate) fn set_kind(
+        &mut self,
+        room_id: u128,
+        kind: RoomKind,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        let metadata = self.rooms.get_mut(&room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        if metadata.closed_at.is_some() {
+            return Err(RoomError::RoomClosed(room_id));
+        }
+        if metadata.kind == kind {
+            return Ok(());
+        }
+
+        let updated = RoomMetadata { kind, ..metadata.clone() };
+        storage.update_room_metadata(room_id, &updated.to_stored())?;
+        *metadata = updated;
+        Ok(())
+    }
+
+    /// Change a room's directory listing and persist it.
+    pub(crate) fn set_listing(
+        &mut self,
+        room_id: u128,
+        listing: Option<String>,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        let metadata = self.rooms.get_mut(&room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        if metadata.closed_at.is_some() {
+            return Err(RoomError::RoomClosed(room_id));
+        }
+        if metadata.listing == listing {
+            return Ok(());
+        }
+
+        let updated = RoomMetadata { listing, ..metadata.clone() };
+        storage.update_room_metadata(room_id, &updated.to_stored())?;
+        *metadata = updated;
+        Ok(())
+    }
+
+    /// Mark a room closed at its `CloseRoom` tombstone and persist that.
+    fn close_room(
+        &mut self,
+        room_id: u128,
+        log_index: u64,
+        storage: &impl Storage,
+    ) -> Result<(), RoomError> {
+        let metadata = self.rooms.get_mut(&room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        let updated = RoomMetadata { closed_at: Some(log_index), ..metadata.clone() };
+        storage.update_room_metadata(room_id, &updated.to_stored())?;
+        *metadata = updated;
+        Ok(())
+    }
+
+    /// Queue a frame for [`Self::drain`]. `position` is its index in the
+    /// caller's batch.
+    pub(crate) fn enqueue(&mut self, position: usize, frame: Frame) {
+        self.queue.push_back((position, frame));
+    }
+
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Process every queued frame in arrival order, returning each result
+    /// with its batch position.
+    pub(crate) fn drain<I: Copy>(
+        &mut self,
+        now: I,
+        storage: &impl Storage,
+    ) -> Vec<(usize, FrameResult<I>)> {
+        let mut results = Vec::with_capacity(self.queue.len());
+        while let Some((position, frame)) = self.queue.pop_front() {
+            results.push((position, self.process_frame(frame, now, storage)));
+        }
+        results
+    }
+
+    /// Authorize, sequence and route one frame. See
+    /// [`RoomManager::process_frame`](crate::RoomManager::process_frame).
+    pub(crate) fn process_frame<I: Copy>(
+        &mut self,
+        frame: Frame,
+        now: I,
+        storage: &impl Storage,
+    ) -> FrameResult<I> {
+        // 1. Room must exist (check metadata) and be open
+        let room_id = frame.header.room_id();
+        let metadata = self.rooms.get(&room_id).ok_or(RoomError::RoomNotFound(room_id))?;
+        if metadata.closed_at.is_some() {
+            return Err(RoomError::RoomClosed(room_id));
+        }
+
+        // 2. Sender must be allowed to send this frame, and the room's kind must take
+        //    it. Routed-only frames skip the log entirely, unless they change
+        //    membership, which only happens from the log
+        let sender_id = frame.header.sender_id();
+        let change = metadata
+            .acl
+            .authorize(sender_id, &frame)
+            .map_err(|reason| RoomError::AccessDenied { room_id, reason })?;
+        if change.is_none() && self.frame_handling(metadata, &frame)? == FrameHandling::Route {
+            return Ok(vec![RoomAction::Broadcast {
+                room_id,
+                frame,
+                exclude_sender: false,
+                processed_at: now,
+            }]);
+        }
+
+        // 3. A retried send is answered with the original, not sequenced again
+        let key = frame.header.idempotency_key();
+        let opcode = frame.header.opcode_enum();
+        let closes = opcode == Some(Opcode::CloseRoom);
+        if let Some(key) = key
+            && let Some(log_index) =
+                self.idempotency_window(room_id, storage)?.lookup(sender_id, key)
+        {
+            let original = storage.load_frames(room_id, log_index, 1)?;
+            return Ok(original
+                .into_iter()
+                .map(|frame| RoomAction::Duplicate { room_id, log_index, frame, processed_at: now })
+                .collect());
+        }
+
+        // 4. Application frames must fit in the room's quota. Control frames are always
+        //    accepted so a full room can still change membership or be closed
+        let size = (FrameHeader::SIZE + frame.payload.len()) as u64;
+        let used = if self.quota.is_some() { self.usage(room_id, storage)? } else { 0 };
+        let limited = !closes && !opcode.is_some_and(Opcode::is_mls) && !is_ephemeral(&frame);
+        if let Some(limit) = self.quota
+            && limited
+            && used + size > limit
+        {
+            return Err(RoomError::QuotaExceeded { room_id, used_bytes: used, limit_bytes: limit });
+        }
+
+        // 5. Sequence the frame (assign log index)
+        let sequencer_actions = self.sequencer.process_frame(frame, storage)?;
+        let stored_at = sequencer_actions.iter().find_map(|action| match action {
+            SequencerAction::StoreFrame { log_index, .. } => Some(*log_index),
+            _ => None,
+        });
+        if let (Some(key), Some(log_index)) = (key, stored_at) {
+            self.idempotency_window(room_id, storage)?.record(sender_id, key, log_index);
+        }
+        let mut warning = None;
+        if let Some(limit) = self.quota
+            && stored_at.is_some()
+        {
+            let after = used + size;
+            self.usage.insert(room_id, after);
+            if crosses_warning(used, after, limit) {
+                warning = Some(RoomAction::QuotaWarning {
+                    room_id,
+                    used_bytes: after,
+                    limit_bytes: limit,
+                    processed_at: now,
+                });
+            }
+        }
+
+        // 6. Membership changes and closing only take effect once the frame is in the
+        //    log
+        if let Some(change) = change
+            && stored_at.is_some()
+        {
+            self.apply_acl_change(room_id, change, storage)?;
+        }
+        if closes && let Some(log_index) = stored_at {
+            self.close_room(room_id, log_index, storage)?;
+        }
+
+        // 7. Convert SequencerAction to RoomAction
+        let mut room_actions: Vec<RoomAction<I>> = sequencer_actions
+            .into_iter()
+            .filter_map(|action| match action {
+                SequencerAction::AcceptFrame { .. } => {
+                    // AcceptFrame is just validation, no storage needed
+                    // StoreFrame handles the actual persistence
+                    None
+                },
+                SequencerAction::StoreFrame { room_id, log_index, frame } => {
+                    Some(RoomAction::PersistFrame { room_id, log_index, frame, processed_at: now })
+                },
+                SequencerAction::BroadcastToRoom { room_id, frame } => {
+                    Some(RoomAction::Broadcast {
+                        room_id,
+                        frame,
+                        exclude_sender: false,
+                        processed_at: now,
+                    })
+                },
+                SequencerAction::RejectFrame { room_id: _, reason, original_frame } => {
+                    Some(RoomAction::Reject {
+                        sender_id: original_frame.header.sender_id(),
+                        reason,
+                        processed_at: now,
+                    })
+                },
+            })
+            .collect();
+        room_actions.extend(warning);
+
+        Ok(room_actions)
+    }
+}
+
+/// Share of its quota a room may fill before the server warns.
+const QUOTA_WARNING_PERCENT: u64 = 80;
+
+/// Whether growing from `before` to `after` bytes crosses the warning
+/// threshold of `limit`.
+fn crosses_warning(before: u64, after: u64, limit: u64) -> bool {
+    let threshold = u128::from(limit) * u128::from(QUOTA_WARNING_PERCENT);
+    u128::from(before) * 100 < threshold && u128::from(after) * 100 >= threshold
+}
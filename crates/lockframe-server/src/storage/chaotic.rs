@@ -153,6 +153,56 @@ impl<S: Storage> Storage for ChaoticStorage<S> {
         self.inner.load_frames(room_id, from, limit)
     }
 
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.earliest_log_index(room_id)
+    }
+
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.stored_bytes(room_id)
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.truncate_frames(room_id, first_kept, tombstone)
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.replace_frame(room_id, log_index, frame)
+    }
+
+    fn offload_history(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.offload_history(room_id)
+    }
+
     fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
         self.increment_operation_count();
         if self.should_fail() {
@@ -220,6 +270,26 @@ impl<S: Storage> Storage for ChaoticStorage<S> {
         }
         self.inner.load_room_metadata(room_id)
     }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.update_room_metadata(room_id, metadata)
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.increment_operation_count();
+        if self.should_fail() {
+            return Err(StorageError::Io("chaotic failure injection".to_string()));
+        }
+        self.inner.flush()
+    }
 }
 
 #[cfg(test)]
@@ -6,7 +6,7 @@ use std::{
 };
 
 use lockframe_core::mls::MlsGroupState;
-use lockframe_proto::Frame;
+use lockframe_proto::{Frame, FrameHeader};
 
 use super::{Storage, StorageError, StoredRoomMetadata};
 
@@ -29,6 +29,10 @@ struct MemoryStorageInner {
     /// Frames organized by room, stored in `log_index` order
     frames: HashMap<u128, Vec<Frame>>,
 
+    /// Log index of each room's first stored frame, for rooms whose history
+    /// was truncated. Absent means zero.
+    first_index: HashMap<u128, u64>,
+
     /// MLS group state per room
     mls_states: HashMap<u128, MlsGroupState>,
 
@@ -44,6 +48,7 @@ impl MemoryStorage {
             inner: Arc::new(Mutex::new(MemoryStorageInner {
                 rooms: HashMap::new(),
                 frames: HashMap::new(),
+                first_index: HashMap::new(),
                 mls_states: HashMap::new(),
                 group_infos: HashMap::new(),
             })),
@@ -98,9 +103,10 @@ impl Storage for MemoryStorage {
     ) -> Result<(), StorageError> {
         let mut inner = self.inner.lock().expect("Mutex poisoned");
 
+        let first_index = inner.first_index.get(&room_id).copied().unwrap_or(0);
         let frames = inner.frames.entry(room_id).or_default();
 
-        let expected_index = frames.len() as u64;
+        let expected_index = first_index + frames.len() as u64;
         debug_assert!(frames.len() < u64::MAX as usize);
 
         if log_index != expected_index {
@@ -113,8 +119,7 @@ impl Storage for MemoryStorage {
         // The payload clone is cheap (Arc increment via Bytes) but header is copied.
         frames.push(frame.clone());
 
-        debug_assert_eq!(frames.len() as u64 - 1, log_index);
-        debug_assert_eq!(frames[log_index as usize].header.log_index(), log_index);
+        debug_assert_eq!(first_index + frames.len() as u64 - 1, log_index);
 
         Ok(())
     }
@@ -127,11 +132,90 @@ impl Storage for MemoryStorage {
     fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
         let inner = self.inner.lock().expect("Mutex poisoned");
 
+        let first_index = inner.first_index.get(&room_id).copied().unwrap_or(0);
         Ok(inner.frames.get(&room_id).and_then(|frames| {
-            if frames.is_empty() { None } else { Some(frames.len() as u64 - 1) }
+            if frames.is_empty() { None } else { Some(first_index + frames.len() as u64 - 1) }
+        }))
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned. This is acceptable for test
+    /// code.
+    #[allow(clippy::expect_used)]
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        let inner = self.inner.lock().expect("Mutex poisoned");
+
+        let first_index = inner.first_index.get(&room_id).copied().unwrap_or(0);
+        Ok(inner.frames.get(&room_id).filter(|frames| !frames.is_empty()).map(|_| first_index))
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned. This is acceptable for test
+    /// code.
+    #[allow(clippy::expect_used)]
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        let inner = self.inner.lock().expect("Mutex poisoned");
+
+        Ok(inner.frames.get(&room_id).map_or(0, |frames| {
+            frames.iter().map(|frame| (FrameHeader::SIZE + frame.payload.len()) as u64).sum()
         }))
     }
 
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned. This is acceptable for test
+    /// code.
+    #[allow(clippy::expect_used)]
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        let latest = self.latest_log_index(room_id)?;
+        let tombstone_index = super::tombstone_index(room_id, first_kept, latest)?;
+        debug_assert_eq!(tombstone.header.log_index(), tombstone_index);
+
+        let mut inner = self.inner.lock().expect("Mutex poisoned");
+        let first_index = inner.first_index.get(&room_id).copied().unwrap_or(0);
+
+        if let Some(frames) = inner.frames.get_mut(&room_id) {
+            let drop_count = tombstone_index.saturating_sub(first_index) as usize;
+            frames.drain(..drop_count);
+            if let Some(first) = frames.first_mut() {
+                *first = tombstone.clone();
+            }
+        }
+        inner.first_index.insert(room_id, tombstone_index.max(first_index));
+
+        Ok(())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned. This is acceptable for test
+    /// code.
+    #[allow(clippy::expect_used)]
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let mut inner = self.inner.lock().expect("Mutex poisoned");
+        let first_index = inner.first_index.get(&room_id).copied().unwrap_or(0);
+
+        let stored = log_index
+            .checked_sub(first_index)
+            .and_then(|offset| usize::try_from(offset).ok())
+            .and_then(|offset| inner.frames.get_mut(&room_id)?.get_mut(offset))
+            .ok_or(StorageError::NotFound { room_id, log_index })?;
+        *stored = frame.clone();
+        Ok(())
+    }
+
     /// # Panics
     ///
     /// Panics if the internal mutex is poisoned. This is acceptable for test
@@ -150,7 +234,8 @@ impl Storage for MemoryStorage {
             .get(&room_id)
             .ok_or(StorageError::NotFound { room_id, log_index: from })?;
 
-        let start = from as usize;
+        let first_index = inner.first_index.get(&room_id).copied().unwrap_or(0);
+        let start = from.saturating_sub(first_index) as usize;
         let end = (start + limit).min(frames.len());
 
         if start > frames.len() {
@@ -253,6 +338,20 @@ impl Storage for MemoryStorage {
     ) -> Result<Option<StoredRoomMetadata>, StorageError> {
         Ok(self.inner.lock().expect("Mutex poisoned").rooms.get(&room_id).cloned())
     }
+
+    /// # Panics
+    ///
+    /// Panics if the internal mutex is poisoned. This is acceptable for test
+    /// code.
+    #[allow(clippy::expect_used)]
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        self.inner.lock().expect("Mutex poisoned").rooms.insert(room_id, metadata.clone());
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -270,6 +369,30 @@ mod tests {
         Frame::new(header, Bytes::new())
     }
 
+    #[test]
+    fn test_truncate_leaves_tombstone_and_keeps_sequencing() {
+        let storage = MemoryStorage::new();
+        let room_id = 1u128;
+        for i in 0..10 {
+            storage.store_frame(room_id, i, &create_test_frame(room_id, i)).unwrap();
+        }
+
+        let mut tombstone = create_test_frame(room_id, 6);
+        tombstone.header.set_sender_id(0);
+        storage.truncate_frames(room_id, 7, &tombstone).unwrap();
+
+        assert_eq!(storage.earliest_log_index(room_id).unwrap(), Some(6));
+        assert_eq!(storage.latest_log_index(room_id).unwrap(), Some(9));
+
+        let frames = storage.load_frames(room_id, 0, 100).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0], tombstone);
+        assert_eq!(frames[1].header.log_index(), 7);
+
+        storage.store_frame(room_id, 10, &create_test_frame(room_id, 10)).unwrap();
+        assert!(storage.truncate_frames(room_id, 12, &tombstone).is_err());
+    }
+
     #[test]
     fn test_new_storage_is_empty() {
         let storage = MemoryStorage::new();
@@ -471,7 +594,7 @@ mod tests {
 
         // Create rooms explicitly
         for room_id in [100u128, 200, 300] {
-            let metadata = StoredRoomMetadata { creator: room_id as u64, created_at_secs: 0 };
+            let metadata = StoredRoomMetadata::new(room_id as u64, 0);
             storage.create_room(room_id, &metadata).unwrap();
         }
 
@@ -485,7 +608,7 @@ mod tests {
     fn test_create_room() {
         let storage = MemoryStorage::new();
         let room_id = 100u128;
-        let metadata = StoredRoomMetadata { creator: 42, created_at_secs: 1_234_567_890 };
+        let metadata = StoredRoomMetadata::new(42, 1_234_567_890);
 
         storage.create_room(room_id, &metadata).unwrap();
 
@@ -498,8 +621,8 @@ mod tests {
     fn test_create_room_idempotent() {
         let storage = MemoryStorage::new();
         let room_id = 100u128;
-        let metadata1 = StoredRoomMetadata { creator: 42, created_at_secs: 100 };
-        let metadata2 = StoredRoomMetadata { creator: 99, created_at_secs: 200 };
+        let metadata1 = StoredRoomMetadata::new(42, 100);
+        let metadata2 = StoredRoomMetadata::new(99, 200);
 
         storage.create_room(room_id, &metadata1).unwrap();
         storage.create_room(room_id, &metadata2).unwrap(); // Should not overwrite
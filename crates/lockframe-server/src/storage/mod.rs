@@ -7,15 +7,26 @@ mod chaotic;
 mod error;
 mod memory;
 mod redb;
+mod sled;
+mod sqlite;
+mod tiered;
+mod wal;
 
 pub use chaotic::ChaoticStorage;
 pub use error::StorageError;
 use lockframe_core::mls::MlsGroupState;
-use lockframe_proto::Frame;
+use lockframe_proto::{Frame, payloads::admin::RoomKind};
 pub use memory::MemoryStorage;
 use serde::{Deserialize, Serialize};
 
-pub use self::redb::RedbStorage;
+pub use self::{
+    redb::RedbStorage,
+    sled::{SledConfig, SledStorage},
+    sqlite::SqliteStorage,
+    tiered::{BlobStore, FsBlobStore, MemoryBlobStore, TierConfig, TieredStorage},
+    wal::{WalConfig, WalStorage},
+};
+use crate::acl::RoomAcl;
 
 /// Metadata about a room stored in the ROOMS table.
 ///
@@ -27,6 +38,34 @@ pub struct StoredRoomMetadata {
     pub creator: u64,
     /// Unix timestamp (seconds) when room was created.
     pub created_at_secs: u64,
+    /// Members, roles and bans. Rooms stored before ACLs existed load with
+    /// an empty list.
+    #[serde(default)]
+    pub acl: RoomAcl,
+    /// Log index of the `CloseRoom` frame, once the owner has closed the
+    /// room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<u64>,
+    /// Name the room is listed under in the room directory, if listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listing: Option<String>,
+    /// Kind of room. Rooms stored before kinds existed load as standard.
+    #[serde(default)]
+    pub kind: RoomKind,
+}
+
+impl StoredRoomMetadata {
+    /// Metadata for an open, standard room with an empty ACL.
+    pub fn new(creator: u64, created_at_secs: u64) -> Self {
+        Self {
+            creator,
+            created_at_secs,
+            acl: RoomAcl::default(),
+            closed_at: None,
+            listing: None,
+            kind: RoomKind::Standard,
+        }
+    }
 }
 
 /// Storage abstraction for frames and MLS group state
@@ -67,6 +106,55 @@ pub trait Storage: Clone + Send + Sync + 'static {
         limit: usize,
     ) -> Result<Vec<Frame>, StorageError>;
 
+    /// Lowest log index still stored for a room. `None` if no frames stored.
+    ///
+    /// Zero unless history was truncated, in which case this is the index of
+    /// the tombstone.
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError>;
+
+    /// Total encoded size in bytes of the frames stored for a room,
+    /// tombstones included. Zero if no frames stored.
+    ///
+    /// May scan the room's log; callers should cache the result.
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError>;
+
+    /// Drop history before `first_kept`, leaving `tombstone` in its place.
+    ///
+    /// Atomically deletes every frame below `first_kept - 1` and replaces the
+    /// frame at `first_kept - 1` with `tombstone`. The latest log index is
+    /// unchanged, so sequencing continues where it was.
+    ///
+    /// # Invariants
+    ///
+    /// - Pre: `1 <= first_kept <= latest_log_index + 1`
+    /// - Pre: `tombstone` has `log_index == first_kept - 1`
+    /// - Post: `earliest_log_index == Some(first_kept - 1)`
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError>;
+
+    /// Overwrite the frame stored at `log_index`, e.g. to strip an expired
+    /// message. The latest log index is unchanged.
+    ///
+    /// Returns `StorageError::NotFound` if no frame is stored there.
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError>;
+
+    /// Move old history out of this backend, if it has somewhere to put it.
+    /// Returns how many frames moved. Reads are unaffected.
+    ///
+    /// Called on every compaction pass; the default keeps everything.
+    fn offload_history(&self, _room_id: u128) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
     /// Store MLS group state for a room
     ///
     /// Overwrites any existing state for this room.
@@ -113,4 +201,35 @@ pub trait Storage: Clone + Send + Sync + 'static {
     /// Returns `None` if room doesn't exist in the ROOMS table.
     fn load_room_metadata(&self, room_id: u128)
     -> Result<Option<StoredRoomMetadata>, StorageError>;
+
+    /// Replace room metadata.
+    ///
+    /// Used when membership changes. Creates the room entry if missing.
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError>;
+
+    /// Make every completed write durable.
+    ///
+    /// Backends that sync on each write have nothing to do.
+    fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+/// Tombstone position for a truncation keeping frames from `first_kept`.
+///
+/// Returns `StorageError::NotFound` if `first_kept` is zero or past the end
+/// of the log, so backends share one precondition check.
+fn tombstone_index(
+    room_id: u128,
+    first_kept: u64,
+    latest: Option<u64>,
+) -> Result<u64, StorageError> {
+    match (first_kept.checked_sub(1), latest) {
+        (Some(index), Some(latest)) if index <= latest => Ok(index),
+        _ => Err(StorageError::NotFound { room_id, log_index: first_kept }),
+    }
 }
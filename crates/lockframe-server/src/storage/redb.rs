@@ -176,6 +176,113 @@ impl Storage for RedbStorage {
         Ok(frames)
     }
 
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        let txn = self.db.begin_read().map_err(|e| StorageError::Io(e.to_string()))?;
+        let table = txn.open_table(FRAMES).map_err(|e| StorageError::Io(e.to_string()))?;
+
+        let start_key = encode_frame_key(room_id, 0);
+        let end_key = encode_frame_key(room_id, u64::MAX);
+
+        let mut results = table
+            .range(start_key.as_slice()..=end_key.as_slice())
+            .map_err(|e| StorageError::Io(e.to_string()))?;
+
+        match results.next() {
+            Some(result) => {
+                let (key, _) = result.map_err(|e| StorageError::Io(e.to_string()))?;
+                Ok(Some(decode_frame_key(key.value()).1))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        let txn = self.db.begin_read().map_err(|e| StorageError::Io(e.to_string()))?;
+        let table = txn.open_table(FRAMES).map_err(|e| StorageError::Io(e.to_string()))?;
+
+        let start_key = encode_frame_key(room_id, 0);
+        let end_key = encode_frame_key(room_id, u64::MAX);
+
+        let mut total = 0;
+        for result in table
+            .range(start_key.as_slice()..=end_key.as_slice())
+            .map_err(|e| StorageError::Io(e.to_string()))?
+        {
+            let (_, value) = result.map_err(|e| StorageError::Io(e.to_string()))?;
+            total += value.value().len() as u64;
+        }
+        Ok(total)
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        let txn = self.db.begin_write().map_err(|e| StorageError::Io(e.to_string()))?;
+
+        {
+            let mut table = txn.open_table(FRAMES).map_err(|e| StorageError::Io(e.to_string()))?;
+
+            let latest = self.compute_latest_log_index(&table, room_id)?;
+            let tombstone_index = super::tombstone_index(room_id, first_kept, latest)?;
+            debug_assert_eq!(tombstone.header.log_index(), tombstone_index);
+
+            let start_key = encode_frame_key(room_id, 0);
+            let end_key = encode_frame_key(room_id, tombstone_index);
+            table
+                .retain_in(start_key.as_slice()..end_key.as_slice(), |_, _| false)
+                .map_err(|e| StorageError::Io(e.to_string()))?;
+
+            let mut frame_bytes = Vec::with_capacity(128 + tombstone.payload.len());
+            tombstone
+                .encode(&mut frame_bytes)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+            table
+                .insert(end_key.as_slice(), frame_bytes.as_slice())
+                .map_err(|e| StorageError::Io(e.to_string()))?;
+        }
+
+        txn.commit().map_err(|e| StorageError::Io(e.to_string()))?;
+
+        Ok(())
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let txn = self.db.begin_write().map_err(|e| StorageError::Io(e.to_string()))?;
+
+        {
+            let mut table = txn.open_table(FRAMES).map_err(|e| StorageError::Io(e.to_string()))?;
+
+            let key = encode_frame_key(room_id, log_index);
+            let exists =
+                table.get(key.as_slice()).map_err(|e| StorageError::Io(e.to_string()))?.is_some();
+            if !exists {
+                return Err(StorageError::NotFound { room_id, log_index });
+            }
+
+            let mut frame_bytes = Vec::with_capacity(128 + frame.payload.len());
+            frame
+                .encode(&mut frame_bytes)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+            table
+                .insert(key.as_slice(), frame_bytes.as_slice())
+                .map_err(|e| StorageError::Io(e.to_string()))?;
+        }
+
+        txn.commit().map_err(|e| StorageError::Io(e.to_string()))?;
+
+        Ok(())
+    }
+
     fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
         let txn = self.db.begin_write().map_err(|e| StorageError::Io(e.to_string()))?;
 
@@ -335,6 +442,29 @@ impl Storage for RedbStorage {
             None => Ok(None),
         }
     }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(metadata, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        let txn = self.db.begin_write().map_err(|e| StorageError::Io(e.to_string()))?;
+
+        {
+            let mut table = txn.open_table(ROOMS).map_err(|e| StorageError::Io(e.to_string()))?;
+            table
+                .insert(encode_room_key(room_id).as_slice(), bytes.as_slice())
+                .map_err(|e| StorageError::Io(e.to_string()))?;
+        }
+
+        txn.commit().map_err(|e| StorageError::Io(e.to_string()))?;
+
+        Ok(())
+    }
 }
 
 /// Encode (`room_id`, `log_index`) as 24-byte big-endian key.
@@ -597,7 +727,7 @@ mod tests {
         assert_eq!(storage.list_rooms().unwrap(), vec![]);
 
         for room_id in [100u128, 200, 300] {
-            let metadata = StoredRoomMetadata { creator: room_id as u64, created_at_secs: 0 };
+            let metadata = StoredRoomMetadata::new(room_id as u64, 0);
             storage.create_room(room_id, &metadata).unwrap();
         }
 
@@ -612,7 +742,7 @@ mod tests {
         let storage = RedbStorage::open(dir.path().join("test.redb")).unwrap();
 
         let room_id = 100u128;
-        let metadata = StoredRoomMetadata { creator: 42, created_at_secs: 1_234_567_890 };
+        let metadata = StoredRoomMetadata::new(42, 1_234_567_890);
 
         storage.create_room(room_id, &metadata).unwrap();
 
@@ -627,8 +757,8 @@ mod tests {
         let storage = RedbStorage::open(dir.path().join("test.redb")).unwrap();
 
         let room_id = 100u128;
-        let metadata1 = StoredRoomMetadata { creator: 42, created_at_secs: 100 };
-        let metadata2 = StoredRoomMetadata { creator: 99, created_at_secs: 200 };
+        let metadata1 = StoredRoomMetadata::new(42, 100);
+        let metadata2 = StoredRoomMetadata::new(99, 200);
 
         storage.create_room(room_id, &metadata1).unwrap();
         storage.create_room(room_id, &metadata2).unwrap(); // Should not overwrite
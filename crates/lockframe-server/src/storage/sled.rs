@@ -0,0 +1,487 @@
+//! Sled-backed durable storage implementation.
+//!
+//! Sled is an embedded log-structured store, suited to single-node
+//! deployments where rooms reach millions of frames and per-row overhead in
+//! SQLite starts to dominate. Appends go to an in-memory log that is fsynced
+//! in groups every [`SledConfig::flush_every_ms`], so a burst of frames costs
+//! one sync instead of one per frame.
+//!
+//! Frames live in a single tree keyed by (`room_id`, `log_index`) in
+//! big-endian order, so a room's log is one contiguous key range. Sync reads
+//! are range scans and the latest index is the last key in the range.
+
+use std::path::Path;
+
+use lockframe_core::mls::MlsGroupState;
+use lockframe_proto::Frame;
+
+use super::{Storage, StorageError, StoredRoomMetadata};
+
+/// Tree: frames
+/// Key: (`room_id`: u128, `log_index`: u64) as big-endian bytes [24 bytes]
+/// Value: Frame bytes (header + payload concatenated)
+const FRAMES: &str = "frames";
+
+/// Tree: `mls_state`
+/// Key: `room_id` as big-endian bytes [16 bytes]
+/// Value: CBOR-encoded `MlsGroupState`
+const MLS_STATE: &str = "mls_state";
+
+/// Tree: `group_info`
+/// Key: `room_id` as big-endian bytes [16 bytes]
+/// Value: epoch (8 bytes BE) + `group_info` bytes
+const GROUP_INFO: &str = "group_info";
+
+/// Tree: rooms
+/// Key: `room_id` as big-endian bytes [16 bytes]
+/// Value: CBOR-encoded `StoredRoomMetadata`
+const ROOMS: &str = "rooms";
+
+/// Tuning for [`SledStorage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SledConfig {
+    /// Page cache size in bytes.
+    pub cache_capacity: u64,
+
+    /// Interval between group fsyncs. Frames written within the last interval
+    /// can be lost on power failure. `None` syncs every write before
+    /// returning, trading throughput for durability.
+    pub flush_every_ms: Option<u64>,
+}
+
+impl Default for SledConfig {
+    fn default() -> Self {
+        Self { cache_capacity: 1024 * 1024 * 1024, flush_every_ms: Some(100) }
+    }
+}
+
+/// Durable storage backed by Sled.
+///
+/// Thread-safe through Sled's lock-free trees. Clone is cheap (trees are
+/// reference counted).
+#[derive(Clone)]
+pub struct SledStorage {
+    db: sled::Db,
+    frames: sled::Tree,
+    mls_state: sled::Tree,
+    group_info: sled::Tree,
+    rooms: sled::Tree,
+    sync_every_write: bool,
+}
+
+impl SledStorage {
+    /// Open or create a Sled database at the given path with default tuning.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StorageError::Io` if the database cannot be opened or created.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::open_with_config(path, &SledConfig::default())
+    }
+
+    /// Open or create a Sled database at the given path.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StorageError::Io` if the database cannot be opened or created.
+    pub fn open_with_config(
+        path: impl AsRef<Path>,
+        config: &SledConfig,
+    ) -> Result<Self, StorageError> {
+        let db = sled::Config::new()
+            .path(path.as_ref())
+            .cache_capacity(config.cache_capacity)
+            .flush_every_ms(config.flush_every_ms)
+            .open()
+            .map_err(io)?;
+
+        Ok(Self {
+            frames: db.open_tree(FRAMES).map_err(io)?,
+            mls_state: db.open_tree(MLS_STATE).map_err(io)?,
+            group_info: db.open_tree(GROUP_INFO).map_err(io)?,
+            rooms: db.open_tree(ROOMS).map_err(io)?,
+            sync_every_write: config.flush_every_ms.is_none(),
+            db,
+        })
+    }
+
+    /// Sync all buffered writes to disk.
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush().map(|_| ()).map_err(io)
+    }
+
+    fn flush_if_unbatched(&self) -> Result<(), StorageError> {
+        if self.sync_every_write { self.flush() } else { Ok(()) }
+    }
+}
+
+impl Storage for SledStorage {
+    fn store_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let expected_index = self.latest_log_index(room_id)?.map_or(0, |latest| latest + 1);
+        if log_index != expected_index {
+            return Err(StorageError::Conflict { expected: expected_index, got: log_index });
+        }
+
+        let mut frame_bytes = Vec::with_capacity(128 + frame.payload.len());
+        frame.encode(&mut frame_bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        // Insert only if absent, so a concurrent writer that passed the same
+        // index check cannot overwrite a sequenced frame
+        let key = encode_frame_key(room_id, log_index);
+        self.frames
+            .compare_and_swap(key, None as Option<&[u8]>, Some(frame_bytes))
+            .map_err(io)?
+            .map_err(|_| StorageError::Conflict {
+                expected: log_index.saturating_add(1),
+                got: log_index,
+            })?;
+
+        self.flush_if_unbatched()
+    }
+
+    fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        let start_key = encode_frame_key(room_id, 0);
+        let end_key = encode_frame_key(room_id, u64::MAX);
+
+        self.frames
+            .range(start_key..=end_key)
+            .next_back()
+            .transpose()
+            .map_err(io)?
+            .map(|(key, _)| decode_frame_key(&key).map(|(_, log_index)| log_index))
+            .transpose()
+    }
+
+    fn load_frames(
+        &self,
+        room_id: u128,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<Frame>, StorageError> {
+        let start_key = encode_frame_key(room_id, from);
+        let end_key = encode_frame_key(room_id, u64::MAX);
+
+        self.frames
+            .range(start_key..=end_key)
+            .take(limit)
+            .map(|result| {
+                let (_, value) = result.map_err(io)?;
+                Frame::decode(&value).map_err(|e| StorageError::Serialization(e.to_string()))
+            })
+            .collect()
+    }
+
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        let start_key = encode_frame_key(room_id, 0);
+        let end_key = encode_frame_key(room_id, u64::MAX);
+
+        self.frames
+            .range(start_key..=end_key)
+            .next()
+            .transpose()
+            .map_err(io)?
+            .map(|(key, _)| decode_frame_key(&key).map(|(_, log_index)| log_index))
+            .transpose()
+    }
+
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        let start_key = encode_frame_key(room_id, 0);
+        let end_key = encode_frame_key(room_id, u64::MAX);
+
+        self.frames.range(start_key..=end_key).try_fold(0, |total, result| {
+            let (_, value) = result.map_err(io)?;
+            Ok(total + value.len() as u64)
+        })
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        let tombstone_index =
+            super::tombstone_index(room_id, first_kept, self.latest_log_index(room_id)?)?;
+        debug_assert_eq!(tombstone.header.log_index(), tombstone_index);
+
+        let mut tombstone_bytes = Vec::with_capacity(128 + tombstone.payload.len());
+        tombstone
+            .encode(&mut tombstone_bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        let mut batch = sled::Batch::default();
+        for key in self
+            .frames
+            .range(encode_frame_key(room_id, 0)..encode_frame_key(room_id, tombstone_index))
+            .keys()
+        {
+            batch.remove(key.map_err(io)?);
+        }
+        batch.insert(&encode_frame_key(room_id, tombstone_index), tombstone_bytes);
+
+        self.frames.apply_batch(batch).map_err(io)?;
+        self.flush_if_unbatched()
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let mut frame_bytes = Vec::with_capacity(128 + frame.payload.len());
+        frame.encode(&mut frame_bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        // Replace only if present, so a concurrent truncation is not undone
+        let key = encode_frame_key(room_id, log_index);
+        let Some(current) = self.frames.get(key).map_err(io)? else {
+            return Err(StorageError::NotFound { room_id, log_index });
+        };
+        self.frames
+            .compare_and_swap(key, Some(current), Some(frame_bytes))
+            .map_err(io)?
+            .map_err(|_| StorageError::NotFound { room_id, log_index })?;
+
+        self.flush_if_unbatched()
+    }
+
+    fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(state, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        self.mls_state.insert(encode_room_key(room_id), bytes).map_err(io)?;
+        self.flush_if_unbatched()
+    }
+
+    fn load_mls_state(&self, room_id: u128) -> Result<Option<MlsGroupState>, StorageError> {
+        self.mls_state
+            .get(encode_room_key(room_id))
+            .map_err(io)?
+            .map(|value| {
+                ciborium::from_reader(value.as_ref())
+                    .map_err(|e| StorageError::Serialization(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn store_group_info(
+        &self,
+        room_id: u128,
+        epoch: u64,
+        group_info: &[u8],
+    ) -> Result<(), StorageError> {
+        // Format: [epoch: 8 bytes BE][group_info bytes]
+        let mut value = Vec::with_capacity(8 + group_info.len());
+        value.extend_from_slice(&epoch.to_be_bytes());
+        value.extend_from_slice(group_info);
+
+        self.group_info.insert(encode_room_key(room_id), value).map_err(io)?;
+        self.flush_if_unbatched()
+    }
+
+    fn load_group_info(&self, room_id: u128) -> Result<Option<(u64, Vec<u8>)>, StorageError> {
+        let Some(value) = self.group_info.get(encode_room_key(room_id)).map_err(io)? else {
+            return Ok(None);
+        };
+
+        let (epoch_bytes, group_info) = value
+            .split_at_checked(8)
+            .ok_or_else(|| StorageError::Serialization("group_info value too short".to_string()))?;
+        let epoch = epoch_bytes.try_into().map(u64::from_be_bytes).map_err(|_| {
+            StorageError::Serialization("group_info epoch is not 8 bytes".to_string())
+        })?;
+
+        Ok(Some((epoch, group_info.to_vec())))
+    }
+
+    fn list_rooms(&self) -> Result<Vec<u128>, StorageError> {
+        self.rooms.iter().keys().map(|key| decode_room_key(&key.map_err(io)?)).collect()
+    }
+
+    fn create_room(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(metadata, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        // Already exists: don't overwrite
+        let _ = self
+            .rooms
+            .compare_and_swap(encode_room_key(room_id), None as Option<&[u8]>, Some(bytes))
+            .map_err(io)?;
+
+        self.flush_if_unbatched()
+    }
+
+    fn load_room_metadata(
+        &self,
+        room_id: u128,
+    ) -> Result<Option<StoredRoomMetadata>, StorageError> {
+        self.rooms
+            .get(encode_room_key(room_id))
+            .map_err(io)?
+            .map(|value| {
+                ciborium::from_reader(value.as_ref())
+                    .map_err(|e| StorageError::Serialization(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(metadata, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        self.rooms.insert(encode_room_key(room_id), bytes).map_err(io)?;
+
+        self.flush_if_unbatched()
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        SledStorage::flush(self)
+    }
+}
+
+// By value so it can be passed to `map_err` directly
+#[allow(clippy::needless_pass_by_value)]
+fn io(err: sled::Error) -> StorageError {
+    StorageError::Io(err.to_string())
+}
+
+/// Encode (`room_id`, `log_index`) as 24-byte big-endian key.
+///
+/// Layout: [`room_id`: 16 bytes BE][log_index: 8 bytes BE]
+/// This ensures lexicographic ordering matches numeric ordering.
+fn encode_frame_key(room_id: u128, log_index: u64) -> [u8; 24] {
+    let mut key = [0u8; 24];
+    key[..16].copy_from_slice(&room_id.to_be_bytes());
+    key[16..].copy_from_slice(&log_index.to_be_bytes());
+    key
+}
+
+/// Decode frame key back to (`room_id`, `log_index`).
+fn decode_frame_key(key: &[u8]) -> Result<(u128, u64), StorageError> {
+    let (room_key, index_bytes) = key
+        .split_at_checked(16)
+        .ok_or_else(|| StorageError::Serialization(format!("frame key is {} bytes", key.len())))?;
+    let log_index = index_bytes
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| StorageError::Serialization(format!("frame key is {} bytes", key.len())))?;
+
+    Ok((decode_room_key(room_key)?, log_index))
+}
+
+/// Encode `room_id` as 16-byte big-endian key.
+fn encode_room_key(room_id: u128) -> [u8; 16] {
+    room_id.to_be_bytes()
+}
+
+fn decode_room_key(key: &[u8]) -> Result<u128, StorageError> {
+    key.try_into()
+        .map(u128::from_be_bytes)
+        .map_err(|_| StorageError::Serialization(format!("room key is {} bytes", key.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_proto::{Frame, FrameHeader, Opcode};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn create_test_frame(room_id: u128, log_index: u64, payload: &[u8]) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(1);
+        header.set_epoch(0);
+        header.set_log_index(log_index);
+        Frame::new(header, Bytes::copy_from_slice(payload))
+    }
+
+    #[test]
+    fn test_frame_key_encoding() {
+        let room_id: u128 = 0x1234_5678_9ABC_DEF0_FEDC_BA98_7654_3210;
+        let key = encode_frame_key(room_id, 42);
+
+        assert_eq!(decode_frame_key(&key).unwrap(), (room_id, 42));
+        assert!(decode_frame_key(&key[..20]).is_err());
+    }
+
+    #[test]
+    fn test_store_frame_conflict() {
+        let dir = tempdir().unwrap();
+        let storage = SledStorage::open(dir.path().join("db")).unwrap();
+
+        let room_id = 100u128;
+        storage.store_frame(room_id, 0, &create_test_frame(room_id, 0, &[0u8; 16])).unwrap();
+
+        let gap = storage.store_frame(room_id, 2, &create_test_frame(room_id, 2, &[2u8; 16]));
+        assert_eq!(gap, Err(StorageError::Conflict { expected: 1, got: 2 }));
+
+        let rewrite = storage.store_frame(room_id, 0, &create_test_frame(room_id, 0, &[9u8; 16]));
+        assert_eq!(rewrite, Err(StorageError::Conflict { expected: 1, got: 0 }));
+    }
+
+    #[test]
+    fn test_range_scan_stays_within_room() {
+        let dir = tempdir().unwrap();
+        let storage = SledStorage::open(dir.path().join("db")).unwrap();
+
+        // Adjacent room IDs share a key prefix up to the last byte
+        for room_id in [100u128, 101] {
+            for i in 0..20 {
+                let frame = create_test_frame(room_id, i, &[i as u8; 16]);
+                storage.store_frame(room_id, i, &frame).unwrap();
+            }
+        }
+
+        let batch = storage.load_frames(100, 15, 10).unwrap();
+        assert_eq!(batch.len(), 5);
+        assert_eq!(batch[0].header.log_index(), 15);
+        assert!(batch.iter().all(|f| f.header.room_id() == 100));
+
+        assert_eq!(storage.latest_log_index(100).unwrap(), Some(19));
+        assert_eq!(storage.latest_log_index(99).unwrap(), None);
+    }
+
+    #[test]
+    fn test_state_survives_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("db");
+        let room_id = 100u128;
+
+        {
+            let storage = SledStorage::open(&path).unwrap();
+            let metadata = StoredRoomMetadata::new(42, 1_234_567_890);
+            storage.create_room(room_id, &metadata).unwrap();
+            storage.create_room(room_id, &StoredRoomMetadata::new(99, 0)).unwrap();
+            storage.store_frame(room_id, 0, &create_test_frame(room_id, 0, b"hello")).unwrap();
+            let state = MlsGroupState::new(room_id, 5, [42u8; 32], vec![100, 200]);
+            storage.store_mls_state(room_id, &state).unwrap();
+            storage.store_group_info(room_id, 5, b"group info").unwrap();
+            storage.flush().unwrap();
+        }
+
+        let storage = SledStorage::open(&path).unwrap();
+        assert_eq!(storage.list_rooms().unwrap(), vec![room_id]);
+        assert_eq!(storage.load_room_metadata(room_id).unwrap().unwrap().creator, 42);
+        assert_eq!(storage.load_frames(room_id, 0, 10).unwrap()[0].payload.as_ref(), b"hello");
+        assert_eq!(storage.load_mls_state(room_id).unwrap().unwrap().epoch, 5);
+        assert_eq!(storage.load_group_info(room_id).unwrap(), Some((5, b"group info".to_vec())));
+    }
+}
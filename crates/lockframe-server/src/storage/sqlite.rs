@@ -0,0 +1,583 @@
+//! SQLite-backed durable storage implementation.
+//!
+//! Runs in WAL mode so sync reads don't block the sequencer's appends. All
+//! rooms share one partitioned `frames` table keyed by (`room_id`,
+//! `log_index`), so creating a room never changes the schema.
+//!
+//! The schema is versioned with `PRAGMA user_version`. On open, every entry in
+//! [`MIGRATIONS`] past the stored version is applied in a single transaction.
+//! Existing migrations must never be edited; append a new one instead.
+
+#![allow(clippy::disallowed_types, reason = "Synchronous database access only")]
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use lockframe_core::mls::MlsGroupState;
+use lockframe_proto::Frame;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Storage, StorageError, StoredRoomMetadata};
+
+/// Schema migrations, in order. Migration `i` moves the schema from version
+/// `i` to `i + 1`.
+///
+/// Room IDs are 16-byte big-endian blobs so byte order matches numeric order.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE rooms (
+        room_id BLOB PRIMARY KEY,
+        metadata BLOB NOT NULL
+    ) WITHOUT ROWID;
+
+    CREATE TABLE frames (
+        room_id BLOB NOT NULL,
+        log_index INTEGER NOT NULL,
+        frame BLOB NOT NULL,
+        PRIMARY KEY (room_id, log_index)
+    ) WITHOUT ROWID;
+
+    CREATE TABLE mls_state (
+        room_id BLOB PRIMARY KEY,
+        state BLOB NOT NULL
+    ) WITHOUT ROWID;
+
+    CREATE TABLE group_info (
+        room_id BLOB PRIMARY KEY,
+        epoch INTEGER NOT NULL,
+        group_info BLOB NOT NULL
+    ) WITHOUT ROWID;",
+];
+
+/// How long a statement waits on a lock held by another connection (e.g. a
+/// backup tool) before failing.
+const BUSY_TIMEOUT_MS: u64 = 5_000;
+
+/// Durable storage backed by SQLite.
+///
+/// A single connection is shared behind a mutex. Clone is cheap (Arc).
+#[derive(Clone)]
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Open or create a SQLite database at the given path.
+    ///
+    /// Enables WAL mode and applies any pending schema migrations.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StorageError::Io` if the database cannot be opened, or if its
+    /// schema is newer than this build supports.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let mut conn = Connection::open(path.as_ref()).map_err(io)?;
+
+        conn.busy_timeout(std::time::Duration::from_millis(BUSY_TIMEOUT_MS)).map_err(io)?;
+        let mode: String = conn
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))
+            .map_err(io)?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(StorageError::Io(format!("failed to enable WAL mode, got {mode}")));
+        }
+        // FULL keeps committed frames across power loss. Clients treat a
+        // sequenced frame as final, so losing one would fork the log.
+        conn.pragma_update(None, "synchronous", "FULL").map_err(io)?;
+
+        migrate(&mut conn)?;
+
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// Current schema version of the database.
+    pub fn schema_version(&self) -> Result<usize, StorageError> {
+        let conn = self.lock()?;
+        schema_version(&conn)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, StorageError> {
+        self.conn.lock().map_err(|_| StorageError::Io("connection mutex poisoned".to_string()))
+    }
+}
+
+/// Apply pending migrations in one transaction.
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let txn = conn.transaction().map_err(io)?;
+
+    let version = schema_version(&txn)?;
+    if version > MIGRATIONS.len() {
+        return Err(StorageError::Io(format!(
+            "database schema version {version} is newer than supported version {}",
+            MIGRATIONS.len()
+        )));
+    }
+
+    for migration in &MIGRATIONS[version..] {
+        txn.execute_batch(migration).map_err(io)?;
+    }
+    txn.pragma_update(None, "user_version", MIGRATIONS.len()).map_err(io)?;
+
+    txn.commit().map_err(io)
+}
+
+fn schema_version(conn: &Connection) -> Result<usize, StorageError> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0)).map_err(io)
+}
+
+impl Storage for SqliteStorage {
+    fn store_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let mut frame_bytes = Vec::with_capacity(128 + frame.payload.len());
+        frame.encode(&mut frame_bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        let mut conn = self.lock()?;
+        let txn = conn.transaction().map_err(io)?;
+
+        let expected_index = latest_log_index(&txn, room_id)?.map_or(0, |latest| latest + 1);
+        if log_index != expected_index {
+            return Err(StorageError::Conflict { expected: expected_index, got: log_index });
+        }
+
+        txn.execute("INSERT INTO frames (room_id, log_index, frame) VALUES (?1, ?2, ?3)", params![
+            encode_room_key(room_id),
+            log_index,
+            frame_bytes
+        ])
+        .map_err(io)?;
+
+        txn.commit().map_err(io)
+    }
+
+    fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        let conn = self.lock()?;
+        latest_log_index(&conn, room_id)
+    }
+
+    fn load_frames(
+        &self,
+        room_id: u128,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<Frame>, StorageError> {
+        let conn = self.lock()?;
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT frame FROM frames WHERE room_id = ?1 AND log_index >= ?2
+                 ORDER BY log_index LIMIT ?3",
+            )
+            .map_err(io)?;
+
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let rows = stmt
+            .query_map(params![encode_room_key(room_id), from, limit], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .map_err(io)?;
+
+        let mut frames = Vec::new();
+        for bytes in rows {
+            let bytes = bytes.map_err(io)?;
+            frames.push(
+                Frame::decode(&bytes).map_err(|e| StorageError::Serialization(e.to_string()))?,
+            );
+        }
+
+        Ok(frames)
+    }
+
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.lock()?
+            .query_row(
+                "SELECT MIN(log_index) FROM frames WHERE room_id = ?1",
+                params![encode_room_key(room_id)],
+                |row| row.get(0),
+            )
+            .map_err(io)
+    }
+
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        let total: i64 = self
+            .lock()?
+            .query_row(
+                "SELECT COALESCE(SUM(LENGTH(frame)), 0) FROM frames WHERE room_id = ?1",
+                params![encode_room_key(room_id)],
+                |row| row.get(0),
+            )
+            .map_err(io)?;
+        Ok(total.unsigned_abs())
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        let mut tombstone_bytes = Vec::with_capacity(128 + tombstone.payload.len());
+        tombstone
+            .encode(&mut tombstone_bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        let mut conn = self.lock()?;
+        let txn = conn.transaction().map_err(io)?;
+
+        let tombstone_index =
+            super::tombstone_index(room_id, first_kept, latest_log_index(&txn, room_id)?)?;
+        debug_assert_eq!(tombstone.header.log_index(), tombstone_index);
+
+        let room_key = encode_room_key(room_id);
+        txn.execute("DELETE FROM frames WHERE room_id = ?1 AND log_index < ?2", params![
+            room_key,
+            tombstone_index
+        ])
+        .map_err(io)?;
+        txn.execute(
+            "INSERT OR REPLACE INTO frames (room_id, log_index, frame) VALUES (?1, ?2, ?3)",
+            params![room_key, tombstone_index, tombstone_bytes],
+        )
+        .map_err(io)?;
+
+        txn.commit().map_err(io)
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let mut frame_bytes = Vec::with_capacity(128 + frame.payload.len());
+        frame.encode(&mut frame_bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        let replaced =
+            self.lock()?
+                .execute(
+                    "UPDATE frames SET frame = ?3 WHERE room_id = ?1 AND log_index = ?2",
+                    params![encode_room_key(room_id), log_index, frame_bytes],
+                )
+                .map_err(io)?;
+
+        if replaced == 0 {
+            return Err(StorageError::NotFound { room_id, log_index });
+        }
+        Ok(())
+    }
+
+    fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(state, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        self.lock()?
+            .execute("INSERT OR REPLACE INTO mls_state (room_id, state) VALUES (?1, ?2)", params![
+                encode_room_key(room_id),
+                bytes
+            ])
+            .map_err(io)?;
+
+        Ok(())
+    }
+
+    fn load_mls_state(&self, room_id: u128) -> Result<Option<MlsGroupState>, StorageError> {
+        let bytes: Option<Vec<u8>> = self
+            .lock()?
+            .query_row(
+                "SELECT state FROM mls_state WHERE room_id = ?1",
+                params![encode_room_key(room_id)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io)?;
+
+        bytes
+            .map(|bytes| {
+                ciborium::from_reader(bytes.as_slice())
+                    .map_err(|e| StorageError::Serialization(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn store_group_info(
+        &self,
+        room_id: u128,
+        epoch: u64,
+        group_info: &[u8],
+    ) -> Result<(), StorageError> {
+        self.lock()?
+            .execute(
+                "INSERT OR REPLACE INTO group_info (room_id, epoch, group_info) VALUES (?1, ?2, ?3)",
+                params![encode_room_key(room_id), epoch, group_info],
+            )
+            .map_err(io)?;
+
+        Ok(())
+    }
+
+    fn load_group_info(&self, room_id: u128) -> Result<Option<(u64, Vec<u8>)>, StorageError> {
+        self.lock()?
+            .query_row(
+                "SELECT epoch, group_info FROM group_info WHERE room_id = ?1",
+                params![encode_room_key(room_id)],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(io)
+    }
+
+    fn list_rooms(&self) -> Result<Vec<u128>, StorageError> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare_cached("SELECT room_id FROM rooms").map_err(io)?;
+        let rows = stmt.query_map([], |row| row.get::<_, Vec<u8>>(0)).map_err(io)?;
+
+        let mut rooms = Vec::new();
+        for key in rows {
+            rooms.push(decode_room_key(&key.map_err(io)?)?);
+        }
+
+        Ok(rooms)
+    }
+
+    fn create_room(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(metadata, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        // Already exists: don't overwrite
+        self.lock()?
+            .execute("INSERT OR IGNORE INTO rooms (room_id, metadata) VALUES (?1, ?2)", params![
+                encode_room_key(room_id),
+                bytes
+            ])
+            .map_err(io)?;
+
+        Ok(())
+    }
+
+    fn load_room_metadata(
+        &self,
+        room_id: u128,
+    ) -> Result<Option<StoredRoomMetadata>, StorageError> {
+        let bytes: Option<Vec<u8>> = self
+            .lock()?
+            .query_row(
+                "SELECT metadata FROM rooms WHERE room_id = ?1",
+                params![encode_room_key(room_id)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io)?;
+
+        bytes
+            .map(|bytes| {
+                ciborium::from_reader(bytes.as_slice())
+                    .map_err(|e| StorageError::Serialization(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(metadata, &mut bytes)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        self.lock()?
+            .execute("INSERT OR REPLACE INTO rooms (room_id, metadata) VALUES (?1, ?2)", params![
+                encode_room_key(room_id),
+                bytes
+            ])
+            .map_err(io)?;
+
+        Ok(())
+    }
+}
+
+fn latest_log_index(conn: &Connection, room_id: u128) -> Result<Option<u64>, StorageError> {
+    conn.query_row(
+        "SELECT MAX(log_index) FROM frames WHERE room_id = ?1",
+        params![encode_room_key(room_id)],
+        |row| row.get(0),
+    )
+    .map_err(io)
+}
+
+// By value so it can be passed to `map_err` directly
+#[allow(clippy::needless_pass_by_value)]
+fn io(err: rusqlite::Error) -> StorageError {
+    StorageError::Io(err.to_string())
+}
+
+/// Encode `room_id` as 16-byte big-endian key.
+fn encode_room_key(room_id: u128) -> [u8; 16] {
+    room_id.to_be_bytes()
+}
+
+fn decode_room_key(key: &[u8]) -> Result<u128, StorageError> {
+    key.try_into()
+        .map(u128::from_be_bytes)
+        .map_err(|_| StorageError::Serialization(format!("room key is {} bytes", key.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_proto::{Frame, FrameHeader, Opcode};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn create_test_frame(room_id: u128, log_index: u64, payload: &[u8]) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_sender_id(1);
+        header.set_epoch(0);
+        header.set_log_index(log_index);
+        Frame::new(header, Bytes::copy_from_slice(payload))
+    }
+
+    #[test]
+    fn test_migrations_applied_once() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.sqlite");
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+        drop(storage);
+
+        // Reopening must not re-run migrations (CREATE TABLE would fail)
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_newer_schema_is_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.sqlite");
+
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+        drop(conn);
+
+        assert!(matches!(SqliteStorage::open(&path), Err(StorageError::Io(_))));
+    }
+
+    #[test]
+    fn test_store_frame_conflict() {
+        let dir = tempdir().unwrap();
+        let storage = SqliteStorage::open(dir.path().join("test.sqlite")).unwrap();
+
+        let room_id = 100u128;
+        storage.store_frame(room_id, 0, &create_test_frame(room_id, 0, &[0u8; 16])).unwrap();
+
+        let result = storage.store_frame(room_id, 2, &create_test_frame(room_id, 2, &[2u8; 16]));
+        assert_eq!(result, Err(StorageError::Conflict { expected: 1, got: 2 }));
+    }
+
+    #[test]
+    fn test_load_frames_pagination_across_rooms() {
+        let dir = tempdir().unwrap();
+        let storage = SqliteStorage::open(dir.path().join("test.sqlite")).unwrap();
+
+        for room_id in [100u128, 101] {
+            for i in 0..20 {
+                let frame = create_test_frame(room_id, i, &[i as u8; 16]);
+                storage.store_frame(room_id, i, &frame).unwrap();
+            }
+        }
+
+        let batch = storage.load_frames(100, 15, 10).unwrap();
+        assert_eq!(batch.len(), 5);
+        assert_eq!(batch[0].header.log_index(), 15);
+        assert!(batch.iter().all(|f| f.header.room_id() == 100));
+
+        assert_eq!(storage.latest_log_index(101).unwrap(), Some(19));
+        assert_eq!(storage.latest_log_index(999).unwrap(), None);
+    }
+
+    #[test]
+    fn test_stored_bytes_per_room() {
+        let dir = tempdir().unwrap();
+        let storage = SqliteStorage::open(dir.path().join("test.sqlite")).unwrap();
+
+        for i in 0..4 {
+            storage.store_frame(100, i, &create_test_frame(100, i, &[0u8; 16])).unwrap();
+        }
+        storage.store_frame(101, 0, &create_test_frame(101, 0, &[0u8; 100])).unwrap();
+
+        let frame_len = (FrameHeader::SIZE + 16) as u64;
+        assert_eq!(storage.stored_bytes(100).unwrap(), 4 * frame_len);
+        assert_eq!(storage.stored_bytes(101).unwrap(), (FrameHeader::SIZE + 100) as u64);
+        assert_eq!(storage.stored_bytes(999).unwrap(), 0);
+
+        storage.truncate_frames(100, 3, &create_test_frame(100, 2, &[])).unwrap();
+        assert_eq!(storage.stored_bytes(100).unwrap(), frame_len + FrameHeader::SIZE as u64);
+    }
+
+    #[test]
+    fn test_state_survives_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.sqlite");
+        let room_id = 100u128;
+
+        {
+            let storage = SqliteStorage::open(&path).unwrap();
+            let metadata = StoredRoomMetadata::new(42, 1_234_567_890);
+            storage.create_room(room_id, &metadata).unwrap();
+            storage.store_frame(room_id, 0, &create_test_frame(room_id, 0, b"hello")).unwrap();
+            let state = MlsGroupState::new(room_id, 5, [42u8; 32], vec![100, 200]);
+            storage.store_mls_state(room_id, &state).unwrap();
+            storage.store_group_info(room_id, 5, b"group info").unwrap();
+        }
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.list_rooms().unwrap(), vec![room_id]);
+        assert_eq!(storage.load_room_metadata(room_id).unwrap().unwrap().creator, 42);
+        assert_eq!(storage.load_frames(room_id, 0, 10).unwrap()[0].payload.as_ref(), b"hello");
+        assert_eq!(storage.load_mls_state(room_id).unwrap().unwrap().epoch, 5);
+        assert_eq!(storage.load_group_info(room_id).unwrap(), Some((5, b"group info".to_vec())));
+    }
+
+    #[test]
+    fn test_create_room_idempotent() {
+        let dir = tempdir().unwrap();
+        let storage = SqliteStorage::open(dir.path().join("test.sqlite")).unwrap();
+
+        let room_id = 100u128;
+        storage.create_room(room_id, &StoredRoomMetadata::new(42, 100)).unwrap();
+        storage.create_room(room_id, &StoredRoomMetadata::new(99, 200)).unwrap();
+
+        assert_eq!(storage.load_room_metadata(room_id).unwrap().unwrap().creator, 42);
+    }
+
+    #[test]
+    fn test_overwrites_keep_latest() {
+        let dir = tempdir().unwrap();
+        let storage = SqliteStorage::open(dir.path().join("test.sqlite")).unwrap();
+
+        let room_id = 100u128;
+        storage.store_group_info(room_id, 1, b"epoch1").unwrap();
+        storage.store_group_info(room_id, 2, b"epoch2").unwrap();
+        storage
+            .store_mls_state(room_id, &MlsGroupState::new(room_id, 1, [1u8; 32], vec![1]))
+            .unwrap();
+        storage
+            .store_mls_state(room_id, &MlsGroupState::new(room_id, 2, [2u8; 32], vec![1, 2]))
+            .unwrap();
+
+        assert_eq!(storage.load_group_info(room_id).unwrap(), Some((2, b"epoch2".to_vec())));
+        assert_eq!(storage.load_mls_state(room_id).unwrap().unwrap().members, vec![1, 2]);
+    }
+}
@@ -0,0 +1,676 @@
+//! Hot/cold tiered frame history.
+//!
+//! [`TieredStorage`] keeps each room's recent frames in a hot backend and
+//! moves older history to a [`BlobStore`] (an object store such as S3, or a
+//! directory) in immutable segments of [`TierConfig::segment_frames`] frames.
+//! Reads below the hot tier are served from the segments, so sync is
+//! unchanged for clients.
+//!
+//! Offloading runs from [`Storage::offload_history`], which the driver calls
+//! on every compaction pass. Whole segments are uploaded first, then the hot
+//! backend's copy is truncated, leaving a tombstone at the segment boundary
+//! that only this layer sees. A crash in between leaves the frames in both
+//! tiers; reads prefer the cold copy and the next pass finishes the
+//! truncation.
+//!
+//! Room metadata, MLS state and `GroupInfo` always stay in the hot backend.
+//!
+//! # Segment keys
+//!
+//! ```text
+//! <room_id as 32 hex digits>/<first log index>-<last log index>.seg
+//! ```
+//!
+//! Indices are zero-padded to 20 digits so keys sort in log order. A segment
+//! is the concatenation of its encoded frames.
+
+#![allow(clippy::disallowed_types, reason = "Segment index shared by clones")]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use lockframe_core::mls::MlsGroupState;
+use lockframe_proto::{Frame, FrameHeader};
+
+use super::{Storage, StorageError, StoredRoomMetadata};
+use crate::retention::tombstone_frame;
+
+/// Flat key/value store for immutable history segments.
+///
+/// Implement this over an object store client to keep cold history off the
+/// server's disk. Calls block the caller, as every [`Storage`] call does.
+pub trait BlobStore: Send + Sync + 'static {
+    /// Store `bytes` under `key`, replacing any previous value.
+    ///
+    /// # Errors
+    ///
+    /// `StorageError::Io` if the store cannot be written.
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError>;
+
+    /// Bytes stored under `key`. `None` if there are none.
+    ///
+    /// # Errors
+    ///
+    /// `StorageError::Io` if the store cannot be read.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Every key starting with `prefix`, in any order.
+    ///
+    /// # Errors
+    ///
+    /// `StorageError::Io` if the store cannot be listed.
+    fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError>;
+
+    /// Remove `key`. Removing a missing key succeeds.
+    ///
+    /// # Errors
+    ///
+    /// `StorageError::Io` if the store cannot be written.
+    fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Blobs kept in memory, for tests and simulation.
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryBlobStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn blobs(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<String, Vec<u8>>>, StorageError> {
+        self.blobs.lock().map_err(|_| StorageError::Io("blob store mutex poisoned".into()))
+    }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        self.blobs()?.insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.blobs()?.get(key).cloned())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        Ok(self.blobs()?.keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.blobs()?.remove(key);
+        Ok(())
+    }
+}
+
+/// Blobs kept as files under a directory, one file per key.
+///
+/// Useful on its own for moving history to a separate (larger, slower)
+/// volume, or over a mounted object store bucket.
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    /// Use `root` as the store, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// `StorageError::Io` if the directory cannot be created.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, StorageError> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write aside and rename so readers never see a partial segment
+        let partial = path.with_extension("partial");
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match fs::read(self.path(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let (dir, name_prefix) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+        let entries = match fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut keys = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name.starts_with(name_prefix)
+                && Path::new(name).extension().is_none_or(|ext| ext != "partial")
+            {
+                keys.push(if dir.is_empty() { name.to_string() } else { format!("{dir}/{name}") });
+            }
+        }
+        Ok(keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// When history moves to the cold tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierConfig {
+    /// Frames per cold segment
+    pub segment_frames: u64,
+    /// Most recent frames per room that always stay hot
+    pub hot_frames: u64,
+}
+
+impl Default for TierConfig {
+    fn default() -> Self {
+        Self { segment_frames: 4096, hot_frames: 16_384 }
+    }
+}
+
+/// Storage that moves old frame history from `S` to a [`BlobStore`].
+pub struct TieredStorage<S, B> {
+    hot: S,
+    cold: Arc<B>,
+    config: TierConfig,
+    state: Arc<Mutex<TierState>>,
+}
+
+impl<S: Clone, B> Clone for TieredStorage<S, B> {
+    fn clone(&self) -> Self {
+        Self {
+            hot: self.hot.clone(),
+            cold: Arc::clone(&self.cold),
+            config: self.config,
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TierState {
+    /// Room ID → first index → last index of each cold segment, loaded from
+    /// the blob store on first use
+    segments: HashMap<u128, BTreeMap<u64, u64>>,
+    /// Most recently read segment, since sync pages through one in order
+    cached: Option<(String, Arc<Vec<Frame>>)>,
+}
+
+impl<S: Storage, B: BlobStore> TieredStorage<S, B> {
+    /// Tier `hot` over `cold`. Segments already in `cold` are found on first
+    /// access to each room.
+    pub fn new(hot: S, cold: B, config: TierConfig) -> Self {
+        Self {
+            hot,
+            cold: Arc::new(cold),
+            config: TierConfig { segment_frames: config.segment_frames.max(1), ..config },
+            state: Arc::new(Mutex::new(TierState::default())),
+        }
+    }
+
+    /// The hot backend.
+    pub fn hot(&self) -> &S {
+        &self.hot
+    }
+
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, TierState>, StorageError> {
+        self.state.lock().map_err(|_| StorageError::Io("tier state mutex poisoned".into()))
+    }
+
+    /// A room's cold segments, listing the blob store if not yet known.
+    fn segments(&self, room_id: u128) -> Result<BTreeMap<u64, u64>, StorageError> {
+        let mut state = self.state()?;
+        if let Some(segments) = state.segments.get(&room_id) {
+            return Ok(segments.clone());
+        }
+
+        let mut segments = BTreeMap::new();
+        for key in self.cold.list(&room_prefix(room_id))? {
+            if let Some((first, last)) = parse_segment_key(&key) {
+                segments.insert(first, last);
+            }
+        }
+        state.segments.insert(room_id, segments.clone());
+        Ok(segments)
+    }
+
+    /// Last log index held in the cold tier.
+    fn cold_last(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        Ok(self.segments(room_id)?.values().next_back().copied())
+    }
+
+    fn load_segment(
+        &self,
+        room_id: u128,
+        first: u64,
+        last: u64,
+    ) -> Result<Arc<Vec<Frame>>, StorageError> {
+        let key = segment_key(room_id, first, last);
+        if let Some((cached_key, frames)) = &self.state()?.cached
+            && *cached_key == key
+        {
+            return Ok(Arc::clone(frames));
+        }
+
+        let bytes =
+            self.cold.get(&key)?.ok_or(StorageError::NotFound { room_id, log_index: first })?;
+        let frames = Arc::new(decode_segment(&bytes)?);
+        self.state()?.cached = Some((key, Arc::clone(&frames)));
+        Ok(frames)
+    }
+
+    fn put_segment(&self, room_id: u128, frames: &[Frame]) -> Result<(), StorageError> {
+        let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+            return Ok(());
+        };
+        let (first, last) = (first.header.log_index(), last.header.log_index());
+
+        let mut bytes = Vec::new();
+        for frame in frames {
+            frame.encode(&mut bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+        }
+        self.cold.put(&segment_key(room_id, first, last), &bytes)?;
+
+        let mut state = self.state()?;
+        state.cached = None;
+        if let Some(segments) = state.segments.get_mut(&room_id) {
+            segments.insert(first, last);
+        }
+        Ok(())
+    }
+
+    fn delete_segment(&self, room_id: u128, first: u64, last: u64) -> Result<(), StorageError> {
+        self.cold.delete(&segment_key(room_id, first, last))?;
+        let mut state = self.state()?;
+        state.cached = None;
+        if let Some(segments) = state.segments.get_mut(&room_id) {
+            segments.remove(&first);
+        }
+        Ok(())
+    }
+
+    /// Apply a retention truncation whose tombstone lands in the cold tier.
+    fn truncate_cold(
+        &self,
+        room_id: u128,
+        segments: &BTreeMap<u64, u64>,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        let tombstone_index = tombstone.header.log_index();
+        for (&first, &last) in segments.range(..=tombstone_index) {
+            if last < tombstone_index {
+                self.delete_segment(room_id, first, last)?;
+                continue;
+            }
+            // The segment holding the tombstone is rewritten from it onwards
+            let mut kept = vec![tombstone.clone()];
+            kept.extend(
+                self.load_segment(room_id, first, last)?
+                    .iter()
+                    .filter(|frame| frame.header.log_index() > tombstone_index)
+                    .cloned(),
+            );
+            self.put_segment(room_id, &kept)?;
+            if first != tombstone_index {
+                self.delete_segment(room_id, first, last)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage, B: BlobStore> Storage for TieredStorage<S, B> {
+    fn store_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        self.hot.store_frame(room_id, log_index, frame)
+    }
+
+    fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.hot.latest_log_index(room_id)
+    }
+
+    fn load_frames(
+        &self,
+        room_id: u128,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<Frame>, StorageError> {
+        let segments = self.segments(room_id)?;
+        let Some(&cold_last) = segments.values().next_back() else {
+            return self.hot.load_frames(room_id, from, limit);
+        };
+
+        let mut frames = Vec::new();
+        for (&first, &last) in &segments {
+            if last < from {
+                continue;
+            }
+            if frames.len() >= limit {
+                return Ok(frames);
+            }
+            let segment = self.load_segment(room_id, first, last)?;
+            let wanted = limit - frames.len();
+            frames.extend(
+                segment
+                    .iter()
+                    .filter(|frame| frame.header.log_index() >= from)
+                    .take(wanted)
+                    .cloned(),
+            );
+        }
+
+        if frames.len() < limit {
+            // The hot tier's frame at `cold_last` is the offload tombstone
+            let hot_from = from.max(cold_last + 1);
+            frames.extend(self.hot.load_frames(room_id, hot_from, limit - frames.len())?);
+        }
+        Ok(frames)
+    }
+
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        match self.segments(room_id)?.keys().next() {
+            Some(&first) => Ok(Some(first)),
+            None => self.hot.earliest_log_index(room_id),
+        }
+    }
+
+    /// Only the hot tier counts, since that is what fills local disk.
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.hot.stored_bytes(room_id)
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        let tombstone_index =
+            super::tombstone_index(room_id, first_kept, self.latest_log_index(room_id)?)?;
+
+        let segments = self.segments(room_id)?;
+        match segments.values().next_back() {
+            Some(&cold_last) if tombstone_index <= cold_last => {
+                self.truncate_cold(room_id, &segments, tombstone)
+            },
+            _ => {
+                for (&first, &last) in &segments {
+                    self.delete_segment(room_id, first, last)?;
+                }
+                self.hot.truncate_frames(room_id, first_kept, tombstone)
+            },
+        }
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        let segments = self.segments(room_id)?;
+        let Some((&first, &last)) =
+            segments.range(..=log_index).next_back().filter(|(_, last)| log_index <= **last)
+        else {
+            return self.hot.replace_frame(room_id, log_index, frame);
+        };
+
+        let mut frames = self.load_segment(room_id, first, last)?.as_ref().clone();
+        let stored = frames
+            .iter_mut()
+            .find(|stored| stored.header.log_index() == log_index)
+            .ok_or(StorageError::NotFound { room_id, log_index })?;
+        *stored = frame.clone();
+        self.put_segment(room_id, &frames)
+    }
+
+    /// Upload whole segments of the room's oldest hot frames, keeping at
+    /// least [`TierConfig::hot_frames`] hot, then truncate the hot copy.
+    fn offload_history(&self, room_id: u128) -> Result<u64, StorageError> {
+        let Some(latest) = self.hot.latest_log_index(room_id)? else {
+            return Ok(0);
+        };
+        let mut first = match self.cold_last(room_id)? {
+            Some(cold_last) => cold_last + 1,
+            None => self.hot.earliest_log_index(room_id)?.unwrap_or(0),
+        };
+
+        let TierConfig { segment_frames, hot_frames } = self.config;
+        let mut moved = 0;
+        while (latest + 1).saturating_sub(first) >= segment_frames.saturating_add(hot_frames) {
+            let limit = usize::try_from(segment_frames).unwrap_or(usize::MAX);
+            let frames = self.hot.load_frames(room_id, first, limit)?;
+            if frames.len() != limit {
+                break;
+            }
+            self.put_segment(room_id, &frames)?;
+            first += segment_frames;
+            moved += segment_frames;
+        }
+
+        // Leaves a tombstone that reads never reach, and finishes a truncation
+        // interrupted by a crash
+        let hot_earliest = self.hot.earliest_log_index(room_id)?.unwrap_or(0);
+        if first > 0 && hot_earliest < first - 1 {
+            self.hot.truncate_frames(room_id, first, &tombstone_frame(room_id, first))?;
+        }
+        Ok(moved)
+    }
+
+    fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
+        self.hot.store_mls_state(room_id, state)
+    }
+
+    fn load_mls_state(&self, room_id: u128) -> Result<Option<MlsGroupState>, StorageError> {
+        self.hot.load_mls_state(room_id)
+    }
+
+    fn store_group_info(
+        &self,
+        room_id: u128,
+        epoch: u64,
+        group_info: &[u8],
+    ) -> Result<(), StorageError> {
+        self.hot.store_group_info(room_id, epoch, group_info)
+    }
+
+    fn load_group_info(&self, room_id: u128) -> Result<Option<(u64, Vec<u8>)>, StorageError> {
+        self.hot.load_group_info(room_id)
+    }
+
+    fn list_rooms(&self) -> Result<Vec<u128>, StorageError> {
+        self.hot.list_rooms()
+    }
+
+    fn create_room(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        self.hot.create_room(room_id, metadata)
+    }
+
+    fn load_room_metadata(
+        &self,
+        room_id: u128,
+    ) -> Result<Option<StoredRoomMetadata>, StorageError> {
+        self.hot.load_room_metadata(room_id)
+    }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        self.hot.update_room_metadata(room_id, metadata)
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.hot.flush()
+    }
+}
+
+fn room_prefix(room_id: u128) -> String {
+    format!("{room_id:032x}/")
+}
+
+fn segment_key(room_id: u128, first: u64, last: u64) -> String {
+    format!("{room_id:032x}/{first:020}-{last:020}.seg")
+}
+
+fn parse_segment_key(key: &str) -> Option<(u64, u64)> {
+    let (_, name) = key.rsplit_once('/')?;
+    let (first, last) = name.strip_suffix(".seg")?.split_once('-')?;
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+fn decode_segment(mut bytes: &[u8]) -> Result<Vec<Frame>, StorageError> {
+    let mut frames = Vec::new();
+    while !bytes.is_empty() {
+        let frame = Frame::decode(bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+        bytes = bytes.get(FrameHeader::SIZE + frame.payload.len()..).unwrap_or_default();
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_proto::Opcode;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const ROOM: u128 = 0x1234;
+
+    fn frame(log_index: u64) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(ROOM);
+        header.set_log_index(log_index);
+        Frame::new(header, Bytes::from(log_index.to_be_bytes().to_vec()))
+    }
+
+    fn indices(frames: &[Frame]) -> Vec<u64> {
+        frames.iter().map(|frame| frame.header.log_index()).collect()
+    }
+
+    fn tiered(
+        hot: MemoryStorage,
+        cold: MemoryBlobStore,
+    ) -> TieredStorage<MemoryStorage, MemoryBlobStore> {
+        TieredStorage::new(hot, cold, TierConfig { segment_frames: 10, hot_frames: 5 })
+    }
+
+    #[test]
+    fn old_history_moves_cold_and_reads_stay_whole() {
+        let storage = tiered(MemoryStorage::new(), MemoryBlobStore::new());
+        for i in 0..37 {
+            storage.store_frame(ROOM, i, &frame(i)).unwrap();
+        }
+
+        // Three whole segments can go while keeping five frames hot
+        assert_eq!(storage.offload_history(ROOM).unwrap(), 30);
+        assert_eq!(storage.offload_history(ROOM).unwrap(), 0);
+        assert_eq!(storage.hot().earliest_log_index(ROOM).unwrap(), Some(29));
+        assert_eq!(storage.earliest_log_index(ROOM).unwrap(), Some(0));
+        assert_eq!(storage.latest_log_index(ROOM).unwrap(), Some(36));
+
+        let all = storage.load_frames(ROOM, 0, 100).unwrap();
+        assert_eq!(indices(&all), (0..37).collect::<Vec<_>>());
+        assert_eq!(all[29], frame(29));
+        assert_eq!(
+            indices(&storage.load_frames(ROOM, 25, 8).unwrap()),
+            (25..33).collect::<Vec<_>>()
+        );
+
+        // Sequencing carries on in the hot tier
+        storage.store_frame(ROOM, 37, &frame(37)).unwrap();
+        assert_eq!(indices(&storage.load_frames(ROOM, 36, 10).unwrap()), vec![36, 37]);
+    }
+
+    #[test]
+    fn retention_truncates_across_tiers() {
+        let storage = tiered(MemoryStorage::new(), MemoryBlobStore::new());
+        for i in 0..40 {
+            storage.store_frame(ROOM, i, &frame(i)).unwrap();
+        }
+        storage.offload_history(ROOM).unwrap();
+
+        let tombstone = tombstone_frame(ROOM, 15);
+        storage.truncate_frames(ROOM, 15, &tombstone).unwrap();
+        assert_eq!(storage.earliest_log_index(ROOM).unwrap(), Some(14));
+        let frames = storage.load_frames(ROOM, 0, 100).unwrap();
+        assert_eq!(frames[0], tombstone);
+        assert_eq!(indices(&frames), (14..40).collect::<Vec<_>>());
+
+        // Past the cold tier, every segment goes
+        storage.truncate_frames(ROOM, 35, &tombstone_frame(ROOM, 35)).unwrap();
+        assert_eq!(
+            indices(&storage.load_frames(ROOM, 0, 100).unwrap()),
+            (34..40).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn segments_are_found_after_restart() {
+        let dir = tempdir().unwrap();
+        let hot = MemoryStorage::new();
+        let storage =
+            TieredStorage::new(hot.clone(), FsBlobStore::open(dir.path()).unwrap(), TierConfig {
+                segment_frames: 10,
+                hot_frames: 5,
+            });
+        for i in 0..20 {
+            storage.store_frame(ROOM, i, &frame(i)).unwrap();
+        }
+        assert_eq!(storage.offload_history(ROOM).unwrap(), 10);
+
+        let reopened =
+            TieredStorage::new(hot, FsBlobStore::open(dir.path()).unwrap(), TierConfig::default());
+        assert_eq!(
+            indices(&reopened.load_frames(ROOM, 0, 100).unwrap()),
+            (0..20).collect::<Vec<_>>()
+        );
+    }
+}
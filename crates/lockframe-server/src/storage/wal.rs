@@ -0,0 +1,463 @@
+//! Write-ahead log in front of another storage backend.
+//!
+//! [`WalStorage`] appends every frame and room metadata write to an
+//! append-only file and syncs it before forwarding the write to the wrapped
+//! backend. The driver persists a frame before it emits the broadcast, so a
+//! frame that reached any subscriber is durable in the log even if the
+//! backend had only buffered it (sled's group commit, for example) when the
+//! process died.
+//!
+//! On open the log is replayed into the backend: frames past the backend's
+//! latest index and room metadata are re-applied, then the backend is flushed
+//! and the log emptied. The log is also emptied whenever it grows past
+//! [`WalConfig::checkpoint_bytes`], after flushing the backend.
+//!
+//! # Record format
+//!
+//! ```text
+//! [kind: u8][body_len: u32 LE][checksum: u32 LE][body]
+//! ```
+//!
+//! A record whose length or checksum doesn't match marks a write torn by the
+//! crash; replay stops there.
+
+#![allow(clippy::disallowed_types, reason = "Serializing appends to one file")]
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use lockframe_core::mls::MlsGroupState;
+use lockframe_proto::Frame;
+
+use super::{Storage, StorageError, StoredRoomMetadata};
+
+const RECORD_HEADER_LEN: usize = 9;
+const KIND_FRAME: u8 = 1;
+const KIND_ROOM_CREATED: u8 = 2;
+const KIND_ROOM_UPDATED: u8 = 3;
+
+/// Write-ahead log settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalConfig {
+    /// Path of the log file
+    pub path: PathBuf,
+    /// Flush the backend and empty the log once it exceeds this size
+    pub checkpoint_bytes: u64,
+}
+
+impl WalConfig {
+    /// Log at `path` with the default checkpoint size.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), checkpoint_bytes: 64 * 1024 * 1024 }
+    }
+
+    /// Log at `<db_path>.wal`, next to a database file.
+    pub fn beside(db_path: impl AsRef<Path>) -> Self {
+        let mut path = db_path.as_ref().as_os_str().to_owned();
+        path.push(".wal");
+        Self::new(PathBuf::from(path))
+    }
+}
+
+/// A replayable write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WalRecord {
+    Frame { room_id: u128, log_index: u64, frame: Frame },
+    RoomCreated { room_id: u128, metadata: StoredRoomMetadata },
+    RoomUpdated { room_id: u128, metadata: StoredRoomMetadata },
+}
+
+impl WalRecord {
+    fn encode(&self) -> Result<Vec<u8>, StorageError> {
+        let (kind, room_id) = match self {
+            Self::Frame { room_id, .. } => (KIND_FRAME, room_id),
+            Self::RoomCreated { room_id, .. } => (KIND_ROOM_CREATED, room_id),
+            Self::RoomUpdated { room_id, .. } => (KIND_ROOM_UPDATED, room_id),
+        };
+
+        let mut body = room_id.to_le_bytes().to_vec();
+        match self {
+            Self::Frame { log_index, frame, .. } => {
+                body.extend_from_slice(&log_index.to_le_bytes());
+                frame.encode(&mut body).map_err(|e| StorageError::Serialization(e.to_string()))?;
+            },
+            Self::RoomCreated { metadata, .. } | Self::RoomUpdated { metadata, .. } => {
+                ciborium::ser::into_writer(metadata, &mut body)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            },
+        }
+
+        let body_len = u32::try_from(body.len())
+            .map_err(|_| StorageError::Serialization("WAL record too large".to_string()))?;
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + body.len());
+        record.push(kind);
+        record.extend_from_slice(&body_len.to_le_bytes());
+        record.extend_from_slice(&checksum(&body).to_le_bytes());
+        record.extend_from_slice(&body);
+        Ok(record)
+    }
+
+    /// Decode the record at the start of `bytes`, returning it and its
+    /// encoded length. `None` if the record is incomplete or corrupt.
+    fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        let header = bytes.get(..RECORD_HEADER_LEN)?;
+        let kind = header[0];
+        let body_len = u32::from_le_bytes(header[1..5].try_into().ok()?) as usize;
+        let expected = u32::from_le_bytes(header[5..9].try_into().ok()?);
+        let body = bytes.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + body_len)?;
+        if checksum(body) != expected {
+            return None;
+        }
+
+        let room_id = u128::from_le_bytes(body.get(..16)?.try_into().ok()?);
+        let rest = &body[16..];
+        let record = match kind {
+            KIND_FRAME => {
+                let log_index = u64::from_le_bytes(rest.get(..8)?.try_into().ok()?);
+                let frame = Frame::decode(&rest[8..]).ok()?;
+                Self::Frame { room_id, log_index, frame }
+            },
+            KIND_ROOM_CREATED => {
+                Self::RoomCreated { room_id, metadata: ciborium::de::from_reader(rest).ok()? }
+            },
+            KIND_ROOM_UPDATED => {
+                Self::RoomUpdated { room_id, metadata: ciborium::de::from_reader(rest).ok()? }
+            },
+            _ => return None,
+        };
+        Some((record, RECORD_HEADER_LEN + body_len))
+    }
+
+    /// Re-apply the write to `storage`, skipping frames it already holds.
+    fn apply(&self, storage: &impl Storage) -> Result<(), StorageError> {
+        match self {
+            Self::Frame { room_id, log_index, frame } => {
+                if storage.latest_log_index(*room_id)?.is_some_and(|latest| *log_index <= latest) {
+                    return Ok(());
+                }
+                storage.store_frame(*room_id, *log_index, frame)
+            },
+            Self::RoomCreated { room_id, metadata } => storage.create_room(*room_id, metadata),
+            Self::RoomUpdated { room_id, metadata } => {
+                storage.update_room_metadata(*room_id, metadata)
+            },
+        }
+    }
+}
+
+/// FNV-1a. Only needs to catch torn writes, not tampering.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193))
+}
+
+/// The open log file and its current size.
+struct WalFile {
+    file: File,
+    len: u64,
+}
+
+/// Storage wrapper that logs writes durably before applying them.
+///
+/// Clone shares the same log file and backend.
+#[derive(Clone)]
+pub struct WalStorage<S: Storage> {
+    inner: S,
+    wal: Arc<Mutex<WalFile>>,
+    checkpoint_bytes: u64,
+}
+
+impl<S: Storage> WalStorage<S> {
+    /// Open the log at `config.path`, replay it into `inner`, and start
+    /// logging.
+    ///
+    /// # Errors
+    ///
+    /// - `StorageError::Io` if the log cannot be opened, read or emptied
+    /// - Any error from `inner` while replaying
+    pub fn open(inner: S, config: &WalConfig) -> Result<Self, StorageError> {
+        let mut file =
+            OpenOptions::new().create(true).read(true).append(true).open(&config.path)?;
+
+        let replayed = replay(&mut file, &inner)?;
+        if replayed > 0 {
+            tracing::info!(replayed, path = %config.path.display(), "replayed write-ahead log");
+        }
+
+        let storage = Self {
+            inner,
+            wal: Arc::new(Mutex::new(WalFile { file, len: 0 })),
+            checkpoint_bytes: config.checkpoint_bytes,
+        };
+        storage.checkpoint()?;
+        Ok(storage)
+    }
+
+    /// Wrapped backend.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Flush the backend and empty the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns the backend's flush error or `StorageError::Io` if the log
+    /// cannot be truncated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the log mutex is poisoned.
+    pub fn checkpoint(&self) -> Result<(), StorageError> {
+        #[allow(clippy::expect_used)]
+        let mut wal = self.wal.lock().expect("WAL mutex poisoned");
+        self.checkpoint_locked(&mut wal)
+    }
+
+    fn checkpoint_locked(&self, wal: &mut WalFile) -> Result<(), StorageError> {
+        self.inner.flush()?;
+        wal.file.set_len(0)?;
+        wal.file.sync_all()?;
+        wal.len = 0;
+        Ok(())
+    }
+
+    /// Append a record durably, then apply it to the backend.
+    fn log_then<T>(
+        &self,
+        record: &WalRecord,
+        apply: impl FnOnce(&S) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        let bytes = record.encode()?;
+
+        #[allow(clippy::expect_used)]
+        let mut wal = self.wal.lock().expect("WAL mutex poisoned");
+        wal.file.write_all(&bytes)?;
+        wal.file.sync_data()?;
+        wal.len += bytes.len() as u64;
+
+        let result = apply(&self.inner)?;
+        if wal.len > self.checkpoint_bytes {
+            self.checkpoint_locked(&mut wal)?;
+        }
+        Ok(result)
+    }
+}
+
+/// Apply every intact record in `file` to `storage`, returning how many
+/// were read.
+fn replay(file: &mut File, storage: &impl Storage) -> Result<usize, StorageError> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let mut offset = 0;
+    let mut count = 0;
+    while let Some((record, len)) = bytes.get(offset..).and_then(WalRecord::decode) {
+        record.apply(storage)?;
+        offset += len;
+        count += 1;
+    }
+
+    if offset < bytes.len() {
+        tracing::warn!(discarded = bytes.len() - offset, "discarding torn write-ahead log tail");
+    }
+    Ok(count)
+}
+
+impl<S: Storage> Storage for WalStorage<S> {
+    fn store_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        // Check the index first so a rejected frame never reaches the log
+        let expected = self.inner.latest_log_index(room_id)?.map_or(0, |latest| latest + 1);
+        if log_index != expected {
+            return Err(StorageError::Conflict { expected, got: log_index });
+        }
+
+        let record = WalRecord::Frame { room_id, log_index, frame: frame.clone() };
+        self.log_then(&record, |inner| inner.store_frame(room_id, log_index, frame))
+    }
+
+    fn latest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.inner.latest_log_index(room_id)
+    }
+
+    fn load_frames(
+        &self,
+        room_id: u128,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<Frame>, StorageError> {
+        self.inner.load_frames(room_id, from, limit)
+    }
+
+    fn earliest_log_index(&self, room_id: u128) -> Result<Option<u64>, StorageError> {
+        self.inner.earliest_log_index(room_id)
+    }
+
+    fn stored_bytes(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.inner.stored_bytes(room_id)
+    }
+
+    fn truncate_frames(
+        &self,
+        room_id: u128,
+        first_kept: u64,
+        tombstone: &Frame,
+    ) -> Result<(), StorageError> {
+        // Compaction is repeated on the next pass if lost, so it isn't logged
+        self.inner.truncate_frames(room_id, first_kept, tombstone)
+    }
+
+    fn replace_frame(
+        &self,
+        room_id: u128,
+        log_index: u64,
+        frame: &Frame,
+    ) -> Result<(), StorageError> {
+        // Like compaction, expiry is repeated after a restart if lost
+        self.inner.replace_frame(room_id, log_index, frame)
+    }
+
+    fn offload_history(&self, room_id: u128) -> Result<u64, StorageError> {
+        self.inner.offload_history(room_id)
+    }
+
+    fn store_mls_state(&self, room_id: u128, state: &MlsGroupState) -> Result<(), StorageError> {
+        self.inner.store_mls_state(room_id, state)
+    }
+
+    fn load_mls_state(&self, room_id: u128) -> Result<Option<MlsGroupState>, StorageError> {
+        self.inner.load_mls_state(room_id)
+    }
+
+    fn store_group_info(
+        &self,
+        room_id: u128,
+        epoch: u64,
+        group_info: &[u8],
+    ) -> Result<(), StorageError> {
+        self.inner.store_group_info(room_id, epoch, group_info)
+    }
+
+    fn load_group_info(&self, room_id: u128) -> Result<Option<(u64, Vec<u8>)>, StorageError> {
+        self.inner.load_group_info(room_id)
+    }
+
+    fn list_rooms(&self) -> Result<Vec<u128>, StorageError> {
+        self.inner.list_rooms()
+    }
+
+    fn create_room(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let record = WalRecord::RoomCreated { room_id, metadata: metadata.clone() };
+        self.log_then(&record, |inner| inner.create_room(room_id, metadata))
+    }
+
+    fn load_room_metadata(
+        &self,
+        room_id: u128,
+    ) -> Result<Option<StoredRoomMetadata>, StorageError> {
+        self.inner.load_room_metadata(room_id)
+    }
+
+    fn update_room_metadata(
+        &self,
+        room_id: u128,
+        metadata: &StoredRoomMetadata,
+    ) -> Result<(), StorageError> {
+        let record = WalRecord::RoomUpdated { room_id, metadata: metadata.clone() };
+        self.log_then(&record, |inner| inner.update_room_metadata(room_id, metadata))
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_proto::{FrameHeader, Opcode};
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn frame(room_id: u128, log_index: u64) -> Frame {
+        let mut header = FrameHeader::new(Opcode::AppMessage);
+        header.set_room_id(room_id);
+        header.set_log_index(log_index);
+        Frame::new(header, Bytes::from(vec![log_index as u8; 4]))
+    }
+
+    #[test]
+    fn replay_restores_writes_the_backend_lost() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = WalConfig::new(dir.path().join("lockframe.wal"));
+
+        // The backend is dropped without the log being checkpointed, as if
+        // the process died before buffered writes reached disk
+        {
+            let storage = WalStorage::open(MemoryStorage::new(), &config).unwrap();
+            storage.create_room(1, &StoredRoomMetadata::new(42, 0)).unwrap();
+            for log_index in 0..3 {
+                storage.store_frame(1, log_index, &frame(1, log_index)).unwrap();
+            }
+        }
+
+        let recovered = WalStorage::open(MemoryStorage::new(), &config).unwrap();
+        assert_eq!(recovered.list_rooms().unwrap(), vec![1]);
+        assert_eq!(recovered.load_room_metadata(1).unwrap().unwrap().creator, 42);
+        assert_eq!(recovered.latest_log_index(1).unwrap(), Some(2));
+        assert_eq!(
+            recovered.load_frames(1, 0, 10).unwrap(),
+            (0..3).map(|i| frame(1, i)).collect::<Vec<_>>()
+        );
+
+        // Replay emptied the log
+        assert_eq!(std::fs::metadata(&config.path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn replay_skips_frames_already_stored_and_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = WalConfig::new(dir.path().join("lockframe.wal"));
+        let backend = MemoryStorage::new();
+
+        {
+            let storage = WalStorage::open(backend.clone(), &config).unwrap();
+            storage.store_frame(1, 0, &frame(1, 0)).unwrap();
+            storage.store_frame(1, 1, &frame(1, 1)).unwrap();
+        }
+        // Half-written record at the end of the log
+        let mut file = OpenOptions::new().append(true).open(&config.path).unwrap();
+        let torn =
+            WalRecord::Frame { room_id: 1, log_index: 2, frame: frame(1, 2) }.encode().unwrap();
+        file.write_all(&torn[..torn.len() / 2]).unwrap();
+
+        let recovered = WalStorage::open(backend, &config).unwrap();
+        assert_eq!(recovered.latest_log_index(1).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn rejected_frames_are_not_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = WalConfig::new(dir.path().join("lockframe.wal"));
+        let storage = WalStorage::open(MemoryStorage::new(), &config).unwrap();
+
+        assert!(matches!(
+            storage.store_frame(1, 5, &frame(1, 5)),
+            Err(StorageError::Conflict { expected: 0, got: 5 })
+        ));
+        assert_eq!(std::fs::metadata(&config.path).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,157 @@
+//! Filtered sync.
+//!
+//! A plain `SyncRequest` is a page of the room log. A filtered one (see
+//! [`SyncRequest::is_filtered`]) walks the log from `from_log_index` and keeps
+//! only the frames that pass its filters, so a rejoining client can fetch the
+//! Commits it needs to reach the current epoch without downloading every
+//! application message first.
+//!
+//! A filter can match few or no frames, so each request examines at most
+//! [`SCAN_LIMIT`] frames. The response tells the client where the scan
+//! stopped, and `has_more` stays set until the scan reaches the end of the
+//! log.
+//!
+//! Tombstones (`HistoryTruncated` and `CloseRoom`) pass every filter.
+
+use lockframe_proto::{Frame, Opcode, payloads::session::SyncRequest};
+
+use crate::storage::{Storage, StorageError};
+
+/// Most frames examined for one filtered request.
+pub(crate) const SCAN_LIMIT: usize = 4096;
+
+/// Frames loaded from storage at a time while scanning.
+const SCAN_BATCH: usize = 256;
+
+/// Frames of a filtered request, and the log index to resume from.
+pub(crate) struct FilteredFrames {
+    pub(crate) frames: Vec<Frame>,
+    pub(crate) next_log_index: u64,
+}
+
+/// Scan a room's log from `request.from_log_index`, keeping up to `limit`
+/// frames that pass the request's filters.
+pub(crate) fn load_filtered(
+    room_id: u128,
+    request: &SyncRequest,
+    limit: usize,
+    storage: &impl Storage,
+) -> Result<FilteredFrames, StorageError> {
+    let mut frames = Vec::new();
+    let mut next_log_index = scan_start(request);
+    let mut scanned = 0;
+
+    while frames.len() < limit && scanned < SCAN_LIMIT {
+        let batch =
+            storage.load_frames(room_id, next_log_index, SCAN_BATCH.min(SCAN_LIMIT - scanned))?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for frame in batch {
+            scanned += 1;
+            // A tombstone can sit below the requested index
+            next_log_index = next_log_index.max(frame.header.log_index().saturating_add(1));
+            if matches(request, &frame) {
+                frames.push(frame);
+                if frames.len() == limit {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(FilteredFrames { frames, next_log_index })
+}
+
+/// First log index any filter could match.
+fn scan_start(request: &SyncRequest) -> u64 {
+    let earliest_cursor = request.senders.iter().map(|cursor| cursor.from_log_index).min();
+    earliest_cursor.map_or(request.from_log_index, |cursor| cursor.max(request.from_log_index))
+}
+
+/// Whether a frame passes every filter of the request.
+fn matches(request: &SyncRequest, frame: &Frame) -> bool {
+    let header = &frame.header;
+    let opcode = header.opcode_enum();
+    if matches!(opcode, Some(Opcode::HistoryTruncated | Opcode::CloseRoom)) {
+        return true;
+    }
+    if request.control_only && !opcode.is_some_and(Opcode::is_mls) {
+        return false;
+    }
+    if let Some(epochs) = request.epochs
+        && !epochs.contains(header.epoch())
+    {
+        return false;
+    }
+    request.senders.is_empty()
+        || request.senders.iter().any(|cursor| {
+            cursor.sender_id == header.sender_id() && header.log_index() >= cursor.from_log_index
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lockframe_proto::FrameHeader;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const ROOM: u128 = 0x1234;
+
+    /// Alternating Commit/AppMessage frames; every Commit advances the epoch
+    /// and senders take turns.
+    fn seeded_storage(count: u64) -> MemoryStorage {
+        let storage = MemoryStorage::new();
+        for log_index in 0..count {
+            let opcode = if log_index % 2 == 0 { Opcode::Commit } else { Opcode::AppMessage };
+            let mut header = FrameHeader::new(opcode);
+            header.set_room_id(ROOM);
+            header.set_log_index(log_index);
+            header.set_epoch(log_index / 2);
+            header.set_sender_id(log_index % 3);
+            storage.store_frame(ROOM, log_index, &Frame::new(header, Bytes::new())).unwrap();
+        }
+        storage
+    }
+
+    fn indices(filtered: &FilteredFrames) -> Vec<u64> {
+        filtered.frames.iter().map(|frame| frame.header.log_index()).collect()
+    }
+
+    #[test]
+    fn filters_combine() {
+        let storage = seeded_storage(20);
+
+        let request = SyncRequest::new(0, 100).control_only();
+        let filtered = load_filtered(ROOM, &request, 100, &storage).unwrap();
+        assert_eq!(indices(&filtered), (0..20).step_by(2).collect::<Vec<_>>());
+
+        let request = request.in_epochs(2, 4);
+        let filtered = load_filtered(ROOM, &request, 100, &storage).unwrap();
+        assert_eq!(indices(&filtered), vec![4, 6, 8]);
+
+        let request = SyncRequest::new(0, 100).from_sender(1, 5).from_sender(2, 14);
+        let filtered = load_filtered(ROOM, &request, 100, &storage).unwrap();
+        assert_eq!(indices(&filtered), vec![7, 10, 13, 14, 16, 17, 19]);
+        assert_eq!(filtered.next_log_index, 20);
+    }
+
+    #[test]
+    fn scan_resumes_where_it_stopped() {
+        let storage = seeded_storage(SCAN_LIMIT as u64 + 10);
+
+        // Nothing matches, so the scan gives up after `SCAN_LIMIT` frames
+        let request = SyncRequest::new(0, 10).in_epochs(u64::MAX, u64::MAX);
+        let filtered = load_filtered(ROOM, &request, 10, &storage).unwrap();
+        assert!(filtered.frames.is_empty());
+        assert_eq!(filtered.next_log_index, SCAN_LIMIT as u64);
+
+        let request = SyncRequest::new(0, 2).control_only();
+        let filtered = load_filtered(ROOM, &request, 2, &storage).unwrap();
+        assert_eq!(indices(&filtered), vec![0, 2]);
+        assert_eq!(filtered.next_log_index, 3);
+    }
+}
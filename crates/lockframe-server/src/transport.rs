@@ -18,13 +18,23 @@
 //! protocol compatibility. Self-signed certificates are only suitable for local
 //! testing - production deployments MUST use proper TLS certificates from a
 //! trusted CA.
+//!
+//! With a client CA configured, clients are asked for a certificate and any
+//! certificate presented must chain to that CA. Clients may still connect
+//! without one; whether that is allowed is the driver's decision, so the
+//! certificate is handed over as a [`PeerIdentity`].
 
 use std::{net::SocketAddr, sync::Arc};
 
 use lockframe_proto::ALPN_PROTOCOL;
 use quinn::{Endpoint, RecvStream, SendStream, ServerConfig};
+use rustls::{
+    RootCertStore,
+    pki_types::CertificateDer,
+    server::{WebPkiClientVerifier, danger::ClientCertVerifier},
+};
 
-use crate::error::ServerError;
+use crate::{auth::PeerIdentity, error::ServerError};
 
 /// QUIC transport using Quinn.
 ///
@@ -47,18 +57,26 @@ impl QuinnTransport {
     ///
     /// If `cert_path` and `key_path` are provided, they will be used for TLS.
     /// Otherwise, a self-signed certificate will be generated for testing.
+    /// If `client_ca_path` is provided, client certificates are requested and
+    /// verified against the CA certificates it holds.
     pub fn bind(
         address: &str,
         cert_path: Option<String>,
         key_path: Option<String>,
+        client_ca_path: Option<String>,
     ) -> Result<Self, ServerError> {
         let addr: SocketAddr = address
             .parse()
             .map_err(|e| ServerError::Config(format!("invalid bind address '{address}': {e}")))?;
 
+        let client_verifier = match client_ca_path {
+            Some(path) => load_client_verifier(&path)?,
+            None => WebPkiClientVerifier::no_client_auth(),
+        };
+
         let server_config = match (cert_path, key_path) {
-            (Some(cert), Some(key)) => load_tls_config(&cert, &key)?,
-            _ => generate_self_signed_config()?,
+            (Some(cert), Some(key)) => load_tls_config(&cert, &key, client_verifier)?,
+            _ => generate_self_signed_config(client_verifier)?,
         };
 
         let endpoint = Endpoint::server(server_config, addr)
@@ -138,6 +156,13 @@ impl QuinnConnection {
         self.connection.remote_address()
     }
 
+    /// Client certificate the peer presented during the handshake.
+    pub fn peer_identity(&self) -> Option<PeerIdentity> {
+        let chain =
+            self.connection.peer_identity()?.downcast::<Vec<CertificateDer<'static>>>().ok()?;
+        chain.first().map(|leaf| PeerIdentity::from_der(leaf.to_vec()))
+    }
+
     /// Close the connection with an error code and reason.
     pub fn close(&self, error_code: quinn::VarInt, reason: &[u8]) {
         self.connection.close(error_code, reason);
@@ -145,7 +170,11 @@ impl QuinnConnection {
 }
 
 /// Load TLS configuration from certificate and key files.
-fn load_tls_config(cert_path: &str, key_path: &str) -> Result<ServerConfig, ServerError> {
+fn load_tls_config(
+    cert_path: &str,
+    key_path: &str,
+    client_verifier: Arc<dyn ClientCertVerifier>,
+) -> Result<ServerConfig, ServerError> {
     use std::fs;
 
     let cert_pem = fs::read(cert_path)
@@ -163,7 +192,7 @@ fn load_tls_config(cert_path: &str, key_path: &str) -> Result<ServerConfig, Serv
         .ok_or_else(|| ServerError::Config("no private key found".to_string()))?;
 
     let mut tls_config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+        .with_client_cert_verifier(client_verifier)
         .with_single_cert(certs, key)
         .map_err(|e| ServerError::Config(format!("invalid TLS config: {e}")))?;
 
@@ -177,8 +206,32 @@ fn load_tls_config(cert_path: &str, key_path: &str) -> Result<ServerConfig, Serv
     Ok(server_config)
 }
 
+/// Load the CA certificates client certificates must chain to.
+///
+/// Clients presenting no certificate are still let through.
+fn load_client_verifier(ca_path: &str) -> Result<Arc<dyn ClientCertVerifier>, ServerError> {
+    let ca_pem = std::fs::read(ca_path)
+        .map_err(|e| ServerError::Config(format!("failed to read client CA '{ca_path}': {e}")))?;
+
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &ca_pem[..]) {
+        let cert =
+            cert.map_err(|e| ServerError::Config(format!("failed to parse client CA: {e}")))?;
+        roots
+            .add(cert)
+            .map_err(|e| ServerError::Config(format!("invalid client CA certificate: {e}")))?;
+    }
+
+    WebPkiClientVerifier::builder(Arc::new(roots))
+        .allow_unauthenticated()
+        .build()
+        .map_err(|e| ServerError::Config(format!("invalid client CA '{ca_path}': {e}")))
+}
+
 /// Generate a self-signed certificate for testing.
-fn generate_self_signed_config() -> Result<ServerConfig, ServerError> {
+fn generate_self_signed_config(
+    client_verifier: Arc<dyn ClientCertVerifier>,
+) -> Result<ServerConfig, ServerError> {
     let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
         .map_err(|e| ServerError::Config(format!("failed to generate self-signed cert: {e}")))?;
 
@@ -189,7 +242,7 @@ fn generate_self_signed_config() -> Result<ServerConfig, ServerError> {
     let key = rustls::pki_types::PrivatePkcs8KeyDer::from(key_der);
 
     let mut tls_config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+        .with_client_cert_verifier(client_verifier)
         .with_single_cert(cert_chain, key.into())
         .map_err(|e| ServerError::Config(format!("invalid TLS config: {e}")))?;
 
@@ -211,7 +264,7 @@ mod tests {
 
     #[tokio::test]
     async fn transport_binds_with_self_signed() {
-        let transport = QuinnTransport::bind("127.0.0.1:0", None, None);
+        let transport = QuinnTransport::bind("127.0.0.1:0", None, None, None);
         assert!(transport.is_ok(), "Transport should bind with self-signed cert");
 
         let transport = transport.unwrap();
@@ -221,7 +274,21 @@ mod tests {
 
     #[tokio::test]
     async fn transport_rejects_invalid_address() {
-        let result = QuinnTransport::bind("invalid:address:format", None, None);
+        let result = QuinnTransport::bind("invalid:address:format", None, None, None);
         assert!(result.is_err(), "Should reject invalid address");
     }
+
+    #[tokio::test]
+    async fn transport_binds_with_client_ca() {
+        let ca = rcgen::generate_simple_self_signed(vec!["device-ca".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let ca_path = dir.path().join("ca.pem");
+        std::fs::write(&ca_path, ca.cert.pem()).unwrap();
+
+        let ca_path = ca_path.to_string_lossy().into_owned();
+        assert!(QuinnTransport::bind("127.0.0.1:0", None, None, Some(ca_path)).is_ok());
+
+        let missing = dir.path().join("missing.pem").to_string_lossy().into_owned();
+        assert!(QuinnTransport::bind("127.0.0.1:0", None, None, Some(missing)).is_err());
+    }
 }
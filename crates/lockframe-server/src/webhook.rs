@@ -0,0 +1,292 @@
+//! Outbound webhooks.
+//!
+//! Rooms can forward a few coarse events to an HTTP endpoint: creation,
+//! member count changes, and message volume (one event every
+//! [`WebhookConfig::volume_every`] stored messages). Events name rooms and
+//! counts, never users or message content.
+//!
+//! A room uses the target set for it with `AdminRequest::SetWebhook`, or the
+//! server's default target. Targets set by admins live in memory and are
+//! forgotten on restart, like retention overrides.
+//!
+//! The driver only decides what to send. Each event becomes a
+//! [`ServerAction::DeliverWebhook`](crate::ServerAction::DeliverWebhook)
+//! carrying the signed body, and the runtime posts it, retrying with
+//! exponential backoff.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    time::Duration,
+};
+
+use lockframe_proto::payloads::admin::{WebhookEvent, WebhookTarget};
+use ring::hmac;
+use serde_json::json;
+
+/// Header carrying the body's signature, as `sha256=<hex HMAC>`.
+pub const SIGNATURE_HEADER: &str = "X-Lockframe-Signature";
+
+/// Longest wait between delivery attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_mins(5);
+
+/// Webhook settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookConfig {
+    /// Target for rooms without one of their own. `None` sends nothing for
+    /// them.
+    pub default_target: Option<WebhookTarget>,
+    /// Stored messages per `MessageVolume` event. 0 disables volume events.
+    pub volume_every: u64,
+    /// Delivery attempts before an event is dropped
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubles after each failure
+    pub retry_delay: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            default_target: None,
+            volume_every: 100,
+            max_attempts: 5,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A signed event ready to post.
+#[derive(Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    /// URL to post to
+    pub url: String,
+    /// JSON request body
+    pub body: Vec<u8>,
+    /// Value of the [`SIGNATURE_HEADER`] header
+    pub signature: String,
+    /// Attempts before giving up
+    pub max_attempts: u32,
+    /// Wait before the first retry
+    pub retry_delay: Duration,
+}
+
+impl WebhookDelivery {
+    /// Wait before each retry, in order: `retry_delay` doubling after each
+    /// failure, capped at five minutes.
+    pub fn retry_delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        (0..self.max_attempts.saturating_sub(1))
+            .map(|retry| self.retry_delay.saturating_mul(1 << retry.min(16)).min(MAX_RETRY_DELAY))
+    }
+}
+
+impl fmt::Debug for WebhookDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookDelivery")
+            .field("url", &self.url)
+            .field("body", &String::from_utf8_lossy(&self.body))
+            .field("max_attempts", &self.max_attempts)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Webhook targets and the counters events are derived from.
+#[derive(Debug, Default)]
+pub(crate) struct Webhooks {
+    config: WebhookConfig,
+    /// Targets set by admins, overriding the default
+    targets: HashMap<u128, WebhookTarget>,
+    /// Member count last reported per room
+    members: HashMap<u128, u32>,
+    /// Messages stored since the last volume event, and when the first of
+    /// them was stored
+    volume: HashMap<u128, (u64, u64)>,
+}
+
+impl Webhooks {
+    pub(crate) fn new(config: WebhookConfig) -> Self {
+        Self { config, ..Self::default() }
+    }
+
+    pub(crate) fn set_config(&mut self, config: WebhookConfig) {
+        self.config = config;
+    }
+
+    /// Set or remove a room's own target. Without one the room falls back
+    /// to the default.
+    pub(crate) fn set_target(&mut self, room_id: u128, target: Option<WebhookTarget>) {
+        match target {
+            Some(target) => self.targets.insert(room_id, target),
+            None => self.targets.remove(&room_id),
+        };
+    }
+
+    /// A room was created.
+    pub(crate) fn room_created(&mut self, room_id: u128, at_secs: u64) -> Option<WebhookDelivery> {
+        let body =
+            json!({ "event": "room_created", "room_id": hex_id(room_id), "at_secs": at_secs });
+        self.deliver(room_id, WebhookEvent::RoomCreated, &body)
+    }
+
+    /// A room's member count is now `members`. Only changes are sent; the
+    /// first count seen for a room is remembered without sending.
+    pub(crate) fn member_count(
+        &mut self,
+        room_id: u128,
+        members: u32,
+        at_secs: u64,
+    ) -> Option<WebhookDelivery> {
+        let previous = self.members.insert(room_id, members)?;
+        if previous == members {
+            return None;
+        }
+        let body = json!({
+            "event": "members_changed",
+            "room_id": hex_id(room_id),
+            "at_secs": at_secs,
+            "members": members,
+            "previous": previous,
+        });
+        self.deliver(room_id, WebhookEvent::MembersChanged, &body)
+    }
+
+    /// A room stored an application message.
+    pub(crate) fn message_stored(
+        &mut self,
+        room_id: u128,
+        at_secs: u64,
+    ) -> Option<WebhookDelivery> {
+        let every = self.config.volume_every;
+        if every == 0 || self.target(room_id).is_none() {
+            return None;
+        }
+
+        let (count, since) = self.volume.entry(room_id).or_insert((0, at_secs));
+        *count += 1;
+        if *count < every {
+            return None;
+        }
+        let body = json!({
+            "event": "message_volume",
+            "room_id": hex_id(room_id),
+            "at_secs": at_secs,
+            "messages": *count,
+            "since_secs": *since,
+        });
+        self.volume.remove(&room_id);
+        self.deliver(room_id, WebhookEvent::MessageVolume, &body)
+    }
+
+    /// Forget a room's counters and target.
+    pub(crate) fn forget(&mut self, room_id: u128) {
+        self.targets.remove(&room_id);
+        self.members.remove(&room_id);
+        self.volume.remove(&room_id);
+    }
+
+    fn target(&self, room_id: u128) -> Option<&WebhookTarget> {
+        self.targets.get(&room_id).or(self.config.default_target.as_ref())
+    }
+
+    /// Sign `body` for the room's target, if it wants `event`.
+    fn deliver(
+        &self,
+        room_id: u128,
+        event: WebhookEvent,
+        body: &serde_json::Value,
+    ) -> Option<WebhookDelivery> {
+        let target = self.target(room_id).filter(|target| target.wants(event))?;
+        let body = body.to_string().into_bytes();
+        Some(WebhookDelivery {
+            url: target.url.clone(),
+            signature: sign(&target.secret, &body),
+            body,
+            max_attempts: self.config.max_attempts.max(1),
+            retry_delay: self.config.retry_delay,
+        })
+    }
+}
+
+/// Why a target cannot be used, if it cannot.
+pub(crate) fn check_target(target: &WebhookTarget) -> Result<(), &'static str> {
+    if !(target.url.starts_with("http://") || target.url.starts_with("https://")) {
+        return Err("webhook URL must be http:// or https://");
+    }
+    if target.secret.is_empty() {
+        return Err("webhook secret is empty");
+    }
+    Ok(())
+}
+
+/// [`SIGNATURE_HEADER`] value for `body` signed with `secret`.
+fn sign(secret: &[u8], body: &[u8]) -> String {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, secret), body);
+    tag.as_ref().iter().fold(String::from("sha256="), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn hex_id(room_id: u128) -> String {
+    format!("{room_id:032x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(events: Vec<WebhookEvent>) -> WebhookTarget {
+        WebhookTarget { url: "https://hooks.example/in".into(), secret: b"key".to_vec(), events }
+    }
+
+    #[test]
+    fn events_follow_counters_and_filters() {
+        let mut webhooks =
+            Webhooks::new(WebhookConfig { volume_every: 2, ..WebhookConfig::default() });
+        assert!(webhooks.room_created(1, 10).is_none(), "no target, no event");
+
+        webhooks.set_target(1, Some(target(vec![WebhookEvent::MembersChanged])));
+        assert!(webhooks.room_created(1, 10).is_none(), "filtered out");
+        assert!(webhooks.member_count(1, 1, 10).is_none(), "first count is a baseline");
+        assert!(webhooks.member_count(1, 1, 11).is_none(), "unchanged");
+        let delivery = webhooks.member_count(1, 2, 12).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&delivery.body).unwrap();
+        assert_eq!(body["members"], 2);
+        assert_eq!(body["previous"], 1);
+
+        webhooks.set_target(1, Some(target(Vec::new())));
+        assert!(webhooks.message_stored(1, 20).is_none());
+        let delivery = webhooks.message_stored(1, 21).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&delivery.body).unwrap();
+        assert_eq!(body["messages"], 2);
+        assert_eq!(body["since_secs"], 20);
+        assert!(webhooks.message_stored(1, 22).is_none(), "window restarts");
+    }
+
+    #[test]
+    fn bodies_are_signed_with_the_target_secret() {
+        let mut webhooks = Webhooks::default();
+        webhooks.set_target(7, Some(target(Vec::new())));
+        let delivery = webhooks.room_created(7, 0).unwrap();
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"key");
+        let hex = delivery.signature.strip_prefix("sha256=").unwrap();
+        let tag: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        assert!(hmac::verify(&key, &delivery.body, &tag).is_ok());
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let delivery = WebhookDelivery {
+            url: String::new(),
+            body: Vec::new(),
+            signature: String::new(),
+            max_attempts: 12,
+            retry_delay: Duration::from_secs(1),
+        };
+        let delays: Vec<u64> = delivery.retry_delays().map(|d| d.as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]);
+    }
+}
@@ -8,7 +8,7 @@
 
 use lockframe_proto::{Frame, FrameHeader, Opcode, Payload, payloads::session::Hello};
 use lockframe_server::{
-    DriverConfig, MemoryStorage, ServerAction, ServerDriver, ServerEvent, SystemEnv,
+    DriverConfig, LogEvent, MemoryStorage, ServerAction, ServerDriver, ServerEvent, SystemEnv,
 };
 
 /// Collect all `SendToSession` actions for a specific session.
@@ -24,11 +24,11 @@ fn frames_for_session(actions: &[ServerAction], target_session: u64) -> Vec<Fram
         .collect()
 }
 
-/// Check if any Log action contains a specific message substring.
-fn has_log_containing(actions: &[ServerAction], substring: &str) -> bool {
-    actions.iter().any(|a| match a {
-        ServerAction::Log { message, .. } => message.contains(substring),
-        _ => false,
+/// Find the first Log action with the given message.
+fn find_log<'a>(actions: &'a [ServerAction], message: &str) -> Option<&'a LogEvent> {
+    actions.iter().find_map(|a| match a {
+        ServerAction::Log(event) if event.message == message => Some(event),
+        _ => None,
     })
 }
 
@@ -48,8 +48,11 @@ fn test_add_member_welcome_routing() {
     let bob_user_id = 99;
 
     // Step 1: Both clients connect
-    server.process_event(ServerEvent::ConnectionAccepted { session_id: session_1 }).unwrap();
-    server.process_event(ServerEvent::ConnectionAccepted { session_id: session_2 }).unwrap();
+    for session_id in [session_1, session_2] {
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+            .unwrap();
+    }
 
     // Step 2: Both clients send Hello with their user_id
     // This establishes user_id → session_id mapping in registry
@@ -104,10 +107,9 @@ fn test_add_member_welcome_routing() {
         .unwrap();
 
     // Should have a Log action for successful publish
-    assert!(
-        has_log_containing(&publish_actions, "KeyPackage published"),
-        "KeyPackage publish should succeed"
-    );
+    let published = find_log(&publish_actions, "KeyPackage published")
+        .expect("KeyPackage publish should succeed");
+    assert_eq!(published.get("user_id"), Some(bob_user_id.to_string().as_str()));
 
     // Step 4: Alice creates room
     let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef_u128;
@@ -167,10 +169,10 @@ fn test_add_member_welcome_routing() {
     );
 
     // Verify subscription via Log action
-    assert!(
-        has_log_containing(&welcome_actions, "subscribed to room"),
-        "Should log that Bob was subscribed to room"
-    );
+    let subscribed = find_log(&welcome_actions, "subscribed via Welcome")
+        .expect("Should log that Bob was subscribed to room");
+    assert_eq!(subscribed.room_id, Some(room_id));
+    assert_eq!(subscribed.session_id, Some(session_2));
 }
 
 #[test]
@@ -185,8 +187,11 @@ fn test_welcome_routing_fails_without_hello() {
     let bob_user_id = 99;
 
     // Both connect
-    server.process_event(ServerEvent::ConnectionAccepted { session_id: session_1 }).unwrap();
-    server.process_event(ServerEvent::ConnectionAccepted { session_id: session_2 }).unwrap();
+    for session_id in [session_1, session_2] {
+        server
+            .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+            .unwrap();
+    }
 
     // Only Alice sends Hello - Bob doesn't authenticate
     let alice_hello = Payload::Hello(Hello {
@@ -219,10 +224,9 @@ fn test_welcome_routing_fails_without_hello() {
         .unwrap();
 
     // Should have warning about recipient not connected
-    assert!(
-        has_log_containing(&actions, "not connected"),
-        "Should log warning when recipient not authenticated"
-    );
+    let warning = find_log(&actions, "Welcome recipient not connected")
+        .expect("Should log warning when recipient not authenticated");
+    assert_eq!(warning.get("recipient_id"), Some(bob_user_id.to_string().as_str()));
 
     // Bob should NOT receive the Welcome
     let bob_frames = frames_for_session(&actions, session_2);
@@ -29,7 +29,10 @@ fn keypackage_fetch_not_found() {
 
     // Connect and authenticate
     driver
-        .process_event(ServerEvent::ConnectionAccepted { session_id: client_session })
+        .process_event(ServerEvent::ConnectionAccepted {
+            session_id: client_session,
+            peer_identity: None,
+        })
         .expect("accept");
 
     let hello = Payload::Hello(lockframe_proto::payloads::session::Hello {
@@ -89,10 +92,16 @@ fn keypackage_consumed_after_fetch() {
 
     // Setup both clients
     driver
-        .process_event(ServerEvent::ConnectionAccepted { session_id: session_a })
+        .process_event(ServerEvent::ConnectionAccepted {
+            session_id: session_a,
+            peer_identity: None,
+        })
         .expect("accept A");
     driver
-        .process_event(ServerEvent::ConnectionAccepted { session_id: session_b })
+        .process_event(ServerEvent::ConnectionAccepted {
+            session_id: session_b,
+            peer_identity: None,
+        })
         .expect("accept B");
 
     // Auth A
@@ -185,3 +194,78 @@ fn keypackage_consumed_after_fetch() {
         "Second fetch should return error (KeyPackage consumed)"
     );
 }
+
+/// Test that the owner is told to publish more once their pool runs low.
+#[test]
+fn keypackage_owner_notified_on_low_stock() {
+    let mut driver = create_driver();
+
+    let session_a = 1001;
+    let session_b = 1002;
+    let user_id_b = 2000;
+
+    for (session_id, sender_id) in [(session_a, 1000), (session_b, user_id_b)] {
+        driver
+            .process_event(ServerEvent::ConnectionAccepted { session_id, peer_identity: None })
+            .expect("accept");
+        let hello = Payload::Hello(lockframe_proto::payloads::session::Hello {
+            version: 1,
+            capabilities: vec![],
+            sender_id: Some(sender_id),
+            auth_token: None,
+        });
+        driver
+            .process_event(ServerEvent::FrameReceived {
+                session_id,
+                frame: hello.into_frame(FrameHeader::new(Opcode::Hello)).unwrap(),
+            })
+            .expect("auth");
+    }
+
+    // B publishes more than the low-stock threshold
+    for i in 0..5u8 {
+        let publish = Payload::KeyPackagePublish(KeyPackagePublishRequest {
+            key_package_bytes: vec![i],
+            hash_ref: vec![i],
+        });
+        driver
+            .process_event(ServerEvent::FrameReceived {
+                session_id: session_b,
+                frame: publish.into_frame(FrameHeader::new(Opcode::KeyPackagePublish)).unwrap(),
+            })
+            .expect("publish");
+    }
+
+    let mut fetch = || {
+        let fetch = Payload::KeyPackageFetch(KeyPackageFetchPayload {
+            user_id: user_id_b,
+            key_package_bytes: vec![],
+            hash_ref: vec![],
+        });
+        driver
+            .process_event(ServerEvent::FrameReceived {
+                session_id: session_a,
+                frame: fetch.into_frame(FrameHeader::new(Opcode::KeyPackageFetch)).unwrap(),
+            })
+            .expect("fetch")
+    };
+    let notice_for_b = |actions: &[ServerAction<_>]| {
+        actions.iter().find_map(|a| match a {
+            ServerAction::SendToSession { session_id, frame } if *session_id == session_b => {
+                Some(frame.clone())
+            },
+            _ => None,
+        })
+    };
+
+    // 4 left: still at the threshold
+    assert!(notice_for_b(&fetch()).is_none());
+
+    // 3 left: below it
+    let notice = notice_for_b(&fetch()).expect("owner should be notified");
+    assert_eq!(notice.header.opcode_enum(), Some(Opcode::KeyPackageLowStock));
+    let Ok(Payload::KeyPackageLowStock(low_stock)) = Payload::from_frame(&notice) else {
+        panic!("expected KeyPackageLowStock payload");
+    };
+    assert_eq!(low_stock.remaining, 3);
+}
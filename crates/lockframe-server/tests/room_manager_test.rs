@@ -3,9 +3,25 @@
 //! Tests for specific routing behaviors of the server `RoomManager`.
 
 use bytes::Bytes;
+use lockframe_client::{Client, ClientAction, ClientEvent, ClientIdentity};
 use lockframe_core::env::test_utils::MockEnv;
-use lockframe_proto::{Frame, FrameHeader, Opcode};
-use lockframe_server::{MemoryStorage, RoomManager};
+use lockframe_proto::{
+    Frame, FrameHeader, Opcode,
+    payloads::{admin::RoomKind, app::AppMessageBody},
+};
+use lockframe_server::{Denial, MemoryStorage, RoomAction, RoomError, RoomManager, Storage};
+
+/// First frame a client sends among `actions`.
+#[allow(clippy::expect_used)]
+fn sent(actions: &[ClientAction]) -> Frame {
+    actions
+        .iter()
+        .find_map(|action| match action {
+            ClientAction::Send(frame) => Some(frame.clone()),
+            _ => None,
+        })
+        .expect("client should send a frame")
+}
 
 /// Test that the server routes frames without MLS validation.
 /// Server is routing-only, clients own the MLS state.
@@ -80,3 +96,62 @@ fn process_welcome_routes_without_mls_validation() {
     let result = manager.process_frame(frame, &env, &storage);
     assert!(result.is_ok());
 }
+
+/// An ephemeral room relays messages without a log index, so clients must
+/// flag them ephemeral. An unflagged message would reach members, and its
+/// sender, looking sequenced.
+#[test]
+fn ephemeral_rooms_relay_only_messages_clients_flag_ephemeral() {
+    let env = MockEnv::with_crypto_rng();
+    let mut manager = RoomManager::new();
+    let storage = MemoryStorage::new();
+    let room_id = 0x1234_5678_90ab_cdef_1234_5678_90ab_cdef;
+
+    let mut alice = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(1));
+    let mut bob = Client::new(MockEnv::with_crypto_rng(), ClientIdentity::new(2));
+    manager.create_room_with_kind(room_id, 1, RoomKind::Ephemeral, &env, &storage).unwrap();
+    manager.admit_member(room_id, 1, 2, &storage).unwrap();
+
+    alice.handle(ClientEvent::CreateRoom { room_id }).unwrap();
+    let (key_package, _) = bob.generate_key_package().unwrap();
+    let actions =
+        alice.handle(ClientEvent::AddMembers { room_id, key_packages: vec![key_package] }).unwrap();
+    for action in actions {
+        let ClientAction::Send(frame) = action else { continue };
+        match frame.header.opcode_enum() {
+            Some(Opcode::Commit) => {
+                alice.handle(ClientEvent::FrameReceived(frame)).unwrap();
+            },
+            Some(Opcode::Welcome) => {
+                let welcome = frame.payload.to_vec();
+                bob.handle(ClientEvent::JoinRoom { room_id, welcome }).unwrap();
+            },
+            _ => {},
+        }
+    }
+
+    let message = ClientEvent::SendMessage { room_id, plaintext: b"hello".to_vec() };
+    let frame = sent(&alice.handle(message).unwrap());
+    let result = manager.process_frame(frame, (), &storage);
+    assert!(matches!(
+        result,
+        Err(RoomError::AccessDenied { reason: Denial::NotEphemeral { .. }, .. })
+    ));
+
+    let typing = AppMessageBody::Typing { active: true };
+    let frame = sent(&alice.handle(ClientEvent::SendAppMessage { room_id, body: typing }).unwrap());
+    let mut actions = manager.process_frame(frame, (), &storage).unwrap();
+    let Some(RoomAction::Broadcast { frame, .. }) = actions.pop() else {
+        panic!("ephemeral frame should be relayed");
+    };
+    assert_eq!(storage.latest_log_index(room_id).unwrap(), None);
+
+    // Bob sees the notice without expecting a log index from it
+    let actions = bob.handle(ClientEvent::FrameReceived(frame.clone())).unwrap();
+    assert!(actions.iter().any(|action| matches!(action, ClientAction::DeliverTyping { .. })));
+    assert!(!actions.iter().any(|action| matches!(action, ClientAction::GapDetected { .. })));
+
+    // ...and Alice's echo is not taken for an acknowledgement
+    let actions = alice.handle(ClientEvent::FrameReceived(frame)).unwrap();
+    assert!(!actions.iter().any(|action| matches!(action, ClientAction::MessageSequenced { .. })));
+}
@@ -41,12 +41,15 @@ fn broadcast_to_room_members() {
         let mut server = SimServer::bind("0.0.0.0:443").await?;
 
         // Create 3 connections using driver directly (simpler for this test)
-        let _ =
-            server.driver_mut().process_event(ServerEvent::ConnectionAccepted { session_id: 1 });
-        let _ =
-            server.driver_mut().process_event(ServerEvent::ConnectionAccepted { session_id: 2 });
-        let _ =
-            server.driver_mut().process_event(ServerEvent::ConnectionAccepted { session_id: 3 });
+        let _ = server
+            .driver_mut()
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None });
+        let _ = server
+            .driver_mut()
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None });
+        let _ = server
+            .driver_mut()
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 3, peer_identity: None });
 
         // Create room with conn1 as creator
         server.create_room(ROOM_1, 1)?;
@@ -85,9 +88,10 @@ fn rooms_are_isolated() {
 
         // Create 4 connections
         for i in 1..=4 {
-            let _ = server
-                .driver_mut()
-                .process_event(ServerEvent::ConnectionAccepted { session_id: i });
+            let _ = server.driver_mut().process_event(ServerEvent::ConnectionAccepted {
+                session_id: i,
+                peer_identity: None,
+            });
         }
 
         // Create room 1 with conn1, conn2
@@ -130,9 +134,10 @@ fn member_in_multiple_rooms() {
 
         // Create 3 connections
         for i in 1..=3 {
-            let _ = server
-                .driver_mut()
-                .process_event(ServerEvent::ConnectionAccepted { session_id: i });
+            let _ = server.driver_mut().process_event(ServerEvent::ConnectionAccepted {
+                session_id: i,
+                peer_identity: None,
+            });
         }
 
         // Conn1 creates room 1
@@ -187,9 +192,10 @@ fn disconnect_removes_from_rooms() {
 
         // Create 3 connections
         for i in 1..=3 {
-            let _ = server
-                .driver_mut()
-                .process_event(ServerEvent::ConnectionAccepted { session_id: i });
+            let _ = server.driver_mut().process_event(ServerEvent::ConnectionAccepted {
+                session_id: i,
+                peer_identity: None,
+            });
         }
 
         // Create room with all 3 members
@@ -228,9 +234,10 @@ fn disconnect_from_multiple_rooms() {
 
         // Create 3 connections
         for i in 1..=3 {
-            let _ = server
-                .driver_mut()
-                .process_event(ServerEvent::ConnectionAccepted { session_id: i });
+            let _ = server.driver_mut().process_event(ServerEvent::ConnectionAccepted {
+                session_id: i,
+                peer_identity: None,
+            });
         }
 
         // Create both rooms
@@ -275,9 +282,10 @@ fn large_room_membership() {
 
         // Create 100 connections
         for i in 1..=100 {
-            let _ = server
-                .driver_mut()
-                .process_event(ServerEvent::ConnectionAccepted { session_id: i });
+            let _ = server.driver_mut().process_event(ServerEvent::ConnectionAccepted {
+                session_id: i,
+                peer_identity: None,
+            });
         }
 
         // Create room with all members
@@ -313,10 +321,12 @@ fn empty_room_after_all_disconnect() {
         let mut server = SimServer::bind("0.0.0.0:443").await?;
 
         // Create 2 connections
-        let _ =
-            server.driver_mut().process_event(ServerEvent::ConnectionAccepted { session_id: 1 });
-        let _ =
-            server.driver_mut().process_event(ServerEvent::ConnectionAccepted { session_id: 2 });
+        let _ = server
+            .driver_mut()
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None });
+        let _ = server
+            .driver_mut()
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 2, peer_identity: None });
 
         // Create room with both members
         server.create_room(ROOM_1, 1)?;
@@ -36,8 +36,9 @@ fn server_stores_and_retrieves_group_info() {
         let mut server = SimServer::bind("0.0.0.0:443").await?;
 
         // Create connection for Alice
-        let _ =
-            server.driver_mut().process_event(ServerEvent::ConnectionAccepted { session_id: 1 });
+        let _ = server
+            .driver_mut()
+            .process_event(ServerEvent::ConnectionAccepted { session_id: 1, peer_identity: None });
 
         // Alice creates room
         let env = SimEnv::new();
@@ -0,0 +1,499 @@
+//! Scripting mode
+//!
+//! Runs one task without the terminal UI, printing to stdout:
+//!
+//! - `send --room <room> <text>` sends a message and exits once the server has
+//!   sequenced it.
+//! - `export --room <room> [--since <index>]` prints the room's history from
+//!   log index `since` and exits.
+//! - `listen [--room <room>]...` prints new messages as they arrive until
+//!   interrupted, in every room when none is named.
+//!
+//! Messages print one per line as `room<TAB>log index<TAB>sender<TAB>text`,
+//! with tabs, newlines and backslashes in the text escaped. With `--json`
+//! each line is instead an object like:
+//!
+//! ```json
+//! {"type":"message","room":"100","log_index":7,"sender":"42","timestamp":1700000000000,"text":"hi"}
+//! ```
+//!
+//! Room and sender IDs are strings, as they don't fit in a JSON number, and
+//! timestamps are Unix milliseconds.
+//!
+//! A [`CliTask`] decides what to do from the App's state, and a [`CliDriver`]
+//! runs it through the same [`lockframe_app::Runtime`] and bridge as the
+//! terminal UI.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use lockframe_app::{
+    AccountId, App, AppAction, AppEvent, ConnectionState, Delivery, Driver, History, Message,
+    NoopNotifier, Severity,
+};
+use lockframe_client::transport::TransportError;
+use lockframe_core::mls::RoomId;
+use lockframe_proto::Frame;
+use thiserror::Error;
+
+use crate::Connections;
+
+/// How long to wait between polls when no frame is waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the server must stay quiet after joining before a room's
+/// initial sync counts as finished.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Scripting mode errors.
+#[derive(Debug, Error)]
+pub enum CliError {
+    /// I/O error writing output.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// Transport error.
+    #[error("transport error: {0}")]
+    Transport(#[from] TransportError),
+
+    /// The App reported an error.
+    #[error("{0}")]
+    Failed(String),
+
+    /// The server didn't sequence the message.
+    #[error("message was not delivered")]
+    NotDelivered,
+
+    /// The task didn't finish in time.
+    #[error("timed out")]
+    TimedOut,
+}
+
+/// What to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Task {
+    /// Send a message.
+    Send {
+        /// Room to send to.
+        room_id: RoomId,
+        /// Message text.
+        text: String,
+    },
+    /// Print a room's history.
+    Export {
+        /// Room to export.
+        room_id: RoomId,
+        /// First log index to print.
+        since: u64,
+    },
+    /// Print new messages until interrupted.
+    Listen {
+        /// Rooms to listen in. Every room when empty.
+        room_ids: Vec<RoomId>,
+    },
+}
+
+/// How far a task has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Waiting for a connection to join the task's rooms.
+    Connecting,
+    /// Asked to join the task's rooms.
+    Joining,
+    /// The message has been handed to the App, or what rooms synced on
+    /// joining has been skipped.
+    Started,
+}
+
+/// A [`Task`] in progress.
+///
+/// Each [`CliTask::step`] looks at the App and returns the actions that move
+/// the task along, ending with [`AppAction::Quit`] once it is done.
+#[derive(Debug)]
+pub struct CliTask {
+    task: Task,
+    json: bool,
+    stage: Stage,
+    /// Next log index to print, by room.
+    next_index: HashMap<RoomId, u64>,
+    /// First notice not yet looked at.
+    next_notice: u64,
+    /// Lines to print.
+    output: Vec<String>,
+}
+
+impl CliTask {
+    /// Start `task`, printing JSON lines if `json`.
+    pub fn new(task: Task, json: bool) -> Self {
+        Self {
+            task,
+            json,
+            stage: Stage::Connecting,
+            next_index: HashMap::new(),
+            next_notice: 0,
+            output: Vec::new(),
+        }
+    }
+
+    /// Move the task along. `settled` says the server has been quiet long
+    /// enough for joined rooms to have synced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the App reports one while sending or exporting,
+    /// or the message isn't delivered.
+    pub fn step(&mut self, app: &mut App, settled: bool) -> Result<Vec<AppAction>, CliError> {
+        self.check_notices(app)?;
+        if !matches!(app.connection_state(), ConnectionState::Connected { .. }) {
+            return Ok(vec![]);
+        }
+
+        let rooms = match &self.task {
+            Task::Send { room_id, .. } | Task::Export { room_id, .. } => vec![*room_id],
+            Task::Listen { room_ids } => room_ids.clone(),
+        };
+        if self.stage == Stage::Connecting {
+            self.stage = Stage::Joining;
+            let missing = rooms.iter().filter(|room_id| !app.rooms().contains_key(room_id));
+            return Ok(missing.flat_map(|&room_id| app.join_room(room_id)).collect());
+        }
+        if !rooms.iter().all(|room_id| app.rooms().contains_key(room_id)) {
+            return Ok(vec![]);
+        }
+
+        match self.task.clone() {
+            Task::Send { room_id, text } => self.send(app, room_id, text),
+            Task::Export { room_id, since } => Ok(self.export(app, room_id, since, settled)),
+            Task::Listen { .. } if settled || self.stage == Stage::Started => {
+                self.listen(app, &rooms);
+                Ok(vec![])
+            },
+            Task::Listen { .. } => Ok(vec![]),
+        }
+    }
+
+    /// Lines printed since the last call.
+    pub fn take_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn send(
+        &mut self,
+        app: &mut App,
+        room_id: RoomId,
+        text: String,
+    ) -> Result<Vec<AppAction>, CliError> {
+        if self.stage == Stage::Joining {
+            self.stage = Stage::Started;
+            return Ok(app.send_message(room_id, text.into_bytes()));
+        }
+        let own = app
+            .rooms()
+            .get(&room_id)
+            .and_then(|room| room.messages.iter().find(|msg| msg.local_id.is_some()));
+        match own.map(|msg| (msg, msg.delivery)) {
+            Some((msg, Delivery::Delivered)) => {
+                self.output.push(self.format(room_id, msg));
+                Ok(vec![AppAction::Quit])
+            },
+            Some((_, Delivery::Failed)) => Err(CliError::NotDelivered),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn export(
+        &mut self,
+        app: &mut App,
+        room_id: RoomId,
+        since: u64,
+        settled: bool,
+    ) -> Vec<AppAction> {
+        let Some(room) = app.rooms().get(&room_id) else {
+            return vec![];
+        };
+        match room.history {
+            History::Complete => {
+                self.next_index.insert(room_id, since);
+                self.print_new(app, room_id);
+                vec![AppAction::Quit]
+            },
+            History::Partial if settled => app.load_history(room_id),
+            History::Partial | History::Loading { .. } => vec![],
+        }
+    }
+
+    /// Print messages that arrived since the last call. Rooms listened in
+    /// from the start skip what they synced on joining.
+    fn listen(&mut self, app: &App, rooms: &[RoomId]) {
+        if self.stage == Stage::Joining {
+            self.stage = Stage::Started;
+            for (&room_id, room) in app.rooms() {
+                let next = room.messages.iter().filter_map(|msg| msg.log_index).max();
+                self.next_index.insert(room_id, next.map_or(0, |index| index + 1));
+            }
+        }
+        let listened: Vec<RoomId> =
+            if rooms.is_empty() { app.rooms().keys().copied().collect() } else { rooms.to_vec() };
+        for room_id in listened {
+            self.print_new(app, room_id);
+        }
+    }
+
+    /// Print `room_id`'s messages from its next log index on, in log order.
+    fn print_new(&mut self, app: &App, room_id: RoomId) {
+        let Some(room) = app.rooms().get(&room_id) else {
+            return;
+        };
+        let next = self.next_index.entry(room_id).or_insert(0);
+        let mut new: Vec<&Message> = room
+            .messages
+            .iter()
+            .filter(|msg| !msg.deleted && msg.log_index.is_some_and(|index| index >= *next))
+            .collect();
+        new.sort_by_key(|msg| msg.log_index);
+        if let Some(last) = new.last().and_then(|msg| msg.log_index) {
+            *next = last + 1;
+        }
+        let lines: Vec<String> = new.into_iter().map(|msg| self.format(room_id, msg)).collect();
+        self.output.extend(lines);
+    }
+
+    /// Fail on errors the App reported since the last call. While listening
+    /// they are logged instead.
+    fn check_notices(&mut self, app: &App) -> Result<(), CliError> {
+        let first = self.next_notice;
+        for notice in app.notices().iter().filter(|notice| notice.id >= first) {
+            self.next_notice = notice.id + 1;
+            match (&self.task, notice.severity) {
+                (Task::Listen { .. }, _) | (_, Severity::Info | Severity::Warning) => {
+                    tracing::warn!(room_id = ?notice.room_id, "{}", notice.message);
+                },
+                (_, Severity::Error) => return Err(CliError::Failed(notice.message.clone())),
+            }
+        }
+        Ok(())
+    }
+
+    fn format(&self, room_id: RoomId, msg: &Message) -> String {
+        let log_index = msg.log_index.unwrap_or_default();
+        if self.json {
+            serde_json::json!({
+                "type": "message",
+                "room": room_id.to_string(),
+                "log_index": log_index,
+                "sender": msg.sender_id.to_string(),
+                "timestamp": msg.timestamp,
+                "text": msg.content_str(),
+            })
+            .to_string()
+        } else {
+            let text =
+                msg.content_str().replace('\\', "\\\\").replace('\n', "\\n").replace('\t', "\\t");
+            format!("{room_id}\t{log_index}\t{}\t{text}", msg.sender_id)
+        }
+    }
+}
+
+/// Driver running a [`CliTask`] without a terminal.
+///
+/// Prints the task's output to stdout and stops with
+/// [`CliError::TimedOut`] if the task outlives its timeout.
+pub struct CliDriver {
+    task: CliTask,
+    connections: Connections,
+    notifier: NoopNotifier,
+    /// When the task must have finished by
+    deadline: Option<Instant>,
+    /// When the last frame arrived, or the driver started
+    last_frame: Instant,
+    /// The last poll for frames found none
+    idle: bool,
+}
+
+impl CliDriver {
+    /// Create a driver running `task`.
+    #[allow(clippy::disallowed_methods)]
+    pub fn new(task: CliTask) -> Self {
+        Self {
+            task,
+            connections: Connections::new(),
+            notifier: NoopNotifier,
+            deadline: None,
+            last_frame: Instant::now(),
+            idle: false,
+        }
+    }
+
+    /// Give up once `timeout` has passed.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(self.last_frame + timeout);
+        self
+    }
+}
+
+impl Driver for CliDriver {
+    type Error = CliError;
+    type Instant = Instant;
+    type Notifier = NoopNotifier;
+
+    async fn poll_event(&mut self, app: &mut App) -> Result<Vec<AppAction>, Self::Error> {
+        let mut actions = Vec::new();
+        if self.idle {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            actions.extend(app.handle(AppEvent::Tick));
+        }
+        let now = self.now();
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(CliError::TimedOut);
+        }
+
+        actions.extend(self.task.step(app, now - self.last_frame >= SETTLE_TIME)?);
+        let mut stdout = io::stdout().lock();
+        for line in self.task.take_output() {
+            writeln!(stdout, "{line}")?;
+        }
+        stdout.flush()?;
+        Ok(actions)
+    }
+
+    async fn send_frame(&mut self, account: AccountId, frame: Frame) -> Result<(), Self::Error> {
+        self.connections.send(account, frame).await;
+        Ok(())
+    }
+
+    async fn recv_frame(&mut self) -> Option<(AccountId, Frame)> {
+        let received = self.connections.recv();
+        self.idle = received.is_none();
+        if !self.idle {
+            self.last_frame = self.now();
+        }
+        received
+    }
+
+    async fn connect(&mut self, account: AccountId, addr: &str) -> Result<(), Self::Error> {
+        Ok(self.connections.connect(account, addr).await?)
+    }
+
+    fn is_connected(&self, account: AccountId) -> bool {
+        self.connections.contains(account)
+    }
+
+    #[allow(clippy::disallowed_methods)]
+    fn now(&self) -> Self::Instant {
+        Instant::now()
+    }
+
+    fn render(&mut self, _app: &App) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn notifier(&mut self) -> &mut Self::Notifier {
+        &mut self.notifier
+    }
+
+    fn stop(&mut self) {
+        self.connections.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected_app() -> App {
+        let mut app = App::new("localhost:4433".into());
+        let _ = app.handle(AppEvent::Connected { session_id: 1, sender_id: 42 });
+        app
+    }
+
+    fn receive(app: &mut App, log_index: u64, text: &str) {
+        let _ = app.handle(AppEvent::MessageReceived {
+            room_id: 100,
+            sender_id: 7,
+            content: text.as_bytes().to_vec(),
+            reply_to: None,
+            log_index: Some(log_index),
+            timestamp: 0,
+        });
+    }
+
+    #[test]
+    fn send_joins_and_waits_for_delivery() {
+        let mut app = App::new("localhost:4433".into());
+        let mut task = CliTask::new(Task::Send { room_id: 100, text: "hi".into() }, false);
+        assert!(task.step(&mut app, false).unwrap().is_empty(), "waits for a connection");
+
+        let mut app = connected_app();
+        let actions = task.step(&mut app, false).unwrap();
+        assert!(actions.contains(&AppAction::JoinRoom { room_id: 100 }));
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 100 });
+        let actions = task.step(&mut app, false).unwrap();
+        assert!(actions.contains(&AppAction::SendMessage {
+            room_id: 100,
+            content: b"hi".to_vec(),
+            reply_to: None,
+        }));
+
+        let _ = app.handle(AppEvent::MessageSending {
+            room_id: 100,
+            sender_id: 42,
+            local_id: 1,
+            content: b"hi\tthere".to_vec(),
+            reply_to: None,
+            delivery: Delivery::Sent,
+            timestamp: 0,
+        });
+        assert!(task.step(&mut app, false).unwrap().is_empty());
+        let _ = app.handle(AppEvent::DeliveryChanged {
+            room_id: 100,
+            local_id: 1,
+            delivery: Delivery::Delivered,
+            log_index: Some(3),
+        });
+        assert_eq!(task.step(&mut app, false).unwrap(), vec![AppAction::Quit]);
+        assert_eq!(task.take_output(), vec!["100\t3\t42\thi\\tthere"]);
+    }
+
+    #[test]
+    fn listen_prints_only_new_messages_as_json() {
+        let mut app = connected_app();
+        let mut task = CliTask::new(Task::Listen { room_ids: vec![100] }, true);
+        let _ = task.step(&mut app, false).unwrap();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 100 });
+        receive(&mut app, 0, "synced on joining");
+        let _ = task.step(&mut app, false).unwrap();
+        let _ = task.step(&mut app, true).unwrap();
+        assert!(task.take_output().is_empty());
+
+        receive(&mut app, 1, "new");
+        let _ = task.step(&mut app, false).unwrap();
+        let output = task.take_output();
+        assert_eq!(output.len(), 1);
+        let line: serde_json::Value = serde_json::from_str(&output[0]).unwrap();
+        assert_eq!(line["room"], "100");
+        assert_eq!(line["log_index"], 1);
+        assert_eq!(line["text"], "new");
+    }
+
+    #[test]
+    fn export_loads_history_before_printing() {
+        let mut app = connected_app();
+        let mut task = CliTask::new(Task::Export { room_id: 100, since: 5 }, false);
+        let _ = task.step(&mut app, false).unwrap();
+        let _ = app.handle(AppEvent::RoomJoined { room_id: 100 });
+        receive(&mut app, 6, "six");
+        receive(&mut app, 5, "five");
+        assert!(task.step(&mut app, false).unwrap().is_empty(), "waits for the sync");
+
+        let actions = task.step(&mut app, true).unwrap();
+        assert!(actions.contains(&AppAction::LoadHistory { room_id: 100, until_log_index: 5 }));
+        let history = History::Complete;
+        let _ = app.handle(AppEvent::HistoryChanged { room_id: 100, history });
+        assert_eq!(task.step(&mut app, true).unwrap(), vec![AppAction::Quit]);
+        assert_eq!(task.take_output(), vec!["100\t5\t7\tfive", "100\t6\t7\tsix"]);
+    }
+}
@@ -0,0 +1,31 @@
+//! System clipboard.
+//!
+//! Copies through the terminal with an OSC 52 escape sequence, which most
+//! terminals pass on to the system clipboard, over SSH too. tmux needs
+//! `set-clipboard on`.
+
+use std::io::{Write, stdout};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+/// Copy `text` to the system clipboard.
+pub fn copy(text: &str) {
+    let mut out = stdout();
+    let _ = out.write_all(sequence(text).as_bytes()).and_then(|()| out.flush());
+}
+
+/// The escape sequence that copies `text`. Base64 keeps anything in `text`
+/// from ending the sequence early.
+fn sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_encoded_into_the_sequence() {
+        assert_eq!(sequence("hi\x07"), "\x1b]52;c;aGkH\x07");
+    }
+}
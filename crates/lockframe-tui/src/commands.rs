@@ -1,131 +1,479 @@
 //! Command parsing for TUI and other text-based interfaces.
 //!
-//! This module parses command strings into structured [`Command`] values.
+//! This module parses command strings into [`Intent`]s for
+//! [`lockframe_app::App::dispatch`], which checks them against the app's
+//! state. Parsing only rejects input that isn't a well-formed command.
+//!
+//! A few commands only change the terminal's own settings; [`parse_local`]
+//! picks those out before [`parse`] sees them.
+//!
+//! Wherever a command takes a room or user ID, it also takes a name from the
+//! [`AddressBook`] given to [`parse_with`].
+//!
+//! [`COMMANDS`] describes every command. Both parsers look names up in it
+//! and take their usage errors from it, and `/help` shows it, so the help
+//! can't drift from what parses.
 
+use lockframe_app::{AccountId, Intent, RoomOrder};
 use lockframe_core::mls::RoomId;
 
-/// Parsed command from user input.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Command {
-    /// Connect to the server.
-    Connect,
+use crate::{
+    contacts::{self, AddressBook},
+    ui::ThreadView,
+};
 
-    /// Create a new room.
-    CreateRoom {
-        /// 128-bit room UUID.
-        room_id: RoomId,
+/// Input that doesn't parse as a command.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    /// Unknown command.
+    #[error("Unknown command: {input}")]
+    Unknown {
+        /// The original input.
+        input: String,
     },
 
-    /// Join an existing room via external commit.
-    JoinRoom {
-        /// 128-bit room UUID.
-        room_id: RoomId,
+    /// Command with missing or invalid arguments.
+    #[error("/{command}: {error}")]
+    InvalidArgs {
+        /// Command name.
+        command: String,
+        /// Error message.
+        error: String,
     },
+}
+
+/// A command's name, arguments and what it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandInfo {
+    /// Name typed after `/`.
+    pub name: &'static str,
+    /// Other names for the command.
+    pub aliases: &'static [&'static str],
+    /// Arguments, `<required>` or `[optional]`.
+    pub args: &'static str,
+    /// What the command does.
+    pub summary: &'static str,
+    /// Example invocations.
+    pub examples: &'static [&'static str],
+}
 
-    /// Leave the active room.
-    LeaveActiveRoom,
+impl CommandInfo {
+    /// The command with its arguments, like `/join <room_id>`.
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.args)
+        }
+    }
 
-    /// Publish a key package to the server.
-    PublishKeyPackage,
+    /// Whether `name` is the command's name or an alias.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
 
-    /// Add a member to the active room.
-    AddMember {
-        /// User ID to add.
-        user_id: u64,
+/// Every command, in the order `/help` lists them.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "connect",
+        aliases: &[],
+        args: "",
+        summary: "Connect to the server",
+        examples: &["/connect"],
+    },
+    CommandInfo {
+        name: "account",
+        aliases: &[],
+        args: "<number>",
+        summary: "Switch to another account",
+        examples: &["/account 1"],
+    },
+    CommandInfo {
+        name: "create",
+        aliases: &[],
+        args: "<room>",
+        summary: "Create a room",
+        examples: &["/create 100"],
+    },
+    CommandInfo {
+        name: "join",
+        aliases: &[],
+        args: "<room>",
+        summary: "Join a room, by ID or alias",
+        examples: &["/join 100", "/join work"],
+    },
+    CommandInfo {
+        name: "leave",
+        aliases: &[],
+        args: "",
+        summary: "Leave the active room",
+        examples: &["/leave"],
+    },
+    CommandInfo {
+        name: "publish",
+        aliases: &[],
+        args: "",
+        summary: "Publish a key package so others can add you",
+        examples: &["/publish"],
+    },
+    CommandInfo {
+        name: "add",
+        aliases: &[],
+        args: "<user>",
+        summary: "Add a user to the active room, by ID or contact name",
+        examples: &["/add 42", "/add alice"],
+    },
+    CommandInfo {
+        name: "remove",
+        aliases: &["kick"],
+        args: "<user>",
+        summary: "Remove a member from the active room",
+        examples: &["/remove 42", "/kick @alice"],
+    },
+    CommandInfo {
+        name: "invite",
+        aliases: &[],
+        args: "<user>",
+        summary: "Invite a user to the active room",
+        examples: &["/invite 42"],
+    },
+    CommandInfo {
+        name: "accept",
+        aliases: &[],
+        args: "[room]",
+        summary: "Accept an invite, the newest by default",
+        examples: &["/accept", "/accept 100"],
+    },
+    CommandInfo {
+        name: "decline",
+        aliases: &[],
+        args: "[room]",
+        summary: "Decline an invite, the newest by default",
+        examples: &["/decline", "/decline 100"],
+    },
+    CommandInfo {
+        name: "rooms",
+        aliases: &[],
+        args: "[query]",
+        summary: "Search the room directory",
+        examples: &["/rooms", "/rooms rust"],
+    },
+    CommandInfo {
+        name: "next",
+        aliases: &[],
+        args: "",
+        summary: "Show more directory results",
+        examples: &["/next"],
+    },
+    CommandInfo {
+        name: "list",
+        aliases: &[],
+        args: "[name]",
+        summary: "List the active room in the directory, or unlist it",
+        examples: &["/list Rust help", "/list"],
+    },
+    CommandInfo {
+        name: "pin",
+        aliases: &[],
+        args: "",
+        summary: "Pin the active room to the top of the room list",
+        examples: &["/pin"],
+    },
+    CommandInfo {
+        name: "unpin",
+        aliases: &[],
+        args: "",
+        summary: "Unpin the active room",
+        examples: &["/unpin"],
+    },
+    CommandInfo {
+        name: "sort",
+        aliases: &[],
+        args: "<recent|alpha>",
+        summary: "Sort the room list",
+        examples: &["/sort recent", "/sort alpha"],
+    },
+    CommandInfo {
+        name: "filter",
+        aliases: &[],
+        args: "[text]",
+        summary: "Show only rooms matching text, or all rooms",
+        examples: &["/filter rust", "/filter"],
+    },
+    CommandInfo {
+        name: "reply",
+        aliases: &[],
+        args: "[log_index]",
+        summary: "Reply to a message, or stop replying",
+        examples: &["/reply 7", "/reply"],
+    },
+    CommandInfo {
+        name: "open",
+        aliases: &[],
+        args: "<log_index>",
+        summary: "Open an attachment in the external viewer",
+        examples: &["/open 7"],
+    },
+    CommandInfo {
+        name: "dismiss",
+        aliases: &[],
+        args: "",
+        summary: "Dismiss the newest notice",
+        examples: &["/dismiss"],
     },
+    CommandInfo {
+        name: "alias",
+        aliases: &[],
+        args: "[name] [room_id]",
+        summary: "Name a room, the active one by default, or list named rooms",
+        examples: &["/alias work 100", "/alias work", "/alias"],
+    },
+    CommandInfo {
+        name: "contact",
+        aliases: &[],
+        args: "[name <user_id>]",
+        summary: "Name a user, or list contacts",
+        examples: &["/contact alice 42", "/contact"],
+    },
+    CommandInfo {
+        name: "theme",
+        aliases: &[],
+        args: "[name]",
+        summary: "Switch theme, or list themes",
+        examples: &["/theme light", "/theme"],
+    },
+    CommandInfo {
+        name: "threads",
+        aliases: &[],
+        args: "[flat|collapsed|expanded]",
+        summary: "Lay out replies, or show the layout",
+        examples: &["/threads expanded", "/threads"],
+    },
+    CommandInfo {
+        name: "help",
+        aliases: &["?"],
+        args: "[command]",
+        summary: "Browse commands",
+        examples: &["/help", "/help join"],
+    },
+    CommandInfo {
+        name: "quit",
+        aliases: &["q"],
+        args: "",
+        summary: "Quit",
+        examples: &["/quit", "/q"],
+    },
+];
 
-    /// Quit the application.
-    Quit,
+/// The command named `name`, by its name or an alias.
+pub fn lookup(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|info| info.is_named(name))
+}
 
-    /// Send a message to the active room.
-    Message {
-        /// Message content.
-        content: String,
+/// Commands whose name starts with `prefix`.
+pub fn completions(prefix: &str) -> impl Iterator<Item = &'static CommandInfo> {
+    COMMANDS.iter().filter(move |info| info.name.starts_with(prefix))
+}
+
+/// Command the TUI handles itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalCommand {
+    /// Switch to a theme, or list them.
+    Theme {
+        /// Theme to switch to.
+        name: Option<String>,
     },
 
-    /// Unknown or invalid command.
-    Unknown {
-        /// The original input.
-        input: String,
+    /// Lay out replies, or show the layout.
+    Threads {
+        /// Layout to switch to.
+        view: Option<ThreadView>,
     },
 
-    /// Command with missing or invalid arguments.
-    InvalidArgs {
-        /// Command name.
-        command: String,
-        /// Error message.
-        error: String,
+    /// Name a room, or list named rooms.
+    Alias {
+        /// Name to give, and the room to give it to, or the active room.
+        alias: Option<(String, Option<RoomId>)>,
+    },
+
+    /// Name a user, or list contacts.
+    Contact {
+        /// Name to give, and the user to give it to.
+        contact: Option<(String, u64)>,
+    },
+
+    /// Decrypt an attachment and open it in the external viewer.
+    Open {
+        /// Log index of the message sharing it.
+        log_index: u64,
+    },
+
+    /// Browse commands, starting at one.
+    Help {
+        /// Command to start at.
+        command: Option<String>,
     },
 }
 
-/// Parse a user input string into a command.
+/// Parse a command the TUI handles itself, if `input` is one.
+pub fn parse_local(input: &str) -> Option<Result<LocalCommand, ParseError>> {
+    let mut parts = input.trim().strip_prefix('/')?.split_whitespace();
+    let info = lookup(parts.next()?)?;
+    let arg = parts.next().map(str::to_string);
+    let invalid =
+        |error: &str| ParseError::InvalidArgs { command: info.name.into(), error: error.into() };
+    let name = |name: String| contacts::validate_name(&name).map(str::to_string).map_err(&invalid);
+
+    let command = match info.name {
+        "theme" => Ok(LocalCommand::Theme { name: arg }),
+        "help" => Ok(LocalCommand::Help { command: arg }),
+        "open" => arg
+            .ok_or_else(|| invalid(&format!("Usage: {}", info.usage())))
+            .and_then(|arg| arg.parse().map_err(|_| invalid("Invalid log index")))
+            .map(|log_index| LocalCommand::Open { log_index }),
+        "threads" => arg
+            .map(|arg| ThreadView::from_name(&arg).ok_or_else(|| invalid("Unknown layout")))
+            .transpose()
+            .map(|view| LocalCommand::Threads { view }),
+        "alias" => arg
+            .map(|arg| {
+                let room_id = parts
+                    .next()
+                    .map(|id_str| id_str.parse().map_err(|_| invalid("Invalid room ID")))
+                    .transpose()?;
+                Ok((name(arg)?, room_id))
+            })
+            .transpose()
+            .map(|alias| LocalCommand::Alias { alias }),
+        "contact" => arg
+            .map(|arg| {
+                let id_str =
+                    parts.next().ok_or_else(|| invalid(&format!("Usage: {}", info.usage())))?;
+                let user_id = id_str.parse().map_err(|_| invalid("Invalid user ID"))?;
+                Ok((name(arg)?, user_id))
+            })
+            .transpose()
+            .map(|contact| LocalCommand::Contact { contact }),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Parse a user input string into an intent.
 ///
 /// Commands start with `/`. Anything else is treated as a message.
-pub fn parse(input: &str) -> Command {
-    let input = input.trim();
+pub fn parse(input: &str) -> Result<Intent, ParseError> {
+    parse_with(input, &AddressBook::new())
+}
 
-    if input.is_empty() {
-        return Command::Message { content: String::new() };
-    }
+/// Parse a user input string into an intent, taking names from `book`
+/// wherever an ID goes.
+pub fn parse_with(input: &str, book: &AddressBook) -> Result<Intent, ParseError> {
+    let input = input.trim();
 
     let Some(cmd_str) = input.strip_prefix('/') else {
-        return Command::Message { content: input.to_string() };
+        return Ok(Intent::SendMessage { content: input.to_string() });
     };
 
     let parts: Vec<&str> = cmd_str.split_whitespace().collect();
-    let command = parts.first().copied().unwrap_or("");
-
-    match command {
-        "connect" => Command::Connect,
+    let Some(info) = parts.first().and_then(|name| lookup(name)) else {
+        return Err(ParseError::Unknown { input: input.to_string() });
+    };
+    let command = info.name;
+    let rest = || parts.get(1..).unwrap_or_default().join(" ");
+    let invalid =
+        |error: &str| ParseError::InvalidArgs { command: command.into(), error: error.into() };
+    let usage = || invalid(&format!("Usage: {}", info.usage()));
+    let room = |arg: &str| book.room(arg).ok_or_else(|| invalid("Unknown room ID or alias"));
+    let user = |arg: &str| book.user(arg).ok_or_else(|| invalid("Unknown user ID or contact"));
+
+    let intent = match command {
+        "connect" => Intent::Connect,
+
+        "account" => match parts.get(1) {
+            Some(id_str) => {
+                let id = id_str.parse::<u32>().map_err(|_| invalid("Invalid account number"))?;
+                Intent::SwitchAccount { account: AccountId(id) }
+            },
+            None => return Err(usage()),
+        },
 
         "create" => match parts.get(1) {
-            Some(id_str) => match id_str.parse::<u128>() {
-                Ok(room_id) => Command::CreateRoom { room_id },
-                Err(_) => Command::InvalidArgs {
-                    command: "create".into(),
-                    error: "Invalid room ID".into(),
-                },
-            },
-            None => Command::InvalidArgs {
-                command: "create".into(),
-                error: "Usage: /create <room_id>".into(),
-            },
+            Some(arg) => Intent::CreateRoom { room_id: room(arg)? },
+            None => return Err(usage()),
         },
 
         "join" => match parts.get(1) {
-            Some(id_str) => match id_str.parse::<u128>() {
-                Ok(room_id) => Command::JoinRoom { room_id },
-                Err(_) => {
-                    Command::InvalidArgs { command: "join".into(), error: "Invalid room ID".into() }
-                },
-            },
-            None => Command::InvalidArgs {
-                command: "join".into(),
-                error: "Usage: /join <room_id>".into(),
-            },
+            Some(arg) => Intent::JoinRoom { room_id: room(arg)? },
+            None => return Err(usage()),
         },
 
-        "leave" => Command::LeaveActiveRoom,
+        "leave" => Intent::LeaveRoom,
 
-        "publish" => Command::PublishKeyPackage,
+        "publish" => Intent::PublishKeyPackage,
 
         "add" => match parts.get(1) {
-            Some(id_str) => match id_str.parse::<u64>() {
-                Ok(user_id) => Command::AddMember { user_id },
-                Err(_) => {
-                    Command::InvalidArgs { command: "add".into(), error: "Invalid user ID".into() }
-                },
-            },
-            None => Command::InvalidArgs {
-                command: "add".into(),
-                error: "Usage: /add <user_id>".into(),
+            Some(arg) => Intent::AddMember { user_id: user(arg)? },
+            None => return Err(usage()),
+        },
+
+        "remove" => match parts.get(1) {
+            Some(arg) => Intent::RemoveMember { user_id: user(arg)? },
+            None => return Err(usage()),
+        },
+
+        "invite" => match parts.get(1) {
+            Some(arg) => Intent::InviteUser { user_id: user(arg)? },
+            None => return Err(usage()),
+        },
+
+        "accept" | "decline" => {
+            let room_id = parts.get(1).map(|arg| room(arg)).transpose()?;
+            if command == "accept" {
+                Intent::AcceptInvite { room_id }
+            } else {
+                Intent::DeclineInvite { room_id }
+            }
+        },
+
+        "rooms" => Intent::SearchRooms { query: rest() },
+
+        "next" => Intent::NextRooms,
+
+        "list" => {
+            let name = rest();
+            Intent::ListRoom { name: (!name.is_empty()).then_some(name) }
+        },
+
+        "pin" => Intent::PinRoom { pinned: true },
+
+        "unpin" => Intent::PinRoom { pinned: false },
+
+        "sort" => match parts.get(1).copied() {
+            Some("recent") => Intent::SortRooms { order: RoomOrder::Recent },
+            Some("alpha" | "name") => Intent::SortRooms { order: RoomOrder::Alphabetical },
+            _ => return Err(usage()),
+        },
+
+        "filter" => Intent::FilterRooms { filter: rest() },
+
+        "reply" => match parts.get(1) {
+            Some(index_str) => {
+                let log_index =
+                    index_str.parse::<u64>().map_err(|_| invalid("Invalid log index"))?;
+                Intent::ReplyTo { log_index: Some(log_index) }
             },
+            None => Intent::ReplyTo { log_index: None },
         },
 
-        "quit" | "q" => Command::Quit,
+        "dismiss" => Intent::DismissNotice { id: None },
 
-        _ => Command::Unknown { input: input.to_string() },
-    }
+        "quit" => Intent::Quit,
+
+        _ => return Err(ParseError::Unknown { input: input.to_string() }),
+    };
+    Ok(intent)
 }
 
 #[cfg(test)]
@@ -134,54 +482,199 @@ mod tests {
 
     #[test]
     fn parse_message() {
-        assert_eq!(parse("hello world"), Command::Message { content: "hello world".into() });
+        assert_eq!(parse("hello world"), Ok(Intent::SendMessage { content: "hello world".into() }));
     }
 
     #[test]
     fn parse_connect() {
-        assert_eq!(parse("/connect"), Command::Connect);
+        assert_eq!(parse("/connect"), Ok(Intent::Connect));
+    }
+
+    #[test]
+    fn parse_switch_account() {
+        assert_eq!(parse("/account 1"), Ok(Intent::SwitchAccount { account: AccountId(1) }));
+        assert!(
+            matches!(parse("/account work"), Err(ParseError::InvalidArgs { command, .. }) if command == "account")
+        );
     }
 
     #[test]
     fn parse_create_room() {
-        assert_eq!(parse("/create 100"), Command::CreateRoom { room_id: 100 });
+        assert_eq!(parse("/create 100"), Ok(Intent::CreateRoom { room_id: 100 }));
     }
 
     #[test]
     fn parse_create_room_missing_id() {
         assert!(
-            matches!(parse("/create"), Command::InvalidArgs { command, .. } if command == "create")
+            matches!(parse("/create"), Err(ParseError::InvalidArgs { command, .. }) if command == "create")
         );
     }
 
     #[test]
     fn parse_join_room() {
-        assert_eq!(parse("/join 200"), Command::JoinRoom { room_id: 200 });
+        assert_eq!(parse("/join 200"), Ok(Intent::JoinRoom { room_id: 200 }));
     }
 
     #[test]
     fn parse_leave() {
-        assert_eq!(parse("/leave"), Command::LeaveActiveRoom);
+        assert_eq!(parse("/leave"), Ok(Intent::LeaveRoom));
     }
 
     #[test]
     fn parse_add_member() {
-        assert_eq!(parse("/add 42"), Command::AddMember { user_id: 42 });
+        assert_eq!(parse("/add 42"), Ok(Intent::AddMember { user_id: 42 }));
+    }
+
+    #[test]
+    fn parse_remove_member() {
+        assert_eq!(parse("/remove 42"), Ok(Intent::RemoveMember { user_id: 42 }));
+        assert_eq!(parse("/kick 42"), Ok(Intent::RemoveMember { user_id: 42 }));
+    }
+
+    #[test]
+    fn parse_invites() {
+        assert_eq!(parse("/invite 42"), Ok(Intent::InviteUser { user_id: 42 }));
+        assert_eq!(parse("/accept"), Ok(Intent::AcceptInvite { room_id: None }));
+        assert_eq!(parse("/decline 7"), Ok(Intent::DeclineInvite { room_id: Some(7) }));
+        assert!(
+            matches!(parse("/invite"), Err(ParseError::InvalidArgs { command, .. }) if command == "invite")
+        );
+    }
+
+    #[test]
+    fn parse_directory_commands() {
+        assert_eq!(parse("/rooms"), Ok(Intent::SearchRooms { query: String::new() }));
+        assert_eq!(
+            parse("/rooms rust  help"),
+            Ok(Intent::SearchRooms { query: "rust help".into() })
+        );
+        assert_eq!(parse("/next"), Ok(Intent::NextRooms));
+        assert_eq!(
+            parse("/list Rust help"),
+            Ok(Intent::ListRoom { name: Some("Rust help".into()) })
+        );
+        assert_eq!(parse("/list"), Ok(Intent::ListRoom { name: None }));
+    }
+
+    #[test]
+    fn parse_room_list_commands() {
+        assert_eq!(parse("/pin"), Ok(Intent::PinRoom { pinned: true }));
+        assert_eq!(parse("/unpin"), Ok(Intent::PinRoom { pinned: false }));
+        assert_eq!(parse("/sort recent"), Ok(Intent::SortRooms { order: RoomOrder::Recent }));
+        assert_eq!(parse("/sort alpha"), Ok(Intent::SortRooms { order: RoomOrder::Alphabetical }));
+        assert!(
+            matches!(parse("/sort"), Err(ParseError::InvalidArgs { command, .. }) if command == "sort")
+        );
+        assert_eq!(parse("/filter rust"), Ok(Intent::FilterRooms { filter: "rust".into() }));
+        assert_eq!(parse("/filter"), Ok(Intent::FilterRooms { filter: String::new() }));
+    }
+
+    #[test]
+    fn parse_reply() {
+        assert_eq!(parse("/reply 7"), Ok(Intent::ReplyTo { log_index: Some(7) }));
+        assert_eq!(parse("/reply"), Ok(Intent::ReplyTo { log_index: None }));
+        assert!(
+            matches!(parse("/reply last"), Err(ParseError::InvalidArgs { command, .. }) if command == "reply")
+        );
+    }
+
+    #[test]
+    fn parse_dismiss() {
+        assert_eq!(parse("/dismiss"), Ok(Intent::DismissNotice { id: None }));
     }
 
     #[test]
     fn parse_quit() {
-        assert_eq!(parse("/quit"), Command::Quit);
-        assert_eq!(parse("/q"), Command::Quit);
+        assert_eq!(parse("/quit"), Ok(Intent::Quit));
+        assert_eq!(parse("/q"), Ok(Intent::Quit));
+    }
+
+    #[test]
+    fn parse_local_commands() {
+        assert_eq!(parse_local("/theme"), Some(Ok(LocalCommand::Theme { name: None })));
+        assert_eq!(
+            parse_local("/theme light"),
+            Some(Ok(LocalCommand::Theme { name: Some("light".into()) }))
+        );
+        assert_eq!(
+            parse_local("/help join"),
+            Some(Ok(LocalCommand::Help { command: Some("join".into()) }))
+        );
+        assert_eq!(parse_local("/?"), Some(Ok(LocalCommand::Help { command: None })));
+        assert_eq!(parse_local("/open 7"), Some(Ok(LocalCommand::Open { log_index: 7 })));
+        assert!(matches!(parse_local("/open"), Some(Err(ParseError::InvalidArgs { .. }))));
+        assert_eq!(
+            parse_local("/threads collapsed"),
+            Some(Ok(LocalCommand::Threads { view: Some(ThreadView::Collapsed) }))
+        );
+        assert!(matches!(
+            parse_local("/threads nested"),
+            Some(Err(ParseError::InvalidArgs { .. }))
+        ));
+        assert_eq!(
+            parse_local("/alias #work"),
+            Some(Ok(LocalCommand::Alias { alias: Some(("work".into(), None)) }))
+        );
+        assert_eq!(
+            parse_local("/contact alice 42"),
+            Some(Ok(LocalCommand::Contact { contact: Some(("alice".into(), 42)) }))
+        );
+        assert!(matches!(parse_local("/contact alice"), Some(Err(ParseError::InvalidArgs { .. }))));
+        assert!(matches!(parse_local("/alias 12 100"), Some(Err(ParseError::InvalidArgs { .. }))));
+        assert_eq!(parse_local("/quit"), None);
+        assert_eq!(parse_local("theme"), None);
+    }
+
+    #[test]
+    fn every_documented_example_parses() {
+        let book = AddressBook::parse("room work = 100\ncontact alice = 42").unwrap();
+        for info in COMMANDS {
+            for example in info.examples {
+                assert!(
+                    matches!(parse_local(example), Some(Ok(_)))
+                        || parse_with(example, &book).is_ok(),
+                    "/{}: example {example} doesn't parse",
+                    info.name
+                );
+            }
+            for alias in info.aliases {
+                assert_eq!(lookup(alias), Some(info));
+            }
+        }
+    }
+
+    #[test]
+    fn usage_errors_come_from_the_table() {
+        assert_eq!(
+            parse("/join"),
+            Err(ParseError::InvalidArgs {
+                command: "join".into(),
+                error: "Usage: /join <room>".into()
+            })
+        );
+    }
+
+    #[test]
+    fn names_stand_in_for_ids() {
+        let book = AddressBook::parse("room work = 100\ncontact alice = 42").unwrap();
+        assert_eq!(parse_with("/join #work", &book), Ok(Intent::JoinRoom { room_id: 100 }));
+        assert_eq!(parse_with("/add @alice", &book), Ok(Intent::AddMember { user_id: 42 }));
+        assert_eq!(
+            parse_with("/accept work", &book),
+            Ok(Intent::AcceptInvite { room_id: Some(100) })
+        );
+        assert!(
+            matches!(parse_with("/invite bob", &book), Err(ParseError::InvalidArgs { command, .. }) if command == "invite")
+        );
     }
 
     #[test]
     fn parse_unknown_command() {
-        assert!(matches!(parse("/unknown"), Command::Unknown { .. }));
+        assert!(matches!(parse("/unknown"), Err(ParseError::Unknown { .. })));
     }
 
     #[test]
     fn parse_empty() {
-        assert_eq!(parse(""), Command::Message { content: String::new() });
+        assert_eq!(parse(""), Ok(Intent::SendMessage { content: String::new() }));
     }
 }
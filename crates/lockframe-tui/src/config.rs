@@ -0,0 +1,280 @@
+//! Configuration file
+//!
+//! Settings the TUI reads from `config.toml` in [`config_dir`] before
+//! applying command-line flags, which override them:
+//!
+//! ```toml
+//! server = "chat.example.com:4433"
+//! user_id = 42
+//! keys = "vi"
+//! keymap = "keys.conf"
+//! theme = "ocean"
+//! theme_files = ["ocean.theme"]
+//! timestamps = "long"
+//! utc_offset = "+02:00"
+//! notify = "desktop"
+//! address_book = "address-book"
+//! viewer = "feh --scale-down"
+//! tick_ms = 50
+//! ```
+//!
+//! Only a flat subset of TOML is read: one `key = value` per line, where a
+//! value is a string, an integer or an array of strings. Relative paths are
+//! relative to the file's directory.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::{Profile, TerminalNotifier, Viewer, ui};
+
+/// A config file that couldn't be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The file couldn't be read.
+    #[error("failed to read config: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A line isn't a valid setting.
+    #[error("config line {line}: {error}")]
+    Invalid {
+        /// Line number, starting at 1.
+        line: usize,
+        /// What is wrong with it.
+        error: String,
+    },
+}
+
+/// Settings from a config file. Unset ones fall back to the flags' defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Server address to connect to.
+    pub server: Option<String>,
+    /// User ID to connect as.
+    pub user_id: Option<u64>,
+    /// Built-in keybindings to start from.
+    pub keys: Option<Profile>,
+    /// File adjusting the keybindings.
+    pub keymap: Option<PathBuf>,
+    /// Theme to start with.
+    pub theme: Option<String>,
+    /// Files defining extra themes.
+    pub theme_files: Vec<PathBuf>,
+    /// How much of each message's time to show.
+    pub timestamps: Option<ui::TimeFormat>,
+    /// Minutes east of UTC to show times in.
+    pub utc_offset: Option<i32>,
+    /// How to surface notifications.
+    pub notify: Option<TerminalNotifier>,
+    /// File that `/alias` and `/contact` save names to.
+    pub address_book: Option<PathBuf>,
+    /// Program `/open` shows attachments with.
+    pub viewer: Option<Viewer>,
+    /// Longest wait for input before the App gets a tick.
+    pub tick_interval: Option<Duration>,
+}
+
+/// A value on the right of `=`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Array(Vec<String>),
+}
+
+/// `$XDG_CONFIG_HOME/lockframe`, or `~/.config/lockframe`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("lockframe"))
+}
+
+impl Config {
+    /// Where the config file is looked for by default.
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Load the config file at `path`. A missing file is an empty config
+    /// unless `required`.
+    pub fn load(path: &Path, required: bool) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(config) => Self::parse(&config, path.parent().unwrap_or(Path::new("."))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parse a config file, resolving relative paths against `base`.
+    pub fn parse(config: &str, base: &Path) -> Result<Self, ConfigError> {
+        let mut settings = Self::default();
+        for (index, line) in config.lines().enumerate() {
+            let invalid = |error: String| ConfigError::Invalid { line: index + 1, error };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) =
+                line.split_once('=').ok_or_else(|| invalid("expected `key = value`".into()))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).map_err(&invalid)?;
+            settings.set(key, value, base).map_err(invalid)?;
+        }
+        Ok(settings)
+    }
+
+    fn set(&mut self, key: &str, value: Value, base: &Path) -> Result<(), String> {
+        let path = |value: Value| string(value).map(|path| base.join(path));
+        match key {
+            "server" => self.server = Some(string(value)?),
+            "user_id" => self.user_id = Some(integer(&value)?),
+            "keys" => self.keys = Some(choice(value)?),
+            "keymap" => self.keymap = Some(path(value)?),
+            "theme" => self.theme = Some(string(value)?),
+            "theme_files" => {
+                let Value::Array(files) = value else {
+                    return Err("expected an array of strings".into());
+                };
+                self.theme_files = files.into_iter().map(|file| base.join(file)).collect();
+            },
+            "timestamps" => self.timestamps = Some(choice(value)?),
+            "utc_offset" => self.utc_offset = Some(ui::parse_utc_offset(&string(value)?)?),
+            "notify" => self.notify = Some(choice(value)?),
+            "address_book" => self.address_book = Some(path(value)?),
+            "viewer" => {
+                self.viewer = Some(Viewer::new(&string(value)?).ok_or("expected a command")?);
+            },
+            "tick_ms" => self.tick_interval = Some(Duration::from_millis(integer(&value)?)),
+            key => return Err(format!("unknown setting: {key}")),
+        }
+        Ok(())
+    }
+}
+
+fn string(value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err("expected a string".into()),
+    }
+}
+
+fn integer<T: TryFrom<i64>>(value: &Value) -> Result<T, String> {
+    match value {
+        Value::Integer(n) => T::try_from(*n).map_err(|_| format!("out of range: {n}")),
+        _ => Err("expected an integer".into()),
+    }
+}
+
+/// One of a flag's values, like `vi` for `keys`.
+fn choice<T: ValueEnum>(value: Value) -> Result<T, String> {
+    let name = string(value)?;
+    T::from_str(&name, true).map_err(|_| format!("unknown value: {name}"))
+}
+
+/// Parse a value, ignoring a trailing comment.
+fn parse_value(text: &str) -> Result<Value, String> {
+    let mut chars = text.chars().peekable();
+    let value = match chars.peek() {
+        Some('"' | '\'') => Value::String(parse_string(&mut chars)?),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+                if chars.next_if_eq(&']').is_some() {
+                    break;
+                }
+                if chars.peek().is_none() {
+                    return Err("unterminated array".into());
+                }
+                items.push(parse_string(&mut chars)?);
+            }
+            Value::Array(items)
+        },
+        _ => {
+            let number: String = chars.by_ref().take_while(|c| !c.is_whitespace()).collect();
+            let number = number.replace('_', "");
+            Value::Integer(number.parse().map_err(|_| format!("invalid value: {text}"))?)
+        },
+    };
+    let rest: String = chars.collect();
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected text after value: {rest}"));
+    }
+    Ok(value)
+}
+
+/// Parse a `"basic"` string with escapes or a `'literal'` one.
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let quote = chars.next().filter(|c| *c == '"' || *c == '\'').ok_or("expected a string")?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".into()),
+            Some(c) if c == quote => return Ok(s),
+            Some('\\') if quote == '"' => match chars.next() {
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                Some(c @ ('"' | '\\')) => s.push(c),
+                _ => return Err("invalid escape".into()),
+            },
+            Some(c) => s.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_resolves_paths() {
+        let config = Config::parse(
+            r#"
+            # Work account
+            server = "chat.example.com:4433"
+            user_id = 42
+            keys = 'vi'
+            theme_files = ["ocean.theme", "/etc/lockframe/dusk.theme"]
+            utc_offset = "+02:00" # summer time
+            notify = "desktop"
+            viewer = "feh --scale-down"
+            tick_ms = 1_000
+            "#,
+            Path::new("/home/me/.config/lockframe"),
+        )
+        .unwrap();
+
+        assert_eq!(config.server.as_deref(), Some("chat.example.com:4433"));
+        assert_eq!(config.user_id, Some(42));
+        assert_eq!(config.keys, Some(Profile::Vi));
+        assert_eq!(config.theme_files, vec![
+            PathBuf::from("/home/me/.config/lockframe/ocean.theme"),
+            PathBuf::from("/etc/lockframe/dusk.theme"),
+        ]);
+        assert_eq!(config.utc_offset, Some(120));
+        assert_eq!(config.notify, Some(TerminalNotifier::Desktop));
+        assert_eq!(config.viewer, Viewer::new("feh --scale-down"));
+        assert_eq!(config.tick_interval, Some(Duration::from_secs(1)));
+        assert_eq!(config.theme, None);
+    }
+
+    #[test]
+    fn invalid_settings_name_the_line() {
+        let error = |config: &str| match Config::parse(config, Path::new(".")) {
+            Err(ConfigError::Invalid { line, error }) => (line, error),
+            other => panic!("expected an invalid line, got {other:?}"),
+        };
+
+        assert_eq!(error("server = \"a\"\ncolor = \"red\"").0, 2);
+        assert_eq!(error("keys = \"nano\"").1, "unknown value: nano");
+        assert_eq!(error("user_id = \"me\"").1, "expected an integer");
+        assert_eq!(error("user_id = -1").1, "out of range: -1");
+        assert_eq!(error("server = \"a").1, "unterminated string");
+    }
+}
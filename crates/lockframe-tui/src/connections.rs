@@ -0,0 +1,75 @@
+//! Server connections
+//!
+//! One QUIC connection per account, shared by the terminal and scripting
+//! drivers. A connection whose transport task ended is dropped, so the
+//! runtime sees the account as disconnected and reconnects.
+
+use std::collections::BTreeMap;
+
+use lockframe_app::AccountId;
+use lockframe_client::transport::{self, ConnectedClient, TransportError};
+use lockframe_proto::Frame;
+use tokio::sync::mpsc::error::TryRecvError;
+
+/// Open connections, by account.
+#[derive(Default)]
+pub struct Connections {
+    clients: BTreeMap<AccountId, ConnectedClient>,
+}
+
+impl Connections {
+    /// No connections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect `account` to `addr`, replacing any connection it had.
+    pub async fn connect(&mut self, account: AccountId, addr: &str) -> Result<(), TransportError> {
+        let client = transport::connect(addr).await?;
+        if let Some(old) = self.clients.insert(account, client) {
+            old.stop();
+        }
+        Ok(())
+    }
+
+    /// Send `frame` to `account`'s server. Frames for accounts without a
+    /// connection are dropped.
+    pub async fn send(&mut self, account: AccountId, frame: Frame) {
+        if let Some(conn) = self.clients.get(&account)
+            && conn.to_server.send(frame).await.is_err()
+        {
+            self.clients.remove(&account);
+        }
+    }
+
+    /// A frame from any account's server, if one is waiting.
+    pub fn recv(&mut self) -> Option<(AccountId, Frame)> {
+        let mut received = None;
+        self.clients.retain(|&account, conn| {
+            if received.is_some() {
+                return true;
+            }
+            match conn.from_server.try_recv() {
+                Ok(frame) => {
+                    received = Some((account, frame));
+                    true
+                },
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Disconnected) => false,
+            }
+        });
+        received
+    }
+
+    /// Whether `account` is connected.
+    pub fn contains(&self, account: AccountId) -> bool {
+        self.clients.contains_key(&account)
+    }
+
+    /// Close every connection.
+    pub fn stop(&self) {
+        for conn in self.clients.values() {
+            conn.stop();
+        }
+    }
+}
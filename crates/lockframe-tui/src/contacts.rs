@@ -0,0 +1,215 @@
+//! Room aliases and contacts
+//!
+//! An [`AddressBook`] names rooms and users so commands can take a name
+//! wherever they take an ID. `/alias` and `/contact` add names, and the book
+//! is saved after each change to a file of lines like:
+//!
+//! ```text
+//! room work = 100
+//! contact alice = 42
+//! ```
+//!
+//! Names can't be numbers, which would read as IDs. A leading `#` on a room
+//! name or `@` on a contact is ignored, so `#work` and `@alice` work too.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use lockframe_core::mls::RoomId;
+
+/// An address book that couldn't be loaded or saved.
+#[derive(Debug, thiserror::Error)]
+pub enum AddressBookError {
+    /// The file couldn't be read or written.
+    #[error("failed to access address book: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A line isn't a valid entry.
+    #[error("address book line {line}: {error}")]
+    Invalid {
+        /// Line number, starting at 1.
+        line: usize,
+        /// What is wrong with it.
+        error: String,
+    },
+}
+
+/// Named rooms and users.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressBook {
+    rooms: BTreeMap<String, RoomId>,
+    contacts: BTreeMap<String, u64>,
+    /// Where changes are saved, if anywhere.
+    path: Option<PathBuf>,
+}
+
+impl AddressBook {
+    /// An empty address book that isn't saved.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the address book at `path`, starting empty if there is no file
+    /// yet. Changes are saved back to `path`.
+    pub fn load(path: &Path) -> Result<Self, AddressBookError> {
+        let mut book = match std::fs::read_to_string(path) {
+            Ok(config) => Self::parse(&config)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::new(),
+            Err(e) => return Err(e.into()),
+        };
+        book.path = Some(path.to_path_buf());
+        Ok(book)
+    }
+
+    /// Parse an address book file.
+    pub fn parse(config: &str) -> Result<Self, AddressBookError> {
+        let mut book = Self::new();
+        for (index, line) in config.lines().enumerate() {
+            let invalid = |error: String| AddressBookError::Invalid { line: index + 1, error };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (entry, id) = line.split_once('=').ok_or_else(|| {
+                invalid("expected `room name = id` or `contact name = id`".into())
+            })?;
+            let (kind, name) = entry.trim().split_once(' ').unwrap_or((entry.trim(), ""));
+            let name = validate_name(name.trim()).map_err(|e| invalid(e.to_string()))?;
+            let id = id.trim();
+            match kind {
+                "room" => {
+                    let room_id =
+                        id.parse().map_err(|_| invalid(format!("invalid room ID: {id}")))?;
+                    book.rooms.insert(name.to_string(), room_id);
+                },
+                "contact" => {
+                    let user_id =
+                        id.parse().map_err(|_| invalid(format!("invalid user ID: {id}")))?;
+                    book.contacts.insert(name.to_string(), user_id);
+                },
+                kind => return Err(invalid(format!("unknown entry: {kind}"))),
+            }
+        }
+        Ok(book)
+    }
+
+    /// Save the address book where it was loaded from. Does nothing for one
+    /// that wasn't loaded from a file.
+    pub fn save(&self) -> Result<(), AddressBookError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_config())?;
+        Ok(())
+    }
+
+    fn to_config(&self) -> String {
+        let rooms = self.rooms.iter().map(|(name, id)| format!("room {name} = {id}\n"));
+        let contacts = self.contacts.iter().map(|(name, id)| format!("contact {name} = {id}\n"));
+        rooms.chain(contacts).collect()
+    }
+
+    /// Name a room, replacing what the name was for.
+    pub fn set_room(&mut self, name: &str, room_id: RoomId) {
+        self.rooms.insert(name.trim_start_matches('#').to_string(), room_id);
+    }
+
+    /// Name a user, replacing who the name was for.
+    pub fn set_contact(&mut self, name: &str, user_id: u64) {
+        self.contacts.insert(name.trim_start_matches('@').to_string(), user_id);
+    }
+
+    /// The room `arg` is the ID or name of.
+    pub fn room(&self, arg: &str) -> Option<RoomId> {
+        arg.parse().ok().or_else(|| self.rooms.get(arg.trim_start_matches('#')).copied())
+    }
+
+    /// The user `arg` is the ID or name of.
+    pub fn user(&self, arg: &str) -> Option<u64> {
+        arg.parse().ok().or_else(|| self.contacts.get(arg.trim_start_matches('@')).copied())
+    }
+
+    /// A name for `room_id`, if it has any.
+    pub fn room_name(&self, room_id: RoomId) -> Option<&str> {
+        self.rooms.iter().find(|&(_, &id)| id == room_id).map(|(name, _)| name.as_str())
+    }
+
+    /// A name for `user_id`, if they have any.
+    pub fn contact_name(&self, user_id: u64) -> Option<&str> {
+        self.contacts.iter().find(|&(_, &id)| id == user_id).map(|(name, _)| name.as_str())
+    }
+
+    /// Named rooms, by name.
+    pub fn rooms(&self) -> impl Iterator<Item = (&str, RoomId)> {
+        self.rooms.iter().map(|(name, &id)| (name.as_str(), id))
+    }
+
+    /// Named users, by name.
+    pub fn contacts(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.contacts.iter().map(|(name, &id)| (name.as_str(), id))
+    }
+}
+
+/// Check that `name` can name a room or user, without its `#` or `@`.
+pub fn validate_name(name: &str) -> Result<&str, &'static str> {
+    let name = name.trim_start_matches(['#', '@']);
+    if name.is_empty() {
+        return Err("missing name");
+    }
+    if name.contains(|c: char| c.is_whitespace() || c == '=') {
+        return Err("names can't contain spaces or `=`");
+    }
+    if name.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("names can't be numbers");
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_resolve_alongside_ids() {
+        let mut book = AddressBook::parse("room work = 100\ncontact alice = 42").unwrap();
+        book.set_contact("@bob", 43);
+
+        assert_eq!(book.room("work"), Some(100));
+        assert_eq!(book.room("#work"), Some(100));
+        assert_eq!(book.room("7"), Some(7));
+        assert_eq!(book.room("home"), None);
+        assert_eq!(book.user("@alice"), Some(42));
+        assert_eq!(book.user("bob"), Some(43));
+        assert_eq!(AddressBook::parse(&book.to_config()).unwrap(), book);
+    }
+
+    #[test]
+    fn invalid_entries_name_the_line() {
+        let error = |config: &str| match AddressBook::parse(config) {
+            Err(AddressBookError::Invalid { line, error }) => (line, error),
+            other => panic!("expected an invalid line, got {other:?}"),
+        };
+
+        assert_eq!(error("room work = 1\nroom 12 = 2"), (2, "names can't be numbers".into()));
+        assert_eq!(error("contact alice = me").1, "invalid user ID: me");
+        assert_eq!(error("user alice = 1").1, "unknown entry: user");
+    }
+
+    #[test]
+    fn saves_where_it_was_loaded_from() {
+        let path = std::env::temp_dir()
+            .join(format!("lockframe-address-book-{}", std::process::id()))
+            .join("address-book");
+        let mut book = AddressBook::load(&path).unwrap();
+        book.set_room("work", 100);
+        book.save().unwrap();
+
+        assert_eq!(AddressBook::load(&path).unwrap(), book);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
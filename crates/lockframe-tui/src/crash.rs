@@ -0,0 +1,48 @@
+//! Crash dumps for bug reports.
+//!
+//! A [`CrashDump`] holds a snapshot of the App as of the latest render and
+//! installs a panic hook that writes it to a file as JSON, so a report can
+//! show what the App looked like when the TUI failed. Message content and
+//! drafts are redacted.
+
+use std::{
+    panic,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError, TryLockError},
+};
+
+use lockframe_app::{App, AppSnapshot};
+
+/// Latest App snapshot, written out if the process panics.
+pub struct CrashDump {
+    last: Arc<Mutex<Option<AppSnapshot>>>,
+}
+
+impl CrashDump {
+    /// Install a panic hook that writes the latest snapshot to `path`, then
+    /// runs the previous hook.
+    pub fn install(path: PathBuf) -> Self {
+        let last = Arc::new(Mutex::new(None::<AppSnapshot>));
+        let recorded = Arc::clone(&last);
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // A panic while recording leaves the lock held on this thread
+            let snapshot = match recorded.try_lock() {
+                Ok(mut last) => last.take(),
+                Err(TryLockError::Poisoned(e)) => e.into_inner().take(),
+                Err(TryLockError::WouldBlock) => None,
+            };
+            if let Some(json) = snapshot.and_then(|s| serde_json::to_vec_pretty(&s).ok()) {
+                let _ = std::fs::write(&path, json);
+            }
+            previous(info);
+        }));
+        Self { last }
+    }
+
+    /// Replace the snapshot with `app`'s current state.
+    pub fn record(&self, app: &App) {
+        let snapshot = app.snapshot();
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+    }
+}
@@ -1,17 +1,59 @@
 //! Input state and key handling for the TUI.
 //!
 //! This module owns all text input state (buffer, cursor) and handles
-//! character-level key events. Command parsing happens here on Enter.
+//! character-level key events. On Enter the buffer is parsed into an
+//! [`lockframe_app::Intent`] and dispatched to the App.
+//!
+//! While a room is active the buffer mirrors that room's draft in the App,
+//! so each room keeps its own half-typed message.
+//!
+//! Keys run the [`KeyAction`]s a [`KeyMap`] binds them to; unbound
+//! characters are typed. Scrolling the oldest message into view loads
+//! earlier history.
+//!
+//! Focus moves between the input line and the other panes. While the room
+//! list has focus, scrolling selects rooms instead; while a sidebar has
+//! focus, left and right move its border.
+//!
+//! While the message view has focus, scrolling moves a selection, which
+//! the copy actions copy from. Copied text waits in
+//! [`InputState::take_clipboard`] for the driver. Pasted text goes into the
+//! buffer as text, newlines included, never as keys.
+//!
+//! While the member list has focus, scrolling selects a member, whom the
+//! copy and remove actions act on. Adding or removing a member starts typing
+//! `/add` or `/remove` on the input line, so nothing happens until the
+//! command is submitted.
+//!
+//! Room and user names in commands resolve through the [`AddressBook`],
+//! which `/alias` and `/contact` add to.
+//!
+//! While `/help` is open, scrolling selects a command and submitting starts
+//! typing it.
+
+use lockframe_app::{App, AppAction, Intent, Member};
+use lockframe_core::mls::RoomId;
 
-use lockframe_app::{App, AppAction};
+use crate::{
+    commands::{self, LocalCommand},
+    contacts::AddressBook,
+    keymap::{KeyAction, KeyMap, Mode},
+    ui::{self, PaneLayout, Themes, ThreadView, Timestamps},
+    viewer::{self, Viewer},
+};
 
-use crate::commands::{self, Command};
+/// Messages one turn of the mouse wheel scrolls.
+const WHEEL_LINES: usize = 3;
 
 /// Key input events from the terminal.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum KeyInput {
     /// Character input.
     Char(char),
+    /// Character with Control held, lowercased.
+    Ctrl(char),
+    /// Character with Alt held.
+    Alt(char),
     /// Enter/Return key.
     Enter,
     /// Backspace key.
@@ -20,6 +62,8 @@ pub enum KeyInput {
     Delete,
     /// Tab key.
     Tab,
+    /// Shift+Tab.
+    BackTab,
     /// Escape key.
     Esc,
     /// Left arrow.
@@ -30,12 +74,43 @@ pub enum KeyInput {
     Up,
     /// Down arrow.
     Down,
+    /// Page Up key.
+    PageUp,
+    /// Page Down key.
+    PageDown,
     /// Home key.
     Home,
     /// End key.
     End,
 }
 
+/// Part of the screen keys act on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pane {
+    /// The input line.
+    #[default]
+    Input,
+    /// The room list.
+    Rooms,
+    /// The active room's messages.
+    Messages,
+    /// The active room's members.
+    Members,
+}
+
+impl Pane {
+    /// The pane focus moves to after this one.
+    #[must_use]
+    pub fn next(self) -> Self {
+        match self {
+            Self::Input => Self::Rooms,
+            Self::Rooms => Self::Messages,
+            Self::Messages => Self::Members,
+            Self::Members => Self::Input,
+        }
+    }
+}
+
 /// Input state for the TUI.
 ///
 /// Manages the text input buffer and cursor position.
@@ -46,6 +121,34 @@ pub struct InputState {
     buffer: String,
     /// Cursor position within the buffer.
     cursor: usize,
+    /// Room whose draft the buffer holds.
+    room: Option<RoomId>,
+    /// What keys do.
+    keymap: KeyMap,
+    /// Whether keys type or run normal mode bindings.
+    mode: Mode,
+    /// Pane keys act on.
+    focus: Pane,
+    /// Sizes of the panes.
+    layout: PaneLayout,
+    /// Themes to switch between with `/theme`.
+    themes: Themes,
+    /// How message times are shown.
+    timestamps: Timestamps,
+    /// How replies are laid out.
+    threads: ThreadView,
+    /// Command selected in the open `/help` browser.
+    help: Option<usize>,
+    /// Names for rooms and users.
+    address_book: AddressBook,
+    /// Program `/open` shows attachments with.
+    viewer: Viewer,
+    /// Selected message, counted up from the bottom of the message view.
+    selection: usize,
+    /// Selected member, as an index into the active room's sorted members.
+    member: usize,
+    /// Text copied since the driver last took it.
+    clipboard: Option<String>,
 }
 
 impl InputState {
@@ -54,6 +157,42 @@ impl InputState {
         Self::default()
     }
 
+    /// Use `keymap` instead of the default bindings.
+    #[must_use]
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.keymap = keymap;
+        self.mode = Mode::Insert;
+        self
+    }
+
+    /// Offer `themes` instead of the built-in ones.
+    #[must_use]
+    pub fn with_themes(mut self, themes: Themes) -> Self {
+        self.themes = themes;
+        self
+    }
+
+    /// Show message times as `timestamps` says.
+    #[must_use]
+    pub fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Resolve names in commands with `address_book`.
+    #[must_use]
+    pub fn with_address_book(mut self, address_book: AddressBook) -> Self {
+        self.address_book = address_book;
+        self
+    }
+
+    /// Open attachments with `viewer` instead of the system's default.
+    #[must_use]
+    pub fn with_viewer(mut self, viewer: Viewer) -> Self {
+        self.viewer = viewer;
+        self
+    }
+
     /// Current text in the input buffer.
     pub fn buffer(&self) -> &str {
         &self.buffer
@@ -64,56 +203,429 @@ impl InputState {
         self.cursor
     }
 
+    /// Whether keys type or run normal mode bindings.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Pane keys act on.
+    pub fn focus(&self) -> Pane {
+        self.focus
+    }
+
+    /// Sizes of the panes.
+    pub fn layout(&self) -> &PaneLayout {
+        &self.layout
+    }
+
+    /// Themes to switch between, and the one in use.
+    pub fn themes(&self) -> &Themes {
+        &self.themes
+    }
+
+    /// How message times are shown.
+    pub fn timestamps(&self) -> &Timestamps {
+        &self.timestamps
+    }
+
+    /// How replies are laid out.
+    pub fn threads(&self) -> ThreadView {
+        self.threads
+    }
+
+    /// Names for rooms and users.
+    pub fn address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    /// Selected message, as messages below it, while the message view has
+    /// focus.
+    pub fn selected(&self, app: &App) -> Option<usize> {
+        let scroll = app.active_room_state()?.scroll;
+        (self.focus == Pane::Messages).then(|| scroll.saturating_add(self.selection))
+    }
+
+    /// Selected member while the member list has focus.
+    pub fn selected_member(&self, app: &App) -> Option<u64> {
+        if self.focus != Pane::Members {
+            return None;
+        }
+        let members = app.active_room_state()?.sorted_members();
+        let last = members.len().checked_sub(1)?;
+        members.get(self.member.min(last)).copied()
+    }
+
+    /// Text copied since the last call, for the driver to put on the
+    /// clipboard.
+    pub fn take_clipboard(&mut self) -> Option<String> {
+        self.clipboard.take()
+    }
+
+    /// Index into [`commands::COMMANDS`] of the command selected in the
+    /// `/help` browser, if it is open.
+    pub fn help(&self) -> Option<usize> {
+        self.help
+    }
+
+    /// Load the active room's draft into the buffer if another room became
+    /// active since the last call.
+    pub fn sync(&mut self, app: &App) {
+        if self.room == app.active_room() {
+            return;
+        }
+        self.room = app.active_room();
+        self.selection = 0;
+        self.member = 0;
+        let draft = app.active_room_state().map(|room| &room.draft);
+        self.buffer = draft.map(|draft| draft.text.clone()).unwrap_or_default();
+        self.cursor = draft.map_or(0, |draft| draft.cursor);
+    }
+
     /// Handle a key input event.
     ///
     /// Returns actions to process (may be empty for input-only keys,
     /// or contain protocol actions for commands).
     pub fn handle_key(&mut self, key: KeyInput, app: &mut App) -> Vec<AppAction> {
-        match key {
-            KeyInput::Char(c) => {
-                self.buffer.insert(self.cursor, c);
-                self.cursor = self.cursor.saturating_add(1);
-                vec![AppAction::Render]
+        self.sync(app);
+        if let Some(selected) = self.help {
+            return self.run_in_help(selected, self.keymap.action(self.mode, key), app);
+        }
+        match (self.keymap.action(self.mode, key), self.focus) {
+            (Some(action), Pane::Input) => self.run(action, app),
+            (Some(action), _) => self.run_in_pane(action, app),
+            (None, Pane::Input) if self.mode == Mode::Insert => match key {
+                KeyInput::Char(c) => {
+                    self.buffer.insert(self.cursor, c);
+                    self.cursor = self.cursor.saturating_add(1);
+                    self.save_draft(app)
+                },
+                _ => vec![],
             },
-            KeyInput::Backspace => {
+            (None, _) => vec![],
+        }
+    }
+
+    /// Run a bound action on the input line.
+    fn run(&mut self, action: KeyAction, app: &mut App) -> Vec<AppAction> {
+        match action {
+            KeyAction::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyAction::Right => self.move_right(),
+            KeyAction::WordLeft => self.cursor = self.word_start(),
+            KeyAction::WordRight => self.cursor = self.next_word(),
+            KeyAction::LineStart => self.cursor = 0,
+            KeyAction::LineEnd => self.cursor = self.buffer.len(),
+            KeyAction::DeleteBack => {
                 if self.cursor > 0 {
                     self.cursor = self.cursor.saturating_sub(1);
                     self.buffer.remove(self.cursor);
                 }
-                vec![AppAction::Render]
             },
-            KeyInput::Delete => {
+            KeyAction::DeleteForward => {
                 if self.cursor < self.buffer.len() {
                     self.buffer.remove(self.cursor);
                 }
+            },
+            KeyAction::DeleteWord => {
+                let start = self.word_start();
+                self.buffer.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            },
+            KeyAction::DeleteToStart => {
+                self.buffer.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            },
+            KeyAction::DeleteToEnd => self.buffer.truncate(self.cursor),
+            KeyAction::Append => {
+                self.mode = Mode::Insert;
+                self.move_right();
+            },
+            KeyAction::Submit => return self.handle_enter(app),
+            KeyAction::Cancel => return Self::cancel(app),
+            KeyAction::NextRoom => return Self::cycle_room(app, true),
+            KeyAction::PrevRoom => return Self::cycle_room(app, false),
+            KeyAction::ScrollUp => return Self::scroll(app, true, 1),
+            KeyAction::ScrollDown => return Self::scroll(app, false, 1),
+            KeyAction::PageUp => return Self::scroll(app, true, Self::page(app)),
+            KeyAction::PageDown => return Self::scroll(app, false, Self::page(app)),
+            KeyAction::FocusNext => return self.focus_next(app),
+            KeyAction::CopyMessage
+            | KeyAction::CopySender
+            | KeyAction::CopyRoom
+            | KeyAction::CopySafetyCode => return self.copy(action, app),
+            KeyAction::AddMember => return self.prompt_member(true, app),
+            KeyAction::RemoveMember => return self.prompt_member(false, app),
+            KeyAction::InsertMode => {
+                self.mode = Mode::Insert;
+                return vec![AppAction::Render];
+            },
+            KeyAction::NormalMode if self.keymap.has_normal_mode() => {
+                self.mode = Mode::Normal;
+                return vec![AppAction::Render];
+            },
+            KeyAction::NormalMode => return vec![],
+        }
+        self.save_draft(app)
+    }
+
+    /// Run a bound action while a pane other than the input line has
+    /// focus. Submitting or cancelling returns to the input line.
+    fn run_in_pane(&mut self, action: KeyAction, app: &mut App) -> Vec<AppAction> {
+        match (self.focus, action) {
+            (_, KeyAction::FocusNext) => self.focus_next(app),
+            (_, KeyAction::Submit | KeyAction::Cancel) => {
+                self.focus = Pane::Input;
                 vec![AppAction::Render]
             },
-            KeyInput::Left => {
-                self.cursor = self.cursor.saturating_sub(1);
+            (Pane::Rooms, KeyAction::ScrollUp) | (_, KeyAction::PrevRoom) => {
+                Self::cycle_room(app, false)
+            },
+            (Pane::Rooms, KeyAction::ScrollDown) | (_, KeyAction::NextRoom) => {
+                Self::cycle_room(app, true)
+            },
+            (
+                _,
+                KeyAction::CopyMessage
+                | KeyAction::CopySender
+                | KeyAction::CopyRoom
+                | KeyAction::CopySafetyCode,
+            ) => self.copy(action, app),
+            (_, KeyAction::AddMember) => self.prompt_member(true, app),
+            (Pane::Members, KeyAction::DeleteBack | KeyAction::DeleteForward)
+            | (_, KeyAction::RemoveMember) => self.prompt_member(false, app),
+            (Pane::Members, KeyAction::ScrollUp) => self.select_member(app, false),
+            (Pane::Members, KeyAction::ScrollDown) => self.select_member(app, true),
+            (Pane::Messages, KeyAction::ScrollUp) => self.select(app, true, 1),
+            (Pane::Messages, KeyAction::ScrollDown) => self.select(app, false, 1),
+            (Pane::Messages, KeyAction::PageUp) => self.select(app, true, Self::page(app)),
+            (Pane::Messages, KeyAction::PageDown) => self.select(app, false, Self::page(app)),
+            (_, KeyAction::ScrollUp) => Self::scroll(app, true, 1),
+            (_, KeyAction::ScrollDown) => Self::scroll(app, false, 1),
+            (_, KeyAction::PageUp) => Self::scroll(app, true, Self::page(app)),
+            (_, KeyAction::PageDown) => Self::scroll(app, false, Self::page(app)),
+            (pane, KeyAction::Left | KeyAction::Right) if self.layout.width(pane).is_some() => {
+                self.layout.move_border(pane, action == KeyAction::Right);
                 vec![AppAction::Render]
             },
-            KeyInput::Right => {
-                if self.cursor < self.buffer.len() {
-                    self.cursor = self.cursor.saturating_add(1);
+            _ => vec![],
+        }
+    }
+
+    /// Run a bound action while `/help` is open. Submitting starts typing
+    /// the selected command; cancelling closes the browser.
+    fn run_in_help(
+        &mut self,
+        selected: usize,
+        action: Option<KeyAction>,
+        app: &mut App,
+    ) -> Vec<AppAction> {
+        let last = commands::COMMANDS.len().saturating_sub(1);
+        self.help = match action {
+            Some(KeyAction::ScrollUp | KeyAction::PrevRoom) => Some(selected.saturating_sub(1)),
+            Some(KeyAction::ScrollDown | KeyAction::NextRoom) => {
+                Some(selected.saturating_add(1).min(last))
+            },
+            Some(KeyAction::PageUp | KeyAction::LineStart) => Some(0),
+            Some(KeyAction::PageDown | KeyAction::LineEnd) => Some(last),
+            Some(KeyAction::Submit) => {
+                if let Some(info) = commands::COMMANDS.get(selected) {
+                    self.buffer = format!("/{} ", info.name);
+                    self.cursor = self.buffer.len();
+                    self.mode = Mode::Insert;
                 }
-                vec![AppAction::Render]
+                self.focus = Pane::Input;
+                None
             },
-            KeyInput::Home => {
-                self.cursor = 0;
-                vec![AppAction::Render]
+            Some(KeyAction::Cancel | KeyAction::NormalMode) => None,
+            _ => return vec![],
+        };
+        if self.help.is_none() {
+            return self.save_draft(app);
+        }
+        vec![AppAction::Render]
+    }
+
+    /// Move focus to the next pane, skipping the member list while no room
+    /// is active.
+    fn focus_next(&mut self, app: &App) -> Vec<AppAction> {
+        self.focus = self.focus.next();
+        if self.focus == Pane::Members && app.active_room().is_none() {
+            self.focus = self.focus.next();
+        }
+        self.selection = 0;
+        vec![AppAction::Render]
+    }
+
+    /// Move the selected message up or down by `count`, scrolling to keep
+    /// it in view.
+    fn select(&mut self, app: &mut App, up: bool, count: usize) -> Vec<AppAction> {
+        let Some(room) = app.active_room_state() else {
+            return vec![];
+        };
+        let rows = ui::chat_rows(app.terminal_size().1).max(1);
+        let below = room.scroll.saturating_add(self.selection);
+        let below = if up {
+            below.saturating_add(count).min(room.messages.len().saturating_sub(1))
+        } else {
+            below.saturating_sub(count)
+        };
+        let scroll = room.scroll.clamp(below.saturating_sub(rows - 1), below);
+        self.selection = below - scroll;
+
+        match scroll.cmp(&room.scroll) {
+            std::cmp::Ordering::Equal => vec![AppAction::Render],
+            std::cmp::Ordering::Greater => Self::scroll(app, true, scroll - room.scroll),
+            std::cmp::Ordering::Less => Self::scroll(app, false, room.scroll - scroll),
+        }
+    }
+
+    /// Move the selected member down or up one, stopping at the ends.
+    fn select_member(&mut self, app: &App, down: bool) -> Vec<AppAction> {
+        let last = app.active_room_state().map_or(0, |room| room.members.len().saturating_sub(1));
+        let member = self.member.min(last);
+        self.member =
+            if down { member.saturating_add(1).min(last) } else { member.saturating_sub(1) };
+        vec![AppAction::Render]
+    }
+
+    /// Start typing `/add`, or `/remove` with the selected member, for the
+    /// user to finish or submit.
+    fn prompt_member(&mut self, add: bool, app: &mut App) -> Vec<AppAction> {
+        if app.active_room().is_none() {
+            return vec![];
+        }
+        self.buffer = match self.selected_member(app).filter(|_| !add) {
+            Some(user_id) => {
+                let name = self
+                    .address_book
+                    .contact_name(user_id)
+                    .map_or_else(|| user_id.to_string(), |name| format!("@{name}"));
+                app.set_status(format!("Press Enter to remove {name}"));
+                format!("/remove {name}")
             },
-            KeyInput::End => {
-                self.cursor = self.buffer.len();
-                vec![AppAction::Render]
+            None if add => "/add ".to_string(),
+            None => "/remove ".to_string(),
+        };
+        self.cursor = self.buffer.len();
+        self.focus = Pane::Input;
+        self.mode = Mode::Insert;
+        self.save_draft(app)
+    }
+
+    /// Copy the selected message's text, the ID or safety code of the
+    /// selected member or message sender, or the active room's ID.
+    fn copy(&mut self, action: KeyAction, app: &mut App) -> Vec<AppAction> {
+        let Some(room) = app.active_room_state() else {
+            return vec![];
+        };
+        let below = self.selected(app).unwrap_or(room.scroll);
+        let message = room
+            .messages
+            .len()
+            .checked_sub(below.saturating_add(1))
+            .and_then(|i| room.messages.get(i));
+        let user_id = match self.focus {
+            Pane::Members => self.selected_member(app),
+            _ => message.map(|msg| msg.sender_id),
+        };
+
+        let copied = match (action, user_id, message) {
+            (KeyAction::CopyRoom, ..) => {
+                Ok((room.room_id.to_string(), format!("Copied room ID {}", room.room_id)))
+            },
+            (KeyAction::CopySender, Some(user_id), _) => {
+                Ok((user_id.to_string(), format!("Copied user ID {user_id}")))
+            },
+            (KeyAction::CopySafetyCode, Some(user_id), _) => room
+                .roster
+                .get(&user_id)
+                .and_then(Member::safety_code)
+                .map(|code| (code, format!("Copied safety code of user {user_id}")))
+                .ok_or_else(|| format!("No safety code for user {user_id} yet")),
+            (KeyAction::CopyMessage, _, Some(msg)) => {
+                Ok((msg.content_str().into_owned(), "Copied message".to_string()))
             },
-            KeyInput::Enter => self.handle_enter(app),
-            KeyInput::Tab => self.handle_tab(app),
-            KeyInput::Esc => vec![AppAction::Quit],
-            KeyInput::Up | KeyInput::Down => vec![],
+            _ => return vec![],
+        };
+        match copied {
+            Ok((text, status)) => {
+                self.clipboard = Some(text);
+                app.set_status(status);
+            },
+            Err(status) => app.set_status(status),
+        }
+        vec![AppAction::Render]
+    }
+
+    /// Handle pasted text: insert it at the cursor as typed text, keeping
+    /// newlines and dropping other control characters.
+    pub fn handle_paste(&mut self, text: &str, app: &mut App) -> Vec<AppAction> {
+        self.sync(app);
+        if self.help.is_some() {
+            return vec![];
+        }
+        let text: String = text
+            .replace("\r\n", "\n")
+            .chars()
+            .map(|c| match c {
+                '\r' => '\n',
+                '\t' => ' ',
+                c => c,
+            })
+            .filter(|&c| c == '\n' || !c.is_control())
+            .collect();
+
+        self.focus = Pane::Input;
+        self.buffer.insert_str(self.cursor, &text);
+        self.cursor = self.cursor.saturating_add(text.len());
+        self.save_draft(app)
+    }
+
+    fn move_right(&mut self) {
+        if self.cursor < self.buffer.len() {
+            self.cursor = self.cursor.saturating_add(1);
+        }
+    }
+
+    /// Start of the word before the cursor.
+    fn word_start(&self) -> usize {
+        let before = self.buffer[..self.cursor].trim_end();
+        before.trim_end_matches(|c: char| !c.is_whitespace()).len()
+    }
+
+    /// Start of the word after the cursor, or the end of the line.
+    fn next_word(&self) -> usize {
+        let rest = &self.buffer[self.cursor..];
+        let after_word = rest.trim_start_matches(|c: char| !c.is_whitespace());
+        self.buffer.len() - after_word.trim_start().len()
+    }
+
+    /// Dismiss the newest notice, else close the directory, else quit.
+    fn cancel(app: &mut App) -> Vec<AppAction> {
+        if !app.notices().is_empty() {
+            let id = app.notices().back().map(|notice| notice.id);
+            return app.dispatch(Intent::DismissNotice { id });
+        }
+        if app.directory().is_some() {
+            app.close_directory();
+            return vec![AppAction::Render];
+        }
+        vec![AppAction::Quit]
+    }
+
+    /// Copy the buffer into the active room's draft.
+    fn save_draft(&self, app: &mut App) -> Vec<AppAction> {
+        if self.room.is_none() {
+            return vec![AppAction::Render];
+        }
+        let mut actions =
+            app.dispatch(Intent::EditDraft { text: self.buffer.clone(), cursor: self.cursor });
+        if !actions.contains(&AppAction::Render) {
+            actions.push(AppAction::Render);
         }
+        actions
     }
 
-    /// Handle Enter key - parse command and call App API.
+    /// Handle Enter key - parse the input and dispatch it to the App.
     fn handle_enter(&mut self, app: &mut App) -> Vec<AppAction> {
         let text = std::mem::take(&mut self.buffer);
         self.cursor = 0;
@@ -122,67 +634,174 @@ impl InputState {
             return vec![];
         }
 
-        match commands::parse(&text) {
-            Command::Connect => {
-                app.set_status("Already connected");
+        let mut actions = self.save_draft(app);
+        match commands::parse_local(&text) {
+            Some(Ok(command)) => {
+                self.run_local(command, app);
+                return actions;
+            },
+            Some(Err(e)) => {
+                app.set_status(e.to_string());
+                return actions;
+            },
+            None => {},
+        }
+        actions.extend(match commands::parse_with(&text, &self.address_book) {
+            Ok(intent) => app.dispatch(intent),
+            Err(e) => {
+                app.set_status(e.to_string());
                 vec![AppAction::Render]
             },
-            Command::CreateRoom { room_id } => app.create_room(room_id),
-            Command::JoinRoom { room_id } => app.join_room(room_id),
-            Command::LeaveActiveRoom => {
-                if let Some(room_id) = app.active_room() {
-                    app.leave_room(room_id)
-                } else {
-                    app.set_status("No active room");
-                    vec![AppAction::Render]
+        });
+        actions
+    }
+
+    /// Run a command that only changes the terminal, reporting the result
+    /// in the status bar.
+    fn run_local(&mut self, command: LocalCommand, app: &mut App) {
+        match command {
+            LocalCommand::Theme { name: Some(name) } if self.themes.select(&name) => {
+                app.set_status(format!("Theme: {name}"));
+            },
+            LocalCommand::Theme { name: Some(name) } => {
+                app.set_status(format!("/theme: Unknown theme {name}"));
+            },
+            LocalCommand::Theme { name: None } => {
+                let names = self.themes.names().join(", ");
+                app.set_status(format!("Themes: {names} (using {})", self.themes.active().name()));
+            },
+            LocalCommand::Alias { alias: Some((name, room_id)) } => {
+                match room_id.or(app.active_room()) {
+                    Some(room_id) => {
+                        self.address_book.set_room(&name, room_id);
+                        self.save_address_book(app, format!("#{name} is room {room_id}"));
+                    },
+                    None => app.set_status("/alias: No active room"),
                 }
             },
-            Command::PublishKeyPackage => app.publish_key_package(),
-            Command::AddMember { user_id } => {
-                if let Some(room_id) = app.active_room() {
-                    app.add_member(room_id, user_id)
+            LocalCommand::Alias { alias: None } => {
+                let rooms: Vec<String> =
+                    self.address_book.rooms().map(|(name, id)| format!("#{name} {id}")).collect();
+                app.set_status(if rooms.is_empty() {
+                    "No room aliases".to_string()
                 } else {
-                    app.set_status("No active room");
-                    vec![AppAction::Render]
-                }
+                    format!("Rooms: {}", rooms.join(", "))
+                });
+            },
+            LocalCommand::Contact { contact: Some((name, user_id)) } => {
+                self.address_book.set_contact(&name, user_id);
+                self.save_address_book(app, format!("@{name} is user {user_id}"));
             },
-            Command::Quit => app.quit(),
-            Command::Message { content } => {
-                if let Some(room_id) = app.active_room() {
-                    app.send_message(room_id, content.into_bytes())
+            LocalCommand::Contact { contact: None } => {
+                let contacts: Vec<String> = self
+                    .address_book
+                    .contacts()
+                    .map(|(name, id)| format!("@{name} {id}"))
+                    .collect();
+                app.set_status(if contacts.is_empty() {
+                    "No contacts".to_string()
                 } else {
-                    app.set_status("No active room to send message");
-                    vec![AppAction::Render]
-                }
+                    format!("Contacts: {}", contacts.join(", "))
+                });
             },
-            Command::Unknown { input } => {
-                app.set_status(format!("Unknown command: {input}"));
-                vec![AppAction::Render]
+            LocalCommand::Threads { view: Some(view) } => {
+                self.threads = view;
+                app.set_status(format!("Threads: {}", view.name()));
             },
-            Command::InvalidArgs { command, error } => {
-                app.set_status(format!("/{command}: {error}"));
-                vec![AppAction::Render]
+            LocalCommand::Threads { view: None } => {
+                app.set_status(format!(
+                    "Threads: {} (flat, collapsed, expanded)",
+                    self.threads.name()
+                ));
+            },
+            LocalCommand::Open { log_index } => self.open(app, log_index),
+            LocalCommand::Help { command: None } => self.help = Some(0),
+            LocalCommand::Help { command: Some(name) } => {
+                let name = name.trim_start_matches('/');
+                match commands::COMMANDS.iter().position(|info| info.is_named(name)) {
+                    Some(index) => self.help = Some(index),
+                    None => app.set_status(format!("/help: Unknown command {name}")),
+                }
             },
         }
     }
 
-    /// Handle Tab key - cycle through rooms.
-    ///
-    /// Cycles to the next room in sorted order, wrapping around.
-    fn handle_tab(&self, app: &mut App) -> Vec<AppAction> {
-        let rooms = app.rooms();
-        if rooms.is_empty() {
+    /// Open the attachment shared by the message at `log_index` in the
+    /// active room, reporting the result.
+    fn open(&self, app: &mut App, log_index: u64) {
+        let attachment = app
+            .active_room_state()
+            .and_then(|room| room.message(log_index))
+            .and_then(|message| message.attachment.as_ref());
+        let Some(attachment) = attachment else {
+            app.set_status(format!("/open: No attachment at {log_index}"));
+            return;
+        };
+        let Some(dir) = viewer::attachment_dir() else {
+            app.set_status("/open: No cache directory to decrypt into");
+            return;
+        };
+        let status = match self.viewer.open(attachment, &dir) {
+            Ok(path) => format!("Opened {}", path.display()),
+            Err(e) => format!("/open: {e}"),
+        };
+        app.set_status(status);
+    }
+
+    /// Save the address book, reporting `done` or why saving failed.
+    fn save_address_book(&self, app: &mut App, done: String) {
+        match self.address_book.save() {
+            Ok(()) => app.set_status(done),
+            Err(e) => app.set_status(e.to_string()),
+        }
+    }
+
+    /// Handle a turn of the mouse wheel.
+    pub fn handle_wheel(&mut self, up: bool, app: &mut App) -> Vec<AppAction> {
+        Self::scroll(app, up, WHEEL_LINES)
+    }
+
+    /// Messages a page scrolls: a screenful, keeping one in view.
+    fn page(app: &App) -> usize {
+        ui::chat_rows(app.terminal_size().1).saturating_sub(1).max(1)
+    }
+
+    /// Scroll the active room's messages by `lines`. Once the oldest message
+    /// is in view, earlier ones are loaded.
+    fn scroll(app: &mut App, up: bool, lines: usize) -> Vec<AppAction> {
+        let Some(room) = app.active_room_state() else {
             return vec![];
+        };
+        let offset =
+            if up { room.scroll.saturating_add(lines) } else { room.scroll.saturating_sub(lines) };
+        let mut actions = app.dispatch(Intent::Scroll { offset });
+
+        let rows = ui::chat_rows(app.terminal_size().1);
+        let at_top = app
+            .active_room_state()
+            .is_some_and(|room| room.messages.len().saturating_sub(room.scroll) <= rows);
+        if up && at_top {
+            actions.extend(app.dispatch(Intent::LoadHistory));
         }
+        actions
+    }
 
-        let mut room_ids: Vec<_> = rooms.keys().copied().collect();
-        room_ids.sort_unstable();
+    /// Switch to the next or previous room in room list order, wrapping
+    /// around.
+    fn cycle_room(app: &mut App, forward: bool) -> Vec<AppAction> {
+        let room_ids = app.room_list();
+        if room_ids.is_empty() {
+            return vec![];
+        }
 
         let current_idx = app.active_room().and_then(|id| room_ids.iter().position(|&r| r == id));
         let len = room_ids.len();
         let next_idx = current_idx.map_or(0, |idx| {
-            let next = idx.saturating_add(1);
-            if next >= len { 0 } else { next }
+            if forward {
+                idx.saturating_add(1) % len
+            } else {
+                idx.checked_sub(1).unwrap_or(len - 1)
+            }
         });
 
         if let Some(&next_room) = room_ids.get(next_idx) {
@@ -259,6 +878,261 @@ mod tests {
         assert_eq!(input.cursor(), 3);
     }
 
+    #[test]
+    fn emacs_chords_edit_the_line() {
+        use crate::Profile;
+
+        let mut input = InputState::new().with_keymap(KeyMap::new(Profile::Emacs));
+        let mut app = App::new("localhost:4433".into());
+        for c in "hello big world".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+
+        input.handle_key(KeyInput::Ctrl('w'), &mut app);
+        assert_eq!(input.buffer(), "hello big ");
+
+        input.handle_key(KeyInput::Alt('b'), &mut app);
+        assert_eq!(input.cursor(), 6);
+        input.handle_key(KeyInput::Ctrl('k'), &mut app);
+        assert_eq!(input.buffer(), "hello ");
+
+        input.handle_key(KeyInput::Ctrl('a'), &mut app);
+        input.handle_key(KeyInput::Alt('f'), &mut app);
+        assert_eq!(input.cursor(), 6);
+        input.handle_key(KeyInput::Ctrl('u'), &mut app);
+        assert!(input.buffer().is_empty());
+    }
+
+    #[test]
+    fn vi_normal_mode_runs_bindings_instead_of_typing() {
+        use crate::Profile;
+
+        let mut input = InputState::new().with_keymap(KeyMap::new(Profile::Vi));
+        let mut app = App::new("localhost:4433".into());
+        for c in "abc".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+
+        input.handle_key(KeyInput::Esc, &mut app);
+        assert_eq!(input.mode(), Mode::Normal);
+        input.handle_key(KeyInput::Char('0'), &mut app);
+        input.handle_key(KeyInput::Char('x'), &mut app);
+        assert_eq!(input.buffer(), "bc");
+
+        assert_eq!(input.handle_key(KeyInput::Char('z'), &mut app), vec![]);
+        assert_eq!(input.buffer(), "bc");
+
+        input.handle_key(KeyInput::Char('a'), &mut app);
+        input.handle_key(KeyInput::Char('!'), &mut app);
+        assert_eq!(input.mode(), Mode::Insert);
+        assert_eq!(input.buffer(), "b!c");
+    }
+
+    #[test]
+    fn room_list_focus_selects_rooms() {
+        use lockframe_app::AppEvent;
+
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+        app.handle(AppEvent::RoomJoined { room_id: 1 });
+        app.handle(AppEvent::RoomJoined { room_id: 2 });
+
+        input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        assert_eq!(input.focus(), Pane::Rooms);
+
+        input.handle_key(KeyInput::Up, &mut app);
+        assert_eq!(app.active_room(), Some(2));
+        input.handle_key(KeyInput::Char('x'), &mut app);
+        assert!(input.buffer().is_empty());
+
+        input.handle_key(KeyInput::Enter, &mut app);
+        assert_eq!(input.focus(), Pane::Input);
+        input.handle_key(KeyInput::BackTab, &mut app);
+        assert_eq!(app.active_room(), Some(1));
+    }
+
+    #[test]
+    fn focus_cycles_through_panes_and_resizes_sidebars() {
+        use lockframe_app::AppEvent;
+
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+
+        input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        assert_eq!(input.focus(), Pane::Messages);
+        input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        assert_eq!(input.focus(), Pane::Input, "no member list without a room");
+
+        app.handle(AppEvent::RoomJoined { room_id: 1 });
+        for _ in 0..3 {
+            input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        }
+        assert_eq!(input.focus(), Pane::Members);
+
+        let width = input.layout().width(Pane::Members);
+        input.handle_key(KeyInput::Left, &mut app);
+        assert!(input.layout().width(Pane::Members) > width);
+        assert!(input.buffer().is_empty());
+    }
+
+    #[test]
+    fn theme_command_switches_themes() {
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+
+        for c in "/theme light".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        assert_eq!(input.handle_key(KeyInput::Enter, &mut app), vec![AppAction::Render]);
+        assert_eq!(input.themes().active().name(), "light");
+
+        for c in "/theme neon".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        input.handle_key(KeyInput::Enter, &mut app);
+        assert_eq!(input.themes().active().name(), "light");
+        assert_eq!(app.status_message(), Some("/theme: Unknown theme neon"));
+    }
+
+    #[test]
+    fn help_browser_selects_a_command_to_type() {
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+
+        for c in "/help join".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        input.handle_key(KeyInput::Enter, &mut app);
+        let join = commands::COMMANDS.iter().position(|info| info.name == "join");
+        assert_eq!(input.help(), join);
+
+        input.handle_key(KeyInput::Down, &mut app);
+        input.handle_key(KeyInput::Char('x'), &mut app);
+        assert!(input.buffer().is_empty());
+
+        input.handle_key(KeyInput::Enter, &mut app);
+        assert_eq!(input.help(), None);
+        assert_eq!(input.buffer(), "/leave ");
+
+        for _ in 0.."/leave ".len() {
+            input.handle_key(KeyInput::Backspace, &mut app);
+        }
+        for c in "/help".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        input.handle_key(KeyInput::Enter, &mut app);
+        assert_eq!(input.help(), Some(0));
+        input.handle_key(KeyInput::Esc, &mut app);
+        assert_eq!(input.help(), None);
+        assert!(input.buffer().is_empty());
+    }
+
+    #[test]
+    fn aliases_name_rooms_for_later_commands() {
+        use lockframe_app::AppEvent;
+
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+        app.handle(AppEvent::RoomJoined { room_id: 100 });
+
+        for c in "/alias work".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        input.handle_key(KeyInput::Enter, &mut app);
+        assert_eq!(input.address_book().room("work"), Some(100));
+        assert_eq!(app.status_message(), Some("#work is room 100"));
+
+        for c in "/contact alice".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        input.handle_key(KeyInput::Enter, &mut app);
+        assert_eq!(app.status_message(), Some("/contact: Usage: /contact [name <user_id>]"));
+    }
+
+    #[test]
+    fn paste_inserts_text_without_running_keys() {
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+
+        input.handle_key(KeyInput::Char('>'), &mut app);
+        let actions = input.handle_paste("line one\r\n/quit\x1b[A\tend", &mut app);
+
+        assert!(!actions.contains(&AppAction::Quit));
+        assert_eq!(input.buffer(), ">line one\n/quit[A end");
+        assert_eq!(input.cursor(), input.buffer().len());
+    }
+
+    #[test]
+    fn selected_message_is_copied() {
+        use lockframe_app::AppEvent;
+
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+        app.handle(AppEvent::RoomJoined { room_id: 100 });
+        for (sender_id, content) in [(7, "first"), (8, "second")] {
+            let content = content.as_bytes().to_vec();
+            app.handle(AppEvent::MessageReceived {
+                room_id: 100,
+                sender_id,
+                log_index: None,
+                content,
+                reply_to: None,
+                timestamp: 0,
+            });
+        }
+
+        input.handle_key(KeyInput::Alt('c'), &mut app);
+        assert_eq!(input.take_clipboard().as_deref(), Some("second"));
+
+        input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        assert_eq!(input.selected(&app), Some(0));
+        input.handle_key(KeyInput::Up, &mut app);
+        input.handle_key(KeyInput::Up, &mut app);
+        assert_eq!(input.selected(&app), Some(1), "selection stops at the oldest message");
+
+        input.handle_key(KeyInput::Alt('u'), &mut app);
+        assert_eq!(input.take_clipboard().as_deref(), Some("7"));
+        input.handle_key(KeyInput::Alt('r'), &mut app);
+        assert_eq!(input.take_clipboard().as_deref(), Some("100"));
+        assert_eq!(app.status_message(), Some("Copied room ID 100"));
+    }
+
+    #[test]
+    fn member_list_prompts_removal_and_copies_safety_codes() {
+        use lockframe_app::{AppEvent, CredentialKind};
+
+        let book = AddressBook::parse("contact alice = 42").unwrap();
+        let mut input = InputState::new().with_address_book(book);
+        let mut app = App::new("localhost:4433".into());
+        app.handle(AppEvent::RoomJoined { room_id: 100 });
+        let member =
+            |user_id| Member { user_id, credential: CredentialKind::Basic, signature_key: None };
+        let members = vec![member(7), Member { signature_key: Some([0; 32]), ..member(42) }];
+        app.handle(AppEvent::MembersChanged { room_id: 100, members });
+
+        for _ in 0..3 {
+            input.handle_key(KeyInput::Ctrl('o'), &mut app);
+        }
+        assert_eq!(input.selected_member(&app), Some(7));
+        input.handle_key(KeyInput::Alt('s'), &mut app);
+        assert_eq!(input.take_clipboard(), None);
+        assert_eq!(app.status_message(), Some("No safety code for user 7 yet"));
+
+        input.handle_key(KeyInput::Down, &mut app);
+        input.handle_key(KeyInput::Down, &mut app);
+        assert_eq!(input.selected_member(&app), Some(42));
+        input.handle_key(KeyInput::Alt('s'), &mut app);
+        assert_eq!(input.take_clipboard().as_deref(), Some("00000 00000 00000 00000 00000 00000"));
+
+        input.handle_key(KeyInput::Delete, &mut app);
+        assert_eq!(input.focus(), Pane::Input);
+        assert_eq!(input.buffer(), "/remove @alice");
+        let actions = input.handle_key(KeyInput::Enter, &mut app);
+        assert!(actions.contains(&AppAction::RemoveMember { room_id: 100, user_id: 42 }));
+    }
+
     #[test]
     fn tab_cycles_rooms() {
         use lockframe_app::AppEvent;
@@ -281,4 +1155,77 @@ mod tests {
         input.handle_key(KeyInput::Tab, &mut app);
         assert_eq!(app.active_room(), Some(1));
     }
+
+    #[test]
+    fn each_room_keeps_its_draft() {
+        use lockframe_app::AppEvent;
+
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+        app.handle(AppEvent::RoomJoined { room_id: 1 });
+        app.handle(AppEvent::RoomJoined { room_id: 2 });
+
+        input.handle_key(KeyInput::Char('h'), &mut app);
+        input.handle_key(KeyInput::Char('i'), &mut app);
+        input.handle_key(KeyInput::Tab, &mut app);
+        input.sync(&app);
+        assert!(input.buffer().is_empty());
+
+        input.handle_key(KeyInput::Tab, &mut app);
+        input.sync(&app);
+        assert_eq!(input.buffer(), "hi");
+        assert_eq!(input.cursor(), 2);
+        assert_eq!(app.rooms()[&1].draft.text, "hi");
+    }
+
+    #[test]
+    fn scrolling_to_the_top_loads_history() {
+        use lockframe_app::AppEvent;
+
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+        app.handle(AppEvent::RoomJoined { room_id: 1 });
+        for log_index in 40..80 {
+            let content = b"hi".to_vec();
+            let log_index = Some(log_index);
+            app.handle(AppEvent::MessageReceived {
+                room_id: 1,
+                sender_id: 7,
+                log_index,
+                content,
+                reply_to: None,
+                timestamp: 0,
+            });
+        }
+
+        let actions = input.handle_key(KeyInput::PageUp, &mut app);
+        assert!(!actions.iter().any(|a| matches!(a, AppAction::LoadHistory { .. })));
+        assert_eq!(app.rooms()[&1].scroll, InputState::page(&app));
+
+        let actions = input.handle_key(KeyInput::PageUp, &mut app);
+        assert!(actions.contains(&AppAction::LoadHistory { room_id: 1, until_log_index: 40 }));
+
+        input.handle_key(KeyInput::PageDown, &mut app);
+        input.handle_wheel(false, &mut app);
+        assert_eq!(app.rooms()[&1].scroll, InputState::page(&app) - WHEEL_LINES);
+    }
+
+    #[test]
+    fn esc_closes_directory_before_quitting() {
+        let mut input = InputState::new();
+        let mut app = App::new("localhost:4433".into());
+
+        for c in "/rooms lobby".chars() {
+            input.handle_key(KeyInput::Char(c), &mut app);
+        }
+        let actions = input.handle_key(KeyInput::Enter, &mut app);
+        assert!(
+            actions.contains(&AppAction::SearchDirectory { query: "lobby".into(), after: None })
+        );
+        assert!(app.directory().is_some());
+
+        assert_eq!(input.handle_key(KeyInput::Esc, &mut app), vec![AppAction::Render]);
+        assert!(app.directory().is_none());
+        assert_eq!(input.handle_key(KeyInput::Esc, &mut app), vec![AppAction::Quit]);
+    }
 }
@@ -0,0 +1,450 @@
+//! Keybindings for the TUI.
+//!
+//! A [`KeyMap`] turns keys into the [`KeyAction`]s [`crate::InputState`]
+//! performs. It starts from a built-in [`Profile`] and can be adjusted by a
+//! config file of `key = action` lines:
+//!
+//! ```text
+//! # Start from the Emacs profile
+//! profile = emacs
+//! ctrl-j = next-room
+//! ctrl-o = none
+//! # Bindings for the Vi profile's normal mode
+//! normal.g = line-start
+//! ```
+//!
+//! Keys are named as typed (`a`, `$`), as `ctrl-<char>` or `alt-<char>`, or
+//! by name (`enter`, `esc`, `tab`, `backtab`, `backspace`, `delete`, `left`,
+//! `right`, `up`, `down`, `pageup`, `pagedown`, `home`, `end`). Binding a key
+//! to `none` unbinds it.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::KeyInput;
+
+/// Something a key does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Move the cursor one character left.
+    Left,
+    /// Move the cursor one character right.
+    Right,
+    /// Move the cursor to the start of the word before it.
+    WordLeft,
+    /// Move the cursor to the start of the next word.
+    WordRight,
+    /// Move the cursor to the start of the line.
+    LineStart,
+    /// Move the cursor to the end of the line.
+    LineEnd,
+    /// Delete the character before the cursor.
+    DeleteBack,
+    /// Delete the character under the cursor.
+    DeleteForward,
+    /// Delete the word before the cursor.
+    DeleteWord,
+    /// Delete from the start of the line to the cursor.
+    DeleteToStart,
+    /// Delete from the cursor to the end of the line.
+    DeleteToEnd,
+    /// Send the line as a message or command.
+    Submit,
+    /// Dismiss the newest notice, close the directory, or quit.
+    Cancel,
+    /// Switch to the next room.
+    NextRoom,
+    /// Switch to the previous room.
+    PrevRoom,
+    /// Scroll the room up a message, or select the previous room from the
+    /// room list.
+    ScrollUp,
+    /// Scroll the room down a message, or select the next room from the
+    /// room list.
+    ScrollDown,
+    /// Scroll the room up a page.
+    PageUp,
+    /// Scroll the room down a page.
+    PageDown,
+    /// Move focus to the next pane.
+    FocusNext,
+    /// Copy the selected message, or the newest in view.
+    CopyMessage,
+    /// Copy the user ID of the selected member or message sender.
+    CopySender,
+    /// Copy the active room's ID.
+    CopyRoom,
+    /// Copy the safety code of the selected member or message sender.
+    CopySafetyCode,
+    /// Start typing a command to add a member.
+    AddMember,
+    /// Start typing a command to remove the selected member.
+    RemoveMember,
+    /// Start typing at the cursor.
+    InsertMode,
+    /// Start typing after the cursor.
+    Append,
+    /// Stop typing; keys run normal mode bindings.
+    NormalMode,
+}
+
+/// Names of actions in config files.
+const ACTION_NAMES: &[(&str, KeyAction)] = &[
+    ("left", KeyAction::Left),
+    ("right", KeyAction::Right),
+    ("word-left", KeyAction::WordLeft),
+    ("word-right", KeyAction::WordRight),
+    ("line-start", KeyAction::LineStart),
+    ("line-end", KeyAction::LineEnd),
+    ("delete-back", KeyAction::DeleteBack),
+    ("delete-forward", KeyAction::DeleteForward),
+    ("delete-word", KeyAction::DeleteWord),
+    ("delete-to-start", KeyAction::DeleteToStart),
+    ("delete-to-end", KeyAction::DeleteToEnd),
+    ("submit", KeyAction::Submit),
+    ("cancel", KeyAction::Cancel),
+    ("next-room", KeyAction::NextRoom),
+    ("prev-room", KeyAction::PrevRoom),
+    ("scroll-up", KeyAction::ScrollUp),
+    ("scroll-down", KeyAction::ScrollDown),
+    ("page-up", KeyAction::PageUp),
+    ("page-down", KeyAction::PageDown),
+    ("focus-next", KeyAction::FocusNext),
+    ("copy-message", KeyAction::CopyMessage),
+    ("copy-sender", KeyAction::CopySender),
+    ("copy-room", KeyAction::CopyRoom),
+    ("copy-safety-code", KeyAction::CopySafetyCode),
+    ("add-member", KeyAction::AddMember),
+    ("remove-member", KeyAction::RemoveMember),
+    ("insert-mode", KeyAction::InsertMode),
+    ("append", KeyAction::Append),
+    ("normal-mode", KeyAction::NormalMode),
+];
+
+/// Names of keys in config files, besides single characters and
+/// `ctrl-`/`alt-` chords.
+const KEY_NAMES: &[(&str, KeyInput)] = &[
+    ("enter", KeyInput::Enter),
+    ("esc", KeyInput::Esc),
+    ("tab", KeyInput::Tab),
+    ("backtab", KeyInput::BackTab),
+    ("backspace", KeyInput::Backspace),
+    ("delete", KeyInput::Delete),
+    ("left", KeyInput::Left),
+    ("right", KeyInput::Right),
+    ("up", KeyInput::Up),
+    ("down", KeyInput::Down),
+    ("pageup", KeyInput::PageUp),
+    ("pagedown", KeyInput::PageDown),
+    ("home", KeyInput::Home),
+    ("end", KeyInput::End),
+];
+
+/// Built-in set of bindings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// Arrow keys, Tab to switch rooms, Esc to quit.
+    #[default]
+    Default,
+    /// Readline-style control and meta chords on top of the defaults.
+    Emacs,
+    /// Modal editing: Esc enters normal mode, `i` returns to typing.
+    Vi,
+}
+
+/// Whether keys type text or run normal mode bindings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Unbound characters are typed into the input line.
+    #[default]
+    Insert,
+    /// Keys only run bindings. Only profiles with normal mode bindings
+    /// enter it.
+    Normal,
+}
+
+/// A keymap config file that couldn't be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum KeyMapError {
+    /// The file couldn't be read.
+    #[error("failed to read keymap: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A line isn't a valid binding.
+    #[error("keymap line {line}: {error}")]
+    Invalid {
+        /// Line number, starting at 1.
+        line: usize,
+        /// What is wrong with it.
+        error: String,
+    },
+}
+
+/// Bindings from keys to actions, for each [`Mode`].
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    insert: HashMap<KeyInput, KeyAction>,
+    normal: HashMap<KeyInput, KeyAction>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::new(Profile::Default)
+    }
+}
+
+impl KeyMap {
+    /// The bindings of a built-in profile.
+    pub fn new(profile: Profile) -> Self {
+        let mut insert: HashMap<_, _> = DEFAULT_BINDINGS.iter().copied().collect();
+        let mut normal = HashMap::new();
+        match profile {
+            Profile::Default => {},
+            Profile::Emacs => insert.extend(EMACS_BINDINGS.iter().copied()),
+            Profile::Vi => {
+                insert.extend(VI_INSERT_BINDINGS.iter().copied());
+                normal.extend(VI_NORMAL_BINDINGS.iter().copied());
+            },
+        }
+        Self { insert, normal }
+    }
+
+    /// Load a config file, starting from `profile` unless the file names
+    /// another.
+    pub fn load(path: &Path, profile: Profile) -> Result<Self, KeyMapError> {
+        Self::parse(&std::fs::read_to_string(path)?, profile)
+    }
+
+    /// Parse a config file, starting from `profile` unless the file names
+    /// another. A `profile` line must come before any binding.
+    pub fn parse(config: &str, profile: Profile) -> Result<Self, KeyMapError> {
+        let mut keymap = Self::new(profile);
+        let mut bound = false;
+        for (index, line) in config.lines().enumerate() {
+            let invalid = |error: String| KeyMapError::Invalid { line: index + 1, error };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) =
+                line.split_once('=').ok_or_else(|| invalid("expected `key = action`".into()))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "profile" {
+                if bound {
+                    return Err(invalid("profile must come before any binding".into()));
+                }
+                let profile = <Profile as clap::ValueEnum>::from_str(value, true)
+                    .map_err(|_| invalid(format!("unknown profile: {value}")))?;
+                keymap = Self::new(profile);
+                continue;
+            }
+
+            let (mode, key) =
+                key.strip_prefix("normal.").map_or((Mode::Insert, key), |key| (Mode::Normal, key));
+            let key = parse_key(key).ok_or_else(|| invalid(format!("unknown key: {key}")))?;
+            let action = match value {
+                "none" => None,
+                name => Some(
+                    parse_action(name).ok_or_else(|| invalid(format!("unknown action: {name}")))?,
+                ),
+            };
+            keymap.bind(mode, key, action);
+            bound = true;
+        }
+        Ok(keymap)
+    }
+
+    /// Bind `key` to `action` in `mode`, or unbind it if `action` is `None`.
+    pub fn bind(&mut self, mode: Mode, key: KeyInput, action: Option<KeyAction>) {
+        let bindings = self.bindings_mut(mode);
+        match action {
+            Some(action) => bindings.insert(key, action),
+            None => bindings.remove(&key),
+        };
+    }
+
+    /// What `key` does in `mode`, if it is bound.
+    pub fn action(&self, mode: Mode, key: KeyInput) -> Option<KeyAction> {
+        match mode {
+            Mode::Insert => self.insert.get(&key).copied(),
+            Mode::Normal => self.normal.get(&key).copied(),
+        }
+    }
+
+    /// Whether there is a normal mode to enter.
+    pub fn has_normal_mode(&self) -> bool {
+        !self.normal.is_empty()
+    }
+
+    fn bindings_mut(&mut self, mode: Mode) -> &mut HashMap<KeyInput, KeyAction> {
+        match mode {
+            Mode::Insert => &mut self.insert,
+            Mode::Normal => &mut self.normal,
+        }
+    }
+}
+
+fn parse_action(name: &str) -> Option<KeyAction> {
+    ACTION_NAMES.iter().find(|(n, _)| *n == name).map(|&(_, action)| action)
+}
+
+fn parse_key(name: &str) -> Option<KeyInput> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+    if let Some(c) = single(name) {
+        return Some(KeyInput::Char(c));
+    }
+    if let Some(c) = name.strip_prefix("ctrl-").and_then(single) {
+        return Some(KeyInput::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(c) = name.strip_prefix("alt-").and_then(single) {
+        return Some(KeyInput::Alt(c));
+    }
+    KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, key)| key)
+}
+
+/// Bindings every profile starts from.
+const DEFAULT_BINDINGS: &[(KeyInput, KeyAction)] = &[
+    (KeyInput::Enter, KeyAction::Submit),
+    (KeyInput::Esc, KeyAction::Cancel),
+    (KeyInput::Backspace, KeyAction::DeleteBack),
+    (KeyInput::Delete, KeyAction::DeleteForward),
+    (KeyInput::Left, KeyAction::Left),
+    (KeyInput::Right, KeyAction::Right),
+    (KeyInput::Home, KeyAction::LineStart),
+    (KeyInput::End, KeyAction::LineEnd),
+    (KeyInput::Tab, KeyAction::NextRoom),
+    (KeyInput::BackTab, KeyAction::PrevRoom),
+    (KeyInput::Up, KeyAction::ScrollUp),
+    (KeyInput::Down, KeyAction::ScrollDown),
+    (KeyInput::PageUp, KeyAction::PageUp),
+    (KeyInput::PageDown, KeyAction::PageDown),
+    (KeyInput::Ctrl('o'), KeyAction::FocusNext),
+    (KeyInput::Alt('c'), KeyAction::CopyMessage),
+    (KeyInput::Alt('u'), KeyAction::CopySender),
+    (KeyInput::Alt('r'), KeyAction::CopyRoom),
+    (KeyInput::Alt('s'), KeyAction::CopySafetyCode),
+    (KeyInput::Alt('a'), KeyAction::AddMember),
+    (KeyInput::Alt('x'), KeyAction::RemoveMember),
+];
+
+const EMACS_BINDINGS: &[(KeyInput, KeyAction)] = &[
+    (KeyInput::Ctrl('a'), KeyAction::LineStart),
+    (KeyInput::Ctrl('e'), KeyAction::LineEnd),
+    (KeyInput::Ctrl('b'), KeyAction::Left),
+    (KeyInput::Ctrl('f'), KeyAction::Right),
+    (KeyInput::Alt('b'), KeyAction::WordLeft),
+    (KeyInput::Alt('f'), KeyAction::WordRight),
+    (KeyInput::Ctrl('h'), KeyAction::DeleteBack),
+    (KeyInput::Ctrl('d'), KeyAction::DeleteForward),
+    (KeyInput::Ctrl('w'), KeyAction::DeleteWord),
+    (KeyInput::Ctrl('u'), KeyAction::DeleteToStart),
+    (KeyInput::Ctrl('k'), KeyAction::DeleteToEnd),
+    (KeyInput::Ctrl('g'), KeyAction::Cancel),
+    (KeyInput::Ctrl('p'), KeyAction::ScrollUp),
+    (KeyInput::Ctrl('n'), KeyAction::ScrollDown),
+    (KeyInput::Alt('v'), KeyAction::PageUp),
+    (KeyInput::Ctrl('v'), KeyAction::PageDown),
+    (KeyInput::Alt('p'), KeyAction::PrevRoom),
+    (KeyInput::Alt('n'), KeyAction::NextRoom),
+];
+
+const VI_INSERT_BINDINGS: &[(KeyInput, KeyAction)] = &[
+    (KeyInput::Esc, KeyAction::NormalMode),
+    (KeyInput::Ctrl('w'), KeyAction::DeleteWord),
+    (KeyInput::Ctrl('u'), KeyAction::DeleteToStart),
+];
+
+const VI_NORMAL_BINDINGS: &[(KeyInput, KeyAction)] = &[
+    (KeyInput::Char('i'), KeyAction::InsertMode),
+    (KeyInput::Char('a'), KeyAction::Append),
+    (KeyInput::Char('h'), KeyAction::Left),
+    (KeyInput::Char('l'), KeyAction::Right),
+    (KeyInput::Left, KeyAction::Left),
+    (KeyInput::Right, KeyAction::Right),
+    (KeyInput::Backspace, KeyAction::Left),
+    (KeyInput::Char('b'), KeyAction::WordLeft),
+    (KeyInput::Char('w'), KeyAction::WordRight),
+    (KeyInput::Char('0'), KeyAction::LineStart),
+    (KeyInput::Char('$'), KeyAction::LineEnd),
+    (KeyInput::Home, KeyAction::LineStart),
+    (KeyInput::End, KeyAction::LineEnd),
+    (KeyInput::Char('x'), KeyAction::DeleteForward),
+    (KeyInput::Char('X'), KeyAction::DeleteBack),
+    (KeyInput::Char('D'), KeyAction::DeleteToEnd),
+    (KeyInput::Char('k'), KeyAction::ScrollUp),
+    (KeyInput::Char('j'), KeyAction::ScrollDown),
+    (KeyInput::Up, KeyAction::ScrollUp),
+    (KeyInput::Down, KeyAction::ScrollDown),
+    (KeyInput::Ctrl('u'), KeyAction::PageUp),
+    (KeyInput::Ctrl('d'), KeyAction::PageDown),
+    (KeyInput::PageUp, KeyAction::PageUp),
+    (KeyInput::PageDown, KeyAction::PageDown),
+    (KeyInput::Char('K'), KeyAction::PrevRoom),
+    (KeyInput::Char('J'), KeyAction::NextRoom),
+    (KeyInput::Tab, KeyAction::NextRoom),
+    (KeyInput::BackTab, KeyAction::PrevRoom),
+    (KeyInput::Ctrl('w'), KeyAction::FocusNext),
+    (KeyInput::Enter, KeyAction::Submit),
+    (KeyInput::Char('q'), KeyAction::Cancel),
+    (KeyInput::Char('y'), KeyAction::CopyMessage),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_layer_on_the_defaults() {
+        let default = KeyMap::new(Profile::Default);
+        assert_eq!(default.action(Mode::Insert, KeyInput::Tab), Some(KeyAction::NextRoom));
+        assert_eq!(default.action(Mode::Insert, KeyInput::Ctrl('a')), None);
+        assert!(!default.has_normal_mode());
+
+        let emacs = KeyMap::new(Profile::Emacs);
+        assert_eq!(emacs.action(Mode::Insert, KeyInput::Ctrl('a')), Some(KeyAction::LineStart));
+        assert_eq!(emacs.action(Mode::Insert, KeyInput::Tab), Some(KeyAction::NextRoom));
+
+        let vi = KeyMap::new(Profile::Vi);
+        assert_eq!(vi.action(Mode::Insert, KeyInput::Esc), Some(KeyAction::NormalMode));
+        assert_eq!(vi.action(Mode::Insert, KeyInput::Char('j')), None);
+        assert_eq!(vi.action(Mode::Normal, KeyInput::Char('j')), Some(KeyAction::ScrollDown));
+        assert!(vi.has_normal_mode());
+    }
+
+    #[test]
+    fn config_adjusts_a_profile() {
+        let config = "
+            # comments and blank lines are skipped
+            profile = emacs
+
+            ctrl-j = next-room
+            ctrl-a = none
+            normal.g = line-start
+            PageUp = prev-room
+        ";
+        let keymap = KeyMap::parse(config, Profile::Default).unwrap();
+
+        assert_eq!(keymap.action(Mode::Insert, KeyInput::Ctrl('j')), Some(KeyAction::NextRoom));
+        assert_eq!(keymap.action(Mode::Insert, KeyInput::Ctrl('a')), None);
+        assert_eq!(keymap.action(Mode::Insert, KeyInput::Ctrl('e')), Some(KeyAction::LineEnd));
+        assert_eq!(keymap.action(Mode::Normal, KeyInput::Char('g')), Some(KeyAction::LineStart));
+        assert_eq!(keymap.action(Mode::Insert, KeyInput::PageUp), Some(KeyAction::PrevRoom));
+    }
+
+    #[test]
+    fn invalid_config_names_the_line() {
+        let error = |config: &str| match KeyMap::parse(config, Profile::Default) {
+            Err(KeyMapError::Invalid { line, error }) => (line, error),
+            other => panic!("expected an invalid line, got {other:?}"),
+        };
+
+        assert_eq!(error("ctrl-j = jump").1, "unknown action: jump");
+        assert_eq!(error("\nhyper-j = submit").0, 2);
+        assert_eq!(error("submit").1, "expected `key = action`");
+        assert_eq!(error("profile = nano").1, "unknown profile: nano");
+        assert_eq!(error("x = submit\nprofile = vi").0, 2);
+    }
+}
@@ -3,12 +3,29 @@
 //! A thin shell over [`lockframe_app::Driver`] that provides terminal-specific
 //! I/O. All orchestration logic lives in the generic [`lockframe_app::Runtime`]
 
+pub mod cli;
+pub mod clipboard;
 pub mod commands;
+pub mod config;
+pub mod connections;
+pub mod contacts;
+pub mod crash;
 pub mod input;
+pub mod keymap;
+pub mod notifier;
 pub mod terminal;
 pub mod ui;
+pub mod viewer;
 
-pub use commands::Command;
-pub use input::{InputState, KeyInput};
+pub use cli::{CliDriver, CliError, CliTask, Task};
+pub use commands::{LocalCommand, ParseError};
+pub use config::{Config, ConfigError};
+pub use connections::Connections;
+pub use contacts::{AddressBook, AddressBookError};
+pub use crash::CrashDump;
+pub use input::{InputState, KeyInput, Pane};
+pub use keymap::{KeyAction, KeyMap, KeyMapError, Mode, Profile};
 pub use lockframe_app::{App, AppAction, AppEvent, Bridge, Driver, Runtime};
+pub use notifier::TerminalNotifier;
 pub use terminal::{TerminalDriver, TerminalError};
+pub use viewer::{Viewer, ViewerError};
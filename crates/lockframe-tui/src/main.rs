@@ -1,37 +1,279 @@
 //! Lockframe TUI entry point.
 
-use clap::Parser;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
 use lockframe_app::Runtime;
 use lockframe_core::env::Environment;
 use lockframe_server::SystemEnv;
-use lockframe_tui::TerminalDriver;
+use lockframe_tui::{
+    AddressBook, CliDriver, CliTask, CrashDump, KeyMap, Profile, Task, TerminalDriver,
+    TerminalNotifier, Viewer,
+    config::{self, Config},
+    ui::{self, Theme, Themes, TimeFormat, Timestamps},
+};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+/// Server to connect to when neither a flag nor the config file names one.
+const DEFAULT_SERVER: &str = "localhost:4433";
+
+/// How long `send` and `export` may take before giving up.
+const TASK_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Lockframe terminal UI client
 #[derive(Parser, Debug)]
 #[command(name = "lockframe-tui")]
 #[command(about = "Terminal UI for the Lockframe messaging protocol")]
 #[command(version)]
 struct Args {
-    /// Server address to connect to
-    #[arg(short, long, default_value = "localhost:4433")]
+    /// Run one task and exit instead of starting the terminal UI
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Config file to read settings from before these flags. Defaults to
+    /// `lockframe/config.toml` in the user's config directory.
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// Server address to connect to [default: localhost:4433]
+    #[arg(short, long)]
+    server: Option<String>,
+
+    /// User ID to connect as. Random if omitted.
+    #[arg(long)]
+    user_id: Option<u64>,
+
+    /// Auth token to present to the server, issued for the user ID
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Extra account as `NAME=USER_ID@SERVER`, connecting without a token.
+    /// Repeatable; switch between accounts with `/account <number>`, the
+    /// first extra account being 1.
+    #[arg(long = "account", value_parser = parse_account)]
+    accounts: Vec<ExtraAccount>,
+
+    /// How to surface mentions, invites and room errors [default: bell]
+    #[arg(long, value_enum)]
+    notify: Option<TerminalNotifier>,
+
+    /// Built-in keybindings to start from [default: default]
+    #[arg(long, value_enum)]
+    keys: Option<Profile>,
+
+    /// File of `key = action` lines adjusting the keybindings
+    #[arg(long)]
+    keymap: Option<std::path::PathBuf>,
+
+    /// Theme to start with; switch at runtime with `/theme <name>`
+    /// [default: default]
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// File of `role = color` lines defining an extra theme. Repeatable,
+    /// adding to the config file's.
+    #[arg(long = "theme-file")]
+    theme_files: Vec<std::path::PathBuf>,
+
+    /// How much of each message's time to show [default: short]
+    #[arg(long, value_enum)]
+    timestamps: Option<TimeFormat>,
+
+    /// Offset from UTC to show times and dates in, like `+02:00`
+    /// [default: UTC]
+    #[arg(long, value_parser = ui::parse_utc_offset)]
+    utc_offset: Option<i32>,
+
+    /// File that `/alias` and `/contact` save names to. Defaults to
+    /// `lockframe/address-book` in the user's config directory.
+    #[arg(long)]
+    address_book: Option<std::path::PathBuf>,
+
+    /// Program to open attachments with, like `feh --scale-down`
+    /// [default: xdg-open]
+    #[arg(long, value_parser = parse_viewer)]
+    viewer: Option<Viewer>,
+
+    /// Write a snapshot of the App's state, with message content redacted,
+    /// to this file if the TUI crashes
+    #[arg(long)]
+    crash_dump: Option<std::path::PathBuf>,
+
+    /// Write the App's event log to this file on exit, for bug reports
+    #[cfg(feature = "devtools")]
+    #[arg(long)]
+    event_log: Option<std::path::PathBuf>,
+}
+
+/// Tasks run without the terminal UI, for scripts.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send a message and exit once the server has sequenced it
+    Send {
+        /// Room ID or alias
+        #[arg(long)]
+        room: String,
+        /// Message text
+        text: String,
+        /// Print the sent message as a JSON line
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a room's history and exit
+    Export {
+        /// Room ID or alias
+        #[arg(long)]
+        room: String,
+        /// First log index to print
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Print JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print new messages as they arrive until interrupted
+    Listen {
+        /// Room ID or alias to listen in. Repeatable; every room if omitted.
+        #[arg(long = "room")]
+        rooms: Vec<String>,
+        /// Print JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Command {
+    /// The task to run and whether it prints JSON, with rooms looked up in
+    /// `address_book`.
+    fn into_task(self, address_book: &AddressBook) -> Result<(Task, bool), String> {
+        let room = |arg: &str| address_book.room(arg).ok_or(format!("unknown room: {arg}"));
+        Ok(match self {
+            Self::Send { room: arg, text, json } => {
+                (Task::Send { room_id: room(&arg)?, text }, json)
+            },
+            Self::Export { room: arg, since, json } => {
+                (Task::Export { room_id: room(&arg)?, since }, json)
+            },
+            Self::Listen { rooms, json } => {
+                let room_ids = rooms.iter().map(|arg| room(arg)).collect::<Result<_, _>>()?;
+                (Task::Listen { room_ids }, json)
+            },
+        })
+    }
+}
+
+/// An extra account from the command line.
+#[derive(Debug, Clone)]
+struct ExtraAccount {
+    name: String,
+    user_id: u64,
     server: String,
 }
 
+fn parse_account(arg: &str) -> Result<ExtraAccount, String> {
+    let (name, rest) = arg.split_once('=').ok_or("expected NAME=USER_ID@SERVER")?;
+    let (user_id, server) = rest.split_once('@').ok_or("expected NAME=USER_ID@SERVER")?;
+    let user_id = user_id.parse().map_err(|_| format!("invalid user ID: {user_id}"))?;
+    Ok(ExtraAccount { name: name.to_string(), user_id, server: server.to_string() })
+}
+
+fn parse_viewer(arg: &str) -> Result<Viewer, String> {
+    Viewer::new(arg).ok_or_else(|| "expected a command".to_string())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
             "lockframe_tui=debug,tower_http=debug,axum::rejection=trace".into()
         }))
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
     let args = Args::parse();
+    let config = match (&args.config, Config::default_path()) {
+        (Some(path), _) => Config::load(path, true)?,
+        (None, Some(path)) => Config::load(&path, false)?,
+        (None, None) => Config::default(),
+    };
+
     let env = SystemEnv::new();
-    let sender_id = Environment::random_u64(&env);
-    let driver = TerminalDriver::new(args.server.clone())?;
-    let runtime = Runtime::new(driver, env, sender_id, args.server);
+    let user_id = args.user_id.or(config.user_id);
+    if args.token.is_some() && user_id.is_none() {
+        return Err("--token needs a user ID, from --user-id or the config file".into());
+    }
+    let sender_id = user_id.unwrap_or_else(|| Environment::random_u64(&env));
+    let server = args.server.or(config.server).unwrap_or_else(|| DEFAULT_SERVER.to_string());
+    let address_book = match args
+        .address_book
+        .or(config.address_book)
+        .or_else(|| config::config_dir().map(|dir| dir.join("address-book")))
+    {
+        Some(path) => AddressBook::load(&path)?,
+        None => AddressBook::new(),
+    };
+
+    if let Some(command) = args.command {
+        let (task, json) = command.into_task(&address_book)?;
+        let listen = matches!(task, Task::Listen { .. });
+        let mut driver = CliDriver::new(CliTask::new(task, json));
+        if !listen {
+            driver = driver.with_timeout(TASK_TIMEOUT);
+        }
+        let mut runtime = Runtime::new(driver, env, sender_id, server);
+        if let Some(token) = args.token {
+            runtime = runtime.with_auth_token(token);
+        }
+        runtime.run().await?;
+        return Ok(());
+    }
+
+    let keys = args.keys.or(config.keys).unwrap_or_default();
+    let keymap = match args.keymap.or(config.keymap) {
+        Some(path) => KeyMap::load(&path, keys)?,
+        None => KeyMap::new(keys),
+    };
+    let truecolor = std::env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit");
+    let mut themes = Themes::new().with_basic_colors(!truecolor);
+    for path in config.theme_files.iter().chain(&args.theme_files) {
+        themes.add(Theme::load(path)?);
+    }
+    let theme = args.theme.or(config.theme).unwrap_or_else(|| "default".to_string());
+    if !themes.select(&theme) {
+        return Err(format!("unknown theme: {theme}").into());
+    }
+    let timestamps = Timestamps::new(
+        args.timestamps.or(config.timestamps).unwrap_or_default(),
+        args.utc_offset.or(config.utc_offset).unwrap_or(0),
+    );
+
+    let mut driver = TerminalDriver::new()?
+        .with_notifier(args.notify.or(config.notify).unwrap_or_default())
+        .with_keymap(keymap)
+        .with_themes(themes)
+        .with_timestamps(timestamps)
+        .with_address_book(address_book)
+        .with_viewer(args.viewer.or(config.viewer).unwrap_or_default());
+    if let Some(interval) = config.tick_interval {
+        driver = driver.with_tick_interval(interval);
+    }
+    if let Some(path) = args.crash_dump {
+        driver = driver.with_crash_dump(CrashDump::install(path));
+    }
+    let mut runtime = Runtime::new(driver, env, sender_id, server);
+    if let Some(token) = args.token {
+        runtime = runtime.with_auth_token(token);
+    }
+    for account in args.accounts {
+        runtime.add_account(account.name, SystemEnv::new(), account.user_id, account.server, None);
+    }
 
-    Ok(runtime.run().await?)
+    let app = runtime.run().await?;
+    #[cfg(feature = "devtools")]
+    if let Some(path) = args.event_log {
+        std::fs::write(path, serde_json::to_vec_pretty(app.event_log())?)?;
+    }
+    #[cfg(not(feature = "devtools"))]
+    let _ = app;
+    Ok(())
 }
@@ -0,0 +1,71 @@
+//! Terminal notifications.
+//!
+//! The TUI surfaces notifications through the terminal itself: the bell,
+//! which most terminals turn into an urgency hint, or an OSC 777 escape
+//! sequence, which terminals such as foot, `WezTerm` and urxvt show as a
+//! desktop notification.
+
+use std::io::{Write, stdout};
+
+use lockframe_app::{Notification, Notifier};
+
+/// How the terminal surfaces notifications.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TerminalNotifier {
+    /// Don't.
+    Off,
+    /// Ring the bell for mentions, invites and room errors.
+    #[default]
+    Bell,
+    /// Raise a desktop notification for every notification.
+    Desktop,
+}
+
+impl Notifier for TerminalNotifier {
+    fn notify(&mut self, notification: &Notification) {
+        let sequence = match self {
+            Self::Bell if notification.is_urgent() => "\x07".to_string(),
+            Self::Off | Self::Bell => return,
+            Self::Desktop => {
+                let (title, body) = describe(notification);
+                format!("\x1b]777;notify;{};{}\x07", sanitize(&title), sanitize(&body))
+            },
+        };
+        let mut out = stdout();
+        let _ = out.write_all(sequence.as_bytes()).and_then(|()| out.flush());
+    }
+}
+
+/// Title and body of a desktop notification.
+fn describe(notification: &Notification) -> (String, String) {
+    let room = format!("#{:04x}", notification.room_id() as u16);
+    match notification {
+        Notification::Message { sender_id, preview, mention, .. } => {
+            let title = if *mention { format!("Mentioned in {room}") } else { room };
+            (title, format!("<{:04x}> {preview}", *sender_id as u16))
+        },
+        Notification::Invite { .. } => ("Lockframe".to_string(), format!("Added to room {room}")),
+        Notification::RoomError { message, .. } => (format!("Error in {room}"), message.clone()),
+    }
+}
+
+/// Drop characters that would end the escape sequence or a field of it.
+fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control() && *c != ';').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_text_cannot_break_the_escape_sequence() {
+        let notification = Notification::RoomError {
+            room_id: 0x1234,
+            message: "closed;\x07\x1b]777;notify;spoofed".into(),
+        };
+        let (title, body) = describe(&notification);
+        assert_eq!(title, "Error in #1234");
+        assert_eq!(sanitize(&body), "closed]777notifyspoofed");
+    }
+}
@@ -1,26 +1,40 @@
 //! Terminal driver for the TUI.
 //!
 //! Implements the [`Driver`] trait for terminal I/O using crossterm for
-//! keyboard events and ratatui for rendering. Network uses quinn for QUIC.
+//! keyboard events and ratatui for rendering. Network goes through
+//! [`Connections`], one QUIC connection per account.
+//!
+//! Bracketed paste is on, so pasted text arrives whole instead of as keys.
+//! Copied text goes to the system clipboard through [`clipboard`].
 
 use std::{
     io::{self, Stdout, stdout},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
     ExecutableCommand,
-    event::{Event, EventStream, KeyCode, KeyEventKind},
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEventKind,
+    },
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use futures::StreamExt;
-use lockframe_app::{App, AppAction, AppEvent, Driver};
-use lockframe_client::transport::{self, ConnectedClient, TransportError};
+use lockframe_app::{AccountId, App, AppAction, AppEvent, Driver};
+use lockframe_client::transport::TransportError;
 use lockframe_proto::Frame;
 use ratatui::{Terminal, backend::CrosstermBackend};
 use thiserror::Error;
 
-use crate::{InputState, KeyInput, ui};
+use crate::{
+    AddressBook, Connections, CrashDump, InputState, KeyInput, KeyMap, TerminalNotifier, Viewer,
+    clipboard, ui,
+    ui::{Themes, Timestamps},
+};
+
+/// Longest wait for input before the App gets a tick, unless configured.
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Terminal driver errors.
 #[derive(Debug, Error)]
@@ -45,16 +59,25 @@ pub enum TerminalError {
 pub struct TerminalDriver {
     terminal: Terminal<CrosstermBackend<Stdout>>,
     event_stream: EventStream,
-    connection: Option<ConnectedClient>,
-    server_addr: String,
+    connections: Connections,
     input_state: InputState,
+    /// When the runtime's next timer is due
+    wakeup: Option<Duration>,
+    /// Longest wait for input before the App gets a tick
+    tick_interval: Duration,
+    notifier: TerminalNotifier,
+    /// Snapshot written out on panic, refreshed every render
+    crash_dump: Option<CrashDump>,
 }
 
 impl TerminalDriver {
     /// Create a new terminal driver.
-    pub fn new(server_addr: String) -> Result<Self, TerminalError> {
+    pub fn new() -> Result<Self, TerminalError> {
         enable_raw_mode()?;
-        stdout().execute(EnterAlternateScreen)?;
+        stdout()
+            .execute(EnterAlternateScreen)?
+            .execute(EnableMouseCapture)?
+            .execute(EnableBracketedPaste)?;
 
         let backend = CrosstermBackend::new(stdout());
         let terminal = Terminal::new(backend)?;
@@ -63,25 +86,95 @@ impl TerminalDriver {
         Ok(Self {
             terminal,
             event_stream,
-            connection: None,
-            server_addr,
+            connections: Connections::new(),
             input_state: InputState::new(),
+            wakeup: None,
+            tick_interval: TICK_INTERVAL,
+            notifier: TerminalNotifier::default(),
+            crash_dump: None,
         })
     }
 
-    /// Convert crossterm `KeyCode` to `KeyInput`.
-    fn convert_key(code: KeyCode) -> Option<KeyInput> {
-        match code {
+    /// Surface notifications with `notifier` instead of the bell.
+    #[must_use]
+    pub fn with_notifier(mut self, notifier: TerminalNotifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Tick the App at least every `interval` instead of every 100ms.
+    #[must_use]
+    pub fn with_tick_interval(mut self, interval: Duration) -> Self {
+        self.tick_interval = interval;
+        self
+    }
+
+    /// Handle keys with `keymap` instead of the default bindings.
+    #[must_use]
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.input_state = std::mem::take(&mut self.input_state).with_keymap(keymap);
+        self
+    }
+
+    /// Offer `themes` for `/theme` and start with the active one.
+    #[must_use]
+    pub fn with_themes(mut self, themes: Themes) -> Self {
+        self.input_state = std::mem::take(&mut self.input_state).with_themes(themes);
+        self
+    }
+
+    /// Show message times as `timestamps` says.
+    #[must_use]
+    pub fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.input_state = std::mem::take(&mut self.input_state).with_timestamps(timestamps);
+        self
+    }
+
+    /// Snapshot the App into `crash_dump` on every render, so a panic
+    /// leaves its latest state behind. Costs a copy of the App's state per
+    /// render.
+    #[must_use]
+    pub fn with_crash_dump(mut self, crash_dump: CrashDump) -> Self {
+        self.crash_dump = Some(crash_dump);
+        self
+    }
+
+    /// Resolve room and user names in commands with `address_book`.
+    #[must_use]
+    pub fn with_address_book(mut self, address_book: AddressBook) -> Self {
+        self.input_state = std::mem::take(&mut self.input_state).with_address_book(address_book);
+        self
+    }
+
+    /// Open attachments with `viewer` instead of the system's default.
+    #[must_use]
+    pub fn with_viewer(mut self, viewer: Viewer) -> Self {
+        self.input_state = std::mem::take(&mut self.input_state).with_viewer(viewer);
+        self
+    }
+
+    /// Convert a crossterm `KeyEvent` to `KeyInput`.
+    fn convert_key(event: KeyEvent) -> Option<KeyInput> {
+        match event.code {
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(KeyInput::Ctrl(c.to_ascii_lowercase()))
+            },
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::ALT) => {
+                Some(KeyInput::Alt(c))
+            },
             KeyCode::Char(c) => Some(KeyInput::Char(c)),
             KeyCode::Enter => Some(KeyInput::Enter),
             KeyCode::Backspace => Some(KeyInput::Backspace),
             KeyCode::Delete => Some(KeyInput::Delete),
             KeyCode::Tab => Some(KeyInput::Tab),
+            KeyCode::BackTab => Some(KeyInput::BackTab),
             KeyCode::Esc => Some(KeyInput::Esc),
             KeyCode::Left => Some(KeyInput::Left),
             KeyCode::Right => Some(KeyInput::Right),
             KeyCode::Up => Some(KeyInput::Up),
             KeyCode::Down => Some(KeyInput::Down),
+            KeyCode::PageUp => Some(KeyInput::PageUp),
+            KeyCode::PageDown => Some(KeyInput::PageDown),
             KeyCode::Home => Some(KeyInput::Home),
             KeyCode::End => Some(KeyInput::End),
             _ => None,
@@ -92,9 +185,10 @@ impl TerminalDriver {
 impl Driver for TerminalDriver {
     type Error = TerminalError;
     type Instant = Instant;
+    type Notifier = TerminalNotifier;
 
     async fn poll_event(&mut self, app: &mut App) -> Result<Vec<AppAction>, Self::Error> {
-        let timeout = tokio::time::Duration::from_millis(100);
+        let timeout = self.wakeup.map_or(self.tick_interval, |after| after.min(self.tick_interval));
 
         tokio::select! {
             biased;
@@ -103,11 +197,23 @@ impl Driver for TerminalDriver {
             maybe_event = self.event_stream.next() => {
                 match maybe_event {
                     Some(Ok(Event::Key(key_event))) if key_event.kind == KeyEventKind::Press => {
-                        match Self::convert_key(key_event.code) {
-                            Some(key_input) => Ok(self.input_state.handle_key(key_input, app)),
+                        match Self::convert_key(key_event) {
+                            Some(key_input) => {
+                                let actions = self.input_state.handle_key(key_input, app);
+                                if let Some(text) = self.input_state.take_clipboard() {
+                                    clipboard::copy(&text);
+                                }
+                                Ok(actions)
+                            },
                             None => Ok(vec![]),
                         }
                     },
+                    Some(Ok(Event::Mouse(mouse_event))) => match mouse_event.kind {
+                        MouseEventKind::ScrollUp => Ok(self.input_state.handle_wheel(true, app)),
+                        MouseEventKind::ScrollDown => Ok(self.input_state.handle_wheel(false, app)),
+                        _ => Ok(vec![]),
+                    },
+                    Some(Ok(Event::Paste(text))) => Ok(self.input_state.handle_paste(&text, app)),
                     Some(Ok(Event::Resize(cols, rows))) => {
                         Ok(app.handle(AppEvent::Resize(cols, rows)))
                     },
@@ -123,25 +229,21 @@ impl Driver for TerminalDriver {
         }
     }
 
-    async fn send_frame(&mut self, frame: Frame) -> Result<(), Self::Error> {
-        if let Some(conn) = &self.connection {
-            conn.to_server.send(frame).await.map_err(|_| TerminalError::ChannelSend)?;
-        }
+    async fn send_frame(&mut self, account: AccountId, frame: Frame) -> Result<(), Self::Error> {
+        self.connections.send(account, frame).await;
         Ok(())
     }
 
-    async fn recv_frame(&mut self) -> Option<Frame> {
-        self.connection.as_mut().and_then(|conn| conn.from_server.try_recv().ok())
+    async fn recv_frame(&mut self) -> Option<(AccountId, Frame)> {
+        self.connections.recv()
     }
 
-    async fn connect(&mut self, _addr: &str) -> Result<(), Self::Error> {
-        let client = transport::connect(&self.server_addr).await?;
-        self.connection = Some(client);
-        Ok(())
+    async fn connect(&mut self, account: AccountId, addr: &str) -> Result<(), Self::Error> {
+        Ok(self.connections.connect(account, addr).await?)
     }
 
-    fn is_connected(&self) -> bool {
-        self.connection.is_some()
+    fn is_connected(&self, account: AccountId) -> bool {
+        self.connections.contains(account)
     }
 
     #[allow(clippy::disallowed_methods)]
@@ -150,16 +252,26 @@ impl Driver for TerminalDriver {
     }
 
     fn render(&mut self, app: &App) -> Result<(), Self::Error> {
+        if let Some(crash_dump) = &self.crash_dump {
+            crash_dump.record(app);
+        }
+        self.input_state.sync(app);
         self.terminal.draw(|frame| {
             ui::render(frame, app, &self.input_state);
         })?;
         Ok(())
     }
 
+    fn set_wakeup(&mut self, after: Option<Duration>) {
+        self.wakeup = after;
+    }
+
+    fn notifier(&mut self) -> &mut Self::Notifier {
+        &mut self.notifier
+    }
+
     fn stop(&mut self) {
-        if let Some(ref conn) = self.connection {
-            conn.stop();
-        }
+        self.connections.stop();
     }
 }
 
@@ -167,6 +279,8 @@ impl Drop for TerminalDriver {
     fn drop(&mut self) {
         self.stop();
         let _ = disable_raw_mode();
+        let _ = stdout().execute(DisableBracketedPaste);
+        let _ = stdout().execute(DisableMouseCapture);
         let _ = stdout().execute(LeaveAlternateScreen);
     }
 }
@@ -1,57 +1,552 @@
 //! Chat area
 //!
-//! Displays messages in the active room.
+//! Displays messages in the active room, scrolled to the room's position,
+//! below a line showing while earlier history loads. The border is
+//! highlighted while the message view has focus.
+//!
+//! Long messages wrap to the pane's width, indented under the sender, with
+//! their reactions counted on a row below. A separator marks where the day
+//! changes, `@` mentions are highlighted, and your own name stands out. The
+//! message selected for copying is shown reversed.
+//!
+//! Replies quote the start of the message they answer, unless the
+//! [`ThreadView`] groups them under it instead. Attachments show as a
+//! placeholder with the file's name, size and the start of its hash, and
+//! the `/open` command that shows the file itself.
 
-use lockframe_app::App;
+use std::collections::{BTreeSet, HashMap};
+
+use lockframe_app::{App, ConnectionState, Delivery, History, Message, RoomState};
+use lockframe_proto::payloads::app::{Attachment, ReplyTo};
 use ratatui::{
     Frame,
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem},
+    widgets::{Block, Borders, Paragraph},
 };
 
-const BORDER_SIZE: u16 = 2;
+use super::{Role, Theme, Timestamps};
+
+pub(super) const BORDER_SIZE: u16 = 2;
+
+/// Columns replies are indented by under the message they answer.
+const THREAD_INDENT: usize = 2;
+
+/// Characters of the answered message quoted above a reply.
+const QUOTE_CHARS: usize = 40;
+
+/// How replies are laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThreadView {
+    /// Every message in log order, replies quoting the message they answer.
+    #[default]
+    Flat,
+    /// Replies hidden under the message they answer, which counts them.
+    Collapsed,
+    /// Replies indented under the message they answer, in log order.
+    Expanded,
+}
+
+impl ThreadView {
+    /// Name used by `/threads`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::Collapsed => "collapsed",
+            Self::Expanded => "expanded",
+        }
+    }
+
+    /// View named `name`, as `/threads` takes it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(Self::Flat),
+            "collapse" | "collapsed" => Some(Self::Collapsed),
+            "expand" | "expanded" => Some(Self::Expanded),
+            _ => None,
+        }
+    }
+}
+
+/// How the chat area looks besides its colors.
+pub struct ChatView<'a> {
+    /// Colors.
+    pub theme: &'a Theme,
+    /// How message times are shown.
+    pub timestamps: &'a Timestamps,
+    /// Whether the message view has focus.
+    pub focused: bool,
+    /// Selected message, as messages below it.
+    pub selected: Option<usize>,
+    /// How replies are laid out.
+    pub threads: ThreadView,
+}
 
 /// Render the chat area.
-pub fn render(frame: &mut Frame, app: &App, area: Rect) {
+pub fn render(frame: &mut Frame, app: &App, view: &ChatView, area: Rect) {
+    let theme = view.theme;
     let title = if let Some(room_id) = app.active_room() {
         format!(" #{:04x} ", room_id as u16)
     } else {
         " No Room ".to_string()
     };
 
-    let block = Block::default().borders(Borders::ALL).title(title);
-
-    let items: Vec<ListItem> = if let Some(room) = app.active_room_state() {
-        room.messages
-            .iter()
-            .map(|msg| {
-                let sender = format!("<{:04x}>", msg.sender_id as u16);
-                let content = msg.content_str();
-
-                ListItem::new(Line::from(vec![
-                    Span::styled(
-                        sender,
-                        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
-                    ),
-                    Span::raw(" "),
-                    Span::raw(content.into_owned()),
-                ]))
-            })
-            .collect()
-    } else {
-        vec![ListItem::new(Line::from(Span::styled(
-            "Join a room to start chatting",
-            Style::default().fg(Color::DarkGray),
-        )))]
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(super::border_style(theme, view.focused));
+
+    let width = area.width.saturating_sub(BORDER_SIZE) as usize;
+    let height = area.height.saturating_sub(BORDER_SIZE) as usize;
+    let lines = match app.active_room_state() {
+        Some(room) => room_lines(room, own_id(app), view, width, height),
+        None => vec![Line::styled("Join a room to start chatting", theme.fg(Role::Muted))],
+    };
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn own_id(app: &App) -> Option<u64> {
+    match app.connection_state() {
+        ConnectionState::Connected { sender_id, .. } => Some(*sender_id),
+        ConnectionState::Disconnected | ConnectionState::Connecting => None,
+    }
+}
+
+/// A message as the view lays it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    /// Index into the room's messages.
+    index: usize,
+    /// Shown under the message it answers.
+    nested: bool,
+    /// Replies hidden under it.
+    hidden_replies: usize,
+}
+
+/// The messages to show, in order, as `threads` lays them out.
+fn entries(room: &RoomState, threads: ThreadView) -> Vec<Entry> {
+    let flat = |index| Entry { index, nested: false, hidden_replies: 0 };
+    if threads == ThreadView::Flat {
+        return (0..room.messages.len()).map(flat).collect();
+    }
+
+    let by_log_index: HashMap<u64, usize> = room
+        .messages
+        .iter()
+        .enumerate()
+        .filter_map(|(index, msg)| msg.log_index.map(|log_index| (log_index, index)))
+        .collect();
+    let held = |reply_to: Option<ReplyTo>| {
+        reply_to.is_some_and(|reply_to| by_log_index.contains_key(&reply_to.target_log_index))
+    };
+
+    let mut entries = Vec::new();
+    for (index, msg) in room.messages.iter().enumerate() {
+        // Replies to held messages are laid out with them
+        if held(msg.reply_to) {
+            continue;
+        }
+        let replies = descendants(room, msg, &by_log_index);
+        if threads == ThreadView::Collapsed {
+            entries.push(Entry { index, nested: false, hidden_replies: replies.len() });
+        } else {
+            entries.push(flat(index));
+            entries.extend(replies.into_iter().map(|index| Entry {
+                index,
+                nested: true,
+                hidden_replies: 0,
+            }));
+        }
+    }
+    entries
+}
+
+/// Indexes of the held replies to `msg`, and replies to those, in room order.
+fn descendants(
+    room: &RoomState,
+    msg: &Message,
+    by_log_index: &HashMap<u64, usize>,
+) -> BTreeSet<usize> {
+    let mut found = BTreeSet::new();
+    let mut pending: Vec<u64> = msg.log_index.into_iter().collect();
+    while let Some(parent) = pending.pop() {
+        for reply in room.replies(parent) {
+            if let Some(&index) = by_log_index.get(&reply)
+                && found.insert(index)
+            {
+                pending.push(reply);
+            }
+        }
+    }
+    found
+}
+
+/// The rows that fill `height`, ending with the newest message in view.
+fn room_lines(
+    room: &RoomState,
+    own_id: Option<u64>,
+    view: &ChatView,
+    width: usize,
+    height: usize,
+) -> Vec<Line<'static>> {
+    let entries = entries(room, view.threads);
+    let end = entries.len().saturating_sub(room.scroll);
+    let in_view = entries.get(..end).unwrap_or_default();
+    let selected = view.selected.and_then(|below| room.messages.len().checked_sub(below + 1));
+
+    // Built newest first, so only what fits is wrapped
+    let mut rows = Vec::new();
+    for (position, entry) in in_view.iter().enumerate().rev() {
+        if rows.len() >= height {
+            break;
+        }
+        let msg = &room.messages[entry.index];
+        let indent = if entry.nested { THREAD_INDENT } else { 0 };
+        let width = width.saturating_sub(indent);
+
+        let mut lines = Vec::new();
+        if let Some(reply_to) = msg.reply_to
+            && !entry.nested
+        {
+            lines.push(quote_line(room, reply_to, view.theme, width));
+        }
+        lines.extend(message_lines(msg, own_id, view, width));
+        if selected == Some(entry.index) {
+            let reversed = Style::default().add_modifier(Modifier::REVERSED);
+            lines = lines.into_iter().map(|line| line.patch_style(reversed)).collect();
+        }
+        if entry.hidden_replies > 0 {
+            let count = match entry.hidden_replies {
+                1 => "1 reply".to_string(),
+                n => format!("{n} replies"),
+            };
+            lines.push(Line::styled(format!("  └ {count}"), view.theme.fg(Role::Muted)));
+        }
+        for line in lines.into_iter().rev() {
+            if indent == 0 {
+                rows.push(line);
+            } else {
+                let mut spans = vec![Span::raw(" ".repeat(indent))];
+                spans.extend(line.spans);
+                rows.push(Line::from(spans).style(line.style));
+            }
+        }
+
+        let previous = position.checked_sub(1).map(|i| &room.messages[in_view[i].index]);
+        let day = view.timestamps.day(msg.timestamp);
+        if day.is_some() && previous.map(|prev| view.timestamps.day(prev.timestamp)) != Some(day) {
+            let date = day.map(Timestamps::date).unwrap_or_default();
+            rows.push(Line::styled(format!("── {date} ──"), view.theme.fg(Role::Muted)).centered());
+        }
+    }
+    if let History::Loading { fetched, total } = room.history
+        && rows.len() < height
+    {
+        rows.push(Line::styled(
+            format!("Loading earlier messages ({fetched}/{total})..."),
+            view.theme.fg(Role::Muted),
+        ));
+    }
+
+    rows.truncate(height);
+    rows.reverse();
+    rows
+}
+
+/// The start of the message a reply answers, noting if it has changed since
+/// the reply was written.
+fn quote_line(room: &RoomState, reply_to: ReplyTo, theme: &Theme, width: usize) -> Line<'static> {
+    let quote = match room.message(reply_to.target_log_index) {
+        Some(target) => {
+            let preview: String = target.content_str().chars().take(QUOTE_CHARS).collect();
+            let changed = if reply_to.matches(&target.content) { "" } else { " (changed)" };
+            format!("↪ <{:04x}> {preview}{changed}", target.sender_id as u16)
+        },
+        None => format!("↪ message {}", reply_to.target_log_index),
+    };
+    let quote: String = quote.lines().next().unwrap_or_default().chars().take(width).collect();
+    Line::styled(quote, theme.fg(Role::Muted))
+}
+
+/// One message as rows wrapped to `width`, continuation rows indented under
+/// the first.
+fn message_lines(
+    msg: &Message,
+    own_id: Option<u64>,
+    view: &ChatView,
+    width: usize,
+) -> Vec<Line<'static>> {
+    let theme = view.theme;
+    let own = msg.local_id.is_some() || own_id == Some(msg.sender_id);
+
+    let mut prefix = Vec::new();
+    if let Some(time) = view.timestamps.time(msg.timestamp) {
+        prefix.push(Span::styled(format!("{time} "), theme.fg(Role::Muted)));
+    }
+    let sender_role = if own { Role::Accent } else { Role::Sender };
+    prefix.push(Span::styled(
+        format!("<{:04x}>", msg.sender_id as u16),
+        theme.fg(sender_role).add_modifier(Modifier::BOLD),
+    ));
+    prefix.push(Span::raw(" "));
+    let indent: usize = prefix.iter().map(Span::width).sum();
+
+    let content = match &msg.attachment {
+        Some(attachment) => attachment_placeholder(attachment, msg.log_index).into(),
+        None => msg.content_str(),
     };
+    let (content, style) = match msg.delivery {
+        Delivery::Delivered if msg.mentions_me => (content.into_owned(), theme.fg(Role::Mention)),
+        Delivery::Delivered => (content.into_owned(), Style::default()),
+        Delivery::Pending | Delivery::Sent => (content.into_owned(), theme.fg(Role::Muted)),
+        Delivery::Failed => (format!("{content} (not delivered)"), theme.fg(Role::Error)),
+    };
+
+    let mut lines = Vec::new();
+    for (row, text) in wrap(&content, width.saturating_sub(indent).max(1)).into_iter().enumerate() {
+        let mut spans = if row == 0 {
+            std::mem::take(&mut prefix)
+        } else {
+            vec![Span::raw(" ".repeat(indent))]
+        };
+        spans.extend(highlight_mentions(&text, style, theme));
+        lines.push(Line::from(spans));
+    }
+
+    let reactions: Vec<String> =
+        msg.reaction_counts().map(|(content, count)| format!("{content} {count}")).collect();
+    if !reactions.is_empty() {
+        lines.push(Line::from(vec![
+            Span::raw(" ".repeat(indent)),
+            Span::styled(reactions.join("  "), theme.fg(Role::Muted)),
+        ]));
+    }
+    lines
+}
+
+/// Stand-in for an attachment's content, like
+/// `[cat.png] 1.5 KiB, abababab /open 7`.
+fn attachment_placeholder(attachment: &Attachment, log_index: Option<u64>) -> String {
+    // The name is the sender's; keep it to one line
+    let name: String = attachment.filename.chars().filter(|c| !c.is_control()).collect();
+    let hash = crate::viewer::short_hash(attachment);
+    let open = log_index.map(|log_index| format!(" /open {log_index}")).unwrap_or_default();
+    format!("[{name}] {}, {hash}{open}", format_size(attachment.size))
+}
+
+/// `bytes` in binary units to one decimal place, like `1.5 KiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut unit = 0;
+    let mut scale: u64 = 1024;
+    while unit + 1 < UNITS.len() && bytes / scale >= 1024 {
+        scale *= 1024;
+        unit += 1;
+    }
+    let tenths = u128::from(bytes) * 10 / u128::from(scale);
+    format!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit])
+}
+
+/// Split `text` at spaces, styling `@` mentions.
+fn highlight_mentions(text: &str, style: Style, theme: &Theme) -> Vec<Span<'static>> {
+    let mention = theme.fg(Role::Mention).add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    for (index, word) in text.split(' ').enumerate() {
+        if index > 0 {
+            spans.push(Span::styled(" ", style));
+        }
+        let word_style = if word.len() > 1 && word.starts_with('@') { mention } else { style };
+        spans.push(Span::styled(word.to_string(), word_style));
+    }
+    spans
+}
+
+/// Wrap `text` into rows at most `width` columns wide, breaking between
+/// words where possible and within words longer than a row.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    for line in text.split('\n') {
+        let mut row = String::new();
+        let mut row_width = 0;
+        for word in line.split(' ') {
+            let word_width = Span::raw(word).width();
+            if row_width > 0 && row_width + 1 + word_width > width {
+                rows.push(std::mem::take(&mut row));
+                row_width = 0;
+            } else if row_width > 0 {
+                row.push(' ');
+                row_width += 1;
+            }
+            for c in word.chars() {
+                let char_width = Span::raw(c.to_string()).width();
+                if row_width > 0 && row_width + char_width > width {
+                    rows.push(std::mem::take(&mut row));
+                    row_width = 0;
+                }
+                row.push(c);
+                row_width += char_width;
+            }
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::*;
+
+    #[test]
+    fn wraps_between_words_and_splits_long_ones() {
+        assert_eq!(wrap("the quick brown fox", 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap("abcdefghij klm", 4), vec!["abcd", "efgh", "ij", "klm"]);
+        assert_eq!(wrap("one\ntwo", 10), vec!["one", "two"]);
+        assert_eq!(wrap("", 10), vec![""]);
+    }
+
+    #[test]
+    fn long_messages_wrap_under_the_sender() {
+        let theme = Theme::default();
+        let timestamps = Timestamps::default();
+        let view = ChatView {
+            theme: &theme,
+            timestamps: &timestamps,
+            focused: false,
+            selected: None,
+            threads: ThreadView::Flat,
+        };
+        let msg = Message {
+            sender_id: 0xabcd,
+            log_index: Some(0),
+            content: b"hello there @alice how are you".to_vec(),
+            edited: false,
+            deleted: false,
+            mentions_me: false,
+            delivery: Delivery::Delivered,
+            local_id: None,
+            timestamp: 0,
+            reactions: BTreeMap::new(),
+            reply_to: None,
+            attachment: None,
+        };
+
+        let lines: Vec<String> =
+            message_lines(&msg, None, &view, 22).iter().map(ToString::to_string).collect();
+        assert_eq!(lines, vec!["<abcd> hello there", "       @alice how are", "       you"]);
+    }
+
+    #[test]
+    fn reactions_are_counted_under_the_message() {
+        let theme = Theme::default();
+        let timestamps = Timestamps::default();
+        let view = ChatView {
+            theme: &theme,
+            timestamps: &timestamps,
+            focused: false,
+            selected: None,
+            threads: ThreadView::Flat,
+        };
+        let msg = Message {
+            sender_id: 0xabcd,
+            log_index: Some(0),
+            content: b"lunch?".to_vec(),
+            edited: false,
+            deleted: false,
+            mentions_me: false,
+            delivery: Delivery::Delivered,
+            local_id: None,
+            timestamp: 0,
+            reactions: BTreeMap::from([
+                ("+1".to_string(), BTreeSet::from([1, 2])),
+                ("no".to_string(), BTreeSet::from([3])),
+            ]),
+            reply_to: None,
+            attachment: None,
+        };
+
+        let lines: Vec<String> =
+            message_lines(&msg, None, &view, 40).iter().map(ToString::to_string).collect();
+        assert_eq!(lines, vec!["<abcd> lunch?", "       +1 2  no 1"]);
+    }
+
+    #[test]
+    fn attachments_show_as_placeholders() {
+        let theme = Theme::default();
+        let timestamps = Timestamps::default();
+        let view = ChatView {
+            theme: &theme,
+            timestamps: &timestamps,
+            focused: false,
+            selected: None,
+            threads: ThreadView::Flat,
+        };
+        let mut room = RoomState::new(1);
+        room.add_message(0xabcd, Some(7), b"cat.png".to_vec(), None, false, 0);
+        room.set_attachment(7, Attachment {
+            filename: "cat\n.png".to_string(),
+            size: 1536,
+            hash: [0xab; 32],
+            key: [0; 32],
+            nonce: [0; 24],
+            ciphertext: vec![],
+        });
+
+        let lines: Vec<String> =
+            room_lines(&room, None, &view, 60, 5).iter().map(ToString::to_string).collect();
+        assert_eq!(lines, vec!["<abcd> [cat.png] 1.5 KiB, abababab /open 7"]);
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024 * 1024 * 1024), "5120.0 TiB");
+    }
 
-    let visible_height = area.height.saturating_sub(BORDER_SIZE) as usize;
-    let skip = items.len().saturating_sub(visible_height);
-    let visible_items: Vec<_> = items.into_iter().skip(skip).collect();
+    #[test]
+    fn thread_views_lay_out_replies() {
+        let theme = Theme::default();
+        let timestamps = Timestamps::default();
+        let mut room = RoomState::new(1);
+        room.add_message(1, Some(0), b"lunch?".to_vec(), None, false, 0);
+        room.add_message(2, Some(1), b"unrelated".to_vec(), None, false, 0);
+        let reply = Some(ReplyTo::new(0, b"lunch?"));
+        room.add_message(3, Some(2), b"yes".to_vec(), reply, false, 0);
+        let stale = Some(ReplyTo::new(0, b"dinner?"));
+        room.add_message(4, Some(3), b"no".to_vec(), stale, false, 0);
 
-    let list = List::new(visible_items).block(block);
+        let rows = |threads| {
+            let view = ChatView {
+                theme: &theme,
+                timestamps: &timestamps,
+                focused: false,
+                selected: None,
+                threads,
+            };
+            let rows = room_lines(&room, None, &view, 40, 20);
+            rows.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
 
-    frame.render_widget(list, area);
+        assert_eq!(rows(ThreadView::Flat), vec![
+            "<0001> lunch?",
+            "<0002> unrelated",
+            "↪ <0001> lunch?",
+            "<0003> yes",
+            "↪ <0001> lunch? (changed)",
+            "<0004> no",
+        ]);
+        assert_eq!(rows(ThreadView::Collapsed), vec![
+            "<0001> lunch?",
+            "  └ 2 replies",
+            "<0002> unrelated",
+        ]);
+        assert_eq!(rows(ThreadView::Expanded), vec![
+            "<0001> lunch?",
+            "  <0003> yes",
+            "  <0004> no",
+            "<0002> unrelated",
+        ]);
+    }
 }
@@ -0,0 +1,48 @@
+//! Room directory
+//!
+//! Replaces the chat area with room directory search results while a search
+//! is open.
+
+use lockframe_app::Directory;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem},
+};
+
+use super::{Role, Theme};
+
+/// Render the directory results.
+pub fn render(frame: &mut Frame, directory: &Directory, theme: &Theme, area: Rect) {
+    let title = if directory.query.is_empty() {
+        " Rooms ".to_string()
+    } else {
+        format!(" Rooms matching \"{}\" ", directory.query)
+    };
+
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    let mut items: Vec<ListItem> = directory
+        .rooms
+        .iter()
+        .map(|entry| {
+            ListItem::new(Line::from(vec![
+                Span::styled(entry.room_id.to_string(), theme.fg(Role::Accent)),
+                Span::raw("  "),
+                Span::styled(entry.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(format!(" ({} members)", entry.member_count), theme.fg(Role::Muted)),
+            ]))
+        })
+        .collect();
+
+    let hint = if directory.next.is_some() {
+        "/next for more, /join <room_id> to join, Esc to close"
+    } else {
+        "/join <room_id> to join, Esc to close"
+    };
+    items.push(ListItem::new(Line::from(Span::styled(hint, theme.fg(Role::Muted)))));
+
+    frame.render_widget(List::new(items).block(block), area);
+}
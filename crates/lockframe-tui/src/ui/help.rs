@@ -0,0 +1,76 @@
+//! Command help
+//!
+//! Lists every command over the screen while `/help` is open, with the
+//! selected command's aliases and examples below the list.
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use super::{Role, Theme};
+use crate::commands::{COMMANDS, CommandInfo};
+
+const WIDTH: u16 = 72;
+/// Rows for the selected command's details, including their top border.
+const DETAIL_HEIGHT: u16 = 4;
+/// Column summaries line up at.
+const USAGE_WIDTH: usize = 24;
+
+/// Render the help browser over `area` with command `selected` highlighted.
+pub fn render(frame: &mut Frame, selected: usize, theme: &Theme, area: Rect) {
+    let rows = u16::try_from(COMMANDS.len()).unwrap_or(u16::MAX);
+    let width = WIDTH.min(area.width);
+    let height = rows.saturating_add(DETAIL_HEIGHT + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Help ")
+        .title_bottom(Line::styled(" Enter to type, Esc to close ", theme.fg(Role::Muted)))
+        .border_style(theme.fg(Role::Accent));
+    let inner = block.inner(popup);
+    frame.render_widget(Clear, popup);
+    frame.render_widget(block, popup);
+
+    let [list_area, detail_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(DETAIL_HEIGHT)]).areas(inner);
+
+    let items: Vec<ListItem> = COMMANDS
+        .iter()
+        .map(|info| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:USAGE_WIDTH$}", info.usage()), theme.fg(Role::Accent)),
+                Span::styled(info.summary, theme.fg(Role::Text)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    if let Some(info) = COMMANDS.get(selected) {
+        let details = Paragraph::new(detail_lines(info, theme))
+            .block(Block::default().borders(Borders::TOP).title(format!(" /{} ", info.name)));
+        frame.render_widget(details, detail_area);
+    }
+}
+
+/// A command's aliases and examples.
+fn detail_lines(info: &CommandInfo, theme: &Theme) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    if !info.aliases.is_empty() {
+        let aliases: Vec<String> = info.aliases.iter().map(|alias| format!("/{alias}")).collect();
+        lines.push(Line::styled(format!("Also {}", aliases.join(", ")), theme.fg(Role::Muted)));
+    }
+    lines.extend(info.examples.iter().map(|example| Line::raw(format!("  {example}"))));
+    lines
+}
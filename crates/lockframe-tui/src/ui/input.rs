@@ -1,32 +1,55 @@
 //! Input line
 //!
-//! Displays the input buffer with cursor.
+//! Displays the input buffer with cursor, the message being replied to, who
+//! else in the room is typing, and whether keys are in normal mode. While a
+//! command is being typed, its usage replaces who is typing. Pasted newlines
+//! show as `↵`.
 
+use lockframe_app::{App, RoomState};
 use ratatui::{
     Frame,
     layout::Rect,
-    style::{Color, Style},
+    text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
 };
 
-use crate::InputState;
+use super::{Role, Theme};
+use crate::{InputState, Mode, Pane, commands};
 
 const PROMPT_WIDTH: u16 = 3; // "> "
 const INPUT_LINE_OFFSET_Y: u16 = 1; // inside top border
 const RIGHT_PADDING: u16 = 1; // inside right border
 
+/// Most typing members named before they are summed up.
+const MAX_TYPING_NAMES: usize = 3;
+
 /// Render the input line.
-pub fn render(frame: &mut Frame, input: &InputState, area: Rect) {
-    let block = Block::default().borders(Borders::ALL);
+pub fn render(frame: &mut Frame, app: &App, input: &InputState, theme: &Theme, area: Rect) {
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(super::border_style(theme, input.focus() == Pane::Input));
+    if let Some(log_index) = app.active_room_state().and_then(|room| room.draft.reply_to) {
+        block = block.title(format!(" Replying to #{log_index} "));
+    }
+    let hint =
+        command_hint(input.buffer()).or_else(|| app.active_room_state().and_then(typing_line));
+    if let Some(hint) = hint {
+        block = block.title_bottom(Line::styled(hint, theme.fg(Role::Muted)));
+    }
+    if input.mode() == Mode::Normal {
+        block = block.title_top(Line::styled(" NORMAL ", theme.fg(Role::Accent)).right_aligned());
+    }
 
-    let input_text = format!("> {}", input.buffer());
-    let paragraph =
-        Paragraph::new(input_text).style(Style::default().fg(Color::White)).block(block);
+    let input_text = format!("> {}", display(input.buffer()));
+    let paragraph = Paragraph::new(input_text).style(theme.fg(Role::Text)).block(block);
 
     frame.render_widget(paragraph, area);
 
     let available_width = area.width.saturating_sub(PROMPT_WIDTH + RIGHT_PADDING);
-    let cursor_offset = (input.cursor() as u16).min(available_width);
+    let before_cursor = input.buffer().get(..input.cursor()).unwrap_or_default();
+    let cursor_offset = u16::try_from(Span::raw(display(before_cursor)).width())
+        .unwrap_or(u16::MAX)
+        .min(available_width);
 
     let cursor_x = area.x.saturating_add(PROMPT_WIDTH).saturating_add(cursor_offset);
     let cursor_y = area.y.saturating_add(INPUT_LINE_OFFSET_Y);
@@ -35,3 +58,36 @@ pub fn render(frame: &mut Frame, input: &InputState, area: Rect) {
 
     frame.set_cursor_position((cursor_x, cursor_y));
 }
+
+/// The buffer as shown, with newlines marked.
+fn display(buffer: &str) -> String {
+    buffer.replace('\n', "↵")
+}
+
+/// Usage of the command being typed, or the commands it could become.
+fn command_hint(buffer: &str) -> Option<String> {
+    let typed = buffer.strip_prefix('/')?;
+    let (name, args) =
+        typed.split_once(' ').map_or((typed, None), |(name, args)| (name, Some(args)));
+    if let Some(info) = commands::lookup(name) {
+        return Some(format!(" {} - {} ", info.usage(), info.summary));
+    }
+    if args.is_some() {
+        return None;
+    }
+    let names: Vec<String> =
+        commands::completions(name).map(|info| format!("/{}", info.name)).collect();
+    (!names.is_empty()).then(|| format!(" {} ", names.join(" ")))
+}
+
+/// Who is typing in the room, if anyone.
+fn typing_line(room: &RoomState) -> Option<String> {
+    let names: Vec<String> =
+        room.typing.iter().map(|&sender_id| format!("<{:04x}>", sender_id as u16)).collect();
+    match names.len() {
+        0 => None,
+        1 => Some(format!(" {} is typing... ", names[0])),
+        n if n <= MAX_TYPING_NAMES => Some(format!(" {} are typing... ", names.join(", "))),
+        n => Some(format!(" {n} people are typing... ")),
+    }
+}
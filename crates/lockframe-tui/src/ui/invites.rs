@@ -0,0 +1,45 @@
+//! Invite prompt
+//!
+//! Asks about the newest pending invite at the bottom of the chat area
+//! until it is accepted or declined.
+
+use lockframe_app::App;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Modifier,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use super::{Role, Theme};
+
+const HEIGHT: u16 = 4;
+
+/// Render the prompt for the newest invite over the bottom of `area`.
+pub fn render(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let Some(invite) = app.invite(None) else {
+        return;
+    };
+
+    let others = app.invites().len() - 1;
+    let title = if others > 0 { format!(" Invite (+{others}) ") } else { " Invite ".to_string() };
+    let block =
+        Block::default().borders(Borders::ALL).title(title).border_style(theme.fg(Role::Unread));
+
+    let lines = vec![
+        Line::from(vec![
+            Span::raw(format!("User {} invited you to room ", invite.inviter)),
+            Span::styled(
+                invite.room_id.to_string(),
+                theme.fg(Role::Accent).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::styled("/accept to join, /decline to ignore", theme.fg(Role::Muted)),
+    ];
+
+    let height = HEIGHT.min(area.height);
+    let prompt = Rect { y: area.y + area.height - height, height, ..area };
+    frame.render_widget(Clear, prompt);
+    frame.render_widget(Paragraph::new(lines).block(block), prompt);
+}
@@ -0,0 +1,134 @@
+//! Pane layout
+//!
+//! Splits the terminal into the room list, message view, member list, input
+//! line and status bar. The sidebars can be resized at runtime; the member
+//! list is left out when the message view would get too narrow.
+
+use ratatui::layout::{Constraint, Layout, Rect};
+
+use crate::Pane;
+
+pub(super) const INPUT_HEIGHT: u16 = 3;
+pub(super) const STATUS_HEIGHT: u16 = 1;
+const MAIN_AREA_MIN_HEIGHT: u16 = 3;
+const CHAT_AREA_MIN_WIDTH: u16 = 20;
+
+const DEFAULT_ROOMS_WIDTH: u16 = 12;
+const DEFAULT_MEMBERS_WIDTH: u16 = 14;
+const MIN_SIDEBAR_WIDTH: u16 = 8;
+const MAX_SIDEBAR_WIDTH: u16 = 40;
+/// Columns one resize moves a sidebar's border.
+const RESIZE_STEP: u16 = 2;
+
+/// Widths of the sidebars either side of the message view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneLayout {
+    rooms_width: u16,
+    members_width: u16,
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self { rooms_width: DEFAULT_ROOMS_WIDTH, members_width: DEFAULT_MEMBERS_WIDTH }
+    }
+}
+
+/// Where each pane is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Areas {
+    /// Room list, on the left.
+    pub rooms: Rect,
+    /// Message view, or the directory while one is open.
+    pub messages: Rect,
+    /// Member list, on the right, if it fits.
+    pub members: Option<Rect>,
+    /// Input line.
+    pub input: Rect,
+    /// Status bar.
+    pub status: Rect,
+}
+
+impl PaneLayout {
+    /// Width of a sidebar, or `None` for the other panes.
+    pub fn width(&self, pane: Pane) -> Option<u16> {
+        match pane {
+            Pane::Rooms => Some(self.rooms_width),
+            Pane::Members => Some(self.members_width),
+            Pane::Input | Pane::Messages => None,
+        }
+    }
+
+    /// Move the border between a sidebar and the message view one step
+    /// right or left. Other panes can't be resized.
+    pub fn move_border(&mut self, pane: Pane, right: bool) {
+        let (width, grows) = match pane {
+            Pane::Rooms => (&mut self.rooms_width, right),
+            Pane::Members => (&mut self.members_width, !right),
+            Pane::Input | Pane::Messages => return,
+        };
+        let resized = if grows {
+            width.saturating_add(RESIZE_STEP)
+        } else {
+            width.saturating_sub(RESIZE_STEP)
+        };
+        *width = resized.clamp(MIN_SIDEBAR_WIDTH, MAX_SIDEBAR_WIDTH);
+    }
+
+    /// Split `area` into panes, with a member list if `members` and it fits.
+    pub fn split(&self, area: Rect, members: bool) -> Areas {
+        let [main, input, status] = Layout::vertical([
+            Constraint::Min(MAIN_AREA_MIN_HEIGHT),
+            Constraint::Length(INPUT_HEIGHT),
+            Constraint::Length(STATUS_HEIGHT),
+        ])
+        .areas(area);
+
+        let fits = main.width >= self.rooms_width + CHAT_AREA_MIN_WIDTH + self.members_width;
+        let members_width = if members && fits { self.members_width } else { 0 };
+        let [rooms, messages, members] = Layout::horizontal([
+            Constraint::Length(self.rooms_width),
+            Constraint::Min(CHAT_AREA_MIN_WIDTH),
+            Constraint::Length(members_width),
+        ])
+        .areas(main);
+
+        let members = (members_width > 0).then_some(members);
+        Areas { rooms, messages, members, input, status }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn member_list_is_left_out_when_narrow() {
+        let mut layout = PaneLayout::default();
+        let areas = layout.split(Rect::new(0, 0, 80, 24), true);
+        assert_eq!(areas.rooms.width, DEFAULT_ROOMS_WIDTH);
+        assert_eq!(areas.members.map(|area| area.width), Some(DEFAULT_MEMBERS_WIDTH));
+        assert_eq!(areas.input.height, INPUT_HEIGHT);
+
+        assert_eq!(layout.split(Rect::new(0, 0, 80, 24), false).members, None);
+
+        layout.move_border(Pane::Rooms, true);
+        layout.move_border(Pane::Members, true);
+        let areas = layout.split(Rect::new(0, 0, 80, 24), true);
+        assert_eq!(areas.rooms.width, DEFAULT_ROOMS_WIDTH + RESIZE_STEP);
+        assert_eq!(areas.members.map(|area| area.width), Some(DEFAULT_MEMBERS_WIDTH - RESIZE_STEP));
+
+        assert_eq!(layout.split(Rect::new(0, 0, 40, 24), true).members, None);
+    }
+
+    #[test]
+    fn sidebars_stay_within_limits() {
+        let mut layout = PaneLayout::default();
+        for _ in 0..50 {
+            layout.move_border(Pane::Rooms, false);
+            layout.move_border(Pane::Members, false);
+        }
+        assert_eq!(layout.width(Pane::Rooms), Some(MIN_SIDEBAR_WIDTH));
+        assert_eq!(layout.width(Pane::Members), Some(MAX_SIDEBAR_WIDTH));
+        assert_eq!(layout.width(Pane::Input), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Member sidebar
+//!
+//! Lists the active room's members by contact name or ID, marking you and
+//! who is typing. While the sidebar has focus, the selected member is shown
+//! reversed, with their credential and safety code below the list.
+
+use lockframe_app::{App, ConnectionState, Member};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+};
+
+use super::{Role, Theme};
+use crate::AddressBook;
+
+const TYPING_MARKER: &str = " ...";
+/// Rows for the selected member's details, including their top border.
+const DETAIL_HEIGHT: u16 = 5;
+
+/// How the member sidebar looks besides its colors.
+pub struct MembersView<'a> {
+    /// Colors.
+    pub theme: &'a Theme,
+    /// Names to show instead of IDs.
+    pub address_book: &'a AddressBook,
+    /// Whether the sidebar has focus.
+    pub focused: bool,
+    /// Selected member, while the sidebar has focus.
+    pub selected: Option<u64>,
+}
+
+/// Render the member sidebar.
+pub fn render(frame: &mut Frame, app: &App, view: &MembersView, area: Rect) {
+    let Some(room) = app.active_room_state() else {
+        return;
+    };
+    let theme = view.theme;
+    let own_id = match app.connection_state() {
+        ConnectionState::Connected { sender_id, .. } => Some(*sender_id),
+        ConnectionState::Disconnected | ConnectionState::Connecting => None,
+    };
+    let members = room.sorted_members();
+
+    let items: Vec<ListItem> = members
+        .iter()
+        .map(|&member| {
+            let style = if Some(member) == own_id {
+                theme.fg(Role::Accent).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let typing = if room.typing.contains(&member) { TYPING_MARKER } else { "" };
+            ListItem::new(Line::from(vec![
+                Span::styled(name(view.address_book, member), style),
+                Span::styled(typing, theme.fg(Role::Muted)),
+            ]))
+        })
+        .collect();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Members ({}) ", members.len()))
+        .border_style(super::border_style(theme, view.focused));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let selected = view.selected.and_then(|id| members.iter().position(|&m| m == id));
+    let detail_height = if selected.is_some() { DETAIL_HEIGHT } else { 0 };
+    let [list_area, detail_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(detail_height)]).areas(inner);
+
+    let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(selected);
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    if let Some(user_id) = view.selected {
+        let details = Paragraph::new(detail_lines(user_id, room.roster.get(&user_id), theme))
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::TOP));
+        frame.render_widget(details, detail_area);
+    }
+}
+
+/// `@name` for contacts, otherwise the user ID in hex.
+fn name(address_book: &AddressBook, user_id: u64) -> String {
+    address_book
+        .contact_name(user_id)
+        .map_or_else(|| format!("<{:04x}>", user_id as u16), |name| format!("@{name}"))
+}
+
+/// A member's ID, credential and safety code.
+fn detail_lines(user_id: u64, member: Option<&Member>, theme: &Theme) -> Vec<Line<'static>> {
+    let muted = theme.fg(Role::Muted);
+    let mut lines = vec![Line::raw(format!("ID {user_id}"))];
+    match member {
+        Some(member) => {
+            lines.push(Line::styled(format!("Credential: {}", member.credential), muted));
+            let code = member.safety_code().unwrap_or_else(|| "unknown".to_string());
+            lines.push(Line::styled(format!("Safety code: {code}"), muted));
+        },
+        None => lines.push(Line::styled("Waiting for the group's next epoch", muted)),
+    }
+    lines
+}
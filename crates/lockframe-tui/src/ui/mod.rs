@@ -3,55 +3,79 @@
 //! Rendering functions that convert App state into terminal output using
 //! ratatui widgets. All functions are pure (no I/O), taking state and
 //! returning widget trees.
+//!
+//! [`PaneLayout`] places the panes; the one with focus has its border
+//! highlighted. Colors come from the active [`Theme`]. The `/help` browser
+//! opens over everything.
 
 mod chat;
+mod directory;
+mod help;
 mod input;
+mod invites;
+mod layout;
+mod members;
+mod notices;
 mod rooms;
 mod status;
+mod theme;
+mod time;
 
+pub use chat::ThreadView;
+pub use layout::{Areas, PaneLayout};
 use lockframe_app::App;
-use ratatui::{
-    Frame,
-    layout::{Constraint, Direction, Layout},
-};
+use ratatui::{Frame, style::Style};
+pub use theme::{Role, Theme, ThemeError, Themes};
+pub use time::{TimeFormat, Timestamps, parse_utc_offset};
+
+use crate::{InputState, Pane};
 
-use crate::InputState;
+/// Message rows the chat area shows in a terminal `rows` tall.
+pub fn chat_rows(rows: u16) -> usize {
+    rows.saturating_sub(layout::INPUT_HEIGHT + layout::STATUS_HEIGHT + chat::BORDER_SIZE) as usize
+}
 
 /// Render the entire UI.
 ///
 /// Takes both App state (rooms, messages) and `InputState` (text buffer,
-/// cursor).
+/// cursor, focus and pane sizes).
 pub fn render(frame: &mut Frame, app: &App, input_state: &InputState) {
-    const MAIN_AREA_MIN_HEIGHT: u16 = 3;
-    const INPUT_HEIGHT: u16 = 3;
-    const STATUS_HEIGHT: u16 = 1;
-    const ROOM_SIDEBAR_WIDTH: u16 = 12;
-    const CHAT_AREA_MIN_WIDTH: u16 = 20;
-
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Min(MAIN_AREA_MIN_HEIGHT),
-            Constraint::Length(INPUT_HEIGHT),
-            Constraint::Length(STATUS_HEIGHT),
-        ])
-        .split(frame.area());
+    let theme = input_state.themes().active();
+    let focus = input_state.focus();
+    let areas = input_state.layout().split(frame.area(), app.active_room().is_some());
 
-    let [main_area, input_area, status_area] = chunks.as_ref() else {
-        return;
-    };
-
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Length(ROOM_SIDEBAR_WIDTH), Constraint::Min(CHAT_AREA_MIN_WIDTH)])
-        .split(*main_area);
-
-    let [rooms_area, chat_area] = chunks.as_ref() else {
-        return;
-    };
+    rooms::render(frame, app, theme, focus == Pane::Rooms, areas.rooms);
+    if let Some(listing) = app.directory() {
+        directory::render(frame, listing, theme, areas.messages);
+    } else {
+        let view = chat::ChatView {
+            theme,
+            timestamps: input_state.timestamps(),
+            focused: focus == Pane::Messages,
+            selected: input_state.selected(app),
+            threads: input_state.threads(),
+        };
+        chat::render(frame, app, &view, areas.messages);
+    }
+    if let Some(members_area) = areas.members {
+        let view = members::MembersView {
+            theme,
+            address_book: input_state.address_book(),
+            focused: focus == Pane::Members,
+            selected: input_state.selected_member(app),
+        };
+        members::render(frame, app, &view, members_area);
+    }
+    invites::render(frame, app, theme, areas.messages);
+    notices::render(frame, app, theme, areas.messages);
+    input::render(frame, app, input_state, theme, areas.input);
+    status::render(frame, app, input_state.address_book(), theme, areas.status);
+    if let Some(selected) = input_state.help() {
+        help::render(frame, selected, theme, frame.area());
+    }
+}
 
-    rooms::render(frame, app, *rooms_area);
-    chat::render(frame, app, *chat_area);
-    input::render(frame, input_state, *input_area);
-    status::render(frame, app, *status_area);
+/// Border style of a pane.
+fn border_style(theme: &Theme, focused: bool) -> Style {
+    if focused { theme.fg(Role::Accent) } else { Style::default() }
 }
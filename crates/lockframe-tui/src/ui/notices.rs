@@ -0,0 +1,84 @@
+//! Notice toasts
+//!
+//! Stacks the newest notices over the top right of the chat area until they
+//! are dismissed with Esc or `/dismiss`. Notices from the server's operator
+//! are labelled with what they are about.
+
+use lockframe_app::{App, Severity};
+use lockframe_proto::payloads::session::NoticeKind;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use super::{Role, Theme};
+
+/// Notices shown at once. Older ones wait behind them.
+const SHOWN: usize = 3;
+const WIDTH: u16 = 40;
+
+/// Render the newest notices over `area`.
+pub fn render(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let notices = app.notices();
+    let Some(newest) = notices.back() else {
+        return;
+    };
+
+    let lines: Vec<Line> = notices
+        .iter()
+        .rev()
+        .take(SHOWN)
+        .map(|notice| {
+            let room = notice
+                .room_id
+                .map_or_else(String::new, |room_id| format!("#{:04x} ", room_id as u16));
+            let repeated =
+                if notice.count > 1 { format!(" (x{})", notice.count) } else { String::new() };
+            let text = Span::styled(
+                format!("{room}{}{repeated}", notice.message),
+                color(theme, notice.severity),
+            );
+            match notice.server {
+                Some(kind) => Line::from(vec![
+                    Span::styled(label(kind), theme.fg(Role::Accent).add_modifier(Modifier::BOLD)),
+                    text,
+                ]),
+                None => Line::from(text),
+            }
+        })
+        .collect();
+
+    let hidden = notices.len().saturating_sub(SHOWN);
+    let title = if hidden > 0 { format!(" Notices (+{hidden}) ") } else { " Notices ".to_string() };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(color(theme, newest.severity));
+
+    let width = WIDTH.min(area.width);
+    let height = (u16::try_from(lines.len()).unwrap_or(u16::MAX) + 2).min(area.height);
+    let toast = Rect { x: area.x + area.width - width, y: area.y, width, height };
+
+    frame.render_widget(Clear, toast);
+    frame.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: true }), toast);
+}
+
+/// Label of a notice from the server's operator.
+fn label(kind: NoticeKind) -> &'static str {
+    match kind {
+        NoticeKind::Info => "[server] ",
+        NoticeKind::Maintenance => "[maintenance] ",
+        NoticeKind::Policy => "[policy] ",
+    }
+}
+
+fn color(theme: &Theme, severity: Severity) -> Style {
+    theme.fg(match severity {
+        Severity::Info => Role::Unread,
+        Severity::Warning => Role::Warning,
+        Severity::Error => Role::Error,
+    })
+}
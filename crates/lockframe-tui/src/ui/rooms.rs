@@ -1,20 +1,27 @@
 //! Rooms sidebar
 //!
-//! Displays the list of joined rooms with unread indicators.
+//! Displays the room list in the App's order, with unread and mention
+//! indicators, and marks rooms we are leaving. The border is highlighted
+//! while the list has focus.
 
 use lockframe_app::App;
 use ratatui::{
     Frame,
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, List, ListItem},
 };
 
+use super::{Role, Theme};
+
 const ACTIVE_PREFIX: &str = ">";
 const INACTIVE_PREFIX: &str = " ";
 const ROOM_ID_PREFIX: &str = "#";
 const UNREAD_MARKER: &str = "*";
+const MENTION_MARKER: &str = "@";
+const PINNED_MARKER: &str = "^";
+const LEAVING_MARKER: &str = " leaving…";
 const EMPTY_MARKER: &str = "";
 const ROOM_ID_HEX_WIDTH: usize = 4;
 
@@ -25,38 +32,46 @@ enum RoomDisplayState {
 }
 
 /// Render the rooms sidebar.
-pub fn render(frame: &mut Frame, app: &App, area: Rect) {
-    let mut room_ids: Vec<_> = app.rooms().keys().copied().collect();
-    room_ids.sort_unstable();
-
-    let items: Vec<ListItem> = room_ids
-        .iter()
-        .map(|&room_id| {
-            let state = if app.active_room() == Some(room_id) {
+pub fn render(frame: &mut Frame, app: &App, theme: &Theme, focused: bool, area: Rect) {
+    let items: Vec<ListItem> = app
+        .room_list()
+        .into_iter()
+        .filter_map(|room_id| app.rooms().get(&room_id))
+        .map(|room| {
+            let state = if app.active_room() == Some(room.room_id) {
                 RoomDisplayState::Active
-            } else if app.rooms().get(&room_id).is_some_and(|r| r.unread) {
+            } else if room.unread > 0 {
                 RoomDisplayState::Unread
             } else {
                 RoomDisplayState::Normal
             };
 
-            let full_hex = format!("{room_id:x}");
-            let tail = &full_hex[full_hex.len().saturating_sub(ROOM_ID_HEX_WIDTH)..];
-            let room_name = format!("{ROOM_ID_PREFIX}{tail:0>ROOM_ID_HEX_WIDTH$}");
+            let room_name = room.name.clone().unwrap_or_else(|| {
+                let full_hex = format!("{:x}", room.room_id);
+                let tail = &full_hex[full_hex.len().saturating_sub(ROOM_ID_HEX_WIDTH)..];
+                format!("{ROOM_ID_PREFIX}{tail:0>ROOM_ID_HEX_WIDTH$}")
+            });
+            let room_name =
+                if room.pinned { format!("{PINNED_MARKER}{room_name}") } else { room_name };
+            let room_name =
+                if room.leaving { format!("{room_name}{LEAVING_MARKER}") } else { room_name };
 
             let (prefix, suffix, style) = match state {
                 RoomDisplayState::Active => (
                     ACTIVE_PREFIX,
                     EMPTY_MARKER,
-                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                    theme.fg(Role::Accent).add_modifier(Modifier::BOLD),
                 ),
+                RoomDisplayState::Unread if room.mentions > 0 => {
+                    (INACTIVE_PREFIX, MENTION_MARKER, theme.fg(Role::Unread))
+                },
                 RoomDisplayState::Unread => {
-                    (INACTIVE_PREFIX, UNREAD_MARKER, Style::default().fg(Color::Cyan))
+                    (INACTIVE_PREFIX, UNREAD_MARKER, theme.fg(Role::Unread))
                 },
                 RoomDisplayState::Normal => (INACTIVE_PREFIX, EMPTY_MARKER, Style::default()),
             };
 
-            let unread_style = Style::default().fg(Color::Red);
+            let unread_style = theme.fg(Role::Mention);
 
             ListItem::new(Line::from(vec![
                 Span::raw(prefix),
@@ -66,7 +81,14 @@ pub fn render(frame: &mut Frame, app: &App, area: Rect) {
         })
         .collect();
 
-    let block = Block::default().borders(Borders::ALL).title(" Rooms ");
+    let title = match app.room_filter() {
+        "" => " Rooms ".to_string(),
+        filter => format!(" Rooms: {filter} "),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(super::border_style(theme, focused));
     let list = List::new(items).block(block);
 
     frame.render_widget(list, area);
@@ -1,49 +1,121 @@
 //! Status bar
 //!
-//! Displays connection status and room information.
+//! Displays the account, connection status and quality, the active room
+//! with its alias and MLS epoch, unread totals across rooms, and progress
+//! restoring the session or loading history. A dropped connection shows
+//! here as soon as it is noticed.
 
-use lockframe_app::{App, ConnectionState};
+use lockframe_app::{App, ConnectionState, History, RestoreStep};
 use ratatui::{
     Frame,
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::Paragraph,
 };
 
+use super::{Role, Theme};
+use crate::AddressBook;
+
 /// Render the status bar.
-pub fn render(frame: &mut Frame, app: &App, area: Rect) {
+pub fn render(frame: &mut Frame, app: &App, address_book: &AddressBook, theme: &Theme, area: Rect) {
     let connection_status = match app.connection_state() {
-        ConnectionState::Disconnected => {
-            Span::styled("Disconnected", Style::default().fg(Color::Red))
-        },
-        ConnectionState::Connecting => {
-            Span::styled("Connecting...", Style::default().fg(Color::Yellow))
+        ConnectionState::Disconnected => Span::styled("Disconnected", theme.fg(Role::Error)),
+        ConnectionState::Connecting => Span::styled("Connecting...", theme.fg(Role::Warning)),
+        ConnectionState::Connected { sender_id, quality, .. } if quality.is_degraded() => {
+            let detail = match quality.rtt {
+                _ if quality.missed_heartbeats > 0 => {
+                    format!("{} heartbeats missed", quality.missed_heartbeats)
+                },
+                Some(rtt) => format!("{}ms round trip", rtt.as_millis()),
+                None => String::new(),
+            };
+            Span::styled(
+                format!("Slow connection ({detail}) | Your ID: {sender_id}"),
+                theme.fg(Role::Warning).add_modifier(Modifier::BOLD),
+            )
         },
         ConnectionState::Connected { sender_id, .. } => Span::styled(
             format!("Connected | Your ID: {sender_id}"),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            theme.fg(Role::Success).add_modifier(Modifier::BOLD),
         ),
     };
 
+    // Name the active account once there is more than one
+    let accounts = app.accounts();
+    let account_info = if accounts.len() > 1 {
+        let others_unread: usize =
+            accounts.iter().filter(|a| a.id != app.active_account()).map(|a| a.unread).sum();
+        let name = accounts
+            .iter()
+            .find(|a| a.id == app.active_account())
+            .map_or("", |account| account.name.as_str());
+        let unread =
+            if others_unread > 0 { format!(" ({others_unread} elsewhere)") } else { String::new() };
+        format!("[{name}{unread}] ")
+    } else {
+        String::new()
+    };
+
     let room_info = app.active_room_state().map_or_else(String::new, |room| {
-        let member_count = room.members.len();
-        let msg_count = room.messages.len();
         let room_short = room.room_id as u16;
-        format!(" | Room: #{room_short:04x} | Members: {member_count} | Messages: {msg_count}")
+        let name = match address_book.room_name(room.room_id) {
+            Some(alias) => format!("#{alias} ({room_short:04x})"),
+            None => format!("#{room_short:04x}"),
+        };
+        format!(
+            " | Room: {name} | Epoch: {} | Members: {} | Messages: {}",
+            room.epoch,
+            room.members.len(),
+            room.messages.len()
+        )
     });
 
+    let (unread, mentions) = app
+        .rooms()
+        .values()
+        .fold((0, 0), |(unread, mentions), room| (unread + room.unread, mentions + room.mentions));
+    let unread_info = match (unread, mentions) {
+        (0, _) => String::new(),
+        (unread, 0) => format!(" | {unread} unread"),
+        (unread, mentions) => format!(" | {unread} unread, {mentions} mentioning you"),
+    };
+
+    let progress = progress(app).map_or_else(String::new, |progress| format!(" | {progress}"));
     let status_msg = app.status_message().map_or_else(String::new, |msg| format!(" | {msg}"));
 
     let status_line = Line::from(vec![
         Span::raw(" "),
+        Span::styled(account_info, Style::default().add_modifier(Modifier::BOLD)),
         connection_status,
-        Span::styled(room_info, Style::default().fg(Color::DarkGray)),
-        Span::styled(status_msg, Style::default().fg(Color::Red)),
+        Span::styled(room_info, theme.fg(Role::Muted)),
+        Span::styled(unread_info, theme.fg(Role::Unread)),
+        Span::styled(progress, theme.fg(Role::Accent)),
+        Span::styled(status_msg, theme.fg(Role::Error)),
     ]);
 
     let paragraph =
-        Paragraph::new(status_line).style(Style::default().bg(Color::DarkGray).fg(Color::White));
+        Paragraph::new(status_line).style(theme.fg(Role::Text).bg(theme.color(Role::StatusBar)));
 
     frame.render_widget(paragraph, area);
 }
+
+/// Progress restoring the session, else loading the active room's history.
+fn progress(app: &App) -> Option<String> {
+    if let ConnectionState::Connected { quality, .. } = app.connection_state() {
+        match quality.restoring {
+            Some(RestoreStep::Authenticating) => return Some("Restoring session".to_string()),
+            Some(RestoreStep::Syncing { remaining }) => {
+                return Some(format!("Syncing {remaining} rooms"));
+            },
+            Some(RestoreStep::Replaying { messages }) => {
+                return Some(format!("Sending {messages} queued messages"));
+            },
+            Some(RestoreStep::Restored) | None => {},
+        }
+    }
+    match app.active_room_state()?.history {
+        History::Loading { fetched, total } => Some(format!("History {fetched}/{total}")),
+        _ => None,
+    }
+}
@@ -0,0 +1,358 @@
+//! Color themes
+//!
+//! Every color the UI draws comes from a [`Role`], which a [`Theme`] maps to
+//! a color. Besides the built-in themes, theme files of `role = color` lines
+//! add user themes:
+//!
+//! ```text
+//! name = ocean
+//! # Start from a built-in theme; unset roles keep its colors
+//! base = solarized
+//! accent = #5fafff
+//! mention = lightmagenta
+//! ```
+//!
+//! Colors are named (`red`, `lightblue`, `darkgray`, ...), `#rrggbb`, or a
+//! 256-color index. On terminals without true color, [`Themes`] maps every
+//! color to the nearest of the 16 basic ones.
+
+use std::path::Path;
+
+use ratatui::style::{Color, Style};
+
+/// What a color is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Ordinary text.
+    Text,
+    /// Hints, pending messages, and other secondary text.
+    Muted,
+    /// The active room, the focused pane, and your own name.
+    Accent,
+    /// Message senders.
+    Sender,
+    /// Rooms with unread messages and informational notices.
+    Unread,
+    /// Mentions of you: messages, and markers in the room list.
+    Mention,
+    /// Failures.
+    Error,
+    /// Warnings and degraded connections.
+    Warning,
+    /// A healthy connection.
+    Success,
+    /// Background of the status bar.
+    StatusBar,
+}
+
+/// Roles with their names in theme files, in the order colors are stored.
+const ROLES: [(&str, Role); 10] = [
+    ("text", Role::Text),
+    ("muted", Role::Muted),
+    ("accent", Role::Accent),
+    ("sender", Role::Sender),
+    ("unread", Role::Unread),
+    ("mention", Role::Mention),
+    ("error", Role::Error),
+    ("warning", Role::Warning),
+    ("success", Role::Success),
+    ("status-bar", Role::StatusBar),
+];
+
+/// The 16 basic colors with their usual RGB values.
+const BASIC_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// A theme file that couldn't be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum ThemeError {
+    /// The file couldn't be read.
+    #[error("failed to read theme: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A line isn't a valid setting.
+    #[error("theme line {line}: {error}")]
+    Invalid {
+        /// Line number, starting at 1.
+        line: usize,
+        /// What is wrong with it.
+        error: String,
+    },
+}
+
+/// Colors for each [`Role`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    name: String,
+    colors: [Color; ROLES.len()],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::named("default", [
+            Color::White,
+            Color::DarkGray,
+            Color::Yellow,
+            Color::Green,
+            Color::Cyan,
+            Color::Magenta,
+            Color::Red,
+            Color::Yellow,
+            Color::Green,
+            Color::DarkGray,
+        ])
+    }
+}
+
+impl Theme {
+    fn named(name: &str, colors: [Color; ROLES.len()]) -> Self {
+        Self { name: name.to_string(), colors }
+    }
+
+    /// The built-in themes.
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::default(),
+            Self::named("light", [
+                Color::Black,
+                Color::DarkGray,
+                Color::Blue,
+                Color::Green,
+                Color::Cyan,
+                Color::Magenta,
+                Color::Red,
+                Color::Yellow,
+                Color::Green,
+                Color::Gray,
+            ]),
+            Self::named("solarized", [
+                Color::Rgb(147, 161, 161),
+                Color::Rgb(88, 110, 117),
+                Color::Rgb(181, 137, 0),
+                Color::Rgb(133, 153, 0),
+                Color::Rgb(42, 161, 152),
+                Color::Rgb(211, 54, 130),
+                Color::Rgb(220, 50, 47),
+                Color::Rgb(203, 75, 22),
+                Color::Rgb(133, 153, 0),
+                Color::Rgb(7, 54, 66),
+            ]),
+        ]
+    }
+
+    /// Load a theme file.
+    pub fn load(path: &Path) -> Result<Self, ThemeError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a theme file. It must have a `name`; a `base` must come before
+    /// any color.
+    pub fn parse(config: &str) -> Result<Self, ThemeError> {
+        let mut theme = Self::default();
+        let mut name = None;
+        let mut colored = false;
+        for (index, line) in config.lines().enumerate() {
+            let invalid = |error: String| ThemeError::Invalid { line: index + 1, error };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) =
+                line.split_once('=').ok_or_else(|| invalid("expected `role = color`".into()))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "name" => name = Some(value.to_string()),
+                "base" if colored => {
+                    return Err(invalid("base must come before any color".into()));
+                },
+                "base" => {
+                    theme = Self::builtin()
+                        .into_iter()
+                        .find(|builtin| builtin.name == value)
+                        .ok_or_else(|| invalid(format!("unknown base theme: {value}")))?;
+                },
+                role => {
+                    let index = ROLES
+                        .iter()
+                        .position(|(name, _)| *name == role)
+                        .ok_or_else(|| invalid(format!("unknown role: {role}")))?;
+                    theme.colors[index] =
+                        value.parse().map_err(|_| invalid(format!("invalid color: {value}")))?;
+                    colored = true;
+                },
+            }
+        }
+        theme.name = name.ok_or_else(|| ThemeError::Invalid {
+            line: config.lines().count(),
+            error: "missing name".into(),
+        })?;
+        Ok(theme)
+    }
+
+    /// Name to select the theme by.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Color of a role.
+    pub fn color(&self, role: Role) -> Color {
+        self.colors[role as usize]
+    }
+
+    /// Style with a role's color in the foreground.
+    pub fn fg(&self, role: Role) -> Style {
+        Style::default().fg(self.color(role))
+    }
+
+    /// The theme with every color replaced by the nearest basic one.
+    #[must_use]
+    pub fn basic(&self) -> Self {
+        Self { name: self.name.clone(), colors: self.colors.map(nearest_basic) }
+    }
+}
+
+/// Nearest of the 16 basic colors to `color`.
+fn nearest_basic(color: Color) -> Color {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index @ 0..16) => return BASIC_COLORS[index as usize].0,
+        Color::Indexed(index @ 16..232) => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + 40 * v };
+            let cube = index - 16;
+            (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+        },
+        Color::Indexed(index) => {
+            let gray = 8 + 10 * (index - 232);
+            (gray, gray, gray)
+        },
+        basic => return basic,
+    };
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    BASIC_COLORS.iter().min_by_key(|(_, rgb)| distance(*rgb)).map_or(color, |(basic, _)| *basic)
+}
+
+/// The themes to choose from and the one in use.
+#[derive(Debug, Clone)]
+pub struct Themes {
+    available: Vec<Theme>,
+    active: Theme,
+    basic_colors: bool,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Self { available: Theme::builtin(), active: Theme::default(), basic_colors: false }
+    }
+}
+
+impl Themes {
+    /// The built-in themes, using the default one.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit colors to the 16 basic ones, for terminals without true color.
+    #[must_use]
+    pub fn with_basic_colors(mut self, basic_colors: bool) -> Self {
+        self.basic_colors = basic_colors;
+        let name = self.active.name.clone();
+        self.select(&name);
+        self
+    }
+
+    /// Add a theme, replacing any with the same name.
+    pub fn add(&mut self, theme: Theme) {
+        self.available.retain(|existing| existing.name != theme.name);
+        self.available.push(theme);
+    }
+
+    /// Use the theme named `name`. Returns whether there is one.
+    pub fn select(&mut self, name: &str) -> bool {
+        let Some(theme) = self.available.iter().find(|theme| theme.name == name) else {
+            return false;
+        };
+        self.active = if self.basic_colors { theme.basic() } else { theme.clone() };
+        true
+    }
+
+    /// Names of the themes, in the order they were added.
+    pub fn names(&self) -> Vec<&str> {
+        self.available.iter().map(Theme::name).collect()
+    }
+
+    /// The theme in use.
+    pub fn active(&self) -> &Theme {
+        &self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_file_overrides_a_base() {
+        let theme = Theme::parse(
+            "
+            name = ocean
+            base = solarized
+            accent = #5fafff
+            mention = lightmagenta
+            unread = 33
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(theme.name(), "ocean");
+        assert_eq!(theme.color(Role::Accent), Color::Rgb(0x5f, 0xaf, 0xff));
+        assert_eq!(theme.color(Role::Mention), Color::LightMagenta);
+        assert_eq!(theme.color(Role::Unread), Color::Indexed(33));
+        assert_eq!(theme.color(Role::Error), Color::Rgb(220, 50, 47));
+    }
+
+    #[test]
+    fn invalid_theme_file_names_the_line() {
+        let error = |config: &str| match Theme::parse(config) {
+            Err(ThemeError::Invalid { line, error }) => (line, error),
+            other => panic!("expected an invalid line, got {other:?}"),
+        };
+
+        assert_eq!(error("name = x\nglow = red").1, "unknown role: glow");
+        assert_eq!(error("name = x\naccent = shiny").0, 2);
+        assert_eq!(error("name = x\naccent = red\nbase = light").0, 3);
+        assert_eq!(error("accent = red").1, "missing name");
+    }
+
+    #[test]
+    fn basic_colors_replace_true_color() {
+        let mut themes = Themes::new().with_basic_colors(true);
+        assert!(themes.select("solarized"));
+
+        let theme = themes.active();
+        assert_eq!(theme.color(Role::Error), Color::Red);
+        assert_eq!(theme.color(Role::StatusBar), Color::Black);
+        assert_eq!(nearest_basic(Color::Indexed(231)), Color::White);
+        assert_eq!(nearest_basic(Color::Indexed(9)), Color::LightRed);
+        assert!(!themes.select("neon"));
+    }
+}
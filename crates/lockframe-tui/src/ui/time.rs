@@ -0,0 +1,141 @@
+//! Message times
+//!
+//! Formats message timestamps and finds where the day changes, at a fixed
+//! offset from UTC.
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// How much of a message's time to show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeFormat {
+    /// Don't show times. Day separators are still shown.
+    Off,
+    /// Hours and minutes.
+    #[default]
+    Short,
+    /// Hours, minutes and seconds.
+    Long,
+}
+
+/// How message times are shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timestamps {
+    format: TimeFormat,
+    /// Minutes east of UTC.
+    utc_offset: i32,
+}
+
+impl Timestamps {
+    /// Show times in `format`, `utc_offset` minutes east of UTC.
+    pub fn new(format: TimeFormat, utc_offset: i32) -> Self {
+        Self { format, utc_offset }
+    }
+
+    /// Time of day of a Unix millisecond timestamp, unless times are off or
+    /// the timestamp is unknown (0).
+    pub fn time(&self, timestamp: u64) -> Option<String> {
+        if timestamp == 0 {
+            return None;
+        }
+        let secs = self.local_secs(timestamp).rem_euclid(SECS_PER_DAY);
+        let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+        match self.format {
+            TimeFormat::Off => None,
+            TimeFormat::Short => Some(format!("{hours:02}:{minutes:02}")),
+            TimeFormat::Long => Some(format!("{hours:02}:{minutes:02}:{seconds:02}")),
+        }
+    }
+
+    /// Day of a Unix millisecond timestamp, counted from 1970-01-01, or
+    /// `None` if the timestamp is unknown (0).
+    pub fn day(&self, timestamp: u64) -> Option<i64> {
+        (timestamp != 0).then(|| self.local_secs(timestamp).div_euclid(SECS_PER_DAY))
+    }
+
+    /// A day from [`Self::day`] as `YYYY-MM-DD`.
+    pub fn date(day: i64) -> String {
+        let (year, month, day) = civil_from_days(day);
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+
+    fn local_secs(self, timestamp: u64) -> i64 {
+        (timestamp / 1000) as i64 + i64::from(self.utc_offset) * 60
+    }
+}
+
+/// Parse an offset from UTC like `+02:00`, `-0530` or `UTC` into minutes.
+pub fn parse_utc_offset(offset: &str) -> Result<i32, String> {
+    if offset.eq_ignore_ascii_case("utc") || offset == "Z" {
+        return Ok(0);
+    }
+    let invalid = || format!("expected an offset like +02:00, got {offset}");
+    let (sign, rest) = match offset.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, ""));
+    let (hours, minutes) = match (hours.len(), minutes.len()) {
+        (1 | 2, 0 | 2) => (hours, minutes),
+        (4, 0) => rest.split_at(2),
+        _ => return Err(invalid()),
+    };
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 =
+        if minutes.is_empty() { 0 } else { minutes.parse().map_err(|_| invalid())? };
+    if hours > 14 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(sign * (hours * 60 + minutes))
+}
+
+/// Year, month and day of a day counted from 1970-01-01, in the proleptic
+/// Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-02-29 23:30:15 UTC.
+    const LEAP_DAY_EVENING: u64 = 1_709_249_415_000;
+
+    #[test]
+    fn times_and_days_follow_the_offset() {
+        let utc = Timestamps::new(TimeFormat::Long, 0);
+        assert_eq!(utc.time(LEAP_DAY_EVENING).as_deref(), Some("23:30:15"));
+        assert_eq!(utc.day(LEAP_DAY_EVENING).map(Timestamps::date).as_deref(), Some("2024-02-29"));
+
+        let east = Timestamps::new(TimeFormat::Short, 60);
+        assert_eq!(east.time(LEAP_DAY_EVENING).as_deref(), Some("00:30"));
+        assert_eq!(east.day(LEAP_DAY_EVENING).map(Timestamps::date).as_deref(), Some("2024-03-01"));
+
+        assert_eq!(Timestamps::new(TimeFormat::Off, 0).time(LEAP_DAY_EVENING), None);
+        assert_eq!(utc.time(0), None);
+        assert_eq!(utc.day(0), None);
+        assert_eq!(Timestamps::date(0), "1970-01-01");
+        assert_eq!(Timestamps::date(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn parses_utc_offsets() {
+        assert_eq!(parse_utc_offset("+02:00"), Ok(120));
+        assert_eq!(parse_utc_offset("-0530"), Ok(-330));
+        assert_eq!(parse_utc_offset("+9"), Ok(540));
+        assert_eq!(parse_utc_offset("UTC"), Ok(0));
+        assert!(parse_utc_offset("02:00").is_err());
+        assert!(parse_utc_offset("+25:00").is_err());
+    }
+}
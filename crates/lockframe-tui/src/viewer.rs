@@ -0,0 +1,156 @@
+//! External viewer for attachments.
+//!
+//! `/open <log_index>` decrypts an attachment into a directory only the user
+//! can read, [`attachment_dir`], and hands the file to a viewer program,
+//! `xdg-open` (`open` on macOS) unless the config names another. The viewer
+//! runs detached and there is no telling when it is done with the file, so
+//! decrypted files are left in place; opening an attachment again
+//! overwrites its file.
+//!
+//! The file name comes from the sender, so only its last component is kept
+//! and it is prefixed with the start of the attachment's hash, which keeps
+//! two attachments of the same name apart.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use lockframe_client::{AttachmentError, open_attachment};
+use lockframe_proto::payloads::app::Attachment;
+
+/// Why an attachment couldn't be opened.
+#[derive(Debug, thiserror::Error)]
+pub enum ViewerError {
+    /// The attachment didn't decrypt.
+    #[error("{0}")]
+    Decrypt(#[from] AttachmentError),
+
+    /// The decrypted file couldn't be written, or the viewer started.
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Program attachments are opened with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Viewer {
+    /// Program to run.
+    program: String,
+    /// Arguments before the file's path.
+    args: Vec<String>,
+}
+
+impl Default for Viewer {
+    fn default() -> Self {
+        let program = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+        Self { program: program.to_string(), args: vec![] }
+    }
+}
+
+impl Viewer {
+    /// Viewer run as `command`, split at spaces, e.g. `"feh --scale-down"`.
+    /// `None` if `command` is blank.
+    pub fn new(command: &str) -> Option<Self> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        Some(Self { program: words.next()?, args: words.collect() })
+    }
+
+    /// Decrypt `attachment` into `dir` and launch the viewer on it,
+    /// returning the file's path.
+    pub fn open(&self, attachment: &Attachment, dir: &Path) -> Result<PathBuf, ViewerError> {
+        let content = open_attachment(attachment)?;
+        let path = write_private(dir, &file_name(attachment), &content)?;
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .arg(&path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Reap the viewer when it exits instead of leaving a zombie
+        std::thread::spawn(move || child.wait());
+        Ok(path)
+    }
+}
+
+/// Where attachments are decrypted to: `$XDG_CACHE_HOME/lockframe/attachments`,
+/// or `~/.cache/lockframe/attachments`. Not the shared temp directory, where
+/// another user could claim the path first.
+pub fn attachment_dir() -> Option<PathBuf> {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache.join("lockframe").join("attachments"))
+}
+
+/// Name to save `attachment` under: the last component of the sender's
+/// name, after the start of its hash.
+fn file_name(attachment: &Attachment) -> String {
+    let name = Path::new(&attachment.filename)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .unwrap_or_else(|| "attachment".to_string());
+    format!("{}-{name}", short_hash(attachment))
+}
+
+/// First four bytes of an attachment's hash in hex, enough to tell
+/// attachments apart at a glance.
+pub fn short_hash(attachment: &Attachment) -> String {
+    let [a, b, c, d, ..] = attachment.hash;
+    format!("{:08x}", u32::from_be_bytes([a, b, c, d]))
+}
+
+/// Write `content` to `dir/name`, readable by the user alone.
+fn write_private(dir: &Path, name: &str, content: &[u8]) -> std::io::Result<PathBuf> {
+    let mut dirs = std::fs::DirBuilder::new();
+    dirs.recursive(true);
+    let mut file = std::fs::OpenOptions::new();
+    file.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        dirs.mode(0o700);
+        file.mode(0o600);
+    }
+    dirs.create(dir)?;
+
+    let path = dir.join(name);
+    file.open(&path)?.write_all(content)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(filename: &str) -> Attachment {
+        Attachment {
+            filename: filename.to_string(),
+            size: 0,
+            hash: [0xab; 32],
+            key: [0; 32],
+            nonce: [0; 24],
+            ciphertext: vec![],
+        }
+    }
+
+    #[test]
+    fn sender_names_cannot_leave_the_directory() {
+        assert_eq!(file_name(&attachment("cat.png")), "abababab-cat.png");
+        assert_eq!(file_name(&attachment("../../.bashrc")), "abababab-attachment");
+        assert_eq!(file_name(&attachment("/etc/passwd")), "abababab-passwd");
+        assert_eq!(file_name(&attachment("")), "abababab-attachment");
+    }
+
+    #[test]
+    fn viewer_commands_split_at_spaces() {
+        assert_eq!(
+            Viewer::new("feh  --scale-down"),
+            Some(Viewer { program: "feh".into(), args: vec!["--scale-down".into()] })
+        );
+        assert_eq!(Viewer::new("  "), None);
+    }
+}
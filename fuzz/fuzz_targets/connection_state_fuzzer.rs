@@ -223,7 +223,7 @@ fn create_frame_from_fuzzed(fuzzed: &FuzzedFrame) -> Frame {
         FuzzedPayload::Pong => Frame::new(FrameHeader::new(Opcode::Pong), Vec::new()),
         FuzzedPayload::Goodbye { reason_len } => {
             let reason = "x".repeat((*reason_len % 100) as usize);
-            let goodbye = Payload::Goodbye(Goodbye { reason });
+            let goodbye = Payload::Goodbye(Goodbye::new(reason));
             goodbye
                 .into_frame(FrameHeader::new(Opcode::Goodbye))
                 .unwrap_or_else(|_| Frame::new(FrameHeader::new(Opcode::Goodbye), Vec::new()))